@@ -5,12 +5,24 @@ use super::*;
 pub struct SelectNext {
     #[serde(default)]
     pub replace_newest: bool,
+    /// Only match whole words, even when the selected text isn't a word under a cursor.
+    #[serde(default)]
+    pub whole_word: bool,
+    /// Don't continue from the start of the buffer after reaching its end.
+    #[serde(default)]
+    pub stop_at_end: bool,
 }
 
 #[derive(PartialEq, Clone, Deserialize, Default)]
 pub struct SelectPrevious {
     #[serde(default)]
     pub replace_newest: bool,
+    /// Only match whole words, even when the selected text isn't a word under a cursor.
+    #[serde(default)]
+    pub whole_word: bool,
+    /// Don't continue from the end of the buffer after reaching its start.
+    #[serde(default)]
+    pub stop_at_start: bool,
 }
 
 #[derive(PartialEq, Clone, Deserialize, Default)]
@@ -161,6 +173,8 @@ gpui::actions!(
         GoToDefinitionSplit,
         GoToDiagnostic,
         GoToHunk,
+        GoToNextBookmark,
+        GoToPrevBookmark,
         GoToPrevDiagnostic,
         GoToPrevHunk,
         GoToTypeDefinition,
@@ -202,10 +216,12 @@ gpui::actions!(
         Paste,
         Redo,
         RedoSelection,
+        ReplayMacro,
         Rename,
         RestartLanguageServer,
         RevealInFinder,
         ReverseLines,
+        Rewrap,
         ScrollCursorBottom,
         ScrollCursorCenter,
         ScrollCursorTop,
@@ -234,7 +250,9 @@ gpui::actions!(
         SplitSelectionIntoLines,
         Tab,
         TabPrev,
+        ToggleBookmark,
         ToggleInlayHints,
+        ToggleMacroRecording,
         ToggleSoftWrap,
         Transpose,
         Undo,
@@ -0,0 +1,133 @@
+use crate::{
+    display_map::ToDisplayPoint, Anchor, Autoscroll, Direction, DisplaySnapshot, Editor,
+    GoToNextBookmark, GoToPrevBookmark, MultiBufferSnapshot, ToggleBookmark,
+};
+use gpui::{SharedString, ViewContext};
+use multi_buffer::ToPoint;
+use std::ops::Range;
+use text::Point;
+
+/// A position the user marked to come back to. Bookmarks are anchored, so they move with
+/// edits around them and follow their excerpt when the multibuffer is rearranged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bookmark {
+    pub anchor: Anchor,
+    pub label: Option<SharedString>,
+}
+
+impl Editor {
+    /// The editor's bookmarks, ordered by their position in the multibuffer.
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Adds a bookmark at `position`, replacing any bookmark on the same row.
+    pub fn add_bookmark(
+        &mut self,
+        position: Anchor,
+        label: Option<SharedString>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        self.remove_invalid_bookmarks(&buffer);
+        let row = position.to_point(&buffer).row;
+        self.bookmarks
+            .retain(|bookmark| bookmark.anchor.to_point(&buffer).row != row);
+        let ix = self
+            .bookmarks
+            .partition_point(|bookmark| bookmark.anchor.cmp(&position, &buffer).is_lt());
+        self.bookmarks.insert(
+            ix,
+            Bookmark {
+                anchor: position,
+                label,
+            },
+        );
+        cx.notify();
+    }
+
+    pub fn clear_bookmarks(&mut self, cx: &mut ViewContext<Self>) {
+        if !self.bookmarks.is_empty() {
+            self.bookmarks.clear();
+            cx.notify();
+        }
+    }
+
+    pub fn toggle_bookmark(&mut self, _: &ToggleBookmark, cx: &mut ViewContext<Self>) {
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        let head = self.selections.newest::<Point>(cx).head();
+        self.remove_invalid_bookmarks(&buffer);
+        if let Some(ix) = self
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.anchor.to_point(&buffer).row == head.row)
+        {
+            self.bookmarks.remove(ix);
+            cx.notify();
+        } else {
+            self.add_bookmark(buffer.anchor_before(head), None, cx);
+        }
+    }
+
+    pub fn go_to_next_bookmark(&mut self, _: &GoToNextBookmark, cx: &mut ViewContext<Self>) {
+        self.go_to_bookmark(Direction::Next, cx);
+    }
+
+    pub fn go_to_prev_bookmark(&mut self, _: &GoToPrevBookmark, cx: &mut ViewContext<Self>) {
+        self.go_to_bookmark(Direction::Prev, cx);
+    }
+
+    /// The display rows within `rows` that contain a bookmark, for rendering in the gutter.
+    pub fn bookmarked_display_rows(
+        &self,
+        rows: Range<u32>,
+        snapshot: &DisplaySnapshot,
+    ) -> Vec<u32> {
+        let mut bookmarked_rows = self
+            .bookmarks
+            .iter()
+            .filter(|bookmark| bookmark.anchor.is_valid(&snapshot.buffer_snapshot))
+            .map(|bookmark| bookmark.anchor.to_display_point(snapshot).row())
+            .filter(|row| rows.contains(row))
+            .collect::<Vec<_>>();
+        bookmarked_rows.dedup();
+        bookmarked_rows
+    }
+
+    /// Moves the cursor to the closest bookmark on another row in `direction`, wrapping around
+    /// the ends of the multibuffer.
+    fn go_to_bookmark(&mut self, direction: Direction, cx: &mut ViewContext<Self>) {
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        self.remove_invalid_bookmarks(&buffer);
+        let head_row = self.selections.newest::<Point>(cx).head().row;
+        let mut positions = self
+            .bookmarks
+            .iter()
+            .map(|bookmark| bookmark.anchor.to_point(&buffer))
+            .collect::<Vec<_>>();
+        positions.sort();
+
+        let target = match direction {
+            Direction::Next => positions
+                .iter()
+                .find(|position| position.row > head_row)
+                .or_else(|| positions.first()),
+            Direction::Prev => positions
+                .iter()
+                .rev()
+                .find(|position| position.row < head_row)
+                .or_else(|| positions.last()),
+        };
+        if let Some(&target) = target {
+            self.change_selections(Some(Autoscroll::center()), cx, |s| {
+                s.select_ranges([target..target]);
+            });
+        }
+    }
+
+    /// Drops bookmarks whose excerpt has been removed from the multibuffer.
+    fn remove_invalid_bookmarks(&mut self, buffer: &MultiBufferSnapshot) {
+        self.bookmarks
+            .retain(|bookmark| bookmark.anchor.is_valid(buffer));
+    }
+}
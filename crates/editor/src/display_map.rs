@@ -25,8 +25,8 @@ mod wrap_map;
 
 use crate::EditorStyle;
 use crate::{
-    hover_links::InlayHighlight, movement::TextLayoutDetails, Anchor, AnchorRangeExt, InlayId,
-    MultiBuffer, MultiBufferSnapshot, ToOffset, ToPoint,
+    hover_links::InlayHighlight, movement::TextLayoutDetails, Anchor, AnchorRangeExt, ExcerptId,
+    InlayId, MultiBuffer, MultiBufferSnapshot, ToOffset, ToPoint,
 };
 pub use block_map::{BlockMap, BlockPoint};
 use collections::{BTreeMap, HashMap, HashSet};
@@ -64,7 +64,23 @@ pub trait ToDisplayPoint {
     fn to_display_point(&self, map: &DisplaySnapshot) -> DisplayPoint;
 }
 
-type TextHighlights = TreeMap<Option<TypeId>, Arc<(HighlightStyle, Vec<Range<Anchor>>)>>;
+/// Identifies a layer of text highlights. Each type can own a single layer, or several layers
+/// that are told apart by a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HighlightKey {
+    Type(TypeId),
+    TypeWithKey(TypeId, usize),
+}
+
+impl HighlightKey {
+    pub fn type_id(&self) -> TypeId {
+        match self {
+            HighlightKey::Type(type_id) | HighlightKey::TypeWithKey(type_id, _) => *type_id,
+        }
+    }
+}
+
+type TextHighlights = TreeMap<HighlightKey, Arc<(HighlightStyle, Vec<Range<Anchor>>)>>;
 type InlayHighlights = BTreeMap<TypeId, HashMap<InlayId, (HighlightStyle, InlayHighlight)>>;
 
 /// Decides how text in a [`MultiBuffer`] should be displayed in a buffer, handling inlay hints,
@@ -244,12 +260,11 @@ impl DisplayMap {
 
     pub fn highlight_text(
         &mut self,
-        type_id: TypeId,
+        key: HighlightKey,
         ranges: Vec<Range<Anchor>>,
         style: HighlightStyle,
     ) {
-        self.text_highlights
-            .insert(Some(type_id), Arc::new((style, ranges)));
+        self.text_highlights.insert(key, Arc::new((style, ranges)));
     }
 
     pub(crate) fn highlight_inlays(
@@ -266,12 +281,29 @@ impl DisplayMap {
         }
     }
 
-    pub fn text_highlights(&self, type_id: TypeId) -> Option<(HighlightStyle, &[Range<Anchor>])> {
-        let highlights = self.text_highlights.get(&Some(type_id))?;
+    pub fn text_highlights(&self, key: HighlightKey) -> Option<(HighlightStyle, &[Range<Anchor>])> {
+        let highlights = self.text_highlights.get(&key)?;
         Some((highlights.0, &highlights.1))
     }
-    pub fn clear_highlights(&mut self, type_id: TypeId) -> bool {
-        let mut cleared = self.text_highlights.remove(&Some(type_id)).is_some();
+
+    /// Clears a single layer of text highlights. Clearing a [`HighlightKey::Type`] also clears
+    /// the type's inlay highlights.
+    pub fn clear_highlights(&mut self, key: HighlightKey) -> bool {
+        let mut cleared = self.text_highlights.remove(&key).is_some();
+        if let HighlightKey::Type(type_id) = key {
+            cleared |= self.inlay_highlights.remove(&type_id).is_some();
+        }
+        cleared
+    }
+
+    /// Clears every layer of text and inlay highlights owned by the given type.
+    pub fn clear_all_highlights(&mut self, type_id: TypeId) -> bool {
+        let mut cleared = false;
+        self.text_highlights.retain(|key, _| {
+            let retain = key.type_id() != type_id;
+            cleared |= !retain;
+            retain
+        });
         cleared |= self.inlay_highlights.remove(&type_id).is_some();
         cleared
     }
@@ -324,11 +356,15 @@ impl DisplayMap {
     }
 
     fn tab_size(buffer: &Model<MultiBuffer>, cx: &mut ModelContext<Self>) -> NonZeroU32 {
-        let language = buffer
-            .read(cx)
-            .as_singleton()
-            .and_then(|buffer| buffer.read(cx).language());
-        language_settings(language.as_deref(), None, cx).tab_size
+        let buffer = buffer.read(cx).as_singleton();
+        if let Some(tab_size) = buffer
+            .as_ref()
+            .and_then(|buffer| buffer.read(cx).editor_config().tab_size())
+        {
+            return tab_size;
+        }
+        let language = buffer.and_then(|buffer| buffer.read(cx).language().cloned());
+        language_settings(language.as_ref(), None, cx).tab_size
     }
 
     #[cfg(test)]
@@ -772,6 +808,18 @@ impl DisplaySnapshot {
         self.fold_snapshot.intersects_fold(offset)
     }
 
+    pub fn is_range_folded<T: ToOffset>(&self, range: Range<T>) -> bool {
+        self.fold_snapshot.is_range_folded(range)
+    }
+
+    pub fn is_range_partially_folded<T: ToOffset>(&self, range: Range<T>) -> bool {
+        self.fold_snapshot.is_range_partially_folded(range)
+    }
+
+    pub fn is_excerpt_folded(&self, excerpt_id: ExcerptId) -> bool {
+        self.fold_snapshot.is_excerpt_folded(excerpt_id)
+    }
+
     pub fn is_line_folded(&self, buffer_row: u32) -> bool {
         self.fold_snapshot.is_line_folded(buffer_row)
     }
@@ -912,7 +960,9 @@ impl DisplaySnapshot {
         &self,
     ) -> Option<Arc<(HighlightStyle, Vec<Range<Anchor>>)>> {
         let type_id = TypeId::of::<Tag>();
-        self.text_highlights.get(&Some(type_id)).cloned()
+        self.text_highlights
+            .get(&HighlightKey::Type(type_id))
+            .cloned()
     }
 
     #[allow(unused)]
@@ -1668,7 +1718,7 @@ pub mod tests {
 
         map.update(cx, |map, _cx| {
             map.highlight_text(
-                TypeId::of::<MyType>(),
+                HighlightKey::Type(TypeId::of::<MyType>()),
                 highlighted_ranges
                     .into_iter()
                     .map(|range| {
@@ -2,7 +2,7 @@ use super::{
     inlay_map::{InlayBufferRows, InlayChunks, InlayEdit, InlayOffset, InlayPoint, InlaySnapshot},
     Highlights,
 };
-use crate::{Anchor, AnchorRangeExt, MultiBufferSnapshot, ToOffset};
+use crate::{Anchor, AnchorRangeExt, ExcerptId, MultiBufferSnapshot, ToOffset};
 use gpui::{ElementId, HighlightStyle, Hsla};
 use language::{Chunk, Edit, Point, TextSummary};
 use std::{
@@ -630,6 +630,65 @@ impl FoldSnapshot {
         cursor.item().map_or(false, |t| t.output_text.is_some())
     }
 
+    /// Returns whether the given range is entirely hidden by folds.
+    pub fn is_range_folded<T>(&self, range: Range<T>) -> bool
+    where
+        T: ToOffset,
+    {
+        let buffer = &self.inlay_snapshot.buffer;
+        let start = self
+            .inlay_snapshot
+            .to_inlay_offset(range.start.to_offset(buffer));
+        let end = self
+            .inlay_snapshot
+            .to_inlay_offset(range.end.to_offset(buffer));
+        let mut cursor = self.transforms.cursor::<InlayOffset>();
+        cursor.seek(&start, Bias::Right, &());
+        while let Some(transform) = cursor.item() {
+            if transform.output_text.is_none() {
+                return false;
+            } else if cursor.end(&()) >= end {
+                return true;
+            }
+            cursor.next(&());
+        }
+        false
+    }
+
+    /// Returns whether any part of the given range is hidden by folds.
+    pub fn is_range_partially_folded<T>(&self, range: Range<T>) -> bool
+    where
+        T: ToOffset,
+    {
+        let buffer = &self.inlay_snapshot.buffer;
+        let start = self
+            .inlay_snapshot
+            .to_inlay_offset(range.start.to_offset(buffer));
+        let end = self
+            .inlay_snapshot
+            .to_inlay_offset(range.end.to_offset(buffer));
+        let mut cursor = self.transforms.cursor::<InlayOffset>();
+        cursor.seek(&start, Bias::Right, &());
+        while let Some(transform) = cursor.item() {
+            if transform.output_text.is_some() {
+                return true;
+            } else if cursor.end(&()) >= end {
+                return false;
+            }
+            cursor.next(&());
+        }
+        false
+    }
+
+    /// Returns whether the given excerpt is entirely hidden by folds.
+    pub fn is_excerpt_folded(&self, excerpt_id: ExcerptId) -> bool {
+        let buffer = &self.inlay_snapshot.buffer;
+        buffer
+            .anchor_at_excerpt_start(excerpt_id)
+            .zip(buffer.anchor_at_excerpt_end(excerpt_id))
+            .map_or(false, |(start, end)| self.is_range_folded(start..end))
+    }
+
     pub fn is_line_folded(&self, buffer_row: u32) -> bool {
         let mut inlay_point = self
             .inlay_snapshot
@@ -1161,12 +1220,14 @@ pub type FoldEdit = Edit<FoldOffset>;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{display_map::inlay_map::InlayMap, MultiBuffer, ToPoint};
+    use crate::{display_map::inlay_map::InlayMap, ExcerptRange, MultiBuffer, ToPoint};
     use collections::HashSet;
+    use gpui::Context;
+    use language::{Buffer, Capability};
     use rand::prelude::*;
     use settings::SettingsStore;
     use std::{env, mem};
-    use text::Patch;
+    use text::{BufferId, Patch};
     use util::test::sample_text;
     use util::RandomCharIter;
     use Bias::{Left, Right};
@@ -1373,6 +1434,96 @@ mod tests {
                 Point::new(1, 2)..Point::new(3, 2)
             ]
         );
+
+        assert!(snapshot.is_range_folded(Point::new(1, 0)..Point::new(2, 0)));
+        assert!(snapshot.is_range_folded(Point::new(0, 2)..Point::new(4, 1)));
+        assert!(!snapshot.is_range_folded(Point::new(0, 0)..Point::new(0, 3)));
+        assert!(!snapshot.is_range_folded(Point::new(4, 0)..Point::new(4, 2)));
+
+        assert!(snapshot.is_range_partially_folded(Point::new(0, 0)..Point::new(0, 3)));
+        assert!(snapshot.is_range_partially_folded(Point::new(4, 0)..Point::new(4, 2)));
+        assert!(!snapshot.is_range_partially_folded(Point::new(0, 0)..Point::new(0, 2)));
+        assert!(!snapshot.is_range_partially_folded(Point::new(4, 1)..Point::new(4, 3)));
+    }
+
+    #[gpui::test]
+    fn test_excerpt_folds_survive_excerpt_changes(cx: &mut gpui::AppContext) {
+        init_test(cx);
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(3, 4, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(3, 4, 'j'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            let mut excerpt_ids = multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(2, 4),
+                    primary: None,
+                }],
+                cx,
+            );
+            excerpt_ids.extend(multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(2, 4),
+                    primary: None,
+                }],
+                cx,
+            ));
+            excerpt_ids
+        });
+
+        let buffer_snapshot = multibuffer.read(cx).snapshot(cx);
+        let (mut inlay_map, inlay_snapshot) = InlayMap::new(buffer_snapshot.clone());
+        let mut map = FoldMap::new(inlay_snapshot.clone()).0;
+        let fold_range = buffer_snapshot
+            .anchor_at_excerpt_start(excerpt_ids[1])
+            .unwrap()
+            ..buffer_snapshot
+                .anchor_at_excerpt_end(excerpt_ids[1])
+                .unwrap();
+        let (mut writer, _, _) = map.write(inlay_snapshot.clone(), vec![]);
+        writer.fold(vec![fold_range]);
+        let (snapshot, _) = map.read(inlay_snapshot, vec![]);
+        assert!(!snapshot.is_excerpt_folded(excerpt_ids[0]));
+        assert!(snapshot.is_excerpt_folded(excerpt_ids[1]));
+
+        // Inserting an excerpt above the fold, and editing the buffers, keeps it anchored to
+        // the excerpt.
+        let subscription = multibuffer.update(cx, |multibuffer, _| multibuffer.subscribe());
+        let new_excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.insert_excerpts_after(
+                ExcerptId::min(),
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(1, 0)..Point::new(1, 4),
+                    primary: None,
+                }],
+                cx,
+            )
+        });
+        buffer_2.update(cx, |buffer, cx| buffer.edit([(1..1, "xyz")], None, cx));
+        let buffer_snapshot = multibuffer.read(cx).snapshot(cx);
+        let (inlay_snapshot, inlay_edits) =
+            inlay_map.sync(buffer_snapshot, subscription.consume().into_inner());
+        let (snapshot, _) = map.read(inlay_snapshot, inlay_edits);
+        assert!(!snapshot.is_excerpt_folded(new_excerpt_ids[0]));
+        assert!(!snapshot.is_excerpt_folded(excerpt_ids[0]));
+        assert!(snapshot.is_excerpt_folded(excerpt_ids[1]));
+        assert!(snapshot.is_range_partially_folded(
+            Point::new(0, 0)..snapshot.inlay_snapshot.buffer.max_point()
+        ));
     }
 
     #[gpui::test(iterations = 100)]
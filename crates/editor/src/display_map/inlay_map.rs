@@ -4,17 +4,16 @@ use gpui::HighlightStyle;
 use language::{Chunk, Edit, Point, TextSummary};
 use multi_buffer::{MultiBufferChunks, MultiBufferRows};
 use std::{
-    any::TypeId,
     cmp,
     iter::Peekable,
     ops::{Add, AddAssign, Range, Sub, SubAssign},
     sync::Arc,
     vec,
 };
-use sum_tree::{Bias, Cursor, SumTree, TreeMap};
+use sum_tree::{Bias, Cursor, SumTree};
 use text::{Patch, Rope};
 
-use super::Highlights;
+use super::{HighlightKey, Highlights, TextHighlights};
 
 /// Decides where the [`Inlay`]s should be displayed.
 ///
@@ -189,7 +188,7 @@ pub struct InlayBufferRows<'a> {
 struct HighlightEndpoint {
     offset: InlayOffset,
     is_start: bool,
-    tag: Option<TypeId>,
+    tag: HighlightKey,
     style: HighlightStyle,
 }
 
@@ -218,7 +217,7 @@ pub struct InlayChunks<'a> {
     inlay_highlight_style: Option<HighlightStyle>,
     suggestion_highlight_style: Option<HighlightStyle>,
     highlight_endpoints: Peekable<vec::IntoIter<HighlightEndpoint>>,
-    active_highlights: BTreeMap<Option<TypeId>, HighlightStyle>,
+    active_highlights: BTreeMap<HighlightKey, HighlightStyle>,
     highlights: Highlights<'a>,
     snapshot: &'a InlaySnapshot,
 }
@@ -356,6 +355,7 @@ impl<'a> Iterator for InlayChunks<'a> {
                 Chunk {
                     text: chunk,
                     highlight_style,
+                    is_suggestion: matches!(inlay.id, InlayId::Suggestion(_)),
                     ..Default::default()
                 }
             }
@@ -1065,7 +1065,7 @@ impl InlaySnapshot {
         &self,
         cursor: &mut Cursor<'_, Transform, (InlayOffset, usize)>,
         range: &Range<InlayOffset>,
-        text_highlights: &TreeMap<Option<TypeId>, Arc<(HighlightStyle, Vec<Range<Anchor>>)>>,
+        text_highlights: &TextHighlights,
         highlight_endpoints: &mut Vec<HighlightEndpoint>,
     ) {
         while cursor.start().0 < range.end {
@@ -1178,7 +1178,7 @@ mod tests {
     use project::{InlayHint, InlayHintLabel, ResolveState};
     use rand::prelude::*;
     use settings::SettingsStore;
-    use std::{cmp::Reverse, env, sync::Arc};
+    use std::{any::TypeId, cmp::Reverse, env, sync::Arc};
     use text::Patch;
     use util::post_inc;
 
@@ -1677,7 +1677,7 @@ mod tests {
             text_highlight_ranges.sort_by_key(|range| (range.start, Reverse(range.end)));
             log::info!("highlighting text ranges {text_highlight_ranges:?}");
             text_highlights.insert(
-                Some(TypeId::of::<()>()),
+                HighlightKey::Type(TypeId::of::<()>()),
                 Arc::new((
                     HighlightStyle::default(),
                     text_highlight_ranges
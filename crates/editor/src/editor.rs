@@ -14,12 +14,15 @@
 //! If you're looking to improve Vim mode, you should check out Vim crate that wraps Editor and overrides it's behaviour.
 pub mod actions;
 mod blink_manager;
+mod bookmarks;
 pub mod display_map;
 mod editor_settings;
 mod element;
 mod inlay_hint_cache;
+mod macros;
 
 mod debounced_delay;
+mod ghost_text;
 mod git;
 mod highlight_matching_bracket;
 mod hover_links;
@@ -30,7 +33,9 @@ pub mod movement;
 mod persistence;
 mod rust_analyzer_ext;
 pub mod scroll;
+mod selection_drag;
 mod selections_collection;
+mod signature_help;
 
 #[cfg(test)]
 mod editor_tests;
@@ -41,6 +46,7 @@ pub(crate) use actions::*;
 use aho_corasick::AhoCorasick;
 use anyhow::{anyhow, Context as _, Result};
 use blink_manager::BlinkManager;
+pub use bookmarks::Bookmark;
 use client::{Collaborator, ParticipantIndex};
 use clock::ReplicaId;
 use collections::{BTreeMap, Bound, HashMap, HashSet, VecDeque};
@@ -54,6 +60,7 @@ use element::LineWithInvisibles;
 pub use element::{Cursor, EditorElement, HighlightedRange, HighlightedRangeLine};
 use futures::FutureExt;
 use fuzzy::{StringMatch, StringMatchCandidate};
+use ghost_text::GhostText;
 use git::diff_hunk_to_display;
 use gpui::{
     div, impl_actions, point, prelude::*, px, relative, rems, size, uniform_list, Action,
@@ -76,6 +83,10 @@ use language::{
     CodeLabel, Completion, CursorShape, Diagnostic, Documentation, IndentKind, IndentSize,
     Language, OffsetRangeExt, Point, Selection, SelectionGoal, TransactionId,
 };
+pub use macros::MacroCommand;
+use macros::MacroState;
+use selection_drag::SelectionDrag;
+pub use signature_help::{SignatureHelpPopover, SignatureHelpState};
 
 use hover_links::{HoverLink, HoveredLinkState, InlayHighlight};
 use lsp::{DiagnosticSeverity, LanguageServerId};
@@ -371,7 +382,7 @@ pub struct Editor {
     selection_history: SelectionHistory,
     autoclose_regions: Vec<AutocloseRegion>,
     snippet_stack: InvalidationStack<SnippetState>,
-    select_larger_syntax_node_stack: Vec<Box<[Selection<usize>]>>,
+    select_larger_syntax_node_history: HashMap<usize, Vec<SelectLargerSyntaxNodeState>>,
     ime_transaction: Option<TransactionId>,
     active_diagnostics: Option<ActiveDiagnosticGroup>,
     soft_wrap_mode_override: Option<language_settings::SoftWrap>,
@@ -397,6 +408,7 @@ pub struct Editor {
     next_completion_id: CompletionId,
     completion_documentation_pre_resolve_debounce: DebouncedDelay,
     available_code_actions: Option<(Model<Buffer>, Arc<[CodeAction]>)>,
+    code_actions_cache: CodeActionsCache,
     code_actions_task: Option<Task<()>>,
     document_highlights_task: Option<Task<()>>,
     pending_rename: Option<RenameState>,
@@ -412,11 +424,16 @@ pub struct Editor {
     leader_peer_id: Option<PeerId>,
     remote_id: Option<ViewId>,
     hover_state: HoverState,
+    signature_help_state: SignatureHelpState,
     gutter_hovered: bool,
     hovered_link_state: Option<HoveredLinkState>,
     copilot_state: CopilotState,
     inlay_hint_cache: InlayHintCache,
     next_inlay_id: usize,
+    ghost_text: Option<GhostText>,
+    bookmarks: Vec<Bookmark>,
+    macro_state: MacroState,
+    selection_drag: Option<SelectionDrag>,
     _subscriptions: Vec<Subscription>,
     pixel_position_of_newest_cursor: Option<gpui::Point<Pixels>>,
     gutter_width: Pixels,
@@ -580,6 +597,8 @@ struct AddSelectionsState {
 #[derive(Clone)]
 struct SelectNextState {
     query: AhoCorasick,
+    /// Whether the query is the word under a cursor, in which case it only matches whole words
+    /// regardless of the action's options.
     wordwise: bool,
     done: bool,
 }
@@ -593,6 +612,15 @@ impl std::fmt::Debug for SelectNextState {
     }
 }
 
+/// A selection's expansion to a larger syntax node, which can be undone by selecting a smaller
+/// syntax node as long as the selection still covers the range it was expanded to.
+#[derive(Debug)]
+struct SelectLargerSyntaxNodeState {
+    expanded_range: Range<Anchor>,
+    /// The selections that were merged into this one by the expansion, before expanding.
+    previous_selections: Box<[Selection<Anchor>]>,
+}
+
 #[derive(Debug)]
 struct AutocloseRegion {
     selection_id: usize,
@@ -800,21 +828,23 @@ impl CompletionsMenu {
         }
 
         let completion_index = self.matches[self.selected_item].candidate_id;
-        let Some(project) = project else {
+        let Some(project) = project.cloned() else {
             return;
         };
-
-        let resolve_task = project.update(cx, |project, cx| {
-            project.resolve_completions(vec![completion_index], self.completions.clone(), cx)
-        });
+        let completions = self.completions.clone();
 
         let delay_ms =
             EditorSettings::get_global(cx).completion_documentation_secondary_query_debounce;
         let delay = Duration::from_millis(delay_ms);
 
+        // Only resolve the documentation once the selection has settled, rather than for
+        // every completion that's selected while moving through the menu.
         self.selected_completion_documentation_resolve_debounce
             .lock()
-            .fire_new(delay, cx, |_, cx| {
+            .fire_new(delay, cx, move |_, cx| {
+                let resolve_task = project.update(cx, |project, cx| {
+                    project.resolve_completions(vec![completion_index], completions, cx)
+                });
                 cx.spawn(move |this, mut cx| async move {
                     if let Some(true) = resolve_task.await.log_err() {
                         this.update(&mut cx, |_, cx| cx.notify()).ok();
@@ -1076,10 +1106,15 @@ impl CompletionsMenu {
 
         for mat in &mut matches {
             let completion = &completions[mat.candidate_id];
-            mat.string = completion.label.text.clone();
-            for position in &mut mat.positions {
-                *position += completion.label.filter_range.start;
+            // Only highlight the matched characters if they were matched within the label.
+            if mat.string == completion.label.text[completion.label.filter_range.clone()] {
+                for position in &mut mat.positions {
+                    *position += completion.label.filter_range.start;
+                }
+            } else {
+                mat.positions.clear();
             }
+            mat.string = completion.label.text.clone();
         }
         drop(completions);
 
@@ -1320,6 +1355,60 @@ impl CopilotState {
     }
 }
 
+/// Code actions that were fetched for ranges of a buffer. They stay valid until the buffer is
+/// edited, reparsed, or receives new diagnostics, so moving the cursor back and forth doesn't
+/// query the language server again.
+#[derive(Default)]
+struct CodeActionsCache {
+    generation: Option<CodeActionsGeneration>,
+    actions: HashMap<Range<usize>, Option<Arc<[CodeAction]>>>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct CodeActionsGeneration {
+    buffer_id: BufferId,
+    version: clock::Global,
+    parse_count: usize,
+    diagnostics_update_count: usize,
+}
+
+impl CodeActionsGeneration {
+    fn new(buffer: &Buffer) -> Self {
+        Self {
+            buffer_id: buffer.remote_id(),
+            version: buffer.version(),
+            parse_count: buffer.parse_count(),
+            diagnostics_update_count: buffer.diagnostics_update_count(),
+        }
+    }
+}
+
+impl CodeActionsCache {
+    fn get(
+        &self,
+        generation: &CodeActionsGeneration,
+        range: &Range<usize>,
+    ) -> Option<Option<Arc<[CodeAction]>>> {
+        if self.generation.as_ref() != Some(generation) {
+            return None;
+        }
+        self.actions.get(range).cloned()
+    }
+
+    fn insert(
+        &mut self,
+        generation: CodeActionsGeneration,
+        range: Range<usize>,
+        actions: Option<Arc<[CodeAction]>>,
+    ) {
+        if self.generation.as_ref() != Some(&generation) {
+            self.actions.clear();
+            self.generation = Some(generation);
+        }
+        self.actions.insert(range, actions);
+    }
+}
+
 #[derive(Debug)]
 struct ActiveDiagnosticGroup {
     primary_range: Range<Anchor>,
@@ -1497,7 +1586,7 @@ impl Editor {
             selection_history: Default::default(),
             autoclose_regions: Default::default(),
             snippet_stack: Default::default(),
-            select_larger_syntax_node_stack: Vec::new(),
+            select_larger_syntax_node_history: HashMap::default(),
             ime_transaction: Default::default(),
             active_diagnostics: None,
             soft_wrap_mode_override,
@@ -1521,7 +1610,12 @@ impl Editor {
             next_completion_id: 0,
             completion_documentation_pre_resolve_debounce: DebouncedDelay::new(),
             next_inlay_id: 0,
+            ghost_text: None,
+            bookmarks: Vec::new(),
+            macro_state: Default::default(),
+            selection_drag: None,
             available_code_actions: Default::default(),
+            code_actions_cache: Default::default(),
             code_actions_task: Default::default(),
             document_highlights_task: Default::default(),
             pending_rename: Default::default(),
@@ -1538,6 +1632,7 @@ impl Editor {
             leader_peer_id: None,
             remote_id: None,
             hover_state: Default::default(),
+            signature_help_state: Default::default(),
             hovered_link_state: Default::default(),
             copilot_state: Default::default(),
             inlay_hint_cache: InlayHintCache::new(inlay_hint_settings),
@@ -1575,6 +1670,13 @@ impl Editor {
 
         this.end_selection(cx);
         this.scroll_manager.show_scrollbar(cx);
+        this.update_large_file_threshold(cx);
+        // Autoscroll requests made through the multi-buffer before the editor was opened
+        // are stale.
+        this.scroll_manager.applied_buffer_autoscroll_request_count = buffer
+            .read(cx)
+            .autoscroll_request()
+            .map_or(0, |(request_count, _)| request_count);
 
         if mode == EditorMode::Full {
             let should_auto_hide_scrollbars = cx.should_auto_hide_scrollbars();
@@ -1847,6 +1949,11 @@ impl Editor {
         old_cursor_position: &Anchor,
         cx: &mut ViewContext<Self>,
     ) {
+        if local {
+            self.buffer.update(cx, |buffer, cx| {
+                buffer.set_local_selections(self.selections.disjoint_anchors(), cx)
+            });
+        }
         if self.focus_handle.is_focused(cx) && self.leader_peer_id.is_none() {
             self.buffer.update(cx, |buffer, cx| {
                 buffer.set_active_selections(
@@ -1865,7 +1972,18 @@ impl Editor {
         self.add_selections_state = None;
         self.select_next_state = None;
         self.select_prev_state = None;
-        self.select_larger_syntax_node_stack.clear();
+        if !self.select_larger_syntax_node_history.is_empty() {
+            // Forget the expansions of selections that no longer exist.
+            let selection_ids = self
+                .selections
+                .disjoint_anchors()
+                .iter()
+                .chain(self.selections.pending_anchor().as_ref())
+                .map(|selection| selection.id)
+                .collect::<HashSet<_>>();
+            self.select_larger_syntax_node_history
+                .retain(|selection_id, _| selection_ids.contains(selection_id));
+        }
         self.invalidate_autoclose_regions(&self.selections.disjoint_anchors(), buffer);
         self.snippet_stack
             .invalidate(&self.selections.disjoint_anchors(), buffer);
@@ -1934,6 +2052,7 @@ impl Editor {
             }
 
             hide_hover(self, cx);
+            self.update_signature_help(cx);
 
             if old_cursor_position.to_display_point(&display_map).row()
                 != new_cursor_position.to_display_point(&display_map).row()
@@ -2053,7 +2172,16 @@ impl Editor {
                 goal_column,
                 scroll_delta,
             } => self.update_selection(position, goal_column, scroll_delta, cx),
-            SelectPhase::End => self.end_selection(cx),
+            SelectPhase::End => {
+                self.end_selection(cx);
+                let ranges = self
+                    .selections
+                    .disjoint_anchors()
+                    .iter()
+                    .map(|selection| selection.tail()..selection.head())
+                    .collect();
+                self.record_macro_command(MacroCommand::SelectRanges(ranges));
+            }
         }
     }
 
@@ -2127,16 +2255,9 @@ impl Editor {
                 auto_scroll = true;
             }
             3 => {
-                let position = display_map
-                    .clip_point(position, Bias::Left)
-                    .to_point(&display_map);
-                let line_start = display_map.prev_line_boundary(position).0;
-                let next_line_start = buffer.clip_point(
-                    display_map.next_line_boundary(position).0 + Point::new(1, 0),
-                    Bias::Left,
-                );
-                start = buffer.anchor_before(line_start);
-                end = buffer.anchor_before(next_line_start);
+                let line_range = line_selection_range(&display_map, position);
+                start = buffer.anchor_before(line_range.start);
+                end = buffer.anchor_before(line_range.end);
                 mode = SelectMode::Line(start.clone()..end.clone());
                 auto_scroll = true;
             }
@@ -2230,20 +2351,11 @@ impl Editor {
                 }
                 SelectMode::Line(original_range) => {
                     let original_range = original_range.to_point(&display_map.buffer_snapshot);
-
-                    let position = display_map
-                        .clip_point(position, Bias::Left)
-                        .to_point(&display_map);
-                    let line_start = display_map.prev_line_boundary(position).0;
-                    let next_line_start = buffer.clip_point(
-                        display_map.next_line_boundary(position).0 + Point::new(1, 0),
-                        Bias::Left,
-                    );
-
-                    if line_start < original_range.start {
-                        head = line_start
+                    let line_range = line_selection_range(&display_map, position);
+                    if line_range.start < original_range.start {
+                        head = line_range.start
                     } else {
-                        head = next_line_start
+                        head = line_range.end
                     }
 
                     if head <= original_range.start {
@@ -2355,6 +2467,14 @@ impl Editor {
             return;
         }
 
+        if self.hide_signature_help(cx) {
+            return;
+        }
+
+        if self.dismiss_ghost_text(cx) {
+            return;
+        }
+
         if self.discard_copilot_suggestion(cx) {
             return;
         }
@@ -2475,7 +2595,20 @@ impl Editor {
                     // If an opening bracket is 1 character long and is typed while
                     // text is selected, then surround that text with the bracket pair.
                     else if is_bracket_pair_start && bracket_pair.start.chars().count() == 1 {
-                        edits.push((selection.start..selection.start, text.clone()));
+                        // Surround the selected text within each excerpt separately, so that
+                        // both brackets of every pair are inserted into the same buffer.
+                        let mut start = selection.start;
+                        for boundary in snapshot.excerpt_boundaries_in_range((
+                            Bound::Excluded(selection.start),
+                            Bound::Excluded(selection.end),
+                        )) {
+                            let end_row = boundary.row - 1;
+                            let end = Point::new(end_row, snapshot.line_len(end_row));
+                            edits.push((start..start, text.clone()));
+                            edits.push((end..end, bracket_pair.end.as_str().into()));
+                            start = Point::new(boundary.row, 0);
+                        }
+                        edits.push((start..start, text.clone()));
                         edits.push((
                             selection.end..selection.end,
                             bracket_pair.end.as_str().into(),
@@ -2576,6 +2709,7 @@ impl Editor {
                 this.trigger_completion_on_input(&text, cx);
                 this.refresh_copilot_suggestions(true, cx);
             }
+            this.trigger_signature_help_on_input(&text, cx);
         });
     }
 
@@ -2629,8 +2763,9 @@ impl Editor {
                                 });
                             // Comment extension on newline is allowed only for cursor selections
                             let comment_delimiter = language.line_comment_prefixes().filter(|_| {
-                                let is_comment_extension_enabled =
-                                    multi_buffer.settings_at(0, cx).extend_comment_on_newline;
+                                let is_comment_extension_enabled = multi_buffer
+                                    .settings_at(start, cx)
+                                    .extend_comment_on_newline;
                                 is_cursor && is_comment_extension_enabled
                             });
                             let get_comment_delimiter = |delimiters: &[Arc<str>]| {
@@ -2903,8 +3038,8 @@ impl Editor {
         }
     }
 
-    /// If any empty selections is touching the start of its innermost containing autoclose
-    /// region, expand it to select the brackets.
+    /// If any empty selection is inside of an empty autoclose region, expand it to select
+    /// the brackets, so that they're deleted together.
     fn select_autoclose_pair(&mut self, cx: &mut ViewContext<Self>) {
         let selections = self.selections.all::<usize>(cx);
         let buffer = self.buffer.read(cx).read(cx);
@@ -2912,7 +3047,7 @@ impl Editor {
         for (mut selection, region) in self.selections_with_autoclose_regions(selections, &buffer) {
             if let (Some(region), true) = (region, selection.is_empty()) {
                 let mut range = region.range.to_offset(&buffer);
-                if selection.start == range.start {
+                if range.is_empty() && selection.start == range.start {
                     if range.start >= region.pair.start.len() {
                         range.start -= region.pair.start.len();
                         if buffer.contains_str_at(range.start, &region.pair.start) {
@@ -3263,8 +3398,7 @@ impl Editor {
                             .map(|(id, completion)| {
                                 StringMatchCandidate::new(
                                     id,
-                                    completion.label.text[completion.label.filter_range.clone()]
-                                        .into(),
+                                    completion_filter_text(completion).into(),
                                 )
                             })
                             .collect(),
@@ -3646,6 +3780,20 @@ impl Editor {
             return None;
         }
 
+        let (generation, range) = {
+            let buffer = start_buffer.read(cx);
+            (
+                CodeActionsGeneration::new(buffer),
+                start.to_offset(buffer)..end.to_offset(buffer),
+            )
+        };
+        if let Some(actions) = self.code_actions_cache.get(&generation, &range) {
+            self.code_actions_task = None;
+            self.available_code_actions = actions.map(|actions| (start_buffer, actions));
+            cx.notify();
+            return None;
+        }
+
         self.code_actions_task = Some(cx.spawn(|this, mut cx| async move {
             cx.background_executor()
                 .timer(CODE_ACTIONS_DEBOUNCE_TIMEOUT)
@@ -3660,13 +3808,18 @@ impl Editor {
             };
 
             this.update(&mut cx, |this, cx| {
-                this.available_code_actions = actions.and_then(|actions| {
+                let actions: Option<Arc<[CodeAction]>> = actions.and_then(|actions| {
                     if actions.is_empty() {
                         None
                     } else {
-                        Some((start_buffer, actions.into()))
+                        Some(actions.into())
                     }
                 });
+                if generation == CodeActionsGeneration::new(start_buffer.read(cx)) {
+                    this.code_actions_cache
+                        .insert(generation, range, actions.clone());
+                }
+                this.available_code_actions = actions.map(|actions| (start_buffer, actions));
                 cx.notify();
             })
             .log_err();
@@ -3674,6 +3827,11 @@ impl Editor {
         None
     }
 
+    /// Whether code actions are available for the newest selection, without requesting them.
+    pub fn has_available_code_actions(&self) -> bool {
+        self.available_code_actions.is_some()
+    }
+
     fn refresh_document_highlights(&mut self, cx: &mut ViewContext<Self>) -> Option<()> {
         if self.pending_rename.is_some() {
             return None;
@@ -3724,10 +3882,9 @@ impl Editor {
                     let cursor_buffer_snapshot = cursor_buffer.read(cx);
                     let mut write_ranges = Vec::new();
                     let mut read_ranges = Vec::new();
+                    let excerpts = buffer.excerpts_for_buffer(&cursor_buffer, cx);
                     for highlight in highlights {
-                        for (excerpt_id, excerpt_range) in
-                            buffer.excerpts_for_buffer(&cursor_buffer, cx)
-                        {
+                        for (excerpt_id, excerpt_range) in &excerpts {
                             let start = highlight
                                 .range
                                 .start
@@ -3742,11 +3899,11 @@ impl Editor {
 
                             let range = Anchor {
                                 buffer_id,
-                                excerpt_id: excerpt_id.clone(),
+                                excerpt_id: *excerpt_id,
                                 text_anchor: start,
                             }..Anchor {
                                 buffer_id,
-                                excerpt_id,
+                                excerpt_id: *excerpt_id,
                                 text_anchor: end,
                             };
                             if highlight.kind == lsp::DocumentHighlightKind::WRITE {
@@ -4054,7 +4211,7 @@ impl Editor {
         is_active: bool,
         cx: &mut ViewContext<Self>,
     ) -> Option<IconButton> {
-        if self.available_code_actions.is_some() {
+        if self.has_available_code_actions() {
             Some(
                 IconButton::new("code_actions_indicator", ui::IconName::Bolt)
                     .icon_size(IconSize::Small)
@@ -4281,7 +4438,19 @@ impl Editor {
                                 buffer.indent_size_for_line(line_buffer_range.start.row);
                             let indent_len = match indent_size.kind {
                                 IndentKind::Space => {
-                                    buffer.settings_at(line_buffer_range.start, cx).tab_size
+                                    match this.buffer.read(cx).indent_size_at(old_head, cx) {
+                                        IndentSize {
+                                            kind: IndentKind::Space,
+                                            len,
+                                        } => NonZeroU32::new(len),
+                                        IndentSize {
+                                            kind: IndentKind::Tab,
+                                            ..
+                                        } => None,
+                                    }
+                                    .unwrap_or_else(|| {
+                                        buffer.settings_at(line_buffer_range.start, cx).tab_size
+                                    })
                                 }
                                 IndentKind::Tab => NonZeroU32::new(1).unwrap(),
                             };
@@ -4395,11 +4564,11 @@ impl Editor {
             }
 
             // Otherwise, insert a hard or soft tab.
-            let settings = buffer.settings_at(cursor, cx);
-            let tab_size = if settings.hard_tabs {
+            let indent_size = buffer.indent_size_at(cursor, cx);
+            let tab_size = if indent_size.kind == IndentKind::Tab {
                 IndentSize::tab()
             } else {
-                let tab_size = settings.tab_size.get();
+                let tab_size = indent_size.len;
                 let char_column = snapshot
                     .text_for_range(Point::new(cursor.row, 0)..cursor)
                     .flat_map(str::chars)
@@ -4452,13 +4621,6 @@ impl Editor {
         delta_for_start_row: u32,
         cx: &AppContext,
     ) -> u32 {
-        let settings = buffer.settings_at(selection.start, cx);
-        let tab_size = settings.tab_size.get();
-        let indent_kind = if settings.hard_tabs {
-            IndentKind::Tab
-        } else {
-            IndentKind::Space
-        };
         let mut start_row = selection.start.row;
         let mut end_row = selection.end.row + 1;
 
@@ -4481,6 +4643,11 @@ impl Editor {
 
         let mut delta_for_end_row = 0;
         for row in start_row..end_row {
+            // Resolve the indentation for each row, since a selection can span excerpts
+            // of buffers that are indented differently.
+            let indent_size = buffer.indent_size_at(Point::new(row, 0), cx);
+            let tab_size = indent_size.len;
+            let indent_kind = indent_size.kind;
             let current_indent = snapshot.indent_size_for_line(row);
             let indent_delta = match (current_indent.kind, indent_kind) {
                 (IndentKind::Space, IndentKind::Space) => {
@@ -4523,8 +4690,6 @@ impl Editor {
             let buffer = self.buffer.read(cx);
             let snapshot = buffer.snapshot(cx);
             for selection in &selections {
-                let settings = buffer.settings_at(selection.start, cx);
-                let tab_size = settings.tab_size.get();
                 let mut rows = selection.spanned_rows(false, &display_map);
 
                 // Avoid re-outdenting a row that has already been outdented by a
@@ -4536,6 +4701,17 @@ impl Editor {
                 }
 
                 for row in rows {
+                    let row_start = Point::new(row, 0);
+                    let tab_size = match buffer.indent_size_at(row_start, cx) {
+                        IndentSize {
+                            kind: IndentKind::Space,
+                            len,
+                        } => len,
+                        IndentSize {
+                            kind: IndentKind::Tab,
+                            ..
+                        } => buffer.settings_at(row_start, cx).tab_size.get(),
+                    };
                     let indent_size = snapshot.indent_size_for_line(row);
                     if indent_size.len > 0 {
                         let deletion_len = match indent_size.kind {
@@ -4688,9 +4864,29 @@ impl Editor {
                 for row in row_range.rev() {
                     let end_of_line = Point::new(row, snapshot.line_len(row));
                     let indent = snapshot.indent_size_for_line(row + 1);
-                    let start_of_next_line = Point::new(row + 1, indent.len);
+                    let mut start_of_next_line = Point::new(row + 1, indent.len);
+
+                    // When joining two line comments, drop the next line's comment prefix
+                    let start_of_line = Point::new(row, snapshot.indent_size_for_line(row).len);
+                    let comment_prefix_len =
+                        snapshot.language_scope_at(start_of_line).and_then(|scope| {
+                            scope.line_comment_prefixes()?.iter().find_map(|prefix| {
+                                let prefix = prefix.trim_end();
+                                (!prefix.is_empty()
+                                    && snapshot.contains_str_at(start_of_line, prefix)
+                                    && snapshot.contains_str_at(start_of_next_line, prefix))
+                                .then_some(prefix.len())
+                            })
+                        });
+                    if let Some(comment_prefix_len) = comment_prefix_len {
+                        start_of_next_line.column += comment_prefix_len as u32;
+                        start_of_next_line.column += snapshot
+                            .chars_at(start_of_next_line)
+                            .take_while(|c| *c == ' ' || *c == '\t')
+                            .count() as u32;
+                    }
 
-                    let replace = if snapshot.line_len(row + 1) > indent.len {
+                    let replace = if snapshot.line_len(row + 1) > start_of_next_line.column {
                         " "
                     } else {
                         ""
@@ -4708,6 +4904,145 @@ impl Editor {
         });
     }
 
+    pub fn rewrap(&mut self, _: &Rewrap, cx: &mut ViewContext<Self>) {
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        let max_row = buffer.max_point().row;
+        let starts_excerpt = |row: u32| {
+            buffer
+                .excerpt_boundaries_in_range(Point::new(row, 0)..=Point::new(row, 0))
+                .next()
+                .is_some()
+        };
+
+        let mut edits = Vec::new();
+        let mut last_rewrapped_row = None;
+        for selection in self.selections.all::<Point>(cx) {
+            let mut start_row = selection.start.row;
+            let mut end_row =
+                if selection.end.row > selection.start.row && selection.end.column == 0 {
+                    selection.end.row - 1
+                } else {
+                    selection.end.row
+                };
+
+            // Without a selection, rewrap the entire paragraph containing the cursor.
+            if selection.is_empty() {
+                let Some((prefix, _)) = rewrap_line_prefix(&buffer, start_row) else {
+                    continue;
+                };
+                let in_paragraph = |row: u32| {
+                    rewrap_line_prefix(&buffer, row)
+                        .map_or(false, |(row_prefix, _)| row_prefix == prefix)
+                };
+                while start_row > 0 && !starts_excerpt(start_row) && in_paragraph(start_row - 1) {
+                    start_row -= 1;
+                }
+                while end_row < max_row && !starts_excerpt(end_row + 1) && in_paragraph(end_row + 1)
+                {
+                    end_row += 1;
+                }
+            }
+
+            // If multiple selections contain a given row, avoid rewrapping it more than once.
+            if let Some(last_rewrapped_row) = last_rewrapped_row {
+                start_row = start_row.max(last_rewrapped_row + 1);
+            }
+            if start_row > end_row {
+                continue;
+            }
+            last_rewrapped_row = Some(end_row);
+
+            // Paragraphs are separated by blank lines, changes in indentation or comment
+            // prefix, and excerpt boundaries.
+            let mut row = start_row;
+            while row <= end_row {
+                let Some((prefix, _)) = rewrap_line_prefix(&buffer, row) else {
+                    row += 1;
+                    continue;
+                };
+
+                let paragraph_start_row = row;
+                let mut words = Vec::new();
+                while row <= end_row {
+                    let Some((row_prefix, content_start)) = rewrap_line_prefix(&buffer, row) else {
+                        break;
+                    };
+                    if row_prefix != prefix || (row > paragraph_start_row && starts_excerpt(row)) {
+                        break;
+                    }
+
+                    let line_end = Point::new(row, buffer.line_len(row));
+                    let mut column = content_start.column;
+                    let mut word = None;
+                    for ch in buffer
+                        .text_for_range(content_start..line_end)
+                        .flat_map(str::chars)
+                    {
+                        if ch.is_whitespace() {
+                            if let Some((start_column, width)) = word.take() {
+                                words.push((
+                                    Point::new(row, start_column)..Point::new(row, column),
+                                    width,
+                                ));
+                            }
+                        } else {
+                            word.get_or_insert((column, 0)).1 += 1;
+                        }
+                        column += ch.len_utf8() as u32;
+                    }
+                    if let Some((start_column, width)) = word {
+                        words.push((Point::new(row, start_column)..line_end, width));
+                    }
+                    row += 1;
+                }
+
+                // Lay the words out greedily, only editing the text between them so that
+                // anchors within the words are preserved.
+                let paragraph_start = Point::new(paragraph_start_row, 0);
+                let wrap_column = self
+                    .buffer
+                    .read(cx)
+                    .editor_config_at(paragraph_start, cx)
+                    .max_line_length
+                    .unwrap_or_else(|| {
+                        buffer
+                            .settings_at(paragraph_start, cx)
+                            .preferred_line_length
+                    }) as usize;
+                let prefix_width = prefix.chars().count();
+                let mut line_width = 0;
+                let mut gap_start = Point::new(paragraph_start_row, 0);
+                for (ix, (word, word_width)) in words.into_iter().enumerate() {
+                    let separator = if ix == 0 {
+                        line_width = prefix_width + word_width;
+                        prefix.clone()
+                    } else if line_width + 1 + word_width > wrap_column {
+                        line_width = prefix_width + word_width;
+                        format!("\n{prefix}")
+                    } else {
+                        line_width += 1 + word_width;
+                        " ".to_string()
+                    };
+                    let gap = gap_start..word.start;
+                    if buffer.text_for_range(gap.clone()).collect::<String>() != separator {
+                        edits.push((gap, separator));
+                    }
+                    gap_start = word.end;
+                }
+                let paragraph_end = Point::new(row - 1, buffer.line_len(row - 1));
+                if gap_start < paragraph_end {
+                    edits.push((gap_start..paragraph_end, String::new()));
+                }
+            }
+        }
+
+        self.transact(cx, |this, cx| {
+            this.buffer
+                .update(cx, |buffer, cx| buffer.edit(edits, None, cx));
+            this.request_autoscroll(Autoscroll::fit(), cx);
+        });
+    }
+
     pub fn sort_lines_case_sensitive(
         &mut self,
         _: &SortLinesCaseSensitive,
@@ -4895,14 +5230,16 @@ impl Editor {
         let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
         let buffer = self.buffer.read(cx).snapshot(cx);
 
-        let mut new_selections = Vec::new();
-        let mut edits = Vec::new();
+        let mut new_selections: Vec<Selection<usize>> = Vec::new();
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
         let mut selection_adjustment = 0i32;
+        // The index of the first selection converted by the last edit.
+        let mut edit_selections_start = 0;
 
         for selection in self.selections.all::<usize>(cx) {
             let selection_is_empty = selection.is_empty();
 
-            let (start, end) = if selection_is_empty {
+            let (mut start, mut end) = if selection_is_empty {
                 let word_range = movement::surrounding_word(
                     &display_map,
                     selection.start.to_display_point(&display_map),
@@ -4914,13 +5251,32 @@ impl Editor {
                 (selection.start, selection.end)
             };
 
+            // Convert overlapping ranges, such as a word containing multiple cursors, together.
+            if edits
+                .last()
+                .map_or(false, |(last_range, _)| start < last_range.end)
+            {
+                let (last_range, last_text) = edits.pop().unwrap();
+                selection_adjustment -= last_range.len() as i32 - last_text.len() as i32;
+                start = last_range.start;
+                end = cmp::max(last_range.end, end);
+            } else {
+                edit_selections_start = new_selections.len();
+            }
+
             let text = buffer.text_for_range(start..end).collect::<String>();
             let old_length = text.len() as i32;
             let text = callback(&text);
 
+            let new_start = (start as i32 - selection_adjustment) as usize;
+            let new_end = ((start + text.len()) as i32 - selection_adjustment) as usize;
+            for selection in &mut new_selections[edit_selections_start..] {
+                selection.start = new_start;
+                selection.end = new_end;
+            }
             new_selections.push(Selection {
-                start: (start as i32 - selection_adjustment) as usize,
-                end: ((start + text.len()) as i32 - selection_adjustment) as usize,
+                start: new_start,
+                end: new_end,
                 goal: SelectionGoal::None,
                 ..selection
             });
@@ -4964,14 +5320,27 @@ impl Editor {
                 }
             }
 
-            // Copy the text from the selected row region and splice it at the start of the region.
-            let start = Point::new(rows.start, 0);
-            let end = Point::new(rows.end - 1, buffer.line_len(rows.end - 1));
-            let text = buffer
-                .text_for_range(start..end)
-                .chain(Some("\n"))
-                .collect::<String>();
-            edits.push((start..start, text));
+            // Duplicate the rows within each excerpt separately, so that lines aren't copied
+            // into another excerpt's buffer.
+            let boundary_rows = buffer
+                .excerpt_boundaries_in_range(
+                    Point::new(rows.start + 1, 0).min(buffer.max_point())
+                        ..Point::new(rows.end, 0).min(buffer.max_point()),
+                )
+                .map(|boundary| boundary.row)
+                .filter(|row| rows.contains(row) && *row > rows.start);
+            let mut start_row = rows.start;
+            for end_row in boundary_rows.chain(Some(rows.end)) {
+                // Copy the text from the selected row region and splice it at the start of the region.
+                let start = Point::new(start_row, 0);
+                let end = Point::new(end_row - 1, buffer.line_len(end_row - 1));
+                let text = buffer
+                    .text_for_range(start..end)
+                    .chain(Some("\n"))
+                    .collect::<String>();
+                edits.push((start..start, text));
+                start_row = end_row;
+            }
         }
 
         self.transact(cx, |this, cx| {
@@ -5229,7 +5598,7 @@ impl Editor {
     }
 
     pub fn cut(&mut self, _: &Cut, cx: &mut ViewContext<Self>) {
-        let mut text = String::new();
+        let mut text = Rope::new();
         let buffer = self.buffer.read(cx).snapshot(cx);
         let mut selections = self.selections.all::<Point>(cx);
         let mut clipboard_selections = Vec::with_capacity(selections.len());
@@ -5238,25 +5607,33 @@ impl Editor {
             let mut is_first = true;
             for selection in &mut selections {
                 let is_entire_line = selection.is_empty() || self.selections.line_mode;
+                let includes_last_line = selection.end.row == max_point.row;
+                let mut copied_range = selection.range();
                 if is_entire_line {
-                    selection.start = Point::new(selection.start.row, 0);
-                    selection.end = cmp::min(max_point, Point::new(selection.end.row + 1, 0));
+                    copied_range = Point::new(selection.start.row, 0)
+                        ..cmp::min(max_point, Point::new(selection.end.row + 1, 0));
+                    let range =
+                        buffer.range_to_delete_rows(selection.start.row..=selection.end.row);
+                    selection.start = range.start;
+                    selection.end = range.end;
                     selection.goal = SelectionGoal::None;
                 }
                 if is_first {
                     is_first = false;
                 } else {
-                    text += "\n";
+                    text.push("\n");
                 }
-                let mut len = 0;
-                for chunk in buffer.text_for_range(selection.start..selection.end) {
-                    text.push_str(chunk);
-                    len += chunk.len();
+                let prefix_len = text.len();
+                text.append(buffer.rope_for_range(copied_range.clone()));
+                // Entire lines always end in a newline, so that pasting them inserts lines.
+                if is_entire_line && includes_last_line {
+                    text.push("\n");
                 }
+                let len = text.len() - prefix_len;
                 clipboard_selections.push(ClipboardSelection {
                     len,
                     is_entire_line,
-                    first_line_indent: buffer.indent_size_for_line(selection.start.row).len,
+                    first_line_indent: buffer.indent_size_for_line(copied_range.start.row).len,
                 });
             }
         }
@@ -5266,14 +5643,16 @@ impl Editor {
                 s.select(selections);
             });
             this.insert("", cx);
-            cx.write_to_clipboard(ClipboardItem::new(text).with_metadata(clipboard_selections));
+            cx.write_to_clipboard(
+                ClipboardItem::new(text.to_string()).with_metadata(clipboard_selections),
+            );
         });
     }
 
     pub fn copy(&mut self, _: &Copy, cx: &mut ViewContext<Self>) {
         let selections = self.selections.all::<Point>(cx);
         let buffer = self.buffer.read(cx).read(cx);
-        let mut text = String::new();
+        let mut text = Rope::new();
 
         let mut clipboard_selections = Vec::with_capacity(selections.len());
         {
@@ -5290,13 +5669,15 @@ impl Editor {
                 if is_first {
                     is_first = false;
                 } else {
-                    text += "\n";
+                    text.push("\n");
                 }
-                let mut len = 0;
-                for chunk in buffer.text_for_range(start..end) {
-                    text.push_str(chunk);
-                    len += chunk.len();
+                let prefix_len = text.len();
+                text.append(buffer.rope_for_range(start..end));
+                // Entire lines always end in a newline, so that pasting them inserts lines.
+                if is_entire_line && selection.end.row == max_point.row {
+                    text.push("\n");
                 }
+                let len = text.len() - prefix_len;
                 clipboard_selections.push(ClipboardSelection {
                     len,
                     is_entire_line,
@@ -5305,7 +5686,9 @@ impl Editor {
             }
         }
 
-        cx.write_to_clipboard(ClipboardItem::new(text).with_metadata(clipboard_selections));
+        cx.write_to_clipboard(
+            ClipboardItem::new(text.to_string()).with_metadata(clipboard_selections),
+        );
     }
 
     pub fn paste(&mut self, _: &Paste, cx: &mut ViewContext<Self>) {
@@ -5390,7 +5773,12 @@ impl Editor {
         }
 
         if let Some(tx_id) = self.buffer.update(cx, |buffer, cx| buffer.undo(cx)) {
-            if let Some((selections, _)) = self.selection_history.transaction(tx_id).cloned() {
+            let selections = self
+                .selection_history
+                .transaction(tx_id)
+                .or_else(|| self.buffer.read(cx).selections_for_transaction(tx_id))
+                .map(|(selections, _)| selections.clone());
+            if let Some(selections) = selections {
                 self.change_selections(None, cx, |s| {
                     s.select_anchors(selections.to_vec());
                 });
@@ -5408,8 +5796,12 @@ impl Editor {
         }
 
         if let Some(tx_id) = self.buffer.update(cx, |buffer, cx| buffer.redo(cx)) {
-            if let Some((_, Some(selections))) = self.selection_history.transaction(tx_id).cloned()
-            {
+            let selections = self
+                .selection_history
+                .transaction(tx_id)
+                .or_else(|| self.buffer.read(cx).selections_for_transaction(tx_id))
+                .and_then(|(_, selections)| selections.clone());
+            if let Some(selections) = selections {
                 self.change_selections(None, cx, |s| {
                     s.select_anchors(selections.to_vec());
                 });
@@ -6334,10 +6726,11 @@ impl Editor {
     pub fn select_next_match_internal(
         &mut self,
         display_map: &DisplaySnapshot,
-        replace_newest: bool,
+        action: &SelectNext,
         autoscroll: Option<Autoscroll>,
         cx: &mut ViewContext<Self>,
     ) -> Result<()> {
+        let replace_newest = action.replace_newest;
         fn select_next_match_ranges(
             this: &mut Editor,
             range: Range<usize>,
@@ -6358,6 +6751,8 @@ impl Editor {
         let mut selections = self.selections.all::<usize>(cx);
         if let Some(mut select_next_state) = self.select_next_state.take() {
             let query = &select_next_state.query;
+            let wrap_around = !action.stop_at_end;
+            let whole_word = select_next_state.wordwise || action.whole_word;
             if !select_next_state.done {
                 let first_selection = selections.iter().min_by_key(|s| s.id).unwrap();
                 let last_selection = selections.iter().max_by_key(|s| s.id).unwrap();
@@ -6365,7 +6760,12 @@ impl Editor {
 
                 let bytes_after_last_selection =
                     buffer.bytes_in_range(last_selection.end..buffer.len());
-                let bytes_before_first_selection = buffer.bytes_in_range(0..first_selection.start);
+                let wrapped_end = if wrap_around {
+                    first_selection.start
+                } else {
+                    0
+                };
+                let bytes_before_first_selection = buffer.bytes_in_range(0..wrapped_end);
                 let query_matches = query
                     .stream_find_iter(bytes_after_last_selection)
                     .map(|result| (last_selection.end, result))
@@ -6382,19 +6782,16 @@ impl Editor {
                     let display_range = offset_range.start.to_display_point(&display_map)
                         ..offset_range.end.to_display_point(&display_map);
 
-                    if !select_next_state.wordwise
+                    // TODO: This is n^2, because we might check all the selections
+                    if (!whole_word
                         || (!movement::is_inside_word(&display_map, display_range.start)
-                            && !movement::is_inside_word(&display_map, display_range.end))
-                    {
-                        // TODO: This is n^2, because we might check all the selections
-                        if selections
+                            && !movement::is_inside_word(&display_map, display_range.end)))
+                        && !selections
                             .iter()
-                            .find(|selection| selection.range().overlaps(&offset_range))
-                            .is_none()
-                        {
-                            next_selected_range = Some(offset_range);
-                            break;
-                        }
+                            .any(|selection| selection.range().overlaps(&offset_range))
+                    {
+                        next_selected_range = Some(offset_range);
+                        break;
                     }
                 }
 
@@ -6406,7 +6803,9 @@ impl Editor {
                         autoscroll,
                         cx,
                     );
-                } else {
+                } else if wrap_around && whole_word == select_next_state.wordwise {
+                    // Only remember that there are no more matches if the action didn't narrow
+                    // the search, as later actions may not.
                     select_next_state.done = true;
                 }
             }
@@ -6488,7 +6887,7 @@ impl Editor {
                     wordwise: false,
                     done: false,
                 });
-                self.select_next_match_internal(display_map, replace_newest, autoscroll, cx)?;
+                self.select_next_match_internal(display_map, action, autoscroll, cx)?;
             }
         }
         Ok(())
@@ -6502,7 +6901,7 @@ impl Editor {
         self.push_to_selection_history();
         let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
 
-        self.select_next_match_internal(&display_map, false, None, cx)?;
+        self.select_next_match_internal(&display_map, &SelectNext::default(), None, cx)?;
         let Some(select_next_state) = self.select_next_state.as_mut() else {
             return Ok(());
         };
@@ -6572,12 +6971,7 @@ impl Editor {
     pub fn select_next(&mut self, action: &SelectNext, cx: &mut ViewContext<Self>) -> Result<()> {
         self.push_to_selection_history();
         let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
-        self.select_next_match_internal(
-            &display_map,
-            action.replace_newest,
-            Some(Autoscroll::newest()),
-            cx,
-        )?;
+        self.select_next_match_internal(&display_map, action, Some(Autoscroll::newest()), cx)?;
         Ok(())
     }
 
@@ -6592,6 +6986,8 @@ impl Editor {
         let mut selections = self.selections.all::<usize>(cx);
         if let Some(mut select_prev_state) = self.select_prev_state.take() {
             let query = &select_prev_state.query;
+            let wrap_around = !action.stop_at_start;
+            let whole_word = select_prev_state.wordwise || action.whole_word;
             if !select_prev_state.done {
                 let first_selection = selections.iter().min_by_key(|s| s.id).unwrap();
                 let last_selection = selections.iter().max_by_key(|s| s.id).unwrap();
@@ -6599,8 +6995,13 @@ impl Editor {
                 // When we're iterating matches backwards, the oldest match will actually be the furthest one in the buffer.
                 let bytes_before_last_selection =
                     buffer.reversed_bytes_in_range(0..last_selection.start);
+                let wrapped_start = if wrap_around {
+                    first_selection.end
+                } else {
+                    buffer.len()
+                };
                 let bytes_after_first_selection =
-                    buffer.reversed_bytes_in_range(first_selection.end..buffer.len());
+                    buffer.reversed_bytes_in_range(wrapped_start..buffer.len());
                 let query_matches = query
                     .stream_find_iter(bytes_before_last_selection)
                     .map(|result| (last_selection.start, result))
@@ -6616,9 +7017,12 @@ impl Editor {
                     let display_range = offset_range.start.to_display_point(&display_map)
                         ..offset_range.end.to_display_point(&display_map);
 
-                    if !select_prev_state.wordwise
+                    if (!whole_word
                         || (!movement::is_inside_word(&display_map, display_range.start)
-                            && !movement::is_inside_word(&display_map, display_range.end))
+                            && !movement::is_inside_word(&display_map, display_range.end)))
+                        && !selections
+                            .iter()
+                            .any(|selection| selection.range().overlaps(&offset_range))
                     {
                         next_selected_range = Some(offset_range);
                         break;
@@ -6633,7 +7037,9 @@ impl Editor {
                         }
                         s.insert_range(next_selected_range);
                     });
-                } else {
+                } else if wrap_around && whole_word == select_prev_state.wordwise {
+                    // Only remember that there are no more matches if the action didn't narrow
+                    // the search, as later actions may not.
                     select_prev_state.done = true;
                 }
             }
@@ -6802,19 +7208,7 @@ impl Editor {
                 }
             }
 
-            // TODO: Handle selections that cross excerpts
             for selection in &mut selections {
-                let start_column = snapshot.indent_size_for_line(selection.start.row).len;
-                let language = if let Some(language) =
-                    snapshot.language_scope_at(Point::new(selection.start.row, start_column))
-                {
-                    language
-                } else {
-                    continue;
-                };
-
-                selection_edit_ranges.clear();
-
                 // If multiple selections contain a given row, avoid processing that
                 // row more than once.
                 let mut start_row = selection.start.row;
@@ -6833,83 +7227,114 @@ impl Editor {
                     continue;
                 }
 
-                // If the language has line comments, toggle those.
-                if let Some(full_comment_prefix) = language
-                    .line_comment_prefixes()
-                    .and_then(|prefixes| prefixes.first())
+                // Toggle the rows of each excerpt separately, using the comment syntax of
+                // that excerpt's language.
+                let mut excerpt_row_ranges = Vec::new();
+                let mut excerpt_start_row = start_row;
+                for boundary_row in snapshot
+                    .excerpt_boundaries_in_range(
+                        Point::new(start_row + 1, 0).min(snapshot.max_point())
+                            ..=Point::new(end_row, 0),
+                    )
+                    .map(|boundary| boundary.row)
+                    .filter(|row| *row > start_row && *row <= end_row)
                 {
-                    // Split the comment prefix's trailing whitespace into a separate string,
-                    // as that portion won't be used for detecting if a line is a comment.
-                    let comment_prefix = full_comment_prefix.trim_end_matches(' ');
-                    let comment_prefix_whitespace = &full_comment_prefix[comment_prefix.len()..];
-                    let mut all_selection_lines_are_comments = true;
-
-                    for row in start_row..=end_row {
-                        if start_row < end_row && snapshot.is_line_blank(row) {
-                            continue;
+                    excerpt_row_ranges.push((excerpt_start_row, boundary_row - 1));
+                    excerpt_start_row = boundary_row;
+                }
+                excerpt_row_ranges.push((excerpt_start_row, end_row));
+
+                for (start_row, end_row) in excerpt_row_ranges {
+                    let start_column = snapshot.indent_size_for_line(start_row).len;
+                    let Some(language) =
+                        snapshot.language_scope_at(Point::new(start_row, start_column))
+                    else {
+                        continue;
+                    };
+
+                    selection_edit_ranges.clear();
+
+                    // If the language has line comments, toggle those.
+                    if let Some(full_comment_prefix) = language
+                        .line_comment_prefixes()
+                        .and_then(|prefixes| prefixes.first())
+                    {
+                        // Split the comment prefix's trailing whitespace into a separate string,
+                        // as that portion won't be used for detecting if a line is a comment.
+                        let comment_prefix = full_comment_prefix.trim_end_matches(' ');
+                        let comment_prefix_whitespace =
+                            &full_comment_prefix[comment_prefix.len()..];
+                        let mut all_selection_lines_are_comments = true;
+
+                        for row in start_row..=end_row {
+                            if start_row < end_row && snapshot.is_line_blank(row) {
+                                continue;
+                            }
+
+                            let prefix_range = comment_prefix_range(
+                                snapshot.deref(),
+                                row,
+                                comment_prefix,
+                                comment_prefix_whitespace,
+                            );
+                            if prefix_range.is_empty() {
+                                all_selection_lines_are_comments = false;
+                            }
+                            selection_edit_ranges.push(prefix_range);
                         }
 
+                        if all_selection_lines_are_comments {
+                            edits.extend(
+                                selection_edit_ranges
+                                    .iter()
+                                    .cloned()
+                                    .map(|range| (range, empty_str.clone())),
+                            );
+                        } else {
+                            let min_column = selection_edit_ranges
+                                .iter()
+                                .map(|r| r.start.column)
+                                .min()
+                                .unwrap_or(0);
+                            edits.extend(selection_edit_ranges.iter().map(|range| {
+                                let position = Point::new(range.start.row, min_column);
+                                (position..position, full_comment_prefix.clone())
+                            }));
+                        }
+                    } else if let Some((full_comment_prefix, comment_suffix)) =
+                        language.block_comment_delimiters()
+                    {
+                        let comment_prefix = full_comment_prefix.trim_end_matches(' ');
+                        let comment_prefix_whitespace =
+                            &full_comment_prefix[comment_prefix.len()..];
                         let prefix_range = comment_prefix_range(
                             snapshot.deref(),
-                            row,
+                            start_row,
                             comment_prefix,
                             comment_prefix_whitespace,
                         );
-                        if prefix_range.is_empty() {
-                            all_selection_lines_are_comments = false;
-                        }
-                        selection_edit_ranges.push(prefix_range);
-                    }
-
-                    if all_selection_lines_are_comments {
-                        edits.extend(
-                            selection_edit_ranges
-                                .iter()
-                                .cloned()
-                                .map(|range| (range, empty_str.clone())),
+                        let suffix_range = comment_suffix_range(
+                            snapshot.deref(),
+                            end_row,
+                            comment_suffix.trim_start_matches(' '),
+                            comment_suffix.starts_with(' '),
                         );
-                    } else {
-                        let min_column = selection_edit_ranges
-                            .iter()
-                            .map(|r| r.start.column)
-                            .min()
-                            .unwrap_or(0);
-                        edits.extend(selection_edit_ranges.iter().map(|range| {
-                            let position = Point::new(range.start.row, min_column);
-                            (position..position, full_comment_prefix.clone())
-                        }));
-                    }
-                } else if let Some((full_comment_prefix, comment_suffix)) =
-                    language.block_comment_delimiters()
-                {
-                    let comment_prefix = full_comment_prefix.trim_end_matches(' ');
-                    let comment_prefix_whitespace = &full_comment_prefix[comment_prefix.len()..];
-                    let prefix_range = comment_prefix_range(
-                        snapshot.deref(),
-                        start_row,
-                        comment_prefix,
-                        comment_prefix_whitespace,
-                    );
-                    let suffix_range = comment_suffix_range(
-                        snapshot.deref(),
-                        end_row,
-                        comment_suffix.trim_start_matches(' '),
-                        comment_suffix.starts_with(' '),
-                    );
 
-                    if prefix_range.is_empty() || suffix_range.is_empty() {
-                        edits.push((
-                            prefix_range.start..prefix_range.start,
-                            full_comment_prefix.clone(),
-                        ));
-                        edits.push((suffix_range.end..suffix_range.end, comment_suffix.clone()));
-                        suffixes_inserted.push((end_row, comment_suffix.len()));
+                        if prefix_range.is_empty() || suffix_range.is_empty() {
+                            edits.push((
+                                prefix_range.start..prefix_range.start,
+                                full_comment_prefix.clone(),
+                            ));
+                            edits
+                                .push((suffix_range.end..suffix_range.end, comment_suffix.clone()));
+                            suffixes_inserted.push((end_row, comment_suffix.len()));
+                        } else {
+                            edits.push((prefix_range, empty_str.clone()));
+                            edits.push((suffix_range, empty_str.clone()));
+                        }
                     } else {
-                        edits.push((prefix_range, empty_str.clone()));
-                        edits.push((suffix_range, empty_str.clone()));
+                        continue;
                     }
-                } else {
-                    continue;
                 }
             }
 
@@ -6989,9 +7414,8 @@ impl Editor {
     ) {
         let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
         let buffer = self.buffer.read(cx).snapshot(cx);
-        let old_selections = self.selections.all::<usize>(cx).into_boxed_slice();
+        let old_selections = self.selections.all::<usize>(cx);
 
-        let mut stack = mem::take(&mut self.select_larger_syntax_node_stack);
         let mut selected_larger_node = false;
         let new_selections = old_selections
             .iter()
@@ -7020,13 +7444,48 @@ impl Editor {
             })
             .collect::<Vec<_>>();
 
-        if selected_larger_node {
-            stack.push(old_selections);
-            self.change_selections(Some(Autoscroll::fit()), cx, |s| {
-                s.select(new_selections);
+        if !selected_larger_node {
+            return;
+        }
+
+        self.change_selections(Some(Autoscroll::fit()), cx, |s| {
+            s.select(new_selections.clone());
+        });
+
+        // Record the selections that were merged into each expanded selection, so that they
+        // can be restored when selecting a smaller syntax node.
+        let mut old_selections = old_selections.iter().zip(&new_selections).peekable();
+        for selection in self.selections.all::<usize>(cx) {
+            let mut previous_selections = Vec::new();
+            while let Some((old_selection, new_selection)) = old_selections.peek() {
+                if new_selection.start < selection.start || new_selection.end > selection.end {
+                    break;
+                }
+                let end_bias = if old_selection.is_empty() {
+                    Bias::Right
+                } else {
+                    Bias::Left
+                };
+                previous_selections.push(Selection {
+                    id: old_selection.id,
+                    start: buffer.anchor_after(old_selection.start),
+                    end: buffer.anchor_at(old_selection.end, end_bias),
+                    reversed: old_selection.reversed,
+                    goal: old_selection.goal,
+                });
+                old_selections.next();
+            }
+
+            let history = self
+                .select_larger_syntax_node_history
+                .entry(selection.id)
+                .or_default();
+            history.push(SelectLargerSyntaxNodeState {
+                expanded_range: buffer.anchor_after(selection.start)
+                    ..buffer.anchor_before(selection.end),
+                previous_selections: previous_selections.into_boxed_slice(),
             });
         }
-        self.select_larger_syntax_node_stack = stack;
     }
 
     pub fn select_smaller_syntax_node(
@@ -7034,13 +7493,43 @@ impl Editor {
         _: &SelectSmallerSyntaxNode,
         cx: &mut ViewContext<Self>,
     ) {
-        let mut stack = mem::take(&mut self.select_larger_syntax_node_stack);
-        if let Some(selections) = stack.pop() {
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        let mut selected_smaller_node = false;
+        let mut new_selections = Vec::new();
+        for selection in self.selections.all::<usize>(cx) {
+            let Some(history) = self
+                .select_larger_syntax_node_history
+                .get_mut(&selection.id)
+            else {
+                new_selections.push(selection);
+                continue;
+            };
+
+            // Only restore the selections preceding an expansion if the selection hasn't
+            // been changed since.
+            match history.last() {
+                Some(state) if state.expanded_range.to_offset(&buffer) == selection.range() => {
+                    let state = history.pop().unwrap();
+                    new_selections.extend(
+                        state
+                            .previous_selections
+                            .iter()
+                            .map(|selection| selection.map(|anchor| anchor.to_offset(&buffer))),
+                    );
+                    selected_smaller_node = true;
+                }
+                _ => {
+                    history.clear();
+                    new_selections.push(selection);
+                }
+            }
+        }
+
+        if selected_smaller_node {
             self.change_selections(Some(Autoscroll::fit()), cx, |s| {
-                s.select(selections.to_vec());
+                s.select(new_selections);
             });
         }
-        self.select_larger_syntax_node_stack = stack;
     }
 
     pub fn move_to_enclosing_bracket(
@@ -7313,6 +7802,61 @@ impl Editor {
         }
     }
 
+    /// Moves the cursor to the given zero-based row and column of the buffer under the newest
+    /// cursor and centers it, returning the anchor that was moved to.
+    pub fn go_to_point(&mut self, row: u32, column: u32, cx: &mut ViewContext<Self>) -> Anchor {
+        let anchor = self.anchor_for_buffer_point(row, column, cx);
+        self.change_selections(Some(Autoscroll::center()), cx, |s| {
+            s.select_anchor_ranges([anchor..anchor])
+        });
+        anchor
+    }
+
+    /// Resolves a zero-based row and column of the buffer under the newest cursor to an anchor
+    /// in the multibuffer. Rows past the end of the buffer are clamped to its last row and
+    /// columns are clipped to the row's length. When the buffer appears in several excerpts,
+    /// the excerpt containing the point is preferred, falling back to the closest one.
+    pub fn anchor_for_buffer_point(&self, row: u32, column: u32, cx: &AppContext) -> Anchor {
+        let multi_buffer = self.buffer.read(cx);
+        let multi_buffer_snapshot = multi_buffer.snapshot(cx);
+        let head = self.selections.newest_anchor().head();
+        let Some((buffer, _)) = multi_buffer.text_anchor_for_position(head, cx) else {
+            let max_point = multi_buffer_snapshot.max_point();
+            let point = multi_buffer_snapshot
+                .clip_point(Point::new(row.min(max_point.row), column), Bias::Left);
+            return multi_buffer_snapshot.anchor_before(point);
+        };
+
+        let buffer_snapshot = buffer.read(cx).snapshot();
+        let max_point = buffer_snapshot.max_point();
+        let point =
+            buffer_snapshot.clip_point(Point::new(row.min(max_point.row), column), Bias::Left);
+        let closest_excerpt = multi_buffer
+            .excerpts_for_buffer(&buffer, cx)
+            .into_iter()
+            .map(|(excerpt_id, excerpt_range)| {
+                let range = excerpt_range.context.to_point(&buffer_snapshot);
+                let distance = if point < range.start {
+                    range.start.row - point.row
+                } else if point > range.end {
+                    point.row - range.end.row
+                } else {
+                    0
+                };
+                (excerpt_id, range, distance)
+            })
+            .min_by_key(|(_, _, distance)| *distance);
+
+        match closest_excerpt {
+            Some((excerpt_id, range, _)) => {
+                let point = point.clamp(range.start, range.end);
+                multi_buffer_snapshot
+                    .anchor_in_excerpt(excerpt_id, buffer_snapshot.anchor_before(point))
+            }
+            None => head,
+        }
+    }
+
     pub fn go_to_definition(&mut self, _: &GoToDefinition, cx: &mut ViewContext<Self>) {
         self.go_to_definition_of_kind(GotoDefinitionKind::Symbol, false, cx);
     }
@@ -7956,9 +8500,7 @@ impl Editor {
             buffer
                 .update(&mut cx, |buffer, cx| {
                     if let Some(transaction) = transaction {
-                        if !buffer.is_singleton() {
-                            buffer.push_transaction(&transaction.0, cx);
-                        }
+                        buffer.push_transaction(&transaction.0, cx);
                     }
 
                     cx.notify();
@@ -8396,7 +8938,20 @@ impl Editor {
             language_settings::SoftWrap::None => SoftWrap::None,
             language_settings::SoftWrap::EditorWidth => SoftWrap::EditorWidth,
             language_settings::SoftWrap::PreferredLineLength => {
-                SoftWrap::Column(settings.preferred_line_length)
+                // Each excerpted file's `.editorconfig` may set a different maximum line
+                // length, so wrap at the narrowest one to keep every excerpt within its own.
+                let mut column = None;
+                self.buffer.read(cx).for_each_buffer(|buffer| {
+                    let max_line_length = buffer
+                        .read(cx)
+                        .editor_config()
+                        .max_line_length
+                        .unwrap_or(settings.preferred_line_length);
+                    column = Some(
+                        column.map_or(max_line_length, |column| cmp::min(column, max_line_length)),
+                    );
+                });
+                SoftWrap::Column(column.unwrap_or(settings.preferred_line_length))
             }
         }
     }
@@ -8830,7 +9385,26 @@ impl Editor {
         cx: &mut ViewContext<Self>,
     ) {
         self.display_map.update(cx, |map, _| {
-            map.highlight_text(TypeId::of::<T>(), ranges, style)
+            map.highlight_text(HighlightKey::Type(TypeId::of::<T>()), ranges, style)
+        });
+        cx.notify();
+    }
+
+    /// Like [`Editor::highlight_text`], but lets `T` own several layers of highlights that
+    /// are distinguished by `key`, and replaced or cleared independently of each other.
+    pub fn highlight_text_key<T: 'static>(
+        &mut self,
+        key: usize,
+        ranges: Vec<Range<Anchor>>,
+        style: HighlightStyle,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.display_map.update(cx, |map, _| {
+            map.highlight_text(
+                HighlightKey::TypeWithKey(TypeId::of::<T>(), key),
+                ranges,
+                style,
+            )
         });
         cx.notify();
     }
@@ -8851,13 +9425,36 @@ impl Editor {
         &'a self,
         cx: &'a AppContext,
     ) -> Option<(HighlightStyle, &'a [Range<Anchor>])> {
-        self.display_map.read(cx).text_highlights(TypeId::of::<T>())
+        self.display_map
+            .read(cx)
+            .text_highlights(HighlightKey::Type(TypeId::of::<T>()))
     }
 
+    pub fn text_highlights_key<'a, T: 'static>(
+        &'a self,
+        key: usize,
+        cx: &'a AppContext,
+    ) -> Option<(HighlightStyle, &'a [Range<Anchor>])> {
+        self.display_map
+            .read(cx)
+            .text_highlights(HighlightKey::TypeWithKey(TypeId::of::<T>(), key))
+    }
+
+    /// Clears all of the highlights owned by `T`, including the ones that were added with
+    /// [`Editor::highlight_text_key`].
     pub fn clear_highlights<T: 'static>(&mut self, cx: &mut ViewContext<Self>) {
         let cleared = self
             .display_map
-            .update(cx, |map, _| map.clear_highlights(TypeId::of::<T>()));
+            .update(cx, |map, _| map.clear_all_highlights(TypeId::of::<T>()));
+        if cleared {
+            cx.notify();
+        }
+    }
+
+    pub fn clear_highlights_key<T: 'static>(&mut self, key: usize, cx: &mut ViewContext<Self>) {
+        let cleared = self.display_map.update(cx, |map, _| {
+            map.clear_highlights(HighlightKey::TypeWithKey(TypeId::of::<T>(), key))
+        });
         if cleared {
             cx.notify();
         }
@@ -8884,6 +9481,7 @@ impl Editor {
             } => {
                 self.refresh_active_diagnostics(cx);
                 self.refresh_code_actions(cx);
+                self.refresh_ghost_text(cx);
                 if self.has_active_copilot_suggestion(cx) {
                     self.update_visible_copilot_suggestion(cx);
                 }
@@ -8975,9 +9573,16 @@ impl Editor {
         let editor_settings = EditorSettings::get_global(cx);
         self.scroll_manager.vertical_scroll_margin = editor_settings.vertical_scroll_margin;
         self.show_breadcrumbs = editor_settings.toolbar.breadcrumbs;
+        self.update_large_file_threshold(cx);
         cx.notify();
     }
 
+    fn update_large_file_threshold(&mut self, cx: &mut ViewContext<Self>) {
+        let threshold = EditorSettings::get_global(cx).large_file_threshold;
+        self.buffer
+            .update(cx, |buffer, _| buffer.set_large_buffer_threshold(threshold));
+    }
+
     pub fn set_searchable(&mut self, searchable: bool) {
         self.searchable = searchable;
     }
@@ -9495,6 +10100,48 @@ fn inlay_hint_settings(
         .inlay_hints
 }
 
+/// Returns the prefix of the given line that's repeated on every line of a rewrapped
+/// paragraph, consisting of its indentation followed by any line comment prefix, along with
+/// the position where the line's text begins. Returns `None` if the line has no text.
+fn rewrap_line_prefix(buffer: &MultiBufferSnapshot, row: u32) -> Option<(String, Point)> {
+    let indent_end = Point::new(row, buffer.indent_size_for_line(row).len);
+    let mut prefix = buffer
+        .text_for_range(Point::new(row, 0)..indent_end)
+        .collect::<String>();
+    let mut content_start = indent_end;
+
+    let comment_prefix = buffer.language_scope_at(indent_end).and_then(|scope| {
+        scope
+            .line_comment_prefixes()?
+            .iter()
+            .filter(|comment_prefix| {
+                let comment_prefix = comment_prefix.trim_end();
+                !comment_prefix.is_empty() && buffer.contains_str_at(indent_end, comment_prefix)
+            })
+            .max_by_key(|comment_prefix| comment_prefix.trim_end().len())
+            .cloned()
+    });
+    if let Some(comment_prefix) = comment_prefix {
+        prefix.push_str(&comment_prefix);
+        content_start.column += comment_prefix.trim_end().len() as u32;
+        content_start.column += buffer
+            .chars_at(content_start)
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count() as u32;
+    }
+
+    (content_start.column < buffer.line_len(row)).then_some((prefix, content_start))
+}
+
+/// Returns the text that a completion is matched against, which is the filterable part of its
+/// label, unless the language server provided filter text that doesn't appear in the label.
+fn completion_filter_text(completion: &Completion) -> &str {
+    match completion.lsp_completion.filter_text.as_deref() {
+        Some(filter_text) if !completion.label.text.contains(filter_text) => filter_text,
+        _ => &completion.label.text[completion.label.filter_range.clone()],
+    }
+}
+
 fn consume_contiguous_rows(
     contiguous_row_selections: &mut Vec<Selection<Point>>,
     selection: &Selection<Point>,
@@ -9524,6 +10171,20 @@ fn ending_row(next_selection: &Selection<Point>, display_map: &DisplaySnapshot)
     }
 }
 
+/// The range selected by triple-clicking at `position`: the whole buffer line containing it,
+/// including its newline.
+fn line_selection_range(display_map: &DisplaySnapshot, position: DisplayPoint) -> Range<Point> {
+    let position = display_map
+        .clip_point(position, Bias::Left)
+        .to_point(display_map);
+    let line_start = display_map.prev_line_boundary(position).0;
+    let next_line_start = display_map.buffer_snapshot.clip_point(
+        display_map.next_line_boundary(position).0 + Point::new(1, 0),
+        Bias::Left,
+    );
+    line_start..next_line_start
+}
+
 impl EditorSnapshot {
     pub fn remote_selections_in_range<'a>(
         &'a self,
@@ -9789,6 +10450,10 @@ impl ViewInputHandler for Editor {
                     })
             });
 
+            this.record_macro_command(MacroCommand::Insertion {
+                text: text.into(),
+                utf16_range_to_replace: range_to_replace.clone(),
+            });
             cx.emit(EditorEvent::InputHandled {
                 utf16_range_to_replace: range_to_replace,
                 text: text.into(),
@@ -16,6 +16,7 @@ pub struct EditorSettings {
     pub relative_line_numbers: bool,
     pub seed_search_query_from_cursor: SeedQuerySetting,
     pub redact_private_values: bool,
+    pub large_file_threshold: Option<usize>,
 }
 
 /// When to populate a new search's query based on the text under the cursor.
@@ -117,6 +118,12 @@ pub struct EditorSettingsContent {
     ///
     /// Default: false
     pub redact_private_values: Option<bool>,
+
+    /// The size in bytes beyond which files are displayed without syntax
+    /// highlighting or diagnostics, to keep editing them responsive.
+    ///
+    /// Default: 20000000
+    pub large_file_threshold: Option<usize>,
 }
 
 // Toolbar related settings
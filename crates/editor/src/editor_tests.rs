@@ -662,6 +662,137 @@ async fn test_navigation_history(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+fn test_navigation_history_across_replaced_excerpts(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    use workspace::item::Item;
+
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(20, 3, 'a'),
+        )
+    });
+    let multibuffer = cx.new_model(|_| MultiBuffer::new(0, ReadWrite));
+    let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+        multibuffer.push_excerpts(
+            buffer.clone(),
+            [
+                ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(3, 0),
+                    primary: None,
+                },
+                ExcerptRange {
+                    context: Point::new(10, 0)..Point::new(14, 0),
+                    primary: None,
+                },
+            ],
+            cx,
+        )
+    });
+    let view = cx.add_window(|cx| build_editor(multibuffer.clone(), cx));
+
+    _ = view.update(cx, |editor, cx| {
+        let snapshot = editor.buffer.read(cx).snapshot(cx);
+        let cursor_anchor = snapshot.anchor_in_excerpt(
+            excerpt_ids[1],
+            buffer.read(cx).anchor_before(Point::new(12, 1)),
+        );
+        let navigation_data = NavigationData {
+            cursor_anchor,
+            cursor_position: cursor_anchor.to_point(&snapshot),
+            scroll_anchor: editor.scroll_manager.anchor(),
+            scroll_top_row: 0,
+        };
+
+        // Replace the excerpt containing the cursor with a larger one.
+        editor.buffer.update(cx, |multibuffer, cx| {
+            multibuffer.remove_excerpts([excerpt_ids[1]], cx);
+            multibuffer.insert_excerpts_after(
+                excerpt_ids[0],
+                buffer.clone(),
+                [ExcerptRange {
+                    context: Point::new(8, 0)..Point::new(16, 0),
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+
+        assert!(editor.navigate(Box::new(navigation_data), cx));
+        let head = editor.selections.newest_anchor().head();
+        assert_ne!(head.excerpt_id, excerpt_ids[1]);
+        assert_eq!(
+            buffer
+                .read(cx)
+                .summary_for_anchor::<Point>(&head.text_anchor),
+            Point::new(12, 1)
+        );
+    });
+}
+
+#[gpui::test]
+fn test_go_to_point(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(20, 3, 'a'),
+        )
+    });
+    let multibuffer = cx.new_model(|_| MultiBuffer::new(0, ReadWrite));
+    let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+        multibuffer.push_excerpts(
+            buffer.clone(),
+            [
+                ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(3, 0),
+                    primary: None,
+                },
+                ExcerptRange {
+                    context: Point::new(10, 0)..Point::new(14, 0),
+                    primary: None,
+                },
+            ],
+            cx,
+        )
+    });
+    let view = cx.add_window(|cx| build_editor(multibuffer.clone(), cx));
+
+    _ = view.update(cx, |editor, cx| {
+        let buffer_point = |anchor: Anchor, cx: &mut ViewContext<Editor>| {
+            buffer
+                .read(cx)
+                .summary_for_anchor::<Point>(&anchor.text_anchor)
+        };
+
+        // Rows are relative to the buffer, and resolve to the excerpt containing them.
+        let anchor = editor.go_to_point(12, 1, cx);
+        assert_eq!(anchor.excerpt_id, excerpt_ids[1]);
+        assert_eq!(buffer_point(anchor, cx), Point::new(12, 1));
+        assert_eq!(editor.selections.newest_anchor().head(), anchor);
+
+        // Columns are clipped to the length of the row.
+        let anchor = editor.go_to_point(1, 100, cx);
+        assert_eq!(anchor.excerpt_id, excerpt_ids[0]);
+        assert_eq!(buffer_point(anchor, cx), Point::new(1, 3));
+
+        // Points outside of every excerpt resolve to the closest one.
+        let anchor = editor.go_to_point(8, 0, cx);
+        assert_eq!(anchor.excerpt_id, excerpt_ids[1]);
+        assert_eq!(buffer_point(anchor, cx), Point::new(10, 0));
+
+        // Rows past the end of the buffer are clamped to its last row.
+        let anchor = editor.go_to_point(100, 0, cx);
+        assert_eq!(anchor.excerpt_id, excerpt_ids[1]);
+        assert_eq!(buffer_point(anchor, cx), Point::new(14, 0));
+    });
+}
+
 #[gpui::test]
 fn test_cancel(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -1570,6 +1701,104 @@ async fn test_autoscroll(cx: &mut gpui::TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_autoscroll_anchor(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+    let mut cx = EditorTestContext::new(cx).await;
+
+    let line_height = cx.update_editor(|editor, cx| {
+        editor.set_vertical_scroll_margin(2, cx);
+        editor
+            .style()
+            .unwrap()
+            .text
+            .line_height_in_pixels(cx.rem_size())
+    });
+    let window = cx.window;
+    cx.simulate_window_resize(window, size(px(1000.), 6. * line_height));
+
+    let text = (0..20)
+        .map(|row| format!("line {row}\n"))
+        .collect::<String>();
+    cx.set_state(&format!("ˇ{text}"));
+    let set_autoscroll_anchor = |editor: &mut Editor, row: u32, cx: &mut ViewContext<Editor>| {
+        editor.buffer().update(cx, |buffer, cx| {
+            let anchor = buffer.read(cx).anchor_before(Point::new(row, 0));
+            buffer.set_autoscroll_anchor(anchor, cx);
+        });
+    };
+
+    // Without any other request, the editor scrolls to the multi-buffer's anchor.
+    cx.update_editor(|editor, cx| set_autoscroll_anchor(editor, 12, cx));
+    cx.update_editor(|editor, cx| {
+        assert_eq!(
+            editor.snapshot(cx).scroll_position(),
+            gpui::Point::new(0., 9.0)
+        );
+    });
+
+    // An explicit request takes priority over the anchor, which is discarded.
+    cx.update_editor(|editor, cx| {
+        set_autoscroll_anchor(editor, 18, cx);
+        editor.change_selections(Some(Autoscroll::fit()), cx, |selections| {
+            selections.select_ranges([Point::new(1, 0)..Point::new(1, 0)]);
+        });
+    });
+    cx.update_editor(|editor, cx| {
+        assert_eq!(
+            editor.snapshot(cx).scroll_position(),
+            gpui::Point::new(0., 0.0)
+        );
+    });
+}
+
+#[gpui::test]
+fn test_autoscroll_anchor_applies_to_every_editor(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let text = (0..20)
+        .map(|row| format!("line {row}\n"))
+        .collect::<String>();
+    let buffer = cx.update(|cx| MultiBuffer::build_simple(&text, cx));
+    let editor_1 = cx.add_window(|cx| build_editor(buffer.clone(), cx));
+    let editor_2 = cx.add_window(|cx| build_editor(buffer.clone(), cx));
+
+    _ = editor_1.update(cx, |editor, cx| {
+        editor.buffer().update(cx, |buffer, cx| {
+            let anchor = buffer.read(cx).anchor_before(Point::new(12, 0));
+            buffer.set_autoscroll_anchor(anchor, cx);
+        });
+    });
+    for editor in [editor_1, editor_2] {
+        _ = editor.update(cx, |editor, cx| {
+            editor.set_vertical_scroll_margin(2, cx);
+            editor.autoscroll_vertically(px(60.), px(10.), cx);
+            assert_eq!(
+                editor.snapshot(cx).scroll_position(),
+                gpui::Point::new(0., 9.0)
+            );
+
+            // Each editor applies the request only once.
+            editor.set_scroll_position(gpui::Point::new(0., 0.), cx);
+            editor.autoscroll_vertically(px(60.), px(10.), cx);
+            assert_eq!(
+                editor.snapshot(cx).scroll_position(),
+                gpui::Point::new(0., 0.0)
+            );
+        });
+    }
+
+    // Editors opened later don't apply earlier requests.
+    let editor_3 = cx.add_window(|cx| build_editor(buffer.clone(), cx));
+    _ = editor_3.update(cx, |editor, cx| {
+        editor.autoscroll_vertically(px(60.), px(10.), cx);
+        assert_eq!(
+            editor.snapshot(cx).scroll_position(),
+            gpui::Point::new(0., 0.0)
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_move_page_up_page_down(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -2435,75 +2664,274 @@ fn test_indent_outdent_with_excerpts(cx: &mut TestAppContext) {
 }
 
 #[gpui::test]
-async fn test_backspace(cx: &mut gpui::TestAppContext) {
-    init_test(cx, |_| {});
+fn test_indent_outdent_selection_spanning_excerpts(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.languages.extend([
+            (
+                "Makefile".into(),
+                LanguageSettingsContent {
+                    hard_tabs: Some(true),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Rust".into(),
+                LanguageSettingsContent {
+                    tab_size: NonZeroU32::new(4),
+                    ..Default::default()
+                },
+            ),
+        ]);
+    });
 
-    let mut cx = EditorTestContext::new(cx).await;
+    let make_language = Arc::new(Language::new(
+        LanguageConfig {
+            name: "Makefile".into(),
+            ..Default::default()
+        },
+        None,
+    ));
+    let rust_language = Arc::new(Language::new(
+        LanguageConfig {
+            name: "Rust".into(),
+            ..Default::default()
+        },
+        None,
+    ));
 
-    // Basic backspace
-    cx.set_state(indoc! {"
-        onˇe two three
-        fou«rˇ» five six
-        seven «ˇeight nine
-        »ten
-    "});
-    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
-    cx.assert_editor_state(indoc! {"
-        oˇe two three
-        fouˇ five six
-        seven ˇten
-    "});
+    let make_buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "all: build\n",
+        )
+        .with_language(make_language, cx)
+    });
+    let rust_buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "fn main() {}\n",
+        )
+        .with_language(rust_language, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        multibuffer.push_excerpts(
+            make_buffer.clone(),
+            [ExcerptRange {
+                context: Point::new(0, 0)..Point::new(0, 10),
+                primary: None,
+            }],
+            cx,
+        );
+        multibuffer.push_excerpts(
+            rust_buffer.clone(),
+            [ExcerptRange {
+                context: Point::new(0, 0)..Point::new(0, 12),
+                primary: None,
+            }],
+            cx,
+        );
+        multibuffer
+    });
 
-    // Test backspace inside and around indents
-    cx.set_state(indoc! {"
-        zero
-            ˇone
-                ˇtwo
-            ˇ ˇ ˇ  three
-        ˇ  ˇ  four
-    "});
-    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
-    cx.assert_editor_state(indoc! {"
-        zero
-        ˇone
-            ˇtwo
-        ˇ  threeˇ  four
-    "});
+    cx.add_window(|cx| {
+        let mut editor = build_editor(multibuffer, cx);
 
-    // Test backspace with line_mode set to true
-    cx.update_editor(|e, _| e.selections.line_mode = true);
-    cx.set_state(indoc! {"
-        The ˇquick ˇbrown
-        fox jumps over
-        the lazy dog
-        ˇThe qu«ick bˇ»rown"});
-    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
-    cx.assert_editor_state(indoc! {"
-        ˇfox jumps over
-        the lazy dogˇ"});
+        select_ranges(&mut editor, "«all: build\nfn mainˇ»() {}", cx);
+
+        editor.indent(&Indent, cx);
+        assert_eq!(make_buffer.read(cx).text(), "\tall: build\n");
+        assert_eq!(rust_buffer.read(cx).text(), "    fn main() {}\n");
+
+        editor.outdent(&Outdent, cx);
+        assert_eq!(make_buffer.read(cx).text(), "all: build\n");
+        assert_eq!(rust_buffer.read(cx).text(), "fn main() {}\n");
+
+        editor
+    });
 }
 
 #[gpui::test]
-async fn test_delete(cx: &mut gpui::TestAppContext) {
-    init_test(cx, |_| {});
-
-    let mut cx = EditorTestContext::new(cx).await;
-    cx.set_state(indoc! {"
-        onˇe two three
-        fou«rˇ» five six
-        seven «ˇeight nine
-        »ten
-    "});
-    cx.update_editor(|e, cx| e.delete(&Delete, cx));
-    cx.assert_editor_state(indoc! {"
-        onˇ two three
-        fouˇ five six
-        seven ˇten
-    "});
+fn test_indent_outdent_with_detected_indentation(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.defaults.tab_size = NonZeroU32::new(4);
+        settings.defaults.detect_indentation = Some(true);
+    });
 
-    // Test backspace with line_mode set to true
-    cx.update_editor(|e, _| e.selections.line_mode = true);
-    cx.set_state(indoc! {"
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "fn a() {\n  b();\n}\n",
+        )
+    });
+    let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+
+    let view = cx.add_window(|cx| {
+        let mut editor = build_editor(multibuffer, cx);
+        assert_eq!(
+            editor
+                .buffer()
+                .read(cx)
+                .detected_indent(buffer.read(cx).remote_id(), cx),
+            Some(IndentSize::spaces(2))
+        );
+
+        select_ranges(&mut editor, "fn a() {\n  «b();ˇ»\n}\n", cx);
+        editor.indent(&Indent, cx);
+        assert_text_with_selections(&mut editor, "fn a() {\n    «b();ˇ»\n}\n", cx);
+        editor.outdent(&Outdent, cx);
+        assert_text_with_selections(&mut editor, "fn a() {\n  «b();ˇ»\n}\n", cx);
+
+        select_ranges(&mut editor, "fn a() {\n  b();\nˇ}\n", cx);
+        editor.tab(&Tab, cx);
+        assert_text_with_selections(&mut editor, "fn a() {\n  b();\n  ˇ}\n", cx);
+        editor.backspace(&Backspace, cx);
+        assert_text_with_selections(&mut editor, "fn a() {\n  b();\nˇ}\n", cx);
+
+        editor
+    });
+
+    // The configured indentation is used when detection is disabled.
+    update_test_language_settings(cx, |settings| {
+        settings.defaults.detect_indentation = Some(false);
+    });
+    _ = view.update(cx, |editor, cx| {
+        select_ranges(editor, "fn a() {\n  «b();ˇ»\n}\n", cx);
+        editor.indent(&Indent, cx);
+        assert_text_with_selections(editor, "fn a() {\n    «b();ˇ»\n}\n", cx);
+        editor.indent(&Indent, cx);
+        assert_text_with_selections(editor, "fn a() {\n        «b();ˇ»\n}\n", cx);
+    });
+}
+
+#[gpui::test]
+fn test_soft_wrap_column_from_editor_config_of_each_excerpt(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.defaults.soft_wrap = Some(language_settings::SoftWrap::PreferredLineLength);
+        settings.defaults.preferred_line_length = Some(80);
+    });
+
+    let buffer_with_max_line_length = |text: &str, max_line_length, cx: &mut TestAppContext| {
+        cx.new_model(|cx| {
+            let mut buffer = Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text);
+            buffer.set_editor_config(
+                language::EditorConfig {
+                    max_line_length,
+                    ..Default::default()
+                },
+                cx,
+            );
+            buffer
+        })
+    };
+    let buffer_1 = buffer_with_max_line_length("aaa\n", Some(100), cx);
+    let buffer_2 = buffer_with_max_line_length("bbb\n", Some(60), cx);
+    let buffer_3 = buffer_with_max_line_length("ccc\n", None, cx);
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [&buffer_1, &buffer_2, &buffer_3] {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(0, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        multibuffer
+    });
+
+    cx.add_window(|cx| {
+        let editor = build_editor(multibuffer.clone(), cx);
+        // The excerpt whose file has the narrowest maximum line length determines the
+        // wrap column, even though it isn't the first one.
+        assert!(matches!(editor.soft_wrap_mode(cx), SoftWrap::Column(60)));
+
+        // Files without a maximum line length use the preferred line length.
+        multibuffer.update(cx, |multibuffer, cx| {
+            let excerpt_ids = multibuffer.excerpt_ids();
+            multibuffer.remove_excerpts([excerpt_ids[1]], cx);
+        });
+        assert!(matches!(editor.soft_wrap_mode(cx), SoftWrap::Column(80)));
+
+        editor
+    });
+}
+
+#[gpui::test]
+async fn test_backspace(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+
+    // Basic backspace
+    cx.set_state(indoc! {"
+        onˇe two three
+        fou«rˇ» five six
+        seven «ˇeight nine
+        »ten
+    "});
+    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
+    cx.assert_editor_state(indoc! {"
+        oˇe two three
+        fouˇ five six
+        seven ˇten
+    "});
+
+    // Test backspace inside and around indents
+    cx.set_state(indoc! {"
+        zero
+            ˇone
+                ˇtwo
+            ˇ ˇ ˇ  three
+        ˇ  ˇ  four
+    "});
+    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
+    cx.assert_editor_state(indoc! {"
+        zero
+        ˇone
+            ˇtwo
+        ˇ  threeˇ  four
+    "});
+
+    // Test backspace with line_mode set to true
+    cx.update_editor(|e, _| e.selections.line_mode = true);
+    cx.set_state(indoc! {"
+        The ˇquick ˇbrown
+        fox jumps over
+        the lazy dog
+        ˇThe qu«ick bˇ»rown"});
+    cx.update_editor(|e, cx| e.backspace(&Backspace, cx));
+    cx.assert_editor_state(indoc! {"
+        ˇfox jumps over
+        the lazy dogˇ"});
+}
+
+#[gpui::test]
+async fn test_delete(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state(indoc! {"
+        onˇe two three
+        fou«rˇ» five six
+        seven «ˇeight nine
+        »ten
+    "});
+    cx.update_editor(|e, cx| e.delete(&Delete, cx));
+    cx.assert_editor_state(indoc! {"
+        onˇ two three
+        fouˇ five six
+        seven ˇten
+    "});
+
+    // Test backspace with line_mode set to true
+    cx.update_editor(|e, _| e.selections.line_mode = true);
+    cx.set_state(indoc! {"
         The ˇquick ˇbrown
         fox «ˇjum»ps over
         the lazy dog
@@ -2656,6 +3084,55 @@ fn test_join_lines_with_single_selection(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_join_lines_with_comments(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let mut cx = EditorTestContext::new(cx).await;
+    let language = Arc::new(Language::new(
+        LanguageConfig {
+            line_comments: vec!["// ".into()],
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    ));
+    cx.update_buffer(|buffer, cx| buffer.set_language(Some(language), cx));
+
+    // The comment prefix of the joined line is removed
+    cx.set_state(indoc! {"
+        fn a() {
+            // oneˇ
+            //   two
+            //
+            b();
+        }
+    "});
+    cx.update_editor(|e, cx| e.join_lines(&JoinLines, cx));
+    cx.assert_editor_state(indoc! {"
+        fn a() {
+            // oneˇ two
+            //
+            b();
+        }
+    "});
+
+    // An empty comment line is joined without a space
+    cx.update_editor(|e, cx| e.join_lines(&JoinLines, cx));
+    cx.assert_editor_state(indoc! {"
+        fn a() {
+            // one twoˇ
+            b();
+        }
+    "});
+
+    // Code following a comment is joined as-is
+    cx.update_editor(|e, cx| e.join_lines(&JoinLines, cx));
+    cx.assert_editor_state(indoc! {"
+        fn a() {
+            // one twoˇ b();
+        }
+    "});
+}
+
 #[gpui::test]
 fn test_join_lines_with_multi_selection(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -2687,6 +3164,56 @@ fn test_join_lines_with_multi_selection(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_rewrap(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.defaults.preferred_line_length = Some(20);
+    });
+    let mut cx = EditorTestContext::new(cx).await;
+    let language = Arc::new(Language::new(
+        LanguageConfig {
+            line_comments: vec!["// ".into()],
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    ));
+    cx.update_buffer(|buffer, cx| buffer.set_language(Some(language), cx));
+
+    // Without a selection, the comment containing the cursor is rewrapped
+    cx.set_state(indoc! {"
+        fn a() {
+            // ˇThis is a long comment that needs to be
+            // wrapped.
+            b();
+        }
+    "});
+    cx.update_editor(|e, cx| e.rewrap(&Rewrap, cx));
+    cx.assert_editor_state(indoc! {"
+        fn a() {
+            // ˇThis is a
+            // long comment
+            // that needs to
+            // be wrapped.
+            b();
+        }
+    "});
+
+    // Each selected paragraph is rewrapped separately
+    cx.set_state(indoc! {"
+        «one two three four five six
+        seven
+
+        eightˇ» nine
+    "});
+    cx.update_editor(|e, cx| e.rewrap(&Rewrap, cx));
+    cx.assert_editor_state(indoc! {"
+        «one two three four
+        five six seven
+
+        eightˇ» nine
+    "});
+}
+
 #[gpui::test]
 async fn test_manipulate_lines_with_single_selection(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -3099,6 +3626,24 @@ async fn test_manipulate_text(cx: &mut TestAppContext) {
     cx.assert_editor_state(indoc! {"
         «aaaBbbˇ» «bbbCccˇ» «cccDddˇ»
     "});
+
+    // Words containing multiple cursors are only converted once
+    cx.set_state(indoc! {"
+        aˇaa_bbˇb ccc
+    "});
+    cx.update_editor(|e, cx| e.convert_to_upper_case(&ConvertToUpperCase, cx));
+    cx.assert_editor_state(indoc! {"
+        «AAA_BBBˇ» ccc
+    "});
+
+    // Selections overlapping the previous selection's word extend its conversion
+    cx.set_state(indoc! {"
+        «fooˇ»Bˇar bazˇQux
+    "});
+    cx.update_editor(|e, cx| e.convert_to_snake_case(&ConvertToSnakeCase, cx));
+    cx.assert_editor_state(indoc! {"
+        «foo_barˇ» «baz_quxˇ»
+    "});
 }
 
 #[gpui::test]
@@ -3154,6 +3699,57 @@ fn test_duplicate_line(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+fn test_duplicate_line_across_excerpts(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'a'),
+        )
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'c'),
+        )
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [buffer_1.clone(), buffer_2.clone()] {
+            multibuffer.push_excerpts(
+                buffer,
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        multibuffer
+    });
+
+    let (view, cx) = cx.add_window_view(|cx| build_editor(multibuffer, cx));
+    _ = view.update(cx, |view, cx| {
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 1)..Point::new(2, 1)])
+        });
+        view.duplicate_line(&DuplicateLine, cx);
+        assert_eq!(view.text(cx), "aaa\nbbb\nbbb\nccc\nccc\nddd");
+    });
+    assert_eq!(
+        buffer_1.read_with(cx, |buffer, _| buffer.text()),
+        "aaa\nbbb\nbbb"
+    );
+    assert_eq!(
+        buffer_2.read_with(cx, |buffer, _| buffer.text()),
+        "ccc\nccc\nddd"
+    );
+}
+
 #[gpui::test]
 fn test_move_line_up_down(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -3282,23 +3878,108 @@ fn test_move_line_up_down_with_blocks(cx: &mut TestAppContext) {
 }
 
 #[gpui::test]
-fn test_transpose(cx: &mut TestAppContext) {
+fn test_move_line_up_down_across_excerpts(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
 
-    _ = cx.add_window(|cx| {
-        let mut editor = build_editor(MultiBuffer::build_simple("abc", cx), cx);
-        editor.set_style(EditorStyle::default(), cx);
-        editor.change_selections(None, cx, |s| s.select_ranges([1..1]));
-        editor.transpose(&Default::default(), cx);
-        assert_eq!(editor.text(cx), "bac");
-        assert_eq!(editor.selections.ranges(cx), [2..2]);
-
-        editor.transpose(&Default::default(), cx);
-        assert_eq!(editor.text(cx), "bca");
-        assert_eq!(editor.selections.ranges(cx), [3..3]);
-
-        editor.transpose(&Default::default(), cx);
-        assert_eq!(editor.text(cx), "bac");
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'a'),
+        )
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'c'),
+        )
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [buffer_1.clone(), buffer_2.clone()] {
+            multibuffer.push_excerpts(
+                buffer,
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        multibuffer
+    });
+
+    let (view, cx) = cx.add_window_view(|cx| build_editor(multibuffer, cx));
+    _ = view.update(cx, |view, cx| {
+        // Lines at the edges of an excerpt stay within it.
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 1)..Point::new(1, 1)])
+        });
+        view.move_line_down(&MoveLineDown, cx);
+        assert_eq!(view.text(cx), "aaa\nbbb\nccc\nddd");
+        assert_eq!(
+            view.selections.ranges(cx),
+            [Point::new(1, 1)..Point::new(1, 1)]
+        );
+
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(2, 1)..Point::new(2, 1)])
+        });
+        view.move_line_up(&MoveLineUp, cx);
+        assert_eq!(view.text(cx), "aaa\nbbb\nccc\nddd");
+        assert_eq!(
+            view.selections.ranges(cx),
+            [Point::new(2, 1)..Point::new(2, 1)]
+        );
+
+        // Selections spanning an excerpt boundary aren't moved.
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 1)..Point::new(2, 1)])
+        });
+        view.move_line_up(&MoveLineUp, cx);
+        view.move_line_down(&MoveLineDown, cx);
+        assert_eq!(view.text(cx), "aaa\nbbb\nccc\nddd");
+
+        // Lines still move within an excerpt.
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(3, 1)..Point::new(3, 1)])
+        });
+        view.move_line_up(&MoveLineUp, cx);
+        assert_eq!(view.text(cx), "aaa\nbbb\nddd\nccc");
+        assert_eq!(
+            view.selections.ranges(cx),
+            [Point::new(2, 1)..Point::new(2, 1)]
+        );
+    });
+    assert_eq!(
+        buffer_1.read_with(cx, |buffer, _| buffer.text()),
+        "aaa\nbbb"
+    );
+    assert_eq!(
+        buffer_2.read_with(cx, |buffer, _| buffer.text()),
+        "ddd\nccc"
+    );
+}
+
+#[gpui::test]
+fn test_transpose(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    _ = cx.add_window(|cx| {
+        let mut editor = build_editor(MultiBuffer::build_simple("abc", cx), cx);
+        editor.set_style(EditorStyle::default(), cx);
+        editor.change_selections(None, cx, |s| s.select_ranges([1..1]));
+        editor.transpose(&Default::default(), cx);
+        assert_eq!(editor.text(cx), "bac");
+        assert_eq!(editor.selections.ranges(cx), [2..2]);
+
+        editor.transpose(&Default::default(), cx);
+        assert_eq!(editor.text(cx), "bca");
+        assert_eq!(editor.selections.ranges(cx), [3..3]);
+
+        editor.transpose(&Default::default(), cx);
+        assert_eq!(editor.text(cx), "bac");
         assert_eq!(editor.selections.ranges(cx), [3..3]);
 
         editor
@@ -3458,6 +4139,29 @@ async fn test_clipboard(cx: &mut gpui::TestAppContext) {
         ˇx jumps over
         fox jumps over
         tˇhe lazy dog"});
+
+    // Cutting the last line with a single cursor removes the line, and pasting it inserts
+    // a new line.
+    cx.set_state(indoc! {"
+        one
+        two
+        thrˇee"});
+    cx.update_editor(|e, cx| e.cut(&Cut, cx));
+    cx.assert_editor_state(indoc! {"
+        one
+        twoˇ"});
+    assert_eq!(
+        cx.read_from_clipboard().map(|item| item.text().to_owned()),
+        Some("three\n".to_owned())
+    );
+    cx.set_state(indoc! {"
+        oˇne
+        two"});
+    cx.update_editor(|e, cx| e.paste(&Paste, cx));
+    cx.assert_editor_state(indoc! {"
+        three
+        oˇne
+        two"});
 }
 
 #[gpui::test]
@@ -4004,6 +4708,98 @@ async fn test_select_all_matches(cx: &mut gpui::TestAppContext) {
     cx.assert_editor_state("«abcˇ»\n«abcˇ» «abcˇ»\ndefabc\n«abcˇ»");
 }
 
+#[gpui::test]
+async fn test_select_next_whole_word_without_wrapping(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state("abc\n«abcˇ» abc\ndefabc\nabc");
+
+    let action = SelectNext {
+        whole_word: true,
+        stop_at_end: true,
+        ..Default::default()
+    };
+    cx.update_editor(|e, cx| e.select_next(&action, cx))
+        .unwrap();
+    cx.assert_editor_state("abc\n«abcˇ» «abcˇ»\ndefabc\nabc");
+
+    cx.update_editor(|e, cx| e.select_next(&action, cx))
+        .unwrap();
+    cx.assert_editor_state("abc\n«abcˇ» «abcˇ»\ndefabc\n«abcˇ»");
+
+    cx.update_editor(|e, cx| e.select_next(&action, cx))
+        .unwrap();
+    cx.assert_editor_state("abc\n«abcˇ» «abcˇ»\ndefabc\n«abcˇ»");
+}
+
+#[gpui::test]
+async fn test_select_next_uses_each_actions_options(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state("abc «abcˇ» xabc");
+
+    cx.update_editor(|e, cx| {
+        e.select_next(
+            &SelectNext {
+                whole_word: true,
+                stop_at_end: true,
+                ..Default::default()
+            },
+            cx,
+        )
+    })
+    .unwrap();
+    cx.assert_editor_state("abc «abcˇ» xabc");
+
+    cx.update_editor(|e, cx| {
+        e.select_next(
+            &SelectNext {
+                whole_word: true,
+                ..Default::default()
+            },
+            cx,
+        )
+    })
+    .unwrap();
+    cx.assert_editor_state("«abcˇ» «abcˇ» xabc");
+
+    cx.update_editor(|e, cx| e.select_next(&SelectNext::default(), cx))
+        .unwrap();
+    cx.assert_editor_state("«abcˇ» «abcˇ» x«abcˇ»");
+}
+
+#[gpui::test]
+async fn test_select_next_skips_existing_selections(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state("abc «abcˇ» «abcˇ»");
+
+    cx.update_editor(|e, cx| e.select_next(&SelectNext::default(), cx))
+        .unwrap();
+    cx.assert_editor_state("«abcˇ» «abcˇ» «abcˇ»");
+
+    // Every remaining match is already selected.
+    cx.update_editor(|e, cx| e.select_next(&SelectNext::default(), cx))
+        .unwrap();
+    cx.assert_editor_state("«abcˇ» «abcˇ» «abcˇ»");
+    cx.editor(|e, _| assert!(e.select_next_state.as_ref().unwrap().done));
+}
+
+#[gpui::test]
+async fn test_select_previous_skips_existing_selections(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state("abc «abcˇ» «abcˇ»");
+
+    cx.update_editor(|e, cx| e.select_previous(&SelectPrevious::default(), cx))
+        .unwrap();
+    cx.assert_editor_state("«abcˇ» «abcˇ» «abcˇ»");
+}
+
 #[gpui::test]
 async fn test_select_next_with_multiple_carets(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -4284,6 +5080,84 @@ async fn test_select_larger_smaller_syntax_node(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_select_smaller_syntax_node_after_other_selection_changes(
+    cx: &mut gpui::TestAppContext,
+) {
+    init_test(cx, |_| {});
+
+    let language = Arc::new(Language::new(
+        LanguageConfig::default(),
+        Some(tree_sitter_rust::language()),
+    ));
+
+    let text = r#"
+        fn a() {
+            b(cc);
+            d(ee);
+        }
+    "#
+    .unindent();
+
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+            .with_language(language, cx)
+    });
+    let buffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+    let (view, cx) = cx.add_window_view(|cx| build_editor(buffer, cx));
+
+    view.condition::<crate::EditorEvent>(&cx, |view, cx| !view.buffer.read(cx).is_parsing(cx))
+        .await;
+
+    _ = view.update(cx, |view, cx| {
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 7)..Point::new(1, 7)])
+        });
+        view.select_larger_syntax_node(&SelectLargerSyntaxNode, cx);
+        view.select_larger_syntax_node(&SelectLargerSyntaxNode, cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [Point::new(1, 5)..Point::new(1, 9)]
+        );
+
+        // Adding another selection doesn't discard the history of the expanded selection.
+        view.add_selection_below(&AddSelectionBelow, cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [
+                Point::new(1, 5)..Point::new(1, 9),
+                Point::new(2, 5)..Point::new(2, 9)
+            ]
+        );
+
+        view.select_smaller_syntax_node(&SelectSmallerSyntaxNode, cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [
+                Point::new(1, 6)..Point::new(1, 8),
+                Point::new(2, 5)..Point::new(2, 9)
+            ]
+        );
+
+        view.select_smaller_syntax_node(&SelectSmallerSyntaxNode, cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [
+                Point::new(1, 7)..Point::new(1, 7),
+                Point::new(2, 5)..Point::new(2, 9)
+            ]
+        );
+
+        // The history of selections that are replaced is discarded.
+        view.select_larger_syntax_node(&SelectLargerSyntaxNode, cx);
+        assert!(!view.select_larger_syntax_node_history.is_empty());
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(0, 0)..Point::new(0, 0)])
+        });
+        assert!(view.select_larger_syntax_node_history.is_empty());
+    });
+}
+
 #[gpui::test]
 async fn test_autoindent_selections(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -5013,7 +5887,7 @@ async fn test_surround_with_pair(cx: &mut gpui::TestAppContext) {
 }
 
 #[gpui::test]
-async fn test_delete_autoclose_pair(cx: &mut gpui::TestAppContext) {
+fn test_surround_with_pair_across_excerpts(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
 
     let language = Arc::new(Language::new(
@@ -5027,34 +5901,107 @@ async fn test_delete_autoclose_pair(cx: &mut gpui::TestAppContext) {
                 }],
                 ..Default::default()
             },
-            autoclose_before: "}".to_string(),
             ..Default::default()
         },
-        Some(tree_sitter_rust::language()),
+        None,
     ));
 
-    let text = r#"
-        a
-        b
-        c
-    "#
-    .unindent();
-
-    let buffer = cx.new_model(|cx| {
-        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
-            .with_language(language, cx)
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'a'),
+        )
+        .with_language(language.clone(), cx)
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 3, 'c'),
+        )
+        .with_language(language, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [buffer_1.clone(), buffer_2.clone()] {
+            multibuffer.push_excerpts(
+                buffer,
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        multibuffer
     });
-    let buffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
-    let (editor, cx) = cx.add_window_view(|cx| build_editor(buffer, cx));
-    editor
-        .condition::<crate::EditorEvent>(cx, |view, cx| !view.buffer.read(cx).is_parsing(cx))
-        .await;
 
-    _ = editor.update(cx, |editor, cx| {
-        editor.change_selections(None, cx, |s| {
-            s.select_ranges([
-                Point::new(0, 1)..Point::new(0, 1),
-                Point::new(1, 1)..Point::new(1, 1),
+    let (view, cx) = cx.add_window_view(|cx| build_editor(multibuffer, cx));
+    _ = view.update(cx, |view, cx| {
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 1)..Point::new(2, 2)])
+        });
+        view.handle_input("{", cx);
+        assert_eq!(view.text(cx), "aaa\nb{bb}\n{cc}c\nddd");
+        assert_eq!(
+            view.selections.ranges(cx),
+            [Point::new(1, 2)..Point::new(2, 3)]
+        );
+    });
+    assert_eq!(
+        buffer_1.read_with(cx, |buffer, _| buffer.text()),
+        "aaa\nb{bb}"
+    );
+    assert_eq!(
+        buffer_2.read_with(cx, |buffer, _| buffer.text()),
+        "{cc}c\nddd"
+    );
+}
+
+#[gpui::test]
+async fn test_delete_autoclose_pair(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let language = Arc::new(Language::new(
+        LanguageConfig {
+            brackets: BracketPairConfig {
+                pairs: vec![BracketPair {
+                    start: "{".to_string(),
+                    end: "}".to_string(),
+                    close: true,
+                    newline: true,
+                }],
+                ..Default::default()
+            },
+            autoclose_before: "}".to_string(),
+            ..Default::default()
+        },
+        Some(tree_sitter_rust::language()),
+    ));
+
+    let text = r#"
+        a
+        b
+        c
+    "#
+    .unindent();
+
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+            .with_language(language, cx)
+    });
+    let buffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+    let (editor, cx) = cx.add_window_view(|cx| build_editor(buffer, cx));
+    editor
+        .condition::<crate::EditorEvent>(cx, |view, cx| !view.buffer.read(cx).is_parsing(cx))
+        .await;
+
+    _ = editor.update(cx, |editor, cx| {
+        editor.change_selections(None, cx, |s| {
+            s.select_ranges([
+                Point::new(0, 1)..Point::new(0, 1),
+                Point::new(1, 1)..Point::new(1, 1),
                 Point::new(2, 1)..Point::new(2, 1),
             ])
         });
@@ -5118,6 +6065,21 @@ async fn test_delete_autoclose_pair(cx: &mut gpui::TestAppContext) {
                 Point::new(2, 1)..Point::new(2, 1)
             ]
         );
+
+        // The closing bracket is kept when the pair isn't empty
+        editor.handle_input("{", cx);
+        editor.handle_input("_", cx);
+        editor.move_left(&Default::default(), cx);
+        editor.backspace(&Default::default(), cx);
+        assert_eq!(
+            editor.text(cx),
+            "
+                a_}
+                b_}
+                c_}
+            "
+            .unindent()
+        );
     });
 }
 
@@ -5747,6 +6709,286 @@ async fn test_strip_whitespace_and_format_via_lsp(cx: &mut gpui::TestAppContext)
     );
 }
 
+#[gpui::test]
+async fn test_code_actions_are_cached_until_the_buffer_changes(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorLspTestContext::new_rust(
+        lsp::ServerCapabilities {
+            code_action_provider: Some(lsp::CodeActionProviderCapability::Simple(true)),
+            ..Default::default()
+        },
+        cx,
+    )
+    .await;
+
+    let request_count = Arc::new(AtomicUsize::new(0));
+    cx.handle_request::<lsp::request::CodeActionRequest, _, _>({
+        let request_count = request_count.clone();
+        move |_, _, _| {
+            request_count.fetch_add(1, atomic::Ordering::SeqCst);
+            async move {
+                Ok(Some(vec![lsp::CodeActionOrCommand::CodeAction(
+                    lsp::CodeAction {
+                        title: "Inline variable".to_string(),
+                        ..Default::default()
+                    },
+                )]))
+            }
+        }
+    });
+    let settle = |cx: &mut EditorLspTestContext| {
+        cx.executor()
+            .advance_clock(CODE_ACTIONS_DEBOUNCE_TIMEOUT + Duration::from_millis(50));
+        cx.executor().run_until_parked();
+    };
+
+    cx.set_state("let ˇa = 1;");
+    settle(&mut cx);
+    assert_eq!(request_count.load(atomic::Ordering::SeqCst), 1);
+    cx.editor(|editor, _| assert!(editor.has_available_code_actions()));
+
+    cx.update_editor(|editor, cx| editor.move_right(&MoveRight, cx));
+    settle(&mut cx);
+    assert_eq!(request_count.load(atomic::Ordering::SeqCst), 2);
+
+    // Returning to a range whose actions were already fetched doesn't query the server.
+    cx.update_editor(|editor, cx| editor.move_left(&MoveLeft, cx));
+    settle(&mut cx);
+    assert_eq!(request_count.load(atomic::Ordering::SeqCst), 2);
+    cx.editor(|editor, _| assert!(editor.has_available_code_actions()));
+
+    // Editing the buffer invalidates the cache.
+    cx.simulate_keystroke("b");
+    cx.update_editor(|editor, cx| editor.move_left(&MoveLeft, cx));
+    settle(&mut cx);
+    cx.assert_editor_state("let ˇba = 1;");
+    assert_eq!(request_count.load(atomic::Ordering::SeqCst), 3);
+}
+
+#[gpui::test]
+fn test_keyed_text_highlights(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    enum SearchHighlight {}
+    enum HoverHighlight {}
+
+    let editor = cx.add_window(|cx| {
+        let buffer = MultiBuffer::build_simple("one two three", cx);
+        build_editor(buffer, cx)
+    });
+    _ = editor.update(cx, |editor, cx| {
+        let snapshot = editor.buffer().read(cx).snapshot(cx);
+        let range = |range: Range<usize>| {
+            snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end)
+        };
+        let offsets = |ranges: &[Range<Anchor>]| {
+            ranges
+                .iter()
+                .map(|range| range.to_offset(&snapshot))
+                .collect::<Vec<_>>()
+        };
+        let ranges = |editor: &Editor, key: Option<usize>, cx: &AppContext| {
+            key.map_or_else(
+                || editor.text_highlights::<SearchHighlight>(cx),
+                |key| editor.text_highlights_key::<SearchHighlight>(key, cx),
+            )
+            .map(|(_, ranges)| offsets(ranges))
+        };
+
+        editor.highlight_text::<SearchHighlight>(vec![range(0..3)], Default::default(), cx);
+        editor.highlight_text_key::<SearchHighlight>(0, vec![range(4..7)], Default::default(), cx);
+        editor.highlight_text_key::<SearchHighlight>(1, vec![range(8..13)], Default::default(), cx);
+        editor.highlight_text::<HoverHighlight>(vec![range(0..13)], Default::default(), cx);
+        assert_eq!(ranges(editor, None, cx), Some(vec![0..3]));
+        assert_eq!(ranges(editor, Some(0), cx), Some(vec![4..7]));
+        assert_eq!(ranges(editor, Some(1), cx), Some(vec![8..13]));
+
+        // Each keyed layer is replaced and cleared independently.
+        editor.highlight_text_key::<SearchHighlight>(0, vec![range(5..6)], Default::default(), cx);
+        editor.clear_highlights_key::<SearchHighlight>(1, cx);
+        assert_eq!(ranges(editor, None, cx), Some(vec![0..3]));
+        assert_eq!(ranges(editor, Some(0), cx), Some(vec![5..6]));
+        assert_eq!(ranges(editor, Some(1), cx), None);
+
+        // Clearing a type's highlights clears all of its layers, but no other type's.
+        editor.clear_highlights::<SearchHighlight>(cx);
+        assert_eq!(ranges(editor, None, cx), None);
+        assert_eq!(ranges(editor, Some(0), cx), None);
+        assert_eq!(
+            editor
+                .text_highlights::<HoverHighlight>(cx)
+                .map(|(_, ranges)| offsets(ranges)),
+            Some(vec![0..13])
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_ghost_text(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    let show_ghost_text = |text: &'static str, cx: &mut EditorTestContext| {
+        cx.update_editor(|editor, cx| {
+            let position = editor.selections.newest_anchor().head();
+            editor.show_ghost_text(position, text, cx);
+        });
+    };
+    let display_text =
+        |cx: &mut EditorTestContext| cx.update_editor(|editor, cx| editor.display_text(cx));
+    let display_chunks = |cx: &mut EditorTestContext| {
+        cx.update_editor(|editor, cx| {
+            let snapshot = editor.snapshot(cx).display_snapshot;
+            let mut chunks: Vec<(String, bool)> = Vec::new();
+            for chunk in snapshot.chunks(0..snapshot.max_point().row() + 1, false, None, None) {
+                match chunks.last_mut() {
+                    Some((text, is_suggestion)) if *is_suggestion == chunk.is_suggestion => {
+                        text.push_str(chunk.text)
+                    }
+                    _ => chunks.push((chunk.text.to_string(), chunk.is_suggestion)),
+                }
+            }
+            chunks
+        })
+    };
+
+    cx.set_state("helloˇ");
+    show_ghost_text(" world", &mut cx);
+    assert_eq!(display_text(&mut cx), "hello world");
+    assert_eq!(cx.buffer_text(), "hello");
+    assert_eq!(
+        display_chunks(&mut cx),
+        [("hello".to_string(), false), (" world".to_string(), true)]
+    );
+
+    // Typing the start of the ghost text consumes it.
+    cx.update_editor(|editor, cx| editor.handle_input(" w", cx));
+    cx.assert_editor_state("hello wˇ");
+    assert_eq!(display_text(&mut cx), "hello world");
+    cx.editor(|editor, _| assert!(editor.has_ghost_text()));
+    assert_eq!(
+        display_chunks(&mut cx),
+        [("hello w".to_string(), false), ("orld".to_string(), true)]
+    );
+
+    // Typing something else dismisses it.
+    cx.update_editor(|editor, cx| editor.handle_input("x", cx));
+    assert_eq!(display_text(&mut cx), "hello wx");
+    cx.editor(|editor, _| assert!(!editor.has_ghost_text()));
+
+    // Accepting the ghost text inserts it and moves the cursor after it.
+    show_ghost_text("yz", &mut cx);
+    cx.update_editor(|editor, cx| assert!(editor.accept_ghost_text(cx)));
+    cx.assert_editor_state("hello wxyzˇ");
+    assert_eq!(display_text(&mut cx), "hello wxyz");
+    assert_eq!(display_chunks(&mut cx), [("hello wxyz".to_string(), false)]);
+
+    show_ghost_text("!", &mut cx);
+    cx.update_editor(|editor, cx| editor.cancel(&Cancel, cx));
+    cx.editor(|editor, _| assert!(!editor.has_ghost_text()));
+    assert_eq!(display_text(&mut cx), "hello wxyz");
+    cx.assert_editor_state("hello wxyzˇ");
+}
+
+#[gpui::test]
+async fn test_signature_help(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorLspTestContext::new_rust(
+        lsp::ServerCapabilities {
+            signature_help_provider: Some(lsp::SignatureHelpOptions {
+                trigger_characters: Some(vec!["(".to_string()]),
+                retrigger_characters: Some(vec![",".to_string()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        cx,
+    )
+    .await;
+
+    let mut requests =
+        cx.handle_request::<lsp::request::SignatureHelpRequest, _, _>(|_, params, _| async move {
+            let column = params.text_document_position_params.position.character;
+            Ok(Some(lsp::SignatureHelp {
+                signatures: vec![lsp::SignatureInformation {
+                    label: "fn foo(a: u8, b: u8)".to_string(),
+                    documentation: None,
+                    parameters: Some(vec![
+                        // A parameter that can't be found in the label keeps its place, so
+                        // that the active parameter refers to the right one.
+                        lsp::ParameterInformation {
+                            label: lsp::ParameterLabel::Simple("self".to_string()),
+                            documentation: None,
+                        },
+                        lsp::ParameterInformation {
+                            label: lsp::ParameterLabel::Simple("a: u8".to_string()),
+                            documentation: None,
+                        },
+                        lsp::ParameterInformation {
+                            label: lsp::ParameterLabel::LabelOffsets([14, 19]),
+                            documentation: None,
+                        },
+                    ]),
+                    active_parameter: None,
+                }],
+                active_signature: Some(0),
+                active_parameter: Some(if column > 5 { 2 } else { 1 }),
+            }))
+        });
+    let active_parameter_range = |cx: &mut EditorLspTestContext| {
+        cx.editor(|editor, _| {
+            editor
+                .signature_help_state()
+                .popover()
+                .and_then(|popover| popover.active_parameter_range())
+        })
+    };
+
+    cx.set_state("fooˇ");
+    cx.simulate_keystroke("(");
+    requests.next().await;
+    cx.executor().run_until_parked();
+    cx.assert_editor_state("foo(ˇ)");
+    assert_eq!(active_parameter_range(&mut cx), Some(7..12));
+
+    // Typing within an argument doesn't query the server again.
+    cx.simulate_keystroke("1");
+    cx.executor().run_until_parked();
+    assert_eq!(active_parameter_range(&mut cx), Some(7..12));
+
+    // Separators advance the active parameter right away, and then retrigger the request.
+    cx.simulate_keystroke(",");
+    assert_eq!(active_parameter_range(&mut cx), Some(14..19));
+    requests.next().await;
+    cx.executor().run_until_parked();
+    assert_eq!(active_parameter_range(&mut cx), Some(14..19));
+
+    // Commas nested in brackets don't separate the call's arguments.
+    cx.simulate_keystrokes(["[", "2", ","]);
+    cx.executor().run_until_parked();
+    cx.assert_editor_state("foo(1,[2,ˇ])");
+    assert_eq!(active_parameter_range(&mut cx), Some(14..19));
+    cx.simulate_keystroke("]");
+    cx.executor().run_until_parked();
+    assert_eq!(active_parameter_range(&mut cx), Some(14..19));
+
+    // Leaving the call dismisses the signature help.
+    cx.simulate_keystroke(")");
+    cx.executor().run_until_parked();
+    cx.assert_editor_state("foo(1,[2,])ˇ");
+    cx.editor(|editor, _| assert!(!editor.signature_help_state().is_shown()));
+
+    cx.set_state("fooˇ");
+    cx.simulate_keystroke("(");
+    requests.next().await;
+    cx.executor().run_until_parked();
+    cx.editor(|editor, _| assert!(editor.signature_help_state().is_shown()));
+    cx.update_editor(|editor, cx| editor.cancel(&Cancel, cx));
+    cx.editor(|editor, _| assert!(!editor.signature_help_state().is_shown()));
+}
+
 #[gpui::test]
 async fn test_completion(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -6280,6 +7522,86 @@ async fn test_toggle_block_comment(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+fn test_toggle_comment_across_excerpts(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let line_comment_language = Arc::new(Language::new(
+        LanguageConfig {
+            line_comments: vec!["// ".into()],
+            ..Default::default()
+        },
+        None,
+    ));
+    let block_comment_language = Arc::new(Language::new(
+        LanguageConfig {
+            block_comment: Some(("/* ".into(), " */".into())),
+            ..Default::default()
+        },
+        None,
+    ));
+
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "a();\nb();",
+        )
+        .with_language(line_comment_language, cx)
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "c\nd")
+            .with_language(block_comment_language, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        multibuffer.push_excerpts(
+            buffer_1.clone(),
+            [ExcerptRange {
+                context: Point::new(0, 0)..Point::new(1, 4),
+                primary: None,
+            }],
+            cx,
+        );
+        multibuffer.push_excerpts(
+            buffer_2.clone(),
+            [ExcerptRange {
+                context: Point::new(0, 0)..Point::new(1, 1),
+                primary: None,
+            }],
+            cx,
+        );
+        multibuffer
+    });
+
+    let (view, cx) = cx.add_window_view(|cx| build_editor(multibuffer, cx));
+
+    // Each excerpt's rows are toggled using the comment syntax of its own language.
+    _ = view.update(cx, |view, cx| {
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 0)..Point::new(3, 1)])
+        });
+        view.toggle_comments(&ToggleComments::default(), cx);
+    });
+    assert_eq!(
+        buffer_1.read_with(cx, |buffer, _| buffer.text()),
+        "a();\n// b();"
+    );
+    assert_eq!(
+        buffer_2.read_with(cx, |buffer, _| buffer.text()),
+        "/* c\nd */"
+    );
+
+    _ = view.update(cx, |view, cx| {
+        view.toggle_comments(&ToggleComments::default(), cx);
+    });
+    assert_eq!(
+        buffer_1.read_with(cx, |buffer, _| buffer.text()),
+        "a();\nb();"
+    );
+    assert_eq!(buffer_2.read_with(cx, |buffer, _| buffer.text()), "c\nd");
+}
+
 #[gpui::test]
 fn test_editing_disjoint_excerpts(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -6434,6 +7756,70 @@ fn test_editing_overlapping_excerpts(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+fn test_add_selection_above_below_in_multibuffer(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 4, 'a'),
+        )
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            sample_text(2, 4, 'c'),
+        )
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [buffer_1, buffer_2] {
+            multibuffer.push_excerpts(
+                buffer,
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 4),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        multibuffer
+    });
+
+    let (view, cx) = cx.add_window_view(|cx| build_editor(multibuffer, cx));
+    _ = view.update(cx, |view, cx| {
+        assert_eq!(view.text(cx), "aaaa\nbbbb\ncccc\ndddd");
+
+        // Cursors skip the header between the excerpts.
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(2, 0)..Point::new(2, 0)])
+        });
+        view.add_selection_above(&Default::default(), cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [
+                Point::new(1, 0)..Point::new(1, 0),
+                Point::new(2, 0)..Point::new(2, 0)
+            ]
+        );
+
+        view.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 2)..Point::new(1, 2)])
+        });
+        view.add_selection_below(&Default::default(), cx);
+        assert_eq!(
+            view.selections.ranges(cx),
+            [
+                Point::new(1, 2)..Point::new(1, 2),
+                Point::new(2, 2)..Point::new(2, 2)
+            ]
+        );
+    });
+}
+
 #[gpui::test]
 fn test_refresh_selections(cx: &mut TestAppContext) {
     init_test(cx, |_| {});
@@ -6472,56 +7858,146 @@ fn test_refresh_selections(cx: &mut TestAppContext) {
     let editor = cx.add_window(|cx| {
         let mut editor = build_editor(multibuffer.clone(), cx);
         let snapshot = editor.snapshot(cx);
-        editor.change_selections(None, cx, |s| {
-            s.select_ranges([Point::new(1, 3)..Point::new(1, 3)])
-        });
-        editor.begin_selection(Point::new(2, 1).to_display_point(&snapshot), true, 1, cx);
+        editor.change_selections(None, cx, |s| {
+            s.select_ranges([Point::new(1, 3)..Point::new(1, 3)])
+        });
+        editor.begin_selection(Point::new(2, 1).to_display_point(&snapshot), true, 1, cx);
+        assert_eq!(
+            editor.selections.ranges(cx),
+            [
+                Point::new(1, 3)..Point::new(1, 3),
+                Point::new(2, 1)..Point::new(2, 1),
+            ]
+        );
+        editor
+    });
+
+    // Refreshing selections is a no-op when excerpts haven't changed.
+    _ = editor.update(cx, |editor, cx| {
+        editor.change_selections(None, cx, |s| s.refresh());
+        assert_eq!(
+            editor.selections.ranges(cx),
+            [
+                Point::new(1, 3)..Point::new(1, 3),
+                Point::new(2, 1)..Point::new(2, 1),
+            ]
+        );
+    });
+
+    _ = multibuffer.update(cx, |multibuffer, cx| {
+        multibuffer.remove_excerpts([excerpt1_id.unwrap()], cx);
+    });
+    _ = editor.update(cx, |editor, cx| {
+        // Removing an excerpt causes the first selection to become degenerate.
+        assert_eq!(
+            editor.selections.ranges(cx),
+            [
+                Point::new(0, 0)..Point::new(0, 0),
+                Point::new(0, 1)..Point::new(0, 1)
+            ]
+        );
+
+        // Refreshing selections will relocate the first selection to the original buffer
+        // location.
+        editor.change_selections(None, cx, |s| s.refresh());
+        assert_eq!(
+            editor.selections.ranges(cx),
+            [
+                Point::new(0, 1)..Point::new(0, 1),
+                Point::new(0, 3)..Point::new(0, 3)
+            ]
+        );
+        assert!(editor.selections.pending_anchor().is_some());
+    });
+}
+
+#[gpui::test]
+fn test_selection_granularity_across_excerpts(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+
+    let buffer_1 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "aaa bbb\nccc",
+        )
+    });
+    let buffer_2 = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "ddd eee",
+        )
+    });
+    let multibuffer = cx.new_model(|cx| {
+        let mut multibuffer = MultiBuffer::new(0, ReadWrite);
+        for buffer in [&buffer_1, &buffer_2] {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: 0..buffer.read(cx).len(),
+                    primary: None,
+                }],
+                cx,
+            );
+        }
+        assert_eq!(multibuffer.read(cx).text(), "aaa bbb\nccc\nddd eee");
+        multibuffer
+    });
+    let editor = cx.add_window(|cx| build_editor(multibuffer, cx));
+
+    // Dragging after a double-click extends the selection by whole words, into the next excerpt.
+    _ = editor.update(cx, |editor, cx| {
+        let snapshot = editor.snapshot(cx);
+        editor.begin_selection(Point::new(0, 5).to_display_point(&snapshot), false, 2, cx);
         assert_eq!(
             editor.selections.ranges(cx),
-            [
-                Point::new(1, 3)..Point::new(1, 3),
-                Point::new(2, 1)..Point::new(2, 1),
-            ]
+            [Point::new(0, 4)..Point::new(0, 7)]
         );
-        editor
-    });
 
-    // Refreshing selections is a no-op when excerpts haven't changed.
-    _ = editor.update(cx, |editor, cx| {
-        editor.change_selections(None, cx, |s| s.refresh());
+        editor.update_selection(
+            Point::new(2, 1).to_display_point(&snapshot),
+            0,
+            gpui::Point::<f32>::default(),
+            cx,
+        );
         assert_eq!(
             editor.selections.ranges(cx),
-            [
-                Point::new(1, 3)..Point::new(1, 3),
-                Point::new(2, 1)..Point::new(2, 1),
-            ]
+            [Point::new(0, 4)..Point::new(2, 3)]
         );
+        editor.end_selection(cx);
     });
 
-    _ = multibuffer.update(cx, |multibuffer, cx| {
-        multibuffer.remove_excerpts([excerpt1_id.unwrap()], cx);
-    });
+    // Dragging after a triple-click extends the selection by whole lines, in either direction.
     _ = editor.update(cx, |editor, cx| {
-        // Removing an excerpt causes the first selection to become degenerate.
+        let snapshot = editor.snapshot(cx);
+        editor.begin_selection(Point::new(1, 1).to_display_point(&snapshot), false, 3, cx);
         assert_eq!(
             editor.selections.ranges(cx),
-            [
-                Point::new(0, 0)..Point::new(0, 0),
-                Point::new(0, 1)..Point::new(0, 1)
-            ]
+            [Point::new(1, 0)..Point::new(2, 0)]
         );
 
-        // Refreshing selections will relocate the first selection to the original buffer
-        // location.
-        editor.change_selections(None, cx, |s| s.refresh());
+        editor.update_selection(
+            Point::new(2, 2).to_display_point(&snapshot),
+            0,
+            gpui::Point::<f32>::default(),
+            cx,
+        );
         assert_eq!(
             editor.selections.ranges(cx),
-            [
-                Point::new(0, 1)..Point::new(0, 1),
-                Point::new(0, 3)..Point::new(0, 3)
-            ]
+            [Point::new(1, 0)..Point::new(2, 7)]
         );
-        assert!(editor.selections.pending_anchor().is_some());
+
+        editor.update_selection(
+            Point::new(0, 2).to_display_point(&snapshot),
+            0,
+            gpui::Point::<f32>::default(),
+            cx,
+        );
+        let selection = editor.selections.newest::<Point>(cx);
+        assert_eq!(selection.range(), Point::new(0, 0)..Point::new(2, 0));
+        assert!(selection.reversed);
+        editor.end_selection(cx);
     });
 }
 
@@ -7324,6 +8800,214 @@ async fn go_to_hunk(executor: BackgroundExecutor, cx: &mut gpui::TestAppContext)
     );
 }
 
+#[gpui::test]
+async fn test_bookmarks(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state(indoc! {"
+        oˇne
+        two
+        three
+        four
+        five
+    "});
+    cx.update_editor(|editor, cx| editor.toggle_bookmark(&ToggleBookmark, cx));
+    cx.set_selections_state(indoc! {"
+        one
+        two
+        three
+        fˇour
+        five
+    "});
+    cx.update_editor(|editor, cx| editor.toggle_bookmark(&ToggleBookmark, cx));
+
+    // Bookmarks move with the text around them.
+    cx.update_editor(|editor, cx| {
+        editor.change_selections(None, cx, |s| s.select_ranges([0..0]));
+        editor.insert("zero\n", cx);
+        editor.go_to_next_bookmark(&GoToNextBookmark, cx);
+    });
+    cx.assert_editor_state(indoc! {"
+        zero
+        one
+        two
+        three
+        fˇour
+        five
+    "});
+
+    // Navigation wraps around the ends of the buffer.
+    cx.update_editor(|editor, cx| editor.go_to_next_bookmark(&GoToNextBookmark, cx));
+    cx.assert_editor_state(indoc! {"
+        zero
+        oˇne
+        two
+        three
+        four
+        five
+    "});
+    cx.update_editor(|editor, cx| editor.go_to_prev_bookmark(&GoToPrevBookmark, cx));
+    cx.assert_editor_state(indoc! {"
+        zero
+        one
+        two
+        three
+        fˇour
+        five
+    "});
+
+    // Toggling on a bookmarked row removes its bookmark.
+    cx.update_editor(|editor, cx| {
+        editor.toggle_bookmark(&ToggleBookmark, cx);
+        assert_eq!(editor.bookmarks().len(), 1);
+        let snapshot = editor.snapshot(cx);
+        assert_eq!(
+            editor.bookmarked_display_rows(0..6, &snapshot.display_snapshot),
+            vec![1]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_macro_recording_and_replay(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.update_buffer(|buffer, _| buffer.set_group_interval(Duration::ZERO));
+    cx.set_state(indoc! {"
+        ˇone
+        two
+        three
+    "});
+
+    cx.dispatch_action(ToggleMacroRecording);
+    cx.dispatch_action(MoveToEndOfLine);
+    cx.simulate_input("!");
+    cx.dispatch_action(MoveDown);
+    cx.dispatch_action(MoveToBeginningOfLine);
+    cx.dispatch_action(ToggleMacroRecording);
+    cx.assert_editor_state(indoc! {"
+        one!
+        ˇtwo
+        three
+    "});
+    cx.update_editor(|editor, _| {
+        assert!(!editor.is_recording_macro());
+        assert_eq!(
+            editor.recorded_macro().map(|commands| commands.len()),
+            Some(4)
+        );
+    });
+
+    cx.dispatch_action(ReplayMacro);
+    cx.assert_editor_state(indoc! {"
+        one!
+        two!
+        ˇthree
+    "});
+    cx.dispatch_action(ReplayMacro);
+    cx.assert_editor_state(indoc! {"
+        one!
+        two!
+        three!
+        ˇ
+    "});
+
+    // Each replay is undone as a whole.
+    cx.update_editor(|editor, cx| editor.undo(&Undo, cx));
+    cx.assert_editor_state(indoc! {"
+        one!
+        two!
+        ˇthree
+    "});
+
+    // Replaying doesn't add to the recorded macro.
+    cx.update_editor(|editor, _| {
+        assert_eq!(
+            editor.recorded_macro().map(|commands| commands.len()),
+            Some(4)
+        );
+    });
+
+    // Macros aren't replayed in an editor that isn't focused.
+    cx.update_editor(|editor, cx| {
+        cx.blur();
+        editor.replay_macro(&ReplayMacro, cx);
+    });
+    cx.run_until_parked();
+    cx.assert_editor_state(indoc! {"
+        one!
+        two!
+        ˇthree
+    "});
+}
+
+#[gpui::test]
+async fn test_selection_drag_and_drop(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+    cx.set_state(indoc! {"
+        «oneˇ» two
+        three
+    "});
+
+    // Dragging the selection moves its text, which remains selected.
+    cx.update_editor(|editor, cx| {
+        assert!(editor.begin_selection_drag(DisplayPoint::new(0, 1), cx));
+        editor.update_selection_drag(DisplayPoint::new(1, 2), cx);
+        editor.update_selection_drag(DisplayPoint::new(1, 5), cx);
+        editor.end_selection_drag(false, cx);
+        assert!(!editor.has_selection_drag());
+    });
+    cx.assert_editor_state(indoc! {"
+         two
+        three«oneˇ»
+    "});
+
+    // The move is undone as a whole.
+    cx.update_editor(|editor, cx| editor.undo(&Undo, cx));
+    cx.assert_editor_state(indoc! {"
+        «oneˇ» two
+        three
+    "});
+
+    // Dropping with the copy modifier leaves the original text in place.
+    cx.update_editor(|editor, cx| {
+        assert!(editor.begin_selection_drag(DisplayPoint::new(0, 1), cx));
+        editor.update_selection_drag(DisplayPoint::new(1, 0), cx);
+        editor.end_selection_drag(true, cx);
+    });
+    cx.assert_editor_state(indoc! {"
+        one two
+        «oneˇ»three
+    "});
+    cx.update_editor(|editor, cx| editor.undo(&Undo, cx));
+
+    // Moving text onto itself does nothing.
+    cx.update_editor(|editor, cx| {
+        assert!(editor.begin_selection_drag(DisplayPoint::new(0, 1), cx));
+        editor.update_selection_drag(DisplayPoint::new(0, 2), cx);
+        editor.end_selection_drag(false, cx);
+    });
+    cx.assert_editor_state(indoc! {"
+        «oneˇ» two
+        three
+    "});
+
+    // Clicking within the selection without dragging places the cursor there.
+    cx.update_editor(|editor, cx| {
+        assert!(!editor.begin_selection_drag(DisplayPoint::new(0, 5), cx));
+        assert!(editor.begin_selection_drag(DisplayPoint::new(0, 1), cx));
+        editor.end_selection_drag(false, cx);
+    });
+    cx.assert_editor_state(indoc! {"
+        oˇne two
+        three
+    "});
+}
+
 #[test]
 fn test_split_words() {
     fn split<'a>(text: &'a str) -> Vec<&'a str> {
@@ -8253,6 +9937,137 @@ async fn test_completions_with_additional_edits(cx: &mut gpui::TestAppContext) {
     cx.assert_editor_state(indoc! {"fn main() { let a = Some(2)ˇ; }"});
 }
 
+#[gpui::test]
+async fn test_selected_completion_documentation_is_resolved_once_settled(
+    cx: &mut gpui::TestAppContext,
+) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorLspTestContext::new_rust(
+        lsp::ServerCapabilities {
+            completion_provider: Some(lsp::CompletionOptions {
+                resolve_provider: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        cx,
+    )
+    .await;
+
+    cx.lsp
+        .handle_request::<lsp::request::Completion, _, _>(move |_, _| async move {
+            Ok(Some(lsp::CompletionResponse::Array(
+                ["first", "second", "third"]
+                    .into_iter()
+                    .map(|label| lsp::CompletionItem {
+                        label: label.into(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )))
+        });
+    let resolved_labels = Arc::new(Mutex::new(Vec::new()));
+    cx.lsp
+        .handle_request::<lsp::request::ResolveCompletionItem, _, _>({
+            let resolved_labels = resolved_labels.clone();
+            move |completion_item, _| {
+                resolved_labels.lock().push(completion_item.label);
+                // Leave the documentation unresolved, so that it would be requested again
+                // whenever the completion is selected.
+                async move { Err(anyhow!("no documentation")) }
+            }
+        });
+
+    cx.set_state("ˇ");
+    cx.update_editor(|editor, cx| editor.show_completions(&ShowCompletions, cx));
+    cx.executor().run_until_parked();
+    let delay = cx.update(|cx| {
+        Duration::from_millis(
+            EditorSettings::get_global(cx).completion_documentation_secondary_query_debounce,
+        )
+    });
+
+    // The documentation of every completion is requested once the menu has been shown.
+    cx.executor().advance_clock(delay);
+    cx.executor().run_until_parked();
+    let mut labels = mem::take(&mut *resolved_labels.lock());
+    labels.sort();
+    assert_eq!(labels, ["first", "second", "third"]);
+
+    // Moving through the menu only requests the documentation of the completion that ends
+    // up selected, once the selection has settled.
+    cx.update_editor(|editor, cx| {
+        editor.context_menu_next(&ContextMenuNext, cx);
+        editor.context_menu_next(&ContextMenuNext, cx);
+    });
+    cx.executor().advance_clock(delay / 2);
+    cx.update_editor(|editor, cx| editor.context_menu_prev(&ContextMenuPrev, cx));
+    cx.executor().advance_clock(delay / 2);
+    cx.executor().run_until_parked();
+    assert!(resolved_labels.lock().is_empty());
+
+    cx.executor().advance_clock(delay);
+    cx.executor().run_until_parked();
+    let selected_label = cx.update_editor(|editor, _| {
+        let Some(ContextMenu::Completions(menu)) = editor.context_menu.read().as_ref() else {
+            panic!("expected the completions menu to be visible");
+        };
+        menu.matches[menu.selected_item].string.clone()
+    });
+    assert_eq!(*resolved_labels.lock(), [selected_label]);
+}
+
+#[gpui::test]
+async fn test_completions_filtered_by_filter_text(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorLspTestContext::new_rust(
+        lsp::ServerCapabilities {
+            completion_provider: Some(lsp::CompletionOptions::default()),
+            ..Default::default()
+        },
+        cx,
+    )
+    .await;
+
+    cx.lsp
+        .handle_request::<lsp::request::Completion, _, _>(move |_, _| async move {
+            Ok(Some(lsp::CompletionResponse::Array(vec![
+                lsp::CompletionItem {
+                    label: "init".into(),
+                    ..Default::default()
+                },
+                lsp::CompletionItem {
+                    label: "insert".into(),
+                    ..Default::default()
+                },
+                lsp::CompletionItem {
+                    label: "new()".into(),
+                    filter_text: Some("instance".into()),
+                    ..Default::default()
+                },
+            ])))
+        });
+
+    cx.set_state("insˇ");
+    cx.update_editor(|editor, cx| editor.show_completions(&ShowCompletions, cx));
+    cx.executor().run_until_parked();
+    cx.update_editor(|editor, _| {
+        if let Some(ContextMenu::Completions(menu)) = editor.context_menu.read().as_ref() {
+            assert_eq!(
+                menu.matches
+                    .iter()
+                    .map(|m| (m.string.as_str(), m.positions.clone()))
+                    .collect::<Vec<_>>(),
+                &[("insert", vec![0, 1, 2]), ("new()", vec![])]
+            );
+        } else {
+            panic!("expected completion menu to be open");
+        }
+    });
+}
+
 #[gpui::test]
 async fn test_completions_in_languages_with_extra_word_characters(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -13,8 +13,8 @@ use crate::{
     scroll::scroll_amount::ScrollAmount,
     CursorShape, DisplayPoint, DocumentHighlightRead, DocumentHighlightWrite, Editor, EditorMode,
     EditorSettings, EditorSnapshot, EditorStyle, HalfPageDown, HalfPageUp, HoveredCursor, LineDown,
-    LineUp, OpenExcerpts, PageDown, PageUp, Point, SelectPhase, Selection, SoftWrap, ToPoint,
-    CURSORS_VISIBLE_FOR, MAX_LINE_LEN,
+    LineUp, OpenExcerpts, PageDown, PageUp, Point, SelectPhase, Selection, SelectionGoal, SoftWrap,
+    ToPoint, CURSORS_VISIBLE_FOR, MAX_LINE_LEN,
 };
 use anyhow::Result;
 use collections::{BTreeMap, HashMap};
@@ -159,6 +159,7 @@ impl EditorElement {
         register_action(view, cx, Editor::outdent);
         register_action(view, cx, Editor::delete_line);
         register_action(view, cx, Editor::join_lines);
+        register_action(view, cx, Editor::rewrap);
         register_action(view, cx, Editor::sort_lines_case_sensitive);
         register_action(view, cx, Editor::sort_lines_case_insensitive);
         register_action(view, cx, Editor::reverse_lines);
@@ -258,6 +259,11 @@ impl EditorElement {
         register_action(view, cx, Editor::go_to_prev_diagnostic);
         register_action(view, cx, Editor::go_to_hunk);
         register_action(view, cx, Editor::go_to_prev_hunk);
+        register_action(view, cx, Editor::toggle_bookmark);
+        register_action(view, cx, Editor::go_to_next_bookmark);
+        register_action(view, cx, Editor::go_to_prev_bookmark);
+        register_action(view, cx, Editor::toggle_macro_recording);
+        register_action(view, cx, Editor::replay_macro);
         register_action(view, cx, Editor::go_to_definition);
         register_action(view, cx, Editor::go_to_definition_split);
         register_action(view, cx, Editor::go_to_type_definition);
@@ -429,6 +435,11 @@ impl EditorElement {
                 },
                 cx,
             );
+        } else if click_count == 1
+            && !modifiers.modified()
+            && editor.begin_selection_drag(position, cx)
+        {
+            // Pressing within a selection starts dragging its text.
         } else {
             editor.select(
                 SelectPhase::Begin {
@@ -472,6 +483,13 @@ impl EditorElement {
         stacking_order: &StackingOrder,
         cx: &mut ViewContext<Editor>,
     ) {
+        if editor.has_selection_drag() {
+            // Holding alt when dropping copies the text instead of moving it.
+            editor.end_selection_drag(event.modifiers.alt, cx);
+            cx.stop_propagation();
+            return;
+        }
+
         let end_selection = editor.has_pending_selection();
         let pending_nonempty_selections = editor.has_pending_nonempty_selection();
 
@@ -503,6 +521,11 @@ impl EditorElement {
         _stacking_order: &StackingOrder,
         cx: &mut ViewContext<Editor>,
     ) {
+        if editor.has_selection_drag() {
+            let point_for_position = position_map.point_for_position(text_bounds, event.position);
+            editor.update_selection_drag(point_for_position.previous_valid, cx);
+            return;
+        }
         if !editor.has_pending_selection() {
             return;
         }
@@ -2067,6 +2090,26 @@ impl EditorElement {
                     layouts.push(layout);
                 }
 
+                // Show a cursor where dragged text will be dropped.
+                if let Some(drop_target) = editor.selection_drop_target() {
+                    let drop_target = drop_target.to_point(&snapshot.buffer_snapshot);
+                    layouts.push(SelectionLayout::new(
+                        Selection {
+                            id: usize::MAX,
+                            start: drop_target,
+                            end: drop_target,
+                            reversed: false,
+                            goal: SelectionGoal::None,
+                        },
+                        false,
+                        CursorShape::Bar,
+                        &snapshot.display_snapshot,
+                        false,
+                        true,
+                        None,
+                    ));
+                }
+
                 let player = if editor.read_only(cx) {
                     cx.theme().players().read_only()
                 } else {
@@ -4058,9 +4101,10 @@ pub fn register_action<T: Action>(
 ) {
     let view = view.clone();
     cx.on_action(TypeId::of::<T>(), move |action, phase, cx| {
-        let action = action.downcast_ref().unwrap();
+        let action = action.downcast_ref::<T>().unwrap();
         if phase == DispatchPhase::Bubble {
             view.update(cx, |editor, cx| {
+                editor.record_macro_action(action);
                 listener(editor, action, cx);
             })
         }
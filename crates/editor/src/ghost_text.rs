@@ -0,0 +1,142 @@
+use crate::{display_map::Inlay, Anchor, Autoscroll, Editor};
+use gpui::ViewContext;
+use multi_buffer::ToOffset;
+use std::cmp::Ordering;
+use util::post_inc;
+
+/// Text that is displayed after a position without being part of the buffer, such as the
+/// preview of an inline completion.
+pub(crate) struct GhostText {
+    /// Where the ghost text was first shown. Text typed after this position that matches the
+    /// start of the ghost text is consumed from it, while any other edit there dismisses it.
+    start: Anchor,
+    text: String,
+    inlay: Inlay,
+}
+
+impl GhostText {
+    /// The part of the ghost text that hasn't been typed yet.
+    fn remaining_text(&self) -> String {
+        self.inlay.text.to_string()
+    }
+}
+
+impl Editor {
+    /// Displays `text` after `position` until it is accepted or dismissed, replacing any ghost
+    /// text that was shown before.
+    pub fn show_ghost_text(
+        &mut self,
+        position: Anchor,
+        text: impl Into<String>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.dismiss_ghost_text(cx);
+        let text = text.into();
+        if text.is_empty() {
+            return;
+        }
+
+        let snapshot = self.buffer.read(cx).snapshot(cx);
+        let offset = position.to_offset(&snapshot);
+        let inlay = Inlay::suggestion(
+            post_inc(&mut self.next_inlay_id),
+            snapshot.anchor_after(offset),
+            text.as_str(),
+        );
+        self.display_map.update(cx, |map, cx| {
+            map.splice_inlays(Vec::new(), vec![inlay.clone()], cx)
+        });
+        self.ghost_text = Some(GhostText {
+            start: snapshot.anchor_before(offset),
+            text,
+            inlay,
+        });
+        cx.notify();
+    }
+
+    pub fn has_ghost_text(&self) -> bool {
+        self.ghost_text.is_some()
+    }
+
+    /// Inserts the ghost text into the buffer, moving the cursor after it if the cursor was
+    /// where the ghost text was displayed.
+    pub fn accept_ghost_text(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        let Some(ghost_text) = self.take_ghost_text(cx) else {
+            return false;
+        };
+
+        let snapshot = self.buffer.read(cx).snapshot(cx);
+        let offset = ghost_text.inlay.position.to_offset(&snapshot);
+        let text = ghost_text.remaining_text();
+        let move_cursor = self.selections.newest::<usize>(cx).head() == offset;
+        self.transact(cx, |this, cx| {
+            this.buffer.update(cx, |buffer, cx| {
+                buffer.edit([(offset..offset, text.as_str())], None, cx)
+            });
+            if move_cursor {
+                let end = offset + text.len();
+                this.change_selections(Some(Autoscroll::fit()), cx, |s| {
+                    s.select_ranges([end..end])
+                });
+            }
+        });
+        true
+    }
+
+    pub fn dismiss_ghost_text(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        self.take_ghost_text(cx).is_some()
+    }
+
+    fn take_ghost_text(&mut self, cx: &mut ViewContext<Self>) -> Option<GhostText> {
+        let ghost_text = self.ghost_text.take()?;
+        self.display_map.update(cx, |map, cx| {
+            map.splice_inlays(vec![ghost_text.inlay.id], Vec::new(), cx)
+        });
+        cx.notify();
+        Some(ghost_text)
+    }
+
+    /// Keeps the ghost text in sync with edits to the buffer. Typing the start of the ghost
+    /// text shortens it, while edits that conflict with it dismiss it.
+    pub(crate) fn refresh_ghost_text(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(ghost_text) = self.ghost_text.as_ref() else {
+            return;
+        };
+
+        let snapshot = self.buffer.read(cx).snapshot(cx);
+        let position = ghost_text.inlay.position;
+        if !ghost_text.start.is_valid(&snapshot)
+            || !position.is_valid(&snapshot)
+            || ghost_text.start.excerpt_id != position.excerpt_id
+            || ghost_text.start.cmp(&position, &snapshot) == Ordering::Greater
+        {
+            self.dismiss_ghost_text(cx);
+            return;
+        }
+
+        let typed_text = snapshot
+            .text_for_range(ghost_text.start..position)
+            .collect::<String>();
+        let Some(remaining_text) = ghost_text
+            .text
+            .strip_prefix(&typed_text)
+            .filter(|text| !text.is_empty())
+        else {
+            self.dismiss_ghost_text(cx);
+            return;
+        };
+        if remaining_text == ghost_text.remaining_text() {
+            return;
+        }
+
+        let inlay = Inlay::suggestion(post_inc(&mut self.next_inlay_id), position, remaining_text);
+        let old_inlay_id = ghost_text.inlay.id;
+        self.display_map.update(cx, |map, cx| {
+            map.splice_inlays(vec![old_inlay_id], vec![inlay.clone()], cx)
+        });
+        if let Some(ghost_text) = self.ghost_text.as_mut() {
+            ghost_text.inlay = inlay;
+        }
+        cx.notify();
+    }
+}
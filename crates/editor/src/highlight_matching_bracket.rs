@@ -15,18 +15,21 @@ pub fn refresh_matching_bracket_highlights(editor: &mut Editor, cx: &mut ViewCon
 
     let head = newest_selection.head();
     let snapshot = editor.snapshot(cx);
-    if let Some((opening_range, closing_range)) = snapshot
-        .buffer_snapshot
-        .innermost_enclosing_bracket_ranges(head..head)
-    {
-        editor.highlight_background::<MatchingBracketHighlight>(
-            vec![
-                opening_range.to_anchors(&snapshot.buffer_snapshot),
-                closing_range.to_anchors(&snapshot.buffer_snapshot),
-            ],
-            |theme| theme.editor_document_highlight_read_background,
-            cx,
-        )
+    // If one of the brackets is outside of the cursor's excerpt, only highlight the other.
+    if let Some(pair) = snapshot.buffer_snapshot.innermost_bracket_pair(head..head) {
+        let ranges = pair
+            .open
+            .into_iter()
+            .chain(pair.close)
+            .map(|range| range.to_anchors(&snapshot.buffer_snapshot))
+            .collect::<Vec<_>>();
+        if !ranges.is_empty() {
+            editor.highlight_background::<MatchingBracketHighlight>(
+                ranges,
+                |theme| theme.editor_document_highlight_read_background,
+                cx,
+            )
+        }
     }
 }
 
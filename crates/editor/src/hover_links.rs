@@ -1,9 +1,10 @@
 use crate::{
     element::PointForPosition,
     hover_popover::{self, InlayHover},
-    Anchor, Editor, EditorSnapshot, GoToDefinition, GoToTypeDefinition, InlayId, SelectPhase,
+    Anchor, Editor, EditorSnapshot, GoToDefinition, GoToTypeDefinition, InlayId,
+    MultiBufferSnapshot, SelectPhase,
 };
-use gpui::{px, AsyncWindowContext, Model, Modifiers, Task, ViewContext};
+use gpui::{px, AppContext, AsyncWindowContext, Model, Modifiers, Task, ViewContext};
 use language::{Bias, ToOffset};
 use linkify::{LinkFinder, LinkKind};
 use lsp::LanguageServerId;
@@ -120,6 +121,12 @@ impl Editor {
         }
     }
 
+    /// The range of the link under the mouse that is underlined while the command key is held.
+    pub fn hovered_link_range(&self, cx: &AppContext) -> Option<Range<Anchor>> {
+        let (_, ranges) = self.text_highlights::<HoveredLinkState>(cx)?;
+        ranges.first().cloned()
+    }
+
     pub(crate) fn hide_hovered_link(&mut self, cx: &mut ViewContext<Self>) {
         self.hovered_link_state.take();
         self.clear_highlights::<HoveredLinkState>(cx);
@@ -418,6 +425,7 @@ pub fn show_link_definition(
     let project = editor.project.clone();
 
     let snapshot = snapshot.buffer_snapshot.clone();
+    hovered_link_state.last_trigger_point = trigger_point.clone();
     hovered_link_state.task = Some(cx.spawn(|this, mut cx| {
         async move {
             let result = match &trigger_point {
@@ -482,9 +490,12 @@ pub fn show_link_definition(
                     return;
                 };
                 hovered_link_state.preferred_kind = preferred_kind;
+                // When the language server doesn't report the symbol's range, remember the
+                // hovered word instead, so that moving within it doesn't query the server again.
                 hovered_link_state.symbol_range = result
                     .as_ref()
-                    .and_then(|(symbol_range, _)| symbol_range.clone());
+                    .and_then(|(symbol_range, _)| symbol_range.clone())
+                    .or_else(|| surrounding_word_range(&trigger_point, &snapshot));
 
                 if let Some((symbol_range, definitions)) = result {
                     hovered_link_state.links = definitions.clone();
@@ -532,31 +543,20 @@ pub fn show_link_definition(
                             color: Some(cx.theme().colors().link_text_hover),
                             ..Default::default()
                         };
-                        let highlight_range =
-                            symbol_range.unwrap_or_else(|| match &trigger_point {
-                                TriggerPoint::Text(trigger_anchor) => {
-                                    // If no symbol range returned from language server, use the surrounding word.
-                                    let (offset_range, _) =
-                                        snapshot.surrounding_word(*trigger_anchor);
-                                    RangeInEditor::Text(
-                                        snapshot.anchor_before(offset_range.start)
-                                            ..snapshot.anchor_after(offset_range.end),
-                                    )
-                                }
-                                TriggerPoint::InlayHint(highlight, _, _) => {
-                                    RangeInEditor::Inlay(highlight.clone())
-                                }
-                            });
+                        let highlight_range = symbol_range
+                            .or_else(|| surrounding_word_range(&trigger_point, &snapshot));
 
                         match highlight_range {
-                            RangeInEditor::Text(text_range) => {
+                            Some(RangeInEditor::Text(text_range)) => {
                                 this.highlight_text::<HoveredLinkState>(vec![text_range], style, cx)
                             }
-                            RangeInEditor::Inlay(highlight) => this
+                            Some(RangeInEditor::Inlay(highlight)) => this
                                 .highlight_inlays::<HoveredLinkState>(vec![highlight], style, cx),
+                            None => {}
                         }
-                    } else {
-                        this.hide_hovered_link(cx);
+                    } else if let Some(hovered_link_state) = this.hovered_link_state.as_mut() {
+                        // Keep the state around so that the empty result stays cached.
+                        hovered_link_state.links.clear();
                     }
                 }
             })?;
@@ -569,6 +569,23 @@ pub fn show_link_definition(
     editor.hovered_link_state = Some(hovered_link_state);
 }
 
+/// The range to underline when the language server doesn't report the range of the symbol
+/// under the trigger point.
+fn surrounding_word_range(
+    trigger_point: &TriggerPoint,
+    snapshot: &MultiBufferSnapshot,
+) -> Option<RangeInEditor> {
+    match trigger_point {
+        TriggerPoint::Text(trigger_anchor) => {
+            let (offset_range, _) = snapshot.surrounding_word(*trigger_anchor);
+            Some(RangeInEditor::Text(
+                snapshot.anchor_before(offset_range.start)..snapshot.anchor_after(offset_range.end),
+            ))
+        }
+        TriggerPoint::InlayHint(highlight, _, _) => Some(RangeInEditor::Inlay(highlight.clone())),
+    }
+}
+
 pub(crate) fn find_url(
     buffer: &Model<language::Buffer>,
     position: text::Anchor,
@@ -643,6 +660,10 @@ mod tests {
     use indoc::indoc;
     use language::language_settings::InlayHintSettings;
     use lsp::request::{GotoDefinition, GotoTypeDefinition};
+    use std::sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    };
     use util::assert_set_eq;
     use workspace::item::Item;
 
@@ -979,6 +1000,60 @@ mod tests {
         cx.background_executor.run_until_parked();
     }
 
+    #[gpui::test]
+    async fn test_hover_links_without_definitions_are_cached(cx: &mut gpui::TestAppContext) {
+        init_test(cx, |_| {});
+
+        let mut cx = EditorLspTestContext::new_rust(
+            lsp::ServerCapabilities {
+                hover_provider: Some(lsp::HoverProviderCapability::Simple(true)),
+                ..Default::default()
+            },
+            cx,
+        )
+        .await;
+
+        cx.set_state(indoc! {"
+                fn ˇtest() { do_work(); }
+                fn do_work() { test(); }
+            "});
+
+        let request_count = Arc::new(AtomicUsize::new(0));
+        cx.lsp.handle_request::<GotoDefinition, _, _>({
+            let request_count = request_count.clone();
+            move |_, _| {
+                request_count.fetch_add(1, atomic::Ordering::SeqCst);
+                async move { Ok(Some(lsp::GotoDefinitionResponse::Link(vec![]))) }
+            }
+        });
+
+        for hover_point in [
+            indoc! {"
+                fn test() { do_wˇork(); }
+                fn do_work() { test(); }
+            "},
+            indoc! {"
+                fn test() { doˇ_work(); }
+                fn do_work() { test(); }
+            "},
+        ] {
+            let hover_point = cx.pixel_position(hover_point);
+            cx.simulate_mouse_move(hover_point, Modifiers::command());
+            cx.background_executor.run_until_parked();
+        }
+        assert_eq!(request_count.load(atomic::Ordering::SeqCst), 1);
+        cx.editor(|editor, cx| assert_eq!(editor.hovered_link_range(cx), None));
+
+        // Hovering another word queries the language server again.
+        let hover_point = cx.pixel_position(indoc! {"
+                fn test() { do_work(); }
+                fn do_work() { teˇst(); }
+            "});
+        cx.simulate_mouse_move(hover_point, Modifiers::command());
+        cx.background_executor.run_until_parked();
+        assert_eq!(request_count.load(atomic::Ordering::SeqCst), 2);
+    }
+
     #[gpui::test]
     async fn test_inlay_hover_links(cx: &mut gpui::TestAppContext) {
         init_test(cx, |settings| {
@@ -4,7 +4,6 @@ use crate::{
     Anchor, AnchorRangeExt, DisplayPoint, Editor, EditorSettings, EditorSnapshot, EditorStyle,
     ExcerptId, Hover, RangeToAnchorExt,
 };
-use futures::FutureExt;
 use gpui::{
     div, px, AnyElement, CursorStyle, Hsla, InteractiveElement, IntoElement, Model, MouseButton,
     ParentElement, Pixels, SharedString, Size, StatefulInteractiveElement, Styled, Task,
@@ -383,9 +382,9 @@ async fn parse_blocks(
 
             HoverBlockKind::Code { language } => {
                 if let Some(language) = language_registry
-                    .language_for_name(language)
-                    .now_or_never()
-                    .and_then(Result::ok)
+                    .language_for_name_or_extension(language)
+                    .await
+                    .ok()
                 {
                     markdown::highlight_code(&mut text, &mut highlights, &block.text, &language);
                 } else {
@@ -614,10 +613,14 @@ mod tests {
     use collections::BTreeSet;
     use gpui::{FontWeight, HighlightStyle, UnderlineStyle};
     use indoc::indoc;
-    use language::{language_settings::InlayHintSettings, Diagnostic, DiagnosticSet};
+    use language::{
+        language_settings::InlayHintSettings, markdown::MarkdownHighlight, Diagnostic,
+        DiagnosticSet, LanguageConfig, LanguageMatcher,
+    };
     use lsp::LanguageServerId;
     use project::{HoverBlock, HoverBlockKind};
     use smol::stream::StreamExt;
+    use theme::SyntaxTheme;
     use unindent::Unindent;
     use util::test::marked_text_ranges;
 
@@ -914,6 +917,90 @@ mod tests {
         });
     }
 
+    #[gpui::test]
+    fn test_code_block_languages(_: &mut gpui::TestAppContext) {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig {
+                    name: "Rust".into(),
+                    matcher: LanguageMatcher {
+                        path_suffixes: vec!["rs".to_string()],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query(r#""fn" @keyword"#)
+            .unwrap(),
+        );
+        language.set_theme(&SyntaxTheme::new_test(vec![(
+            "keyword",
+            Hsla::red().into(),
+        )]));
+        let registry = Arc::new(LanguageRegistry::test());
+        registry.add(language.clone());
+
+        let highlighted_ranges = |text: &str, kind: HoverBlockKind, language| {
+            let blocks = [HoverBlock {
+                text: text.to_string(),
+                kind,
+            }];
+            let rendered = smol::block_on(parse_blocks(&blocks, &registry, language));
+            rendered
+                .highlights
+                .into_iter()
+                .filter_map(|(range, highlight)| match highlight {
+                    MarkdownHighlight::Code(_) => Some(rendered.text[range].to_string()),
+                    MarkdownHighlight::Style(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Fenced code blocks are resolved by language name or extension, ignoring attributes.
+        for info in ["rust", "rs", "rust,ignore", "rust title"] {
+            assert_eq!(
+                highlighted_ranges(
+                    &format!("```{info}\nfn a() {{}}\n```"),
+                    HoverBlockKind::Markdown,
+                    None
+                ),
+                ["fn"],
+                "wrong highlights for info string {info:?}"
+            );
+        }
+        assert!(highlighted_ranges(
+            "```python\nfn a() {}\n```",
+            HoverBlockKind::Markdown,
+            Some(language.clone())
+        )
+        .is_empty());
+
+        // Fenced code blocks without a language are in the hovered buffer's language.
+        assert_eq!(
+            highlighted_ranges(
+                "```\nfn a() {}\n```",
+                HoverBlockKind::Markdown,
+                Some(language.clone())
+            ),
+            ["fn"]
+        );
+        assert!(
+            highlighted_ranges("```\nfn a() {}\n```", HoverBlockKind::Markdown, None).is_empty()
+        );
+
+        assert_eq!(
+            highlighted_ranges(
+                "fn a() {}",
+                HoverBlockKind::Code {
+                    language: "rs".to_string()
+                },
+                None
+            ),
+            ["fn"]
+        );
+    }
+
     #[gpui::test]
     fn test_render_blocks(cx: &mut gpui::TestAppContext) {
         init_test(cx, |_| {});
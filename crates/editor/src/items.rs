@@ -1,6 +1,6 @@
 use crate::{
     editor_settings::SeedQuerySetting, persistence::DB, scroll::ScrollAnchor, Anchor, Autoscroll,
-    Editor, EditorEvent, EditorSettings, ExcerptId, ExcerptRange, MultiBuffer, MultiBufferSnapshot,
+    Editor, EditorEvent, EditorSettings, ExcerptId, MultiBuffer, MultiBufferSnapshot,
     NavigationData, ToPoint as _,
 };
 use anyhow::{anyhow, Context as _, Result};
@@ -15,8 +15,14 @@ use language::{
     proto::serialize_anchor as serialize_text_anchor, Bias, Buffer, CharKind, OffsetRangeExt,
     Point, SelectionGoal,
 };
+use multi_buffer::{
+    proto::serialize_excerpt, ExcerptOperation, MultiBufferBytes, SerializedHistory,
+};
 use project::repository::GitFileStatus;
-use project::{search::SearchQuery, FormatTrigger, Item as _, Project, ProjectPath};
+use project::{
+    search::{SearchQuery, SearchableText},
+    FormatTrigger, Item as _, Project, ProjectPath,
+};
 use rpc::proto::{self, update_view, PeerId};
 use settings::Settings;
 use workspace::item::ItemSettings;
@@ -25,7 +31,6 @@ use std::fmt::Write;
 use std::{
     borrow::Cow,
     cmp::{self, Ordering},
-    iter,
     ops::Range,
     path::{Path, PathBuf},
     sync::Arc,
@@ -46,6 +51,82 @@ use workspace::{
 
 pub const MAX_TAB_TITLE_LEN: usize = 24;
 
+impl Editor {
+    /// Restores the undo history that was saved along with this editor, including the labels
+    /// and selections of its transactions, if the buffer's text hasn't changed since.
+    pub fn read_undo_history_from_db(
+        &mut self,
+        item_id: ItemId,
+        workspace_id: WorkspaceId,
+        cx: &mut ViewContext<Editor>,
+    ) {
+        let history = DB
+            .get_undo_history(item_id, workspace_id)
+            .log_err()
+            .flatten()
+            .flatten()
+            .and_then(|history| serde_json::from_str::<SerializedHistory>(&history).log_err());
+        if let Some(history) = history {
+            self.buffer
+                .update(cx, |buffer, cx| buffer.restore_history(history, cx));
+        }
+    }
+
+    /// Captures this editor's excerpts, selections, and scroll position, so that another
+    /// participant can reconstruct the view with [`Editor::restore_view_state_proto`].
+    pub fn view_state_proto(&self, cx: &AppContext) -> proto::view::Editor {
+        let buffer = self.buffer.read(cx);
+        let scroll_anchor = self.scroll_manager.anchor();
+        proto::view::Editor {
+            singleton: buffer.is_singleton(),
+            title: (!buffer.is_singleton()).then(|| buffer.title(cx).into()),
+            excerpts: buffer.to_proto(cx),
+            scroll_top_anchor: Some(serialize_anchor(&scroll_anchor.anchor)),
+            scroll_x: scroll_anchor.offset.x,
+            scroll_y: scroll_anchor.offset.y,
+            selections: self
+                .selections
+                .disjoint_anchors()
+                .iter()
+                .map(serialize_selection)
+                .collect(),
+            pending_selection: self
+                .selections
+                .pending_anchor()
+                .as_ref()
+                .map(serialize_selection),
+        }
+    }
+
+    /// Brings this editor's excerpts, selections, and scroll position in line with a state
+    /// captured with [`Editor::view_state_proto`], opening any buffers it refers to.
+    pub fn restore_view_state_proto(
+        &mut self,
+        state: proto::view::Editor,
+        project: Model<Project>,
+        cx: &mut ViewContext<Self>,
+    ) -> Task<Result<()>> {
+        let buffer = self.buffer.read(cx);
+        let excerpt_operation = if buffer.is_singleton() {
+            ExcerptOperation::default()
+        } else {
+            buffer.excerpt_operation_to(&state.excerpts)
+        };
+        let message = proto::update_view::Editor {
+            inserted_excerpts: excerpt_operation.inserted_excerpts,
+            deleted_excerpts: excerpt_operation.deleted_excerpts,
+            selections: state.selections,
+            pending_selection: state.pending_selection,
+            scroll_top_anchor: state.scroll_top_anchor,
+            scroll_x: state.scroll_x,
+            scroll_y: state.scroll_y,
+        };
+        cx.spawn(|this, mut cx| async move {
+            update_editor_from_message(this, project, message, &mut cx).await
+        })
+    }
+}
+
 impl FollowableItem for Editor {
     fn remote_id(&self) -> Option<ViewId> {
         self.remote_id
@@ -105,28 +186,17 @@ impl FollowableItem for Editor {
                         if state.singleton && buffers.len() == 1 {
                             multibuffer = MultiBuffer::singleton(buffers.pop().unwrap(), cx)
                         } else {
-                            multibuffer =
-                                MultiBuffer::new(replica_id, project.read(cx).capability());
-                            let mut excerpts = state.excerpts.into_iter().peekable();
-                            while let Some(excerpt) = excerpts.peek() {
-                                let Ok(buffer_id) = BufferId::new(excerpt.buffer_id) else {
-                                    continue;
-                                };
-                                let buffer_excerpts = iter::from_fn(|| {
-                                    let excerpt = excerpts.peek()?;
-                                    (excerpt.buffer_id == u64::from(buffer_id))
-                                        .then(|| excerpts.next().unwrap())
-                                });
-                                let buffer =
-                                    buffers.iter().find(|b| b.read(cx).remote_id() == buffer_id);
-                                if let Some(buffer) = buffer {
-                                    multibuffer.push_excerpts(
-                                        buffer.clone(),
-                                        buffer_excerpts.filter_map(deserialize_excerpt_range),
-                                        cx,
-                                    );
-                                }
-                            }
+                            let capability = project.read(cx).capability();
+                            // The buffer ids were validated when opening the buffers.
+                            multibuffer = MultiBuffer::from_proto(
+                                replica_id,
+                                capability,
+                                state.excerpts.clone(),
+                                &buffers,
+                                cx,
+                            )
+                            .log_err()
+                            .unwrap_or_else(|| MultiBuffer::new(replica_id, capability));
                         };
 
                         if let Some(title) = &state.title {
@@ -145,20 +215,11 @@ impl FollowableItem for Editor {
                 })?
             };
 
-            update_editor_from_message(
-                editor.downgrade(),
-                project,
-                proto::update_view::Editor {
-                    selections: state.selections,
-                    pending_selection: state.pending_selection,
-                    scroll_top_anchor: state.scroll_top_anchor,
-                    scroll_x: state.scroll_x,
-                    scroll_y: state.scroll_y,
-                    ..Default::default()
-                },
-                &mut cx,
-            )
-            .await?;
+            editor
+                .update(&mut cx, |editor, cx| {
+                    editor.restore_view_state_proto(state, project, cx)
+                })?
+                .await?;
 
             Ok(editor)
         }))
@@ -193,45 +254,7 @@ impl FollowableItem for Editor {
             return None;
         }
 
-        let scroll_anchor = self.scroll_manager.anchor();
-        let excerpts = buffer
-            .read(cx)
-            .excerpts()
-            .map(|(id, buffer, range)| proto::Excerpt {
-                id: id.to_proto(),
-                buffer_id: buffer.remote_id().into(),
-                context_start: Some(serialize_text_anchor(&range.context.start)),
-                context_end: Some(serialize_text_anchor(&range.context.end)),
-                primary_start: range
-                    .primary
-                    .as_ref()
-                    .map(|range| serialize_text_anchor(&range.start)),
-                primary_end: range
-                    .primary
-                    .as_ref()
-                    .map(|range| serialize_text_anchor(&range.end)),
-            })
-            .collect();
-
-        Some(proto::view::Variant::Editor(proto::view::Editor {
-            singleton: buffer.is_singleton(),
-            title: (!buffer.is_singleton()).then(|| buffer.title(cx).into()),
-            excerpts,
-            scroll_top_anchor: Some(serialize_anchor(&scroll_anchor.anchor)),
-            scroll_x: scroll_anchor.offset.x,
-            scroll_y: scroll_anchor.offset.y,
-            selections: self
-                .selections
-                .disjoint_anchors()
-                .iter()
-                .map(serialize_selection)
-                .collect(),
-            pending_selection: self
-                .selections
-                .pending_anchor()
-                .as_ref()
-                .map(serialize_selection),
-        }))
+        Some(proto::view::Variant::Editor(self.view_state_proto(cx)))
     }
 
     fn to_follow_event(event: &EditorEvent) -> Option<workspace::item::FollowEvent> {
@@ -266,19 +289,14 @@ impl FollowableItem for Editor {
                     excerpts,
                 } => {
                     let buffer_id = buffer.read(cx).remote_id();
-                    let mut excerpts = excerpts.iter();
-                    if let Some((id, range)) = excerpts.next() {
-                        update.inserted_excerpts.push(proto::ExcerptInsertion {
-                            previous_excerpt_id: Some(predecessor.to_proto()),
-                            excerpt: serialize_excerpt(buffer_id, id, range),
-                        });
-                        update.inserted_excerpts.extend(excerpts.map(|(id, range)| {
+                    update
+                        .inserted_excerpts
+                        .extend(excerpts.iter().enumerate().map(|(ix, (id, range))| {
                             proto::ExcerptInsertion {
-                                previous_excerpt_id: None,
-                                excerpt: serialize_excerpt(buffer_id, id, range),
+                                previous_excerpt_id: (ix == 0).then(|| predecessor.to_proto()),
+                                excerpt: Some(serialize_excerpt(buffer_id, *id, range)),
                             }
-                        }))
-                    }
+                        }));
                     true
                 }
                 EditorEvent::ExcerptsRemoved { ids } => {
@@ -337,12 +355,13 @@ async fn update_editor_from_message(
     message: proto::update_view::Editor,
     cx: &mut AsyncWindowContext,
 ) -> Result<()> {
+    let excerpt_operation = ExcerptOperation {
+        inserted_excerpts: message.inserted_excerpts,
+        deleted_excerpts: message.deleted_excerpts,
+    };
+
     // Open all of the buffers of which excerpts were added to the editor.
-    let inserted_excerpt_buffer_ids = message
-        .inserted_excerpts
-        .iter()
-        .filter_map(|insertion| Some(insertion.excerpt.as_ref()?.buffer_id))
-        .collect::<HashSet<_>>();
+    let inserted_excerpt_buffer_ids = excerpt_operation.buffer_ids().collect::<HashSet<_>>();
     let inserted_excerpt_buffers = project.update(cx, |project, cx| {
         inserted_excerpt_buffer_ids
             .into_iter()
@@ -354,58 +373,11 @@ async fn update_editor_from_message(
     // Update the editor's excerpts.
     this.update(cx, |editor, cx| {
         editor.buffer.update(cx, |multibuffer, cx| {
-            let mut removed_excerpt_ids = message
-                .deleted_excerpts
-                .into_iter()
-                .map(ExcerptId::from_proto)
-                .collect::<Vec<_>>();
-            removed_excerpt_ids.sort_by({
-                let multibuffer = multibuffer.read(cx);
-                move |a, b| a.cmp(&b, &multibuffer)
-            });
-
-            let mut insertions = message.inserted_excerpts.into_iter().peekable();
-            while let Some(insertion) = insertions.next() {
-                let Some(excerpt) = insertion.excerpt else {
-                    continue;
-                };
-                let Some(previous_excerpt_id) = insertion.previous_excerpt_id else {
-                    continue;
-                };
-                let buffer_id = BufferId::new(excerpt.buffer_id)?;
-                let Some(buffer) = project.read(cx).buffer_for_id(buffer_id) else {
-                    continue;
-                };
-
-                let adjacent_excerpts = iter::from_fn(|| {
-                    let insertion = insertions.peek()?;
-                    if insertion.previous_excerpt_id.is_none()
-                        && insertion.excerpt.as_ref()?.buffer_id == u64::from(buffer_id)
-                    {
-                        insertions.next()?.excerpt
-                    } else {
-                        None
-                    }
-                });
-
-                multibuffer.insert_excerpts_with_ids_after(
-                    ExcerptId::from_proto(previous_excerpt_id),
-                    buffer,
-                    [excerpt]
-                        .into_iter()
-                        .chain(adjacent_excerpts)
-                        .filter_map(|excerpt| {
-                            Some((
-                                ExcerptId::from_proto(excerpt.id),
-                                deserialize_excerpt_range(excerpt)?,
-                            ))
-                        }),
-                    cx,
-                );
-            }
-
-            multibuffer.remove_excerpts(removed_excerpt_ids, cx);
-            Result::<(), anyhow::Error>::Ok(())
+            multibuffer.apply_excerpt_operation(
+                excerpt_operation,
+                |buffer_id, cx| project.read(cx).buffer_for_id(buffer_id),
+                cx,
+            )
         })
     })??;
 
@@ -460,27 +432,6 @@ async fn update_editor_from_message(
     Ok(())
 }
 
-fn serialize_excerpt(
-    buffer_id: BufferId,
-    id: &ExcerptId,
-    range: &ExcerptRange<language::Anchor>,
-) -> Option<proto::Excerpt> {
-    Some(proto::Excerpt {
-        id: id.to_proto(),
-        buffer_id: buffer_id.into(),
-        context_start: Some(serialize_text_anchor(&range.context.start)),
-        context_end: Some(serialize_text_anchor(&range.context.end)),
-        primary_start: range
-            .primary
-            .as_ref()
-            .map(|r| serialize_text_anchor(&r.start)),
-        primary_end: range
-            .primary
-            .as_ref()
-            .map(|r| serialize_text_anchor(&r.end)),
-    })
-}
-
 fn serialize_selection(selection: &Selection<Anchor>) -> proto::Selection {
     proto::Selection {
         id: selection.id as u64,
@@ -497,23 +448,6 @@ fn serialize_anchor(anchor: &Anchor) -> proto::EditorAnchor {
     }
 }
 
-fn deserialize_excerpt_range(excerpt: proto::Excerpt) -> Option<ExcerptRange<language::Anchor>> {
-    let context = {
-        let start = language::proto::deserialize_anchor(excerpt.context_start?)?;
-        let end = language::proto::deserialize_anchor(excerpt.context_end?)?;
-        start..end
-    };
-    let primary = excerpt
-        .primary_start
-        .zip(excerpt.primary_end)
-        .and_then(|(start, end)| {
-            let start = language::proto::deserialize_anchor(start)?;
-            let end = language::proto::deserialize_anchor(end)?;
-            Some(start..end)
-        });
-    Some(ExcerptRange { context, primary })
-}
-
 fn deserialize_selection(
     buffer: &MultiBufferSnapshot,
     selection: proto::Selection,
@@ -543,18 +477,29 @@ impl Item for Editor {
         if let Ok(data) = data.downcast::<NavigationData>() {
             let newest_selection = self.selections.newest::<Point>(cx);
             let buffer = self.buffer.read(cx).read(cx);
-            let offset = if buffer.can_resolve(&data.cursor_anchor) {
-                data.cursor_anchor.to_point(&buffer)
+
+            // The excerpts the anchors were created in may have been replaced since, in which
+            // case the anchors are moved into an excerpt that still contains their position.
+            let resolve = |anchor: &Anchor| {
+                if buffer.can_resolve(anchor) {
+                    return Some(*anchor);
+                }
+                let (_, anchor, kept_position) = buffer.refresh_anchors([anchor]).pop()?;
+                kept_position.then_some(anchor)
+            };
+
+            let offset = if let Some(cursor_anchor) = resolve(&data.cursor_anchor) {
+                cursor_anchor.to_point(&buffer)
             } else {
                 buffer.clip_point(data.cursor_position, Bias::Left)
             };
 
             let mut scroll_anchor = data.scroll_anchor;
-            if !buffer.can_resolve(&scroll_anchor.anchor) {
-                scroll_anchor.anchor = buffer.anchor_before(
+            scroll_anchor.anchor = resolve(&scroll_anchor.anchor).unwrap_or_else(|| {
+                buffer.anchor_before(
                     buffer.clip_point(Point::new(data.scroll_top_row, 0), Bias::Left),
-                );
-            }
+                )
+            });
 
             drop(buffer);
 
@@ -783,9 +728,7 @@ impl Item for Editor {
             buffer
                 .update(&mut cx, |buffer, cx| {
                     if let Some(transaction) = transaction {
-                        if !buffer.is_singleton() {
-                            buffer.push_transaction(&transaction.0, cx);
-                        }
+                        buffer.push_transaction(&transaction.0, cx);
                     }
                 })
                 .ok();
@@ -847,31 +790,41 @@ impl Item for Editor {
 
         fn serialize(
             buffer: Model<Buffer>,
+            multibuffer: &Model<MultiBuffer>,
             workspace_id: WorkspaceId,
             item_id: ItemId,
             cx: &mut AppContext,
         ) {
             if let Some(file) = buffer.read(cx).file().and_then(|file| file.as_local()) {
                 let path = file.abs_path(cx);
+                let undo_history =
+                    serde_json::to_string(&multibuffer.read(cx).serialize_history(cx)).log_err();
 
                 cx.background_executor()
                     .spawn(async move {
                         DB.save_path(item_id, workspace_id, path.clone())
                             .await
-                            .log_err()
+                            .log_err();
+                        if let Some(undo_history) = undo_history {
+                            DB.save_undo_history(item_id, workspace_id, undo_history)
+                                .await
+                                .log_err();
+                        }
                     })
                     .detach();
             }
         }
 
         if let Some(buffer) = self.buffer().read(cx).as_singleton() {
-            serialize(buffer.clone(), workspace_id, item_id, cx);
+            serialize(buffer.clone(), self.buffer(), workspace_id, item_id, cx);
 
             cx.subscribe(&buffer, |this, buffer, event, cx| {
                 if let Some((_, workspace_id)) = this.workspace.as_ref() {
-                    if let language::Event::FileHandleChanged = event {
+                    // The undo history is only useful for restoring the saved contents.
+                    if let language::Event::FileHandleChanged | language::Event::Saved = event {
                         serialize(
                             buffer,
+                            this.buffer(),
                             *workspace_id,
                             cx.view().item_id().as_u64() as ItemId,
                             cx,
@@ -957,6 +910,7 @@ impl Item for Editor {
                             let mut editor = Editor::for_buffer(buffer, Some(project), cx);
 
                             editor.read_scroll_position_from_db(item_id, workspace_id, cx);
+                            editor.read_undo_history_from_db(item_id, workspace_id, cx);
                             editor
                         })
                     })?)
@@ -981,6 +935,30 @@ impl ProjectItem for Editor {
 impl EventEmitter<SearchEvent> for Editor {}
 
 pub(crate) enum BufferSearchHighlights {}
+/// The text of a multi-buffer, including the newlines separating its excerpts, for searching
+/// across excerpt boundaries.
+struct MultiBufferText<'a>(&'a MultiBufferSnapshot);
+
+impl SearchableText for MultiBufferText<'_> {
+    type Bytes<'a> = MultiBufferBytes<'a> where Self: 'a;
+
+    fn bytes(&self) -> Self::Bytes<'_> {
+        self.0.bytes_in_range(0..self.0.len())
+    }
+
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.0.text_for_range(0..self.0.len()))
+    }
+
+    fn chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_> {
+        Box::new(self.0.chars_at(offset))
+    }
+
+    fn reversed_chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_> {
+        Box::new(self.0.reversed_chars_at(offset))
+    }
+}
+
 impl SearchableItem for Editor {
     type Match = Range<Anchor>;
 
@@ -1132,6 +1110,18 @@ impl SearchableItem for Editor {
                             buffer.anchor_after(range.start)..buffer.anchor_before(range.end)
                         }),
                 );
+            } else if buffer.searches_across_excerpts() {
+                ranges.extend(
+                    query
+                        .search_text(&MultiBufferText(&buffer), |offset| {
+                            buffer.language_scope_at(offset)
+                        })
+                        .await
+                        .into_iter()
+                        .map(|range| {
+                            buffer.anchor_after(range.start)..buffer.anchor_before(range.end)
+                        }),
+                );
             } else {
                 for excerpt in buffer.excerpt_boundaries_in_range(0..buffer.len()) {
                     let excerpt_range = excerpt.range.context.to_offset(&excerpt.buffer);
@@ -0,0 +1,122 @@
+use crate::{Anchor, Autoscroll, Editor, ReplayMacro, ToggleMacroRecording};
+use gpui::{Action, Task, ViewContext};
+use std::{ops::Range, sync::Arc, time::Instant};
+
+/// A single step of a recorded keyboard macro.
+#[derive(Debug)]
+pub enum MacroCommand {
+    /// An editor action, such as a movement or an edit.
+    Action(Box<dyn Action>),
+    /// Typed text, replacing a range relative to the newest cursor.
+    Insertion {
+        text: Arc<str>,
+        utf16_range_to_replace: Option<Range<isize>>,
+    },
+    /// Selections made with the mouse.
+    SelectRanges(Vec<Range<Anchor>>),
+}
+
+#[derive(Default)]
+pub(crate) struct MacroState {
+    recording: Option<Vec<MacroCommand>>,
+    last_macro: Option<Arc<[MacroCommand]>>,
+    replay_task: Option<Task<Option<()>>>,
+}
+
+impl Editor {
+    pub fn is_recording_macro(&self) -> bool {
+        self.macro_state.recording.is_some()
+    }
+
+    /// The most recently recorded macro, which [`ReplayMacro`] replays.
+    pub fn recorded_macro(&self) -> Option<&[MacroCommand]> {
+        self.macro_state.last_macro.as_deref()
+    }
+
+    pub fn toggle_macro_recording(&mut self, _: &ToggleMacroRecording, cx: &mut ViewContext<Self>) {
+        if let Some(commands) = self.macro_state.recording.take() {
+            if !commands.is_empty() {
+                self.macro_state.last_macro = Some(commands.into());
+            }
+        } else if self.macro_state.replay_task.is_none() {
+            self.macro_state.recording = Some(Vec::new());
+        }
+        cx.notify();
+    }
+
+    /// Replays the most recently recorded macro. All of its edits are grouped into a single
+    /// transaction, so that undoing reverts the whole replay.
+    pub fn replay_macro(&mut self, _: &ReplayMacro, cx: &mut ViewContext<Self>) {
+        if self.is_recording_macro()
+            || self.macro_state.replay_task.is_some()
+            || !self.focus_handle.is_focused(cx)
+        {
+            return;
+        }
+        let Some(commands) = self.macro_state.last_macro.clone() else {
+            return;
+        };
+
+        let focus_handle = self.focus_handle.clone();
+        self.start_transaction_at(Instant::now(), cx);
+        self.macro_state.replay_task = Some(cx.spawn(|editor, mut cx| async move {
+            for command in commands.iter() {
+                match command {
+                    // Actions are dispatched on this editor's element, so that they reach the
+                    // same handlers they did when they were recorded, even if an earlier
+                    // command moved the focus elsewhere.
+                    MacroCommand::Action(action) => {
+                        cx.update(|cx| focus_handle.dispatch_action(action.as_ref(), cx))
+                            .ok()?;
+                    }
+                    MacroCommand::Insertion {
+                        text,
+                        utf16_range_to_replace,
+                    } => {
+                        editor
+                            .update(&mut cx, |editor, cx| {
+                                editor.replay_insert_event(text, utf16_range_to_replace.clone(), cx)
+                            })
+                            .ok()?;
+                    }
+                    MacroCommand::SelectRanges(ranges) => {
+                        editor
+                            .update(&mut cx, |editor, cx| {
+                                editor.change_selections(Some(Autoscroll::fit()), cx, |s| {
+                                    s.select_anchor_ranges(ranges.iter().cloned())
+                                });
+                            })
+                            .ok()?;
+                    }
+                }
+            }
+
+            editor
+                .update(&mut cx, |editor, cx| {
+                    editor.macro_state.replay_task = None;
+                    editor.end_transaction_at(Instant::now(), cx);
+                })
+                .ok()
+        }));
+    }
+
+    pub(crate) fn record_macro_action(&mut self, action: &dyn Action) {
+        if self.is_recording_macro()
+            && !ToggleMacroRecording.partial_eq(action)
+            && !ReplayMacro.partial_eq(action)
+        {
+            self.record_macro_command(MacroCommand::Action(action.boxed_clone()));
+        }
+    }
+
+    /// Records a command if a macro is being recorded. Commands performed while a macro is
+    /// replayed are never recorded.
+    pub(crate) fn record_macro_command(&mut self, command: MacroCommand) {
+        if self.macro_state.replay_task.is_some() {
+            return;
+        }
+        if let Some(commands) = self.macro_state.recording.as_mut() {
+            commands.push(command);
+        }
+    }
+}
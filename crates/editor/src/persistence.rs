@@ -14,6 +14,7 @@ define_connection!(
     //   scroll_top_row: usize,
     //   scroll_vertical_offset: f32,
     //   scroll_horizontal_offset: f32,
+    //   undo_history: Option<String>,
     // )
     pub static ref DB: EditorDb<WorkspaceDb> =
         &[sql! (
@@ -31,6 +32,9 @@ define_connection!(
             ALTER TABLE editors ADD COLUMN scroll_top_row INTEGER NOT NULL DEFAULT 0;
             ALTER TABLE editors ADD COLUMN scroll_horizontal_offset REAL NOT NULL DEFAULT 0;
             ALTER TABLE editors ADD COLUMN scroll_vertical_offset REAL NOT NULL DEFAULT 0;
+        ),
+        sql! (
+            ALTER TABLE editors ADD COLUMN undo_history TEXT;
         )];
 );
 
@@ -80,4 +84,24 @@ impl EditorDb {
             WHERE item_id = ?1 AND workspace_id = ?2
         }
     }
+
+    // Returns the editor's undo history, serialized as JSON
+    query! {
+        pub fn get_undo_history(item_id: ItemId, workspace_id: WorkspaceId) -> Result<Option<Option<String>>> {
+            SELECT undo_history FROM editors
+            WHERE item_id = ? AND workspace_id = ?
+        }
+    }
+
+    query! {
+        pub async fn save_undo_history(
+            item_id: ItemId,
+            workspace_id: WorkspaceId,
+            undo_history: String
+        ) -> Result<()> {
+            UPDATE OR IGNORE editors
+            SET undo_history = ?3
+            WHERE item_id = ?1 AND workspace_id = ?2
+        }
+    }
 }
@@ -132,6 +132,9 @@ pub struct ScrollManager {
     anchor: ScrollAnchor,
     ongoing: OngoingScroll,
     autoscroll_request: Option<(Autoscroll, bool)>,
+    /// The count of the last autoscroll request made through the multi-buffer that this
+    /// editor has applied.
+    pub(crate) applied_buffer_autoscroll_request_count: usize,
     last_autoscroll: Option<(gpui::Point<f32>, f32, f32, AutoscrollStrategy)>,
     show_scrollbars: bool,
     hide_scrollbar_task: Option<Task<()>>,
@@ -146,6 +149,7 @@ impl ScrollManager {
             anchor: ScrollAnchor::new(),
             ongoing: OngoingScroll::new(),
             autoscroll_request: None,
+            applied_buffer_autoscroll_request_count: 0,
             show_scrollbars: true,
             hide_scrollbar_task: None,
             dragging_scrollbar: false,
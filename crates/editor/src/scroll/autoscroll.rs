@@ -75,15 +75,34 @@ impl Editor {
             self.set_scroll_position(scroll_position, cx);
         }
 
-        let Some((autoscroll, local)) = self.scroll_manager.autoscroll_request.take() else {
-            return false;
-        };
+        // The multi-buffer may ask for a position to be kept on screen after its excerpts
+        // were restructured, which counts as an autoscroll request on its own. Explicit
+        // requests, such as those made when the selections change, take priority over it.
+        // Every view of the multi-buffer applies each of its requests once.
+        let mut autoscroll_anchor = None;
+        if let Some((request_count, anchor)) = self.buffer.read(cx).autoscroll_request() {
+            if request_count > self.scroll_manager.applied_buffer_autoscroll_request_count {
+                self.scroll_manager.applied_buffer_autoscroll_request_count = request_count;
+                autoscroll_anchor = Some(anchor);
+            }
+        }
+        let (autoscroll, local, autoscroll_anchor) =
+            match self.scroll_manager.autoscroll_request.take() {
+                Some((autoscroll, local)) => (autoscroll, local, None),
+                None => match autoscroll_anchor {
+                    Some(anchor) => (Autoscroll::fit(), true, Some(anchor)),
+                    None => return false,
+                },
+            };
 
         let mut target_top;
         let mut target_bottom;
         if let Some(highlighted_rows) = &self.highlighted_rows {
             target_top = highlighted_rows.start as f32;
             target_bottom = target_top + 1.;
+        } else if let Some(anchor) = autoscroll_anchor {
+            target_top = anchor.to_display_point(&display_map).row() as f32;
+            target_bottom = target_top + 1.;
         } else {
             let selections = self.selections.all::<Point>(cx);
             target_top = selections
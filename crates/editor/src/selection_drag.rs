@@ -0,0 +1,112 @@
+use crate::{Anchor, Autoscroll, Bias, DisplayPoint, Editor};
+use gpui::ViewContext;
+use multi_buffer::ToOffset;
+use std::ops::Range;
+use text::Point;
+
+/// A selection whose text is being dragged with the mouse to another location.
+pub(crate) struct SelectionDrag {
+    /// The text being dragged.
+    source: Range<Anchor>,
+    /// Where the mouse was pressed, which becomes the cursor if the text isn't dragged anywhere.
+    origin: Anchor,
+    /// Where the text will be dropped, once the mouse has moved.
+    drop_target: Option<Anchor>,
+}
+
+impl Editor {
+    /// Starts dragging the text of the selection at `position`, if it's within a non-empty
+    /// selection. Returns whether a drag was started.
+    pub fn begin_selection_drag(
+        &mut self,
+        position: DisplayPoint,
+        cx: &mut ViewContext<Self>,
+    ) -> bool {
+        if self.read_only(cx) {
+            return false;
+        }
+
+        let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
+        let point = display_map
+            .clip_point(position, Bias::Left)
+            .to_point(&display_map);
+        let Some(selection) = self
+            .selections
+            .all::<Point>(cx)
+            .into_iter()
+            .find(|selection| selection.start <= point && point < selection.end)
+        else {
+            return false;
+        };
+
+        let buffer = &display_map.buffer_snapshot;
+        self.selection_drag = Some(SelectionDrag {
+            source: buffer.anchor_before(selection.start)..buffer.anchor_after(selection.end),
+            origin: buffer.anchor_before(point),
+            drop_target: None,
+        });
+        true
+    }
+
+    pub fn has_selection_drag(&self) -> bool {
+        self.selection_drag.is_some()
+    }
+
+    /// Where the dragged text will be dropped, for rendering a cursor there.
+    pub(crate) fn selection_drop_target(&self) -> Option<Anchor> {
+        self.selection_drag.as_ref()?.drop_target
+    }
+
+    pub fn update_selection_drag(&mut self, position: DisplayPoint, cx: &mut ViewContext<Self>) {
+        let display_map = self.display_map.update(cx, |map, cx| map.snapshot(cx));
+        let Some(drag) = self.selection_drag.as_mut() else {
+            return;
+        };
+        let point = display_map
+            .clip_point(position, Bias::Left)
+            .to_point(&display_map);
+        drag.drop_target = Some(display_map.buffer_snapshot.anchor_before(point));
+        cx.notify();
+    }
+
+    /// Drops the dragged text at the drop target, moving it there or, if `copy` is true,
+    /// inserting a copy of it. The edit is a single transaction, and the dragged text is
+    /// selected at its new location. If the mouse never moved, the selection is replaced
+    /// with a cursor where it was pressed.
+    pub fn end_selection_drag(&mut self, copy: bool, cx: &mut ViewContext<Self>) {
+        let Some(drag) = self.selection_drag.take() else {
+            return;
+        };
+        let Some(drop_target) = drag.drop_target else {
+            self.change_selections(Some(Autoscroll::fit()), cx, |s| {
+                s.select_anchor_ranges([drag.origin..drag.origin])
+            });
+            return;
+        };
+
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        let source = drag.source.start.to_offset(&buffer)..drag.source.end.to_offset(&buffer);
+        let target = drop_target.to_offset(&buffer);
+        // Moving text onto itself does nothing.
+        if !copy && source.start <= target && target <= source.end {
+            cx.notify();
+            return;
+        }
+
+        let text = buffer.text_for_range(source.clone()).collect::<String>();
+        let dropped_range = buffer.anchor_before(target)..buffer.anchor_after(target);
+        let mut edits = vec![(target..target, text)];
+        if !copy {
+            edits.push((source, String::new()));
+        }
+        edits.sort_by_key(|(range, _)| range.start);
+
+        self.transact(cx, |this, cx| {
+            this.buffer
+                .update(cx, |buffer, cx| buffer.edit(edits, None, cx));
+            this.change_selections(Some(Autoscroll::fit()), cx, |s| {
+                s.select_anchor_ranges([dropped_range])
+            });
+        });
+    }
+}
@@ -313,6 +313,11 @@ impl SelectionsCollection {
         reversed: bool,
         text_layout_details: &TextLayoutDetails,
     ) -> Option<Selection<Point>> {
+        // Rows of blocks, such as excerpt headers, don't contain any text to select.
+        if display_map.is_block_line(row) {
+            return None;
+        }
+
         let is_empty = positions.start == positions.end;
         let line_len = display_map.line_len(row);
 
@@ -871,19 +876,19 @@ where
     I: 'a + IntoIterator<Item = &'a Selection<Anchor>>,
 {
     let (to_summarize, selections) = selections.into_iter().tee();
+    let ranges = to_summarize.map(|s| s.start..s.end).collect::<Vec<_>>();
     let mut summaries = snapshot
-        .summaries_for_anchors::<D, _>(
-            to_summarize
-                .flat_map(|s| [&s.start, &s.end])
-                .collect::<Vec<_>>(),
-        )
+        .summaries_for_anchor_ranges::<D, _>(&ranges)
         .into_iter();
-    selections.map(move |s| Selection {
-        id: s.id,
-        start: summaries.next().unwrap(),
-        end: summaries.next().unwrap(),
-        reversed: s.reversed,
-        goal: s.goal,
+    selections.map(move |s| {
+        let range = summaries.next().unwrap();
+        Selection {
+            id: s.id,
+            start: range.start,
+            end: range.end,
+            reversed: s.reversed,
+            goal: s.goal,
+        }
     })
 }
 
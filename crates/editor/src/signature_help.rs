@@ -0,0 +1,194 @@
+use crate::{Anchor, Editor, MultiBufferSnapshot, ToOffset};
+use gpui::{Task, ViewContext};
+use project::SignatureHelp;
+use std::{cmp::Ordering, ops::Range};
+
+/// Tracks the signature help of the call the newest cursor is in.
+///
+/// Help is requested when one of the language server's trigger characters is typed, and
+/// requested again whenever one of its retrigger characters is typed while it's visible. In
+/// between, the active parameter is advanced locally by counting the argument separators
+/// that have been typed since the last response.
+#[derive(Default)]
+pub struct SignatureHelpState {
+    task: Option<Task<Option<()>>>,
+    popover: Option<SignatureHelpPopover>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureHelpPopover {
+    pub help: SignatureHelp,
+    /// Spans from the position signature help was first triggered at to the newest cursor.
+    /// The popover is placed above the start of this range.
+    pub range: Range<Anchor>,
+    /// The position the current [`SignatureHelp`] was requested at.
+    requested_at: Anchor,
+    /// The number of top-level argument separators in the call when it was requested.
+    separators_at_request: usize,
+    /// The number of top-level argument separators in the call up to the cursor.
+    separators: usize,
+}
+
+impl SignatureHelpPopover {
+    pub fn active_parameter(&self) -> Option<usize> {
+        let active_parameter = self.help.active_parameter()? + self.separators;
+        active_parameter.checked_sub(self.separators_at_request)
+    }
+
+    /// The range of the active parameter within the active signature's label.
+    pub fn active_parameter_range(&self) -> Option<Range<usize>> {
+        let signature = self.help.active_signature()?;
+        signature.parameters.get(self.active_parameter()?)?.clone()
+    }
+}
+
+impl SignatureHelpState {
+    pub fn popover(&self) -> Option<&SignatureHelpPopover> {
+        self.popover.as_ref()
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.popover.is_some()
+    }
+}
+
+impl Editor {
+    pub fn signature_help_state(&self) -> &SignatureHelpState {
+        &self.signature_help_state
+    }
+
+    pub(crate) fn trigger_signature_help_on_input(
+        &mut self,
+        text: &str,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(project) = self.project.clone() else {
+            return;
+        };
+        let position = self.selections.newest_anchor().head();
+        let Some((buffer, _)) = self.buffer.read(cx).text_anchor_for_position(position, cx) else {
+            return;
+        };
+
+        let (triggers, retriggers) = project
+            .read(cx)
+            .signature_help_trigger_characters(&buffer, cx);
+        let is_trigger = triggers.iter().any(|trigger| trigger == text);
+        let is_retrigger = self.signature_help_state.is_shown()
+            && retriggers.iter().any(|trigger| trigger == text);
+        if is_trigger || is_retrigger {
+            self.request_signature_help(is_retrigger, cx);
+        }
+    }
+
+    /// Requests signature help at the newest cursor. When `keep_range` is true, the popover
+    /// stays anchored where signature help was first triggered.
+    fn request_signature_help(&mut self, keep_range: bool, cx: &mut ViewContext<Self>) {
+        let Some(project) = self.project.clone() else {
+            return;
+        };
+        let position = {
+            let buffer = self.buffer.read(cx).snapshot(cx);
+            buffer.anchor_before(self.selections.newest_anchor().head().to_offset(&buffer))
+        };
+        let Some((buffer, buffer_position)) =
+            self.buffer.read(cx).text_anchor_for_position(position, cx)
+        else {
+            return;
+        };
+
+        let range_start = keep_range
+            .then(|| {
+                self.signature_help_state
+                    .popover
+                    .as_ref()
+                    .map(|popover| popover.range.start)
+            })
+            .flatten()
+            .unwrap_or(position);
+        let request = project.update(cx, |project, cx| {
+            project.signature_help(&buffer, buffer_position, cx)
+        });
+        self.signature_help_state.task = Some(cx.spawn(move |editor, mut cx| async move {
+            let help = request.await.ok().flatten();
+            editor
+                .update(&mut cx, |editor, cx| {
+                    editor.signature_help_state.task = None;
+                    let buffer = editor.buffer.read(cx).snapshot(cx);
+                    let separators = count_argument_separators(
+                        &buffer,
+                        range_start.to_offset(&buffer)..position.to_offset(&buffer),
+                    );
+                    editor.signature_help_state.popover =
+                        help.zip(separators)
+                            .map(|(help, separators)| SignatureHelpPopover {
+                                help,
+                                range: range_start..position,
+                                requested_at: position,
+                                separators_at_request: separators,
+                                separators,
+                            });
+                    editor.update_signature_help(cx);
+                    cx.notify();
+                })
+                .ok()
+        }));
+    }
+
+    /// Keeps the visible signature help in sync with the newest cursor, dismissing it once the
+    /// cursor leaves the call it was triggered in.
+    pub(crate) fn update_signature_help(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(popover) = self.signature_help_state.popover.as_mut() else {
+            return;
+        };
+
+        let buffer = self.buffer.read(cx).snapshot(cx);
+        let head = self.selections.newest_anchor().head();
+        if head.cmp(&popover.range.start, &buffer) == Ordering::Less {
+            self.hide_signature_help(cx);
+            return;
+        }
+        if head.cmp(&popover.requested_at, &buffer) == Ordering::Less {
+            self.request_signature_help(true, cx);
+            return;
+        }
+
+        let range = popover.range.start.to_offset(&buffer)..head.to_offset(&buffer);
+        match count_argument_separators(&buffer, range) {
+            Some(separators) => {
+                popover.separators = separators;
+                popover.range.end = head;
+            }
+            None => {
+                self.hide_signature_help(cx);
+            }
+        }
+    }
+
+    pub fn hide_signature_help(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        self.signature_help_state.task = None;
+        let was_shown = self.signature_help_state.popover.take().is_some();
+        if was_shown {
+            cx.notify();
+        }
+        was_shown
+    }
+}
+
+/// Counts the commas in `range` that aren't nested in brackets, or returns `None` if the
+/// range closes the bracket it starts in.
+fn count_argument_separators(buffer: &MultiBufferSnapshot, range: Range<usize>) -> Option<usize> {
+    let mut depth = 0_usize;
+    let mut separators = 0;
+    for chunk in buffer.text_for_range(range) {
+        for ch in chunk.chars() {
+            match ch {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.checked_sub(1)?,
+                ',' if depth == 0 => separators += 1,
+                _ => {}
+            }
+        }
+    }
+    Some(separators)
+}
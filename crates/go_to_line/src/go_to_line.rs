@@ -3,7 +3,7 @@ use gpui::{
     actions, div, prelude::*, AnyWindowHandle, AppContext, DismissEvent, EventEmitter, FocusHandle,
     FocusableView, Render, SharedString, Styled, Subscription, View, ViewContext, VisualContext,
 };
-use text::{Bias, Point};
+use text::Point;
 use theme::ActiveTheme;
 use ui::{h_flex, prelude::*, v_flex, Label};
 use util::paths::FILE_ROW_COLUMN_DELIMITER;
@@ -105,8 +105,8 @@ impl GoToLine {
         if let Some(point) = self.point_from_query(cx) {
             self.active_editor.update(cx, |active_editor, cx| {
                 let snapshot = active_editor.snapshot(cx).display_snapshot;
-                let point = snapshot.buffer_snapshot.clip_point(point, Bias::Left);
-                let display_point = point.to_display_point(&snapshot);
+                let anchor = active_editor.anchor_for_buffer_point(point.row, point.column, cx);
+                let display_point = anchor.to_display_point(&snapshot);
                 let row = display_point.row();
                 active_editor.highlight_rows(Some(row..row + 1));
                 active_editor.request_autoscroll(Autoscroll::center(), cx);
@@ -136,11 +136,7 @@ impl GoToLine {
     fn confirm(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
         if let Some(point) = self.point_from_query(cx) {
             self.active_editor.update(cx, |editor, cx| {
-                let snapshot = editor.snapshot(cx).display_snapshot;
-                let point = snapshot.buffer_snapshot.clip_point(point, Bias::Left);
-                editor.change_selections(Some(Autoscroll::center()), cx, |s| {
-                    s.select_ranges([point..point])
-                });
+                editor.go_to_point(point.row, point.column, cx);
                 editor.focus(cx);
                 cx.notify();
             });
@@ -197,6 +197,20 @@ impl FocusHandle {
     pub fn contains(&self, other: &Self, cx: &WindowContext) -> bool {
         self.id.contains(other.id, cx)
     }
+
+    /// Dispatch an action on the element associated with this handle, whether or not it's
+    /// focused. Does nothing if the element wasn't part of the most recently rendered frame.
+    pub fn dispatch_action(&self, action: &dyn Action, cx: &mut WindowContext) {
+        if let Some(node_id) = cx
+            .window
+            .rendered_frame
+            .dispatch_tree
+            .focusable_node_id(self.id)
+        {
+            cx.propagate_event = true;
+            cx.dispatch_action_on_node(node_id, action.boxed_clone());
+        }
+    }
 }
 
 impl Clone for FocusHandle {
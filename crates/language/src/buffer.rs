@@ -1,5 +1,6 @@
 pub use crate::{
     diagnostic_set::DiagnosticSet,
+    editorconfig::EditorConfig,
     highlight_map::{HighlightId, HighlightMap},
     markdown::ParsedMarkdown,
     proto, Grammar, Language, LanguageRegistry,
@@ -44,10 +45,11 @@ use sum_tree::TreeMap;
 use text::operation_queue::OperationQueue;
 use text::*;
 pub use text::{
-    Anchor, Bias, Buffer as TextBuffer, BufferId, BufferSnapshot as TextBufferSnapshot, Edit,
-    OffsetRangeExt, OffsetUtf16, Patch, Point, PointUtf16, Rope, RopeFingerprint, Selection,
-    SelectionGoal, Subscription, TextDimension, TextSummary, ToOffset, ToOffsetUtf16, ToPoint,
-    ToPointUtf16, Transaction, TransactionId, Unclipped,
+    Anchor, Bias, Buffer as TextBuffer, BufferHistory, BufferId,
+    BufferSnapshot as TextBufferSnapshot, Edit, OffsetRangeExt, OffsetUtf16, Patch, Point,
+    PointUtf16, Rope, RopeFingerprint, Selection, SelectionGoal, Subscription, TextDimension,
+    TextSummary, ToOffset, ToOffsetUtf16, ToPoint, ToPointUtf16, Transaction, TransactionId,
+    Unclipped,
 };
 use theme::SyntaxTheme;
 #[cfg(any(test, feature = "test-support"))]
@@ -81,6 +83,11 @@ pub struct Buffer {
     diff_base: Option<String>,
     git_diff: git::diff::BufferDiff,
     file: Option<Arc<dyn File>>,
+    /// The properties of the `.editorconfig` files that apply to the buffer's file.
+    editor_config: EditorConfig,
+    /// The indentation detected in the buffer's contents, along with the version
+    /// it was detected at. This is computed on demand by [`Buffer::detected_indent`].
+    detected_indent: Mutex<Option<(clock::Global, Option<IndentSize>)>>,
     /// The mtime of the file when this buffer was last loaded from
     /// or saved to disk.
     saved_mtime: SystemTime,
@@ -127,6 +134,7 @@ pub struct BufferSnapshot {
     selections_update_count: usize,
     language: Option<Arc<Language>>,
     parse_count: usize,
+    is_parsing: bool,
 }
 
 /// The kind and amount of indentation in a particular line. For now,
@@ -495,6 +503,24 @@ pub struct Chunk<'a> {
     pub is_unnecessary: bool,
     /// Whether this chunk of text was originally a tab character.
     pub is_tab: bool,
+    /// Whether this chunk of text is a suggestion that is displayed in the editor without
+    /// being part of the buffer.
+    pub is_suggestion: bool,
+    /// Where this chunk's whitespace appears within its line, if the chunk consists of
+    /// whitespace and the iterator producing it was asked to mark whitespace.
+    pub whitespace: Option<WhitespacePosition>,
+}
+
+/// The position of a run of whitespace within its line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespacePosition {
+    /// Whitespace preceding the first non-whitespace character of the line.
+    Leading,
+    /// Whitespace between non-whitespace characters.
+    Inner,
+    /// Whitespace following the last non-whitespace character of the line, including the
+    /// whitespace of lines that contain nothing else.
+    Trailing,
 }
 
 /// A set of edits to a given version of a buffer, computed asynchronously.
@@ -680,6 +706,8 @@ impl Buffer {
             diff_base,
             git_diff: git::diff::BufferDiff::new(),
             file,
+            editor_config: EditorConfig::default(),
+            detected_indent: Default::default(),
             capability,
             syntax_map: Mutex::new(SyntaxMap::new()),
             parsing_in_background: false,
@@ -721,6 +749,7 @@ impl Buffer {
             git_diff_update_count: self.git_diff_update_count,
             language: self.language.clone(),
             parse_count: self.parse_count,
+            is_parsing: self.parsing_in_background,
             selections_update_count: self.selections_update_count,
         }
     }
@@ -909,6 +938,62 @@ impl Buffer {
         }
     }
 
+    /// Returns the properties of the `.editorconfig` files that apply to the buffer's file.
+    pub fn editor_config(&self) -> &EditorConfig {
+        &self.editor_config
+    }
+
+    /// Sets the `.editorconfig` properties that apply to the buffer's file.
+    pub fn set_editor_config(&mut self, editor_config: EditorConfig, cx: &mut ModelContext<Self>) {
+        if self.editor_config != editor_config {
+            self.editor_config = editor_config;
+            cx.notify();
+        }
+    }
+
+    /// Returns the indentation detected in the buffer's contents, if it is consistent
+    /// enough to tell. The result is cached until the buffer is next edited.
+    pub fn detected_indent(&self) -> Option<IndentSize> {
+        let mut detected_indent = self.detected_indent.lock();
+        if let Some((version, indent)) = detected_indent.as_ref() {
+            if *version == self.version() {
+                return *indent;
+            }
+        }
+
+        let indent = detect_indent(&self.text);
+        *detected_indent = Some((self.version(), indent));
+        indent
+    }
+
+    /// Returns the unit of indentation to use at `position`. This is the indentation
+    /// configured by the buffer's `.editorconfig` files if they set one, then the one
+    /// detected in the buffer's contents if the language's `detect_indentation` setting
+    /// is enabled, and otherwise the one configured for the language.
+    pub fn indent_size_at<T: ToOffset>(&self, position: T, cx: &AppContext) -> IndentSize {
+        self.indent_size_for_settings(language_settings(
+            self.language_at(position).as_ref(),
+            self.file(),
+            cx,
+        ))
+    }
+
+    fn indent_size_for_settings(&self, settings: &LanguageSettings) -> IndentSize {
+        if let Some(indent) = self.editor_config.indent_size() {
+            return indent;
+        }
+        if settings.detect_indentation {
+            if let Some(indent) = self.detected_indent() {
+                return indent;
+            }
+        }
+        if settings.hard_tabs {
+            IndentSize::tab()
+        } else {
+            IndentSize::spaces(settings.tab_size.get())
+        }
+    }
+
     /// Returns the current diff base, see [Buffer::set_diff_base].
     pub fn diff_base(&self) -> Option<&str> {
         self.diff_base.as_deref()
@@ -1458,6 +1543,34 @@ impl Buffer {
         })
     }
 
+    /// Like [Buffer::remove_trailing_whitespace], but only removes whitespace
+    /// from the lines that were modified since the buffer was last saved.
+    pub fn remove_trailing_whitespace_on_modified_lines(&self, cx: &AppContext) -> Task<Diff> {
+        let old_text = self.as_rope().clone();
+        let line_ending = self.line_ending();
+        let base_version = self.version();
+        let modified_rows = self
+            .edits_since::<Point>(&self.saved_version)
+            .map(|edit| edit.new.start.row..edit.new.end.row + 1)
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let empty = Arc::<str>::from("");
+            let edits = trailing_whitespace_ranges(&old_text)
+                .into_iter()
+                .filter(|range| {
+                    let row = old_text.offset_to_point(range.end).row;
+                    modified_rows.iter().any(|rows| rows.contains(&row))
+                })
+                .map(|range| (range, empty.clone()))
+                .collect();
+            Diff {
+                base_version,
+                line_ending,
+                edits,
+            }
+        })
+    }
+
     /// Ensures that the buffer ends with a single newline character, and
     /// no other whitespace.
     pub fn ensure_final_newline(&mut self, cx: &mut ModelContext<Self>) {
@@ -1611,6 +1724,22 @@ impl Buffer {
         self.text.merge_transactions(transaction, destination);
     }
 
+    /// Recreates an undo history captured by [`TextBuffer::serialize_history`] from a buffer
+    /// with the same text. Returns false if the text differs or the buffer already has a history.
+    pub fn restore_history(&mut self, history: BufferHistory, cx: &mut ModelContext<Self>) -> bool {
+        let was_dirty = self.is_dirty();
+        let old_version = self.version.clone();
+        if let Some(operations) = self.text.restore_history(history) {
+            for operation in operations {
+                self.send_operation(Operation::Buffer(operation), cx);
+            }
+            self.did_edit(&old_version, was_dirty, cx);
+            true
+        } else {
+            false
+        }
+    }
+
     /// Waits for the buffer to receive operations with the given timestamps.
     pub fn wait_for_edits(
         &mut self,
@@ -1792,7 +1921,11 @@ impl Buffer {
                     AutoindentRequestEntry {
                         first_line_is_new,
                         original_indent_column,
-                        indent_size: before_edit.language_indent_size_at(range.start, cx),
+                        indent_size: self.indent_size_for_settings(language_settings(
+                            before_edit.language_at(range.start),
+                            self.file(),
+                            cx,
+                        )),
                         range: self.anchor_before(new_start + range_of_insertion_to_indent.start)
                             ..self.anchor_after(new_start + range_of_insertion_to_indent.end),
                     }
@@ -2049,6 +2182,24 @@ impl Buffer {
         }
     }
 
+    /// Manually redoes a specific transaction in the buffer's redo history, leaving
+    /// any transactions that were undone after it on the redo stack.
+    pub fn redo_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        let was_dirty = self.is_dirty();
+        let old_version = self.version.clone();
+        if let Some(operation) = self.text.redo_transaction(transaction_id) {
+            self.send_operation(Operation::Buffer(operation), cx);
+            self.did_edit(&old_version, was_dirty, cx);
+            true
+        } else {
+            false
+        }
+    }
+
     /// Manually undoes all changes until a given transaction in the buffer's redo history.
     pub fn redo_to_transaction(
         &mut self,
@@ -2178,6 +2329,15 @@ impl BufferSnapshot {
         }
     }
 
+    /// Infers the unit of indentation used in the buffer from its leading whitespace.
+    ///
+    /// Returns a tab if most indented lines start with tabs, and otherwise the most common
+    /// increase in indentation between consecutive non-blank lines. Returns `None` if the
+    /// buffer doesn't contain enough indentation to tell.
+    pub fn detect_indent(&self) -> Option<IndentSize> {
+        detect_indent(self)
+    }
+
     /// Retrieve the suggested indent size for all of the given rows. The unit of indentation
     /// is passed in as `single_indent_size`.
     pub fn suggested_indents(
@@ -3085,6 +3245,12 @@ impl BufferSnapshot {
         self.parse_count
     }
 
+    /// Whether the buffer was being parsed in the background when this snapshot was taken,
+    /// in which case its syntax highlighting may be out of date.
+    pub fn is_parsing(&self) -> bool {
+        self.is_parsing
+    }
+
     /// The number of times selections were updated.
     pub fn selections_update_count(&self) -> usize {
         self.selections_update_count
@@ -3119,6 +3285,51 @@ impl BufferSnapshot {
     }
 }
 
+fn detect_indent(text: &text::BufferSnapshot) -> Option<IndentSize> {
+    const MAX_ROWS: u32 = 1000;
+    const MAX_INDENT_WIDTH: u32 = 8;
+
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut width_counts = [0; MAX_INDENT_WIDTH as usize + 1];
+    let mut prev_indent_len = 0;
+    for row in 0..=text.max_point().row.min(MAX_ROWS) {
+        if text.is_line_blank(row) {
+            continue;
+        }
+
+        let indent = indent_size_for_line(text, row);
+        match indent.kind {
+            IndentKind::Tab => {
+                tab_lines += 1;
+                prev_indent_len = 0;
+            }
+            IndentKind::Space => {
+                if indent.len > 0 {
+                    space_lines += 1;
+                }
+                // Single spaces are usually alignment, such as in block comments, rather
+                // than indentation.
+                let width = indent.len.saturating_sub(prev_indent_len);
+                if (2..=MAX_INDENT_WIDTH).contains(&width) {
+                    width_counts[width as usize] += 1;
+                }
+                prev_indent_len = indent.len;
+            }
+        }
+    }
+
+    if tab_lines > space_lines {
+        return Some(IndentSize::tab());
+    }
+    let (width, count) = width_counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, count)| **count)?;
+    (*count > 0).then(|| IndentSize::spaces(width as u32))
+}
+
 fn indent_size_for_line(text: &text::BufferSnapshot, row: u32) -> IndentSize {
     indent_size_for_text(text.chars_at(Point::new(row, 0)))
 }
@@ -3154,6 +3365,7 @@ impl Clone for BufferSnapshot {
             git_diff_update_count: self.git_diff_update_count,
             language: self.language.clone(),
             parse_count: self.parse_count,
+            is_parsing: self.is_parsing,
         }
     }
 }
@@ -15,6 +15,7 @@ use regex::RegexBuilder;
 use settings::SettingsStore;
 use std::{
     env,
+    num::NonZeroU32,
     ops::Range,
     time::{Duration, Instant},
 };
@@ -252,6 +253,37 @@ async fn test_apply_diff(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_remove_trailing_whitespace_on_modified_lines(cx: &mut gpui::TestAppContext) {
+    let buffer = cx.new_model(|cx| {
+        Buffer::new(
+            0,
+            BufferId::new(cx.entity_id().as_u64()).unwrap(),
+            "one  \ntwo  \nthree  \nfour  \n",
+        )
+    });
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit(
+            [
+                (Point::new(1, 3)..Point::new(1, 3), "!"),
+                (Point::new(3, 0)..Point::new(3, 0), "\t"),
+            ],
+            None,
+            cx,
+        );
+    });
+
+    let diff = buffer
+        .update(cx, |buffer, cx| {
+            buffer.remove_trailing_whitespace_on_modified_lines(cx)
+        })
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.apply_diff(diff, cx);
+        assert_eq!(buffer.text(), "one  \ntwo!\nthree  \n\tfour\n");
+    });
+}
+
 #[gpui::test(iterations = 10)]
 async fn test_normalize_whitespace(cx: &mut gpui::TestAppContext) {
     let text = [
@@ -938,6 +970,31 @@ fn test_range_for_syntax_ancestor(cx: &mut AppContext) {
     }
 }
 
+#[gpui::test]
+fn test_detect_indent(cx: &mut AppContext) {
+    fn detect_indent(text: &str, cx: &mut AppContext) -> Option<IndentSize> {
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        buffer.read(cx).snapshot().detect_indent()
+    }
+
+    assert_eq!(detect_indent("a\nb\n", cx), None);
+    assert_eq!(
+        detect_indent("fn a() {\n  if b {\n    c();\n  }\n}\n", cx),
+        Some(IndentSize::spaces(2))
+    );
+    assert_eq!(
+        detect_indent("fn a() {\n\tb();\n\tif c {\n\t\td();\n\t}\n}\n", cx),
+        Some(IndentSize::tab())
+    );
+
+    // Alignment inside of block comments doesn't count as indentation.
+    assert_eq!(
+        detect_indent("/*\n * a\n * b\n */\nfn c() {\n    d();\n}\n", cx),
+        Some(IndentSize::spaces(4))
+    );
+}
+
 #[gpui::test]
 fn test_autoindent_with_soft_tabs(cx: &mut AppContext) {
     init_settings(cx, |_| {});
@@ -979,6 +1036,81 @@ fn test_autoindent_with_soft_tabs(cx: &mut AppContext) {
     });
 }
 
+#[gpui::test]
+fn test_autoindent_with_detected_indentation(cx: &mut AppContext) {
+    init_settings(cx, |settings| {
+        settings.defaults.detect_indentation = Some(true);
+    });
+
+    cx.new_model(|cx| {
+        let text = "fn a() {\n  if b {}\n}";
+        let mut buffer = Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+            .with_language(Arc::new(rust_lang()), cx);
+        assert_eq!(buffer.detected_indent(), Some(IndentSize::spaces(2)));
+
+        buffer.edit(
+            [(Point::new(1, 8)..Point::new(1, 8), "\n\n")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n  if b {\n    \n  }\n}");
+
+        // The detected indentation follows the buffer's contents as they change.
+        buffer.edit([(0..buffer.len(), "fn a() {\n\tb();\n}")], None, cx);
+        assert_eq!(buffer.detected_indent(), Some(IndentSize::tab()));
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_autoindent_with_editor_config(cx: &mut AppContext) {
+    init_settings(cx, |settings| {
+        settings.defaults.detect_indentation = Some(true);
+    });
+
+    cx.new_model(|cx| {
+        let text = "fn a() {\n  b();\n}\nfn c() {}";
+        let mut buffer = Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+            .with_language(Arc::new(rust_lang()), cx);
+        assert_eq!(buffer.detected_indent(), Some(IndentSize::spaces(2)));
+
+        // The `.editorconfig` indentation takes precedence over the detected one.
+        buffer.set_editor_config(
+            EditorConfig {
+                indent_style: Some(IndentKind::Tab),
+                ..Default::default()
+            },
+            cx,
+        );
+        assert_eq!(buffer.indent_size_at(0, cx), IndentSize::tab());
+        buffer.edit(
+            [(Point::new(3, 8)..Point::new(3, 8), "\n\n")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n  b();\n}\nfn c() {\n\t\n}");
+
+        buffer.set_editor_config(
+            EditorConfig {
+                indent_style: Some(IndentKind::Space),
+                indent_size: NonZeroU32::new(3),
+                ..Default::default()
+            },
+            cx,
+        );
+        buffer.edit([(0..buffer.len(), text)], None, cx);
+        buffer.edit(
+            [(Point::new(3, 8)..Point::new(3, 8), "\n\n")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n  b();\n}\nfn c() {\n   \n}");
+
+        buffer
+    });
+}
+
 #[gpui::test]
 fn test_autoindent_with_hard_tabs(cx: &mut AppContext) {
     init_settings(cx, |settings| {
@@ -0,0 +1,184 @@
+//! Support for [EditorConfig](https://editorconfig.org) files, which configure the formatting
+//! of the files in a directory tree independently of the editor being used.
+
+use crate::{IndentKind, IndentSize};
+use globset::GlobBuilder;
+use std::{num::NonZeroU32, path::Path};
+
+/// The EditorConfig properties that apply to a single file.
+///
+/// Every property is optional, and properties that aren't set defer to the user's settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EditorConfig {
+    pub indent_style: Option<IndentKind>,
+    /// The number of columns of each level of indentation.
+    pub indent_size: Option<NonZeroU32>,
+    /// The number of columns a tab character occupies.
+    pub tab_width: Option<NonZeroU32>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub max_line_length: Option<u32>,
+}
+
+impl EditorConfig {
+    /// Returns whether the given `.editorconfig` contents declare themselves as the root,
+    /// meaning that files in ancestor directories shouldn't be consulted.
+    pub fn is_root(contents: &str) -> bool {
+        properties(contents)
+            .take_while(|(section, _, _)| section.is_none())
+            .any(|(_, key, value)| key == "root" && value == "true")
+    }
+
+    /// Applies the properties in the given `.editorconfig` contents that match `path`, which
+    /// is relative to the directory containing the `.editorconfig` file. Later sections take
+    /// precedence over earlier ones, so files should be applied from the root down.
+    pub fn apply(&mut self, contents: &str, path: &Path) {
+        let mut section_matches = false;
+        let mut current_section = None;
+        for (section, key, value) in properties(contents) {
+            if section != current_section {
+                current_section = section;
+                section_matches = section.map_or(false, |glob| glob_matches(glob, path));
+            }
+            if section_matches {
+                self.set(&key, &value);
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        fn parse<T: std::str::FromStr>(value: &str) -> Option<T> {
+            value.parse().ok()
+        }
+
+        // Values that fail to parse, such as `unset`, reset the property to its default.
+        match key {
+            "indent_style" => {
+                self.indent_style = match value {
+                    "tab" => Some(IndentKind::Tab),
+                    "space" => Some(IndentKind::Space),
+                    _ => None,
+                }
+            }
+            "indent_size" if value == "tab" => {
+                self.indent_style = Some(IndentKind::Tab);
+                self.indent_size = None;
+            }
+            "indent_size" => self.indent_size = parse(value),
+            "tab_width" => self.tab_width = parse(value),
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = parse(value),
+            "insert_final_newline" => self.insert_final_newline = parse(value),
+            "max_line_length" => self.max_line_length = parse(value),
+            _ => {}
+        }
+    }
+
+    /// The number of columns a tab character occupies, which defaults to the indent size.
+    pub fn tab_size(&self) -> Option<NonZeroU32> {
+        self.tab_width.or(self.indent_size)
+    }
+
+    /// The unit of indentation, if the indent style is set.
+    pub fn indent_size(&self) -> Option<IndentSize> {
+        match self.indent_style? {
+            IndentKind::Tab => Some(IndentSize::tab()),
+            IndentKind::Space => Some(IndentSize::spaces(
+                self.indent_size.or(self.tab_width)?.get(),
+            )),
+        }
+    }
+}
+
+/// Iterates over the properties in an `.editorconfig` file's contents, along with the glob of
+/// the section they're in. Properties that precede every section have no glob.
+fn properties(contents: &str) -> impl Iterator<Item = (Option<&str>, String, String)> + '_ {
+    let mut section = None;
+    contents.lines().filter_map(move |line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return None;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = Some(glob);
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        Some((
+            section,
+            key.trim().to_lowercase(),
+            value.trim().to_lowercase(),
+        ))
+    })
+}
+
+fn glob_matches(glob: &str, path: &Path) -> bool {
+    // Globs without a slash match files with that name in any directory, while globs with
+    // one are relative to the directory of the `.editorconfig` file.
+    let glob = if glob.contains('/') {
+        glob.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{glob}")
+    };
+    GlobBuilder::new(&glob)
+        .literal_separator(true)
+        .build()
+        .map_or(false, |glob| glob.compile_matcher().is_match(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_config() {
+        let contents = "
+            root = true
+
+            [*]
+            indent_style = space
+            indent_size = 4
+            insert_final_newline = true
+
+            # Makefiles require tabs.
+            [Makefile]
+            indent_style = tab
+
+            [*.{js,ts}]
+            indent_size = 2
+            max_line_length = 80
+
+            [docs/**/*.md]
+            trim_trailing_whitespace = false
+            insert_final_newline = unset
+        ";
+        assert!(EditorConfig::is_root(contents));
+        assert!(!EditorConfig::is_root("[*]\nroot = true\n"));
+
+        let config_for = |path: &str| {
+            let mut config = EditorConfig::default();
+            config.apply(contents, Path::new(path));
+            config
+        };
+
+        let rust = config_for("src/main.rs");
+        assert_eq!(rust.indent_size(), Some(IndentSize::spaces(4)));
+        assert_eq!(rust.insert_final_newline, Some(true));
+        assert_eq!(rust.max_line_length, None);
+
+        let makefile = config_for("sub/Makefile");
+        assert_eq!(makefile.indent_size(), Some(IndentSize::tab()));
+        assert_eq!(makefile.tab_size(), NonZeroU32::new(4));
+
+        let script = config_for("web/index.ts");
+        assert_eq!(script.indent_size(), Some(IndentSize::spaces(2)));
+        assert_eq!(script.max_line_length, Some(80));
+
+        let docs = config_for("docs/guide/intro.md");
+        assert_eq!(docs.trim_trailing_whitespace, Some(false));
+        assert_eq!(docs.insert_final_newline, None);
+        assert_eq!(config_for("intro.md").trim_trailing_whitespace, None);
+    }
+}
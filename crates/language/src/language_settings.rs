@@ -54,6 +54,9 @@ pub struct LanguageSettings {
     /// Whether to indent lines using tab characters, as opposed to multiple
     /// spaces.
     pub hard_tabs: bool,
+    /// Whether to infer the indentation from the buffer's existing contents,
+    /// instead of using `tab_size` and `hard_tabs`.
+    pub detect_indentation: bool,
     /// How to soft-wrap long lines of text.
     pub soft_wrap: SoftWrap,
     /// The column at which to soft-wrap lines, for buffers where soft-wrap
@@ -71,6 +74,9 @@ pub struct LanguageSettings {
     /// Whether or not to remove any trailing whitespace from lines of a buffer
     /// before saving it.
     pub remove_trailing_whitespace_on_save: bool,
+    /// Whether to only remove trailing whitespace from the lines that were
+    /// modified since the buffer was last saved.
+    pub remove_trailing_whitespace_on_modified_lines_only: bool,
     /// Whether or not to ensure there's a single newline at the end of a buffer
     /// when saving it.
     pub ensure_final_newline_on_save: bool,
@@ -137,6 +143,12 @@ pub struct LanguageSettingsContent {
     /// Default: false
     #[serde(default)]
     pub hard_tabs: Option<bool>,
+    /// Whether to infer the indentation from the buffer's existing contents,
+    /// instead of using `tab_size` and `hard_tabs`.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub detect_indentation: Option<bool>,
     /// How to soft-wrap long lines of text.
     ///
     /// Default: none
@@ -172,6 +184,12 @@ pub struct LanguageSettingsContent {
     /// Default: true
     #[serde(default)]
     pub remove_trailing_whitespace_on_save: Option<bool>,
+    /// Whether to only remove trailing whitespace from the lines that were
+    /// modified since the buffer was last saved.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub remove_trailing_whitespace_on_modified_lines_only: Option<bool>,
     /// Whether or not to ensure there's a single newline at the end of a buffer
     /// when saving it.
     ///
@@ -553,6 +571,7 @@ impl settings::Settings for AllLanguageSettings {
 fn merge_settings(settings: &mut LanguageSettings, src: &LanguageSettingsContent) {
     merge(&mut settings.tab_size, src.tab_size);
     merge(&mut settings.hard_tabs, src.hard_tabs);
+    merge(&mut settings.detect_indentation, src.detect_indentation);
     merge(&mut settings.soft_wrap, src.soft_wrap);
     merge(&mut settings.use_autoclose, src.use_autoclose);
     merge(&mut settings.show_wrap_guides, src.show_wrap_guides);
@@ -573,6 +592,10 @@ fn merge_settings(settings: &mut LanguageSettings, src: &LanguageSettingsContent
         &mut settings.remove_trailing_whitespace_on_save,
         src.remove_trailing_whitespace_on_save,
     );
+    merge(
+        &mut settings.remove_trailing_whitespace_on_modified_lines_only,
+        src.remove_trailing_whitespace_on_modified_lines_only,
+    );
     merge(
         &mut settings.ensure_final_newline_on_save,
         src.ensure_final_newline_on_save,
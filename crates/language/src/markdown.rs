@@ -228,11 +228,9 @@ pub async fn parse_markdown_block(
 
                 Tag::CodeBlock(kind) => {
                     new_paragraph(text, &mut list_stack);
-                    current_language = if let CodeBlockKind::Fenced(language) = kind {
-                        language_registry
-                            .language_for_name(language.as_ref())
+                    current_language = if let CodeBlockKind::Fenced(info) = kind {
+                        code_block_language(info.as_ref(), language_registry, language.clone())
                             .await
-                            .ok()
                     } else {
                         language.clone()
                     }
@@ -290,6 +288,26 @@ pub async fn parse_markdown_block(
     }
 }
 
+/// Resolves the language of a fenced code block from its info string, such as `rust` or
+/// `rs,ignore`. Code blocks without an info string are assumed to be in the given language.
+pub async fn code_block_language(
+    info: &str,
+    language_registry: &Arc<LanguageRegistry>,
+    language: Option<Arc<Language>>,
+) -> Option<Arc<Language>> {
+    let name = info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+    if name.is_empty() {
+        return language;
+    }
+    language_registry
+        .language_for_name_or_extension(name)
+        .await
+        .ok()
+}
+
 /// Appends a highlighted run of text to the provided `text` buffer.
 pub fn highlight_code(
     text: &mut String,
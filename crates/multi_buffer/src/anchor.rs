@@ -1,8 +1,10 @@
-use super::{ExcerptId, MultiBufferSnapshot, ToOffset, ToOffsetUtf16, ToPoint};
-use language::{OffsetUtf16, Point, TextDimension};
+use super::{Excerpt, ExcerptId, MultiBufferSnapshot, ToOffset, ToOffsetUtf16, ToPoint};
+use language::{BufferSnapshot, OffsetRangeExt, OffsetUtf16, Point, TextDimension};
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
     ops::{Range, Sub},
+    path::PathBuf,
 };
 use sum_tree::Bias;
 use text::BufferId;
@@ -76,6 +78,13 @@ impl Anchor {
         self.clone()
     }
 
+    pub fn with_bias(&self, bias: Bias, snapshot: &MultiBufferSnapshot) -> Anchor {
+        match bias {
+            Bias::Left => self.bias_left(snapshot),
+            Bias::Right => self.bias_right(snapshot),
+        }
+    }
+
     pub fn summary<D>(&self, snapshot: &MultiBufferSnapshot) -> D
     where
         D: TextDimension + Ord + Sub<D, Output = D>,
@@ -83,6 +92,54 @@ impl Anchor {
         snapshot.summary_for_anchor(self)
     }
 
+    /// Resolves this anchor to an offset in its underlying buffer, clipped to its excerpt.
+    pub fn to_buffer_offset(&self, snapshot: &MultiBufferSnapshot) -> Option<(BufferId, usize)> {
+        let (buffer, offset) = self.buffer_offset(snapshot)?;
+        Some((buffer.remote_id(), offset))
+    }
+
+    /// Resolves this anchor to a point in its underlying buffer, clipped to its excerpt.
+    pub fn to_buffer_point(&self, snapshot: &MultiBufferSnapshot) -> Option<(BufferId, Point)> {
+        let (buffer, offset) = self.buffer_offset(snapshot)?;
+        Some((buffer.remote_id(), buffer.offset_to_point(offset)))
+    }
+
+    fn buffer_offset<'a>(
+        &self,
+        snapshot: &'a MultiBufferSnapshot,
+    ) -> Option<(&'a BufferSnapshot, usize)> {
+        if self.excerpt_id == ExcerptId::min() || self.excerpt_id == ExcerptId::max() {
+            return snapshot.point_to_buffer_offset(self.to_offset(snapshot));
+        }
+
+        let excerpt = snapshot.excerpt(self.excerpt_id)?;
+        if self.buffer_id != Some(excerpt.buffer_id) {
+            return None;
+        }
+        let offset =
+            text::ToOffset::to_offset(&excerpt.clip_anchor(self.text_anchor), &excerpt.buffer);
+        Some((&*excerpt.buffer, offset))
+    }
+
+    pub fn serialize(&self, snapshot: &MultiBufferSnapshot) -> SerializedAnchor {
+        let mut serialized = SerializedAnchor {
+            excerpt_id: self.excerpt_id,
+            path: None,
+            offset: 0,
+            right_biased: self.text_anchor.bias == Bias::Right,
+        };
+        if self.excerpt_id != ExcerptId::min() && self.excerpt_id != ExcerptId::max() {
+            if let Some(excerpt) = snapshot.excerpt(self.excerpt_id) {
+                serialized.path = excerpt.buffer.file().map(|file| file.path().to_path_buf());
+                serialized.offset = text::ToOffset::to_offset(
+                    &excerpt.clip_anchor(self.text_anchor),
+                    &excerpt.buffer,
+                );
+            }
+        }
+        serialized
+    }
+
     pub fn is_valid(&self, snapshot: &MultiBufferSnapshot) -> bool {
         if *self == Anchor::min() || *self == Anchor::max() {
             true
@@ -97,6 +154,61 @@ impl Anchor {
     }
 }
 
+/// A representation of an [`Anchor`] that doesn't depend on the in-memory state of its
+/// buffer, so that it can be persisted and later resolved against a rebuilt multi-buffer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedAnchor {
+    pub excerpt_id: ExcerptId,
+    /// The path of the excerpted buffer's file, if it has one.
+    pub path: Option<PathBuf>,
+    /// The anchor's offset within the excerpted buffer.
+    pub offset: usize,
+    pub right_biased: bool,
+}
+
+impl SerializedAnchor {
+    /// Resolves this anchor against the given snapshot, preferring the excerpt with the same id
+    /// but falling back to any excerpt of the same file that contains the anchor's offset.
+    /// Anchors in buffers without a file can only be resolved against their original excerpt.
+    pub fn resolve(&self, snapshot: &MultiBufferSnapshot) -> Option<Anchor> {
+        let excerpt_id = self.excerpt_id;
+        if excerpt_id == ExcerptId::min() {
+            return Some(Anchor::min());
+        } else if excerpt_id == ExcerptId::max() {
+            return Some(Anchor::max());
+        }
+
+        let contains_anchor = |excerpt: &Excerpt| {
+            let range = excerpt.range.context.to_offset(&excerpt.buffer);
+            excerpt.buffer.file().map(|file| file.path().as_ref()) == self.path.as_deref()
+                && range.start <= self.offset
+                && self.offset <= range.end
+        };
+        let excerpt = snapshot
+            .excerpt(excerpt_id)
+            .filter(|excerpt| contains_anchor(excerpt))
+            .or_else(|| {
+                self.path.as_ref()?;
+                snapshot
+                    .excerpts
+                    .iter()
+                    .find(|excerpt| contains_anchor(excerpt))
+            })?;
+
+        let bias = if self.right_biased {
+            Bias::Right
+        } else {
+            Bias::Left
+        };
+        let offset = excerpt.buffer.clip_offset(self.offset, bias);
+        Some(Anchor {
+            buffer_id: Some(excerpt.buffer_id),
+            excerpt_id: excerpt.id,
+            text_anchor: excerpt.buffer.anchor_at(offset, bias),
+        })
+    }
+}
+
 impl ToOffset for Anchor {
     fn to_offset(&self, snapshot: &MultiBufferSnapshot) -> usize {
         self.summary(snapshot)
@@ -137,3 +249,142 @@ impl AnchorRangeExt for Range<Anchor> {
         self.start.to_point(content)..self.end.to_point(content)
     }
 }
+
+/// A collection of anchored ranges with associated values, kept sorted by their position in
+/// the multi-buffer so that they can be resolved in bulk.
+#[derive(Clone, Debug)]
+pub struct AnchorRangeMap<T> {
+    entries: Vec<(Range<Anchor>, T)>,
+}
+
+/// A collection of anchored ranges, kept sorted by their position in the multi-buffer so that
+/// they can be resolved in bulk.
+#[derive(Clone, Debug, Default)]
+pub struct AnchorRangeSet(AnchorRangeMap<()>);
+
+impl<T> Default for AnchorRangeMap<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> AnchorRangeMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_entries(
+        entries: impl IntoIterator<Item = (Range<Anchor>, T)>,
+        snapshot: &MultiBufferSnapshot,
+    ) -> Self {
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b, snapshot));
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, range: Range<Anchor>, value: T, snapshot: &MultiBufferSnapshot) {
+        let ix = match self
+            .entries
+            .binary_search_by(|(probe, _)| probe.cmp(&range, snapshot))
+        {
+            Ok(ix) | Err(ix) => ix,
+        };
+        self.entries.insert(ix, (range, value));
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Range<Anchor>, &T) -> bool) {
+        self.entries.retain(|(range, value)| f(range, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Range<Anchor>, &T)> + '_ {
+        self.entries.iter().map(|(range, value)| (range, value))
+    }
+
+    /// Resolves every range in the map against the given snapshot, walking the snapshot's
+    /// excerpts once for the range starts and once for the range ends.
+    pub fn resolve<'a, D>(
+        &'a self,
+        snapshot: &'a MultiBufferSnapshot,
+    ) -> impl Iterator<Item = (Range<D>, &'a T)> + 'a
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+    {
+        let starts = snapshot
+            .summaries_for_anchors::<D, _>(self.entries.iter().map(|(range, _)| &range.start));
+
+        // Range ends aren't necessarily sorted, even though the starts are.
+        let ends = snapshot.summaries_for_unsorted_anchors::<D, _>(
+            self.entries.iter().map(|(range, _)| &range.end),
+        );
+
+        starts
+            .into_iter()
+            .zip(ends)
+            .zip(self.entries.iter())
+            .map(|((start, end), (_, value))| (start..end, value))
+    }
+}
+
+impl AnchorRangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_ranges(
+        ranges: impl IntoIterator<Item = Range<Anchor>>,
+        snapshot: &MultiBufferSnapshot,
+    ) -> Self {
+        Self(AnchorRangeMap::from_entries(
+            ranges.into_iter().map(|range| (range, ())),
+            snapshot,
+        ))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn insert(&mut self, range: Range<Anchor>, snapshot: &MultiBufferSnapshot) {
+        self.0.insert(range, (), snapshot);
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Range<Anchor>) -> bool) {
+        self.0.retain(|range, _| f(range));
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Range<Anchor>> + '_ {
+        self.0.iter().map(|(range, _)| range)
+    }
+
+    pub fn resolve<'a, D>(
+        &'a self,
+        snapshot: &'a MultiBufferSnapshot,
+    ) -> impl Iterator<Item = Range<D>> + 'a
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+    {
+        self.0.resolve(snapshot).map(|(range, _)| range)
+    }
+}
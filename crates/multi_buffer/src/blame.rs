@@ -0,0 +1,280 @@
+//! Fetches and caches blame information for the rows of a [`MultiBuffer`], such as for
+//! rendering inline blame annotations.
+
+use crate::{MultiBuffer, MultiBufferSnapshot};
+use anyhow::Result;
+use collections::{BTreeMap, HashMap};
+use gpui::{AppContext, Model, ModelContext, SharedString, Task};
+use language::{Bias, Buffer, Point};
+use std::{ops::Range, sync::Arc};
+use text::BufferId;
+use util::ResultExt as _;
+
+/// The commit that last changed a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlameEntry {
+    pub commit: SharedString,
+    pub author: SharedString,
+    /// The time of the commit, in seconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// Computes blame information for buffers, typically by running `git blame`.
+pub trait BlameProvider: 'static {
+    /// Returns the blame for each of the given rows of the buffer, in order, with `None` for
+    /// rows that haven't been committed.
+    fn blame(
+        &self,
+        buffer: &Model<Buffer>,
+        rows: Range<u32>,
+        cx: &mut AppContext,
+    ) -> Task<Result<Vec<Option<BlameEntry>>>>;
+}
+
+/// Requests blame information for the rows of a [`MultiBuffer`] as they become visible, and
+/// caches it per buffer.
+///
+/// A buffer's cached blame is discarded whenever the buffer is edited, as its rows no longer
+/// line up with the blame, and responses to requests made before an edit are ignored. The
+/// model is notified whenever new blame information arrives.
+pub struct MultiBufferBlame {
+    multibuffer: Model<MultiBuffer>,
+    provider: Arc<dyn BlameProvider>,
+    buffers: HashMap<BufferId, BufferBlame>,
+}
+
+#[derive(Default)]
+struct BufferBlame {
+    version: clock::Global,
+    /// The blame of each row that has been fetched, keyed by buffer row.
+    rows: BTreeMap<u32, Option<BlameEntry>>,
+    /// The row ranges of requests that haven't completed yet.
+    pending_rows: Vec<Range<u32>>,
+}
+
+impl MultiBufferBlame {
+    pub fn new(multibuffer: Model<MultiBuffer>, provider: Arc<dyn BlameProvider>) -> Self {
+        Self {
+            multibuffer,
+            provider,
+            buffers: HashMap::default(),
+        }
+    }
+
+    pub fn multibuffer(&self) -> &Model<MultiBuffer> {
+        &self.multibuffer
+    }
+
+    /// Requests blame for any rows in the given range of multi-buffer rows that aren't cached
+    /// or already being fetched.
+    pub fn request_blame(&mut self, rows: Range<u32>, cx: &mut ModelContext<Self>) {
+        if rows.is_empty() {
+            return;
+        }
+        let buffer_ranges = {
+            let multibuffer = self.multibuffer.read(cx);
+            let snapshot = multibuffer.read(cx);
+            let start = Point::new(rows.start, 0);
+            let end = snapshot.clip_point(
+                Point::new(rows.end - 1, u32::MAX).min(snapshot.max_point()),
+                Bias::Left,
+            );
+            drop(snapshot);
+            multibuffer.range_to_buffer_ranges(start..end, cx)
+        };
+
+        for (buffer, range, _) in buffer_ranges {
+            let snapshot = buffer.read(cx).snapshot();
+            let start_row = snapshot.offset_to_point(range.start).row;
+            let end_row = snapshot.offset_to_point(range.end).row + 1;
+
+            let blame = self.buffers.entry(snapshot.remote_id()).or_default();
+            if blame.version != *snapshot.version() {
+                *blame = BufferBlame {
+                    version: snapshot.version().clone(),
+                    ..Default::default()
+                };
+            }
+
+            // Request each run of rows that isn't cached or being fetched separately, so
+            // that rows between them aren't fetched again.
+            let mut missing_row_ranges = Vec::<Range<u32>>::new();
+            for row in start_row..end_row {
+                if blame.rows.contains_key(&row)
+                    || blame.pending_rows.iter().any(|range| range.contains(&row))
+                {
+                    continue;
+                }
+                match missing_row_ranges.last_mut() {
+                    Some(range) if range.end == row => range.end += 1,
+                    _ => missing_row_ranges.push(row..row + 1),
+                }
+            }
+
+            for requested_rows in missing_row_ranges {
+                blame.pending_rows.push(requested_rows.clone());
+                let task = self.provider.blame(&buffer, requested_rows.clone(), cx);
+                let version = snapshot.version().clone();
+                let buffer_id = snapshot.remote_id();
+                cx.spawn(move |this, mut cx| async move {
+                    let entries = task.await;
+                    this.update(&mut cx, |this, cx| {
+                        let Some(blame) = this
+                            .buffers
+                            .get_mut(&buffer_id)
+                            .filter(|blame| blame.version == version)
+                        else {
+                            return;
+                        };
+                        blame.pending_rows.retain(|rows| *rows != requested_rows);
+                        if let Some(entries) = entries.log_err() {
+                            blame.rows.extend(requested_rows.zip(entries));
+                            cx.notify();
+                        }
+                    })
+                })
+                .detach_and_log_err(cx);
+            }
+        }
+    }
+
+    /// Returns the blame for the given multi-buffer row, if it has been fetched for the
+    /// current contents of its buffer.
+    pub fn blame_for_row(&self, row: u32, snapshot: &MultiBufferSnapshot) -> Option<&BlameEntry> {
+        let (buffer, range) = snapshot.buffer_line_for_row(row)?;
+        let blame = self.buffers.get(&buffer.remote_id())?;
+        if blame.version != *buffer.version() {
+            return None;
+        }
+        blame.rows.get(&range.start.row)?.as_ref()
+    }
+
+    /// Discards the cached blame of the given buffer, such as after it has been saved.
+    pub fn invalidate_buffer(&mut self, buffer_id: BufferId) {
+        self.buffers.remove(&buffer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExcerptRange;
+    use gpui::{Context, TestAppContext};
+    use language::Capability;
+    use parking_lot::Mutex;
+    use util::test::sample_text;
+
+    struct FakeBlameProvider {
+        requests: Arc<Mutex<Vec<Range<u32>>>>,
+    }
+
+    impl BlameProvider for FakeBlameProvider {
+        fn blame(
+            &self,
+            _: &Model<Buffer>,
+            rows: Range<u32>,
+            _: &mut AppContext,
+        ) -> Task<Result<Vec<Option<BlameEntry>>>> {
+            self.requests.lock().push(rows.clone());
+            let entries = rows
+                .map(|row| {
+                    (row % 2 == 0).then(|| BlameEntry {
+                        commit: format!("commit-{row}").into(),
+                        author: "author".into(),
+                        timestamp: row as i64,
+                    })
+                })
+                .collect();
+            Task::ready(Ok(entries))
+        }
+    }
+
+    #[gpui::test]
+    async fn test_multibuffer_blame(cx: &mut TestAppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(8, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(2, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(5, 0)..Point::new(7, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer
+        });
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let blame = cx.new_model(|_| {
+            MultiBufferBlame::new(
+                multibuffer.clone(),
+                Arc::new(FakeBlameProvider {
+                    requests: requests.clone(),
+                }),
+            )
+        });
+
+        let commits_for_rows = |rows: Range<u32>, cx: &mut TestAppContext| {
+            let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+            blame.read_with(cx, |blame, _| {
+                rows.map(|row| {
+                    blame
+                        .blame_for_row(row, &snapshot)
+                        .map(|entry| entry.commit.to_string())
+                })
+                .collect::<Vec<_>>()
+            })
+        };
+
+        blame.update(cx, |blame, cx| blame.request_blame(1..4, cx));
+        cx.run_until_parked();
+        assert_eq!(*requests.lock(), [1..3, 5..6]);
+        assert_eq!(
+            commits_for_rows(0..6, cx),
+            [None, None, Some("commit-2".into()), None, None, None,]
+        );
+
+        // Rows that are already cached aren't requested again.
+        blame.update(cx, |blame, cx| blame.request_blame(0..6, cx));
+        cx.run_until_parked();
+        assert_eq!(*requests.lock(), [1..3, 5..6, 0..1, 6..8]);
+        assert_eq!(
+            commits_for_rows(0..6, cx),
+            [
+                Some("commit-0".into()),
+                None,
+                Some("commit-2".into()),
+                None,
+                Some("commit-6".into()),
+                None,
+            ]
+        );
+
+        // Rows between cached rows are requested separately, and rows that are being fetched
+        // aren't requested again.
+        blame.update(cx, |blame, cx| {
+            blame.invalidate_buffer(buffer.read(cx).remote_id())
+        });
+        blame.update(cx, |blame, cx| blame.request_blame(1..2, cx));
+        blame.update(cx, |blame, cx| blame.request_blame(0..3, cx));
+        blame.update(cx, |blame, cx| blame.request_blame(0..3, cx));
+        cx.run_until_parked();
+        assert_eq!(requests.lock()[4..], [1..2, 0..1, 2..3]);
+
+        // Editing a buffer discards its blame.
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "X")], None, cx));
+        assert_eq!(commits_for_rows(0..6, cx), vec![None; 6]);
+    }
+}
@@ -0,0 +1,309 @@
+//! Maintains the excerpts of a [`MultiBuffer`] that shows the diagnostics of several buffers,
+//! such as the one backing a project diagnostics view.
+
+use crate::{build_excerpt_ranges, ExcerptId, MultiBuffer};
+use gpui::{AppContext, Model};
+use language::{Buffer, DiagnosticEntry, DiagnosticSeverity, OffsetRangeExt as _};
+use std::{mem, ops::Range, path::Path, sync::Arc};
+use text::BufferId;
+
+/// Keeps a [`MultiBuffer`] populated with excerpts around the diagnostics of a set of buffers.
+///
+/// Excerpts are grouped by file, with files ordered by path, and each excerpt includes a
+/// configurable number of context lines around its diagnostics. Diagnostics whose context
+/// lines overlap share an excerpt. When the diagnostics change, excerpts whose range didn't
+/// change are kept, so that selections and scroll positions within them are preserved. The
+/// remaining excerpts are removed, and new ones are inserted in their place, which is also
+/// how excerpts grow or shrink.
+pub struct DiagnosticsMultiBuffer {
+    multibuffer: Model<MultiBuffer>,
+    context_line_count: u32,
+    /// The buffers with diagnostics, ordered by path.
+    buffers: Vec<BufferDiagnostics>,
+}
+
+struct BufferDiagnostics {
+    buffer: Model<Buffer>,
+    buffer_id: BufferId,
+    path: Option<Arc<Path>>,
+    /// The buffer's diagnostics, ordered by their start position.
+    diagnostics: Vec<DiagnosticEntry<text::Anchor>>,
+    excerpts: Vec<DiagnosticExcerpt>,
+}
+
+/// An excerpt maintained by a [`DiagnosticsMultiBuffer`], along with the diagnostics it shows.
+#[derive(Clone, Debug)]
+pub struct DiagnosticExcerpt {
+    pub id: ExcerptId,
+    pub buffer_id: BufferId,
+    pub context: Range<text::Anchor>,
+    /// The diagnostics within the excerpt, ordered by their start position.
+    pub diagnostics: Vec<DiagnosticEntry<text::Anchor>>,
+}
+
+impl DiagnosticExcerpt {
+    /// Returns the severity of the most severe diagnostic in the excerpt.
+    pub fn max_severity(&self) -> Option<DiagnosticSeverity> {
+        // Lower values are more severe.
+        self.diagnostics
+            .iter()
+            .map(|entry| entry.diagnostic.severity)
+            .min()
+    }
+}
+
+impl DiagnosticsMultiBuffer {
+    pub fn new(multibuffer: Model<MultiBuffer>, context_line_count: u32) -> Self {
+        Self {
+            multibuffer,
+            context_line_count,
+            buffers: Vec::new(),
+        }
+    }
+
+    pub fn multibuffer(&self) -> &Model<MultiBuffer> {
+        &self.multibuffer
+    }
+
+    pub fn context_line_count(&self) -> u32 {
+        self.context_line_count
+    }
+
+    /// Changes the number of context lines around diagnostics, resizing every excerpt.
+    pub fn set_context_line_count(&mut self, context_line_count: u32, cx: &mut AppContext) {
+        if context_line_count != self.context_line_count {
+            self.context_line_count = context_line_count;
+            for ix in 0..self.buffers.len() {
+                self.refresh_buffer(ix, cx);
+            }
+        }
+    }
+
+    /// Replaces the diagnostics shown for the given buffer, updating its excerpts. Setting
+    /// an empty set of diagnostics removes the buffer's excerpts.
+    pub fn set_diagnostics(
+        &mut self,
+        buffer: Model<Buffer>,
+        diagnostics: impl IntoIterator<Item = DiagnosticEntry<text::Anchor>>,
+        cx: &mut AppContext,
+    ) {
+        let snapshot = buffer.read(cx).snapshot();
+        let mut diagnostics = diagnostics.into_iter().collect::<Vec<_>>();
+        diagnostics.sort_by(|a, b| {
+            a.range
+                .start
+                .cmp(&b.range.start, &snapshot)
+                .then_with(|| b.range.end.cmp(&a.range.end, &snapshot))
+        });
+
+        let buffer_id = snapshot.remote_id();
+        let ix = if let Some(ix) = self
+            .buffers
+            .iter()
+            .position(|state| state.buffer_id == buffer_id)
+        {
+            ix
+        } else if diagnostics.is_empty() {
+            return;
+        } else {
+            let path = snapshot.file().map(|file| file.path().clone());
+            let ix = self
+                .buffers
+                .partition_point(|state| (&state.path, state.buffer_id) < (&path, buffer_id));
+            self.buffers.insert(
+                ix,
+                BufferDiagnostics {
+                    buffer,
+                    buffer_id,
+                    path,
+                    diagnostics: Vec::new(),
+                    excerpts: Vec::new(),
+                },
+            );
+            ix
+        };
+
+        self.buffers[ix].diagnostics = diagnostics;
+        self.refresh_buffer(ix, cx);
+        if self.buffers[ix].excerpts.is_empty() {
+            self.buffers.remove(ix);
+        }
+    }
+
+    /// Removes all of the given buffer's excerpts.
+    pub fn remove_buffer(&mut self, buffer_id: BufferId, cx: &mut AppContext) {
+        if let Some(ix) = self
+            .buffers
+            .iter()
+            .position(|state| state.buffer_id == buffer_id)
+        {
+            let state = self.buffers.remove(ix);
+            self.multibuffer.update(cx, |multibuffer, cx| {
+                multibuffer.remove_excerpts(state.excerpts.iter().map(|excerpt| excerpt.id), cx)
+            });
+        }
+    }
+
+    /// Returns all excerpts, in the order they appear in the multi-buffer.
+    pub fn excerpts(&self) -> impl Iterator<Item = &DiagnosticExcerpt> {
+        self.buffers.iter().flat_map(|state| &state.excerpts)
+    }
+
+    pub fn excerpts_for_buffer(&self, buffer_id: BufferId) -> &[DiagnosticExcerpt] {
+        self.buffers
+            .iter()
+            .find(|state| state.buffer_id == buffer_id)
+            .map_or(&[], |state| &state.excerpts)
+    }
+
+    pub fn excerpt(&self, excerpt_id: ExcerptId) -> Option<&DiagnosticExcerpt> {
+        self.excerpts().find(|excerpt| excerpt.id == excerpt_id)
+    }
+
+    /// Brings the excerpts of the buffer at the given index in line with its diagnostics.
+    fn refresh_buffer(&mut self, ix: usize, cx: &mut AppContext) {
+        let mut prev_excerpt_id = self.buffers[..ix]
+            .iter()
+            .rev()
+            .find_map(|state| state.excerpts.last())
+            .map_or(ExcerptId::min(), |excerpt| excerpt.id);
+        let state = &mut self.buffers[ix];
+        let snapshot = state.buffer.read(cx).snapshot();
+        let diagnostic_ranges = state
+            .diagnostics
+            .iter()
+            .map(|entry| entry.range.clone())
+            .collect::<Vec<_>>();
+        let (excerpt_ranges, diagnostic_counts) =
+            build_excerpt_ranges(&snapshot, &diagnostic_ranges, self.context_line_count);
+
+        let mut diagnostics = state.diagnostics.iter().cloned();
+        let mut old_excerpts = mem::take(&mut state.excerpts).into_iter().peekable();
+        let mut new_excerpts = Vec::new();
+        let mut removed_excerpt_ids = Vec::new();
+        self.multibuffer.update(cx, |multibuffer, cx| {
+            for (range, diagnostic_count) in excerpt_ranges.into_iter().zip(diagnostic_counts) {
+                // Old excerpts that start before this one and don't match its range are no
+                // longer needed, as excerpts never overlap.
+                let mut kept_excerpt = None;
+                while let Some(old_excerpt) = old_excerpts.peek() {
+                    let old_range = old_excerpt.context.to_point(&snapshot);
+                    if old_range == range.context {
+                        kept_excerpt = old_excerpts.next();
+                        break;
+                    } else if old_range.start <= range.context.start {
+                        removed_excerpt_ids.extend(old_excerpts.next().map(|excerpt| excerpt.id));
+                    } else {
+                        break;
+                    }
+                }
+
+                let diagnostics = diagnostics.by_ref().take(diagnostic_count).collect();
+                let excerpt = if let Some(mut excerpt) = kept_excerpt {
+                    excerpt.diagnostics = diagnostics;
+                    excerpt
+                } else {
+                    let context = snapshot.anchor_before(range.context.start)
+                        ..snapshot.anchor_after(range.context.end);
+                    let id = multibuffer.insert_excerpts_after(
+                        prev_excerpt_id,
+                        state.buffer.clone(),
+                        [range],
+                        cx,
+                    )[0];
+                    DiagnosticExcerpt {
+                        id,
+                        buffer_id: state.buffer_id,
+                        context,
+                        diagnostics,
+                    }
+                };
+                prev_excerpt_id = excerpt.id;
+                new_excerpts.push(excerpt);
+            }
+
+            removed_excerpt_ids.extend(old_excerpts.map(|excerpt| excerpt.id));
+            multibuffer.remove_excerpts(removed_excerpt_ids, cx);
+        });
+        state.excerpts = new_excerpts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::Context;
+    use language::{Diagnostic, Point, ToPoint as _};
+    use settings::SettingsStore;
+
+    #[gpui::test]
+    fn test_diagnostics_multibuffer(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+
+        let text = (0..20)
+            .map(|row| format!("line {row}\n"))
+            .collect::<String>();
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, language::Capability::ReadWrite));
+        let mut diagnostics = DiagnosticsMultiBuffer::new(multibuffer.clone(), 1);
+
+        let diagnostic_entry = |row: u32, severity: DiagnosticSeverity, cx: &AppContext| {
+            let buffer = buffer.read(cx);
+            DiagnosticEntry {
+                range: buffer.anchor_before(Point::new(row, 0))
+                    ..buffer.anchor_after(Point::new(row, 4)),
+                diagnostic: Diagnostic {
+                    severity,
+                    ..Default::default()
+                },
+            }
+        };
+
+        let entries = [
+            diagnostic_entry(2, DiagnosticSeverity::WARNING, cx),
+            diagnostic_entry(10, DiagnosticSeverity::WARNING, cx),
+            diagnostic_entry(3, DiagnosticSeverity::ERROR, cx),
+        ];
+        diagnostics.set_diagnostics(buffer.clone(), entries.clone(), cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\nline 4\n\nline 9\nline 10\nline 11\n"
+        );
+        let excerpts = diagnostics.excerpts().cloned().collect::<Vec<_>>();
+        assert_eq!(excerpts.len(), 2);
+        assert_eq!(excerpts[0].diagnostics.len(), 2);
+        assert_eq!(excerpts[0].max_severity(), Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            excerpts[1].max_severity(),
+            Some(DiagnosticSeverity::WARNING)
+        );
+
+        // Excerpts whose range is unchanged are kept when the diagnostics change.
+        diagnostics.set_diagnostics(buffer.clone(), entries[..2].to_vec(), cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\n\nline 9\nline 10\nline 11\n"
+        );
+        assert_ne!(diagnostics.excerpts().next().unwrap().id, excerpts[0].id);
+        assert_eq!(diagnostics.excerpts().nth(1).unwrap().id, excerpts[1].id);
+
+        // Changing the number of context lines resizes the excerpts.
+        diagnostics.set_context_line_count(0, cx);
+        assert_eq!(multibuffer.read(cx).text(), "line 2\n\nline 10\n");
+        let excerpt = diagnostics.excerpts().nth(1).unwrap();
+        let snapshot = buffer.read(cx).snapshot();
+        assert_eq!(
+            excerpt.context.start.to_point(&snapshot)..excerpt.context.end.to_point(&snapshot),
+            Point::new(10, 0)..Point::new(11, 0)
+        );
+
+        // Removing all diagnostics removes the buffer's excerpts.
+        diagnostics.set_diagnostics(buffer.clone(), [], cx);
+        assert_eq!(multibuffer.read(cx).text(), "");
+        assert!(diagnostics
+            .excerpts_for_buffer(snapshot.remote_id())
+            .is_empty());
+    }
+}
@@ -1,50 +1,78 @@
 mod anchor;
-
-pub use anchor::{Anchor, AnchorRangeExt};
+mod blame;
+mod diagnostics;
+mod patch;
+pub mod proto;
+mod search_results;
+mod text_objects;
+
+pub use anchor::{Anchor, AnchorRangeExt, AnchorRangeMap, AnchorRangeSet, SerializedAnchor};
 use anyhow::{anyhow, Result};
+pub use blame::{BlameEntry, BlameProvider, MultiBufferBlame};
 use clock::ReplicaId;
-use collections::{BTreeMap, Bound, HashMap, HashSet};
+use collections::{BTreeMap, Bound, HashMap, HashSet, VecDeque};
+pub use diagnostics::{DiagnosticExcerpt, DiagnosticsMultiBuffer};
 use futures::{channel::mpsc, SinkExt};
 use git::diff::DiffHunk;
-use gpui::{AppContext, EventEmitter, Model, ModelContext};
+use gpui::{AppContext, EventEmitter, Model, ModelContext, SharedString, Task};
 pub use language::Completion;
 use language::{
     char_kind,
     language_settings::{language_settings, LanguageSettings},
     AutoindentMode, Buffer, BufferChunks, BufferSnapshot, Capability, CharKind, Chunk, CursorShape,
-    DiagnosticEntry, File, IndentSize, Language, LanguageScope, OffsetRangeExt, OffsetUtf16,
-    Outline, OutlineItem, Point, PointUtf16, Selection, TextDimension, ToOffset as _,
-    ToOffsetUtf16 as _, ToPoint as _, ToPointUtf16 as _, TransactionId, Unclipped,
+    DiagnosticEntry, DiagnosticSeverity, EditorConfig, File, HighlightId, IndentSize, Language,
+    LanguageScope, OffsetRangeExt, OffsetUtf16, Outline, OutlineItem, Point, PointUtf16, Rope,
+    Selection, TextDimension, ToOffset as _, ToOffsetUtf16 as _, ToPoint as _, ToPointUtf16 as _,
+    TransactionId, Unclipped, WhitespacePosition,
 };
+use parking_lot::Mutex;
+pub use patch::{parse_unified_diff, FailedHunk, FilePatch, PatchHunk};
+pub use proto::ExcerptOperation;
+use regex::Regex;
+pub use search_results::{SearchMatchId, SearchResultsMultiBuffer};
+use serde::{Deserialize, Serialize};
 use std::{
+    any::Any,
     borrow::Cow,
-    cell::{Ref, RefCell},
+    cell::{Cell, Ref, RefCell, RefMut},
     cmp, fmt,
     future::Future,
     io,
     iter::{self, FromIterator},
     mem,
-    ops::{Range, RangeBounds, Sub},
+    ops::{Range, RangeBounds, RangeInclusive, Sub},
+    path::PathBuf,
     str,
-    sync::Arc,
+    sync::{Arc, Weak},
     time::{Duration, Instant},
 };
-use sum_tree::{Bias, Cursor, SumTree};
+use sum_tree::{Bias, Cursor, SumTree, TreeMap};
 use text::{
     locator::Locator,
     subscription::{Subscription, Topic},
-    BufferId, Edit, TextSummary,
+    BufferHistory, BufferId, Edit, RopeFingerprint, SelectionGoal, TextSummary, TransactionEdits,
 };
+pub use text_objects::TextObjectScope;
 use theme::SyntaxTheme;
 
-use util::post_inc;
-
 #[cfg(any(test, feature = "test-support"))]
 use gpui::Context;
 
 const NEWLINES: &[u8] = &[b'\n'; u8::MAX as usize];
 
-#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+/// The locator length beyond which excerpt locators are reassigned, to keep them from
+/// growing without bound when excerpts are repeatedly inserted between the same neighbors.
+const MAX_LOCATOR_LEN: usize = 4;
+
+/// A stable identifier for an [`Excerpt`] within a [`MultiBuffer`].
+///
+/// Excerpt ids are plain integers that are never reused within a multi-buffer, which makes
+/// them cheap to copy, hash, and persist. Their position in the multi-buffer is tracked
+/// separately via a mapping to [`Locator`]s, so use [`ExcerptId::cmp`] to order them.
+#[derive(
+    Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(transparent)]
 pub struct ExcerptId(usize);
 
 /// One or more [`Buffers`](Buffer) being edited in a single view.
@@ -54,13 +82,46 @@ pub struct MultiBuffer {
     /// A snapshot of the [`Excerpt`]s in the MultiBuffer.
     /// Use [`MultiBuffer::snapshot`] to get a up-to-date snapshot.
     snapshot: RefCell<MultiBufferSnapshot>,
+    /// Incremented every time `snapshot` changes.
+    snapshot_version: Cell<usize>,
+    /// A shared copy of `snapshot`, handed out by [`MultiBuffer::shared_snapshot`] until
+    /// the snapshot changes.
+    shared_snapshot: RefCell<Option<Arc<MultiBufferSnapshot>>>,
     /// Contains the state of the buffers being edited
     buffers: RefCell<HashMap<BufferId, BufferState>>,
+    /// The buffers that may have changed since the last sync, either because they notified
+    /// their observers or because this multi-buffer updated them. Only these buffers are
+    /// checked for changes when syncing.
+    dirty_buffers: RefCell<HashSet<BufferId>>,
     subscriptions: Topic,
+    edit_sources: EditSourceTopic,
     /// If true, the multi-buffer only contains a single [`Buffer`] and a single [`Excerpt`]
     singleton: bool,
     replica_id: ReplicaId,
     history: History,
+    /// The local selections before and after each transaction, restored on undo and redo.
+    #[allow(clippy::type_complexity)]
+    selections_by_transaction:
+        HashMap<TransactionId, (Arc<[Selection<Anchor>]>, Option<Arc<[Selection<Anchor>]>>)>,
+    /// Human-readable descriptions of transactions, for display in undo history UI.
+    transaction_labels: HashMap<TransactionId, SharedString>,
+    /// Excerpts that have been registered with [`MultiBuffer::push_lazy_excerpts`], but
+    /// not yet inserted.
+    pending_excerpts: VecDeque<(Model<Buffer>, ExcerptRange<text::Anchor>)>,
+    /// The maximum length of newly inserted excerpts, beyond which their content is elided.
+    max_excerpt_len: Option<usize>,
+    /// How the edges of newly inserted excerpts treat insertions at their boundaries.
+    excerpt_edges: ExcerptEdges,
+    /// The position that views should scroll to the next time they lay out, which lets
+    /// code that restructures the excerpts keep the relevant content on screen.
+    autoscroll_anchor: Option<Anchor>,
+    /// The number of times the autoscroll anchor was set, so that each view can tell which
+    /// requests it has already applied.
+    autoscroll_request_count: usize,
+    /// Whether excerpts whose content is entirely deleted are removed automatically.
+    remove_emptied_excerpts: bool,
+    /// Excerpts that were emptied by edits since the last sync, pending removal.
+    emptied_excerpts: RefCell<Vec<ExcerptId>>,
     title: Option<String>,
     capability: Capability,
 }
@@ -94,6 +155,7 @@ pub enum Event {
     Closed,
     DirtyChanged,
     DiagnosticsUpdated,
+    LocalSelectionsChanged,
 }
 
 #[derive(Clone)]
@@ -103,17 +165,44 @@ struct History {
     redo_stack: Vec<Transaction>,
     transaction_depth: usize,
     group_interval: Duration,
+    /// The maximum number of transactions kept on the undo stack, if any.
+    max_transactions: Option<usize>,
 }
 
 #[derive(Clone)]
 struct Transaction {
     id: TransactionId,
     buffer_transactions: HashMap<BufferId, text::TransactionId>,
+    /// The excerpts that were inserted or removed during the transaction, in order.
+    excerpt_changes: Vec<ExcerptChange>,
     first_edit_at: Instant,
     last_edit_at: Instant,
     suppress_grouping: bool,
 }
 
+/// A transaction of an underlying buffer that must be merged into an earlier one, because
+/// the multi-buffer transactions containing them were grouped together.
+#[derive(Clone, Copy, Debug)]
+struct BufferTransactionMerge {
+    buffer_id: BufferId,
+    transaction_id: TransactionId,
+    destination: TransactionId,
+}
+
+#[derive(Clone)]
+enum ExcerptChange {
+    Inserted(ExcerptRecord),
+    Removed(ExcerptRecord),
+}
+
+/// Everything needed to restore an excerpt with its original id and position.
+#[derive(Clone)]
+struct ExcerptRecord {
+    id: ExcerptId,
+    buffer: Model<Buffer>,
+    range: ExcerptRange<text::Anchor>,
+}
+
 pub trait ToOffset: 'static + fmt::Debug {
     fn to_offset(&self, snapshot: &MultiBufferSnapshot) -> usize;
 }
@@ -138,12 +227,19 @@ struct BufferState {
     last_diagnostics_update_count: usize,
     last_file_update_count: usize,
     last_git_diff_update_count: usize,
+    last_is_dirty: bool,
+    last_has_conflict: bool,
+    /// The snapshot of the buffer shared by all of its excerpts, as of the last sync.
+    snapshot: Arc<BufferSnapshot>,
     excerpts: Vec<Locator>,
+    /// The ids of the excerpts in `excerpts`, computed on demand by
+    /// [`MultiBuffer::excerpt_ids_for_buffer`] and cleared when the excerpts change.
+    excerpt_ids: Option<Arc<[ExcerptId]>>,
     _subscriptions: [gpui::Subscription; 2],
 }
 
 /// The contents of a [`MultiBuffer`] at a single point in time.
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct MultiBufferSnapshot {
     singleton: bool,
     excerpts: SumTree<Excerpt>,
@@ -151,10 +247,99 @@ pub struct MultiBufferSnapshot {
     parse_count: usize,
     diagnostics_update_count: usize,
     trailing_excerpt_update_count: usize,
+    excerpt_update_count: usize,
     git_diff_update_count: usize,
     edit_count: usize,
+    remote_selections_update_count: usize,
     is_dirty: bool,
     has_conflict: bool,
+    /// If true, the newlines separating excerpts are treated as real text when
+    /// searching, so that matches may span multiple excerpts.
+    search_across_excerpts: bool,
+    /// The length in bytes beyond which a buffer's excerpts are rendered without syntax
+    /// highlighting or diagnostics, to keep very large files responsive.
+    large_buffer_threshold: Option<usize>,
+    /// The selections of the view that most recently edited this multi-buffer's selections.
+    local_selections: Arc<[Selection<Anchor>]>,
+    selections_update_count: usize,
+    /// The original end of each excerpt whose content was truncated because it exceeded
+    /// the multi-buffer's maximum excerpt length.
+    elided_excerpts: TreeMap<ExcerptId, text::Anchor>,
+    /// Values attached to excerpts with [`MultiBuffer::set_excerpt_annotation`].
+    excerpt_annotations: TreeMap<ExcerptId, ExcerptAnnotation>,
+    /// Every excerpt that has been inserted, including removed ones, keyed by locator.
+    /// This is used to order concurrently inserted excerpts the same way on every replica.
+    excerpt_ids_by_locator: TreeMap<Locator, ExcerptId>,
+}
+
+// `Arc<[T]>` doesn't implement `Default` on our toolchain, so this can't be derived.
+impl Default for MultiBufferSnapshot {
+    fn default() -> Self {
+        Self {
+            singleton: Default::default(),
+            excerpts: Default::default(),
+            excerpt_ids: Default::default(),
+            parse_count: Default::default(),
+            diagnostics_update_count: Default::default(),
+            trailing_excerpt_update_count: Default::default(),
+            excerpt_update_count: Default::default(),
+            git_diff_update_count: Default::default(),
+            edit_count: Default::default(),
+            remote_selections_update_count: Default::default(),
+            is_dirty: Default::default(),
+            has_conflict: Default::default(),
+            search_across_excerpts: Default::default(),
+            large_buffer_threshold: Default::default(),
+            local_selections: Arc::from([]),
+            selections_update_count: Default::default(),
+            elided_excerpts: Default::default(),
+            excerpt_annotations: Default::default(),
+            excerpt_ids_by_locator: Default::default(),
+        }
+    }
+}
+
+/// A value attached to an excerpt, such as a match count or a diagnostic severity, that
+/// views can query to render excerpt headers and gutters.
+#[derive(Clone)]
+pub struct ExcerptAnnotation(Arc<dyn Any + Send + Sync>);
+
+impl ExcerptAnnotation {
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+impl fmt::Debug for ExcerptAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExcerptAnnotation").finish_non_exhaustive()
+    }
+}
+
+/// The kinds of changes between two snapshots of a [`MultiBuffer`], as returned by
+/// [`MultiBufferSnapshot::changes_since`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MultiBufferChanges {
+    /// The text was edited, or excerpts were added or removed.
+    pub text: bool,
+    /// Any of the buffers were reparsed.
+    pub syntax: bool,
+    /// The local selections or any collaborator's selections changed.
+    pub selections: bool,
+    pub diagnostics: bool,
+    pub git_diff: bool,
+}
+
+impl MultiBufferChanges {
+    /// Returns true if nothing but decorations, such as selections, diagnostics, or
+    /// git status, changed, meaning that the text doesn't need to be laid out again.
+    pub fn is_decoration_only(&self) -> bool {
+        !self.text && !self.syntax
+    }
 }
 
 /// A boundary between [`Excerpt`]s in a [`MultiBuffer`]
@@ -171,6 +356,54 @@ pub struct ExcerptBoundary {
     pub starts_new_buffer: bool,
 }
 
+/// The markers within one of the equally tall row ranges that a multi-buffer is divided into
+/// by [`MultiBufferSnapshot::marker_summaries`], such as for rendering scrollbar markers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MarkerSummary {
+    /// The severity of the most severe diagnostic starting in these rows.
+    pub diagnostic_severity: Option<DiagnosticSeverity>,
+    pub search_match_count: usize,
+    /// The number of local selections whose head is in these rows.
+    pub selection_count: usize,
+}
+
+/// The selection on one row of a rectangular selection, as returned by
+/// [`MultiBufferSnapshot::column_ranges`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnRange {
+    pub range: Range<Point>,
+    /// Whether the rectangle was selected from right to left.
+    pub reversed: bool,
+    /// The number of columns by which the row's line falls short of the rectangle's left
+    /// edge, which [`MultiBuffer::edit_columns`] fills with spaces.
+    pub padding: u32,
+}
+
+/// The excerpt header that should be pinned to the top of the viewport, as returned by
+/// [`MultiBufferSnapshot::header_for_row`].
+pub struct StickyHeader {
+    /// The boundary at the start of the excerpt containing the top row.
+    pub boundary: ExcerptBoundary,
+    /// The row where the next excerpt's header starts, which pushes this header out of the
+    /// viewport once it reaches the top, or `None` if this is the last excerpt.
+    pub next_header_row: Option<u32>,
+}
+
+/// The ranges of a pair of matching brackets, as returned by
+/// [`MultiBufferSnapshot::innermost_bracket_pair`]. A bracket that lies outside of the excerpt
+/// containing the queried range is omitted, in which case the pair is clipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BracketPairRanges {
+    pub open: Option<Range<usize>>,
+    pub close: Option<Range<usize>>,
+}
+
+impl BracketPairRanges {
+    pub fn is_clipped(&self) -> bool {
+        self.open.is_none() || self.close.is_none()
+    }
+}
+
 /// A slice into a [`Buffer`] that is being edited in a [`MultiBuffer`].
 #[derive(Clone)]
 struct Excerpt {
@@ -180,8 +413,8 @@ struct Excerpt {
     locator: Locator,
     /// The buffer being excerpted
     buffer_id: BufferId,
-    /// A snapshot of the buffer being excerpted
-    buffer: BufferSnapshot,
+    /// A snapshot of the buffer being excerpted, shared with the buffer's other excerpts
+    buffer: Arc<BufferSnapshot>,
     /// The range of the buffer to be shown in the excerpt
     range: ExcerptRange<text::Anchor>,
     /// The last row in the excerpted slice of the buffer
@@ -191,12 +424,143 @@ struct Excerpt {
     has_trailing_newline: bool,
 }
 
+/// Identifies the excerpt and buffer responsible for an edit to a [`MultiBuffer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EditSource {
+    pub excerpt_id: ExcerptId,
+    pub buffer_id: BufferId,
+    pub edit: Edit<usize>,
+}
+
+#[derive(Default)]
+struct EditSourceTopic(Mutex<Vec<Weak<Mutex<Vec<EditSource>>>>>);
+
+/// Accumulates the [`EditSource`]s published by a [`MultiBuffer`] until they are consumed.
+pub struct EditSourceSubscription(Arc<Mutex<Vec<EditSource>>>);
+
+/// The state of a [`MultiBuffer`]'s buffers at a point in time, created by
+/// [`MultiBuffer::checkpoint`].
+#[derive(Clone)]
+pub struct MultiBufferCheckpoint {
+    buffers: HashMap<BufferId, text::BufferSnapshot>,
+}
+
+/// Describes an entry in a [`MultiBuffer`]'s undo or redo stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionSummary {
+    pub id: TransactionId,
+    pub label: Option<SharedString>,
+}
+
+/// A serializable form of a [`MultiBuffer`]'s undo history, created by
+/// [`MultiBuffer::serialize_history`]. Buffers are referred to by path, since buffer ids
+/// aren't stable across reloads.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedHistory {
+    pub undo_stack: Vec<SerializedTransaction>,
+    pub redo_stack: Vec<SerializedTransaction>,
+    /// The fingerprint of a singleton multi-buffer's text, which its transactions' edits
+    /// apply to.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedTransaction {
+    pub id: SerializedTransactionId,
+    /// The transactions of the edited buffers that make up this transaction. This is empty
+    /// for singleton multi-buffers, whose transactions are their buffer's transactions.
+    pub buffer_transactions: Vec<(PathBuf, SerializedTransactionId)>,
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The local selections before the transaction, restored when it is undone.
+    #[serde(default)]
+    pub selections_before: Vec<SerializedSelection>,
+    /// The local selections after the transaction, restored when it is redone.
+    #[serde(default)]
+    pub selections_after: Option<Vec<SerializedSelection>>,
+    /// In singleton multi-buffers, the edits that undo this transaction, or that redo it if
+    /// it's in the redo stack. These recreate the buffer's history after a reload.
+    #[serde(default)]
+    pub edits: Vec<(Range<usize>, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedSelection {
+    pub start: SerializedAnchor,
+    pub end: SerializedAnchor,
+    pub reversed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedTransactionId {
+    pub replica_id: ReplicaId,
+    pub value: clock::Seq,
+}
+
+impl From<TransactionId> for SerializedTransactionId {
+    fn from(id: TransactionId) -> Self {
+        Self {
+            replica_id: id.replica_id,
+            value: id.value,
+        }
+    }
+}
+
+impl From<SerializedTransactionId> for TransactionId {
+    fn from(id: SerializedTransactionId) -> Self {
+        Self {
+            replica_id: id.replica_id,
+            value: id.value,
+        }
+    }
+}
+
+/// Why an [`Anchor`] can't be resolved in a [`MultiBufferSnapshot`], as reported by
+/// [`MultiBufferSnapshot::validate_anchors`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaleAnchorReason {
+    /// The anchor's excerpt id was never issued by this multi-buffer.
+    UnknownExcerpt,
+    /// The anchor's excerpt has been removed from the multi-buffer.
+    ExcerptRemoved,
+    /// The anchor's buffer id doesn't match the buffer of its excerpt.
+    BufferMismatch,
+    /// The anchor refers to edits that the excerpt's buffer hasn't observed yet.
+    UnknownBufferVersion,
+    /// The anchor lies outside of its excerpt's context range.
+    OutsideExcerpt,
+}
+
 #[derive(Clone, Debug)]
 struct ExcerptIdMapping {
     id: ExcerptId,
     locator: Locator,
 }
 
+/// Determines whether text inserted exactly at the start or end of an excerpt becomes part
+/// of the excerpt.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExcerptEdges {
+    /// Insertions at either edge extend the excerpt, so that typing at its boundaries stays
+    /// visible.
+    #[default]
+    Inclusive,
+    /// Insertions at either edge fall outside of the excerpt, so that it keeps showing the
+    /// same text.
+    Exclusive,
+}
+
+impl ExcerptEdges {
+    /// The biases with which to anchor the start and end of an excerpt's context.
+    fn biases(self) -> (Bias, Bias) {
+        match self {
+            ExcerptEdges::Inclusive => (Bias::Left, Bias::Right),
+            ExcerptEdges::Exclusive => (Bias::Right, Bias::Left),
+        }
+    }
+}
+
 /// A range of text from a single [`Buffer`], to be shown as an [`Excerpt`].
 /// These ranges are relative to the buffer itself
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -222,6 +586,15 @@ struct ExcerptSummary {
 pub struct MultiBufferRows<'a> {
     buffer_row_range: Range<u32>,
     excerpts: Cursor<'a, Excerpt, Point>,
+    /// The multi-buffer row of the next buffer row to be yielded.
+    row: u32,
+}
+
+/// Yields the buffer row of each multi-buffer row, along with its distance from a current
+/// row, for relative line numbers. See [`MultiBufferRows::relative_to`].
+pub struct RelativeMultiBufferRows<'a> {
+    rows: MultiBufferRows<'a>,
+    current_row: u32,
 }
 
 pub struct MultiBufferChunks<'a> {
@@ -229,6 +602,47 @@ pub struct MultiBufferChunks<'a> {
     excerpts: Cursor<'a, Excerpt, usize>,
     excerpt_chunks: Option<ExcerptChunks<'a>>,
     language_aware: bool,
+    /// If true, excerpts whose buffers are still being parsed are yielded without styling.
+    plain_text_while_parsing: bool,
+    large_buffer_threshold: Option<usize>,
+    highlights_pending: bool,
+    /// If true, runs of whitespace are yielded as separate chunks, marked with their position
+    /// within their line.
+    mark_whitespace: bool,
+    /// The remainder of a chunk that was split at a whitespace boundary.
+    pending_chunk: Option<Chunk<'a>>,
+    /// Whether the current line contains any non-whitespace text before the current offset.
+    line_has_content: bool,
+    /// The offset at which the current line's non-whitespace text ends, computed when the
+    /// line's first run of whitespace is reached.
+    line_content_end: Option<usize>,
+}
+
+/// Yields a downsampled version of the styled text of a range of rows, for rendering a
+/// minimap. See [`MultiBufferSnapshot::minimap_runs`].
+pub struct MinimapRuns<'a> {
+    snapshot: &'a MultiBufferSnapshot,
+    chunks: MultiBufferChunks<'a>,
+    max_runs_per_line: usize,
+    max_column: u32,
+    /// The unprocessed text of the current chunk.
+    text: &'a str,
+    syntax_highlight_id: Option<HighlightId>,
+    row: u32,
+    column: u32,
+    line_run_count: usize,
+    /// The run being extended, which is yielded once a run that follows it starts.
+    run: Option<MinimapRun>,
+}
+
+/// A run of non-whitespace text with a single syntax highlight, on one row of a minimap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinimapRun {
+    pub row: u32,
+    /// The characters covered by the run, counting each character, including tabs, as one
+    /// column.
+    pub columns: Range<u32>,
+    pub syntax_highlight_id: Option<HighlightId>,
 }
 
 pub struct MultiBufferBytes<'a> {
@@ -259,8 +673,12 @@ impl MultiBuffer {
     pub fn new(replica_id: ReplicaId, capability: Capability) -> Self {
         Self {
             snapshot: Default::default(),
+            snapshot_version: Default::default(),
+            shared_snapshot: Default::default(),
             buffers: Default::default(),
+            dirty_buffers: Default::default(),
             subscriptions: Default::default(),
+            edit_sources: Default::default(),
             singleton: false,
             capability,
             replica_id,
@@ -270,7 +688,17 @@ impl MultiBuffer {
                 redo_stack: Default::default(),
                 transaction_depth: 0,
                 group_interval: Duration::from_millis(300),
+                max_transactions: None,
             },
+            selections_by_transaction: Default::default(),
+            transaction_labels: Default::default(),
+            pending_excerpts: Default::default(),
+            max_excerpt_len: None,
+            excerpt_edges: ExcerptEdges::default(),
+            autoscroll_anchor: None,
+            autoscroll_request_count: 0,
+            remove_emptied_excerpts: false,
+            emptied_excerpts: Default::default(),
             title: Default::default(),
         }
     }
@@ -288,9 +716,13 @@ impl MultiBuffer {
                     last_diagnostics_update_count: buffer_state.last_diagnostics_update_count,
                     last_file_update_count: buffer_state.last_file_update_count,
                     last_git_diff_update_count: buffer_state.last_git_diff_update_count,
+                    last_is_dirty: buffer_state.last_is_dirty,
+                    last_has_conflict: buffer_state.last_has_conflict,
+                    snapshot: buffer_state.snapshot.clone(),
                     excerpts: buffer_state.excerpts.clone(),
+                    excerpt_ids: buffer_state.excerpt_ids.clone(),
                     _subscriptions: [
-                        new_cx.observe(&buffer_state.buffer, |_, _, cx| cx.notify()),
+                        new_cx.observe(&buffer_state.buffer, Self::on_buffer_notified),
                         new_cx.subscribe(&buffer_state.buffer, Self::on_buffer_event),
                     ],
                 },
@@ -298,12 +730,25 @@ impl MultiBuffer {
         }
         Self {
             snapshot: RefCell::new(self.snapshot.borrow().clone()),
+            snapshot_version: self.snapshot_version.clone(),
+            shared_snapshot: Default::default(),
             buffers: RefCell::new(buffers),
+            dirty_buffers: self.dirty_buffers.clone(),
             subscriptions: Default::default(),
+            edit_sources: Default::default(),
             singleton: self.singleton,
             capability: self.capability,
             replica_id: self.replica_id,
             history: self.history.clone(),
+            selections_by_transaction: self.selections_by_transaction.clone(),
+            transaction_labels: self.transaction_labels.clone(),
+            pending_excerpts: self.pending_excerpts.clone(),
+            max_excerpt_len: self.max_excerpt_len,
+            excerpt_edges: self.excerpt_edges,
+            autoscroll_anchor: self.autoscroll_anchor,
+            autoscroll_request_count: self.autoscroll_request_count,
+            remove_emptied_excerpts: self.remove_emptied_excerpts,
+            emptied_excerpts: Default::default(),
             title: self.title.clone(),
         }
     }
@@ -328,7 +773,7 @@ impl MultiBuffer {
             }],
             cx,
         );
-        this.snapshot.borrow_mut().singleton = true;
+        this.snapshot_mut().singleton = true;
         this
     }
 
@@ -347,6 +792,31 @@ impl MultiBuffer {
         self.snapshot.borrow()
     }
 
+    /// Returns an up-to-date snapshot of the MultiBuffer that can be shared cheaply.
+    ///
+    /// Unlike [`MultiBuffer::snapshot`], the snapshot is only cloned once per change to
+    /// the MultiBuffer, and the same `Arc` is returned until then.
+    pub fn shared_snapshot(&self, cx: &AppContext) -> Arc<MultiBufferSnapshot> {
+        self.sync(cx);
+        self.shared_snapshot
+            .borrow_mut()
+            .get_or_insert_with(|| Arc::new(self.snapshot.borrow().clone()))
+            .clone()
+    }
+
+    /// Returns a counter that's incremented whenever the MultiBuffer's snapshot changes,
+    /// so that callers can avoid fetching a new snapshot when nothing has changed.
+    pub fn snapshot_version(&self, cx: &AppContext) -> usize {
+        self.sync(cx);
+        self.snapshot_version.get()
+    }
+
+    fn snapshot_mut(&self) -> RefMut<MultiBufferSnapshot> {
+        self.snapshot_version.set(self.snapshot_version.get() + 1);
+        self.shared_snapshot.borrow_mut().take();
+        self.snapshot.borrow_mut()
+    }
+
     pub fn as_singleton(&self) -> Option<Model<Buffer>> {
         if self.singleton {
             return Some(
@@ -371,6 +841,12 @@ impl MultiBuffer {
         self.subscriptions.subscribe()
     }
 
+    /// Subscribes to the excerpts and buffers that produced the edits published via
+    /// [`MultiBuffer::subscribe`], so that observers can invalidate only the affected excerpts.
+    pub fn subscribe_to_edit_sources(&mut self) -> EditSourceSubscription {
+        self.edit_sources.subscribe()
+    }
+
     pub fn is_dirty(&self, cx: &AppContext) -> bool {
         self.read(cx).is_dirty()
     }
@@ -389,6 +865,74 @@ impl MultiBuffer {
         self.len(cx) != 0
     }
 
+    /// Controls whether searches treat the newlines separating excerpts as real text.
+    ///
+    /// This is off by default, because those newlines are synthetic, but views that
+    /// present contiguous text across excerpts (such as diffs) may want to enable it.
+    pub fn set_search_across_excerpts(&mut self, search_across_excerpts: bool) {
+        self.snapshot_mut().search_across_excerpts = search_across_excerpts;
+    }
+
+    /// Sets the length in bytes beyond which buffers are considered too large to decorate.
+    /// Their excerpts are still editable, but are yielded as plain text by
+    /// [`MultiBufferSnapshot::chunks`], without syntax highlighting or diagnostics.
+    pub fn set_large_buffer_threshold(&mut self, threshold: Option<usize>) {
+        if self.snapshot.borrow().large_buffer_threshold != threshold {
+            self.snapshot_mut().large_buffer_threshold = threshold;
+        }
+    }
+
+    /// Limits the length of excerpts inserted from now on. The content of longer excerpts
+    /// is truncated, and can be restored with [`MultiBuffer::expand_elided`].
+    pub fn set_max_excerpt_len(&mut self, max_excerpt_len: Option<usize>) {
+        self.max_excerpt_len = max_excerpt_len;
+    }
+
+    /// Controls whether excerpts are removed once edits delete all of their content, rather
+    /// than being left behind as blank lines. This is off by default, but is useful for views
+    /// such as search results, where an emptied excerpt no longer shows anything relevant.
+    pub fn set_remove_emptied_excerpts(&mut self, remove_emptied_excerpts: bool) {
+        self.remove_emptied_excerpts = remove_emptied_excerpts;
+    }
+
+    /// Controls whether excerpts inserted from now on grow to include text inserted exactly
+    /// at their edges. Excerpts are inclusive by default.
+    pub fn set_excerpt_edges(&mut self, excerpt_edges: ExcerptEdges) {
+        self.excerpt_edges = excerpt_edges;
+    }
+
+    /// Asks views of this multi-buffer to scroll the given position into view the next time
+    /// they lay out, instead of their newest selection.
+    pub fn set_autoscroll_anchor(&mut self, anchor: Anchor, cx: &mut ModelContext<Self>) {
+        self.autoscroll_anchor = Some(anchor);
+        self.autoscroll_request_count += 1;
+        cx.notify();
+    }
+
+    /// Returns the most recent autoscroll anchor, along with the number of times one was
+    /// set. Views remember the count of the last request they applied, so that every view
+    /// of the multi-buffer applies each request once.
+    pub fn autoscroll_request(&self) -> Option<(usize, Anchor)> {
+        Some((self.autoscroll_request_count, self.autoscroll_anchor?))
+    }
+
+    /// Stores the local selections, so that every view of this multi-buffer can observe
+    /// them, the same way remote selections are observed through the underlying buffers.
+    pub fn set_local_selections(
+        &mut self,
+        selections: Arc<[Selection<Anchor>]>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if Arc::ptr_eq(&self.snapshot.borrow().local_selections, &selections) {
+            return;
+        }
+        let mut snapshot = self.snapshot_mut();
+        snapshot.local_selections = selections;
+        snapshot.selections_update_count += 1;
+        drop(snapshot);
+        cx.emit(Event::LocalSelectionsChanged);
+    }
+
     pub fn symbols_containing<T: ToOffset>(
         &self,
         offset: T,
@@ -440,69 +984,70 @@ impl MultiBuffer {
             is_insertion: bool,
             original_indent_column: u32,
         }
+        // Only the edit that inserts text holds onto it. The other buffers touched by the same
+        // edit just have their text deleted, so they can share a single empty string.
+        let empty_str: Arc<str> = "".into();
         let mut buffer_edits: HashMap<BufferId, Vec<BufferEdit>> = Default::default();
         let mut edited_excerpt_ids = Vec::new();
         let mut cursor = snapshot.excerpts.cursor::<usize>();
+        // Edits usually arrive sorted, one per cursor, so we sweep forward through the excerpts
+        // instead of seeking from the root for every edit, and remember where the last edited
+        // excerpt starts in its buffer. The previous edit may have moved the cursor past the
+        // start of this one, so we only seek forward from where the cursor actually is.
+        let mut last_excerpt_start = None;
         for (ix, (range, new_text)) in edits.enumerate() {
             let new_text: Arc<str> = new_text.into();
             let original_indent_column = original_indent_columns.get(ix).copied().unwrap_or(0);
-            cursor.seek(&range.start, Bias::Right, &());
+            if range.start >= *cursor.start() {
+                cursor.seek_forward(&range.start, Bias::Right, &());
+            } else {
+                cursor.seek(&range.start, Bias::Right, &());
+            }
             if cursor.item().is_none() && range.start == *cursor.start() {
                 cursor.prev(&());
             }
             let start_excerpt = cursor.item().expect("start offset out of bounds");
-            let start_overshoot = range.start - cursor.start();
-            let buffer_start = start_excerpt
-                .range
-                .context
-                .start
-                .to_offset(&start_excerpt.buffer)
-                + start_overshoot;
-            edited_excerpt_ids.push(start_excerpt.id);
+            let start_excerpt_start = excerpt_buffer_start(start_excerpt, &mut last_excerpt_start);
+            let buffer_start = start_excerpt_start + range.start - cursor.start();
+            if edited_excerpt_ids.last() != Some(&start_excerpt.id) {
+                edited_excerpt_ids.push(start_excerpt.id);
+            }
 
-            cursor.seek(&range.end, Bias::Right, &());
-            if cursor.item().is_none() && range.end == *cursor.start() {
-                cursor.prev(&());
+            if range.end >= cursor.end(&()) {
+                cursor.seek_forward(&range.end, Bias::Right, &());
+                if cursor.item().is_none() && range.end == *cursor.start() {
+                    cursor.prev(&());
+                }
             }
             let end_excerpt = cursor.item().expect("end offset out of bounds");
             let end_overshoot = range.end - cursor.start();
-            let buffer_end = end_excerpt
-                .range
-                .context
-                .start
-                .to_offset(&end_excerpt.buffer)
-                + end_overshoot;
 
             if start_excerpt.id == end_excerpt.id {
                 buffer_edits
                     .entry(start_excerpt.buffer_id)
                     .or_insert(Vec::new())
                     .push(BufferEdit {
-                        range: buffer_start..buffer_end,
+                        range: buffer_start..start_excerpt_start + end_overshoot,
                         new_text,
                         is_insertion: true,
                         original_indent_column,
                     });
             } else {
                 edited_excerpt_ids.push(end_excerpt.id);
+                let end_excerpt_start = excerpt_buffer_start(end_excerpt, &mut last_excerpt_start);
                 let start_excerpt_range = buffer_start
                     ..start_excerpt
                         .range
                         .context
                         .end
                         .to_offset(&start_excerpt.buffer);
-                let end_excerpt_range = end_excerpt
-                    .range
-                    .context
-                    .start
-                    .to_offset(&end_excerpt.buffer)
-                    ..buffer_end;
+                let end_excerpt_range = end_excerpt_start..end_excerpt_start + end_overshoot;
                 buffer_edits
                     .entry(start_excerpt.buffer_id)
                     .or_insert(Vec::new())
                     .push(BufferEdit {
                         range: start_excerpt_range,
-                        new_text: new_text.clone(),
+                        new_text,
                         is_insertion: true,
                         original_indent_column,
                     });
@@ -511,14 +1056,15 @@ impl MultiBuffer {
                     .or_insert(Vec::new())
                     .push(BufferEdit {
                         range: end_excerpt_range,
-                        new_text: new_text.clone(),
+                        new_text: empty_str.clone(),
                         is_insertion: false,
                         original_indent_column,
                     });
 
-                cursor.seek(&range.start, Bias::Right, &());
-                cursor.next(&());
-                while let Some(excerpt) = cursor.item() {
+                let mut middle_excerpts = snapshot.excerpts.cursor::<usize>();
+                middle_excerpts.seek(&range.start, Bias::Right, &());
+                middle_excerpts.next(&());
+                while let Some(excerpt) = middle_excerpts.item() {
                     if excerpt.id == end_excerpt.id {
                         break;
                     }
@@ -527,12 +1073,26 @@ impl MultiBuffer {
                         .or_insert(Vec::new())
                         .push(BufferEdit {
                             range: excerpt.range.context.to_offset(&excerpt.buffer),
-                            new_text: new_text.clone(),
+                            new_text: empty_str.clone(),
                             is_insertion: false,
                             original_indent_column,
                         });
                     edited_excerpt_ids.push(excerpt.id);
-                    cursor.next(&());
+                    middle_excerpts.next(&());
+                }
+            }
+        }
+
+        fn excerpt_buffer_start(
+            excerpt: &Excerpt,
+            last_excerpt_start: &mut Option<(ExcerptId, usize)>,
+        ) -> usize {
+            match last_excerpt_start {
+                Some((id, offset)) if *id == excerpt.id => *offset,
+                _ => {
+                    let offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+                    *last_excerpt_start = Some((excerpt.id, offset));
+                    offset
                 }
             }
         }
@@ -543,12 +1103,18 @@ impl MultiBuffer {
         fn tail(
             this: &mut MultiBuffer,
             buffer_edits: HashMap<BufferId, Vec<BufferEdit>>,
+            empty_str: Arc<str>,
             autoindent_mode: Option<AutoindentMode>,
             edited_excerpt_ids: Vec<ExcerptId>,
             cx: &mut ModelContext<MultiBuffer>,
         ) {
             for (buffer_id, mut edits) in buffer_edits {
-                edits.sort_unstable_by_key(|edit| edit.range.start);
+                if !edits
+                    .windows(2)
+                    .all(|pair| pair[0].range.start <= pair[1].range.start)
+                {
+                    edits.sort_by_key(|edit| edit.range.start);
+                }
                 this.buffers.borrow()[&buffer_id]
                     .buffer
                     .update(cx, |buffer, cx| {
@@ -556,24 +1122,22 @@ impl MultiBuffer {
                         let mut insertions = Vec::new();
                         let mut original_indent_columns = Vec::new();
                         let mut deletions = Vec::new();
-                        let empty_str: Arc<str> = "".into();
                         while let Some(BufferEdit {
                             mut range,
-                            new_text,
+                            mut new_text,
                             mut is_insertion,
-                            original_indent_column,
+                            mut original_indent_column,
                         }) = edits.next()
                         {
-                            while let Some(BufferEdit {
-                                range: next_range,
-                                is_insertion: next_is_insertion,
-                                ..
-                            }) = edits.peek()
-                            {
-                                if range.end >= next_range.start {
-                                    range.end = cmp::max(next_range.end, range.end);
-                                    is_insertion |= *next_is_insertion;
-                                    edits.next();
+                            while let Some(next) = edits.peek() {
+                                if range.end >= next.range.start {
+                                    let next = edits.next().unwrap();
+                                    range.end = cmp::max(next.range.end, range.end);
+                                    if next.is_insertion && !is_insertion {
+                                        new_text = next.new_text;
+                                        original_indent_column = next.original_indent_column;
+                                        is_insertion = true;
+                                    }
                                 } else {
                                     break;
                                 }
@@ -584,7 +1148,7 @@ impl MultiBuffer {
                                 insertions.push((
                                     buffer.anchor_before(range.start)
                                         ..buffer.anchor_before(range.end),
-                                    new_text.clone(),
+                                    new_text,
                                 ));
                             } else if !range.is_empty() {
                                 deletions.push((
@@ -621,4803 +1185,9988 @@ impl MultiBuffer {
                 ids: edited_excerpt_ids,
             });
         }
-        tail(self, buffer_edits, autoindent_mode, edited_excerpt_ids, cx);
-    }
-
-    pub fn start_transaction(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
-        self.start_transaction_at(Instant::now(), cx)
+        tail(
+            self,
+            buffer_edits,
+            empty_str,
+            autoindent_mode,
+            edited_excerpt_ids,
+            cx,
+        );
     }
 
-    pub fn start_transaction_at(
+    /// Replaces the rows of a rectangular selection, as returned by
+    /// [`MultiBufferSnapshot::column_ranges`], with the corresponding texts. Rows that are
+    /// shorter than the selection are padded with spaces first, so that the inserted texts
+    /// line up. Use [`iter::repeat`] to insert the same text on every row.
+    pub fn edit_columns<T: AsRef<str>>(
         &mut self,
-        now: Instant,
+        column_ranges: &[ColumnRange],
+        texts: impl IntoIterator<Item = T>,
         cx: &mut ModelContext<Self>,
-    ) -> Option<TransactionId> {
-        if let Some(buffer) = self.as_singleton() {
-            return buffer.update(cx, |buffer, _| buffer.start_transaction_at(now));
-        }
-
-        for BufferState { buffer, .. } in self.buffers.borrow().values() {
-            buffer.update(cx, |buffer, _| buffer.start_transaction_at(now));
-        }
-        self.history.start_transaction(now)
-    }
-
-    pub fn end_transaction(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
-        self.end_transaction_at(Instant::now(), cx)
+    ) {
+        let edits = column_ranges
+            .iter()
+            .zip(texts)
+            .map(|(column_range, text)| {
+                let padding = " ".repeat(column_range.padding as usize);
+                (column_range.range.clone(), padding + text.as_ref())
+            })
+            .collect::<Vec<_>>();
+        self.edit(edits, None, cx);
     }
 
-    pub fn end_transaction_at(
+    /// Replaces every match of `regex` with `replacement`, which can refer to capture groups
+    /// using `$1` or `${name}` syntax. All of the replacements are applied in a single
+    /// transaction, and the ranges of the replaced text are returned.
+    pub fn replace_all(
         &mut self,
-        now: Instant,
+        regex: &Regex,
+        replacement: &str,
         cx: &mut ModelContext<Self>,
-    ) -> Option<TransactionId> {
-        if let Some(buffer) = self.as_singleton() {
-            return buffer.update(cx, |buffer, cx| buffer.end_transaction_at(now, cx));
+    ) -> Vec<Range<Anchor>> {
+        let snapshot = self.read(cx);
+        let mut search_ranges = Vec::new();
+        if snapshot.search_across_excerpts {
+            search_ranges.push(0..snapshot.len());
+        } else {
+            let mut cursor = snapshot.excerpts.cursor::<usize>();
+            cursor.next(&());
+            while let Some(excerpt) = cursor.item() {
+                let start = *cursor.start();
+                search_ranges.push(start..start + excerpt.text_summary.len);
+                cursor.next(&());
+            }
         }
 
-        let mut buffer_transactions = HashMap::default();
-        for BufferState { buffer, .. } in self.buffers.borrow().values() {
-            if let Some(transaction_id) =
-                buffer.update(cx, |buffer, cx| buffer.end_transaction_at(now, cx))
-            {
-                buffer_transactions.insert(buffer.read(cx).remote_id(), transaction_id);
+        let mut edits = Vec::new();
+        for search_range in search_ranges {
+            let text = snapshot
+                .text_for_range(search_range.clone())
+                .collect::<String>();
+            for captures in regex.captures_iter(&text) {
+                let mat = captures.get(0).unwrap();
+                let mut new_text = String::new();
+                captures.expand(replacement, &mut new_text);
+                edits.push((
+                    search_range.start + mat.start()..search_range.start + mat.end(),
+                    new_text,
+                ));
             }
         }
-
-        if self.history.end_transaction(now, buffer_transactions) {
-            let transaction_id = self.history.group().unwrap();
-            Some(transaction_id)
-        } else {
-            None
+        if edits.is_empty() {
+            return Vec::new();
         }
+
+        // The new text is inserted before the text it replaces, so an anchor biased towards the
+        // end of each replaced range ends up after its new text, even when the next match starts
+        // right there. Empty matches have no replaced text to attach to, so they're biased right.
+        let replaced_ranges = edits
+            .iter()
+            .map(|(range, _)| {
+                let end = if range.is_empty() {
+                    snapshot.anchor_after(range.end)
+                } else {
+                    snapshot.anchor_before(range.end)
+                };
+                snapshot.anchor_before(range.start)..end
+            })
+            .collect::<Vec<_>>();
+        drop(snapshot);
+
+        self.transact(cx, |this, cx| this.edit(edits, None, cx));
+        replaced_ranges
     }
 
-    pub fn merge_transactions(
+    /// Applies a unified diff to the buffers in this multi-buffer as a single transaction.
+    ///
+    /// Each file in the diff is matched against the paths of the buffers being edited. If the
+    /// diff doesn't contain any file headers, it is applied to the singleton buffer. Hunks whose
+    /// old text can't be located in the target buffer are skipped and returned.
+    pub fn apply_diff(
         &mut self,
-        transaction: TransactionId,
-        destination: TransactionId,
+        patch_text: &str,
         cx: &mut ModelContext<Self>,
-    ) {
-        if let Some(buffer) = self.as_singleton() {
-            buffer.update(cx, |buffer, _| {
-                buffer.merge_transactions(transaction, destination)
-            });
-        } else {
-            if let Some(transaction) = self.history.forget(transaction) {
-                if let Some(destination) = self.history.transaction_mut(destination) {
-                    for (buffer_id, buffer_transaction_id) in transaction.buffer_transactions {
-                        if let Some(destination_buffer_transaction_id) =
-                            destination.buffer_transactions.get(&buffer_id)
-                        {
-                            if let Some(state) = self.buffers.borrow().get(&buffer_id) {
-                                state.buffer.update(cx, |buffer, _| {
-                                    buffer.merge_transactions(
-                                        buffer_transaction_id,
-                                        *destination_buffer_transaction_id,
-                                    )
-                                });
-                            }
-                        } else {
-                            destination
-                                .buffer_transactions
-                                .insert(buffer_id, buffer_transaction_id);
-                        }
+    ) -> Result<Vec<FailedHunk>> {
+        let patches = parse_unified_diff(patch_text)?;
+        let mut failed_hunks = Vec::new();
+        let mut buffer_edits = Vec::new();
+        for patch in patches {
+            let buffer = match patch.path.as_ref() {
+                Some(path) => self.buffers.borrow().values().find_map(|state| {
+                    let file = state.buffer.read(cx).file()?;
+                    (file.path().as_ref() == path || file.full_path(cx).ends_with(path))
+                        .then(|| state.buffer.clone())
+                }),
+                None => self.as_singleton(),
+            };
+            let Some(buffer) = buffer else {
+                failed_hunks.extend(patch.hunks.into_iter().map(|hunk| FailedHunk {
+                    path: patch.path.clone(),
+                    hunk,
+                }));
+                continue;
+            };
+
+            let buffer_snapshot = buffer.read(cx).snapshot();
+            let buffer_text = buffer_snapshot.text();
+            let mut edits = Vec::new();
+            let mut prev_end = 0;
+            for hunk in patch.hunks {
+                let expected_start = buffer_snapshot.point_to_offset(
+                    buffer_snapshot.clip_point(Point::new(hunk.old_start_row, 0), Bias::Left),
+                );
+                let start = if buffer_text[expected_start..].starts_with(&hunk.old_text) {
+                    Some(expected_start)
+                } else {
+                    buffer_text
+                        .match_indices(&hunk.old_text)
+                        .map(|(ix, _)| ix)
+                        .filter(|ix| *ix >= prev_end)
+                        .min_by_key(|ix| ix.abs_diff(expected_start))
+                };
+
+                match start {
+                    Some(start) if start >= prev_end => {
+                        let end = start + hunk.old_text.len();
+                        prev_end = end;
+                        edits.push((start..end, hunk.new_text));
                     }
+                    _ => failed_hunks.push(FailedHunk {
+                        path: patch.path.clone(),
+                        hunk,
+                    }),
                 }
             }
+            buffer_edits.push((buffer, edits));
         }
+
+        self.transact(cx, |_, cx| {
+            for (buffer, edits) in buffer_edits {
+                buffer.update(cx, |buffer, cx| buffer.edit(edits, None, cx));
+            }
+        });
+
+        Ok(failed_hunks)
     }
 
-    pub fn finalize_last_transaction(&mut self, cx: &mut ModelContext<Self>) {
-        self.history.finalize_last_transaction();
-        for BufferState { buffer, .. } in self.buffers.borrow().values() {
-            buffer.update(cx, |buffer, _| {
-                buffer.finalize_last_transaction();
-            });
+    /// Captures the current contents of every buffer in the multi-buffer, so that they
+    /// can later be restored with [`MultiBuffer::revert_to_checkpoint`].
+    pub fn checkpoint(&self, cx: &AppContext) -> MultiBufferCheckpoint {
+        MultiBufferCheckpoint {
+            buffers: self
+                .buffers
+                .borrow()
+                .iter()
+                .map(|(buffer_id, state)| (*buffer_id, state.buffer.read(cx).text_snapshot()))
+                .collect(),
         }
     }
 
-    pub fn push_transaction<'a, T>(&mut self, buffer_transactions: T, cx: &mut ModelContext<Self>)
-    where
-        T: IntoIterator<Item = (&'a Model<Buffer>, &'a language::Transaction)>,
-    {
-        self.history
-            .push_transaction(buffer_transactions, Instant::now(), cx);
-        self.history.finalize_last_transaction();
-    }
-
-    pub fn group_until_transaction(
+    /// Rolls every buffer back to its contents at the given checkpoint in a single
+    /// transaction, which can itself be undone. This also reverts edits made by other
+    /// collaborators since the checkpoint. Buffers that were added to the multi-buffer
+    /// after the checkpoint are left unchanged.
+    pub fn revert_to_checkpoint(
         &mut self,
-        transaction_id: TransactionId,
+        checkpoint: &MultiBufferCheckpoint,
         cx: &mut ModelContext<Self>,
-    ) {
-        if let Some(buffer) = self.as_singleton() {
-            buffer.update(cx, |buffer, _| {
-                buffer.group_until_transaction(transaction_id)
-            });
-        } else {
-            self.history.group_until(transaction_id);
+    ) -> Option<TransactionId> {
+        let mut buffer_edits = Vec::new();
+        for (buffer_id, old_snapshot) in &checkpoint.buffers {
+            let Some(buffer) = self
+                .buffers
+                .borrow()
+                .get(buffer_id)
+                .map(|state| state.buffer.clone())
+            else {
+                continue;
+            };
+            let edits = buffer
+                .read(cx)
+                .edits_since::<usize>(old_snapshot.version())
+                .map(|edit| {
+                    let old_text = old_snapshot.text_for_range(edit.old).collect::<String>();
+                    (edit.new, old_text)
+                })
+                .collect::<Vec<_>>();
+            if !edits.is_empty() {
+                buffer_edits.push((buffer, edits));
+            }
+        }
+
+        if buffer_edits.is_empty() {
+            return None;
         }
+
+        // Keep the revert separate from surrounding edits in the undo history.
+        self.finalize_last_transaction(cx);
+        let transaction_id = self.transact(cx, |_, cx| {
+            for (buffer, edits) in buffer_edits {
+                buffer.update(cx, |buffer, cx| buffer.edit(edits, None, cx));
+            }
+        });
+        self.finalize_last_transaction(cx);
+        transaction_id
     }
 
-    pub fn set_active_selections(
+    /// Runs `update` inside a transaction, returning the id of the transaction if any edits
+    /// were made. The transaction is always ended when `update` returns, so early returns
+    /// within it can't leave the transaction open.
+    pub fn transact(
         &mut self,
-        selections: &[Selection<Anchor>],
-        line_mode: bool,
-        cursor_shape: CursorShape,
         cx: &mut ModelContext<Self>,
-    ) {
-        let mut selections_by_buffer: HashMap<BufferId, Vec<Selection<text::Anchor>>> =
-            Default::default();
-        let snapshot = self.read(cx);
-        let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
-        for selection in selections {
-            let start_locator = snapshot.excerpt_locator_for_id(selection.start.excerpt_id);
-            let end_locator = snapshot.excerpt_locator_for_id(selection.end.excerpt_id);
+        update: impl FnOnce(&mut Self, &mut ModelContext<Self>),
+    ) -> Option<TransactionId> {
+        self.start_transaction_at(Instant::now(), cx);
+        let mut transaction = TransactionGuard {
+            multibuffer: self,
+            cx,
+            ended: false,
+        };
+        update(transaction.multibuffer, transaction.cx);
+        transaction.end()
+    }
 
-            cursor.seek(&Some(start_locator), Bias::Left, &());
-            while let Some(excerpt) = cursor.item() {
-                if excerpt.locator > *end_locator {
-                    break;
-                }
+    pub fn start_transaction(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        self.start_transaction_at(Instant::now(), cx)
+    }
 
-                let mut start = excerpt.range.context.start;
-                let mut end = excerpt.range.context.end;
-                if excerpt.id == selection.start.excerpt_id {
-                    start = selection.start.text_anchor;
-                }
-                if excerpt.id == selection.end.excerpt_id {
-                    end = selection.end.text_anchor;
-                }
-                selections_by_buffer
-                    .entry(excerpt.buffer_id)
-                    .or_default()
-                    .push(Selection {
-                        id: selection.id,
-                        start,
-                        end,
-                        reversed: selection.reversed,
-                        goal: selection.goal,
-                    });
+    /// Starts a transaction with a human-readable label, such as "Format Document", that is
+    /// reported by [`MultiBuffer::undo_stack_summaries`].
+    pub fn start_transaction_with_label(
+        &mut self,
+        label: impl Into<SharedString>,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<TransactionId> {
+        let transaction_id = self.start_transaction(cx)?;
+        self.transaction_labels
+            .entry(transaction_id)
+            .or_insert_with(|| label.into());
+        Some(transaction_id)
+    }
 
-                cursor.next(&());
-            }
-        }
+    pub fn set_transaction_label(
+        &mut self,
+        transaction_id: TransactionId,
+        label: impl Into<SharedString>,
+    ) {
+        self.transaction_labels.insert(transaction_id, label.into());
+    }
 
-        for (buffer_id, buffer_state) in self.buffers.borrow().iter() {
-            if !selections_by_buffer.contains_key(buffer_id) {
-                buffer_state
-                    .buffer
-                    .update(cx, |buffer, cx| buffer.remove_active_selections(cx));
+    pub fn start_transaction_at(
+        &mut self,
+        now: Instant,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<TransactionId> {
+        let transaction_id = if let Some(buffer) = self.as_singleton() {
+            buffer.update(cx, |buffer, _| buffer.start_transaction_at(now))
+        } else {
+            for BufferState { buffer, .. } in self.buffers.borrow().values() {
+                buffer.update(cx, |buffer, _| buffer.start_transaction_at(now));
             }
-        }
+            self.history.start_transaction(now)
+        };
 
-        for (buffer_id, mut selections) in selections_by_buffer {
-            self.buffers.borrow()[&buffer_id]
-                .buffer
-                .update(cx, |buffer, cx| {
-                    selections.sort_unstable_by(|a, b| a.start.cmp(&b.start, buffer));
-                    let mut selections = selections.into_iter().peekable();
-                    let merged_selections = Arc::from_iter(iter::from_fn(|| {
-                        let mut selection = selections.next()?;
-                        while let Some(next_selection) = selections.peek() {
-                            if selection.end.cmp(&next_selection.start, buffer).is_ge() {
-                                let next_selection = selections.next().unwrap();
-                                if next_selection.end.cmp(&selection.end, buffer).is_ge() {
-                                    selection.end = next_selection.end;
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-                        Some(selection)
-                    }));
-                    buffer.set_active_selections(merged_selections, line_mode, cursor_shape, cx);
-                });
+        if let Some(transaction_id) = transaction_id {
+            let selections = self.snapshot.borrow().local_selections.clone();
+            self.selections_by_transaction
+                .entry(transaction_id)
+                .or_insert((selections, None));
         }
+        transaction_id
     }
 
-    pub fn remove_active_selections(&mut self, cx: &mut ModelContext<Self>) {
-        for buffer in self.buffers.borrow().values() {
-            buffer
-                .buffer
-                .update(cx, |buffer, cx| buffer.remove_active_selections(cx));
-        }
+    pub fn end_transaction(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        self.end_transaction_at(Instant::now(), cx)
     }
 
-    pub fn undo(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
-        let mut transaction_id = None;
-        if let Some(buffer) = self.as_singleton() {
-            transaction_id = buffer.update(cx, |buffer, cx| buffer.undo(cx));
+    pub fn end_transaction_at(
+        &mut self,
+        now: Instant,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<TransactionId> {
+        let transaction_id = if let Some(buffer) = self.as_singleton() {
+            buffer.update(cx, |buffer, cx| buffer.end_transaction_at(now, cx))
         } else {
-            while let Some(transaction) = self.history.pop_undo() {
-                let mut undone = false;
-                for (buffer_id, buffer_transaction_id) in &mut transaction.buffer_transactions {
-                    if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
-                        undone |= buffer.update(cx, |buffer, cx| {
-                            let undo_to = *buffer_transaction_id;
-                            if let Some(entry) = buffer.peek_undo_stack() {
-                                *buffer_transaction_id = entry.transaction_id();
-                            }
-                            buffer.undo_to_transaction(undo_to, cx)
-                        });
-                    }
+            let mut buffer_transactions = HashMap::default();
+            for BufferState { buffer, .. } in self.buffers.borrow().values() {
+                if let Some(transaction_id) =
+                    buffer.update(cx, |buffer, cx| buffer.end_transaction_at(now, cx))
+                {
+                    buffer_transactions.insert(buffer.read(cx).remote_id(), transaction_id);
                 }
+            }
 
-                if undone {
-                    transaction_id = Some(transaction.id);
-                    break;
-                }
+            if self.history.end_transaction(now, buffer_transactions) {
+                let (transaction_id, merges) = self.history.group();
+                self.merge_buffer_transactions(merges, cx);
+                self.evict_oldest_transactions(cx);
+                transaction_id
+            } else {
+                None
             }
-        }
+        };
 
         if let Some(transaction_id) = transaction_id {
-            cx.emit(Event::TransactionUndone { transaction_id });
+            if let Some((_, selections_after)) =
+                self.selections_by_transaction.get_mut(&transaction_id)
+            {
+                *selections_after = Some(self.snapshot.borrow().local_selections.clone());
+            }
         }
-
+        self.prune_transaction_metadata(cx);
         transaction_id
     }
 
-    pub fn redo(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
-        if let Some(buffer) = self.as_singleton() {
-            return buffer.update(cx, |buffer, cx| buffer.redo(cx));
-        }
-
-        while let Some(transaction) = self.history.pop_redo() {
-            let mut redone = false;
-            for (buffer_id, buffer_transaction_id) in &mut transaction.buffer_transactions {
-                if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
-                    redone |= buffer.update(cx, |buffer, cx| {
-                        let redo_to = *buffer_transaction_id;
-                        if let Some(entry) = buffer.peek_redo_stack() {
-                            *buffer_transaction_id = entry.transaction_id();
-                        }
-                        buffer.redo_to_transaction(redo_to, cx)
-                    });
-                }
+    /// Discards the selections and labels of transactions that are no longer in the undo
+    /// history, such as transactions that made no edits, were grouped into an earlier one,
+    /// or were cleared from the redo stack.
+    fn prune_transaction_metadata(&mut self, cx: &AppContext) {
+        let transaction_ids = if let Some(buffer) = self.as_singleton() {
+            let buffer = buffer.read(cx);
+            let history_len = buffer.undo_stack().len() + buffer.redo_stack().len();
+            // Only scan the history once there are more entries than it could account for,
+            // which keeps the number of stale entries bounded by the size of the history.
+            if self.selections_by_transaction.len() <= history_len {
+                return;
             }
-
-            if redone {
-                return Some(transaction.id);
+            buffer
+                .undo_stack()
+                .iter()
+                .chain(buffer.redo_stack())
+                .map(|entry| entry.transaction_id())
+                .collect::<HashSet<_>>()
+        } else {
+            let history_len = self.history.undo_stack.len() + self.history.redo_stack.len();
+            if self.selections_by_transaction.len() <= history_len {
+                return;
             }
-        }
+            self.history
+                .undo_stack
+                .iter()
+                .chain(&self.history.redo_stack)
+                .map(|transaction| transaction.id)
+                .collect::<HashSet<_>>()
+        };
+        self.selections_by_transaction
+            .retain(|transaction_id, _| transaction_ids.contains(transaction_id));
+        self.transaction_labels
+            .retain(|transaction_id, _| transaction_ids.contains(transaction_id));
+    }
 
-        None
+    /// Returns the local selections that were active before and after the given transaction.
+    #[allow(clippy::type_complexity)]
+    pub fn selections_for_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Option<&(Arc<[Selection<Anchor>]>, Option<Arc<[Selection<Anchor>]>>)> {
+        self.selections_by_transaction.get(&transaction_id)
     }
 
-    pub fn undo_transaction(&mut self, transaction_id: TransactionId, cx: &mut ModelContext<Self>) {
+    /// Removes a transaction from the undo history, along with the corresponding
+    /// transactions of the underlying buffers, without undoing its edits.
+    pub fn forget_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        cx: &mut ModelContext<Self>,
+    ) {
         if let Some(buffer) = self.as_singleton() {
-            buffer.update(cx, |buffer, cx| buffer.undo_transaction(transaction_id, cx));
-        } else if let Some(transaction) = self.history.remove_from_undo(transaction_id) {
-            for (buffer_id, transaction_id) in &transaction.buffer_transactions {
-                if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
-                    buffer.update(cx, |buffer, cx| {
-                        buffer.undo_transaction(*transaction_id, cx)
+            buffer.update(cx, |buffer, _| buffer.forget_transaction(transaction_id));
+        } else if let Some(transaction) = self.history.forget(transaction_id) {
+            for (buffer_id, buffer_transaction_id) in transaction.buffer_transactions {
+                if let Some(state) = self.buffers.borrow().get(&buffer_id) {
+                    state.buffer.update(cx, |buffer, _| {
+                        buffer.forget_transaction(buffer_transaction_id)
                     });
                 }
             }
         }
+        self.selections_by_transaction.remove(&transaction_id);
+        self.transaction_labels.remove(&transaction_id);
     }
 
-    pub fn stream_excerpts_with_context_lines(
+    pub fn merge_transactions(
         &mut self,
-        buffer: Model<Buffer>,
-        ranges: Vec<Range<text::Anchor>>,
-        context_line_count: u32,
+        transaction: TransactionId,
+        destination: TransactionId,
         cx: &mut ModelContext<Self>,
-    ) -> mpsc::Receiver<Range<Anchor>> {
-        let (buffer_id, buffer_snapshot) =
-            buffer.update(cx, |buffer, _| (buffer.remote_id(), buffer.snapshot()));
-
-        let (mut tx, rx) = mpsc::channel(256);
-        cx.spawn(move |this, mut cx| async move {
-            let mut excerpt_ranges = Vec::new();
-            let mut range_counts = Vec::new();
-            cx.background_executor()
-                .scoped(|scope| {
-                    scope.spawn(async {
-                        let (ranges, counts) =
-                            build_excerpt_ranges(&buffer_snapshot, &ranges, context_line_count);
-                        excerpt_ranges = ranges;
-                        range_counts = counts;
-                    });
-                })
-                .await;
-
-            let mut ranges = ranges.into_iter();
-            let mut range_counts = range_counts.into_iter();
-            for excerpt_ranges in excerpt_ranges.chunks(100) {
-                let excerpt_ids = match this.update(&mut cx, |this, cx| {
-                    this.push_excerpts(buffer.clone(), excerpt_ranges.iter().cloned(), cx)
-                }) {
-                    Ok(excerpt_ids) => excerpt_ids,
-                    Err(_) => return,
-                };
-
-                for (excerpt_id, range_count) in excerpt_ids.into_iter().zip(range_counts.by_ref())
-                {
-                    for range in ranges.by_ref().take(range_count) {
-                        let start = Anchor {
-                            buffer_id: Some(buffer_id),
-                            excerpt_id: excerpt_id.clone(),
-                            text_anchor: range.start,
-                        };
-                        let end = Anchor {
-                            buffer_id: Some(buffer_id),
-                            excerpt_id: excerpt_id.clone(),
-                            text_anchor: range.end,
-                        };
-                        if tx.send(start..end).await.is_err() {
-                            break;
+    ) {
+        if let Some(buffer) = self.as_singleton() {
+            buffer.update(cx, |buffer, _| {
+                buffer.merge_transactions(transaction, destination)
+            });
+        } else {
+            if let Some(transaction) = self.history.forget(transaction) {
+                if let Some(destination) = self.history.transaction_mut(destination) {
+                    for (buffer_id, buffer_transaction_id) in transaction.buffer_transactions {
+                        if let Some(destination_buffer_transaction_id) =
+                            destination.buffer_transactions.get(&buffer_id)
+                        {
+                            if let Some(state) = self.buffers.borrow().get(&buffer_id) {
+                                state.buffer.update(cx, |buffer, _| {
+                                    buffer.merge_transactions(
+                                        buffer_transaction_id,
+                                        *destination_buffer_transaction_id,
+                                    )
+                                });
+                            }
+                        } else {
+                            destination
+                                .buffer_transactions
+                                .insert(buffer_id, buffer_transaction_id);
                         }
                     }
                 }
             }
-        })
-        .detach();
-
-        rx
+        }
     }
 
-    pub fn push_excerpts<O>(
+    /// Prevents the last transaction from being grouped with any subsequent transactions,
+    /// even if they occur within the undo grouping interval. Returns the id of the
+    /// finalized transaction.
+    pub fn finalize_last_transaction(
         &mut self,
-        buffer: Model<Buffer>,
-        ranges: impl IntoIterator<Item = ExcerptRange<O>>,
         cx: &mut ModelContext<Self>,
-    ) -> Vec<ExcerptId>
-    where
-        O: text::ToOffset,
-    {
-        self.insert_excerpts_after(ExcerptId::max(), buffer, ranges, cx)
+    ) -> Option<TransactionId> {
+        let mut transaction_id = self.history.finalize_last_transaction();
+        for BufferState { buffer, .. } in self.buffers.borrow().values() {
+            let buffer_transaction_id = buffer.update(cx, |buffer, _| {
+                buffer
+                    .finalize_last_transaction()
+                    .map(|transaction| transaction.id)
+            });
+            if self.singleton {
+                transaction_id = buffer_transaction_id;
+            }
+        }
+        transaction_id
     }
 
-    pub fn push_excerpts_with_context_lines<O>(
+    pub fn push_transaction<'a, T>(
         &mut self,
-        buffer: Model<Buffer>,
-        ranges: Vec<Range<O>>,
-        context_line_count: u32,
+        buffer_transactions: T,
         cx: &mut ModelContext<Self>,
-    ) -> Vec<Range<Anchor>>
+    ) -> Option<TransactionId>
     where
-        O: text::ToPoint + text::ToOffset,
+        T: IntoIterator<Item = (&'a Model<Buffer>, &'a language::Transaction)>,
     {
-        let buffer_id = buffer.read(cx).remote_id();
-        let buffer_snapshot = buffer.read(cx).snapshot();
-        let (excerpt_ranges, range_counts) =
-            build_excerpt_ranges(&buffer_snapshot, &ranges, context_line_count);
-
-        let excerpt_ids = self.push_excerpts(buffer, excerpt_ranges, cx);
-
-        let mut anchor_ranges = Vec::new();
-        let mut ranges = ranges.into_iter();
-        for (excerpt_id, range_count) in excerpt_ids.into_iter().zip(range_counts.into_iter()) {
-            anchor_ranges.extend(ranges.by_ref().take(range_count).map(|range| {
-                let start = Anchor {
-                    buffer_id: Some(buffer_id),
-                    excerpt_id: excerpt_id.clone(),
-                    text_anchor: buffer_snapshot.anchor_after(range.start),
-                };
-                let end = Anchor {
-                    buffer_id: Some(buffer_id),
-                    excerpt_id: excerpt_id.clone(),
-                    text_anchor: buffer_snapshot.anchor_after(range.end),
-                };
-                start..end
-            }))
-        }
-        anchor_ranges
+        self.push_transaction_at(buffer_transactions, Instant::now(), cx)
     }
 
-    pub fn insert_excerpts_after<O>(
+    /// Registers transactions that were performed directly on the underlying buffers
+    /// (for example, by formatting or by a language server) as a single transaction in
+    /// this multi-buffer's undo history.
+    ///
+    /// Singleton multi-buffers use their buffer's history directly, so this does nothing
+    /// for them.
+    pub fn push_transaction_at<'a, T>(
         &mut self,
-        prev_excerpt_id: ExcerptId,
-        buffer: Model<Buffer>,
-        ranges: impl IntoIterator<Item = ExcerptRange<O>>,
+        buffer_transactions: T,
+        now: Instant,
         cx: &mut ModelContext<Self>,
-    ) -> Vec<ExcerptId>
+    ) -> Option<TransactionId>
     where
-        O: text::ToOffset,
+        T: IntoIterator<Item = (&'a Model<Buffer>, &'a language::Transaction)>,
     {
-        let mut ids = Vec::new();
-        let mut next_excerpt_id =
-            if let Some(last_entry) = self.snapshot.borrow().excerpt_ids.last() {
-                last_entry.id.0 + 1
-            } else {
-                1
-            };
-        self.insert_excerpts_with_ids_after(
-            prev_excerpt_id,
-            buffer,
-            ranges.into_iter().map(|range| {
-                let id = ExcerptId(post_inc(&mut next_excerpt_id));
-                ids.push(id);
-                (id, range)
-            }),
-            cx,
-        );
-        ids
+        if self.singleton {
+            return None;
+        }
+
+        let transaction_id = self.history.push_transaction(buffer_transactions, now, cx);
+        self.history.finalize_last_transaction();
+        self.evict_oldest_transactions(cx);
+        transaction_id
     }
 
-    pub fn insert_excerpts_with_ids_after<O>(
+    /// Limits the number of transactions kept on the undo stack. When the limit is
+    /// exceeded, the oldest transactions are forgotten, along with the corresponding
+    /// transactions in the underlying buffers.
+    ///
+    /// Singleton multi-buffers use their buffer's history directly, so this has no
+    /// effect on them.
+    pub fn set_max_undo_transactions(
         &mut self,
-        prev_excerpt_id: ExcerptId,
-        buffer: Model<Buffer>,
-        ranges: impl IntoIterator<Item = (ExcerptId, ExcerptRange<O>)>,
+        max_transactions: Option<usize>,
         cx: &mut ModelContext<Self>,
-    ) where
-        O: text::ToOffset,
-    {
-        assert_eq!(self.history.transaction_depth, 0);
-        let mut ranges = ranges.into_iter().peekable();
-        if ranges.peek().is_none() {
-            return Default::default();
-        }
+    ) {
+        self.history.max_transactions = max_transactions;
+        self.evict_oldest_transactions(cx);
+    }
 
-        self.sync(cx);
+    pub fn undo_stack_len(&self) -> usize {
+        self.history.undo_stack.len()
+    }
 
-        let buffer_id = buffer.read(cx).remote_id();
-        let buffer_snapshot = buffer.read(cx).snapshot();
+    pub fn redo_stack_len(&self) -> usize {
+        self.history.redo_stack.len()
+    }
 
-        let mut buffers = self.buffers.borrow_mut();
-        let buffer_state = buffers.entry(buffer_id).or_insert_with(|| BufferState {
-            last_version: buffer_snapshot.version().clone(),
-            last_parse_count: buffer_snapshot.parse_count(),
-            last_selections_update_count: buffer_snapshot.selections_update_count(),
-            last_diagnostics_update_count: buffer_snapshot.diagnostics_update_count(),
-            last_file_update_count: buffer_snapshot.file_update_count(),
-            last_git_diff_update_count: buffer_snapshot.git_diff_update_count(),
-            excerpts: Default::default(),
-            _subscriptions: [
-                cx.observe(&buffer, |_, _, cx| cx.notify()),
-                cx.subscribe(&buffer, Self::on_buffer_event),
-            ],
-            buffer: buffer.clone(),
-        });
+    /// Describes the transactions that can be undone, most recent first.
+    pub fn undo_stack_summaries(&self, cx: &AppContext) -> Vec<TransactionSummary> {
+        let transaction_ids = if let Some(buffer) = self.as_singleton() {
+            let buffer = buffer.read(cx);
+            buffer
+                .undo_stack()
+                .iter()
+                .rev()
+                .map(|entry| entry.transaction_id())
+                .collect::<Vec<_>>()
+        } else {
+            self.history
+                .undo_stack
+                .iter()
+                .rev()
+                .map(|transaction| transaction.id)
+                .collect()
+        };
+        self.transaction_summaries(transaction_ids)
+    }
 
-        let mut snapshot = self.snapshot.borrow_mut();
+    /// Describes the transactions that can be redone, most recently undone first.
+    pub fn redo_stack_summaries(&self, cx: &AppContext) -> Vec<TransactionSummary> {
+        let transaction_ids = if let Some(buffer) = self.as_singleton() {
+            let buffer = buffer.read(cx);
+            buffer
+                .redo_stack()
+                .iter()
+                .rev()
+                .map(|entry| entry.transaction_id())
+                .collect::<Vec<_>>()
+        } else {
+            self.history
+                .redo_stack
+                .iter()
+                .rev()
+                .map(|transaction| transaction.id)
+                .collect()
+        };
+        self.transaction_summaries(transaction_ids)
+    }
 
-        let mut prev_locator = snapshot.excerpt_locator_for_id(prev_excerpt_id).clone();
-        let mut new_excerpt_ids = mem::take(&mut snapshot.excerpt_ids);
-        let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
-        let mut new_excerpts = cursor.slice(&prev_locator, Bias::Right, &());
-        prev_locator = cursor.start().unwrap_or(Locator::min_ref()).clone();
+    fn transaction_summaries(
+        &self,
+        transaction_ids: Vec<TransactionId>,
+    ) -> Vec<TransactionSummary> {
+        transaction_ids
+            .into_iter()
+            .map(|id| TransactionSummary {
+                id,
+                label: self.transaction_labels.get(&id).cloned(),
+            })
+            .collect()
+    }
 
-        let edit_start = new_excerpts.summary().text.len;
-        new_excerpts.update_last(
-            |excerpt| {
-                excerpt.has_trailing_newline = true;
-            },
-            &(),
-        );
+    /// Serializes the undo and redo stacks, so that they can be restored with
+    /// [`MultiBuffer::restore_history`]. Transactions in buffers without a file are omitted,
+    /// except in singleton multi-buffers, whose history is their buffer's history. That history
+    /// is serialized along with its edits, so that it can be recreated after a reload.
+    pub fn serialize_history(&self, cx: &AppContext) -> SerializedHistory {
+        let snapshot = self.read(cx);
+        let serialize_selections = |selections: &[Selection<Anchor>]| {
+            selections
+                .iter()
+                .map(|selection| SerializedSelection {
+                    start: selection.start.serialize(&snapshot),
+                    end: selection.end.serialize(&snapshot),
+                    reversed: selection.reversed,
+                })
+                .collect::<Vec<_>>()
+        };
+        let serialize_transaction =
+            |id: TransactionId, buffer_transactions: Vec<(PathBuf, SerializedTransactionId)>| {
+                let selections = self.selections_by_transaction.get(&id);
+                SerializedTransaction {
+                    id: id.into(),
+                    buffer_transactions,
+                    label: self.transaction_labels.get(&id).map(ToString::to_string),
+                    selections_before: selections
+                        .map(|(before, _)| serialize_selections(&before[..]))
+                        .unwrap_or_default(),
+                    selections_after: selections
+                        .and_then(|(_, after)| Some(serialize_selections(&after.as_ref()?[..]))),
+                    edits: Vec::new(),
+                }
+            };
 
-        let next_locator = if let Some(excerpt) = cursor.item() {
-            excerpt.locator.clone()
-        } else {
-            Locator::max()
+        if let Some(buffer) = self.as_singleton() {
+            let history = buffer.read(cx).serialize_history();
+            let serialize_stack = |stack: Vec<TransactionEdits>| {
+                stack
+                    .into_iter()
+                    .map(|transaction| SerializedTransaction {
+                        edits: transaction
+                            .edits
+                            .into_iter()
+                            .map(|(range, text)| (range, text.to_string()))
+                            .collect(),
+                        ..serialize_transaction(transaction.id, Vec::new())
+                    })
+                    .collect()
+            };
+            return SerializedHistory {
+                undo_stack: serialize_stack(history.undo_stack),
+                redo_stack: serialize_stack(history.redo_stack),
+                fingerprint: Some(history.fingerprint.to_hex()),
+            };
+        }
+
+        let buffers = self.buffers.borrow();
+        let serialize_stack = |stack: &[Transaction]| {
+            stack
+                .iter()
+                .filter_map(|transaction| {
+                    let buffer_transactions = transaction
+                        .buffer_transactions
+                        .iter()
+                        .filter_map(|(buffer_id, buffer_transaction_id)| {
+                            let file = buffers.get(buffer_id)?.buffer.read(cx).file()?;
+                            Some((
+                                file.path().to_path_buf(),
+                                SerializedTransactionId::from(*buffer_transaction_id),
+                            ))
+                        })
+                        .collect::<Vec<_>>();
+                    if buffer_transactions.is_empty() {
+                        None
+                    } else {
+                        Some(serialize_transaction(transaction.id, buffer_transactions))
+                    }
+                })
+                .collect()
         };
+        SerializedHistory {
+            undo_stack: serialize_stack(&self.history.undo_stack),
+            redo_stack: serialize_stack(&self.history.redo_stack),
+            fingerprint: None,
+        }
+    }
 
-        let mut excerpts = Vec::new();
-        while let Some((id, range)) = ranges.next() {
-            let locator = Locator::between(&prev_locator, &next_locator);
-            if let Err(ix) = buffer_state.excerpts.binary_search(&locator) {
-                buffer_state.excerpts.insert(ix, locator.clone());
+    /// Replaces the undo and redo stacks with the given serialized history, along with the
+    /// labels and selections of its transactions. References to buffers that aren't part of
+    /// this multi-buffer are dropped. A singleton multi-buffer recreates its buffer's history
+    /// if the buffer has none and its text hasn't changed, and otherwise only restores the
+    /// transactions that are still in its buffer's history.
+    pub fn restore_history(&mut self, history: SerializedHistory, cx: &mut ModelContext<Self>) {
+        assert_eq!(self.history.transaction_depth, 0);
+
+        let mut restored = Vec::new();
+        if let Some(buffer) = self.as_singleton() {
+            if let Some(fingerprint) = history
+                .fingerprint
+                .as_deref()
+                .and_then(|fingerprint| RopeFingerprint::from_hex(fingerprint).ok())
+            {
+                let deserialize_stack = |stack: &[SerializedTransaction]| {
+                    stack
+                        .iter()
+                        .map(|transaction| TransactionEdits {
+                            id: transaction.id.into(),
+                            edits: transaction
+                                .edits
+                                .iter()
+                                .map(|(range, text)| (range.clone(), text.as_str().into()))
+                                .collect(),
+                        })
+                        .collect()
+                };
+                let buffer_history = BufferHistory {
+                    fingerprint,
+                    undo_stack: deserialize_stack(&history.undo_stack),
+                    redo_stack: deserialize_stack(&history.redo_stack),
+                };
+                buffer.update(cx, |buffer, cx| buffer.restore_history(buffer_history, cx));
             }
-            let range = ExcerptRange {
-                context: buffer_snapshot.anchor_before(&range.context.start)
-                    ..buffer_snapshot.anchor_after(&range.context.end),
-                primary: range.primary.map(|primary| {
-                    buffer_snapshot.anchor_before(&primary.start)
-                        ..buffer_snapshot.anchor_after(&primary.end)
-                }),
+
+            let buffer = buffer.read(cx);
+            let transaction_ids = buffer
+                .undo_stack()
+                .iter()
+                .chain(buffer.redo_stack())
+                .map(|entry| entry.transaction_id())
+                .collect::<HashSet<_>>();
+            restored.extend(
+                history
+                    .undo_stack
+                    .into_iter()
+                    .chain(history.redo_stack)
+                    .filter(|transaction| {
+                        transaction_ids.contains(&TransactionId::from(transaction.id))
+                    }),
+            );
+        } else {
+            let buffer_ids_by_path = self
+                .buffers
+                .borrow()
+                .iter()
+                .filter_map(|(buffer_id, state)| {
+                    let file = state.buffer.read(cx).file()?;
+                    Some((file.path().to_path_buf(), *buffer_id))
+                })
+                .collect::<HashMap<_, _>>();
+            let now = Instant::now();
+            let mut restore_stack = |stack: Vec<SerializedTransaction>| {
+                stack
+                    .into_iter()
+                    .filter_map(|transaction| {
+                        let id = TransactionId::from(transaction.id);
+                        self.history.next_transaction_id.observe(id);
+                        let buffer_transactions = transaction
+                            .buffer_transactions
+                            .iter()
+                            .filter_map(|(path, buffer_transaction_id)| {
+                                let buffer_id = *buffer_ids_by_path.get(path)?;
+                                Some((buffer_id, TransactionId::from(*buffer_transaction_id)))
+                            })
+                            .collect::<HashMap<_, _>>();
+                        if buffer_transactions.is_empty() {
+                            None
+                        } else {
+                            restored.push(transaction);
+                            Some(Transaction {
+                                id,
+                                buffer_transactions,
+                                excerpt_changes: Default::default(),
+                                first_edit_at: now,
+                                last_edit_at: now,
+                                suppress_grouping: true,
+                            })
+                        }
+                    })
+                    .collect::<Vec<_>>()
             };
-            excerpts.push((id, range.clone()));
-            let excerpt = Excerpt::new(
+            let undo_stack = restore_stack(history.undo_stack);
+            let redo_stack = restore_stack(history.redo_stack);
+            self.history.undo_stack = undo_stack;
+            self.history.redo_stack = redo_stack;
+        }
+
+        let snapshot = self.snapshot(cx);
+        let restore_selections = |selections: Vec<SerializedSelection>| {
+            selections
+                .into_iter()
+                .enumerate()
+                .filter_map(|(id, selection)| {
+                    Some(Selection {
+                        id,
+                        start: selection.start.resolve(&snapshot)?,
+                        end: selection.end.resolve(&snapshot)?,
+                        reversed: selection.reversed,
+                        goal: SelectionGoal::None,
+                    })
+                })
+                .collect::<Arc<[_]>>()
+        };
+        for transaction in restored {
+            let id = TransactionId::from(transaction.id);
+            if let Some(label) = transaction.label {
+                self.transaction_labels.insert(id, label.into());
+            }
+            self.selections_by_transaction.insert(
                 id,
-                locator.clone(),
-                buffer_id,
-                buffer_snapshot.clone(),
-                range,
-                ranges.peek().is_some() || cursor.item().is_some(),
+                (
+                    restore_selections(transaction.selections_before),
+                    transaction.selections_after.map(restore_selections),
+                ),
             );
-            new_excerpts.push(excerpt, &());
-            prev_locator = locator.clone();
+        }
+        self.evict_oldest_transactions(cx);
+    }
 
-            if let Some(last_mapping_entry) = new_excerpt_ids.last() {
-                assert!(id > last_mapping_entry.id, "excerpt ids must be increasing");
+    fn evict_oldest_transactions(&mut self, cx: &mut ModelContext<Self>) {
+        let Some(max_transactions) = self.history.max_transactions else {
+            return;
+        };
+        // Never evict a transaction that is still in progress.
+        let max_transactions = max_transactions.max(self.history.transaction_depth.min(1));
+        let excess = self
+            .history
+            .undo_stack
+            .len()
+            .saturating_sub(max_transactions);
+        for transaction in self.history.undo_stack.drain(..excess) {
+            for (buffer_id, buffer_transaction_id) in transaction.buffer_transactions {
+                if let Some(state) = self.buffers.borrow().get(&buffer_id) {
+                    state.buffer.update(cx, |buffer, _| {
+                        buffer.forget_transaction(buffer_transaction_id)
+                    });
+                }
             }
-            new_excerpt_ids.push(ExcerptIdMapping { id, locator }, &());
+            self.selections_by_transaction.remove(&transaction.id);
+            self.transaction_labels.remove(&transaction.id);
         }
+    }
 
-        let edit_end = new_excerpts.summary().text.len;
-
-        let suffix = cursor.suffix(&());
-        let changed_trailing_excerpt = suffix.is_empty();
-        new_excerpts.append(suffix, &());
-        drop(cursor);
-        snapshot.excerpts = new_excerpts;
-        snapshot.excerpt_ids = new_excerpt_ids;
-        if changed_trailing_excerpt {
-            snapshot.trailing_excerpt_update_count += 1;
+    pub fn group_until_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(buffer) = self.as_singleton() {
+            buffer.update(cx, |buffer, _| {
+                buffer.group_until_transaction(transaction_id)
+            });
+        } else {
+            let merges = self.history.group_until(transaction_id);
+            self.merge_buffer_transactions(merges, cx);
         }
-
-        self.subscriptions.publish_mut([Edit {
-            old: edit_start..edit_start,
-            new: edit_start..edit_end,
-        }]);
-        cx.emit(Event::Edited {
-            singleton_buffer_edited: false,
-        });
-        cx.emit(Event::ExcerptsAdded {
-            buffer,
-            predecessor: prev_excerpt_id,
-            excerpts,
-        });
-        cx.notify();
     }
 
-    pub fn clear(&mut self, cx: &mut ModelContext<Self>) {
-        self.sync(cx);
-        let ids = self.excerpt_ids();
-        self.buffers.borrow_mut().clear();
-        let mut snapshot = self.snapshot.borrow_mut();
-        let prev_len = snapshot.len();
-        snapshot.excerpts = Default::default();
-        snapshot.trailing_excerpt_update_count += 1;
-        snapshot.is_dirty = false;
-        snapshot.has_conflict = false;
-
-        self.subscriptions.publish_mut([Edit {
-            old: 0..prev_len,
-            new: 0..0,
-        }]);
-        cx.emit(Event::Edited {
-            singleton_buffer_edited: false,
-        });
-        cx.emit(Event::ExcerptsRemoved { ids });
-        cx.notify();
+    /// Merges buffer transactions whose multi-buffer transactions were grouped, so that
+    /// undoing the group undoes all of them.
+    fn merge_buffer_transactions(
+        &mut self,
+        merges: Vec<BufferTransactionMerge>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let buffers = self.buffers.borrow();
+        for merge in merges {
+            if let Some(state) = buffers.get(&merge.buffer_id) {
+                state.buffer.update(cx, |buffer, _| {
+                    buffer.merge_transactions(merge.transaction_id, merge.destination)
+                });
+            }
+        }
     }
 
-    pub fn excerpts_for_buffer(
-        &self,
-        buffer: &Model<Buffer>,
-        cx: &AppContext,
-    ) -> Vec<(ExcerptId, ExcerptRange<text::Anchor>)> {
-        let mut excerpts = Vec::new();
+    pub fn set_active_selections(
+        &mut self,
+        selections: &[Selection<Anchor>],
+        line_mode: bool,
+        cursor_shape: CursorShape,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let mut selections_by_buffer: HashMap<BufferId, Vec<Selection<text::Anchor>>> =
+            Default::default();
         let snapshot = self.read(cx);
-        let buffers = self.buffers.borrow();
         let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
-        for locator in buffers
-            .get(&buffer.read(cx).remote_id())
-            .map(|state| &state.excerpts)
-            .into_iter()
-            .flatten()
-        {
-            cursor.seek_forward(&Some(locator), Bias::Left, &());
-            if let Some(excerpt) = cursor.item() {
-                if excerpt.locator == *locator {
-                    excerpts.push((excerpt.id.clone(), excerpt.range.clone()));
+        for selection in selections {
+            let start_locator = snapshot.excerpt_locator_for_id(selection.start.excerpt_id);
+            let end_locator = snapshot.excerpt_locator_for_id(selection.end.excerpt_id);
+
+            cursor.seek(&Some(start_locator), Bias::Left, &());
+            while let Some(excerpt) = cursor.item() {
+                if excerpt.locator > *end_locator {
+                    break;
+                }
+
+                let mut start = excerpt.range.context.start;
+                let mut end = excerpt.range.context.end;
+                if excerpt.id == selection.start.excerpt_id {
+                    start = selection.start.text_anchor;
+                }
+                if excerpt.id == selection.end.excerpt_id {
+                    end = selection.end.text_anchor;
                 }
+                selections_by_buffer
+                    .entry(excerpt.buffer_id)
+                    .or_default()
+                    .push(Selection {
+                        id: selection.id,
+                        start,
+                        end,
+                        reversed: selection.reversed,
+                        goal: selection.goal,
+                    });
+
+                cursor.next(&());
             }
         }
 
-        excerpts
-    }
+        for (buffer_id, buffer_state) in self.buffers.borrow().iter() {
+            if !selections_by_buffer.contains_key(buffer_id) {
+                self.mark_buffer_dirty(*buffer_id);
+                buffer_state
+                    .buffer
+                    .update(cx, |buffer, cx| buffer.remove_active_selections(cx));
+            }
+        }
 
-    pub fn excerpt_ids(&self) -> Vec<ExcerptId> {
-        self.snapshot
-            .borrow()
-            .excerpts
-            .iter()
-            .map(|entry| entry.id)
-            .collect()
+        for (buffer_id, mut selections) in selections_by_buffer {
+            self.mark_buffer_dirty(buffer_id);
+            self.buffers.borrow()[&buffer_id]
+                .buffer
+                .update(cx, |buffer, cx| {
+                    selections.sort_unstable_by(|a, b| a.start.cmp(&b.start, buffer));
+                    let mut selections = selections.into_iter().peekable();
+                    let merged_selections = Arc::from_iter(iter::from_fn(|| {
+                        let mut selection = selections.next()?;
+                        while let Some(next_selection) = selections.peek() {
+                            if selection.end.cmp(&next_selection.start, buffer).is_ge() {
+                                let next_selection = selections.next().unwrap();
+                                if next_selection.end.cmp(&selection.end, buffer).is_ge() {
+                                    selection.end = next_selection.end;
+                                }
+                            } else {
+                                break;
+                            }
+                        }
+                        Some(selection)
+                    }));
+                    buffer.set_active_selections(merged_selections, line_mode, cursor_shape, cx);
+                });
+        }
     }
 
-    pub fn excerpt_containing(
-        &self,
-        position: impl ToOffset,
-        cx: &AppContext,
-    ) -> Option<(ExcerptId, Model<Buffer>, Range<text::Anchor>)> {
-        let snapshot = self.read(cx);
-        let position = position.to_offset(&snapshot);
-
-        let mut cursor = snapshot.excerpts.cursor::<usize>();
-        cursor.seek(&position, Bias::Right, &());
-        cursor
-            .item()
-            .or_else(|| snapshot.excerpts.last())
-            .map(|excerpt| {
-                (
-                    excerpt.id.clone(),
-                    self.buffers
-                        .borrow()
-                        .get(&excerpt.buffer_id)
-                        .unwrap()
-                        .buffer
-                        .clone(),
-                    excerpt.range.context.clone(),
-                )
-            })
+    pub fn remove_active_selections(&mut self, cx: &mut ModelContext<Self>) {
+        for (buffer_id, buffer) in self.buffers.borrow().iter() {
+            self.mark_buffer_dirty(*buffer_id);
+            buffer
+                .buffer
+                .update(cx, |buffer, cx| buffer.remove_active_selections(cx));
+        }
     }
 
-    // If point is at the end of the buffer, the last excerpt is returned
-    pub fn point_to_buffer_offset<T: ToOffset>(
-        &self,
-        point: T,
-        cx: &AppContext,
-    ) -> Option<(Model<Buffer>, usize, ExcerptId)> {
-        let snapshot = self.read(cx);
-        let offset = point.to_offset(&snapshot);
-        let mut cursor = snapshot.excerpts.cursor::<usize>();
-        cursor.seek(&offset, Bias::Right, &());
-        if cursor.item().is_none() {
-            cursor.prev(&());
+    pub fn undo(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        let mut transaction_id = None;
+        if let Some(buffer) = self.as_singleton() {
+            transaction_id = buffer.update(cx, |buffer, cx| buffer.undo(cx));
+        } else {
+            while let Some((id, undone)) = self.undo_last_in_history(cx) {
+                if undone {
+                    transaction_id = Some(id);
+                    break;
+                }
+            }
         }
 
-        cursor.item().map(|excerpt| {
-            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let buffer_point = excerpt_start + offset - *cursor.start();
-            let buffer = self.buffers.borrow()[&excerpt.buffer_id].buffer.clone();
+        if let Some(transaction_id) = transaction_id {
+            if let Some((selections_before, _)) =
+                self.selections_by_transaction.get(&transaction_id).cloned()
+            {
+                self.set_local_selections(selections_before, cx);
+            }
+            cx.emit(Event::TransactionUndone { transaction_id });
+        }
 
-            (buffer, buffer_point, excerpt.id)
-        })
+        transaction_id
     }
 
-    pub fn range_to_buffer_ranges<T: ToOffset>(
-        &self,
-        range: Range<T>,
-        cx: &AppContext,
-    ) -> Vec<(Model<Buffer>, Range<usize>, ExcerptId)> {
-        let snapshot = self.read(cx);
-        let start = range.start.to_offset(&snapshot);
-        let end = range.end.to_offset(&snapshot);
-
-        let mut result = Vec::new();
-        let mut cursor = snapshot.excerpts.cursor::<usize>();
-        cursor.seek(&start, Bias::Right, &());
-        if cursor.item().is_none() {
-            cursor.prev(&());
-        }
-
-        while let Some(excerpt) = cursor.item() {
-            if *cursor.start() > end {
-                break;
+    pub fn redo(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        let mut transaction_id = None;
+        if let Some(buffer) = self.as_singleton() {
+            transaction_id = buffer.update(cx, |buffer, cx| buffer.redo(cx));
+        } else {
+            while let Some((id, redone)) = self.redo_last_in_history(cx) {
+                if redone {
+                    transaction_id = Some(id);
+                    break;
+                }
             }
+        }
 
-            let mut end_before_newline = cursor.end(&());
-            if excerpt.has_trailing_newline {
-                end_before_newline -= 1;
+        if let Some(transaction_id) = transaction_id {
+            if let Some((_, Some(selections_after))) =
+                self.selections_by_transaction.get(&transaction_id).cloned()
+            {
+                self.set_local_selections(selections_after, cx);
             }
-            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let start = excerpt_start + (cmp::max(start, *cursor.start()) - *cursor.start());
-            let end = excerpt_start + (cmp::min(end, end_before_newline) - *cursor.start());
-            let buffer = self.buffers.borrow()[&excerpt.buffer_id].buffer.clone();
-            result.push((buffer, start..end, excerpt.id));
-            cursor.next(&());
         }
 
-        result
+        transaction_id
     }
 
-    pub fn remove_excerpts(
+    /// Undoes transactions until the given transaction has been undone, returning the
+    /// ids of the transactions that were undone, most recent first. Nothing is undone if
+    /// the transaction isn't on the undo stack.
+    pub fn undo_to_transaction(
         &mut self,
-        excerpt_ids: impl IntoIterator<Item = ExcerptId>,
+        transaction_id: TransactionId,
         cx: &mut ModelContext<Self>,
-    ) {
-        self.sync(cx);
-        let ids = excerpt_ids.into_iter().collect::<Vec<_>>();
-        if ids.is_empty() {
-            return;
+    ) -> Vec<TransactionId> {
+        let mut undone = Vec::new();
+        if let Some(buffer) = self.as_singleton() {
+            undone = transactions_until(buffer.read(cx).undo_stack(), transaction_id);
+            if !undone.is_empty() {
+                buffer.update(cx, |buffer, cx| {
+                    buffer.undo_to_transaction(transaction_id, cx)
+                });
+            }
+        } else if self
+            .history
+            .undo_stack
+            .iter()
+            .any(|transaction| transaction.id == transaction_id)
+        {
+            while let Some((id, was_undone)) = self.undo_last_in_history(cx) {
+                if was_undone {
+                    undone.push(id);
+                }
+                if id == transaction_id {
+                    break;
+                }
+            }
         }
 
-        let mut buffers = self.buffers.borrow_mut();
-        let mut snapshot = self.snapshot.borrow_mut();
-        let mut new_excerpts = SumTree::new();
-        let mut cursor = snapshot.excerpts.cursor::<(Option<&Locator>, usize)>();
-        let mut edits = Vec::new();
-        let mut excerpt_ids = ids.iter().copied().peekable();
+        if let Some((selections_before, _)) =
+            self.selections_by_transaction.get(&transaction_id).cloned()
+        {
+            if !undone.is_empty() {
+                self.set_local_selections(selections_before, cx);
+            }
+        }
+        for transaction_id in &undone {
+            cx.emit(Event::TransactionUndone {
+                transaction_id: *transaction_id,
+            });
+        }
+        undone
+    }
 
-        while let Some(excerpt_id) = excerpt_ids.next() {
-            // Seek to the next excerpt to remove, preserving any preceding excerpts.
-            let locator = snapshot.excerpt_locator_for_id(excerpt_id);
-            new_excerpts.append(cursor.slice(&Some(locator), Bias::Left, &()), &());
-
-            if let Some(mut excerpt) = cursor.item() {
-                if excerpt.id != excerpt_id {
-                    continue;
+    /// Redoes transactions until the given transaction has been redone, returning the
+    /// ids of the transactions that were redone, oldest first. Nothing is redone if the
+    /// transaction isn't on the redo stack.
+    pub fn redo_to_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<TransactionId> {
+        let mut redone = Vec::new();
+        if let Some(buffer) = self.as_singleton() {
+            redone = transactions_until(buffer.read(cx).redo_stack(), transaction_id);
+            if !redone.is_empty() {
+                buffer.update(cx, |buffer, cx| {
+                    buffer.redo_to_transaction(transaction_id, cx)
+                });
+            }
+        } else if self
+            .history
+            .redo_stack
+            .iter()
+            .any(|transaction| transaction.id == transaction_id)
+        {
+            while let Some((id, was_redone)) = self.redo_last_in_history(cx) {
+                if was_redone {
+                    redone.push(id);
                 }
-                let mut old_start = cursor.start().1;
-
-                // Skip over the removed excerpt.
-                'remove_excerpts: loop {
-                    if let Some(buffer_state) = buffers.get_mut(&excerpt.buffer_id) {
-                        buffer_state.excerpts.retain(|l| l != &excerpt.locator);
-                        if buffer_state.excerpts.is_empty() {
-                            buffers.remove(&excerpt.buffer_id);
-                        }
-                    }
-                    cursor.next(&());
-
-                    // Skip over any subsequent excerpts that are also removed.
-                    while let Some(&next_excerpt_id) = excerpt_ids.peek() {
-                        let next_locator = snapshot.excerpt_locator_for_id(next_excerpt_id);
-                        if let Some(next_excerpt) = cursor.item() {
-                            if next_excerpt.locator == *next_locator {
-                                excerpt_ids.next();
-                                excerpt = next_excerpt;
-                                continue 'remove_excerpts;
-                            }
-                        }
-                        break;
-                    }
-
+                if id == transaction_id {
                     break;
                 }
+            }
+        }
 
-                // When removing the last excerpt, remove the trailing newline from
-                // the previous excerpt.
-                if cursor.item().is_none() && old_start > 0 {
-                    old_start -= 1;
-                    new_excerpts.update_last(|e| e.has_trailing_newline = false, &());
-                }
+        if let Some((_, Some(selections_after))) =
+            self.selections_by_transaction.get(&transaction_id).cloned()
+        {
+            if !redone.is_empty() {
+                self.set_local_selections(selections_after, cx);
+            }
+        }
+        redone
+    }
 
-                // Push an edit for the removal of this run of excerpts.
-                let old_end = cursor.start().1;
-                let new_start = new_excerpts.summary().text.len;
-                edits.push(Edit {
-                    old: old_start..old_end,
-                    new: new_start..new_start,
+    /// Moves the most recent transaction from the undo stack to the redo stack, undoing
+    /// its edits in the underlying buffers, along with any local edits made to those buffers
+    /// since. Transactions made by collaborators that were pushed onto the buffers' histories
+    /// are left applied. Returns the transaction's id and whether any edits were actually
+    /// undone.
+    fn undo_last_in_history(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<(TransactionId, bool)> {
+        let transaction = self.history.pop_undo()?;
+        let transaction_id = transaction.id;
+        let (inserted, removed) = partition_excerpt_changes(&transaction.excerpt_changes);
+        let mut undone = !inserted.is_empty() || !removed.is_empty();
+
+        // Restore removed excerpts first, so that the edits to their buffers can be undone.
+        self.restore_excerpts(removed, cx);
+        let transaction = self.history.redo_stack.last_mut()?;
+        for (buffer_id, buffer_transaction_id) in &mut transaction.buffer_transactions {
+            if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
+                undone |= buffer.update(cx, |buffer, cx| {
+                    let transaction_ids =
+                        own_transactions_until(buffer.undo_stack(), *buffer_transaction_id, buffer);
+                    // Redoing this transaction redoes everything that was undone along with it.
+                    if let Some(most_recent) = transaction_ids.first() {
+                        *buffer_transaction_id = *most_recent;
+                    }
+                    let mut undone = false;
+                    for transaction_id in transaction_ids {
+                        undone |= buffer.undo_transaction(transaction_id, cx);
+                    }
+                    undone
                 });
             }
         }
-        let suffix = cursor.suffix(&());
-        let changed_trailing_excerpt = suffix.is_empty();
-        new_excerpts.append(suffix, &());
-        drop(cursor);
-        snapshot.excerpts = new_excerpts;
+        self.remove_recorded_excerpts(inserted, cx);
+        Some((transaction_id, undone))
+    }
 
-        if changed_trailing_excerpt {
-            snapshot.trailing_excerpt_update_count += 1;
+    /// Moves the most recent transaction from the redo stack to the undo stack, redoing
+    /// its edits in the underlying buffers. Returns the transaction's id and whether any
+    /// edits were actually redone.
+    fn redo_last_in_history(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<(TransactionId, bool)> {
+        let transaction = self.history.pop_redo()?;
+        let transaction_id = transaction.id;
+        let (inserted, removed) = partition_excerpt_changes(&transaction.excerpt_changes);
+        let mut redone = !inserted.is_empty() || !removed.is_empty();
+
+        self.restore_excerpts(inserted, cx);
+        let transaction = self.history.undo_stack.last_mut()?;
+        for (buffer_id, buffer_transaction_id) in &mut transaction.buffer_transactions {
+            if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
+                redone |= buffer.update(cx, |buffer, cx| {
+                    let transaction_ids =
+                        own_transactions_until(buffer.redo_stack(), *buffer_transaction_id, buffer);
+                    // Undoing this transaction again undoes everything that was redone with it.
+                    if let Some(oldest) = transaction_ids.first() {
+                        *buffer_transaction_id = *oldest;
+                    }
+                    let mut redone = false;
+                    for transaction_id in transaction_ids {
+                        redone |= buffer.redo_transaction(transaction_id, cx);
+                    }
+                    redone
+                });
+            }
         }
-
-        self.subscriptions.publish_mut(edits);
-        cx.emit(Event::Edited {
-            singleton_buffer_edited: false,
-        });
-        cx.emit(Event::ExcerptsRemoved { ids });
-        cx.notify();
+        self.remove_recorded_excerpts(removed, cx);
+        Some((transaction_id, redone))
     }
 
-    pub fn wait_for_anchors<'a>(
-        &self,
-        anchors: impl 'a + Iterator<Item = Anchor>,
-        cx: &mut ModelContext<Self>,
-    ) -> impl 'static + Future<Output = Result<()>> {
-        let borrow = self.buffers.borrow();
-        let mut error = None;
-        let mut futures = Vec::new();
-        for anchor in anchors {
-            if let Some(buffer_id) = anchor.buffer_id {
-                if let Some(buffer) = borrow.get(&buffer_id) {
-                    buffer.buffer.update(cx, |buffer, _| {
-                        futures.push(buffer.wait_for_anchors([anchor.text_anchor]))
+    pub fn undo_transaction(&mut self, transaction_id: TransactionId, cx: &mut ModelContext<Self>) {
+        if let Some(buffer) = self.as_singleton() {
+            buffer.update(cx, |buffer, cx| buffer.undo_transaction(transaction_id, cx));
+        } else if let Some(transaction) = self.history.remove_from_undo(transaction_id) {
+            for (buffer_id, transaction_id) in &transaction.buffer_transactions {
+                if let Some(BufferState { buffer, .. }) = self.buffers.borrow().get(buffer_id) {
+                    buffer.update(cx, |buffer, cx| {
+                        buffer.undo_transaction(*transaction_id, cx)
                     });
-                } else {
-                    error = Some(anyhow!(
-                        "buffer {buffer_id} is not part of this multi-buffer"
-                    ));
-                    break;
                 }
             }
         }
-        async move {
-            if let Some(error) = error {
-                Err(error)?;
-            }
-            for future in futures {
-                future.await?;
-            }
-            Ok(())
-        }
     }
 
-    pub fn text_anchor_for_position<T: ToOffset>(
-        &self,
-        position: T,
-        cx: &AppContext,
-    ) -> Option<(Model<Buffer>, language::Anchor)> {
-        let snapshot = self.read(cx);
-        let anchor = snapshot.anchor_before(position);
-        let buffer = self
-            .buffers
-            .borrow()
-            .get(&anchor.buffer_id?)?
-            .buffer
-            .clone();
-        Some((buffer, anchor.text_anchor))
+    pub fn stream_excerpts_with_context_lines(
+        &mut self,
+        buffer: Model<Buffer>,
+        ranges: Vec<Range<text::Anchor>>,
+        context_line_count: u32,
+        cx: &mut ModelContext<Self>,
+    ) -> mpsc::Receiver<Range<Anchor>> {
+        let (buffer_id, buffer_snapshot) =
+            buffer.update(cx, |buffer, _| (buffer.remote_id(), buffer.snapshot()));
+
+        let (mut tx, rx) = mpsc::channel(256);
+        cx.spawn(move |this, mut cx| async move {
+            let mut excerpt_ranges = Vec::new();
+            let mut range_counts = Vec::new();
+            cx.background_executor()
+                .scoped(|scope| {
+                    scope.spawn(async {
+                        let (ranges, counts) =
+                            build_excerpt_ranges(&buffer_snapshot, &ranges, context_line_count);
+                        excerpt_ranges = ranges;
+                        range_counts = counts;
+                    });
+                })
+                .await;
+
+            let mut ranges = ranges.into_iter();
+            let mut range_counts = range_counts.into_iter();
+            for excerpt_ranges in excerpt_ranges.chunks(100) {
+                let excerpt_ids = match this.update(&mut cx, |this, cx| {
+                    this.push_excerpts(buffer.clone(), excerpt_ranges.iter().cloned(), cx)
+                }) {
+                    Ok(excerpt_ids) => excerpt_ids,
+                    Err(_) => return,
+                };
+
+                for (excerpt_id, range_count) in excerpt_ids.into_iter().zip(range_counts.by_ref())
+                {
+                    for range in ranges.by_ref().take(range_count) {
+                        let start = Anchor {
+                            buffer_id: Some(buffer_id),
+                            excerpt_id: excerpt_id.clone(),
+                            text_anchor: range.start,
+                        };
+                        let end = Anchor {
+                            buffer_id: Some(buffer_id),
+                            excerpt_id: excerpt_id.clone(),
+                            text_anchor: range.end,
+                        };
+                        if tx.send(start..end).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+        .detach();
+
+        rx
     }
 
-    fn on_buffer_event(
+    pub fn push_excerpts<O>(
         &mut self,
         buffer: Model<Buffer>,
-        event: &language::Event,
+        ranges: impl IntoIterator<Item = ExcerptRange<O>>,
         cx: &mut ModelContext<Self>,
-    ) {
-        cx.emit(match event {
-            language::Event::Edited => Event::Edited {
-                singleton_buffer_edited: true,
-            },
-            language::Event::DirtyChanged => Event::DirtyChanged,
-            language::Event::Saved => Event::Saved,
-            language::Event::FileHandleChanged => Event::FileHandleChanged,
-            language::Event::Reloaded => Event::Reloaded,
-            language::Event::DiffBaseChanged => Event::DiffBaseChanged,
-            language::Event::LanguageChanged => Event::LanguageChanged,
-            language::Event::Reparsed => Event::Reparsed,
-            language::Event::DiagnosticsUpdated => Event::DiagnosticsUpdated,
-            language::Event::Closed => Event::Closed,
-            language::Event::CapabilityChanged => {
-                self.capability = buffer.read(cx).capability();
-                Event::CapabilityChanged
-            }
+    ) -> Vec<ExcerptId>
+    where
+        O: text::ToOffset,
+    {
+        self.insert_excerpts_after(ExcerptId::max(), buffer, ranges, cx)
+    }
 
-            //
-            language::Event::Operation(_) => return,
-        });
+    /// Registers excerpts to be appended to the multi-buffer later, when they're first
+    /// needed, which avoids building thousands of excerpts up front for large result sets.
+    /// Lazy excerpts are inserted in order by [`MultiBuffer::hydrate_excerpts`], after any
+    /// excerpts that exist at that point.
+    pub fn push_lazy_excerpts(
+        &mut self,
+        buffer: Model<Buffer>,
+        ranges: impl IntoIterator<Item = ExcerptRange<text::Anchor>>,
+    ) {
+        self.pending_excerpts
+            .extend(ranges.into_iter().map(|range| (buffer.clone(), range)));
     }
 
-    pub fn all_buffers(&self) -> HashSet<Model<Buffer>> {
-        self.buffers
-            .borrow()
-            .values()
-            .map(|state| state.buffer.clone())
-            .collect()
+    pub fn pending_excerpt_count(&self) -> usize {
+        self.pending_excerpts.len()
     }
 
-    pub fn buffer(&self, buffer_id: BufferId) -> Option<Model<Buffer>> {
-        self.buffers
-            .borrow()
-            .get(&buffer_id)
-            .map(|state| state.buffer.clone())
+    /// Estimates how many rows the pending lazy excerpts will occupy once inserted, so
+    /// that views can size their scrollbars before the excerpts exist.
+    pub fn estimated_pending_rows(&self, cx: &AppContext) -> u32 {
+        self.pending_excerpts
+            .iter()
+            .map(|(buffer, range)| {
+                let buffer = buffer.read(cx);
+                let start = range.context.start.to_point(buffer);
+                let end = range.context.end.to_point(buffer);
+                end.row - start.row + 1
+            })
+            .sum()
     }
 
-    pub fn is_completion_trigger(&self, position: Anchor, text: &str, cx: &AppContext) -> bool {
-        let mut chars = text.chars();
-        let char = if let Some(char) = chars.next() {
-            char
-        } else {
-            return false;
-        };
-        if chars.next().is_some() {
-            return false;
-        }
+    /// Inserts pending lazy excerpts until the multi-buffer extends beyond the given row or
+    /// there are none left, returning the ids of the inserted excerpts.
+    pub fn hydrate_excerpts(
+        &mut self,
+        max_row: u32,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<ExcerptId> {
+        let mut excerpt_ids = Vec::new();
+        while !self.pending_excerpts.is_empty() {
+            let snapshot = self.read(cx);
+            let covers_row = !snapshot.is_empty() && snapshot.max_point().row > max_row;
+            drop(snapshot);
+            if covers_row {
+                break;
+            }
 
-        let snapshot = self.snapshot(cx);
-        let position = position.to_offset(&snapshot);
-        let scope = snapshot.language_scope_at(position);
-        if char_kind(&scope, char) == CharKind::Word {
-            return true;
+            let (buffer, range) = self.pending_excerpts.pop_front().unwrap();
+            excerpt_ids.extend(self.push_excerpts(buffer, [range], cx));
         }
+        excerpt_ids
+    }
 
-        let anchor = snapshot.anchor_before(position);
-        anchor
-            .buffer_id
-            .and_then(|buffer_id| {
-                let buffer = self.buffers.borrow().get(&buffer_id)?.buffer.clone();
-                Some(
-                    buffer
-                        .read(cx)
-                        .completion_triggers()
-                        .iter()
-                        .any(|string| string == text),
-                )
-            })
-            .unwrap_or(false)
-    }
+    pub fn push_excerpts_with_context_lines<O>(
+        &mut self,
+        buffer: Model<Buffer>,
+        ranges: Vec<Range<O>>,
+        context_line_count: u32,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<Range<Anchor>>
+    where
+        O: text::ToPoint + text::ToOffset,
+    {
+        let buffer_id = buffer.read(cx).remote_id();
+        let buffer_snapshot = buffer.read(cx).snapshot();
+        let (excerpt_ranges, range_counts) =
+            build_excerpt_ranges(&buffer_snapshot, &ranges, context_line_count);
 
-    pub fn language_at<'a, T: ToOffset>(
-        &self,
-        point: T,
-        cx: &'a AppContext,
-    ) -> Option<Arc<Language>> {
-        self.point_to_buffer_offset(point, cx)
-            .and_then(|(buffer, offset, _)| buffer.read(cx).language_at(offset))
+        let excerpt_ids = self.push_excerpts(buffer, excerpt_ranges, cx);
+
+        let mut anchor_ranges = Vec::new();
+        let mut ranges = ranges.into_iter();
+        for (excerpt_id, range_count) in excerpt_ids.into_iter().zip(range_counts.into_iter()) {
+            anchor_ranges.extend(ranges.by_ref().take(range_count).map(|range| {
+                let start = Anchor {
+                    buffer_id: Some(buffer_id),
+                    excerpt_id: excerpt_id.clone(),
+                    text_anchor: buffer_snapshot.anchor_after(range.start),
+                };
+                let end = Anchor {
+                    buffer_id: Some(buffer_id),
+                    excerpt_id: excerpt_id.clone(),
+                    text_anchor: buffer_snapshot.anchor_after(range.end),
+                };
+                start..end
+            }))
+        }
+        anchor_ranges
     }
 
-    pub fn settings_at<'a, T: ToOffset>(
-        &self,
-        point: T,
-        cx: &'a AppContext,
-    ) -> &'a LanguageSettings {
-        let mut language = None;
-        let mut file = None;
-        if let Some((buffer, offset, _)) = self.point_to_buffer_offset(point, cx) {
-            let buffer = buffer.read(cx);
-            language = buffer.language_at(offset);
-            file = buffer.file();
+    pub fn insert_excerpts_after<O>(
+        &mut self,
+        prev_excerpt_id: ExcerptId,
+        buffer: Model<Buffer>,
+        ranges: impl IntoIterator<Item = ExcerptRange<O>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<ExcerptId>
+    where
+        O: text::ToOffset,
+    {
+        if !self.snapshot.borrow().has_excerpt_id(prev_excerpt_id) {
+            log::error!(
+                "inserting excerpts after unknown excerpt {:?}",
+                prev_excerpt_id
+            );
+            return Vec::new();
         }
-        language_settings(language.as_ref(), file, cx)
+
+        let mut ids = Vec::new();
+        let mut next_excerpt_ids = self.next_excerpt_ids();
+        self.insert_excerpts_with_ids_after(
+            prev_excerpt_id,
+            buffer,
+            ranges.into_iter().map(|range| {
+                let id = next_excerpt_ids.next().unwrap();
+                ids.push(id);
+                (id, range)
+            }),
+            cx,
+        );
+        ids
     }
 
-    pub fn for_each_buffer(&self, mut f: impl FnMut(&Model<Buffer>)) {
-        self.buffers
+    /// Like [`MultiBuffer::push_excerpts`], but computes the text summaries of the excerpts
+    /// on the background executor, so that pushing hundreds of excerpts at once doesn't block
+    /// the main thread. The excerpts are appended in order once all of them are ready.
+    pub fn push_excerpts_in_background(
+        &mut self,
+        buffer: Model<Buffer>,
+        ranges: Vec<ExcerptRange<text::Anchor>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Vec<ExcerptId>> {
+        let buffer_snapshot = buffer.read(cx).snapshot();
+        cx.spawn(move |this, mut cx| async move {
+            let background = cx.background_executor().clone();
+            let chunk_size = cmp::max(1, ranges.len().div_ceil(background.num_cpus()));
+            let mut summaries = vec![None; ranges.len()];
+            background
+                .scoped(|scope| {
+                    for (ranges, summaries) in ranges
+                        .chunks(chunk_size)
+                        .zip(summaries.chunks_mut(chunk_size))
+                    {
+                        let buffer_snapshot = &buffer_snapshot;
+                        scope.spawn(async move {
+                            for (range, summary) in ranges.iter().zip(summaries) {
+                                *summary = Some((
+                                    buffer_snapshot.text_summary_for_range::<TextSummary, _>(
+                                        range.context.to_offset(buffer_snapshot),
+                                    ),
+                                    range.context.end.to_point(buffer_snapshot).row,
+                                ));
+                            }
+                        });
+                    }
+                })
+                .await;
+
+            this.update(&mut cx, |this, cx| {
+                // The summaries are only valid if the buffer hasn't changed in the meantime.
+                let buffer_changed = buffer
+                    .read(cx)
+                    .version()
+                    .changed_since(buffer_snapshot.version());
+                let mut next_excerpt_ids = this.next_excerpt_ids();
+                let mut ids = Vec::new();
+                this.insert_prepared_excerpts_after(
+                    ExcerptId::max(),
+                    buffer,
+                    ranges.into_iter().zip(summaries).map(|(range, summary)| {
+                        let id = next_excerpt_ids.next().unwrap();
+                        ids.push(id);
+                        (id, range, summary.filter(|_| !buffer_changed))
+                    }),
+                    cx,
+                );
+                ids
+            })
+            .unwrap_or_default()
+        })
+    }
+
+    /// Returns the ids to assign to newly inserted excerpts, which are greater than the id of
+    /// any excerpt this replica has seen.
+    fn next_excerpt_ids(&self) -> impl Iterator<Item = ExcerptId> {
+        let replica_id = self.replica_id;
+        let last_id = self
+            .snapshot
             .borrow()
-            .values()
-            .for_each(|state| f(&state.buffer))
+            .excerpt_ids
+            .last()
+            .map_or(ExcerptId::min(), |entry| entry.id);
+        iter::successors(Some(last_id.successor(replica_id)), move |id| {
+            Some(id.successor(replica_id))
+        })
     }
 
-    pub fn title<'a>(&'a self, cx: &'a AppContext) -> Cow<'a, str> {
-        if let Some(title) = self.title.as_ref() {
-            return title.into();
+    pub fn insert_excerpts_with_ids_after<O>(
+        &mut self,
+        prev_excerpt_id: ExcerptId,
+        buffer: Model<Buffer>,
+        ranges: impl IntoIterator<Item = (ExcerptId, ExcerptRange<O>)>,
+        cx: &mut ModelContext<Self>,
+    ) where
+        O: text::ToOffset,
+    {
+        self.insert_prepared_excerpts_after(
+            prev_excerpt_id,
+            buffer,
+            ranges.into_iter().map(|(id, range)| (id, range, None)),
+            cx,
+        )
+    }
+
+    /// Inserts excerpts, optionally using a precomputed text summary and max buffer row for
+    /// each of them, which must have been computed against the buffer's current version.
+    fn insert_prepared_excerpts_after<O>(
+        &mut self,
+        prev_excerpt_id: ExcerptId,
+        buffer: Model<Buffer>,
+        ranges: impl IntoIterator<Item = (ExcerptId, ExcerptRange<O>, Option<(TextSummary, u32)>)>,
+        cx: &mut ModelContext<Self>,
+    ) where
+        O: text::ToOffset,
+    {
+        let mut ranges = ranges.into_iter().peekable();
+        if ranges.peek().is_none() {
+            return Default::default();
+        }
+        // Callers may hold on to excerpt ids from another multi-buffer, such as one that was
+        // replaced. Panicking here would leave any open transaction unbalanced.
+        if !self.snapshot.borrow().has_excerpt_id(prev_excerpt_id) {
+            log::error!(
+                "inserting excerpts after unknown excerpt {:?}",
+                prev_excerpt_id
+            );
+            return;
         }
 
-        if let Some(buffer) = self.as_singleton() {
-            if let Some(file) = buffer.read(cx).file() {
-                return file.file_name(cx).to_string_lossy();
-            }
+        self.sync(cx);
+
+        let buffer_id = buffer.read(cx).remote_id();
+        let mut buffers = self.buffers.borrow_mut();
+        let buffer_snapshot = self.shared_buffer_snapshot(&buffers, &buffer, cx);
+        let is_new_buffer = !buffers.contains_key(&buffer_id);
+        let buffer_state = buffers
+            .entry(buffer_id)
+            .or_insert_with(|| Self::new_buffer_state(&buffer, buffer_snapshot.clone(), cx));
+        if is_new_buffer {
+            self.mark_buffer_dirty(buffer_id);
         }
 
-        "untitled".into()
-    }
+        let mut snapshot = self.snapshot_mut();
 
-    #[cfg(any(test, feature = "test-support"))]
-    pub fn is_parsing(&self, cx: &AppContext) -> bool {
-        self.as_singleton().unwrap().read(cx).is_parsing()
-    }
+        let first_excerpt_id = ranges.peek().unwrap().0;
+        let (prev_excerpt_id, mut prev_locator, next_locator) =
+            snapshot.insertion_point(prev_excerpt_id, first_excerpt_id);
+        let mut new_excerpt_ids = mem::take(&mut snapshot.excerpt_ids);
+        let mut new_excerpt_ids_by_locator = mem::take(&mut snapshot.excerpt_ids_by_locator);
+        let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
+        let mut new_excerpts = cursor.slice(&prev_locator, Bias::Right, &());
 
-    fn sync(&self, cx: &AppContext) {
-        let mut snapshot = self.snapshot.borrow_mut();
-        let mut excerpts_to_edit = Vec::new();
-        let mut reparsed = false;
-        let mut diagnostics_updated = false;
-        let mut git_diff_updated = false;
-        let mut is_dirty = false;
-        let mut has_conflict = false;
-        let mut edited = false;
-        let mut buffers = self.buffers.borrow_mut();
-        for buffer_state in buffers.values_mut() {
-            let buffer = buffer_state.buffer.read(cx);
-            let version = buffer.version();
-            let parse_count = buffer.parse_count();
-            let selections_update_count = buffer.selections_update_count();
-            let diagnostics_update_count = buffer.diagnostics_update_count();
-            let file_update_count = buffer.file_update_count();
-            let git_diff_update_count = buffer.git_diff_update_count();
+        let edit_start = new_excerpts.summary().text.len;
+        new_excerpts.update_last(
+            |excerpt| {
+                excerpt.has_trailing_newline = true;
+            },
+            &(),
+        );
 
-            let buffer_edited = version.changed_since(&buffer_state.last_version);
-            let buffer_reparsed = parse_count > buffer_state.last_parse_count;
-            let buffer_selections_updated =
-                selections_update_count > buffer_state.last_selections_update_count;
-            let buffer_diagnostics_updated =
-                diagnostics_update_count > buffer_state.last_diagnostics_update_count;
-            let buffer_file_updated = file_update_count > buffer_state.last_file_update_count;
-            let buffer_git_diff_updated =
-                git_diff_update_count > buffer_state.last_git_diff_update_count;
-            if buffer_edited
-                || buffer_reparsed
-                || buffer_selections_updated
-                || buffer_diagnostics_updated
-                || buffer_file_updated
-                || buffer_git_diff_updated
+        let mut excerpts = Vec::new();
+        let mut edit_sources = Vec::new();
+        let mut elided_excerpts = Vec::new();
+        let mut needs_rebalance = false;
+        while let Some((id, range, mut summary)) = ranges.next() {
+            let locator = Locator::between(&prev_locator, &next_locator);
+            needs_rebalance |= locator.len() > MAX_LOCATOR_LEN;
+            if let Err(ix) = buffer_state.excerpts.binary_search(&locator) {
+                buffer_state.excerpts.insert(ix, locator.clone());
+                buffer_state.excerpt_ids = None;
+            }
+            let (start_bias, end_bias) = self.excerpt_edges.biases();
+            let mut range = ExcerptRange {
+                context: buffer_snapshot.anchor_at(&range.context.start, start_bias)
+                    ..buffer_snapshot.anchor_at(&range.context.end, end_bias),
+                primary: range.primary.map(|primary| {
+                    buffer_snapshot.anchor_before(&primary.start)
+                        ..buffer_snapshot.anchor_after(&primary.end)
+                }),
+            };
+            if range
+                .context
+                .end
+                .cmp(&range.context.start, &buffer_snapshot)
+                .is_lt()
             {
-                buffer_state.last_version = version;
-                buffer_state.last_parse_count = parse_count;
-                buffer_state.last_selections_update_count = selections_update_count;
-                buffer_state.last_diagnostics_update_count = diagnostics_update_count;
-                buffer_state.last_file_update_count = file_update_count;
-                buffer_state.last_git_diff_update_count = git_diff_update_count;
-                excerpts_to_edit.extend(
-                    buffer_state
-                        .excerpts
-                        .iter()
-                        .map(|locator| (locator, buffer_state.buffer.clone(), buffer_edited)),
-                );
+                range.context.end = range.context.start;
+                summary = None;
+            }
+            if let Some(max_excerpt_len) = self.max_excerpt_len {
+                let context = range.context.to_offset(&buffer_snapshot);
+                if context.len() > max_excerpt_len {
+                    let end =
+                        buffer_snapshot.clip_offset(context.start + max_excerpt_len, Bias::Left);
+                    elided_excerpts.push((id, range.context.end));
+                    range.context.end = buffer_snapshot.anchor_after(end);
+                    summary = None;
+                }
             }
+            excerpts.push((id, range.clone()));
+            let excerpt_start = new_excerpts.summary().text.len;
+            let has_trailing_newline = ranges.peek().is_some() || cursor.item().is_some();
+            let excerpt = if let Some((text_summary, max_buffer_row)) = summary {
+                Excerpt {
+                    id,
+                    locator: locator.clone(),
+                    max_buffer_row,
+                    text_summary,
+                    buffer_id,
+                    buffer: buffer_snapshot.clone(),
+                    range,
+                    has_trailing_newline,
+                }
+            } else {
+                Excerpt::new(
+                    id,
+                    locator.clone(),
+                    buffer_id,
+                    buffer_snapshot.clone(),
+                    range,
+                    has_trailing_newline,
+                )
+            };
+            new_excerpts.push(excerpt, &());
+            prev_locator = locator.clone();
+            edit_sources.push(EditSource {
+                excerpt_id: id,
+                buffer_id,
+                edit: Edit {
+                    old: edit_start..edit_start,
+                    new: excerpt_start..new_excerpts.summary().text.len,
+                },
+            });
 
-            edited |= buffer_edited;
-            reparsed |= buffer_reparsed;
-            diagnostics_updated |= buffer_diagnostics_updated;
-            git_diff_updated |= buffer_git_diff_updated;
-            is_dirty |= buffer.is_dirty();
-            has_conflict |= buffer.has_conflict();
+            new_excerpt_ids_by_locator.insert(locator.clone(), id);
+            // Excerpts from other replicas may have been inserted concurrently with ones
+            // that this replica has already seen, so their ids aren't always the greatest.
+            if new_excerpt_ids.last().map_or(true, |entry| id > entry.id) {
+                new_excerpt_ids.push(ExcerptIdMapping { id, locator }, &());
+            } else {
+                new_excerpt_ids.insert_or_replace(ExcerptIdMapping { id, locator }, &());
+            }
         }
-        if edited {
-            snapshot.edit_count += 1;
+
+        let edit_end = new_excerpts.summary().text.len;
+
+        let suffix = cursor.suffix(&());
+        let changed_trailing_excerpt = suffix.is_empty();
+        new_excerpts.append(suffix, &());
+        drop(cursor);
+        snapshot.excerpts = new_excerpts;
+        snapshot.excerpt_ids = new_excerpt_ids;
+        snapshot.excerpt_ids_by_locator = new_excerpt_ids_by_locator;
+        snapshot.excerpt_update_count += 1;
+        for (id, end) in elided_excerpts {
+            snapshot.elided_excerpts.insert(id, end);
         }
-        if reparsed {
-            snapshot.parse_count += 1;
-        }
-        if diagnostics_updated {
-            snapshot.diagnostics_update_count += 1;
+        if changed_trailing_excerpt {
+            snapshot.trailing_excerpt_update_count += 1;
         }
-        if git_diff_updated {
-            snapshot.git_diff_update_count += 1;
+        drop(snapshot);
+        drop(buffers);
+        if needs_rebalance {
+            self.rebalance_locators();
+        }
+
+        if !self.singleton && self.history.transaction_depth > 0 {
+            // A buffer that joins mid-transaction must be at the same transaction depth as
+            // the others, so that its edits are grouped into the current transaction.
+            if is_new_buffer {
+                let depth = self.history.transaction_depth;
+                let now = Instant::now();
+                buffer.update(cx, |buffer, _| {
+                    for _ in 0..depth {
+                        buffer.start_transaction_at(now);
+                    }
+                });
+            }
+            if let Some(transaction) = self.history.undo_stack.last_mut() {
+                transaction
+                    .excerpt_changes
+                    .extend(excerpts.iter().map(|(id, range)| {
+                        ExcerptChange::Inserted(ExcerptRecord {
+                            id: *id,
+                            buffer: buffer.clone(),
+                            range: range.clone(),
+                        })
+                    }));
+            }
         }
-        snapshot.is_dirty = is_dirty;
-        snapshot.has_conflict = has_conflict;
 
-        excerpts_to_edit.sort_unstable_by_key(|(locator, _, _)| *locator);
-
-        let mut edits = Vec::new();
-        let mut new_excerpts = SumTree::new();
-        let mut cursor = snapshot.excerpts.cursor::<(Option<&Locator>, usize)>();
+        self.subscriptions.publish_mut([Edit {
+            old: edit_start..edit_start,
+            new: edit_start..edit_end,
+        }]);
+        self.edit_sources.publish(edit_sources);
+        cx.emit(Event::Edited {
+            singleton_buffer_edited: false,
+        });
+        cx.emit(Event::ExcerptsAdded {
+            buffer,
+            predecessor: prev_excerpt_id,
+            excerpts,
+        });
+        cx.notify();
+    }
 
-        for (locator, buffer, buffer_edited) in excerpts_to_edit {
-            new_excerpts.append(cursor.slice(&Some(locator), Bias::Left, &()), &());
-            let old_excerpt = cursor.item().unwrap();
-            let buffer = buffer.read(cx);
-            let buffer_id = buffer.remote_id();
+    pub fn clear(&mut self, cx: &mut ModelContext<Self>) {
+        self.pending_excerpts.clear();
+        self.sync(cx);
+        let ids = self.excerpt_ids();
+        self.buffers.borrow_mut().clear();
+        let mut snapshot = self.snapshot_mut();
+        let prev_len = snapshot.len();
+        let edit_sources = snapshot
+            .excerpts
+            .iter()
+            .map(|excerpt| EditSource {
+                excerpt_id: excerpt.id,
+                buffer_id: excerpt.buffer_id,
+                edit: Edit {
+                    old: 0..prev_len,
+                    new: 0..0,
+                },
+            })
+            .collect::<Vec<_>>();
+        snapshot.excerpts = Default::default();
+        snapshot.elided_excerpts = Default::default();
+        snapshot.excerpt_annotations = Default::default();
+        snapshot.excerpt_update_count += 1;
+        snapshot.trailing_excerpt_update_count += 1;
+        snapshot.is_dirty = false;
+        snapshot.has_conflict = false;
 
-            let mut new_excerpt;
-            if buffer_edited {
-                edits.extend(
-                    buffer
-                        .edits_since_in_range::<usize>(
-                            old_excerpt.buffer.version(),
-                            old_excerpt.range.context.clone(),
-                        )
-                        .map(|mut edit| {
-                            let excerpt_old_start = cursor.start().1;
-                            let excerpt_new_start = new_excerpts.summary().text.len;
-                            edit.old.start += excerpt_old_start;
-                            edit.old.end += excerpt_old_start;
-                            edit.new.start += excerpt_new_start;
-                            edit.new.end += excerpt_new_start;
-                            edit
-                        }),
-                );
+        self.subscriptions.publish_mut([Edit {
+            old: 0..prev_len,
+            new: 0..0,
+        }]);
+        self.edit_sources.publish(edit_sources);
+        cx.emit(Event::Edited {
+            singleton_buffer_edited: false,
+        });
+        cx.emit(Event::ExcerptsRemoved { ids });
+        cx.notify();
+    }
 
-                new_excerpt = Excerpt::new(
-                    old_excerpt.id,
-                    locator.clone(),
-                    buffer_id,
-                    buffer.snapshot(),
-                    old_excerpt.range.clone(),
-                    old_excerpt.has_trailing_newline,
-                );
-            } else {
-                new_excerpt = old_excerpt.clone();
-                new_excerpt.buffer = buffer.snapshot();
+    pub fn excerpts_for_buffer(
+        &self,
+        buffer: &Model<Buffer>,
+        cx: &AppContext,
+    ) -> Vec<(ExcerptId, ExcerptRange<text::Anchor>)> {
+        let mut excerpts = Vec::new();
+        let snapshot = self.read(cx);
+        let buffers = self.buffers.borrow();
+        let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
+        for locator in buffers
+            .get(&buffer.read(cx).remote_id())
+            .map(|state| &state.excerpts)
+            .into_iter()
+            .flatten()
+        {
+            cursor.seek_forward(&Some(locator), Bias::Left, &());
+            if let Some(excerpt) = cursor.item() {
+                if excerpt.locator == *locator {
+                    excerpts.push((excerpt.id.clone(), excerpt.range.clone()));
+                }
             }
-
-            new_excerpts.push(new_excerpt, &());
-            cursor.next(&());
         }
-        new_excerpts.append(cursor.suffix(&()), &());
 
-        drop(cursor);
-        snapshot.excerpts = new_excerpts;
+        excerpts
+    }
 
-        self.subscriptions.publish(edits);
+    /// Returns the ids of the given buffer's excerpts, in the order they appear in the
+    /// multi-buffer. The ids are cached until the buffer's excerpts change, so this is
+    /// cheap to call repeatedly.
+    pub fn excerpt_ids_for_buffer(&self, buffer_id: BufferId, cx: &AppContext) -> Arc<[ExcerptId]> {
+        let snapshot = self.read(cx);
+        let mut buffers = self.buffers.borrow_mut();
+        let Some(buffer_state) = buffers.get_mut(&buffer_id) else {
+            return Arc::from([]);
+        };
+        let excerpts = &buffer_state.excerpts;
+        buffer_state
+            .excerpt_ids
+            .get_or_insert_with(|| {
+                let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
+                excerpts
+                    .iter()
+                    .filter_map(|locator| {
+                        cursor.seek_forward(&Some(locator), Bias::Left, &());
+                        let excerpt = cursor.item()?;
+                        (excerpt.locator == *locator).then_some(excerpt.id)
+                    })
+                    .collect()
+            })
+            .clone()
     }
-}
 
-#[cfg(any(test, feature = "test-support"))]
-impl MultiBuffer {
-    pub fn build_simple(text: &str, cx: &mut gpui::AppContext) -> Model<Self> {
-        let buffer = cx
-            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
-        cx.new_model(|cx| Self::singleton(buffer, cx))
+    pub fn excerpt_ids(&self) -> Vec<ExcerptId> {
+        self.snapshot
+            .borrow()
+            .excerpts
+            .iter()
+            .map(|entry| entry.id)
+            .collect()
     }
 
-    pub fn build_multi<const COUNT: usize>(
-        excerpts: [(&str, Vec<Range<Point>>); COUNT],
-        cx: &mut gpui::AppContext,
-    ) -> Model<Self> {
-        let multi = cx.new_model(|_| Self::new(0, Capability::ReadWrite));
-        for (text, ranges) in excerpts {
-            let buffer = cx.new_model(|cx| {
-                Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
-            });
-            let excerpt_ranges = ranges.into_iter().map(|range| ExcerptRange {
-                context: range,
-                primary: None,
-            });
-            multi.update(cx, |multi, cx| {
-                multi.push_excerpts(buffer, excerpt_ranges, cx)
-            });
-        }
+    pub fn excerpt_containing(
+        &self,
+        position: impl ToOffset,
+        cx: &AppContext,
+    ) -> Option<(ExcerptId, Model<Buffer>, Range<text::Anchor>)> {
+        let snapshot = self.read(cx);
+        let position = position.to_offset(&snapshot);
 
-        multi
+        let mut cursor = snapshot.excerpts.cursor::<usize>();
+        cursor.seek(&position, Bias::Right, &());
+        cursor
+            .item()
+            .or_else(|| snapshot.excerpts.last())
+            .map(|excerpt| {
+                (
+                    excerpt.id.clone(),
+                    self.buffers
+                        .borrow()
+                        .get(&excerpt.buffer_id)
+                        .unwrap()
+                        .buffer
+                        .clone(),
+                    excerpt.range.context.clone(),
+                )
+            })
     }
 
-    pub fn build_from_buffer(buffer: Model<Buffer>, cx: &mut gpui::AppContext) -> Model<Self> {
-        cx.new_model(|cx| Self::singleton(buffer, cx))
-    }
+    // If point is at the end of the buffer, the last excerpt is returned
+    pub fn point_to_buffer_offset<T: ToOffset>(
+        &self,
+        point: T,
+        cx: &AppContext,
+    ) -> Option<(Model<Buffer>, usize, ExcerptId)> {
+        let snapshot = self.read(cx);
+        let offset = point.to_offset(&snapshot);
+        let mut cursor = snapshot.excerpts.cursor::<usize>();
+        cursor.seek(&offset, Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
+        }
 
-    pub fn build_random(rng: &mut impl rand::Rng, cx: &mut gpui::AppContext) -> Model<Self> {
-        cx.new_model(|cx| {
-            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
-            let mutation_count = rng.gen_range(1..=5);
-            multibuffer.randomly_edit_excerpts(rng, mutation_count, cx);
-            multibuffer
+        cursor.item().map(|excerpt| {
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let buffer_point = excerpt_start + offset - *cursor.start();
+            let buffer = self.buffers.borrow()[&excerpt.buffer_id].buffer.clone();
+
+            (buffer, buffer_point, excerpt.id)
         })
     }
 
-    pub fn randomly_edit(
-        &mut self,
-        rng: &mut impl rand::Rng,
-        edit_count: usize,
-        cx: &mut ModelContext<Self>,
-    ) {
-        use util::RandomCharIter;
+    pub fn range_to_buffer_ranges<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        cx: &AppContext,
+    ) -> Vec<(Model<Buffer>, Range<usize>, ExcerptId)> {
+        let mut result = Vec::new();
+        self.for_each_buffer_range(range, cx, |buffer, range, excerpt_id| {
+            result.push((buffer.clone(), range, excerpt_id));
+        });
+        result
+    }
 
+    /// Calls `f` with each buffer range that intersects the given range, without
+    /// allocating or cloning buffer handles. This MultiBuffer is borrowed while `f`
+    /// runs, so `f` must not read from or update it.
+    pub fn for_each_buffer_range<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        cx: &AppContext,
+        mut f: impl FnMut(&Model<Buffer>, Range<usize>, ExcerptId),
+    ) {
         let snapshot = self.read(cx);
-        let mut edits: Vec<(Range<usize>, Arc<str>)> = Vec::new();
-        let mut last_end = None;
-        for _ in 0..edit_count {
-            if last_end.map_or(false, |last_end| last_end >= snapshot.len()) {
-                break;
-            }
+        let buffers = self.buffers.borrow();
+        let start = range.start.to_offset(&snapshot);
+        let end = range.end.to_offset(&snapshot);
 
-            let new_start = last_end.map_or(0, |last_end| last_end + 1);
-            let end = snapshot.clip_offset(rng.gen_range(new_start..=snapshot.len()), Bias::Right);
-            let start = snapshot.clip_offset(rng.gen_range(new_start..=end), Bias::Right);
-            last_end = Some(end);
+        let mut cursor = snapshot.excerpts.cursor::<usize>();
+        cursor.seek(&start, Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
+        }
 
-            let mut range = start..end;
-            if rng.gen_bool(0.2) {
-                mem::swap(&mut range.start, &mut range.end);
+        while let Some(excerpt) = cursor.item() {
+            if *cursor.start() > end {
+                break;
             }
 
-            let new_text_len = rng.gen_range(0..10);
-            let new_text: String = RandomCharIter::new(&mut *rng).take(new_text_len).collect();
-
-            edits.push((range, new_text.into()));
+            let mut end_before_newline = cursor.end(&());
+            if excerpt.has_trailing_newline {
+                end_before_newline -= 1;
+            }
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let start = excerpt_start + (cmp::max(start, *cursor.start()) - *cursor.start());
+            let end = excerpt_start + (cmp::min(end, end_before_newline) - *cursor.start());
+            f(&buffers[&excerpt.buffer_id].buffer, start..end, excerpt.id);
+            cursor.next(&());
         }
-        log::info!("mutating multi-buffer with {:?}", edits);
-        drop(snapshot);
+    }
 
-        self.edit(edits, None, cx);
+    /// Like [`MultiBuffer::push_excerpts`], but attaches an annotation to each excerpt.
+    pub fn push_annotated_excerpts<O>(
+        &mut self,
+        buffer: Model<Buffer>,
+        ranges: impl IntoIterator<Item = (ExcerptRange<O>, ExcerptAnnotation)>,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<ExcerptId>
+    where
+        O: text::ToOffset,
+    {
+        let (ranges, annotations): (Vec<_>, Vec<_>) = ranges.into_iter().unzip();
+        let excerpt_ids = self.push_excerpts(buffer, ranges, cx);
+        let mut snapshot = self.snapshot_mut();
+        for (excerpt_id, annotation) in excerpt_ids.iter().zip(annotations) {
+            snapshot.excerpt_annotations.insert(*excerpt_id, annotation);
+        }
+        excerpt_ids
     }
 
-    pub fn randomly_edit_excerpts(
+    /// Attaches an annotation to the given excerpt, replacing any previous one. The annotation
+    /// is dropped when the excerpt is removed.
+    pub fn set_excerpt_annotation(
         &mut self,
-        rng: &mut impl rand::Rng,
-        mutation_count: usize,
+        excerpt_id: ExcerptId,
+        annotation: ExcerptAnnotation,
         cx: &mut ModelContext<Self>,
     ) {
-        use rand::prelude::*;
-        use std::env;
-        use util::RandomCharIter;
+        let mut snapshot = self.snapshot_mut();
+        if snapshot.excerpt(excerpt_id).is_some() {
+            snapshot.excerpt_annotations.insert(excerpt_id, annotation);
+            drop(snapshot);
+            cx.notify();
+        }
+    }
 
-        let max_excerpts = env::var("MAX_EXCERPTS")
-            .map(|i| i.parse().expect("invalid `MAX_EXCERPTS` variable"))
-            .unwrap_or(5);
+    pub fn clear_excerpt_annotation(&mut self, excerpt_id: ExcerptId, cx: &mut ModelContext<Self>) {
+        if self
+            .snapshot_mut()
+            .excerpt_annotations
+            .remove(&excerpt_id)
+            .is_some()
+        {
+            cx.notify();
+        }
+    }
 
-        let mut buffers = Vec::new();
-        for _ in 0..mutation_count {
-            if rng.gen_bool(0.05) {
-                log::info!("Clearing multi-buffer");
-                self.clear(cx);
+    /// Restores the full content of an excerpt that was truncated because it exceeded
+    /// the maximum excerpt length.
+    pub fn expand_elided(&mut self, excerpt_id: ExcerptId, cx: &mut ModelContext<Self>) {
+        self.sync(cx);
+        let mut snapshot = self.snapshot_mut();
+        let Some(end) = snapshot.elided_excerpts.remove(&excerpt_id) else {
+            return;
+        };
+
+        let locator = snapshot.excerpt_locator_for_id(excerpt_id).clone();
+        let mut cursor = snapshot.excerpts.cursor::<(Option<&Locator>, usize)>();
+        let mut new_excerpts = cursor.slice(&Some(&locator), Bias::Left, &());
+        let Some(old_excerpt) = cursor.item().filter(|excerpt| excerpt.id == excerpt_id) else {
+            return;
+        };
+
+        let mut range = old_excerpt.range.clone();
+        range.context.end = end;
+        let new_excerpt = Excerpt::new(
+            excerpt_id,
+            locator,
+            old_excerpt.buffer_id,
+            old_excerpt.buffer.clone(),
+            range,
+            old_excerpt.has_trailing_newline,
+        );
+        let old_end = cursor.start().1 + old_excerpt.text_summary.len;
+        let new_end = cursor.start().1 + new_excerpt.text_summary.len;
+        let buffer_id = old_excerpt.buffer_id;
+        new_excerpts.push(new_excerpt, &());
+        cursor.next(&());
+        new_excerpts.append(cursor.suffix(&()), &());
+        drop(cursor);
+        snapshot.excerpts = new_excerpts;
+        snapshot.excerpt_update_count += 1;
+        drop(snapshot);
+
+        let edit = Edit {
+            old: old_end..old_end,
+            new: old_end..new_end,
+        };
+        self.subscriptions.publish_mut([edit.clone()]);
+        self.edit_sources.publish(vec![EditSource {
+            excerpt_id,
+            buffer_id,
+            edit,
+        }]);
+        cx.emit(Event::Edited {
+            singleton_buffer_edited: false,
+        });
+        cx.emit(Event::ExcerptsEdited {
+            ids: vec![excerpt_id],
+        });
+        cx.notify();
+    }
+
+    /// Reinserts previously-removed excerpts at their original positions, preserving their
+    /// ids so that anchors into them remain valid.
+    fn restore_excerpts(&mut self, excerpts: Vec<ExcerptRecord>, cx: &mut ModelContext<Self>) {
+        if excerpts.is_empty() {
+            return;
+        }
+
+        self.sync(cx);
+        for ExcerptRecord { id, buffer, range } in excerpts {
+            let buffer_id = buffer.read(cx).remote_id();
+            let mut buffers = self.buffers.borrow_mut();
+            let buffer_snapshot = self.shared_buffer_snapshot(&buffers, &buffer, cx);
+            let mut snapshot = self.snapshot_mut();
+            let locator = snapshot.excerpt_locator_for_id(id).clone();
+            let mut cursor = snapshot.excerpts.cursor::<Option<&Locator>>();
+            let mut new_excerpts = cursor.slice(&Some(&locator), Bias::Left, &());
+            if cursor.item().map_or(false, |excerpt| excerpt.id == id) {
                 continue;
             }
 
-            let excerpt_ids = self.excerpt_ids();
-            if excerpt_ids.is_empty() || (rng.gen() && excerpt_ids.len() < max_excerpts) {
-                let buffer_handle = if rng.gen() || self.buffers.borrow().is_empty() {
-                    let text = RandomCharIter::new(&mut *rng).take(10).collect::<String>();
-                    buffers.push(cx.new_model(|cx| {
-                        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
-                    }));
-                    let buffer = buffers.last().unwrap().read(cx);
-                    log::info!(
-                        "Creating new buffer {} with text: {:?}",
-                        buffer.remote_id(),
-                        buffer.text()
-                    );
-                    buffers.last().unwrap().clone()
-                } else {
-                    self.buffers
-                        .borrow()
-                        .values()
-                        .choose(rng)
-                        .unwrap()
-                        .buffer
-                        .clone()
-                };
+            let buffer_state = buffers.entry(buffer_id).or_insert_with(|| {
+                self.dirty_buffers.borrow_mut().insert(buffer_id);
+                Self::new_buffer_state(&buffer, buffer_snapshot.clone(), cx)
+            });
+            if let Err(ix) = buffer_state.excerpts.binary_search(&locator) {
+                buffer_state.excerpts.insert(ix, locator.clone());
+                buffer_state.excerpt_ids = None;
+            }
 
-                let buffer = buffer_handle.read(cx);
-                let buffer_text = buffer.text();
-                let ranges = (0..rng.gen_range(0..5))
-                    .map(|_| {
-                        let end_ix =
-                            buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Right);
-                        let start_ix = buffer.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
-                        ExcerptRange {
-                            context: start_ix..end_ix,
-                            primary: None,
-                        }
-                    })
-                    .collect::<Vec<_>>();
-                log::info!(
-                    "Inserting excerpts from buffer {} and ranges {:?}: {:?}",
-                    buffer_handle.read(cx).remote_id(),
-                    ranges.iter().map(|r| &r.context).collect::<Vec<_>>(),
-                    ranges
-                        .iter()
-                        .map(|r| &buffer_text[r.context.clone()])
-                        .collect::<Vec<_>>()
-                );
+            let predecessor = new_excerpts
+                .last()
+                .map_or(ExcerptId::min(), |excerpt| excerpt.id);
+            let edit_start = new_excerpts.summary().text.len;
+            new_excerpts.update_last(|excerpt| excerpt.has_trailing_newline = true, &());
+            new_excerpts.push(
+                Excerpt::new(
+                    id,
+                    locator,
+                    buffer_id,
+                    buffer_snapshot,
+                    range.clone(),
+                    cursor.item().is_some(),
+                ),
+                &(),
+            );
+            let edit_end = new_excerpts.summary().text.len;
+
+            let suffix = cursor.suffix(&());
+            let changed_trailing_excerpt = suffix.is_empty();
+            new_excerpts.append(suffix, &());
+            drop(cursor);
+            snapshot.excerpts = new_excerpts;
+            snapshot.excerpt_update_count += 1;
+            if changed_trailing_excerpt {
+                snapshot.trailing_excerpt_update_count += 1;
+            }
+            drop(snapshot);
+            drop(buffers);
 
-                let excerpt_id = self.push_excerpts(buffer_handle.clone(), ranges, cx);
-                log::info!("Inserted with ids: {:?}", excerpt_id);
-            } else {
-                let remove_count = rng.gen_range(1..=excerpt_ids.len());
-                let mut excerpts_to_remove = excerpt_ids
-                    .choose_multiple(rng, remove_count)
-                    .cloned()
-                    .collect::<Vec<_>>();
-                let snapshot = self.snapshot.borrow();
-                excerpts_to_remove.sort_unstable_by(|a, b| a.cmp(b, &*snapshot));
-                drop(snapshot);
-                log::info!("Removing excerpts {:?}", excerpts_to_remove);
-                self.remove_excerpts(excerpts_to_remove, cx);
+            let edit = Edit {
+                old: edit_start..edit_start,
+                new: edit_start..edit_end,
+            };
+            self.subscriptions.publish_mut([edit.clone()]);
+            self.edit_sources.publish(vec![EditSource {
+                excerpt_id: id,
+                buffer_id,
+                edit,
+            }]);
+            cx.emit(Event::Edited {
+                singleton_buffer_edited: false,
+            });
+            cx.emit(Event::ExcerptsAdded {
+                buffer,
+                predecessor,
+                excerpts: vec![(id, range)],
+            });
+        }
+        cx.notify();
+    }
+
+    /// Reassigns every excerpt locator, including those of removed excerpts, so that they're
+    /// as short as possible while preserving their relative order. Anchors refer to excerpts
+    /// by id, so they remain valid.
+    fn rebalance_locators(&mut self) {
+        let mut snapshot = self.snapshot_mut();
+        let mut mappings = snapshot.excerpt_ids.iter().cloned().collect::<Vec<_>>();
+        mappings.sort_by(|a, b| a.locator.cmp(&b.locator));
+
+        let mut new_locators = HashMap::<Locator, Locator>::default();
+        let mut prev_locator = Locator::min();
+        for mapping in &mut mappings {
+            // Excerpts that shared a locator keep sharing one.
+            let locator = new_locators
+                .entry(mapping.locator.clone())
+                .or_insert_with(|| {
+                    prev_locator = Locator::between(&prev_locator, Locator::max_ref());
+                    prev_locator.clone()
+                })
+                .clone();
+            mapping.locator = locator;
+        }
+
+        let excerpts = snapshot
+            .excerpts
+            .iter()
+            .cloned()
+            .map(|mut excerpt| {
+                excerpt.locator = new_locators[&excerpt.locator].clone();
+                excerpt
+            })
+            .collect::<Vec<_>>();
+        snapshot.excerpts = SumTree::from_iter(excerpts, &());
+        let mut excerpt_ids_by_locator = mappings
+            .iter()
+            .map(|mapping| (mapping.locator.clone(), mapping.id))
+            .collect::<Vec<_>>();
+        excerpt_ids_by_locator.dedup_by(|a, b| a.0 == b.0);
+        snapshot.excerpt_ids_by_locator = TreeMap::from_ordered_entries(excerpt_ids_by_locator);
+        mappings.sort_by_key(|mapping| mapping.id);
+        snapshot.excerpt_ids = SumTree::from_iter(mappings, &());
+        drop(snapshot);
+
+        for buffer_state in self.buffers.borrow_mut().values_mut() {
+            for locator in &mut buffer_state.excerpts {
+                *locator = new_locators[locator].clone();
             }
         }
     }
 
-    pub fn randomly_mutate(
+    fn remove_recorded_excerpts(
         &mut self,
-        rng: &mut impl rand::Rng,
-        mutation_count: usize,
+        excerpts: Vec<ExcerptRecord>,
         cx: &mut ModelContext<Self>,
     ) {
-        use rand::prelude::*;
-
-        if rng.gen_bool(0.7) || self.singleton {
-            let buffer = self
-                .buffers
-                .borrow()
-                .values()
-                .choose(rng)
-                .map(|state| state.buffer.clone());
-
-            if let Some(buffer) = buffer {
-                buffer.update(cx, |buffer, cx| {
-                    if rng.gen() {
-                        buffer.randomly_edit(rng, mutation_count, cx);
-                    } else {
-                        buffer.randomly_undo_redo(rng, cx);
-                    }
-                });
-            } else {
-                self.randomly_edit(rng, mutation_count, cx);
-            }
-        } else {
-            self.randomly_edit_excerpts(rng, mutation_count, cx);
+        if excerpts.is_empty() {
+            return;
         }
 
-        self.check_invariants(cx);
+        // `remove_excerpts` expects the excerpts in the order they appear in the multi-buffer.
+        let mut ids = excerpts
+            .into_iter()
+            .map(|excerpt| excerpt.id)
+            .collect::<Vec<_>>();
+        let snapshot = self.snapshot.borrow();
+        ids.sort_by_cached_key(|id| snapshot.excerpt_locator_for_id(*id).clone());
+        ids.dedup();
+        drop(snapshot);
+        self.remove_excerpts(ids, cx);
     }
 
-    fn check_invariants(&self, cx: &mut ModelContext<Self>) {
-        let snapshot = self.read(cx);
-        let excerpts = snapshot.excerpts.items(&());
-        let excerpt_ids = snapshot.excerpt_ids.items(&());
-
-        for (ix, excerpt) in excerpts.iter().enumerate() {
-            if ix == 0 {
-                if excerpt.locator <= Locator::min() {
-                    panic!("invalid first excerpt locator {:?}", excerpt.locator);
-                }
-            } else {
-                if excerpt.locator <= excerpts[ix - 1].locator {
-                    panic!("excerpts are out-of-order: {:?}", excerpts);
-                }
+    /// Returns the snapshot shared by the excerpts of the given buffer, or a new one if the
+    /// buffer isn't excerpted yet or has been edited since it was last synced.
+    fn shared_buffer_snapshot(
+        &self,
+        buffers: &HashMap<BufferId, BufferState>,
+        buffer: &Model<Buffer>,
+        cx: &AppContext,
+    ) -> Arc<BufferSnapshot> {
+        let buffer = buffer.read(cx);
+        match buffers.get(&buffer.remote_id()) {
+            Some(state)
+                if text::BufferSnapshot::version(&state.snapshot)
+                    == text::BufferSnapshot::version(buffer) =>
+            {
+                state.snapshot.clone()
             }
+            _ => Arc::new(buffer.snapshot()),
         }
+    }
 
-        for (ix, entry) in excerpt_ids.iter().enumerate() {
-            if ix == 0 {
-                if entry.id.cmp(&ExcerptId::min(), &*snapshot).is_le() {
-                    panic!("invalid first excerpt id {:?}", entry.id);
-                }
-            } else {
-                if entry.id <= excerpt_ids[ix - 1].id {
-                    panic!("excerpt ids are out-of-order: {:?}", excerpt_ids);
-                }
-            }
+    fn new_buffer_state(
+        buffer: &Model<Buffer>,
+        buffer_snapshot: Arc<BufferSnapshot>,
+        cx: &mut ModelContext<Self>,
+    ) -> BufferState {
+        BufferState {
+            last_version: buffer_snapshot.version().clone(),
+            last_parse_count: buffer_snapshot.parse_count(),
+            last_selections_update_count: buffer_snapshot.selections_update_count(),
+            last_diagnostics_update_count: buffer_snapshot.diagnostics_update_count(),
+            last_file_update_count: buffer_snapshot.file_update_count(),
+            last_git_diff_update_count: buffer_snapshot.git_diff_update_count(),
+            last_is_dirty: false,
+            last_has_conflict: false,
+            excerpts: Default::default(),
+            excerpt_ids: None,
+            snapshot: buffer_snapshot,
+            _subscriptions: [
+                cx.observe(buffer, Self::on_buffer_notified),
+                cx.subscribe(buffer, Self::on_buffer_event),
+            ],
+            buffer: buffer.clone(),
         }
     }
-}
 
-impl EventEmitter<Event> for MultiBuffer {}
+    pub fn remove_excerpts(
+        &mut self,
+        excerpt_ids: impl IntoIterator<Item = ExcerptId>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.sync(cx);
+        let ids = excerpt_ids.into_iter().collect::<Vec<_>>();
+        if ids.is_empty() {
+            return;
+        }
 
-impl MultiBufferSnapshot {
-    pub fn text(&self) -> String {
-        self.chunks(0..self.len(), false)
-            .map(|chunk| chunk.text)
-            .collect()
-    }
+        let record_changes = !self.singleton && self.history.transaction_depth > 0;
+        let mut removed_records = Vec::new();
+        let mut removed_buffers = Vec::new();
+        let mut buffers = self.buffers.borrow_mut();
+        let mut snapshot = self.snapshot_mut();
+        let mut new_excerpts = SumTree::new();
+        let mut cursor = snapshot.excerpts.cursor::<(Option<&Locator>, usize)>();
+        let mut edits = Vec::new();
+        let mut edit_sources = Vec::new();
+        let mut excerpt_ids = ids.iter().copied().peekable();
 
-    pub fn reversed_chars_at<T: ToOffset>(&self, position: T) -> impl Iterator<Item = char> + '_ {
-        let mut offset = position.to_offset(self);
-        let mut cursor = self.excerpts.cursor::<usize>();
-        cursor.seek(&offset, Bias::Left, &());
-        let mut excerpt_chunks = cursor.item().map(|excerpt| {
-            let end_before_footer = cursor.start() + excerpt.text_summary.len;
-            let start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let end = start + (cmp::min(offset, end_before_footer) - cursor.start());
-            excerpt.buffer.reversed_chunks_in_range(start..end)
-        });
-        iter::from_fn(move || {
-            if offset == *cursor.start() {
-                cursor.prev(&());
-                let excerpt = cursor.item()?;
-                excerpt_chunks = Some(
-                    excerpt
-                        .buffer
-                        .reversed_chunks_in_range(excerpt.range.context.clone()),
-                );
-            }
-
-            let excerpt = cursor.item().unwrap();
-            if offset == cursor.end(&()) && excerpt.has_trailing_newline {
-                offset -= 1;
-                Some("\n")
-            } else {
-                let chunk = excerpt_chunks.as_mut().unwrap().next().unwrap();
-                offset -= chunk.len();
-                Some(chunk)
-            }
-        })
-        .flat_map(|c| c.chars().rev())
-    }
-
-    pub fn chars_at<T: ToOffset>(&self, position: T) -> impl Iterator<Item = char> + '_ {
-        let offset = position.to_offset(self);
-        self.text_for_range(offset..self.len())
-            .flat_map(|chunk| chunk.chars())
-    }
+        while let Some(excerpt_id) = excerpt_ids.next() {
+            // Seek to the next excerpt to remove, preserving any preceding excerpts.
+            let locator = snapshot.excerpt_locator_for_id(excerpt_id);
+            new_excerpts.append(cursor.slice(&Some(locator), Bias::Left, &()), &());
 
-    pub fn text_for_range<T: ToOffset>(&self, range: Range<T>) -> impl Iterator<Item = &str> + '_ {
-        self.chunks(range, false).map(|chunk| chunk.text)
-    }
+            if let Some(mut excerpt) = cursor.item() {
+                if excerpt.id != excerpt_id {
+                    continue;
+                }
+                let mut old_start = cursor.start().1;
+                let mut removed_excerpts = Vec::new();
 
-    pub fn is_line_blank(&self, row: u32) -> bool {
-        self.text_for_range(Point::new(row, 0)..Point::new(row, self.line_len(row)))
-            .all(|chunk| chunk.matches(|c: char| !c.is_whitespace()).next().is_none())
-    }
+                // Skip over the removed excerpt.
+                'remove_excerpts: loop {
+                    removed_excerpts.push((excerpt.id, excerpt.buffer_id));
+                    if let Some(buffer_state) = buffers.get_mut(&excerpt.buffer_id) {
+                        if record_changes {
+                            removed_records.push(ExcerptRecord {
+                                id: excerpt.id,
+                                buffer: buffer_state.buffer.clone(),
+                                range: excerpt.range.clone(),
+                            });
+                        }
+                        buffer_state.excerpts.retain(|l| l != &excerpt.locator);
+                        buffer_state.excerpt_ids = None;
+                        if buffer_state.excerpts.is_empty() {
+                            if let Some(buffer_state) = buffers.remove(&excerpt.buffer_id) {
+                                removed_buffers.push(buffer_state.buffer);
+                            }
+                        }
+                    }
+                    cursor.next(&());
 
-    pub fn contains_str_at<T>(&self, position: T, needle: &str) -> bool
-    where
-        T: ToOffset,
-    {
-        let position = position.to_offset(self);
-        position == self.clip_offset(position, Bias::Left)
-            && self
-                .bytes_in_range(position..self.len())
-                .flatten()
-                .copied()
-                .take(needle.len())
-                .eq(needle.bytes())
-    }
+                    // Skip over any subsequent excerpts that are also removed.
+                    while let Some(&next_excerpt_id) = excerpt_ids.peek() {
+                        let next_locator = snapshot.excerpt_locator_for_id(next_excerpt_id);
+                        if let Some(next_excerpt) = cursor.item() {
+                            if next_excerpt.locator == *next_locator {
+                                excerpt_ids.next();
+                                excerpt = next_excerpt;
+                                continue 'remove_excerpts;
+                            }
+                        }
+                        break;
+                    }
 
-    pub fn surrounding_word<T: ToOffset>(&self, start: T) -> (Range<usize>, Option<CharKind>) {
-        let mut start = start.to_offset(self);
-        let mut end = start;
-        let mut next_chars = self.chars_at(start).peekable();
-        let mut prev_chars = self.reversed_chars_at(start).peekable();
+                    break;
+                }
 
-        let scope = self.language_scope_at(start);
-        let kind = |c| char_kind(&scope, c);
-        let word_kind = cmp::max(
-            prev_chars.peek().copied().map(kind),
-            next_chars.peek().copied().map(kind),
-        );
+                // When removing the last excerpt, remove the trailing newline from
+                // the previous excerpt.
+                if cursor.item().is_none() && old_start > 0 {
+                    old_start -= 1;
+                    new_excerpts.update_last(|e| e.has_trailing_newline = false, &());
+                }
 
-        for ch in prev_chars {
-            if Some(kind(ch)) == word_kind && ch != '\n' {
-                start -= ch.len_utf8();
-            } else {
-                break;
+                // Push an edit for the removal of this run of excerpts.
+                let old_end = cursor.start().1;
+                let new_start = new_excerpts.summary().text.len;
+                let edit = Edit {
+                    old: old_start..old_end,
+                    new: new_start..new_start,
+                };
+                edit_sources.extend(removed_excerpts.into_iter().map(|(excerpt_id, buffer_id)| {
+                    EditSource {
+                        excerpt_id,
+                        buffer_id,
+                        edit: edit.clone(),
+                    }
+                }));
+                edits.push(edit);
             }
         }
+        let suffix = cursor.suffix(&());
+        let changed_trailing_excerpt = suffix.is_empty();
+        new_excerpts.append(suffix, &());
+        drop(cursor);
+        snapshot.excerpts = new_excerpts;
+        snapshot.excerpt_update_count += 1;
+        for id in &ids {
+            snapshot.elided_excerpts.remove(id);
+            snapshot.excerpt_annotations.remove(id);
+        }
 
-        for ch in next_chars {
-            if Some(kind(ch)) == word_kind && ch != '\n' {
-                end += ch.len_utf8();
-            } else {
-                break;
+        if changed_trailing_excerpt {
+            snapshot.trailing_excerpt_update_count += 1;
+        }
+        if !removed_buffers.is_empty() {
+            (snapshot.is_dirty, snapshot.has_conflict) = dirty_and_conflict(&buffers);
+        }
+        drop(snapshot);
+        drop(buffers);
+
+        if record_changes {
+            // Close the transactions of buffers that left mid-transaction, so that their
+            // edits can still be undone along with the current transaction.
+            let depth = self.history.transaction_depth;
+            let now = Instant::now();
+            for buffer in removed_buffers {
+                let mut buffer_transaction_id = None;
+                buffer.update(cx, |buffer, cx| {
+                    for _ in 0..depth {
+                        buffer_transaction_id = buffer.end_transaction_at(now, cx);
+                    }
+                });
+                if let Some((transaction, buffer_transaction_id)) = self
+                    .history
+                    .undo_stack
+                    .last_mut()
+                    .zip(buffer_transaction_id)
+                {
+                    transaction
+                        .buffer_transactions
+                        .entry(buffer.read(cx).remote_id())
+                        .or_insert(buffer_transaction_id);
+                }
+            }
+            if let Some(transaction) = self.history.undo_stack.last_mut() {
+                transaction
+                    .excerpt_changes
+                    .extend(removed_records.into_iter().map(ExcerptChange::Removed));
             }
         }
 
-        (start..end, word_kind)
+        self.subscriptions.publish_mut(edits);
+        self.edit_sources.publish(edit_sources);
+        cx.emit(Event::Edited {
+            singleton_buffer_edited: false,
+        });
+        cx.emit(Event::ExcerptsRemoved { ids });
+        cx.notify();
     }
 
-    pub fn as_singleton(&self) -> Option<(&ExcerptId, BufferId, &BufferSnapshot)> {
-        if self.singleton {
-            self.excerpts
-                .iter()
-                .next()
-                .map(|e| (&e.id, e.buffer_id, &e.buffer))
-        } else {
-            None
+    pub fn wait_for_anchors<'a>(
+        &self,
+        anchors: impl 'a + Iterator<Item = Anchor>,
+        cx: &mut ModelContext<Self>,
+    ) -> impl 'static + Future<Output = Result<()>> {
+        let borrow = self.buffers.borrow();
+        let mut error = None;
+        let mut futures = Vec::new();
+        for anchor in anchors {
+            if let Some(buffer_id) = anchor.buffer_id {
+                if let Some(buffer) = borrow.get(&buffer_id) {
+                    buffer.buffer.update(cx, |buffer, _| {
+                        futures.push(buffer.wait_for_anchors([anchor.text_anchor]))
+                    });
+                } else {
+                    error = Some(anyhow!(
+                        "buffer {buffer_id} is not part of this multi-buffer"
+                    ));
+                    break;
+                }
+            }
+        }
+        async move {
+            if let Some(error) = error {
+                Err(error)?;
+            }
+            for future in futures {
+                future.await?;
+            }
+            Ok(())
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.excerpts.summary().text.len
+    pub fn text_anchor_for_position<T: ToOffset>(
+        &self,
+        position: T,
+        cx: &AppContext,
+    ) -> Option<(Model<Buffer>, language::Anchor)> {
+        let snapshot = self.read(cx);
+        let anchor = snapshot.anchor_before(position);
+        let buffer = self
+            .buffers
+            .borrow()
+            .get(&anchor.buffer_id?)?
+            .buffer
+            .clone();
+        Some((buffer, anchor.text_anchor))
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.excerpts.summary().text.len == 0
+    fn on_buffer_notified(&mut self, buffer: Model<Buffer>, cx: &mut ModelContext<Self>) {
+        self.mark_buffer_dirty(buffer.read(cx).remote_id());
+        cx.notify();
     }
 
-    pub fn max_buffer_row(&self) -> u32 {
-        self.excerpts.summary().max_buffer_row
+    /// Records that the given buffer's state other than its text may have changed, so that
+    /// the next sync checks it. Buffers only notify their observers once the current update
+    /// finishes, so this is called when the multi-buffer updates a buffer's selections and
+    /// may sync before then.
+    fn mark_buffer_dirty(&self, buffer_id: BufferId) {
+        self.dirty_buffers.borrow_mut().insert(buffer_id);
     }
 
-    pub fn clip_offset(&self, offset: usize, bias: Bias) -> usize {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.clip_offset(offset, bias);
-        }
+    fn on_buffer_event(
+        &mut self,
+        buffer: Model<Buffer>,
+        event: &language::Event,
+        cx: &mut ModelContext<Self>,
+    ) {
+        cx.emit(match event {
+            language::Event::Edited => Event::Edited {
+                singleton_buffer_edited: true,
+            },
+            language::Event::DirtyChanged => Event::DirtyChanged,
+            language::Event::Saved => Event::Saved,
+            language::Event::FileHandleChanged => Event::FileHandleChanged,
+            language::Event::Reloaded => Event::Reloaded,
+            language::Event::DiffBaseChanged => Event::DiffBaseChanged,
+            language::Event::LanguageChanged => Event::LanguageChanged,
+            language::Event::Reparsed => Event::Reparsed,
+            language::Event::DiagnosticsUpdated => Event::DiagnosticsUpdated,
+            language::Event::Closed => Event::Closed,
+            language::Event::CapabilityChanged => {
+                self.capability = buffer.read(cx).capability();
+                Event::CapabilityChanged
+            }
 
-        let mut cursor = self.excerpts.cursor::<usize>();
-        cursor.seek(&offset, Bias::Right, &());
-        let overshoot = if let Some(excerpt) = cursor.item() {
-            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let buffer_offset = excerpt
-                .buffer
-                .clip_offset(excerpt_start + (offset - cursor.start()), bias);
-            buffer_offset.saturating_sub(excerpt_start)
-        } else {
-            0
-        };
-        cursor.start() + overshoot
+            //
+            language::Event::Operation(_) => return,
+        });
+
+        if matches!(event, language::Event::Edited) {
+            self.remove_emptied_excerpts(cx);
+        }
     }
 
-    pub fn clip_point(&self, point: Point, bias: Bias) -> Point {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.clip_point(point, bias);
+    fn remove_emptied_excerpts(&mut self, cx: &mut ModelContext<Self>) {
+        if !self.remove_emptied_excerpts || self.singleton {
+            return;
         }
 
-        let mut cursor = self.excerpts.cursor::<Point>();
-        cursor.seek(&point, Bias::Right, &());
-        let overshoot = if let Some(excerpt) = cursor.item() {
-            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer);
-            let buffer_point = excerpt
-                .buffer
-                .clip_point(excerpt_start + (point - cursor.start()), bias);
-            buffer_point.saturating_sub(excerpt_start)
-        } else {
-            Point::zero()
-        };
-        *cursor.start() + overshoot
-    }
-
-    pub fn clip_offset_utf16(&self, offset: OffsetUtf16, bias: Bias) -> OffsetUtf16 {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.clip_offset_utf16(offset, bias);
+        self.sync(cx);
+        let mut excerpt_ids = mem::take(&mut *self.emptied_excerpts.borrow_mut());
+        let snapshot = self.read(cx);
+        // Excerpts may have been removed or refilled since they were emptied.
+        excerpt_ids.retain(|id| {
+            snapshot
+                .excerpt(*id)
+                .map_or(false, |excerpt| excerpt.text_summary.len == 0)
+        });
+        excerpt_ids.sort_by(|a, b| a.cmp(b, &snapshot));
+        excerpt_ids.dedup();
+        drop(snapshot);
+        if !excerpt_ids.is_empty() {
+            self.remove_excerpts(excerpt_ids, cx);
         }
-
-        let mut cursor = self.excerpts.cursor::<OffsetUtf16>();
-        cursor.seek(&offset, Bias::Right, &());
-        let overshoot = if let Some(excerpt) = cursor.item() {
-            let excerpt_start = excerpt.range.context.start.to_offset_utf16(&excerpt.buffer);
-            let buffer_offset = excerpt
-                .buffer
-                .clip_offset_utf16(excerpt_start + (offset - cursor.start()), bias);
-            OffsetUtf16(buffer_offset.0.saturating_sub(excerpt_start.0))
-        } else {
-            OffsetUtf16(0)
-        };
-        *cursor.start() + overshoot
     }
 
-    pub fn clip_point_utf16(&self, point: Unclipped<PointUtf16>, bias: Bias) -> PointUtf16 {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.clip_point_utf16(point, bias);
-        }
-
-        let mut cursor = self.excerpts.cursor::<PointUtf16>();
-        cursor.seek(&point.0, Bias::Right, &());
-        let overshoot = if let Some(excerpt) = cursor.item() {
-            let excerpt_start = excerpt
-                .buffer
-                .offset_to_point_utf16(excerpt.range.context.start.to_offset(&excerpt.buffer));
-            let buffer_point = excerpt
-                .buffer
-                .clip_point_utf16(Unclipped(excerpt_start + (point.0 - cursor.start())), bias);
-            buffer_point.saturating_sub(excerpt_start)
-        } else {
-            PointUtf16::zero()
-        };
-        *cursor.start() + overshoot
+    pub fn all_buffers(&self) -> HashSet<Model<Buffer>> {
+        self.buffers
+            .borrow()
+            .values()
+            .map(|state| state.buffer.clone())
+            .collect()
     }
 
-    pub fn bytes_in_range<T: ToOffset>(&self, range: Range<T>) -> MultiBufferBytes {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
-        let mut excerpts = self.excerpts.cursor::<usize>();
-        excerpts.seek(&range.start, Bias::Right, &());
+    pub fn buffer(&self, buffer_id: BufferId) -> Option<Model<Buffer>> {
+        self.buffers
+            .borrow()
+            .get(&buffer_id)
+            .map(|state| state.buffer.clone())
+    }
 
-        let mut chunk = &[][..];
-        let excerpt_bytes = if let Some(excerpt) = excerpts.item() {
-            let mut excerpt_bytes = excerpt
-                .bytes_in_range(range.start - excerpts.start()..range.end - excerpts.start());
-            chunk = excerpt_bytes.next().unwrap_or(&[][..]);
-            Some(excerpt_bytes)
+    pub fn is_completion_trigger(&self, position: Anchor, text: &str, cx: &AppContext) -> bool {
+        let mut chars = text.chars();
+        let char = if let Some(char) = chars.next() {
+            char
         } else {
-            None
+            return false;
         };
-        MultiBufferBytes {
-            range,
-            excerpts,
-            excerpt_bytes,
-            chunk,
+        if chars.next().is_some() {
+            return false;
+        }
+
+        let snapshot = self.snapshot(cx);
+        let position = position.to_offset(&snapshot);
+        let scope = snapshot.language_scope_at(position);
+        if char_kind(&scope, char) == CharKind::Word {
+            return true;
         }
+
+        let anchor = snapshot.anchor_before(position);
+        anchor
+            .buffer_id
+            .and_then(|buffer_id| {
+                let buffer = self.buffers.borrow().get(&buffer_id)?.buffer.clone();
+                Some(
+                    buffer
+                        .read(cx)
+                        .completion_triggers()
+                        .iter()
+                        .any(|string| string == text),
+                )
+            })
+            .unwrap_or(false)
     }
 
-    pub fn reversed_bytes_in_range<T: ToOffset>(
+    pub fn language_at<'a, T: ToOffset>(
         &self,
-        range: Range<T>,
-    ) -> ReversedMultiBufferBytes {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
-        let mut excerpts = self.excerpts.cursor::<usize>();
-        excerpts.seek(&range.end, Bias::Left, &());
-
-        let mut chunk = &[][..];
-        let excerpt_bytes = if let Some(excerpt) = excerpts.item() {
-            let mut excerpt_bytes = excerpt.reversed_bytes_in_range(
-                range.start - excerpts.start()..range.end - excerpts.start(),
-            );
-            chunk = excerpt_bytes.next().unwrap_or(&[][..]);
-            Some(excerpt_bytes)
-        } else {
-            None
-        };
+        point: T,
+        cx: &'a AppContext,
+    ) -> Option<Arc<Language>> {
+        self.point_to_buffer_offset(point, cx)
+            .and_then(|(buffer, offset, _)| buffer.read(cx).language_at(offset))
+    }
 
-        ReversedMultiBufferBytes {
-            range,
-            excerpts,
-            excerpt_bytes,
-            chunk,
+    pub fn settings_at<'a, T: ToOffset>(
+        &self,
+        point: T,
+        cx: &'a AppContext,
+    ) -> &'a LanguageSettings {
+        let mut language = None;
+        let mut file = None;
+        if let Some((buffer, offset, _)) = self.point_to_buffer_offset(point, cx) {
+            let buffer = buffer.read(cx);
+            language = buffer.language_at(offset);
+            file = buffer.file();
         }
+        language_settings(language.as_ref(), file, cx)
     }
 
-    pub fn buffer_rows(&self, start_row: u32) -> MultiBufferRows {
-        let mut result = MultiBufferRows {
-            buffer_row_range: 0..0,
-            excerpts: self.excerpts.cursor(),
-        };
-        result.seek(start_row);
-        result
+    /// The indentation detected in the given buffer's contents, if it is consistent enough
+    /// to tell.
+    pub fn detected_indent(&self, buffer_id: BufferId, cx: &AppContext) -> Option<IndentSize> {
+        self.buffers
+            .borrow()
+            .get(&buffer_id)?
+            .buffer
+            .read(cx)
+            .detected_indent()
     }
 
-    pub fn chunks<T: ToOffset>(&self, range: Range<T>, language_aware: bool) -> MultiBufferChunks {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
-        let mut chunks = MultiBufferChunks {
-            range: range.clone(),
-            excerpts: self.excerpts.cursor(),
-            excerpt_chunks: None,
-            language_aware,
-        };
-        chunks.seek(range.start);
-        chunks
+    /// The `.editorconfig` properties that apply to the file of the buffer at `point`.
+    pub fn editor_config_at<T: ToOffset>(&self, point: T, cx: &AppContext) -> EditorConfig {
+        self.point_to_buffer_offset(point, cx)
+            .map(|(buffer, _, _)| *buffer.read(cx).editor_config())
+            .unwrap_or_default()
     }
 
-    pub fn offset_to_point(&self, offset: usize) -> Point {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.offset_to_point(offset);
+    /// The unit of indentation to use at `point`. This is the indentation configured by the
+    /// buffer's `.editorconfig` files, falling back to the one detected in its contents or
+    /// configured for its language.
+    pub fn indent_size_at<T: ToOffset>(&self, point: T, cx: &AppContext) -> IndentSize {
+        if let Some((buffer, offset, _)) = self.point_to_buffer_offset(point, cx) {
+            return buffer.read(cx).indent_size_at(offset, cx);
         }
 
-        let mut cursor = self.excerpts.cursor::<(usize, Point)>();
-        cursor.seek(&offset, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_offset, start_point) = cursor.start();
-            let overshoot = offset - start_offset;
-            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
-            let buffer_point = excerpt
-                .buffer
-                .offset_to_point(excerpt_start_offset + overshoot);
-            *start_point + (buffer_point - excerpt_start_point)
+        let settings = language_settings(None, None, cx);
+        if settings.hard_tabs {
+            IndentSize::tab()
         } else {
-            self.excerpts.summary().text.lines
+            IndentSize::spaces(settings.tab_size.get())
         }
     }
 
-    pub fn offset_to_point_utf16(&self, offset: usize) -> PointUtf16 {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.offset_to_point_utf16(offset);
-        }
-
-        let mut cursor = self.excerpts.cursor::<(usize, PointUtf16)>();
-        cursor.seek(&offset, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_offset, start_point) = cursor.start();
-            let overshoot = offset - start_offset;
-            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_start_point = excerpt.range.context.start.to_point_utf16(&excerpt.buffer);
-            let buffer_point = excerpt
-                .buffer
-                .offset_to_point_utf16(excerpt_start_offset + overshoot);
-            *start_point + (buffer_point - excerpt_start_point)
-        } else {
-            self.excerpts.summary().text.lines_utf16()
-        }
+    pub fn for_each_buffer(&self, mut f: impl FnMut(&Model<Buffer>)) {
+        self.buffers
+            .borrow()
+            .values()
+            .for_each(|state| f(&state.buffer))
     }
 
-    pub fn point_to_point_utf16(&self, point: Point) -> PointUtf16 {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.point_to_point_utf16(point);
+    pub fn title<'a>(&'a self, cx: &'a AppContext) -> Cow<'a, str> {
+        if let Some(title) = self.title.as_ref() {
+            return title.into();
         }
 
-        let mut cursor = self.excerpts.cursor::<(Point, PointUtf16)>();
-        cursor.seek(&point, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_offset, start_point) = cursor.start();
-            let overshoot = point - start_offset;
-            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
-            let excerpt_start_point_utf16 =
-                excerpt.range.context.start.to_point_utf16(&excerpt.buffer);
-            let buffer_point = excerpt
-                .buffer
-                .point_to_point_utf16(excerpt_start_point + overshoot);
-            *start_point + (buffer_point - excerpt_start_point_utf16)
-        } else {
-            self.excerpts.summary().text.lines_utf16()
+        if let Some(buffer) = self.as_singleton() {
+            if let Some(file) = buffer.read(cx).file() {
+                return file.file_name(cx).to_string_lossy();
+            }
         }
-    }
 
-    pub fn point_to_offset(&self, point: Point) -> usize {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.point_to_offset(point);
-        }
+        "untitled".into()
+    }
 
-        let mut cursor = self.excerpts.cursor::<(Point, usize)>();
-        cursor.seek(&point, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_point, start_offset) = cursor.start();
-            let overshoot = point - start_point;
-            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
-            let buffer_offset = excerpt
-                .buffer
-                .point_to_offset(excerpt_start_point + overshoot);
-            *start_offset + buffer_offset - excerpt_start_offset
-        } else {
-            self.excerpts.summary().text.len
-        }
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn is_parsing(&self, cx: &AppContext) -> bool {
+        self.as_singleton().unwrap().read(cx).is_parsing()
     }
 
-    pub fn offset_utf16_to_offset(&self, offset_utf16: OffsetUtf16) -> usize {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.offset_utf16_to_offset(offset_utf16);
-        }
+    fn sync(&self, cx: &AppContext) {
+        let mut dirty_buffers = self.dirty_buffers.borrow_mut();
+        let mut snapshot = self.snapshot.borrow_mut();
+        let mut excerpts_to_edit = Vec::new();
+        let mut reparsed = false;
+        let mut diagnostics_updated = false;
+        let mut git_diff_updated = false;
+        let mut selections_updated = false;
+        let mut dirty_or_conflict_changed = false;
+        let mut edited = false;
+        let mut buffers = self.buffers.borrow_mut();
+        for (buffer_id, buffer_state) in buffers.iter_mut() {
+            let buffer = buffer_state.buffer.read(cx);
+            // Borrow the version rather than cloning it, since most buffers are unchanged.
+            let version = text::BufferSnapshot::version(buffer);
+            // Buffers only notify this multi-buffer once the update that edited them finishes,
+            // so their versions are always compared. Their other state can only change along
+            // with a notification, so it's only checked for the buffers that sent one.
+            let buffer_edited = version.changed_since(&buffer_state.last_version);
+            if !dirty_buffers.remove(buffer_id) && !buffer_edited {
+                continue;
+            }
 
-        let mut cursor = self.excerpts.cursor::<(OffsetUtf16, usize)>();
-        cursor.seek(&offset_utf16, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_offset_utf16, start_offset) = cursor.start();
-            let overshoot = offset_utf16 - start_offset_utf16;
-            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_start_offset_utf16 =
-                excerpt.buffer.offset_to_offset_utf16(excerpt_start_offset);
-            let buffer_offset = excerpt
-                .buffer
-                .offset_utf16_to_offset(excerpt_start_offset_utf16 + overshoot);
-            *start_offset + (buffer_offset - excerpt_start_offset)
-        } else {
-            self.excerpts.summary().text.len
-        }
-    }
+            let parse_count = buffer.parse_count();
+            let selections_update_count = buffer.selections_update_count();
+            let diagnostics_update_count = buffer.diagnostics_update_count();
+            let file_update_count = buffer.file_update_count();
+            let git_diff_update_count = buffer.git_diff_update_count();
 
-    pub fn offset_to_offset_utf16(&self, offset: usize) -> OffsetUtf16 {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.offset_to_offset_utf16(offset);
-        }
+            let buffer_reparsed = parse_count > buffer_state.last_parse_count;
+            let buffer_selections_updated =
+                selections_update_count > buffer_state.last_selections_update_count;
+            let buffer_diagnostics_updated =
+                diagnostics_update_count > buffer_state.last_diagnostics_update_count;
+            let buffer_file_updated = file_update_count > buffer_state.last_file_update_count;
+            let buffer_git_diff_updated =
+                git_diff_update_count > buffer_state.last_git_diff_update_count;
+            if buffer_edited
+                || buffer_reparsed
+                || buffer_selections_updated
+                || buffer_diagnostics_updated
+                || buffer_file_updated
+                || buffer_git_diff_updated
+            {
+                buffer_state.last_version = version.clone();
+                buffer_state.last_parse_count = parse_count;
+                buffer_state.last_selections_update_count = selections_update_count;
+                buffer_state.last_diagnostics_update_count = diagnostics_update_count;
+                buffer_state.last_file_update_count = file_update_count;
+                buffer_state.last_git_diff_update_count = git_diff_update_count;
 
-        let mut cursor = self.excerpts.cursor::<(usize, OffsetUtf16)>();
-        cursor.seek(&offset, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_offset, start_offset_utf16) = cursor.start();
-            let overshoot = offset - start_offset;
-            let excerpt_start_offset_utf16 =
-                excerpt.range.context.start.to_offset_utf16(&excerpt.buffer);
-            let excerpt_start_offset = excerpt
-                .buffer
-                .offset_utf16_to_offset(excerpt_start_offset_utf16);
-            let buffer_offset_utf16 = excerpt
-                .buffer
-                .offset_to_offset_utf16(excerpt_start_offset + overshoot);
-            *start_offset_utf16 + (buffer_offset_utf16 - excerpt_start_offset_utf16)
-        } else {
-            self.excerpts.summary().text.len_utf16
-        }
-    }
+                // Take a single snapshot of the buffer and share it between all of its excerpts.
+                let buffer_snapshot = Arc::new(buffer.snapshot());
+                buffer_state.snapshot = buffer_snapshot.clone();
+                excerpts_to_edit.extend(
+                    buffer_state
+                        .excerpts
+                        .iter()
+                        .map(|locator| (locator, buffer_snapshot.clone(), buffer_edited)),
+                );
+            }
 
-    pub fn point_utf16_to_offset(&self, point: PointUtf16) -> usize {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer.point_utf16_to_offset(point);
-        }
+            let is_dirty = buffer.is_dirty();
+            let has_conflict = buffer.has_conflict();
+            dirty_or_conflict_changed |= is_dirty != buffer_state.last_is_dirty
+                || has_conflict != buffer_state.last_has_conflict;
+            buffer_state.last_is_dirty = is_dirty;
+            buffer_state.last_has_conflict = has_conflict;
 
-        let mut cursor = self.excerpts.cursor::<(PointUtf16, usize)>();
-        cursor.seek(&point, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let (start_point, start_offset) = cursor.start();
-            let overshoot = point - start_point;
-            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_start_point = excerpt
-                .buffer
-                .offset_to_point_utf16(excerpt.range.context.start.to_offset(&excerpt.buffer));
-            let buffer_offset = excerpt
-                .buffer
-                .point_utf16_to_offset(excerpt_start_point + overshoot);
-            *start_offset + (buffer_offset - excerpt_start_offset)
-        } else {
-            self.excerpts.summary().text.len
+            edited |= buffer_edited;
+            reparsed |= buffer_reparsed;
+            diagnostics_updated |= buffer_diagnostics_updated;
+            git_diff_updated |= buffer_git_diff_updated;
+            selections_updated |= buffer_selections_updated;
         }
-    }
+        // Drop any buffers that were removed since they were marked.
+        dirty_buffers.clear();
+        drop(dirty_buffers);
+        let (is_dirty, has_conflict) = if dirty_or_conflict_changed {
+            dirty_and_conflict(&buffers)
+        } else {
+            (snapshot.is_dirty, snapshot.has_conflict)
+        };
 
-    pub fn point_to_buffer_offset<T: ToOffset>(
-        &self,
-        point: T,
-    ) -> Option<(&BufferSnapshot, usize)> {
-        let offset = point.to_offset(self);
-        let mut cursor = self.excerpts.cursor::<usize>();
-        cursor.seek(&offset, Bias::Right, &());
-        if cursor.item().is_none() {
-            cursor.prev(&());
+        if edited {
+            snapshot.edit_count += 1;
         }
+        if reparsed {
+            snapshot.parse_count += 1;
+        }
+        if diagnostics_updated {
+            snapshot.diagnostics_update_count += 1;
+        }
+        if git_diff_updated {
+            snapshot.git_diff_update_count += 1;
+        }
+        if selections_updated {
+            snapshot.remote_selections_update_count += 1;
+        }
+        if edited
+            || reparsed
+            || diagnostics_updated
+            || git_diff_updated
+            || !excerpts_to_edit.is_empty()
+            || snapshot.is_dirty != is_dirty
+            || snapshot.has_conflict != has_conflict
+        {
+            self.snapshot_version.set(self.snapshot_version.get() + 1);
+            self.shared_snapshot.borrow_mut().take();
+        }
+        snapshot.is_dirty = is_dirty;
+        snapshot.has_conflict = has_conflict;
 
-        cursor.item().map(|excerpt| {
-            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let buffer_point = excerpt_start + offset - *cursor.start();
-            (&excerpt.buffer, buffer_point)
-        })
-    }
-
-    pub fn suggested_indents(
-        &self,
-        rows: impl IntoIterator<Item = u32>,
-        cx: &AppContext,
-    ) -> BTreeMap<u32, IndentSize> {
-        let mut result = BTreeMap::new();
+        // Avoid rebuilding the excerpt tree when none of the buffers have changed,
+        // which is by far the most common case.
+        if excerpts_to_edit.is_empty() {
+            return;
+        }
 
-        let mut rows_for_excerpt = Vec::new();
-        let mut cursor = self.excerpts.cursor::<Point>();
-        let mut rows = rows.into_iter().peekable();
-        let mut prev_row = u32::MAX;
-        let mut prev_language_indent_size = IndentSize::default();
+        excerpts_to_edit.sort_unstable_by_key(|(locator, _, _)| *locator);
 
-        while let Some(row) = rows.next() {
-            cursor.seek(&Point::new(row, 0), Bias::Right, &());
-            let excerpt = match cursor.item() {
-                Some(excerpt) => excerpt,
-                _ => continue,
-            };
+        let mut edits = Vec::new();
+        let mut edit_sources = Vec::new();
+        let mut new_excerpts = SumTree::new();
+        let mut cursor = snapshot.excerpts.cursor::<(Option<&Locator>, usize)>();
 
-            // Retrieve the language and indent size once for each disjoint region being indented.
-            let single_indent_size = if row.saturating_sub(1) == prev_row {
-                prev_language_indent_size
-            } else {
-                excerpt
-                    .buffer
-                    .language_indent_size_at(Point::new(row, 0), cx)
-            };
-            prev_language_indent_size = single_indent_size;
-            prev_row = row;
+        for (locator, buffer, buffer_edited) in excerpts_to_edit {
+            new_excerpts.append(cursor.slice(&Some(locator), Bias::Left, &()), &());
+            let old_excerpt = cursor.item().unwrap();
+            let buffer_id = buffer.remote_id();
 
-            let start_buffer_row = excerpt.range.context.start.to_point(&excerpt.buffer).row;
-            let start_multibuffer_row = cursor.start().row;
+            let mut new_excerpt;
+            if buffer_edited {
+                let edits_start = edits.len();
+                edits.extend(
+                    buffer
+                        .edits_since_in_range::<usize>(
+                            old_excerpt.buffer.version(),
+                            old_excerpt.range.context.clone(),
+                        )
+                        .map(|mut edit| {
+                            let excerpt_old_start = cursor.start().1;
+                            let excerpt_new_start = new_excerpts.summary().text.len;
+                            edit.old.start += excerpt_old_start;
+                            edit.old.end += excerpt_old_start;
+                            edit.new.start += excerpt_new_start;
+                            edit.new.end += excerpt_new_start;
+                            edit
+                        }),
+                );
+                edit_sources.extend(edits[edits_start..].iter().map(|edit| EditSource {
+                    excerpt_id: old_excerpt.id,
+                    buffer_id,
+                    edit: edit.clone(),
+                }));
 
-            rows_for_excerpt.push(row);
-            while let Some(next_row) = rows.peek().copied() {
-                if cursor.end(&()).row > next_row {
-                    rows_for_excerpt.push(next_row);
-                    rows.next();
-                } else {
-                    break;
+                new_excerpt = Excerpt::new(
+                    old_excerpt.id,
+                    locator.clone(),
+                    buffer_id,
+                    buffer,
+                    old_excerpt.range.clone(),
+                    old_excerpt.has_trailing_newline,
+                );
+                if self.remove_emptied_excerpts
+                    && old_excerpt.text_summary.len > 0
+                    && new_excerpt.text_summary.len == 0
+                {
+                    self.emptied_excerpts.borrow_mut().push(old_excerpt.id);
                 }
+            } else {
+                new_excerpt = old_excerpt.clone();
+                new_excerpt.buffer = buffer;
             }
 
-            let buffer_rows = rows_for_excerpt
-                .drain(..)
-                .map(|row| start_buffer_row + row - start_multibuffer_row);
-            let buffer_indents = excerpt
-                .buffer
-                .suggested_indents(buffer_rows, single_indent_size);
-            let multibuffer_indents = buffer_indents
-                .into_iter()
-                .map(|(row, indent)| (start_multibuffer_row + row - start_buffer_row, indent));
-            result.extend(multibuffer_indents);
+            new_excerpts.push(new_excerpt, &());
+            cursor.next(&());
         }
+        new_excerpts.append(cursor.suffix(&()), &());
 
-        result
+        drop(cursor);
+        snapshot.excerpts = new_excerpts;
+
+        self.subscriptions.publish(edits);
+        self.edit_sources.publish(edit_sources);
     }
+}
 
-    pub fn indent_size_for_line(&self, row: u32) -> IndentSize {
-        if let Some((buffer, range)) = self.buffer_line_for_row(row) {
-            let mut size = buffer.indent_size_for_line(range.start.row);
-            size.len = size
-                .len
-                .min(range.end.column)
-                .saturating_sub(range.start.column);
-            size
-        } else {
-            IndentSize::spaces(0)
-        }
+#[cfg(any(test, feature = "test-support"))]
+impl MultiBuffer {
+    pub fn build_simple(text: &str, cx: &mut gpui::AppContext) -> Model<Self> {
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        cx.new_model(|cx| Self::singleton(buffer, cx))
     }
 
-    pub fn prev_non_blank_row(&self, mut row: u32) -> Option<u32> {
-        while row > 0 {
-            row -= 1;
-            if !self.is_line_blank(row) {
-                return Some(row);
-            }
+    pub fn build_multi<const COUNT: usize>(
+        excerpts: [(&str, Vec<Range<Point>>); COUNT],
+        cx: &mut gpui::AppContext,
+    ) -> Model<Self> {
+        let multi = cx.new_model(|_| Self::new(0, Capability::ReadWrite));
+        for (text, ranges) in excerpts {
+            let buffer = cx.new_model(|cx| {
+                Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+            });
+            let excerpt_ranges = ranges.into_iter().map(|range| ExcerptRange {
+                context: range,
+                primary: None,
+            });
+            multi.update(cx, |multi, cx| {
+                multi.push_excerpts(buffer, excerpt_ranges, cx)
+            });
         }
-        None
-    }
 
-    pub fn line_len(&self, row: u32) -> u32 {
-        if let Some((_, range)) = self.buffer_line_for_row(row) {
-            range.end.column - range.start.column
-        } else {
-            0
-        }
+        multi
     }
 
-    pub fn buffer_line_for_row(&self, row: u32) -> Option<(&BufferSnapshot, Range<Point>)> {
-        let mut cursor = self.excerpts.cursor::<Point>();
-        let point = Point::new(row, 0);
-        cursor.seek(&point, Bias::Right, &());
-        if cursor.item().is_none() && *cursor.start() == point {
-            cursor.prev(&());
-        }
-        if let Some(excerpt) = cursor.item() {
-            let overshoot = row - cursor.start().row;
-            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer);
-            let excerpt_end = excerpt.range.context.end.to_point(&excerpt.buffer);
-            let buffer_row = excerpt_start.row + overshoot;
-            let line_start = Point::new(buffer_row, 0);
-            let line_end = Point::new(buffer_row, excerpt.buffer.line_len(buffer_row));
-            return Some((
-                &excerpt.buffer,
-                line_start.max(excerpt_start)..line_end.min(excerpt_end),
-            ));
-        }
-        None
+    pub fn build_from_buffer(buffer: Model<Buffer>, cx: &mut gpui::AppContext) -> Model<Self> {
+        cx.new_model(|cx| Self::singleton(buffer, cx))
     }
 
-    pub fn max_point(&self) -> Point {
-        self.text_summary().lines
+    pub fn build_random(rng: &mut impl rand::Rng, cx: &mut gpui::AppContext) -> Model<Self> {
+        cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            let mutation_count = rng.gen_range(1..=5);
+            multibuffer.randomly_edit_excerpts(rng, mutation_count, cx);
+            multibuffer
+        })
     }
 
-    pub fn text_summary(&self) -> TextSummary {
-        self.excerpts.summary().text.clone()
-    }
+    pub fn randomly_edit(
+        &mut self,
+        rng: &mut impl rand::Rng,
+        edit_count: usize,
+        cx: &mut ModelContext<Self>,
+    ) {
+        use util::RandomCharIter;
 
-    pub fn text_summary_for_range<D, O>(&self, range: Range<O>) -> D
-    where
-        D: TextDimension,
-        O: ToOffset,
-    {
-        let mut summary = D::default();
-        let mut range = range.start.to_offset(self)..range.end.to_offset(self);
-        let mut cursor = self.excerpts.cursor::<usize>();
-        cursor.seek(&range.start, Bias::Right, &());
-        if let Some(excerpt) = cursor.item() {
-            let mut end_before_newline = cursor.end(&());
-            if excerpt.has_trailing_newline {
-                end_before_newline -= 1;
+        let snapshot = self.read(cx);
+        let mut edits: Vec<(Range<usize>, Arc<str>)> = Vec::new();
+        let mut last_end = None;
+        for _ in 0..edit_count {
+            if last_end.map_or(false, |last_end| last_end >= snapshot.len()) {
+                break;
             }
 
-            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let start_in_excerpt = excerpt_start + (range.start - cursor.start());
-            let end_in_excerpt =
-                excerpt_start + (cmp::min(end_before_newline, range.end) - cursor.start());
-            summary.add_assign(
-                &excerpt
-                    .buffer
-                    .text_summary_for_range(start_in_excerpt..end_in_excerpt),
-            );
+            let new_start = last_end.map_or(0, |last_end| last_end + 1);
+            let end = snapshot.clip_offset(rng.gen_range(new_start..=snapshot.len()), Bias::Right);
+            let start = snapshot.clip_offset(rng.gen_range(new_start..=end), Bias::Right);
+            last_end = Some(end);
 
-            if range.end > end_before_newline {
-                summary.add_assign(&D::from_text_summary(&TextSummary::from("\n")));
+            let mut range = start..end;
+            if rng.gen_bool(0.2) {
+                mem::swap(&mut range.start, &mut range.end);
             }
 
-            cursor.next(&());
-        }
-
-        if range.end > *cursor.start() {
-            summary.add_assign(&D::from_text_summary(&cursor.summary::<_, TextSummary>(
-                &range.end,
-                Bias::Right,
-                &(),
-            )));
-            if let Some(excerpt) = cursor.item() {
-                range.end = cmp::max(*cursor.start(), range.end);
+            let new_text_len = rng.gen_range(0..10);
+            let new_text: String = RandomCharIter::new(&mut *rng).take(new_text_len).collect();
 
-                let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-                let end_in_excerpt = excerpt_start + (range.end - cursor.start());
-                summary.add_assign(
-                    &excerpt
-                        .buffer
-                        .text_summary_for_range(excerpt_start..end_in_excerpt),
-                );
-            }
+            edits.push((range, new_text.into()));
         }
+        log::info!("mutating multi-buffer with {:?}", edits);
+        drop(snapshot);
 
-        summary
+        self.edit(edits, None, cx);
     }
 
-    pub fn summary_for_anchor<D>(&self, anchor: &Anchor) -> D
-    where
-        D: TextDimension + Ord + Sub<D, Output = D>,
-    {
-        let mut cursor = self.excerpts.cursor::<ExcerptSummary>();
-        let locator = self.excerpt_locator_for_id(anchor.excerpt_id);
+    pub fn randomly_edit_excerpts(
+        &mut self,
+        rng: &mut impl rand::Rng,
+        mutation_count: usize,
+        cx: &mut ModelContext<Self>,
+    ) {
+        use rand::prelude::*;
+        use std::env;
+        use util::RandomCharIter;
 
-        cursor.seek(locator, Bias::Left, &());
-        if cursor.item().is_none() {
-            cursor.next(&());
-        }
+        let max_excerpts = env::var("MAX_EXCERPTS")
+            .map(|i| i.parse().expect("invalid `MAX_EXCERPTS` variable"))
+            .unwrap_or(5);
 
-        let mut position = D::from_text_summary(&cursor.start().text);
-        if let Some(excerpt) = cursor.item() {
-            if excerpt.id == anchor.excerpt_id {
-                let excerpt_buffer_start =
-                    excerpt.range.context.start.summary::<D>(&excerpt.buffer);
-                let excerpt_buffer_end = excerpt.range.context.end.summary::<D>(&excerpt.buffer);
-                let buffer_position = cmp::min(
-                    excerpt_buffer_end,
-                    anchor.text_anchor.summary::<D>(&excerpt.buffer),
+        let mut buffers = Vec::new();
+        for _ in 0..mutation_count {
+            if rng.gen_bool(0.05) {
+                log::info!("Clearing multi-buffer");
+                self.clear(cx);
+                continue;
+            }
+
+            let excerpt_ids = self.excerpt_ids();
+            if excerpt_ids.is_empty() || (rng.gen() && excerpt_ids.len() < max_excerpts) {
+                let buffer_handle = if rng.gen() || self.buffers.borrow().is_empty() {
+                    let text = RandomCharIter::new(&mut *rng).take(10).collect::<String>();
+                    buffers.push(cx.new_model(|cx| {
+                        Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+                    }));
+                    let buffer = buffers.last().unwrap().read(cx);
+                    log::info!(
+                        "Creating new buffer {} with text: {:?}",
+                        buffer.remote_id(),
+                        buffer.text()
+                    );
+                    buffers.last().unwrap().clone()
+                } else {
+                    self.buffers
+                        .borrow()
+                        .values()
+                        .choose(rng)
+                        .unwrap()
+                        .buffer
+                        .clone()
+                };
+
+                let buffer = buffer_handle.read(cx);
+                let buffer_text = buffer.text();
+                let ranges = (0..rng.gen_range(0..5))
+                    .map(|_| {
+                        let end_ix =
+                            buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Right);
+                        let start_ix = buffer.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
+                        ExcerptRange {
+                            context: start_ix..end_ix,
+                            primary: None,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                log::info!(
+                    "Inserting excerpts from buffer {} and ranges {:?}: {:?}",
+                    buffer_handle.read(cx).remote_id(),
+                    ranges.iter().map(|r| &r.context).collect::<Vec<_>>(),
+                    ranges
+                        .iter()
+                        .map(|r| &buffer_text[r.context.clone()])
+                        .collect::<Vec<_>>()
                 );
-                if buffer_position > excerpt_buffer_start {
-                    position.add_assign(&(buffer_position - excerpt_buffer_start));
-                }
+
+                let excerpt_id = self.push_excerpts(buffer_handle.clone(), ranges, cx);
+                log::info!("Inserted with ids: {:?}", excerpt_id);
+            } else {
+                let remove_count = rng.gen_range(1..=excerpt_ids.len());
+                let mut excerpts_to_remove = excerpt_ids
+                    .choose_multiple(rng, remove_count)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let snapshot = self.snapshot.borrow();
+                excerpts_to_remove.sort_unstable_by(|a, b| a.cmp(b, &*snapshot));
+                drop(snapshot);
+                log::info!("Removing excerpts {:?}", excerpts_to_remove);
+                self.remove_excerpts(excerpts_to_remove, cx);
             }
         }
-        position
     }
 
-    pub fn summaries_for_anchors<'a, D, I>(&'a self, anchors: I) -> Vec<D>
-    where
-        D: TextDimension + Ord + Sub<D, Output = D>,
-        I: 'a + IntoIterator<Item = &'a Anchor>,
-    {
-        if let Some((_, _, buffer)) = self.as_singleton() {
-            return buffer
-                .summaries_for_anchors(anchors.into_iter().map(|a| &a.text_anchor))
-                .collect();
-        }
+    pub fn randomly_mutate(
+        &mut self,
+        rng: &mut impl rand::Rng,
+        mutation_count: usize,
+        cx: &mut ModelContext<Self>,
+    ) {
+        use rand::prelude::*;
 
-        let mut anchors = anchors.into_iter().peekable();
-        let mut cursor = self.excerpts.cursor::<ExcerptSummary>();
-        let mut summaries = Vec::new();
-        while let Some(anchor) = anchors.peek() {
-            let excerpt_id = anchor.excerpt_id;
-            let excerpt_anchors = iter::from_fn(|| {
-                let anchor = anchors.peek()?;
-                if anchor.excerpt_id == excerpt_id {
-                    Some(&anchors.next().unwrap().text_anchor)
-                } else {
-                    None
-                }
-            });
+        if rng.gen_bool(0.7) || self.singleton {
+            let buffer = self
+                .buffers
+                .borrow()
+                .values()
+                .choose(rng)
+                .map(|state| state.buffer.clone());
 
-            let locator = self.excerpt_locator_for_id(excerpt_id);
-            cursor.seek_forward(locator, Bias::Left, &());
-            if cursor.item().is_none() {
-                cursor.next(&());
+            if let Some(buffer) = buffer {
+                buffer.update(cx, |buffer, cx| {
+                    if rng.gen() {
+                        buffer.randomly_edit(rng, mutation_count, cx);
+                    } else {
+                        buffer.randomly_undo_redo(rng, cx);
+                    }
+                });
+            } else {
+                self.randomly_edit(rng, mutation_count, cx);
             }
+        } else {
+            self.randomly_edit_excerpts(rng, mutation_count, cx);
+        }
 
-            let position = D::from_text_summary(&cursor.start().text);
-            if let Some(excerpt) = cursor.item() {
-                if excerpt.id == excerpt_id {
-                    let excerpt_buffer_start =
-                        excerpt.range.context.start.summary::<D>(&excerpt.buffer);
-                    let excerpt_buffer_end =
-                        excerpt.range.context.end.summary::<D>(&excerpt.buffer);
-                    summaries.extend(
-                        excerpt
-                            .buffer
-                            .summaries_for_anchors::<D, _>(excerpt_anchors)
-                            .map(move |summary| {
-                                let summary = cmp::min(excerpt_buffer_end.clone(), summary);
-                                let mut position = position.clone();
-                                let excerpt_buffer_start = excerpt_buffer_start.clone();
-                                if summary > excerpt_buffer_start {
-                                    position.add_assign(&(summary - excerpt_buffer_start));
-                                }
-                                position
-                            }),
-                    );
-                    continue;
+        self.check_invariants(cx);
+    }
+
+    fn check_invariants(&self, cx: &mut ModelContext<Self>) {
+        let snapshot = self.read(cx);
+        let excerpts = snapshot.excerpts.items(&());
+        let excerpt_ids = snapshot.excerpt_ids.items(&());
+
+        for (ix, excerpt) in excerpts.iter().enumerate() {
+            if ix == 0 {
+                if excerpt.locator <= Locator::min() {
+                    panic!("invalid first excerpt locator {:?}", excerpt.locator);
+                }
+            } else {
+                if excerpt.locator <= excerpts[ix - 1].locator {
+                    panic!("excerpts are out-of-order: {:?}", excerpts);
                 }
             }
-
-            summaries.extend(excerpt_anchors.map(|_| position.clone()));
         }
 
-        summaries
+        for (ix, entry) in excerpt_ids.iter().enumerate() {
+            if ix == 0 {
+                if entry.id.cmp(&ExcerptId::min(), &*snapshot).is_le() {
+                    panic!("invalid first excerpt id {:?}", entry.id);
+                }
+            } else {
+                if entry.id <= excerpt_ids[ix - 1].id {
+                    panic!("excerpt ids are out-of-order: {:?}", excerpt_ids);
+                }
+            }
+        }
     }
+}
 
-    pub fn refresh_anchors<'a, I>(&'a self, anchors: I) -> Vec<(usize, Anchor, bool)>
-    where
-        I: 'a + IntoIterator<Item = &'a Anchor>,
-    {
-        let mut anchors = anchors.into_iter().enumerate().peekable();
-        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
-        cursor.next(&());
-
-        let mut result = Vec::new();
+impl EventEmitter<Event> for MultiBuffer {}
 
-        while let Some((_, anchor)) = anchors.peek() {
-            let old_excerpt_id = anchor.excerpt_id;
+impl MultiBufferSnapshot {
+    pub fn text(&self) -> String {
+        self.chunks(0..self.len(), false)
+            .map(|chunk| chunk.text)
+            .collect()
+    }
 
-            // Find the location where this anchor's excerpt should be.
-            let old_locator = self.excerpt_locator_for_id(old_excerpt_id);
-            cursor.seek_forward(&Some(old_locator), Bias::Left, &());
+    pub fn reversed_chars_at<T: ToOffset>(&self, position: T) -> impl Iterator<Item = char> + '_ {
+        let mut offset = position.to_offset(self);
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&offset, Bias::Left, &());
+        let mut excerpt_chunks = cursor.item().map(|excerpt| {
+            let end_before_footer = cursor.start() + excerpt.text_summary.len;
+            let start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let end = start + (cmp::min(offset, end_before_footer) - cursor.start());
+            excerpt.buffer.reversed_chunks_in_range(start..end)
+        });
+        iter::from_fn(move || {
+            if offset == *cursor.start() {
+                cursor.prev(&());
+                let excerpt = cursor.item()?;
+                excerpt_chunks = Some(
+                    excerpt
+                        .buffer
+                        .reversed_chunks_in_range(excerpt.range.context.clone()),
+                );
+            }
 
-            if cursor.item().is_none() {
-                cursor.next(&());
+            let excerpt = cursor.item().unwrap();
+            if offset == cursor.end(&()) && excerpt.has_trailing_newline {
+                offset -= 1;
+                Some("\n")
+            } else {
+                let chunk = excerpt_chunks.as_mut().unwrap().next().unwrap();
+                offset -= chunk.len();
+                Some(chunk)
             }
+        })
+        .flat_map(|c| c.chars().rev())
+    }
 
-            let next_excerpt = cursor.item();
-            let prev_excerpt = cursor.prev_item();
+    pub fn chars_at<T: ToOffset>(&self, position: T) -> impl Iterator<Item = char> + '_ {
+        let offset = position.to_offset(self);
+        self.text_for_range(offset..self.len())
+            .flat_map(|chunk| chunk.chars())
+    }
 
-            // Process all of the anchors for this excerpt.
-            while let Some((_, anchor)) = anchors.peek() {
-                if anchor.excerpt_id != old_excerpt_id {
-                    break;
-                }
-                let (anchor_ix, anchor) = anchors.next().unwrap();
-                let mut anchor = *anchor;
+    /// Returns the text in the given range as a [`Rope`]. Unlike collecting the result of
+    /// [`MultiBufferSnapshot::text_for_range`], this shares most of its storage with the
+    /// excerpted buffers, so it's cheap even for very large ranges.
+    pub fn rope_for_range<T: ToOffset>(&self, range: Range<T>) -> Rope {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        let mut rope = Rope::new();
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&range.start, Bias::Right, &());
+        while let Some(excerpt) = cursor.item() {
+            if *cursor.start() >= range.end {
+                break;
+            }
 
-                // Leave min and max anchors unchanged if invalid or
-                // if the old excerpt still exists at this location
-                let mut kept_position = next_excerpt
-                    .map_or(false, |e| e.id == old_excerpt_id && e.contains(&anchor))
-                    || old_excerpt_id == ExcerptId::max()
-                    || old_excerpt_id == ExcerptId::min();
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let start_in_excerpt = range.start.saturating_sub(*cursor.start());
+            let end_in_excerpt = cmp::min(range.end - cursor.start(), excerpt.text_summary.len);
+            if start_in_excerpt < end_in_excerpt {
+                rope.append(
+                    excerpt
+                        .buffer
+                        .as_rope()
+                        .slice(excerpt_start + start_in_excerpt..excerpt_start + end_in_excerpt),
+                );
+            }
+            if excerpt.has_trailing_newline && range.end > cursor.start() + excerpt.text_summary.len
+            {
+                rope.push("\n");
+            }
+            cursor.next(&());
+        }
+        rope
+    }
 
-                // If the old excerpt no longer exists at this location, then attempt to
-                // find an equivalent position for this anchor in an adjacent excerpt.
-                if !kept_position {
-                    for excerpt in [next_excerpt, prev_excerpt].iter().filter_map(|e| *e) {
-                        if excerpt.contains(&anchor) {
-                            anchor.excerpt_id = excerpt.id.clone();
-                            kept_position = true;
-                            break;
-                        }
-                    }
-                }
+    pub fn text_for_range<T: ToOffset>(&self, range: Range<T>) -> impl Iterator<Item = &str> + '_ {
+        self.chunks(range, false).map(|chunk| chunk.text)
+    }
 
-                // If there's no adjacent excerpt that contains the anchor's position,
-                // then report that the anchor has lost its position.
-                if !kept_position {
-                    anchor = if let Some(excerpt) = next_excerpt {
-                        let mut text_anchor = excerpt
-                            .range
-                            .context
-                            .start
-                            .bias(anchor.text_anchor.bias, &excerpt.buffer);
-                        if text_anchor
-                            .cmp(&excerpt.range.context.end, &excerpt.buffer)
-                            .is_gt()
-                        {
-                            text_anchor = excerpt.range.context.end;
-                        }
-                        Anchor {
-                            buffer_id: Some(excerpt.buffer_id),
-                            excerpt_id: excerpt.id.clone(),
-                            text_anchor,
-                        }
-                    } else if let Some(excerpt) = prev_excerpt {
-                        let mut text_anchor = excerpt
-                            .range
-                            .context
-                            .end
-                            .bias(anchor.text_anchor.bias, &excerpt.buffer);
-                        if text_anchor
-                            .cmp(&excerpt.range.context.start, &excerpt.buffer)
-                            .is_lt()
-                        {
-                            text_anchor = excerpt.range.context.start;
-                        }
-                        Anchor {
-                            buffer_id: Some(excerpt.buffer_id),
-                            excerpt_id: excerpt.id.clone(),
-                            text_anchor,
-                        }
-                    } else if anchor.text_anchor.bias == Bias::Left {
-                        Anchor::min()
-                    } else {
-                        Anchor::max()
-                    };
-                }
+    pub fn is_line_blank(&self, row: u32) -> bool {
+        self.text_for_range(Point::new(row, 0)..Point::new(row, self.line_len(row)))
+            .all(|chunk| chunk.matches(|c: char| !c.is_whitespace()).next().is_none())
+    }
 
-                result.push((anchor_ix, anchor, kept_position));
-            }
-        }
-        result.sort_unstable_by(|a, b| a.1.cmp(&b.1, self));
-        result
+    pub fn contains_str_at<T>(&self, position: T, needle: &str) -> bool
+    where
+        T: ToOffset,
+    {
+        let position = position.to_offset(self);
+        let end = if self.search_across_excerpts {
+            self.len()
+        } else {
+            let mut cursor = self.excerpts.cursor::<usize>();
+            cursor.seek(&position, Bias::Right, &());
+            cursor.item().map_or(position, |excerpt| {
+                cmp::max(position, cursor.start() + excerpt.text_summary.len)
+            })
+        };
+        position == self.clip_offset(position, Bias::Left)
+            && self
+                .bytes_in_range(position..end)
+                .flatten()
+                .copied()
+                .take(needle.len())
+                .eq(needle.bytes())
     }
 
-    pub fn anchor_before<T: ToOffset>(&self, position: T) -> Anchor {
-        self.anchor_at(position, Bias::Left)
+    pub fn searches_across_excerpts(&self) -> bool {
+        self.search_across_excerpts
     }
 
-    pub fn anchor_after<T: ToOffset>(&self, position: T) -> Anchor {
-        self.anchor_at(position, Bias::Right)
+    /// Returns whether the given excerpt's buffer exceeds the multi-buffer's large buffer
+    /// threshold, so that its text is yielded without syntax highlighting or diagnostics.
+    pub fn is_degraded(&self, excerpt_id: ExcerptId) -> bool {
+        self.excerpt(excerpt_id).map_or(false, |excerpt| {
+            is_large_buffer(&excerpt.buffer, self.large_buffer_threshold)
+        })
     }
 
-    pub fn anchor_at<T: ToOffset>(&self, position: T, mut bias: Bias) -> Anchor {
-        let offset = position.to_offset(self);
-        if let Some((excerpt_id, buffer_id, buffer)) = self.as_singleton() {
-            return Anchor {
-                buffer_id: Some(buffer_id),
-                excerpt_id: excerpt_id.clone(),
-                text_anchor: buffer.anchor_at(offset, bias),
-            };
-        }
-
-        let mut cursor = self.excerpts.cursor::<(usize, Option<ExcerptId>)>();
-        cursor.seek(&offset, Bias::Right, &());
-        if cursor.item().is_none() && offset == cursor.start().0 && bias == Bias::Left {
-            cursor.prev(&());
-        }
-        if let Some(excerpt) = cursor.item() {
-            let mut overshoot = offset.saturating_sub(cursor.start().0);
-            if excerpt.has_trailing_newline && offset == cursor.end(&()).0 {
-                overshoot -= 1;
-                bias = Bias::Right;
-            }
-
-            let buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let text_anchor =
-                excerpt.clip_anchor(excerpt.buffer.anchor_at(buffer_start + overshoot, bias));
-            Anchor {
-                buffer_id: Some(excerpt.buffer_id),
-                excerpt_id: excerpt.id.clone(),
-                text_anchor,
-            }
-        } else if offset == 0 && bias == Bias::Left {
-            Anchor::min()
-        } else {
-            Anchor::max()
-        }
-    }
-
-    pub fn anchor_in_excerpt(&self, excerpt_id: ExcerptId, text_anchor: text::Anchor) -> Anchor {
-        let locator = self.excerpt_locator_for_id(excerpt_id);
-        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
-        cursor.seek(locator, Bias::Left, &());
-        if let Some(excerpt) = cursor.item() {
-            if excerpt.id == excerpt_id {
-                let text_anchor = excerpt.clip_anchor(text_anchor);
-                drop(cursor);
-                return Anchor {
-                    buffer_id: Some(excerpt.buffer_id),
-                    excerpt_id,
-                    text_anchor,
-                };
-            }
-        }
-        panic!("excerpt not found");
+    /// Returns whether any excerpt is degraded, so that views can indicate it.
+    pub fn has_degraded_excerpts(&self) -> bool {
+        self.large_buffer_threshold.is_some()
+            && self
+                .excerpts
+                .iter()
+                .any(|excerpt| is_large_buffer(&excerpt.buffer, self.large_buffer_threshold))
     }
 
-    pub fn can_resolve(&self, anchor: &Anchor) -> bool {
-        if anchor.excerpt_id == ExcerptId::min() || anchor.excerpt_id == ExcerptId::max() {
-            true
-        } else if let Some(excerpt) = self.excerpt(anchor.excerpt_id) {
-            excerpt.buffer.can_resolve(&anchor.text_anchor)
-        } else {
-            false
-        }
+    /// Returns whether the given excerpt's content was truncated because it exceeded the
+    /// multi-buffer's maximum excerpt length. Views should render an elision marker at the
+    /// end of such excerpts.
+    pub fn is_elided(&self, excerpt_id: ExcerptId) -> bool {
+        self.elided_excerpts.get(&excerpt_id).is_some()
     }
 
-    pub fn excerpts(
-        &self,
-    ) -> impl Iterator<Item = (ExcerptId, &BufferSnapshot, ExcerptRange<text::Anchor>)> {
-        self.excerpts
-            .iter()
-            .map(|excerpt| (excerpt.id, &excerpt.buffer, excerpt.range.clone()))
+    /// Returns the annotation attached to the given excerpt, if it has one of type `T`.
+    pub fn excerpt_annotation<T: Any>(&self, excerpt_id: ExcerptId) -> Option<&T> {
+        self.excerpt_annotations.get(&excerpt_id)?.downcast_ref()
     }
 
-    fn excerpts_for_range<'a, T: ToOffset>(
-        &'a self,
-        range: Range<T>,
-    ) -> impl Iterator<Item = (&'a Excerpt, usize)> + 'a {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
+    pub fn surrounding_word<T: ToOffset>(&self, start: T) -> (Range<usize>, Option<CharKind>) {
+        let mut start = start.to_offset(self);
+        let mut end = start;
+        let mut next_chars = self.chars_at(start).peekable();
+        let mut prev_chars = self.reversed_chars_at(start).peekable();
 
-        let mut cursor = self.excerpts.cursor::<usize>();
-        cursor.seek(&range.start, Bias::Right, &());
-        cursor.prev(&());
+        let scope = self.language_scope_at(start);
+        let kind = |c| char_kind(&scope, c);
+        let word_kind = cmp::max(
+            prev_chars.peek().copied().map(kind),
+            next_chars.peek().copied().map(kind),
+        );
 
-        iter::from_fn(move || {
-            cursor.next(&());
-            if cursor.start() < &range.end {
-                cursor.item().map(|item| (item, *cursor.start()))
+        for ch in prev_chars {
+            if Some(kind(ch)) == word_kind && ch != '\n' {
+                start -= ch.len_utf8();
             } else {
-                None
+                break;
             }
-        })
-    }
+        }
 
-    pub fn excerpt_boundaries_in_range<R, T>(
-        &self,
-        range: R,
-    ) -> impl Iterator<Item = ExcerptBoundary> + '_
-    where
-        R: RangeBounds<T>,
-        T: ToOffset,
-    {
-        let start_offset;
-        let start = match range.start_bound() {
-            Bound::Included(start) => {
-                start_offset = start.to_offset(self);
-                Bound::Included(start_offset)
-            }
-            Bound::Excluded(start) => {
-                start_offset = start.to_offset(self);
-                Bound::Excluded(start_offset)
-            }
-            Bound::Unbounded => {
-                start_offset = 0;
-                Bound::Unbounded
+        for ch in next_chars {
+            if Some(kind(ch)) == word_kind && ch != '\n' {
+                end += ch.len_utf8();
+            } else {
+                break;
             }
-        };
-        let end = match range.end_bound() {
-            Bound::Included(end) => Bound::Included(end.to_offset(self)),
-            Bound::Excluded(end) => Bound::Excluded(end.to_offset(self)),
-            Bound::Unbounded => Bound::Unbounded,
-        };
-        let bounds = (start, end);
-
-        let mut cursor = self.excerpts.cursor::<(usize, Point)>();
-        cursor.seek(&start_offset, Bias::Right, &());
-        if cursor.item().is_none() {
-            cursor.prev(&());
-        }
-        if !bounds.contains(&cursor.start().0) {
-            cursor.next(&());
         }
 
-        let mut prev_buffer_id = cursor.prev_item().map(|excerpt| excerpt.buffer_id);
-        std::iter::from_fn(move || {
-            if self.singleton {
-                None
-            } else if bounds.contains(&cursor.start().0) {
-                let excerpt = cursor.item()?;
-                let starts_new_buffer = Some(excerpt.buffer_id) != prev_buffer_id;
-                let boundary = ExcerptBoundary {
-                    id: excerpt.id.clone(),
-                    row: cursor.start().1.row,
-                    buffer: excerpt.buffer.clone(),
-                    range: excerpt.range.clone(),
-                    starts_new_buffer,
-                };
+        (start..end, word_kind)
+    }
 
-                prev_buffer_id = Some(excerpt.buffer_id);
-                cursor.next(&());
-                Some(boundary)
-            } else {
-                None
-            }
-        })
+    pub fn as_singleton(&self) -> Option<(&ExcerptId, BufferId, &BufferSnapshot)> {
+        if self.singleton {
+            self.excerpts
+                .iter()
+                .next()
+                .map(|e| (&e.id, e.buffer_id, &*e.buffer))
+        } else {
+            None
+        }
     }
 
-    pub fn edit_count(&self) -> usize {
-        self.edit_count
+    pub fn len(&self) -> usize {
+        self.excerpts.summary().text.len
     }
 
-    pub fn parse_count(&self) -> usize {
-        self.parse_count
+    pub fn is_empty(&self) -> bool {
+        self.excerpts.summary().text.len == 0
     }
 
-    /// Returns the smallest enclosing bracket ranges containing the given range or
-    /// None if no brackets contain range or the range is not contained in a single
-    /// excerpt
-    pub fn innermost_enclosing_bracket_ranges<T: ToOffset>(
-        &self,
-        range: Range<T>,
-    ) -> Option<(Range<usize>, Range<usize>)> {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
+    pub fn max_buffer_row(&self) -> u32 {
+        self.excerpts.summary().max_buffer_row
+    }
 
-        // Get the ranges of the innermost pair of brackets.
-        let mut result: Option<(Range<usize>, Range<usize>)> = None;
+    pub fn clip_offset(&self, offset: usize, bias: Bias) -> usize {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.clip_offset(offset, bias);
+        }
 
-        let Some(enclosing_bracket_ranges) = self.enclosing_bracket_ranges(range.clone()) else {
-            return None;
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&offset, Bias::Right, &());
+        let overshoot = if let Some(excerpt) = cursor.item() {
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let buffer_offset = excerpt
+                .buffer
+                .clip_offset(excerpt_start + (offset - cursor.start()), bias);
+            buffer_offset.saturating_sub(excerpt_start)
+        } else {
+            0
         };
+        cursor.start() + overshoot
+    }
 
-        for (open, close) in enclosing_bracket_ranges {
-            let len = close.end - open.start;
+    pub fn clip_point(&self, point: Point, bias: Bias) -> Point {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.clip_point(point, bias);
+        }
 
-            if let Some((existing_open, existing_close)) = &result {
-                let existing_len = existing_close.end - existing_open.start;
-                if len > existing_len {
-                    continue;
-                }
-            }
+        let mut cursor = self.excerpts.cursor::<Point>();
+        cursor.seek(&point, Bias::Right, &());
+        let overshoot = if let Some(excerpt) = cursor.item() {
+            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer);
+            let buffer_point = excerpt
+                .buffer
+                .clip_point(excerpt_start + (point - cursor.start()), bias);
+            buffer_point.saturating_sub(excerpt_start)
+        } else {
+            Point::zero()
+        };
+        *cursor.start() + overshoot
+    }
 
-            result = Some((open, close));
+    pub fn clip_offset_utf16(&self, offset: OffsetUtf16, bias: Bias) -> OffsetUtf16 {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.clip_offset_utf16(offset, bias);
         }
 
-        result
+        let mut cursor = self.excerpts.cursor::<OffsetUtf16>();
+        cursor.seek(&offset, Bias::Right, &());
+        let overshoot = if let Some(excerpt) = cursor.item() {
+            let excerpt_start = excerpt.range.context.start.to_offset_utf16(&excerpt.buffer);
+            let buffer_offset = excerpt
+                .buffer
+                .clip_offset_utf16(excerpt_start + (offset - cursor.start()), bias);
+            OffsetUtf16(buffer_offset.0.saturating_sub(excerpt_start.0))
+        } else {
+            OffsetUtf16(0)
+        };
+        *cursor.start() + overshoot
     }
 
-    /// Returns enclosing bracket ranges containing the given range or returns None if the range is
-    /// not contained in a single excerpt
-    pub fn enclosing_bracket_ranges<'a, T: ToOffset>(
-        &'a self,
-        range: Range<T>,
-    ) -> Option<impl Iterator<Item = (Range<usize>, Range<usize>)> + 'a> {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
+    pub fn clip_point_utf16(&self, point: Unclipped<PointUtf16>, bias: Bias) -> PointUtf16 {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.clip_point_utf16(point, bias);
+        }
 
-        self.bracket_ranges(range.clone()).map(|range_pairs| {
-            range_pairs
-                .filter(move |(open, close)| open.start <= range.start && close.end >= range.end)
-        })
+        let mut cursor = self.excerpts.cursor::<PointUtf16>();
+        cursor.seek(&point.0, Bias::Right, &());
+        let overshoot = if let Some(excerpt) = cursor.item() {
+            let excerpt_start = excerpt
+                .buffer
+                .offset_to_point_utf16(excerpt.range.context.start.to_offset(&excerpt.buffer));
+            let buffer_point = excerpt
+                .buffer
+                .clip_point_utf16(Unclipped(excerpt_start + (point.0 - cursor.start())), bias);
+            buffer_point.saturating_sub(excerpt_start)
+        } else {
+            PointUtf16::zero()
+        };
+        *cursor.start() + overshoot
     }
 
-    /// Returns bracket range pairs overlapping the given `range` or returns None if the `range` is
-    /// not contained in a single excerpt
-    pub fn bracket_ranges<'a, T: ToOffset>(
-        &'a self,
-        range: Range<T>,
-    ) -> Option<impl Iterator<Item = (Range<usize>, Range<usize>)> + 'a> {
+    pub fn bytes_in_range<T: ToOffset>(&self, range: Range<T>) -> MultiBufferBytes {
         let range = range.start.to_offset(self)..range.end.to_offset(self);
-        let excerpt = self.excerpt_containing(range.clone());
-        excerpt.map(|(excerpt, excerpt_offset)| {
-            let excerpt_buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-            let excerpt_buffer_end = excerpt_buffer_start + excerpt.text_summary.len;
-
-            let start_in_buffer = excerpt_buffer_start + range.start.saturating_sub(excerpt_offset);
-            let end_in_buffer = excerpt_buffer_start + range.end.saturating_sub(excerpt_offset);
-
-            excerpt
-                .buffer
-                .bracket_ranges(start_in_buffer..end_in_buffer)
-                .filter_map(move |(start_bracket_range, end_bracket_range)| {
-                    if start_bracket_range.start < excerpt_buffer_start
-                        || end_bracket_range.end > excerpt_buffer_end
-                    {
-                        return None;
-                    }
-
-                    let mut start_bracket_range = start_bracket_range.clone();
-                    start_bracket_range.start =
-                        excerpt_offset + (start_bracket_range.start - excerpt_buffer_start);
-                    start_bracket_range.end =
-                        excerpt_offset + (start_bracket_range.end - excerpt_buffer_start);
+        let mut excerpts = self.excerpts.cursor::<usize>();
+        excerpts.seek(&range.start, Bias::Right, &());
 
-                    let mut end_bracket_range = end_bracket_range.clone();
-                    end_bracket_range.start =
-                        excerpt_offset + (end_bracket_range.start - excerpt_buffer_start);
-                    end_bracket_range.end =
-                        excerpt_offset + (end_bracket_range.end - excerpt_buffer_start);
-                    Some((start_bracket_range, end_bracket_range))
-                })
-        })
+        let mut chunk = &[][..];
+        let excerpt_bytes = if let Some(excerpt) = excerpts.item() {
+            let mut excerpt_bytes = excerpt
+                .bytes_in_range(range.start - excerpts.start()..range.end - excerpts.start());
+            chunk = excerpt_bytes.next().unwrap_or(&[][..]);
+            Some(excerpt_bytes)
+        } else {
+            None
+        };
+        MultiBufferBytes {
+            range,
+            excerpts,
+            excerpt_bytes,
+            chunk,
+        }
     }
 
-    pub fn redacted_ranges<'a, T: ToOffset>(
-        &'a self,
+    pub fn reversed_bytes_in_range<T: ToOffset>(
+        &self,
         range: Range<T>,
-        redaction_enabled: impl Fn(Option<&Arc<dyn File>>) -> bool + 'a,
-    ) -> impl Iterator<Item = Range<usize>> + 'a {
+    ) -> ReversedMultiBufferBytes {
         let range = range.start.to_offset(self)..range.end.to_offset(self);
-        self.excerpts_for_range(range.clone())
-            .filter_map(move |(excerpt, excerpt_offset)| {
-                redaction_enabled(excerpt.buffer.file()).then(move || {
-                    let excerpt_buffer_start =
-                        excerpt.range.context.start.to_offset(&excerpt.buffer);
+        let mut excerpts = self.excerpts.cursor::<usize>();
+        excerpts.seek(&range.end, Bias::Left, &());
 
-                    excerpt
-                        .buffer
-                        .redacted_ranges(excerpt.range.context.clone())
-                        .map(move |mut redacted_range| {
-                            // Re-base onto the excerpts coordinates in the multibuffer
-                            redacted_range.start =
-                                excerpt_offset + (redacted_range.start - excerpt_buffer_start);
-                            redacted_range.end =
-                                excerpt_offset + (redacted_range.end - excerpt_buffer_start);
+        let mut chunk = &[][..];
+        let excerpt_bytes = if let Some(excerpt) = excerpts.item() {
+            let mut excerpt_bytes = excerpt.reversed_bytes_in_range(
+                range.start - excerpts.start()..range.end - excerpts.start(),
+            );
+            chunk = excerpt_bytes.next().unwrap_or(&[][..]);
+            Some(excerpt_bytes)
+        } else {
+            None
+        };
 
-                            redacted_range
-                        })
-                        .skip_while(move |redacted_range| redacted_range.end < range.start)
-                        .take_while(move |redacted_range| redacted_range.start < range.end)
-                })
-            })
-            .flatten()
+        ReversedMultiBufferBytes {
+            range,
+            excerpts,
+            excerpt_bytes,
+            chunk,
+        }
     }
 
-    pub fn diagnostics_update_count(&self) -> usize {
-        self.diagnostics_update_count
+    pub fn buffer_rows(&self, start_row: u32) -> MultiBufferRows {
+        let mut result = MultiBufferRows {
+            buffer_row_range: 0..0,
+            excerpts: self.excerpts.cursor(),
+            row: start_row,
+        };
+        result.seek(start_row);
+        result
     }
 
-    pub fn git_diff_update_count(&self) -> usize {
-        self.git_diff_update_count
+    pub fn chunks<T: ToOffset>(&self, range: Range<T>, language_aware: bool) -> MultiBufferChunks {
+        self.chunks_internal(range, language_aware, false, false)
     }
 
-    pub fn trailing_excerpt_update_count(&self) -> usize {
-        self.trailing_excerpt_update_count
+    /// Like [`MultiBufferSnapshot::chunks`], but yields each run of spaces or tabs as its own
+    /// chunk, with [`Chunk::whitespace`] set to where the run appears within its line, and
+    /// [`Chunk::is_tab`] set for runs of tabs. Whether whitespace is trailing is determined
+    /// from the current buffer contents, so that it reflects unsaved edits.
+    pub fn chunks_with_whitespace<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        language_aware: bool,
+    ) -> MultiBufferChunks {
+        self.chunks_internal(range, language_aware, false, true)
     }
 
-    pub fn file_at<'a, T: ToOffset>(&'a self, point: T) -> Option<&'a Arc<dyn File>> {
-        self.point_to_buffer_offset(point)
-            .and_then(|(buffer, _)| buffer.file())
+    /// Returns a downsampled version of the styled text in the given rows, for rendering a
+    /// minimap at a fraction of the cost of [`MultiBufferSnapshot::chunks`].
+    ///
+    /// Each line yields at most `max_runs_per_line` runs of non-whitespace text, with the last
+    /// of them extended over any text that would otherwise start another run. Text past
+    /// `max_column` is skipped without being highlighted.
+    pub fn minimap_runs(
+        &self,
+        rows: Range<u32>,
+        max_runs_per_line: usize,
+        max_column: u32,
+    ) -> MinimapRuns {
+        let start = Point::new(rows.start, 0).min(self.max_point());
+        let end = Point::new(rows.end, 0).min(self.max_point());
+        MinimapRuns {
+            snapshot: self,
+            chunks: self.chunks(start..end, true),
+            max_runs_per_line: cmp::max(max_runs_per_line, 1),
+            max_column,
+            text: "",
+            syntax_highlight_id: None,
+            row: start.row,
+            column: 0,
+            line_run_count: 0,
+            run: None,
+        }
+    }
+
+    /// Like [`MultiBufferSnapshot::chunks`], but yields unstyled text for any excerpts whose
+    /// buffers are still being parsed in the background, instead of highlighting them using
+    /// a syntax tree that is out of date. Use [`MultiBufferChunks::highlights_pending`] to
+    /// find out whether this happened. The snapshot's [`MultiBufferSnapshot::parse_count`]
+    /// is incremented once the highlights are available.
+    pub fn chunks_unstyled_while_parsing<T: ToOffset>(&self, range: Range<T>) -> MultiBufferChunks {
+        self.chunks_internal(range, true, true, false)
+    }
+
+    fn chunks_internal<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        language_aware: bool,
+        plain_text_while_parsing: bool,
+        mark_whitespace: bool,
+    ) -> MultiBufferChunks {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        let mut excerpts = self.excerpts.cursor();
+        excerpts.seek(&range.start, Bias::Right, &());
+        let mut chunks = MultiBufferChunks {
+            range: range.clone(),
+            excerpts,
+            excerpt_chunks: None,
+            language_aware,
+            plain_text_while_parsing,
+            large_buffer_threshold: self.large_buffer_threshold,
+            highlights_pending: false,
+            mark_whitespace,
+            pending_chunk: None,
+            line_has_content: false,
+            line_content_end: None,
+        };
+        chunks.seek(range.start);
+        chunks
     }
 
-    pub fn language_at<'a, T: ToOffset>(&'a self, point: T) -> Option<&'a Arc<Language>> {
-        self.point_to_buffer_offset(point)
-            .and_then(|(buffer, offset)| buffer.language_at(offset))
-    }
+    pub fn offset_to_point(&self, offset: usize) -> Point {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.offset_to_point(offset);
+        }
 
-    pub fn settings_at<'a, T: ToOffset>(
-        &'a self,
-        point: T,
-        cx: &'a AppContext,
-    ) -> &'a LanguageSettings {
-        let mut language = None;
-        let mut file = None;
-        if let Some((buffer, offset)) = self.point_to_buffer_offset(point) {
-            language = buffer.language_at(offset);
-            file = buffer.file();
+        let mut cursor = self.excerpts.cursor::<(usize, Point)>();
+        cursor.seek(&offset, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_offset, start_point) = cursor.start();
+            let overshoot = offset - start_offset;
+            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
+            let buffer_point = excerpt
+                .buffer
+                .offset_to_point(excerpt_start_offset + overshoot);
+            *start_point + (buffer_point - excerpt_start_point)
+        } else {
+            self.excerpts.summary().text.lines
         }
-        language_settings(language, file, cx)
     }
 
-    pub fn language_scope_at<'a, T: ToOffset>(&'a self, point: T) -> Option<LanguageScope> {
-        self.point_to_buffer_offset(point)
-            .and_then(|(buffer, offset)| buffer.language_scope_at(offset))
-    }
+    pub fn offset_to_point_utf16(&self, offset: usize) -> PointUtf16 {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.offset_to_point_utf16(offset);
+        }
 
-    pub fn language_indent_size_at<T: ToOffset>(
-        &self,
-        position: T,
-        cx: &AppContext,
-    ) -> Option<IndentSize> {
-        let (buffer_snapshot, offset) = self.point_to_buffer_offset(position)?;
-        Some(buffer_snapshot.language_indent_size_at(offset, cx))
+        let mut cursor = self.excerpts.cursor::<(usize, PointUtf16)>();
+        cursor.seek(&offset, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_offset, start_point) = cursor.start();
+            let overshoot = offset - start_offset;
+            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_start_point = excerpt.range.context.start.to_point_utf16(&excerpt.buffer);
+            let buffer_point = excerpt
+                .buffer
+                .offset_to_point_utf16(excerpt_start_offset + overshoot);
+            *start_point + (buffer_point - excerpt_start_point)
+        } else {
+            self.excerpts.summary().text.lines_utf16()
+        }
     }
 
-    pub fn is_dirty(&self) -> bool {
-        self.is_dirty
-    }
+    pub fn point_to_point_utf16(&self, point: Point) -> PointUtf16 {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.point_to_point_utf16(point);
+        }
 
-    pub fn has_conflict(&self) -> bool {
-        self.has_conflict
+        let mut cursor = self.excerpts.cursor::<(Point, PointUtf16)>();
+        cursor.seek(&point, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_offset, start_point) = cursor.start();
+            let overshoot = point - start_offset;
+            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
+            let excerpt_start_point_utf16 =
+                excerpt.range.context.start.to_point_utf16(&excerpt.buffer);
+            let buffer_point = excerpt
+                .buffer
+                .point_to_point_utf16(excerpt_start_point + overshoot);
+            *start_point + (buffer_point - excerpt_start_point_utf16)
+        } else {
+            self.excerpts.summary().text.lines_utf16()
+        }
     }
 
-    pub fn has_diagnostics(&self) -> bool {
-        self.excerpts
-            .iter()
-            .any(|excerpt| excerpt.buffer.has_diagnostics())
-    }
+    pub fn point_to_offset(&self, point: Point) -> usize {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.point_to_offset(point);
+        }
 
-    pub fn diagnostic_group<'a, O>(
-        &'a self,
-        group_id: usize,
-    ) -> impl Iterator<Item = DiagnosticEntry<O>> + 'a
-    where
-        O: text::FromAnchor + 'a,
-    {
-        self.as_singleton()
-            .into_iter()
-            .flat_map(move |(_, _, buffer)| buffer.diagnostic_group(group_id))
+        let mut cursor = self.excerpts.cursor::<(Point, usize)>();
+        cursor.seek(&point, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_point, start_offset) = cursor.start();
+            let overshoot = point - start_point;
+            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_start_point = excerpt.range.context.start.to_point(&excerpt.buffer);
+            let buffer_offset = excerpt
+                .buffer
+                .point_to_offset(excerpt_start_point + overshoot);
+            *start_offset + buffer_offset - excerpt_start_offset
+        } else {
+            self.excerpts.summary().text.len
+        }
     }
 
-    pub fn diagnostics_in_range<'a, T, O>(
-        &'a self,
-        range: Range<T>,
-        reversed: bool,
-    ) -> impl Iterator<Item = DiagnosticEntry<O>> + 'a
-    where
-        T: 'a + ToOffset,
-        O: 'a + text::FromAnchor + Ord,
-    {
-        self.as_singleton()
-            .into_iter()
-            .flat_map(move |(_, _, buffer)| {
-                buffer.diagnostics_in_range(
-                    range.start.to_offset(self)..range.end.to_offset(self),
-                    reversed,
-                )
-            })
-    }
+    pub fn offset_utf16_to_offset(&self, offset_utf16: OffsetUtf16) -> usize {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.offset_utf16_to_offset(offset_utf16);
+        }
 
-    pub fn has_git_diffs(&self) -> bool {
-        for excerpt in self.excerpts.iter() {
-            if excerpt.buffer.has_git_diff() {
-                return true;
-            }
+        let mut cursor = self.excerpts.cursor::<(OffsetUtf16, usize)>();
+        cursor.seek(&offset_utf16, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_offset_utf16, start_offset) = cursor.start();
+            let overshoot = offset_utf16 - start_offset_utf16;
+            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_start_offset_utf16 =
+                excerpt.buffer.offset_to_offset_utf16(excerpt_start_offset);
+            let buffer_offset = excerpt
+                .buffer
+                .offset_utf16_to_offset(excerpt_start_offset_utf16 + overshoot);
+            *start_offset + (buffer_offset - excerpt_start_offset)
+        } else {
+            self.excerpts.summary().text.len
         }
-        false
     }
 
-    pub fn git_diff_hunks_in_range_rev<'a>(
-        &'a self,
-        row_range: Range<u32>,
-    ) -> impl 'a + Iterator<Item = DiffHunk<u32>> {
-        let mut cursor = self.excerpts.cursor::<Point>();
-
-        cursor.seek(&Point::new(row_range.end, 0), Bias::Left, &());
-        if cursor.item().is_none() {
-            cursor.prev(&());
+    pub fn offset_to_offset_utf16(&self, offset: usize) -> OffsetUtf16 {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.offset_to_offset_utf16(offset);
         }
 
-        std::iter::from_fn(move || {
-            let excerpt = cursor.item()?;
-            let multibuffer_start = *cursor.start();
-            let multibuffer_end = multibuffer_start + excerpt.text_summary.lines;
-            if multibuffer_start.row >= row_range.end {
-                return None;
-            }
-
-            let mut buffer_start = excerpt.range.context.start;
-            let mut buffer_end = excerpt.range.context.end;
-            let excerpt_start_point = buffer_start.to_point(&excerpt.buffer);
-            let excerpt_end_point = excerpt_start_point + excerpt.text_summary.lines;
-
-            if row_range.start > multibuffer_start.row {
-                let buffer_start_point =
-                    excerpt_start_point + Point::new(row_range.start - multibuffer_start.row, 0);
-                buffer_start = excerpt.buffer.anchor_before(buffer_start_point);
-            }
+        let mut cursor = self.excerpts.cursor::<(usize, OffsetUtf16)>();
+        cursor.seek(&offset, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_offset, start_offset_utf16) = cursor.start();
+            let overshoot = offset - start_offset;
+            let excerpt_start_offset_utf16 =
+                excerpt.range.context.start.to_offset_utf16(&excerpt.buffer);
+            let excerpt_start_offset = excerpt
+                .buffer
+                .offset_utf16_to_offset(excerpt_start_offset_utf16);
+            let buffer_offset_utf16 = excerpt
+                .buffer
+                .offset_to_offset_utf16(excerpt_start_offset + overshoot);
+            *start_offset_utf16 + (buffer_offset_utf16 - excerpt_start_offset_utf16)
+        } else {
+            self.excerpts.summary().text.len_utf16
+        }
+    }
 
-            if row_range.end < multibuffer_end.row {
-                let buffer_end_point =
-                    excerpt_start_point + Point::new(row_range.end - multibuffer_start.row, 0);
-                buffer_end = excerpt.buffer.anchor_before(buffer_end_point);
-            }
+    pub fn point_utf16_to_offset(&self, point: PointUtf16) -> usize {
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer.point_utf16_to_offset(point);
+        }
 
-            let buffer_hunks = excerpt
+        let mut cursor = self.excerpts.cursor::<(PointUtf16, usize)>();
+        cursor.seek(&point, Bias::Right, &());
+        if let Some(excerpt) = cursor.item() {
+            let (start_point, start_offset) = cursor.start();
+            let overshoot = point - start_point;
+            let excerpt_start_offset = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_start_point = excerpt
                 .buffer
-                .git_diff_hunks_intersecting_range_rev(buffer_start..buffer_end)
-                .filter_map(move |hunk| {
-                    let start = multibuffer_start.row
-                        + hunk
-                            .buffer_range
-                            .start
-                            .saturating_sub(excerpt_start_point.row);
-                    let end = multibuffer_start.row
-                        + hunk
-                            .buffer_range
-                            .end
-                            .min(excerpt_end_point.row + 1)
-                            .saturating_sub(excerpt_start_point.row);
-
-                    Some(DiffHunk {
-                        buffer_range: start..end,
-                        diff_base_byte_range: hunk.diff_base_byte_range.clone(),
-                    })
-                });
+                .offset_to_point_utf16(excerpt.range.context.start.to_offset(&excerpt.buffer));
+            let buffer_offset = excerpt
+                .buffer
+                .point_utf16_to_offset(excerpt_start_point + overshoot);
+            *start_offset + (buffer_offset - excerpt_start_offset)
+        } else {
+            self.excerpts.summary().text.len
+        }
+    }
 
+    pub fn point_to_buffer_offset<T: ToOffset>(
+        &self,
+        point: T,
+    ) -> Option<(&BufferSnapshot, usize)> {
+        let offset = point.to_offset(self);
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&offset, Bias::Right, &());
+        if cursor.item().is_none() {
             cursor.prev(&());
+        }
 
-            Some(buffer_hunks)
+        cursor.item().map(|excerpt| {
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let buffer_point = excerpt_start + offset - *cursor.start();
+            (&*excerpt.buffer, buffer_point)
         })
-        .flatten()
     }
 
-    pub fn git_diff_hunks_in_range<'a>(
-        &'a self,
-        row_range: Range<u32>,
-    ) -> impl 'a + Iterator<Item = DiffHunk<u32>> {
-        let mut cursor = self.excerpts.cursor::<Point>();
+    pub fn suggested_indents(
+        &self,
+        rows: impl IntoIterator<Item = u32>,
+        cx: &AppContext,
+    ) -> BTreeMap<u32, IndentSize> {
+        let mut result = BTreeMap::new();
 
-        cursor.seek(&Point::new(row_range.start, 0), Bias::Right, &());
+        let mut rows_for_excerpt = Vec::new();
+        let mut cursor = self.excerpts.cursor::<Point>();
+        let mut rows = rows.into_iter().peekable();
+        let mut prev_row = u32::MAX;
+        let mut prev_language_indent_size = IndentSize::default();
 
-        std::iter::from_fn(move || {
-            let excerpt = cursor.item()?;
-            let multibuffer_start = *cursor.start();
-            let multibuffer_end = multibuffer_start + excerpt.text_summary.lines;
-            if multibuffer_start.row >= row_range.end {
-                return None;
-            }
+        while let Some(row) = rows.next() {
+            cursor.seek(&Point::new(row, 0), Bias::Right, &());
+            let excerpt = match cursor.item() {
+                Some(excerpt) => excerpt,
+                _ => continue,
+            };
 
-            let mut buffer_start = excerpt.range.context.start;
-            let mut buffer_end = excerpt.range.context.end;
-            let excerpt_start_point = buffer_start.to_point(&excerpt.buffer);
-            let excerpt_end_point = excerpt_start_point + excerpt.text_summary.lines;
+            // Retrieve the language and indent size once for each disjoint region being indented.
+            let single_indent_size = if row.saturating_sub(1) == prev_row {
+                prev_language_indent_size
+            } else {
+                excerpt
+                    .buffer
+                    .language_indent_size_at(Point::new(row, 0), cx)
+            };
+            prev_language_indent_size = single_indent_size;
+            prev_row = row;
 
-            if row_range.start > multibuffer_start.row {
-                let buffer_start_point =
-                    excerpt_start_point + Point::new(row_range.start - multibuffer_start.row, 0);
-                buffer_start = excerpt.buffer.anchor_before(buffer_start_point);
-            }
+            let start_buffer_row = excerpt.range.context.start.to_point(&excerpt.buffer).row;
+            let start_multibuffer_row = cursor.start().row;
 
-            if row_range.end < multibuffer_end.row {
-                let buffer_end_point =
-                    excerpt_start_point + Point::new(row_range.end - multibuffer_start.row, 0);
-                buffer_end = excerpt.buffer.anchor_before(buffer_end_point);
+            rows_for_excerpt.push(row);
+            while let Some(next_row) = rows.peek().copied() {
+                if cursor.end(&()).row > next_row {
+                    rows_for_excerpt.push(next_row);
+                    rows.next();
+                } else {
+                    break;
+                }
             }
 
-            let buffer_hunks = excerpt
+            let buffer_rows = rows_for_excerpt
+                .drain(..)
+                .map(|row| start_buffer_row + row - start_multibuffer_row);
+            let buffer_indents = excerpt
                 .buffer
-                .git_diff_hunks_intersecting_range(buffer_start..buffer_end)
-                .filter_map(move |hunk| {
-                    let start = multibuffer_start.row
-                        + hunk
-                            .buffer_range
-                            .start
-                            .saturating_sub(excerpt_start_point.row);
-                    let end = multibuffer_start.row
-                        + hunk
-                            .buffer_range
-                            .end
-                            .min(excerpt_end_point.row + 1)
-                            .saturating_sub(excerpt_start_point.row);
+                .suggested_indents(buffer_rows, single_indent_size);
+            let multibuffer_indents = buffer_indents
+                .into_iter()
+                .map(|(row, indent)| (start_multibuffer_row + row - start_buffer_row, indent));
+            result.extend(multibuffer_indents);
+        }
 
-                    Some(DiffHunk {
-                        buffer_range: start..end,
-                        diff_base_byte_range: hunk.diff_base_byte_range.clone(),
-                    })
-                });
-
-            cursor.next(&());
-
-            Some(buffer_hunks)
-        })
-        .flatten()
+        result
     }
 
-    pub fn range_for_syntax_ancestor<T: ToOffset>(&self, range: Range<T>) -> Option<Range<usize>> {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
-
-        self.excerpt_containing(range.clone())
-            .and_then(|(excerpt, excerpt_offset)| {
-                let excerpt_buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
-                let excerpt_buffer_end = excerpt_buffer_start + excerpt.text_summary.len;
-
-                let start_in_buffer =
-                    excerpt_buffer_start + range.start.saturating_sub(excerpt_offset);
-                let end_in_buffer = excerpt_buffer_start + range.end.saturating_sub(excerpt_offset);
-                let mut ancestor_buffer_range = excerpt
-                    .buffer
-                    .range_for_syntax_ancestor(start_in_buffer..end_in_buffer)?;
-                ancestor_buffer_range.start =
-                    cmp::max(ancestor_buffer_range.start, excerpt_buffer_start);
-                ancestor_buffer_range.end = cmp::min(ancestor_buffer_range.end, excerpt_buffer_end);
-
-                let start = excerpt_offset + (ancestor_buffer_range.start - excerpt_buffer_start);
-                let end = excerpt_offset + (ancestor_buffer_range.end - excerpt_buffer_start);
-                Some(start..end)
-            })
+    pub fn indent_size_for_line(&self, row: u32) -> IndentSize {
+        if let Some((buffer, range)) = self.buffer_line_for_row(row) {
+            let mut size = buffer.indent_size_for_line(range.start.row);
+            size.len = size
+                .len
+                .min(range.end.column)
+                .saturating_sub(range.start.column);
+            size
+        } else {
+            IndentSize::spaces(0)
+        }
     }
 
-    pub fn outline(&self, theme: Option<&SyntaxTheme>) -> Option<Outline<Anchor>> {
-        let (excerpt_id, _, buffer) = self.as_singleton()?;
-        let outline = buffer.outline(theme)?;
-        Some(Outline::new(
-            outline
-                .items
-                .into_iter()
-                .map(|item| OutlineItem {
-                    depth: item.depth,
-                    range: self.anchor_in_excerpt(excerpt_id.clone(), item.range.start)
-                        ..self.anchor_in_excerpt(excerpt_id.clone(), item.range.end),
-                    text: item.text,
-                    highlight_ranges: item.highlight_ranges,
-                    name_ranges: item.name_ranges,
-                })
-                .collect(),
-        ))
+    pub fn prev_non_blank_row(&self, mut row: u32) -> Option<u32> {
+        while row > 0 {
+            row -= 1;
+            if !self.is_line_blank(row) {
+                return Some(row);
+            }
+        }
+        None
     }
 
-    pub fn symbols_containing<T: ToOffset>(
+    /// Returns the range to select on each row of the rectangle with the given corners, for
+    /// block selections. The corners' columns are measured in UTF-16 code units rather than
+    /// bytes, so that the rectangle has the same width on every row regardless of the
+    /// characters it contains. Each range is clipped to its line, and rows whose line ends
+    /// before the rectangle's left edge are skipped, unless `include_short_lines` is true, in
+    /// which case they get an empty range at the end of their line, along with the padding
+    /// needed to reach the left edge. Rows belong to whichever excerpt contains them, so
+    /// rectangles can span excerpts.
+    pub fn column_ranges(
         &self,
-        offset: T,
-        theme: Option<&SyntaxTheme>,
-    ) -> Option<(BufferId, Vec<OutlineItem<Anchor>>)> {
-        let anchor = self.anchor_before(offset);
-        let excerpt_id = anchor.excerpt_id;
-        let excerpt = self.excerpt(excerpt_id)?;
-        Some((
-            excerpt.buffer_id,
-            excerpt
-                .buffer
-                .symbols_containing(anchor.text_anchor, theme)
-                .into_iter()
-                .flatten()
-                .map(|item| OutlineItem {
-                    depth: item.depth,
-                    range: self.anchor_in_excerpt(excerpt_id, item.range.start)
-                        ..self.anchor_in_excerpt(excerpt_id, item.range.end),
-                    text: item.text,
-                    highlight_ranges: item.highlight_ranges,
-                    name_ranges: item.name_ranges,
+        tail: PointUtf16,
+        head: PointUtf16,
+        include_short_lines: bool,
+    ) -> Vec<ColumnRange> {
+        let start_column = cmp::min(tail.column, head.column);
+        let end_column = cmp::max(tail.column, head.column);
+        let reversed = head.column < tail.column;
+        let rows =
+            cmp::min(tail.row, head.row)..=cmp::max(tail.row, head.row).min(self.max_point().row);
+        rows.filter_map(|row| {
+            let line_end = Point::new(row, self.line_len(row));
+            let line_len = self.point_to_point_utf16(line_end).column;
+            if start_column <= line_len {
+                let start = self
+                    .clip_point_utf16(Unclipped(PointUtf16::new(row, start_column)), Bias::Left);
+                let end = self.clip_point_utf16(
+                    Unclipped(PointUtf16::new(row, cmp::min(end_column, line_len))),
+                    Bias::Right,
+                );
+                Some(ColumnRange {
+                    range: self.offset_to_point(self.point_utf16_to_offset(start))
+                        ..self.offset_to_point(self.point_utf16_to_offset(end)),
+                    reversed,
+                    padding: 0,
                 })
-                .collect(),
-        ))
+            } else if include_short_lines {
+                Some(ColumnRange {
+                    range: line_end..line_end,
+                    reversed,
+                    padding: start_column - line_len,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
     }
 
-    fn excerpt_locator_for_id<'a>(&'a self, id: ExcerptId) -> &'a Locator {
-        if id == ExcerptId::min() {
-            Locator::min_ref()
-        } else if id == ExcerptId::max() {
-            Locator::max_ref()
-        } else {
-            let mut cursor = self.excerpt_ids.cursor::<ExcerptId>();
-            cursor.seek(&id, Bias::Left, &());
-            if let Some(entry) = cursor.item() {
-                if entry.id == id {
-                    return &entry.locator;
-                }
-            }
-            panic!("invalid excerpt id {:?}", id)
+    /// Returns the range to remove in order to delete the given rows, along with one of the
+    /// newlines around them, such as when cutting entire lines. The newline after the last
+    /// excerpt row separates it from the next excerpt and can't be removed, so in that case
+    /// the newline before the first row is removed instead, as long as it's in the same
+    /// excerpt.
+    pub fn range_to_delete_rows(&self, rows: RangeInclusive<u32>) -> Range<Point> {
+        let start_row = *rows.start();
+        let end_row = cmp::min(*rows.end(), self.max_point().row);
+        let mut cursor = self.excerpts.cursor::<Point>();
+        cursor.seek(&Point::new(end_row, 0), Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
         }
-    }
+        let (excerpt_start_row, excerpt_end_row) = cursor.item().map_or((0, 0), |excerpt| {
+            let start_row = cursor.start().row;
+            (start_row, start_row + excerpt.text_summary.lines.row)
+        });
 
-    pub fn buffer_id_for_excerpt(&self, excerpt_id: ExcerptId) -> Option<BufferId> {
-        Some(self.excerpt(excerpt_id)?.buffer_id)
+        let end_of_line = Point::new(end_row, self.line_len(end_row));
+        if end_row < excerpt_end_row {
+            Point::new(start_row, 0)..Point::new(end_row + 1, 0)
+        } else if start_row > excerpt_start_row {
+            Point::new(start_row - 1, self.line_len(start_row - 1))..end_of_line
+        } else {
+            Point::new(start_row, 0)..end_of_line
+        }
     }
 
-    pub fn buffer_for_excerpt(&self, excerpt_id: ExcerptId) -> Option<&BufferSnapshot> {
-        Some(&self.excerpt(excerpt_id)?.buffer)
+    pub fn line_len(&self, row: u32) -> u32 {
+        if let Some((_, range)) = self.buffer_line_for_row(row) {
+            range.end.column - range.start.column
+        } else {
+            0
+        }
     }
 
-    fn excerpt<'a>(&'a self, excerpt_id: ExcerptId) -> Option<&'a Excerpt> {
-        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
-        let locator = self.excerpt_locator_for_id(excerpt_id);
-        cursor.seek(&Some(locator), Bias::Left, &());
+    pub fn buffer_line_for_row(&self, row: u32) -> Option<(&BufferSnapshot, Range<Point>)> {
+        let mut cursor = self.excerpts.cursor::<Point>();
+        let point = Point::new(row, 0);
+        cursor.seek(&point, Bias::Right, &());
+        if cursor.item().is_none() && *cursor.start() == point {
+            cursor.prev(&());
+        }
         if let Some(excerpt) = cursor.item() {
-            if excerpt.id == excerpt_id {
-                return Some(excerpt);
-            }
+            let overshoot = row - cursor.start().row;
+            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer);
+            let excerpt_end = excerpt.range.context.end.to_point(&excerpt.buffer);
+            let buffer_row = excerpt_start.row + overshoot;
+            let line_start = Point::new(buffer_row, 0);
+            let line_end = Point::new(buffer_row, excerpt.buffer.line_len(buffer_row));
+            return Some((
+                &*excerpt.buffer,
+                line_start.max(excerpt_start)..line_end.min(excerpt_end),
+            ));
         }
         None
     }
 
-    /// Returns the excerpt containing range and its offset start within the multibuffer or none if `range` spans multiple excerpts
-    fn excerpt_containing<'a, T: ToOffset>(
-        &'a self,
-        range: Range<T>,
-    ) -> Option<(&'a Excerpt, usize)> {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
+    pub fn max_point(&self) -> Point {
+        self.text_summary().lines
+    }
+
+    pub fn text_summary(&self) -> TextSummary {
+        self.excerpts.summary().text.clone()
+    }
 
+    pub fn text_summary_for_range<D, O>(&self, range: Range<O>) -> D
+    where
+        D: TextDimension,
+        O: ToOffset,
+    {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
         let mut cursor = self.excerpts.cursor::<usize>();
         cursor.seek(&range.start, Bias::Right, &());
-        let start_excerpt = cursor.item();
+        Self::text_summary_at_cursor(&mut cursor, range)
+    }
 
-        if range.start == range.end {
-            return start_excerpt.map(|excerpt| (excerpt, *cursor.start()));
+    /// Returns the summaries of several ranges at once. This is cheaper than calling
+    /// [`MultiBufferSnapshot::text_summary_for_range`] repeatedly, because the excerpts are
+    /// traversed with a single cursor while the ranges are sorted by their start.
+    pub fn text_summaries_for_ranges<'a, D, O>(
+        &'a self,
+        ranges: impl IntoIterator<Item = Range<O>> + 'a,
+    ) -> impl Iterator<Item = D> + 'a
+    where
+        D: TextDimension,
+        O: ToOffset,
+    {
+        let mut cursor = self.excerpts.cursor::<usize>();
+        ranges.into_iter().map(move |range| {
+            let range = range.start.to_offset(self)..range.end.to_offset(self);
+            if range.start >= *cursor.start() {
+                cursor.seek_forward(&range.start, Bias::Right, &());
+            } else {
+                cursor.seek(&range.start, Bias::Right, &());
+            }
+            Self::text_summary_at_cursor(&mut cursor, range)
+        })
+    }
+
+    /// Summarizes the given range, given a cursor that has been seeked to its start.
+    fn text_summary_at_cursor<D: TextDimension>(
+        cursor: &mut Cursor<Excerpt, usize>,
+        mut range: Range<usize>,
+    ) -> D {
+        let mut summary = D::default();
+        if let Some(excerpt) = cursor.item() {
+            let mut end_before_newline = cursor.end(&());
+            if excerpt.has_trailing_newline {
+                end_before_newline -= 1;
+            }
+
+            let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let start_in_excerpt = excerpt_start + (range.start - cursor.start());
+            let end_in_excerpt =
+                excerpt_start + (cmp::min(end_before_newline, range.end) - cursor.start());
+            summary.add_assign(
+                &excerpt
+                    .buffer
+                    .text_summary_for_range(start_in_excerpt..end_in_excerpt),
+            );
+
+            if range.end > end_before_newline {
+                summary.add_assign(&D::from_text_summary(&TextSummary::from("\n")));
+            }
+
+            cursor.next(&());
         }
 
-        cursor.seek(&range.end, Bias::Right, &());
-        let end_excerpt = cursor.item();
+        if range.end > *cursor.start() {
+            summary.add_assign(&D::from_text_summary(&cursor.summary::<_, TextSummary>(
+                &range.end,
+                Bias::Right,
+                &(),
+            )));
+            if let Some(excerpt) = cursor.item() {
+                range.end = cmp::max(*cursor.start(), range.end);
 
-        start_excerpt
-            .zip(end_excerpt)
-            .and_then(|(start_excerpt, end_excerpt)| {
-                if start_excerpt.id != end_excerpt.id {
-                    return None;
-                }
+                let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+                let end_in_excerpt = excerpt_start + (range.end - cursor.start());
+                summary.add_assign(
+                    &excerpt
+                        .buffer
+                        .text_summary_for_range(excerpt_start..end_in_excerpt),
+                );
+            }
+        }
 
-                Some((start_excerpt, *cursor.start()))
-            })
+        summary
     }
 
-    pub fn remote_selections_in_range<'a>(
-        &'a self,
-        range: &'a Range<Anchor>,
-    ) -> impl 'a + Iterator<Item = (ReplicaId, bool, CursorShape, Selection<Anchor>)> {
+    pub fn summary_for_anchor<D>(&self, anchor: &Anchor) -> D
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+    {
         let mut cursor = self.excerpts.cursor::<ExcerptSummary>();
-        let start_locator = self.excerpt_locator_for_id(range.start.excerpt_id);
-        let end_locator = self.excerpt_locator_for_id(range.end.excerpt_id);
-        cursor.seek(start_locator, Bias::Left, &());
-        cursor
-            .take_while(move |excerpt| excerpt.locator <= *end_locator)
-            .flat_map(move |excerpt| {
-                let mut query_range = excerpt.range.context.start..excerpt.range.context.end;
-                if excerpt.id == range.start.excerpt_id {
-                    query_range.start = range.start.text_anchor;
-                }
-                if excerpt.id == range.end.excerpt_id {
-                    query_range.end = range.end.text_anchor;
-                }
-
-                excerpt
-                    .buffer
-                    .remote_selections_in_range(query_range)
-                    .flat_map(move |(replica_id, line_mode, cursor_shape, selections)| {
-                        selections.map(move |selection| {
-                            let mut start = Anchor {
-                                buffer_id: Some(excerpt.buffer_id),
-                                excerpt_id: excerpt.id.clone(),
-                                text_anchor: selection.start,
-                            };
-                            let mut end = Anchor {
-                                buffer_id: Some(excerpt.buffer_id),
-                                excerpt_id: excerpt.id.clone(),
-                                text_anchor: selection.end,
-                            };
-                            if range.start.cmp(&start, self).is_gt() {
-                                start = range.start.clone();
-                            }
-                            if range.end.cmp(&end, self).is_lt() {
-                                end = range.end.clone();
-                            }
-
-                            (
-                                replica_id,
-                                line_mode,
-                                cursor_shape,
-                                Selection {
-                                    id: selection.id,
-                                    start,
-                                    end,
-                                    reversed: selection.reversed,
-                                    goal: selection.goal,
-                                },
-                            )
-                        })
-                    })
-            })
-    }
-}
-
-#[cfg(any(test, feature = "test-support"))]
-impl MultiBufferSnapshot {
-    pub fn random_byte_range(&self, start_offset: usize, rng: &mut impl rand::Rng) -> Range<usize> {
-        let end = self.clip_offset(rng.gen_range(start_offset..=self.len()), Bias::Right);
-        let start = self.clip_offset(rng.gen_range(start_offset..=end), Bias::Right);
-        start..end
-    }
-}
+        let locator = self.excerpt_locator_for_id(anchor.excerpt_id);
 
-impl History {
-    fn start_transaction(&mut self, now: Instant) -> Option<TransactionId> {
-        self.transaction_depth += 1;
-        if self.transaction_depth == 1 {
-            let id = self.next_transaction_id.tick();
-            self.undo_stack.push(Transaction {
-                id,
-                buffer_transactions: Default::default(),
-                first_edit_at: now,
-                last_edit_at: now,
-                suppress_grouping: false,
-            });
-            Some(id)
-        } else {
-            None
+        cursor.seek(locator, Bias::Left, &());
+        if cursor.item().is_none() {
+            cursor.next(&());
         }
-    }
 
-    fn end_transaction(
-        &mut self,
-        now: Instant,
-        buffer_transactions: HashMap<BufferId, TransactionId>,
-    ) -> bool {
-        assert_ne!(self.transaction_depth, 0);
-        self.transaction_depth -= 1;
-        if self.transaction_depth == 0 {
-            if buffer_transactions.is_empty() {
-                self.undo_stack.pop();
-                false
-            } else {
-                self.redo_stack.clear();
-                let transaction = self.undo_stack.last_mut().unwrap();
-                transaction.last_edit_at = now;
-                for (buffer_id, transaction_id) in buffer_transactions {
-                    transaction
-                        .buffer_transactions
-                        .entry(buffer_id)
-                        .or_insert(transaction_id);
+        let mut position = D::from_text_summary(&cursor.start().text);
+        if let Some(excerpt) = cursor.item() {
+            if excerpt.id == anchor.excerpt_id {
+                let excerpt_buffer_start =
+                    excerpt.range.context.start.summary::<D>(&excerpt.buffer);
+                let excerpt_buffer_end = excerpt.range.context.end.summary::<D>(&excerpt.buffer);
+                let buffer_position = cmp::min(
+                    excerpt_buffer_end,
+                    anchor.text_anchor.summary::<D>(&excerpt.buffer),
+                );
+                if buffer_position > excerpt_buffer_start {
+                    position.add_assign(&(buffer_position - excerpt_buffer_start));
                 }
-                true
             }
-        } else {
-            false
         }
+        position
     }
 
-    fn push_transaction<'a, T>(
-        &mut self,
-        buffer_transactions: T,
-        now: Instant,
-        cx: &mut ModelContext<MultiBuffer>,
-    ) where
-        T: IntoIterator<Item = (&'a Model<Buffer>, &'a language::Transaction)>,
+    /// Resolves the given anchors in a single pass over the excerpts. The anchors must be
+    /// sorted; use [`Self::summaries_for_unsorted_anchors`] when that can't be guaranteed.
+    pub fn summaries_for_anchors<'a, D, I>(&'a self, anchors: I) -> Vec<D>
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+        I: 'a + IntoIterator<Item = &'a Anchor>,
     {
-        assert_eq!(self.transaction_depth, 0);
-        let transaction = Transaction {
-            id: self.next_transaction_id.tick(),
-            buffer_transactions: buffer_transactions
-                .into_iter()
-                .map(|(buffer, transaction)| (buffer.read(cx).remote_id(), transaction.id))
-                .collect(),
-            first_edit_at: now,
-            last_edit_at: now,
-            suppress_grouping: false,
-        };
-        if !transaction.buffer_transactions.is_empty() {
-            self.undo_stack.push(transaction);
-            self.redo_stack.clear();
+        if let Some((_, _, buffer)) = self.as_singleton() {
+            return buffer
+                .summaries_for_anchors(anchors.into_iter().map(|a| &a.text_anchor))
+                .collect();
         }
-    }
 
-    fn finalize_last_transaction(&mut self) {
-        if let Some(transaction) = self.undo_stack.last_mut() {
-            transaction.suppress_grouping = true;
+        let mut anchors = anchors.into_iter().peekable();
+        let mut cursor = self.excerpts.cursor::<ExcerptSummary>();
+        let mut summaries = Vec::new();
+        while let Some(anchor) = anchors.peek() {
+            let excerpt_id = anchor.excerpt_id;
+            let excerpt_anchors = iter::from_fn(|| {
+                let anchor = anchors.peek()?;
+                if anchor.excerpt_id == excerpt_id {
+                    Some(&anchors.next().unwrap().text_anchor)
+                } else {
+                    None
+                }
+            });
+
+            let locator = self.excerpt_locator_for_id(excerpt_id);
+            cursor.seek_forward(locator, Bias::Left, &());
+            if cursor.item().is_none() {
+                cursor.next(&());
+            }
+
+            let position = D::from_text_summary(&cursor.start().text);
+            if let Some(excerpt) = cursor.item() {
+                if excerpt.id == excerpt_id {
+                    let excerpt_buffer_start =
+                        excerpt.range.context.start.summary::<D>(&excerpt.buffer);
+                    let excerpt_buffer_end =
+                        excerpt.range.context.end.summary::<D>(&excerpt.buffer);
+                    summaries.extend(
+                        excerpt
+                            .buffer
+                            .summaries_for_anchors::<D, _>(excerpt_anchors)
+                            .map(move |summary| {
+                                let summary = cmp::min(excerpt_buffer_end.clone(), summary);
+                                let mut position = position.clone();
+                                let excerpt_buffer_start = excerpt_buffer_start.clone();
+                                if summary > excerpt_buffer_start {
+                                    position.add_assign(&(summary - excerpt_buffer_start));
+                                }
+                                position
+                            }),
+                    );
+                    continue;
+                }
+            }
+
+            summaries.extend(excerpt_anchors.map(|_| position.clone()));
         }
+
+        summaries
     }
 
-    fn forget(&mut self, transaction_id: TransactionId) -> Option<Transaction> {
-        if let Some(ix) = self
-            .undo_stack
-            .iter()
-            .rposition(|transaction| transaction.id == transaction_id)
-        {
-            Some(self.undo_stack.remove(ix))
-        } else if let Some(ix) = self
-            .redo_stack
+    /// Like [`Self::summaries_for_anchors`], but accepts anchors in any order, returning
+    /// their summaries in the order the anchors were given.
+    pub fn summaries_for_unsorted_anchors<'a, D, I>(&'a self, anchors: I) -> Vec<D>
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+        I: 'a + IntoIterator<Item = &'a Anchor>,
+    {
+        let anchors = anchors.into_iter().collect::<Vec<_>>();
+        let mut order = (0..anchors.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| anchors[*a].cmp(anchors[*b], self));
+        if order
             .iter()
-            .rposition(|transaction| transaction.id == transaction_id)
+            .enumerate()
+            .all(|(ix, order_ix)| ix == *order_ix)
         {
-            Some(self.redo_stack.remove(ix))
-        } else {
-            None
+            return self.summaries_for_anchors(anchors);
         }
-    }
-
-    fn transaction_mut(&mut self, transaction_id: TransactionId) -> Option<&mut Transaction> {
-        self.undo_stack
-            .iter_mut()
-            .find(|transaction| transaction.id == transaction_id)
-            .or_else(|| {
-                self.redo_stack
-                    .iter_mut()
-                    .find(|transaction| transaction.id == transaction_id)
-            })
-    }
 
-    fn pop_undo(&mut self) -> Option<&mut Transaction> {
-        assert_eq!(self.transaction_depth, 0);
-        if let Some(transaction) = self.undo_stack.pop() {
-            self.redo_stack.push(transaction);
-            self.redo_stack.last_mut()
-        } else {
-            None
+        let sorted_summaries =
+            self.summaries_for_anchors::<D, _>(order.iter().map(|ix| anchors[*ix]));
+        let mut summaries = vec![D::default(); anchors.len()];
+        for (ix, summary) in order.into_iter().zip(sorted_summaries) {
+            summaries[ix] = summary;
         }
+        summaries
     }
 
-    fn pop_redo(&mut self) -> Option<&mut Transaction> {
-        assert_eq!(self.transaction_depth, 0);
-        if let Some(transaction) = self.redo_stack.pop() {
-            self.undo_stack.push(transaction);
-            self.undo_stack.last_mut()
-        } else {
-            None
-        }
+    /// Resolves the given anchor ranges in a single pass over the excerpts. The ranges
+    /// must be sorted and non-overlapping, as selections are.
+    pub fn summaries_for_anchor_ranges<'a, D, I>(&'a self, ranges: I) -> Vec<Range<D>>
+    where
+        D: TextDimension + Ord + Sub<D, Output = D>,
+        I: 'a + IntoIterator<Item = &'a Range<Anchor>>,
+    {
+        let mut summaries = self
+            .summaries_for_anchors::<D, _>(
+                ranges
+                    .into_iter()
+                    .flat_map(|range| [&range.start, &range.end]),
+            )
+            .into_iter();
+        iter::from_fn(|| Some(summaries.next()?..summaries.next()?)).collect()
     }
 
-    fn remove_from_undo(&mut self, transaction_id: TransactionId) -> Option<&Transaction> {
-        let ix = self
-            .undo_stack
-            .iter()
-            .rposition(|transaction| transaction.id == transaction_id)?;
-        let transaction = self.undo_stack.remove(ix);
-        self.redo_stack.push(transaction);
-        self.redo_stack.last()
-    }
+    /// Updates the given anchors so that they point into excerpts that still exist, moving
+    /// anchors whose excerpt was removed into an adjacent excerpt.
+    ///
+    /// The anchors can be given in any order and may contain duplicates. The results are
+    /// returned in input order, each with the index of the anchor it was computed from and
+    /// whether that anchor kept its original position.
+    pub fn refresh_anchors<'a, I>(&'a self, anchors: I) -> Vec<(usize, Anchor, bool)>
+    where
+        I: 'a + IntoIterator<Item = &'a Anchor>,
+    {
+        // Group the anchors by excerpt so that the excerpts can be visited in a single pass.
+        let mut anchors = anchors.into_iter().enumerate().collect::<Vec<_>>();
+        anchors.sort_by_key(|(_, anchor)| self.excerpt_locator_for_id(anchor.excerpt_id));
+        let mut anchors = anchors.into_iter().peekable();
+        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
+        cursor.next(&());
 
-    fn group(&mut self) -> Option<TransactionId> {
-        let mut count = 0;
-        let mut transactions = self.undo_stack.iter();
-        if let Some(mut transaction) = transactions.next_back() {
-            while let Some(prev_transaction) = transactions.next_back() {
-                if !prev_transaction.suppress_grouping
-                    && transaction.first_edit_at - prev_transaction.last_edit_at
-                        <= self.group_interval
-                {
-                    transaction = prev_transaction;
-                    count += 1;
-                } else {
+        let mut result = Vec::new();
+
+        while let Some((_, anchor)) = anchors.peek() {
+            let old_excerpt_id = anchor.excerpt_id;
+
+            // Find the location where this anchor's excerpt should be.
+            let old_locator = self.excerpt_locator_for_id(old_excerpt_id);
+            cursor.seek_forward(&Some(old_locator), Bias::Left, &());
+
+            if cursor.item().is_none() {
+                cursor.next(&());
+            }
+
+            let next_excerpt = cursor.item();
+            let prev_excerpt = cursor.prev_item();
+
+            // Process all of the anchors for this excerpt.
+            while let Some((_, anchor)) = anchors.peek() {
+                if anchor.excerpt_id != old_excerpt_id {
                     break;
                 }
-            }
-        }
-        self.group_trailing(count)
-    }
+                let (anchor_ix, anchor) = anchors.next().unwrap();
+                let mut anchor = *anchor;
 
-    fn group_until(&mut self, transaction_id: TransactionId) {
-        let mut count = 0;
-        for transaction in self.undo_stack.iter().rev() {
-            if transaction.id == transaction_id {
-                self.group_trailing(count);
-                break;
-            } else if transaction.suppress_grouping {
-                break;
-            } else {
-                count += 1;
-            }
-        }
-    }
+                // Leave min and max anchors unchanged if invalid or
+                // if the old excerpt still exists at this location
+                let mut kept_position = next_excerpt
+                    .map_or(false, |e| e.id == old_excerpt_id && e.contains(&anchor))
+                    || old_excerpt_id == ExcerptId::max()
+                    || old_excerpt_id == ExcerptId::min();
 
-    fn group_trailing(&mut self, n: usize) -> Option<TransactionId> {
-        let new_len = self.undo_stack.len() - n;
-        let (transactions_to_keep, transactions_to_merge) = self.undo_stack.split_at_mut(new_len);
-        if let Some(last_transaction) = transactions_to_keep.last_mut() {
-            if let Some(transaction) = transactions_to_merge.last() {
-                last_transaction.last_edit_at = transaction.last_edit_at;
-            }
-            for to_merge in transactions_to_merge {
-                for (buffer_id, transaction_id) in &to_merge.buffer_transactions {
-                    last_transaction
-                        .buffer_transactions
-                        .entry(*buffer_id)
-                        .or_insert(*transaction_id);
+                // If the old excerpt no longer exists at this location, then attempt to
+                // find an equivalent position for this anchor in an adjacent excerpt.
+                if !kept_position {
+                    for excerpt in [next_excerpt, prev_excerpt].iter().filter_map(|e| *e) {
+                        if excerpt.contains(&anchor) {
+                            anchor.excerpt_id = excerpt.id.clone();
+                            kept_position = true;
+                            break;
+                        }
+                    }
+                }
+
+                // If there's no adjacent excerpt that contains the anchor's position,
+                // then report that the anchor has lost its position.
+                if !kept_position {
+                    anchor = if let Some(excerpt) = next_excerpt {
+                        let mut text_anchor = excerpt
+                            .range
+                            .context
+                            .start
+                            .bias(anchor.text_anchor.bias, &excerpt.buffer);
+                        if text_anchor
+                            .cmp(&excerpt.range.context.end, &excerpt.buffer)
+                            .is_gt()
+                        {
+                            text_anchor = excerpt.range.context.end;
+                        }
+                        Anchor {
+                            buffer_id: Some(excerpt.buffer_id),
+                            excerpt_id: excerpt.id.clone(),
+                            text_anchor,
+                        }
+                    } else if let Some(excerpt) = prev_excerpt {
+                        let mut text_anchor = excerpt
+                            .range
+                            .context
+                            .end
+                            .bias(anchor.text_anchor.bias, &excerpt.buffer);
+                        if text_anchor
+                            .cmp(&excerpt.range.context.start, &excerpt.buffer)
+                            .is_lt()
+                        {
+                            text_anchor = excerpt.range.context.start;
+                        }
+                        Anchor {
+                            buffer_id: Some(excerpt.buffer_id),
+                            excerpt_id: excerpt.id.clone(),
+                            text_anchor,
+                        }
+                    } else if anchor.text_anchor.bias == Bias::Left {
+                        Anchor::min()
+                    } else {
+                        Anchor::max()
+                    };
                 }
+
+                result.push((anchor_ix, anchor, kept_position));
             }
         }
+        result.sort_unstable_by_key(|(anchor_ix, _, _)| *anchor_ix);
+        result
+    }
 
-        self.undo_stack.truncate(new_len);
-        self.undo_stack.last().map(|t| t.id)
+    pub fn anchor_before<T: ToOffset>(&self, position: T) -> Anchor {
+        self.anchor_at(position, Bias::Left)
     }
-}
 
-impl Excerpt {
-    fn new(
-        id: ExcerptId,
-        locator: Locator,
-        buffer_id: BufferId,
-        buffer: BufferSnapshot,
-        range: ExcerptRange<text::Anchor>,
-        has_trailing_newline: bool,
-    ) -> Self {
-        Excerpt {
-            id,
-            locator,
-            max_buffer_row: range.context.end.to_point(&buffer).row,
-            text_summary: buffer
-                .text_summary_for_range::<TextSummary, _>(range.context.to_offset(&buffer)),
-            buffer_id,
-            buffer,
-            range,
-            has_trailing_newline,
-        }
+    pub fn anchor_after<T: ToOffset>(&self, position: T) -> Anchor {
+        self.anchor_at(position, Bias::Right)
     }
 
-    fn chunks_in_range(&self, range: Range<usize>, language_aware: bool) -> ExcerptChunks {
-        let content_start = self.range.context.start.to_offset(&self.buffer);
-        let chunks_start = content_start + range.start;
-        let chunks_end = content_start + cmp::min(range.end, self.text_summary.len);
+    pub fn anchor_at<T: ToOffset>(&self, position: T, mut bias: Bias) -> Anchor {
+        let offset = position.to_offset(self);
+        if let Some((excerpt_id, buffer_id, buffer)) = self.as_singleton() {
+            return Anchor {
+                buffer_id: Some(buffer_id),
+                excerpt_id: excerpt_id.clone(),
+                text_anchor: buffer.anchor_at(offset, bias),
+            };
+        }
 
-        let footer_height = if self.has_trailing_newline
-            && range.start <= self.text_summary.len
-            && range.end > self.text_summary.len
-        {
-            1
-        } else {
-            0
-        };
+        let mut cursor = self.excerpts.cursor::<(usize, Option<ExcerptId>)>();
+        cursor.seek(&offset, Bias::Right, &());
+        if cursor.item().is_none() && offset == cursor.start().0 && bias == Bias::Left {
+            cursor.prev(&());
+        }
+        if let Some(excerpt) = cursor.item() {
+            let mut overshoot = offset.saturating_sub(cursor.start().0);
+            if excerpt.has_trailing_newline && offset == cursor.end(&()).0 {
+                overshoot -= 1;
+                bias = Bias::Right;
+            }
 
-        let content_chunks = self.buffer.chunks(chunks_start..chunks_end, language_aware);
+            let buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let text_anchor =
+                excerpt.clip_anchor(excerpt.buffer.anchor_at(buffer_start + overshoot, bias));
+            Anchor {
+                buffer_id: Some(excerpt.buffer_id),
+                excerpt_id: excerpt.id.clone(),
+                text_anchor,
+            }
+        } else if offset == 0 && bias == Bias::Left {
+            Anchor::min()
+        } else {
+            Anchor::max()
+        }
+    }
 
-        ExcerptChunks {
-            content_chunks,
-            footer_height,
+    pub fn anchor_in_excerpt(&self, excerpt_id: ExcerptId, text_anchor: text::Anchor) -> Anchor {
+        let locator = self.excerpt_locator_for_id(excerpt_id);
+        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
+        cursor.seek(locator, Bias::Left, &());
+        if let Some(excerpt) = cursor.item() {
+            if excerpt.id == excerpt_id {
+                let text_anchor = excerpt.clip_anchor(text_anchor);
+                drop(cursor);
+                return Anchor {
+                    buffer_id: Some(excerpt.buffer_id),
+                    excerpt_id,
+                    text_anchor,
+                };
+            }
         }
+        panic!("excerpt not found");
     }
 
-    fn bytes_in_range(&self, range: Range<usize>) -> ExcerptBytes {
-        let content_start = self.range.context.start.to_offset(&self.buffer);
-        let bytes_start = content_start + range.start;
-        let bytes_end = content_start + cmp::min(range.end, self.text_summary.len);
-        let footer_height = if self.has_trailing_newline
-            && range.start <= self.text_summary.len
-            && range.end > self.text_summary.len
-        {
-            1
-        } else {
-            0
-        };
-        let content_bytes = self.buffer.bytes_in_range(bytes_start..bytes_end);
+    /// Returns an anchor pinned to the start of the given excerpt's context range.
+    pub fn anchor_at_excerpt_start(&self, excerpt_id: ExcerptId) -> Option<Anchor> {
+        let excerpt = self.excerpt(excerpt_id)?;
+        Some(Anchor {
+            buffer_id: Some(excerpt.buffer_id),
+            excerpt_id,
+            text_anchor: excerpt.range.context.start,
+        })
+    }
 
-        ExcerptBytes {
-            content_bytes,
-            footer_height,
-        }
+    /// Returns an anchor pinned to the end of the given excerpt's context range.
+    pub fn anchor_at_excerpt_end(&self, excerpt_id: ExcerptId) -> Option<Anchor> {
+        let excerpt = self.excerpt(excerpt_id)?;
+        Some(Anchor {
+            buffer_id: Some(excerpt.buffer_id),
+            excerpt_id,
+            text_anchor: excerpt.range.context.end,
+        })
     }
 
-    fn reversed_bytes_in_range(&self, range: Range<usize>) -> ExcerptBytes {
-        let content_start = self.range.context.start.to_offset(&self.buffer);
-        let bytes_start = content_start + range.start;
-        let bytes_end = content_start + cmp::min(range.end, self.text_summary.len);
-        let footer_height = if self.has_trailing_newline
-            && range.start <= self.text_summary.len
-            && range.end > self.text_summary.len
-        {
-            1
+    pub fn can_resolve(&self, anchor: &Anchor) -> bool {
+        if anchor.excerpt_id == ExcerptId::min() || anchor.excerpt_id == ExcerptId::max() {
+            true
+        } else if let Some(excerpt) = self.excerpt(anchor.excerpt_id) {
+            excerpt.buffer.can_resolve(&anchor.text_anchor)
         } else {
-            0
-        };
-        let content_bytes = self.buffer.reversed_bytes_in_range(bytes_start..bytes_end);
-
-        ExcerptBytes {
-            content_bytes,
-            footer_height,
+            false
         }
     }
 
-    fn clip_anchor(&self, text_anchor: text::Anchor) -> text::Anchor {
-        if text_anchor
-            .cmp(&self.range.context.start, &self.buffer)
-            .is_lt()
-        {
-            self.range.context.start
-        } else if text_anchor
-            .cmp(&self.range.context.end, &self.buffer)
-            .is_gt()
-        {
-            self.range.context.end
-        } else {
-            text_anchor
-        }
-    }
-
-    fn contains(&self, anchor: &Anchor) -> bool {
-        Some(self.buffer_id) == anchor.buffer_id
-            && self
-                .range
-                .context
-                .start
-                .cmp(&anchor.text_anchor, &self.buffer)
-                .is_le()
-            && self
-                .range
-                .context
-                .end
-                .cmp(&anchor.text_anchor, &self.buffer)
-                .is_ge()
+    pub fn can_resolve_all(&self, anchors: &[Anchor]) -> Vec<bool> {
+        let mut last_excerpt: Option<(ExcerptId, Option<&Excerpt>)> = None;
+        anchors
+            .iter()
+            .map(|anchor| {
+                if anchor.excerpt_id == ExcerptId::min() || anchor.excerpt_id == ExcerptId::max() {
+                    return true;
+                }
+                let excerpt = match last_excerpt {
+                    Some((excerpt_id, excerpt)) if excerpt_id == anchor.excerpt_id => excerpt,
+                    _ => {
+                        let excerpt = self.excerpt(anchor.excerpt_id);
+                        last_excerpt = Some((anchor.excerpt_id, excerpt));
+                        excerpt
+                    }
+                };
+                excerpt.map_or(false, |excerpt| {
+                    excerpt.buffer.can_resolve(&anchor.text_anchor)
+                })
+            })
+            .collect()
     }
-}
 
-impl ExcerptId {
-    pub fn min() -> Self {
-        Self(0)
-    }
+    /// Reports which of the given anchors can't be resolved in this snapshot, along with
+    /// their index and the reason. This is intended for debugging anchor invalidation.
+    pub fn validate_anchors(&self, anchors: &[Anchor]) -> Vec<(usize, StaleAnchorReason)> {
+        let mut stale_anchors = Vec::new();
+        for (ix, anchor) in anchors.iter().enumerate() {
+            if anchor.excerpt_id == ExcerptId::min() || anchor.excerpt_id == ExcerptId::max() {
+                continue;
+            }
 
-    pub fn max() -> Self {
-        Self(usize::MAX)
-    }
+            if !self.has_excerpt_id(anchor.excerpt_id) {
+                stale_anchors.push((ix, StaleAnchorReason::UnknownExcerpt));
+                continue;
+            }
 
-    pub fn to_proto(&self) -> u64 {
-        self.0 as _
+            let reason = if let Some(excerpt) = self.excerpt(anchor.excerpt_id) {
+                if anchor.buffer_id != Some(excerpt.buffer_id) {
+                    Some(StaleAnchorReason::BufferMismatch)
+                } else if !excerpt.buffer.can_resolve(&anchor.text_anchor) {
+                    Some(StaleAnchorReason::UnknownBufferVersion)
+                } else if !excerpt.contains(anchor) {
+                    Some(StaleAnchorReason::OutsideExcerpt)
+                } else {
+                    None
+                }
+            } else {
+                Some(StaleAnchorReason::ExcerptRemoved)
+            };
+            if let Some(reason) = reason {
+                stale_anchors.push((ix, reason));
+            }
+        }
+        stale_anchors
     }
 
-    pub fn from_proto(proto: u64) -> Self {
-        Self(proto as _)
+    pub fn excerpts(
+        &self,
+    ) -> impl Iterator<Item = (ExcerptId, &BufferSnapshot, ExcerptRange<text::Anchor>)> {
+        self.excerpts
+            .iter()
+            .map(|excerpt| (excerpt.id, &*excerpt.buffer, excerpt.range.clone()))
     }
 
-    pub fn cmp(&self, other: &Self, snapshot: &MultiBufferSnapshot) -> cmp::Ordering {
-        let a = snapshot.excerpt_locator_for_id(*self);
-        let b = snapshot.excerpt_locator_for_id(*other);
-        a.cmp(b).then_with(|| self.0.cmp(&other.0))
-    }
-}
+    fn excerpts_for_range<'a, T: ToOffset>(
+        &'a self,
+        range: Range<T>,
+    ) -> impl Iterator<Item = (&'a Excerpt, usize)> + 'a {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
 
-impl Into<usize> for ExcerptId {
-    fn into(self) -> usize {
-        self.0
-    }
-}
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&range.start, Bias::Right, &());
+        cursor.prev(&());
 
-impl fmt::Debug for Excerpt {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Excerpt")
-            .field("id", &self.id)
-            .field("locator", &self.locator)
-            .field("buffer_id", &self.buffer_id)
-            .field("range", &self.range)
-            .field("text_summary", &self.text_summary)
-            .field("has_trailing_newline", &self.has_trailing_newline)
-            .finish()
+        iter::from_fn(move || {
+            cursor.next(&());
+            if cursor.start() < &range.end {
+                cursor.item().map(|item| (item, *cursor.start()))
+            } else {
+                None
+            }
+        })
     }
-}
 
-impl sum_tree::Item for Excerpt {
-    type Summary = ExcerptSummary;
+    pub fn excerpt_boundaries_in_range<R, T>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = ExcerptBoundary> + '_
+    where
+        R: RangeBounds<T>,
+        T: ToOffset,
+    {
+        let start_offset;
+        let start = match range.start_bound() {
+            Bound::Included(start) => {
+                start_offset = start.to_offset(self);
+                Bound::Included(start_offset)
+            }
+            Bound::Excluded(start) => {
+                start_offset = start.to_offset(self);
+                Bound::Excluded(start_offset)
+            }
+            Bound::Unbounded => {
+                start_offset = 0;
+                Bound::Unbounded
+            }
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(end.to_offset(self)),
+            Bound::Excluded(end) => Bound::Excluded(end.to_offset(self)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let bounds = (start, end);
 
-    fn summary(&self) -> Self::Summary {
-        let mut text = self.text_summary.clone();
-        if self.has_trailing_newline {
-            text += TextSummary::from("\n");
+        let mut cursor = self.excerpts.cursor::<(usize, Point)>();
+        cursor.seek(&start_offset, Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
         }
-        ExcerptSummary {
-            excerpt_id: self.id,
-            excerpt_locator: self.locator.clone(),
-            max_buffer_row: self.max_buffer_row,
-            text,
+        if !bounds.contains(&cursor.start().0) {
+            cursor.next(&());
         }
-    }
-}
 
-impl sum_tree::Item for ExcerptIdMapping {
-    type Summary = ExcerptId;
+        let mut prev_buffer_id = cursor.prev_item().map(|excerpt| excerpt.buffer_id);
+        std::iter::from_fn(move || {
+            if self.singleton {
+                None
+            } else if bounds.contains(&cursor.start().0) {
+                let excerpt = cursor.item()?;
+                let starts_new_buffer = Some(excerpt.buffer_id) != prev_buffer_id;
+                let boundary = ExcerptBoundary {
+                    id: excerpt.id.clone(),
+                    row: cursor.start().1.row,
+                    buffer: BufferSnapshot::clone(&excerpt.buffer),
+                    range: excerpt.range.clone(),
+                    starts_new_buffer,
+                };
 
-    fn summary(&self) -> Self::Summary {
-        self.id
+                prev_buffer_id = Some(excerpt.buffer_id);
+                cursor.next(&());
+                Some(boundary)
+            } else {
+                None
+            }
+        })
     }
-}
 
-impl sum_tree::KeyedItem for ExcerptIdMapping {
-    type Key = ExcerptId;
+    /// Returns the header that should be pinned to the top of the viewport when the given row
+    /// is at its top. Use [`MultiBufferSnapshot::excerpt_annotation`] with the header's excerpt
+    /// id to retrieve any data attached to it.
+    pub fn header_for_row(&self, top_row: u32) -> Option<StickyHeader> {
+        if self.singleton {
+            return None;
+        }
 
-    fn key(&self) -> Self::Key {
-        self.id
+        let mut cursor = self.excerpts.cursor::<Point>();
+        cursor.seek(&Point::new(top_row, 0), Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
+        }
+        let excerpt = cursor.item()?;
+        let boundary = ExcerptBoundary {
+            id: excerpt.id,
+            row: cursor.start().row,
+            buffer: BufferSnapshot::clone(&excerpt.buffer),
+            range: excerpt.range.clone(),
+            starts_new_buffer: cursor.prev_item().map_or(true, |prev_excerpt| {
+                prev_excerpt.buffer_id != excerpt.buffer_id
+            }),
+        };
+        cursor.next(&());
+        let next_header_row = cursor.item().map(|_| cursor.start().row);
+        Some(StickyHeader {
+            boundary,
+            next_header_row,
+        })
     }
-}
 
-impl sum_tree::Summary for ExcerptId {
-    type Context = ();
+    pub fn edit_count(&self) -> usize {
+        self.edit_count
+    }
 
-    fn add_summary(&mut self, other: &Self, _: &()) {
-        *self = *other;
+    pub fn parse_count(&self) -> usize {
+        self.parse_count
     }
-}
 
-impl sum_tree::Summary for ExcerptSummary {
-    type Context = ();
+    /// Returns the smallest enclosing bracket ranges containing the given range or
+    /// None if no brackets contain range or the range is not contained in a single
+    /// excerpt
+    pub fn innermost_enclosing_bracket_ranges<T: ToOffset>(
+        &self,
+        range: Range<T>,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
 
-    fn add_summary(&mut self, summary: &Self, _: &()) {
-        debug_assert!(summary.excerpt_locator > self.excerpt_locator);
-        self.excerpt_locator = summary.excerpt_locator.clone();
-        self.text.add_summary(&summary.text, &());
-        self.max_buffer_row = cmp::max(self.max_buffer_row, summary.max_buffer_row);
-    }
-}
+        // Get the ranges of the innermost pair of brackets.
+        let mut result: Option<(Range<usize>, Range<usize>)> = None;
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for TextSummary {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self += &summary.text;
-    }
-}
+        let Some(enclosing_bracket_ranges) = self.enclosing_bracket_ranges(range.clone()) else {
+            return None;
+        };
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for usize {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self += summary.text.len;
-    }
-}
+        for (open, close) in enclosing_bracket_ranges {
+            let len = close.end - open.start;
 
-impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, ExcerptSummary> for usize {
-    fn cmp(&self, cursor_location: &ExcerptSummary, _: &()) -> cmp::Ordering {
-        Ord::cmp(self, &cursor_location.text.len)
-    }
-}
+            if let Some((existing_open, existing_close)) = &result {
+                let existing_len = existing_close.end - existing_open.start;
+                if len > existing_len {
+                    continue;
+                }
+            }
 
-impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, Option<&'a Locator>> for Locator {
-    fn cmp(&self, cursor_location: &Option<&'a Locator>, _: &()) -> cmp::Ordering {
-        Ord::cmp(&Some(self), cursor_location)
-    }
-}
+            result = Some((open, close));
+        }
 
-impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, ExcerptSummary> for Locator {
-    fn cmp(&self, cursor_location: &ExcerptSummary, _: &()) -> cmp::Ordering {
-        Ord::cmp(self, &cursor_location.excerpt_locator)
+        result
     }
-}
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for OffsetUtf16 {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self += summary.text.len_utf16;
-    }
-}
+    /// Returns the innermost pair of brackets enclosing the given range, or None if no brackets
+    /// contain the range or the range is not contained in a single excerpt. Unlike
+    /// [`Self::innermost_enclosing_bracket_ranges`], this includes pairs that extend beyond the
+    /// excerpt, omitting the brackets that aren't visible.
+    pub fn innermost_bracket_pair<T: ToOffset>(
+        &self,
+        range: Range<T>,
+    ) -> Option<BracketPairRanges> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        let (excerpt, excerpt_offset) = self.excerpt_containing(range.clone())?;
+        let excerpt_buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+        let excerpt_buffer_end = excerpt_buffer_start + excerpt.text_summary.len;
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Point {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self += summary.text.lines;
+        let start_in_buffer = excerpt_buffer_start + range.start.saturating_sub(excerpt_offset);
+        let end_in_buffer = excerpt_buffer_start + range.end.saturating_sub(excerpt_offset);
+        let (open, close) = excerpt
+            .buffer
+            .bracket_ranges(start_in_buffer..end_in_buffer)
+            .filter(|(open, close)| open.start <= start_in_buffer && close.end >= end_in_buffer)
+            .min_by_key(|(open, close)| close.end - open.start)?;
+
+        let to_excerpt_range = |range: Range<usize>| {
+            (range.start >= excerpt_buffer_start && range.end <= excerpt_buffer_end).then(|| {
+                excerpt_offset + (range.start - excerpt_buffer_start)
+                    ..excerpt_offset + (range.end - excerpt_buffer_start)
+            })
+        };
+        Some(BracketPairRanges {
+            open: to_excerpt_range(open),
+            close: to_excerpt_range(close),
+        })
     }
-}
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for PointUtf16 {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self += summary.text.lines_utf16()
-    }
-}
+    /// Returns enclosing bracket ranges containing the given range or returns None if the range is
+    /// not contained in a single excerpt
+    pub fn enclosing_bracket_ranges<'a, T: ToOffset>(
+        &'a self,
+        range: Range<T>,
+    ) -> Option<impl Iterator<Item = (Range<usize>, Range<usize>)> + 'a> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Option<&'a Locator> {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self = Some(&summary.excerpt_locator);
+        self.bracket_ranges(range.clone()).map(|range_pairs| {
+            range_pairs
+                .filter(move |(open, close)| open.start <= range.start && close.end >= range.end)
+        })
     }
-}
 
-impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Option<ExcerptId> {
-    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
-        *self = Some(summary.excerpt_id);
-    }
-}
+    /// Returns bracket range pairs overlapping the given `range` or returns None if the `range` is
+    /// not contained in a single excerpt
+    pub fn bracket_ranges<'a, T: ToOffset>(
+        &'a self,
+        range: Range<T>,
+    ) -> Option<impl Iterator<Item = (Range<usize>, Range<usize>)> + 'a> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        let excerpt = self.excerpt_containing(range.clone());
+        excerpt.map(|(excerpt, excerpt_offset)| {
+            let excerpt_buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+            let excerpt_buffer_end = excerpt_buffer_start + excerpt.text_summary.len;
 
-impl<'a> MultiBufferRows<'a> {
-    pub fn seek(&mut self, row: u32) {
-        self.buffer_row_range = 0..0;
+            let start_in_buffer = excerpt_buffer_start + range.start.saturating_sub(excerpt_offset);
+            let end_in_buffer = excerpt_buffer_start + range.end.saturating_sub(excerpt_offset);
 
-        self.excerpts
-            .seek_forward(&Point::new(row, 0), Bias::Right, &());
-        if self.excerpts.item().is_none() {
-            self.excerpts.prev(&());
+            excerpt
+                .buffer
+                .bracket_ranges(start_in_buffer..end_in_buffer)
+                .filter_map(move |(start_bracket_range, end_bracket_range)| {
+                    if start_bracket_range.start < excerpt_buffer_start
+                        || end_bracket_range.end > excerpt_buffer_end
+                    {
+                        return None;
+                    }
 
-            if self.excerpts.item().is_none() && row == 0 {
-                self.buffer_row_range = 0..1;
-                return;
-            }
-        }
+                    let mut start_bracket_range = start_bracket_range.clone();
+                    start_bracket_range.start =
+                        excerpt_offset + (start_bracket_range.start - excerpt_buffer_start);
+                    start_bracket_range.end =
+                        excerpt_offset + (start_bracket_range.end - excerpt_buffer_start);
 
-        if let Some(excerpt) = self.excerpts.item() {
-            let overshoot = row - self.excerpts.start().row;
-            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer).row;
-            self.buffer_row_range.start = excerpt_start + overshoot;
-            self.buffer_row_range.end = excerpt_start + excerpt.text_summary.lines.row + 1;
-        }
+                    let mut end_bracket_range = end_bracket_range.clone();
+                    end_bracket_range.start =
+                        excerpt_offset + (end_bracket_range.start - excerpt_buffer_start);
+                    end_bracket_range.end =
+                        excerpt_offset + (end_bracket_range.end - excerpt_buffer_start);
+                    Some((start_bracket_range, end_bracket_range))
+                })
+        })
     }
-}
 
-impl<'a> Iterator for MultiBufferRows<'a> {
-    type Item = Option<u32>;
+    pub fn redacted_ranges<'a, T: ToOffset>(
+        &'a self,
+        range: Range<T>,
+        redaction_enabled: impl Fn(Option<&Arc<dyn File>>) -> bool + 'a,
+    ) -> impl Iterator<Item = Range<usize>> + 'a {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        self.excerpts_for_range(range.clone())
+            .filter_map(move |(excerpt, excerpt_offset)| {
+                redaction_enabled(excerpt.buffer.file()).then(move || {
+                    let excerpt_buffer_start =
+                        excerpt.range.context.start.to_offset(&excerpt.buffer);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if !self.buffer_row_range.is_empty() {
-                let row = Some(self.buffer_row_range.start);
-                self.buffer_row_range.start += 1;
-                return Some(row);
-            }
-            self.excerpts.item()?;
-            self.excerpts.next(&());
-            let excerpt = self.excerpts.item()?;
-            self.buffer_row_range.start = excerpt.range.context.start.to_point(&excerpt.buffer).row;
-            self.buffer_row_range.end =
-                self.buffer_row_range.start + excerpt.text_summary.lines.row + 1;
-        }
-    }
-}
+                    excerpt
+                        .buffer
+                        .redacted_ranges(excerpt.range.context.clone())
+                        .map(move |mut redacted_range| {
+                            // Re-base onto the excerpts coordinates in the multibuffer
+                            redacted_range.start =
+                                excerpt_offset + (redacted_range.start - excerpt_buffer_start);
+                            redacted_range.end =
+                                excerpt_offset + (redacted_range.end - excerpt_buffer_start);
 
-impl<'a> MultiBufferChunks<'a> {
-    pub fn offset(&self) -> usize {
-        self.range.start
+                            redacted_range
+                        })
+                        .skip_while(move |redacted_range| redacted_range.end < range.start)
+                        .take_while(move |redacted_range| redacted_range.start < range.end)
+                })
+            })
+            .flatten()
     }
 
-    pub fn seek(&mut self, offset: usize) {
-        self.range.start = offset;
-        self.excerpts.seek(&offset, Bias::Right, &());
-        if let Some(excerpt) = self.excerpts.item() {
-            self.excerpt_chunks = Some(excerpt.chunks_in_range(
-                self.range.start - self.excerpts.start()..self.range.end - self.excerpts.start(),
-                self.language_aware,
-            ));
-        } else {
-            self.excerpt_chunks = None;
-        }
+    pub fn diagnostics_update_count(&self) -> usize {
+        self.diagnostics_update_count
     }
-}
 
-impl<'a> Iterator for MultiBufferChunks<'a> {
-    type Item = Chunk<'a>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.range.is_empty() {
-            None
-        } else if let Some(chunk) = self.excerpt_chunks.as_mut()?.next() {
-            self.range.start += chunk.text.len();
-            Some(chunk)
-        } else {
-            self.excerpts.next(&());
-            let excerpt = self.excerpts.item()?;
-            self.excerpt_chunks = Some(excerpt.chunks_in_range(
-                0..self.range.end - self.excerpts.start(),
-                self.language_aware,
-            ));
-            self.next()
-        }
+    pub fn git_diff_update_count(&self) -> usize {
+        self.git_diff_update_count
     }
-}
 
-impl<'a> MultiBufferBytes<'a> {
-    fn consume(&mut self, len: usize) {
-        self.range.start += len;
-        self.chunk = &self.chunk[len..];
+    pub fn local_selections(&self) -> &Arc<[Selection<Anchor>]> {
+        &self.local_selections
+    }
 
-        if !self.range.is_empty() && self.chunk.is_empty() {
-            if let Some(chunk) = self.excerpt_bytes.as_mut().and_then(|bytes| bytes.next()) {
-                self.chunk = chunk;
-            } else {
-                self.excerpts.next(&());
-                if let Some(excerpt) = self.excerpts.item() {
-                    let mut excerpt_bytes =
-                        excerpt.bytes_in_range(0..self.range.end - self.excerpts.start());
-                    self.chunk = excerpt_bytes.next().unwrap();
-                    self.excerpt_bytes = Some(excerpt_bytes);
-                }
-            }
-        }
+    pub fn selections_update_count(&self) -> usize {
+        self.selections_update_count
     }
-}
 
-impl<'a> Iterator for MultiBufferBytes<'a> {
-    type Item = &'a [u8];
+    pub fn trailing_excerpt_update_count(&self) -> usize {
+        self.trailing_excerpt_update_count
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let chunk = self.chunk;
-        if chunk.is_empty() {
-            None
-        } else {
-            self.consume(chunk.len());
-            Some(chunk)
-        }
+    /// The number of times the selections of other collaborators changed in any of the
+    /// excerpted buffers.
+    pub fn remote_selections_update_count(&self) -> usize {
+        self.remote_selections_update_count
     }
-}
 
-impl<'a> io::Read for MultiBufferBytes<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let len = cmp::min(buf.len(), self.chunk.len());
-        buf[..len].copy_from_slice(&self.chunk[..len]);
-        if len > 0 {
-            self.consume(len);
+    /// Describes what changed between an older snapshot of the same MultiBuffer and this
+    /// one, so that subscribers can avoid relaying out text when only decorations such as
+    /// selections or diagnostics have changed.
+    pub fn changes_since(&self, old: &MultiBufferSnapshot) -> MultiBufferChanges {
+        MultiBufferChanges {
+            text: self.edit_count != old.edit_count
+                || self.excerpt_update_count != old.excerpt_update_count,
+            syntax: self.parse_count != old.parse_count,
+            selections: self.selections_update_count != old.selections_update_count
+                || self.remote_selections_update_count != old.remote_selections_update_count,
+            diagnostics: self.diagnostics_update_count != old.diagnostics_update_count,
+            git_diff: self.git_diff_update_count != old.git_diff_update_count,
         }
-        Ok(len)
     }
-}
 
-impl<'a> ReversedMultiBufferBytes<'a> {
-    fn consume(&mut self, len: usize) {
-        self.range.end -= len;
-        self.chunk = &self.chunk[..self.chunk.len() - len];
+    /// Returns the excerpts that changed since an older snapshot of the same MultiBuffer,
+    /// along with what changed about each of them. Unlike [`MultiBufferSnapshot::changes_since`],
+    /// reparsing a buffer or updating its diagnostics only affects that buffer's excerpts.
+    /// Excerpts that were inserted since the older snapshot are reported as changed text.
+    pub fn excerpt_changes_since<'a>(
+        &'a self,
+        old: &'a MultiBufferSnapshot,
+    ) -> impl Iterator<Item = (ExcerptId, MultiBufferChanges)> + 'a {
+        let old_excerpts = old
+            .excerpts
+            .iter()
+            .map(|excerpt| (excerpt.id, excerpt))
+            .collect::<HashMap<_, _>>();
+        self.excerpts.iter().filter_map(move |excerpt| {
+            let Some(old_excerpt) = old_excerpts.get(&excerpt.id) else {
+                return Some((
+                    excerpt.id,
+                    MultiBufferChanges {
+                        text: true,
+                        ..Default::default()
+                    },
+                ));
+            };
 
-        if !self.range.is_empty() && self.chunk.is_empty() {
-            if let Some(chunk) = self.excerpt_bytes.as_mut().and_then(|bytes| bytes.next()) {
-                self.chunk = chunk;
-            } else {
-                self.excerpts.next(&());
-                if let Some(excerpt) = self.excerpts.item() {
-                    let mut excerpt_bytes =
-                        excerpt.bytes_in_range(0..self.range.end - self.excerpts.start());
-                    self.chunk = excerpt_bytes.next().unwrap();
-                    self.excerpt_bytes = Some(excerpt_bytes);
-                }
-            }
-        }
+            let buffer = &excerpt.buffer;
+            let old_buffer = &old_excerpt.buffer;
+            let changes = MultiBufferChanges {
+                text: excerpt.range.context != old_excerpt.range.context
+                    || (buffer.version().changed_since(old_buffer.version())
+                        && buffer
+                            .edits_since_in_range::<usize>(
+                                old_buffer.version(),
+                                old_excerpt.range.context.clone(),
+                            )
+                            .next()
+                            .is_some()),
+                syntax: buffer.parse_count() != old_buffer.parse_count(),
+                selections: buffer.selections_update_count()
+                    != old_buffer.selections_update_count(),
+                diagnostics: buffer.diagnostics_update_count()
+                    != old_buffer.diagnostics_update_count(),
+                git_diff: buffer.git_diff_update_count() != old_buffer.git_diff_update_count(),
+            };
+            (changes != MultiBufferChanges::default()).then_some((excerpt.id, changes))
+        })
     }
-}
 
-impl<'a> io::Read for ReversedMultiBufferBytes<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let len = cmp::min(buf.len(), self.chunk.len());
-        buf[..len].copy_from_slice(&self.chunk[..len]);
-        buf[..len].reverse();
-        if len > 0 {
-            self.consume(len);
-        }
-        Ok(len)
+    pub fn file_at<'a, T: ToOffset>(&'a self, point: T) -> Option<&'a Arc<dyn File>> {
+        self.point_to_buffer_offset(point)
+            .and_then(|(buffer, _)| buffer.file())
     }
-}
-impl<'a> Iterator for ExcerptBytes<'a> {
-    type Item = &'a [u8];
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(chunk) = self.content_bytes.next() {
-            if !chunk.is_empty() {
-                return Some(chunk);
-            }
-        }
-
-        if self.footer_height > 0 {
-            let result = &NEWLINES[..self.footer_height];
-            self.footer_height = 0;
-            return Some(result);
-        }
-
-        None
+    /// Returns the buffer, point, and file underlying the given position, so that the position
+    /// can be opened in an editor for that buffer alone.
+    pub fn jump_target_for<T: ToOffset>(
+        &self,
+        position: T,
+    ) -> Option<(BufferId, Point, Option<Arc<dyn File>>)> {
+        let (buffer, offset) = self.point_to_buffer_offset(position)?;
+        Some((
+            buffer.remote_id(),
+            buffer.offset_to_point(offset),
+            buffer.file().cloned(),
+        ))
     }
-}
-
-impl<'a> Iterator for ExcerptChunks<'a> {
-    type Item = Chunk<'a>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(chunk) = self.content_chunks.next() {
-            return Some(chunk);
-        }
+    pub fn language_at<'a, T: ToOffset>(&'a self, point: T) -> Option<&'a Arc<Language>> {
+        self.point_to_buffer_offset(point)
+            .and_then(|(buffer, offset)| buffer.language_at(offset))
+    }
 
-        if self.footer_height > 0 {
-            let text = unsafe { str::from_utf8_unchecked(&NEWLINES[..self.footer_height]) };
-            self.footer_height = 0;
-            return Some(Chunk {
-                text,
-                ..Default::default()
-            });
+    pub fn settings_at<'a, T: ToOffset>(
+        &'a self,
+        point: T,
+        cx: &'a AppContext,
+    ) -> &'a LanguageSettings {
+        let mut language = None;
+        let mut file = None;
+        if let Some((buffer, offset)) = self.point_to_buffer_offset(point) {
+            language = buffer.language_at(offset);
+            file = buffer.file();
         }
-
-        None
+        language_settings(language, file, cx)
     }
-}
 
-impl ToOffset for Point {
-    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
-        snapshot.point_to_offset(*self)
+    pub fn language_scope_at<'a, T: ToOffset>(&'a self, point: T) -> Option<LanguageScope> {
+        self.point_to_buffer_offset(point)
+            .and_then(|(buffer, offset)| buffer.language_scope_at(offset))
     }
-}
 
-impl ToOffset for usize {
-    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
-        assert!(*self <= snapshot.len(), "offset is out of range");
-        *self
+    pub fn language_indent_size_at<T: ToOffset>(
+        &self,
+        position: T,
+        cx: &AppContext,
+    ) -> Option<IndentSize> {
+        let (buffer_snapshot, offset) = self.point_to_buffer_offset(position)?;
+        Some(buffer_snapshot.language_indent_size_at(offset, cx))
     }
-}
 
-impl ToOffset for OffsetUtf16 {
-    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
-        snapshot.offset_utf16_to_offset(*self)
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
     }
-}
 
-impl ToOffset for PointUtf16 {
-    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
-        snapshot.point_utf16_to_offset(*self)
+    pub fn has_conflict(&self) -> bool {
+        self.has_conflict
     }
-}
 
-impl ToOffsetUtf16 for OffsetUtf16 {
-    fn to_offset_utf16(&self, _snapshot: &MultiBufferSnapshot) -> OffsetUtf16 {
-        *self
+    pub fn has_diagnostics(&self) -> bool {
+        self.excerpts
+            .iter()
+            .any(|excerpt| excerpt.buffer.has_diagnostics())
     }
-}
 
-impl ToOffsetUtf16 for usize {
-    fn to_offset_utf16(&self, snapshot: &MultiBufferSnapshot) -> OffsetUtf16 {
-        snapshot.offset_to_offset_utf16(*self)
+    pub fn diagnostic_group<'a, O>(
+        &'a self,
+        group_id: usize,
+    ) -> impl Iterator<Item = DiagnosticEntry<O>> + 'a
+    where
+        O: text::FromAnchor + 'a,
+    {
+        self.as_singleton()
+            .into_iter()
+            .flat_map(move |(_, _, buffer)| buffer.diagnostic_group(group_id))
     }
-}
 
-impl ToPoint for usize {
-    fn to_point<'a>(&self, snapshot: &MultiBufferSnapshot) -> Point {
-        snapshot.offset_to_point(*self)
+    pub fn diagnostics_in_range<'a, T, O>(
+        &'a self,
+        range: Range<T>,
+        reversed: bool,
+    ) -> impl Iterator<Item = DiagnosticEntry<O>> + 'a
+    where
+        T: 'a + ToOffset,
+        O: 'a + text::FromAnchor + Ord,
+    {
+        self.as_singleton()
+            .into_iter()
+            .flat_map(move |(_, _, buffer)| {
+                buffer.diagnostics_in_range(
+                    range.start.to_offset(self)..range.end.to_offset(self),
+                    reversed,
+                )
+            })
     }
-}
 
-impl ToPoint for Point {
-    fn to_point<'a>(&self, _: &MultiBufferSnapshot) -> Point {
-        *self
-    }
-}
+    /// Divides the multi-buffer's rows into `bucket_count` ranges of equal height, and
+    /// summarizes the diagnostics, the given search matches, and the heads of the local
+    /// selections within each of them. The search matches must be sorted.
+    ///
+    /// Anchors are resolved in a single pass over the excerpts, and the result only depends on
+    /// this snapshot's edit, diagnostics, and selections update counts and on the search
+    /// matches, so callers can cache it until one of those changes.
+    pub fn marker_summaries(
+        &self,
+        bucket_count: usize,
+        search_matches: &[Range<Anchor>],
+    ) -> Vec<MarkerSummary> {
+        let mut summaries = vec![MarkerSummary::default(); bucket_count];
+        if bucket_count == 0 {
+            return summaries;
+        }
+        let row_count = self.max_point().row as u64 + 1;
+        let bucket_for_row = |row: u32| {
+            cmp::min(
+                (row as u64 * bucket_count as u64 / row_count) as usize,
+                bucket_count - 1,
+            )
+        };
 
-impl ToPointUtf16 for usize {
-    fn to_point_utf16<'a>(&self, snapshot: &MultiBufferSnapshot) -> PointUtf16 {
-        snapshot.offset_to_point_utf16(*self)
-    }
-}
+        let mut cursor = self.excerpts.cursor::<Point>();
+        cursor.next(&());
+        while let Some(excerpt) = cursor.item() {
+            let excerpt_start_row = excerpt.range.context.start.to_point(&excerpt.buffer).row;
+            for entry in excerpt
+                .buffer
+                .diagnostics_in_range::<_, Point>(excerpt.range.context.clone(), false)
+            {
+                let row =
+                    cursor.start().row + entry.range.start.row.saturating_sub(excerpt_start_row);
+                let summary = &mut summaries[bucket_for_row(row)];
+                let severity = entry.diagnostic.severity;
+                // Lower values are more severe.
+                summary.diagnostic_severity = Some(
+                    summary
+                        .diagnostic_severity
+                        .map_or(severity, |max_severity| max_severity.min(severity)),
+                );
+            }
+            cursor.next(&());
+        }
 
-impl ToPointUtf16 for Point {
-    fn to_point_utf16<'a>(&self, snapshot: &MultiBufferSnapshot) -> PointUtf16 {
-        snapshot.point_to_point_utf16(*self)
-    }
-}
+        for point in self.summaries_for_anchors::<Point, _>(
+            search_matches
+                .iter()
+                .map(|search_match| &search_match.start),
+        ) {
+            summaries[bucket_for_row(point.row)].search_match_count += 1;
+        }
 
-impl ToPointUtf16 for PointUtf16 {
-    fn to_point_utf16<'a>(&self, _: &MultiBufferSnapshot) -> PointUtf16 {
-        *self
-    }
-}
+        let selection_heads = self
+            .local_selections
+            .iter()
+            .map(|selection| selection.head())
+            .collect::<Vec<_>>();
+        for point in self.summaries_for_anchors::<Point, _>(&selection_heads) {
+            summaries[bucket_for_row(point.row)].selection_count += 1;
+        }
 
-fn build_excerpt_ranges<T>(
-    buffer: &BufferSnapshot,
-    ranges: &[Range<T>],
-    context_line_count: u32,
-) -> (Vec<ExcerptRange<Point>>, Vec<usize>)
-where
-    T: text::ToPoint,
-{
-    let max_point = buffer.max_point();
-    let mut range_counts = Vec::new();
-    let mut excerpt_ranges = Vec::new();
-    let mut range_iter = ranges
-        .iter()
-        .map(|range| range.start.to_point(buffer)..range.end.to_point(buffer))
-        .peekable();
-    while let Some(range) = range_iter.next() {
-        let excerpt_start = Point::new(range.start.row.saturating_sub(context_line_count), 0);
-        let mut excerpt_end = Point::new(range.end.row + 1 + context_line_count, 0).min(max_point);
-        let mut ranges_in_excerpt = 1;
+        summaries
+    }
 
-        while let Some(next_range) = range_iter.peek() {
-            if next_range.start.row <= excerpt_end.row + context_line_count {
-                excerpt_end =
-                    Point::new(next_range.end.row + 1 + context_line_count, 0).min(max_point);
-                ranges_in_excerpt += 1;
-                range_iter.next();
-            } else {
-                break;
+    pub fn has_git_diffs(&self) -> bool {
+        for excerpt in self.excerpts.iter() {
+            if excerpt.buffer.has_git_diff() {
+                return true;
             }
         }
-
-        excerpt_ranges.push(ExcerptRange {
-            context: excerpt_start..excerpt_end,
-            primary: Some(range),
-        });
-        range_counts.push(ranges_in_excerpt);
+        false
     }
 
-    (excerpt_ranges, range_counts)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use futures::StreamExt;
-    use gpui::{AppContext, Context, TestAppContext};
-    use language::{Buffer, Rope};
-    use parking_lot::RwLock;
-    use rand::prelude::*;
-    use settings::SettingsStore;
-    use std::env;
-    use util::test::sample_text;
+    pub fn git_diff_hunks_in_range_rev<'a>(
+        &'a self,
+        row_range: Range<u32>,
+    ) -> impl 'a + Iterator<Item = DiffHunk<u32>> {
+        let mut cursor = self.excerpts.cursor::<Point>();
 
-    #[gpui::test]
-    fn test_singleton(cx: &mut AppContext) {
-        let buffer = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(6, 6, 'a'),
-            )
-        });
-        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        cursor.seek(&Point::new(row_range.end, 0), Bias::Left, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
+        }
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot.text(), buffer.read(cx).text());
+        std::iter::from_fn(move || {
+            let excerpt = cursor.item()?;
+            let multibuffer_start = *cursor.start();
+            let multibuffer_end = multibuffer_start + excerpt.text_summary.lines;
+            if multibuffer_start.row >= row_range.end {
+                return None;
+            }
 
-        assert_eq!(
-            snapshot.buffer_rows(0).collect::<Vec<_>>(),
-            (0..buffer.read(cx).row_count())
-                .map(Some)
-                .collect::<Vec<_>>()
-        );
+            let mut buffer_start = excerpt.range.context.start;
+            let mut buffer_end = excerpt.range.context.end;
+            let excerpt_start_point = buffer_start.to_point(&excerpt.buffer);
+            let excerpt_end_point = excerpt_start_point + excerpt.text_summary.lines;
 
-        buffer.update(cx, |buffer, cx| buffer.edit([(1..3, "XXX\n")], None, cx));
-        let snapshot = multibuffer.read(cx).snapshot(cx);
+            if row_range.start > multibuffer_start.row {
+                let buffer_start_point =
+                    excerpt_start_point + Point::new(row_range.start - multibuffer_start.row, 0);
+                buffer_start = excerpt.buffer.anchor_before(buffer_start_point);
+            }
 
-        assert_eq!(snapshot.text(), buffer.read(cx).text());
-        assert_eq!(
-            snapshot.buffer_rows(0).collect::<Vec<_>>(),
-            (0..buffer.read(cx).row_count())
-                .map(Some)
-                .collect::<Vec<_>>()
-        );
-    }
+            if row_range.end < multibuffer_end.row {
+                let buffer_end_point =
+                    excerpt_start_point + Point::new(row_range.end - multibuffer_start.row, 0);
+                buffer_end = excerpt.buffer.anchor_before(buffer_end_point);
+            }
 
-    #[gpui::test]
-    fn test_remote(cx: &mut AppContext) {
-        let host_buffer =
-            cx.new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "a"));
-        let guest_buffer = cx.new_model(|cx| {
-            let state = host_buffer.read(cx).to_proto();
-            let ops = cx
-                .background_executor()
-                .block(host_buffer.read(cx).serialize_ops(None, cx));
-            let mut buffer = Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap();
-            buffer
-                .apply_ops(
-                    ops.into_iter()
-                        .map(|op| language::proto::deserialize_operation(op).unwrap()),
-                    cx,
-                )
-                .unwrap();
-            buffer
-        });
-        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(guest_buffer.clone(), cx));
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot.text(), "a");
+            let buffer_hunks = excerpt
+                .buffer
+                .git_diff_hunks_intersecting_range_rev(buffer_start..buffer_end)
+                .filter_map(move |hunk| {
+                    let start = multibuffer_start.row
+                        + hunk
+                            .buffer_range
+                            .start
+                            .saturating_sub(excerpt_start_point.row);
+                    let end = multibuffer_start.row
+                        + hunk
+                            .buffer_range
+                            .end
+                            .min(excerpt_end_point.row + 1)
+                            .saturating_sub(excerpt_start_point.row);
 
-        guest_buffer.update(cx, |buffer, cx| buffer.edit([(1..1, "b")], None, cx));
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot.text(), "ab");
+                    Some(DiffHunk {
+                        buffer_range: start..end,
+                        diff_base_byte_range: hunk.diff_base_byte_range.clone(),
+                    })
+                });
 
-        guest_buffer.update(cx, |buffer, cx| buffer.edit([(2..2, "c")], None, cx));
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot.text(), "abc");
+            cursor.prev(&());
+
+            Some(buffer_hunks)
+        })
+        .flatten()
     }
 
-    #[gpui::test]
-    fn test_excerpt_boundaries_and_clipping(cx: &mut AppContext) {
-        let buffer_1 = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(6, 6, 'a'),
-            )
-        });
-        let buffer_2 = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(6, 6, 'g'),
-            )
-        });
-        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+    pub fn git_diff_hunks_in_range<'a>(
+        &'a self,
+        row_range: Range<u32>,
+    ) -> impl 'a + Iterator<Item = DiffHunk<u32>> {
+        let mut cursor = self.excerpts.cursor::<Point>();
 
-        let events = Arc::new(RwLock::new(Vec::<Event>::new()));
-        multibuffer.update(cx, |_, cx| {
-            let events = events.clone();
-            cx.subscribe(&multibuffer, move |_, _, event, _| {
-                if let Event::Edited { .. } = event {
-                    events.write().push(event.clone())
-                }
-            })
-            .detach();
-        });
+        cursor.seek(&Point::new(row_range.start, 0), Bias::Right, &());
 
-        let subscription = multibuffer.update(cx, |multibuffer, cx| {
-            let subscription = multibuffer.subscribe();
-            multibuffer.push_excerpts(
-                buffer_1.clone(),
-                [ExcerptRange {
-                    context: Point::new(1, 2)..Point::new(2, 5),
-                    primary: None,
-                }],
-                cx,
-            );
-            assert_eq!(
-                subscription.consume().into_inner(),
-                [Edit {
-                    old: 0..0,
-                    new: 0..10
-                }]
-            );
+        std::iter::from_fn(move || {
+            let excerpt = cursor.item()?;
+            let multibuffer_start = *cursor.start();
+            let multibuffer_end = multibuffer_start + excerpt.text_summary.lines;
+            if multibuffer_start.row >= row_range.end {
+                return None;
+            }
 
-            multibuffer.push_excerpts(
-                buffer_1.clone(),
-                [ExcerptRange {
-                    context: Point::new(3, 3)..Point::new(4, 4),
-                    primary: None,
-                }],
-                cx,
-            );
-            multibuffer.push_excerpts(
-                buffer_2.clone(),
-                [ExcerptRange {
-                    context: Point::new(3, 1)..Point::new(3, 3),
-                    primary: None,
-                }],
-                cx,
-            );
-            assert_eq!(
-                subscription.consume().into_inner(),
-                [Edit {
-                    old: 10..10,
-                    new: 10..22
-                }]
-            );
+            let mut buffer_start = excerpt.range.context.start;
+            let mut buffer_end = excerpt.range.context.end;
+            let excerpt_start_point = buffer_start.to_point(&excerpt.buffer);
+            let excerpt_end_point = excerpt_start_point + excerpt.text_summary.lines;
 
-            subscription
-        });
+            if row_range.start > multibuffer_start.row {
+                let buffer_start_point =
+                    excerpt_start_point + Point::new(row_range.start - multibuffer_start.row, 0);
+                buffer_start = excerpt.buffer.anchor_before(buffer_start_point);
+            }
 
-        // Adding excerpts emits an edited event.
-        assert_eq!(
-            events.read().as_slice(),
-            &[
-                Event::Edited {
-                    singleton_buffer_edited: false
-                },
-                Event::Edited {
-                    singleton_buffer_edited: false
-                },
-                Event::Edited {
-                    singleton_buffer_edited: false
-                }
-            ]
-        );
+            if row_range.end < multibuffer_end.row {
+                let buffer_end_point =
+                    excerpt_start_point + Point::new(row_range.end - multibuffer_start.row, 0);
+                buffer_end = excerpt.buffer.anchor_before(buffer_end_point);
+            }
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(
-            snapshot.text(),
-            concat!(
-                "bbbb\n",  // Preserve newlines
-                "ccccc\n", //
-                "ddd\n",   //
-                "eeee\n",  //
-                "jj"       //
-            )
-        );
-        assert_eq!(
-            snapshot.buffer_rows(0).collect::<Vec<_>>(),
-            [Some(1), Some(2), Some(3), Some(4), Some(3)]
-        );
-        assert_eq!(
-            snapshot.buffer_rows(2).collect::<Vec<_>>(),
-            [Some(3), Some(4), Some(3)]
-        );
-        assert_eq!(snapshot.buffer_rows(4).collect::<Vec<_>>(), [Some(3)]);
-        assert_eq!(snapshot.buffer_rows(5).collect::<Vec<_>>(), []);
+            let buffer_hunks = excerpt
+                .buffer
+                .git_diff_hunks_intersecting_range(buffer_start..buffer_end)
+                .filter_map(move |hunk| {
+                    let start = multibuffer_start.row
+                        + hunk
+                            .buffer_range
+                            .start
+                            .saturating_sub(excerpt_start_point.row);
+                    let end = multibuffer_start.row
+                        + hunk
+                            .buffer_range
+                            .end
+                            .min(excerpt_end_point.row + 1)
+                            .saturating_sub(excerpt_start_point.row);
 
-        assert_eq!(
-            boundaries_in_range(Point::new(0, 0)..Point::new(4, 2), &snapshot),
-            &[
-                (0, "bbbb\nccccc".to_string(), true),
-                (2, "ddd\neeee".to_string(), false),
-                (4, "jj".to_string(), true),
-            ]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(0, 0)..Point::new(2, 0), &snapshot),
-            &[(0, "bbbb\nccccc".to_string(), true)]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(1, 0)..Point::new(1, 5), &snapshot),
-            &[]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(1, 0)..Point::new(2, 0), &snapshot),
-            &[]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(1, 0)..Point::new(4, 0), &snapshot),
-            &[(2, "ddd\neeee".to_string(), false)]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(1, 0)..Point::new(4, 0), &snapshot),
-            &[(2, "ddd\neeee".to_string(), false)]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(2, 0)..Point::new(3, 0), &snapshot),
-            &[(2, "ddd\neeee".to_string(), false)]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(4, 0)..Point::new(4, 2), &snapshot),
-            &[(4, "jj".to_string(), true)]
-        );
-        assert_eq!(
-            boundaries_in_range(Point::new(4, 2)..Point::new(4, 2), &snapshot),
-            &[]
-        );
+                    Some(DiffHunk {
+                        buffer_range: start..end,
+                        diff_base_byte_range: hunk.diff_base_byte_range.clone(),
+                    })
+                });
 
-        buffer_1.update(cx, |buffer, cx| {
-            let text = "\n";
-            buffer.edit(
-                [
-                    (Point::new(0, 0)..Point::new(0, 0), text),
-                    (Point::new(2, 1)..Point::new(2, 3), text),
-                ],
-                None,
-                cx,
-            );
-        });
+            cursor.next(&());
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(
-            snapshot.text(),
-            concat!(
-                "bbbb\n", // Preserve newlines
-                "c\n",    //
-                "cc\n",   //
-                "ddd\n",  //
-                "eeee\n", //
-                "jj"      //
-            )
-        );
+            Some(buffer_hunks)
+        })
+        .flatten()
+    }
 
-        assert_eq!(
-            subscription.consume().into_inner(),
-            [Edit {
-                old: 6..8,
-                new: 6..7
-            }]
-        );
+    pub fn range_for_syntax_ancestor<T: ToOffset>(&self, range: Range<T>) -> Option<Range<usize>> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(
-            snapshot.clip_point(Point::new(0, 5), Bias::Left),
-            Point::new(0, 4)
-        );
-        assert_eq!(
-            snapshot.clip_point(Point::new(0, 5), Bias::Right),
-            Point::new(0, 4)
-        );
-        assert_eq!(
-            snapshot.clip_point(Point::new(5, 1), Bias::Right),
-            Point::new(5, 1)
-        );
-        assert_eq!(
-            snapshot.clip_point(Point::new(5, 2), Bias::Right),
-            Point::new(5, 2)
-        );
-        assert_eq!(
-            snapshot.clip_point(Point::new(5, 3), Bias::Right),
-            Point::new(5, 2)
-        );
+        self.excerpt_containing(range.clone())
+            .and_then(|(excerpt, excerpt_offset)| {
+                let excerpt_buffer_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+                let excerpt_buffer_end = excerpt_buffer_start + excerpt.text_summary.len;
 
-        let snapshot = multibuffer.update(cx, |multibuffer, cx| {
-            let (buffer_2_excerpt_id, _) =
-                multibuffer.excerpts_for_buffer(&buffer_2, cx)[0].clone();
-            multibuffer.remove_excerpts([buffer_2_excerpt_id], cx);
-            multibuffer.snapshot(cx)
-        });
+                let start_in_buffer =
+                    excerpt_buffer_start + range.start.saturating_sub(excerpt_offset);
+                let end_in_buffer = excerpt_buffer_start + range.end.saturating_sub(excerpt_offset);
+                let mut ancestor_buffer_range = excerpt
+                    .buffer
+                    .range_for_syntax_ancestor(start_in_buffer..end_in_buffer)?;
+                ancestor_buffer_range.start =
+                    cmp::max(ancestor_buffer_range.start, excerpt_buffer_start);
+                ancestor_buffer_range.end = cmp::min(ancestor_buffer_range.end, excerpt_buffer_end);
 
-        assert_eq!(
-            snapshot.text(),
-            concat!(
-                "bbbb\n", // Preserve newlines
-                "c\n",    //
-                "cc\n",   //
-                "ddd\n",  //
-                "eeee",   //
-            )
-        );
+                let start = excerpt_offset + (ancestor_buffer_range.start - excerpt_buffer_start);
+                let end = excerpt_offset + (ancestor_buffer_range.end - excerpt_buffer_start);
+                Some(start..end)
+            })
+    }
 
-        fn boundaries_in_range(
-            range: Range<Point>,
-            snapshot: &MultiBufferSnapshot,
-        ) -> Vec<(u32, String, bool)> {
-            snapshot
-                .excerpt_boundaries_in_range(range)
-                .map(|boundary| {
-                    (
-                        boundary.row,
-                        boundary
-                            .buffer
-                            .text_for_range(boundary.range.context)
-                            .collect::<String>(),
-                        boundary.starts_new_buffer,
-                    )
+    pub fn outline(&self, theme: Option<&SyntaxTheme>) -> Option<Outline<Anchor>> {
+        let (excerpt_id, _, buffer) = self.as_singleton()?;
+        let outline = buffer.outline(theme)?;
+        Some(Outline::new(
+            outline
+                .items
+                .into_iter()
+                .map(|item| OutlineItem {
+                    depth: item.depth,
+                    range: self.anchor_in_excerpt(excerpt_id.clone(), item.range.start)
+                        ..self.anchor_in_excerpt(excerpt_id.clone(), item.range.end),
+                    text: item.text,
+                    highlight_ranges: item.highlight_ranges,
+                    name_ranges: item.name_ranges,
                 })
-                .collect::<Vec<_>>()
+                .collect(),
+        ))
+    }
+
+    pub fn symbols_containing<T: ToOffset>(
+        &self,
+        offset: T,
+        theme: Option<&SyntaxTheme>,
+    ) -> Option<(BufferId, Vec<OutlineItem<Anchor>>)> {
+        let anchor = self.anchor_before(offset);
+        let excerpt_id = anchor.excerpt_id;
+        let excerpt = self.excerpt(excerpt_id)?;
+        Some((
+            excerpt.buffer_id,
+            excerpt
+                .buffer
+                .symbols_containing(anchor.text_anchor, theme)
+                .into_iter()
+                .flatten()
+                .map(|item| OutlineItem {
+                    depth: item.depth,
+                    range: self.anchor_in_excerpt(excerpt_id, item.range.start)
+                        ..self.anchor_in_excerpt(excerpt_id, item.range.end),
+                    text: item.text,
+                    highlight_ranges: item.highlight_ranges,
+                    name_ranges: item.name_ranges,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Returns the predecessor and the locators between which excerpts inserted after the
+    /// given excerpt should be placed, starting with the given id.
+    ///
+    /// If `prev_excerpt_id` is [`ExcerptId::max`], the excerpts are appended. Otherwise,
+    /// excerpts that follow the predecessor and have a greater id than the new ones are
+    /// skipped. Those were inserted concurrently, or after them causally, so skipping them
+    /// orders concurrent insertions the same way on every replica regardless of the order
+    /// in which they're received.
+    fn insertion_point(
+        &self,
+        prev_excerpt_id: ExcerptId,
+        new_excerpt_id: ExcerptId,
+    ) -> (ExcerptId, Locator, Locator) {
+        let prev_excerpt_id = if prev_excerpt_id == ExcerptId::max() {
+            self.excerpts
+                .last()
+                .map_or(ExcerptId::min(), |excerpt| excerpt.id)
+        } else {
+            prev_excerpt_id
+        };
+        let start_locator = self.excerpt_locator_for_id(prev_excerpt_id).clone();
+        let mut prev_locator = start_locator.clone();
+        let mut next_locator = Locator::max();
+        for (locator, id) in self.excerpt_ids_by_locator.iter_from(&start_locator) {
+            if *locator == start_locator {
+                continue;
+            }
+            if *id > new_excerpt_id {
+                prev_locator = locator.clone();
+            } else {
+                next_locator = locator.clone();
+                break;
+            }
         }
+        (prev_excerpt_id, prev_locator, next_locator)
     }
 
-    #[gpui::test]
-    fn test_excerpt_events(cx: &mut AppContext) {
-        let buffer_1 = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(10, 3, 'a'),
-            )
-        });
-        let buffer_2 = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(10, 3, 'm'),
-            )
-        });
+    /// Returns whether the given excerpt id was issued by this multi-buffer, even if the
+    /// excerpt has since been removed.
+    fn has_excerpt_id(&self, id: ExcerptId) -> bool {
+        if id == ExcerptId::min() || id == ExcerptId::max() {
+            return true;
+        }
+        let mut cursor = self.excerpt_ids.cursor::<ExcerptId>();
+        cursor.seek(&id, Bias::Left, &());
+        cursor.item().map_or(false, |entry| entry.id == id)
+    }
 
-        let leader_multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
-        let follower_multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
-        let follower_edit_event_count = Arc::new(RwLock::new(0));
+    fn excerpt_locator_for_id<'a>(&'a self, id: ExcerptId) -> &'a Locator {
+        if id == ExcerptId::min() {
+            Locator::min_ref()
+        } else if id == ExcerptId::max() {
+            Locator::max_ref()
+        } else {
+            let mut cursor = self.excerpt_ids.cursor::<ExcerptId>();
+            cursor.seek(&id, Bias::Left, &());
+            if let Some(entry) = cursor.item() {
+                if entry.id == id {
+                    return &entry.locator;
+                }
+            }
+            panic!("invalid excerpt id {:?}", id)
+        }
+    }
 
-        follower_multibuffer.update(cx, |_, cx| {
-            let follower_edit_event_count = follower_edit_event_count.clone();
-            cx.subscribe(
-                &leader_multibuffer,
-                move |follower, _, event, cx| match event.clone() {
-                    Event::ExcerptsAdded {
-                        buffer,
-                        predecessor,
-                        excerpts,
-                    } => follower.insert_excerpts_with_ids_after(predecessor, buffer, excerpts, cx),
-                    Event::ExcerptsRemoved { ids } => follower.remove_excerpts(ids, cx),
-                    Event::Edited { .. } => {
-                        *follower_edit_event_count.write() += 1;
-                    }
-                    _ => {}
-                },
-            )
-            .detach();
-        });
+    pub fn buffer_id_for_excerpt(&self, excerpt_id: ExcerptId) -> Option<BufferId> {
+        Some(self.excerpt(excerpt_id)?.buffer_id)
+    }
 
-        leader_multibuffer.update(cx, |leader, cx| {
-            leader.push_excerpts(
-                buffer_1.clone(),
-                [
-                    ExcerptRange {
-                        context: 0..8,
-                        primary: None,
-                    },
-                    ExcerptRange {
-                        context: 12..16,
-                        primary: None,
-                    },
-                ],
-                cx,
-            );
-            leader.insert_excerpts_after(
-                leader.excerpt_ids()[0],
-                buffer_2.clone(),
-                [
-                    ExcerptRange {
-                        context: 0..5,
-                        primary: None,
-                    },
-                    ExcerptRange {
-                        context: 10..15,
-                        primary: None,
-                    },
-                ],
-                cx,
-            )
-        });
-        assert_eq!(
-            leader_multibuffer.read(cx).snapshot(cx).text(),
-            follower_multibuffer.read(cx).snapshot(cx).text(),
-        );
-        assert_eq!(*follower_edit_event_count.read(), 2);
+    pub fn buffer_for_excerpt(&self, excerpt_id: ExcerptId) -> Option<&BufferSnapshot> {
+        Some(&*self.excerpt(excerpt_id)?.buffer)
+    }
 
-        leader_multibuffer.update(cx, |leader, cx| {
-            let excerpt_ids = leader.excerpt_ids();
-            leader.remove_excerpts([excerpt_ids[1], excerpt_ids[3]], cx);
-        });
-        assert_eq!(
-            leader_multibuffer.read(cx).snapshot(cx).text(),
-            follower_multibuffer.read(cx).snapshot(cx).text(),
-        );
-        assert_eq!(*follower_edit_event_count.read(), 3);
+    /// Returns the id of the excerpt preceding the given one, or [`ExcerptId::min`] if there
+    /// is none.
+    fn excerpt_id_before(&self, excerpt_id: ExcerptId) -> ExcerptId {
+        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
+        let locator = self.excerpt_locator_for_id(excerpt_id);
+        cursor.seek(&Some(locator), Bias::Left, &());
+        cursor.prev(&());
+        cursor.item().map_or(ExcerptId::min(), |excerpt| excerpt.id)
+    }
 
-        // Removing an empty set of excerpts is a noop.
-        leader_multibuffer.update(cx, |leader, cx| {
-            leader.remove_excerpts([], cx);
-        });
-        assert_eq!(
-            leader_multibuffer.read(cx).snapshot(cx).text(),
-            follower_multibuffer.read(cx).snapshot(cx).text(),
-        );
-        assert_eq!(*follower_edit_event_count.read(), 3);
+    fn excerpt<'a>(&'a self, excerpt_id: ExcerptId) -> Option<&'a Excerpt> {
+        let mut cursor = self.excerpts.cursor::<Option<&Locator>>();
+        let locator = self.excerpt_locator_for_id(excerpt_id);
+        cursor.seek(&Some(locator), Bias::Left, &());
+        if let Some(excerpt) = cursor.item() {
+            if excerpt.id == excerpt_id {
+                return Some(excerpt);
+            }
+        }
+        None
+    }
 
-        // Adding an empty set of excerpts is a noop.
-        leader_multibuffer.update(cx, |leader, cx| {
-            leader.push_excerpts::<usize>(buffer_2.clone(), [], cx);
-        });
-        assert_eq!(
-            leader_multibuffer.read(cx).snapshot(cx).text(),
-            follower_multibuffer.read(cx).snapshot(cx).text(),
-        );
-        assert_eq!(*follower_edit_event_count.read(), 3);
+    /// Returns the excerpt containing range and its offset start within the multibuffer or none if `range` spans multiple excerpts
+    fn excerpt_containing<'a, T: ToOffset>(
+        &'a self,
+        range: Range<T>,
+    ) -> Option<(&'a Excerpt, usize)> {
+        let range = range.start.to_offset(self)..range.end.to_offset(self);
 
-        leader_multibuffer.update(cx, |leader, cx| {
-            leader.clear(cx);
-        });
-        assert_eq!(
-            leader_multibuffer.read(cx).snapshot(cx).text(),
-            follower_multibuffer.read(cx).snapshot(cx).text(),
-        );
-        assert_eq!(*follower_edit_event_count.read(), 4);
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&range.start, Bias::Right, &());
+        let start_excerpt = cursor.item();
+
+        if range.start == range.end {
+            return start_excerpt.map(|excerpt| (excerpt, *cursor.start()));
+        }
+
+        cursor.seek(&range.end, Bias::Right, &());
+        let end_excerpt = cursor.item();
+
+        start_excerpt
+            .zip(end_excerpt)
+            .and_then(|(start_excerpt, end_excerpt)| {
+                if start_excerpt.id != end_excerpt.id {
+                    return None;
+                }
+
+                Some((start_excerpt, *cursor.start()))
+            })
     }
 
-    #[gpui::test]
-    fn test_push_excerpts_with_context_lines(cx: &mut AppContext) {
-        let buffer = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(20, 3, 'a'),
-            )
+    pub fn remote_selections_in_range<'a>(
+        &'a self,
+        range: &'a Range<Anchor>,
+    ) -> impl 'a + Iterator<Item = (ReplicaId, bool, CursorShape, Selection<Anchor>)> {
+        let mut cursor = self.excerpts.cursor::<ExcerptSummary>();
+        let start_locator = self.excerpt_locator_for_id(range.start.excerpt_id);
+        let end_locator = self.excerpt_locator_for_id(range.end.excerpt_id);
+        cursor.seek(start_locator, Bias::Left, &());
+        cursor
+            .take_while(move |excerpt| excerpt.locator <= *end_locator)
+            .flat_map(move |excerpt| {
+                let mut query_range = excerpt.range.context.start..excerpt.range.context.end;
+                if excerpt.id == range.start.excerpt_id {
+                    query_range.start = range.start.text_anchor;
+                }
+                if excerpt.id == range.end.excerpt_id {
+                    query_range.end = range.end.text_anchor;
+                }
+
+                excerpt
+                    .buffer
+                    .remote_selections_in_range(query_range)
+                    .flat_map(move |(replica_id, line_mode, cursor_shape, selections)| {
+                        selections.map(move |selection| {
+                            let mut start = Anchor {
+                                buffer_id: Some(excerpt.buffer_id),
+                                excerpt_id: excerpt.id.clone(),
+                                text_anchor: selection.start,
+                            };
+                            let mut end = Anchor {
+                                buffer_id: Some(excerpt.buffer_id),
+                                excerpt_id: excerpt.id.clone(),
+                                text_anchor: selection.end,
+                            };
+                            if range.start.cmp(&start, self).is_gt() {
+                                start = range.start.clone();
+                            }
+                            if range.end.cmp(&end, self).is_lt() {
+                                end = range.end.clone();
+                            }
+
+                            (
+                                replica_id,
+                                line_mode,
+                                cursor_shape,
+                                Selection {
+                                    id: selection.id,
+                                    start,
+                                    end,
+                                    reversed: selection.reversed,
+                                    goal: selection.goal,
+                                },
+                            )
+                        })
+                    })
+            })
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl MultiBufferSnapshot {
+    pub fn random_byte_range(&self, start_offset: usize, rng: &mut impl rand::Rng) -> Range<usize> {
+        let end = self.clip_offset(rng.gen_range(start_offset..=self.len()), Bias::Right);
+        let start = self.clip_offset(rng.gen_range(start_offset..=end), Bias::Right);
+        start..end
+    }
+}
+
+impl EditSourceTopic {
+    fn subscribe(&mut self) -> EditSourceSubscription {
+        let subscription = EditSourceSubscription(Default::default());
+        self.0.get_mut().push(Arc::downgrade(&subscription.0));
+        subscription
+    }
+
+    fn publish(&self, edit_sources: Vec<EditSource>) {
+        if edit_sources.is_empty() {
+            return;
+        }
+
+        self.0.lock().retain(|subscription| {
+            if let Some(subscription) = subscription.upgrade() {
+                subscription.lock().extend(edit_sources.iter().cloned());
+                true
+            } else {
+                false
+            }
         });
-        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
-        let anchor_ranges = multibuffer.update(cx, |multibuffer, cx| {
-            multibuffer.push_excerpts_with_context_lines(
-                buffer.clone(),
-                vec![
-                    Point::new(3, 2)..Point::new(4, 2),
-                    Point::new(7, 1)..Point::new(7, 3),
-                    Point::new(15, 0)..Point::new(15, 0),
-                ],
-                2,
-                cx,
+    }
+}
+
+impl EditSourceSubscription {
+    pub fn consume(&self) -> Vec<EditSource> {
+        mem::take(&mut *self.0.lock())
+    }
+}
+
+/// Returns whether any of the buffers are dirty or have a conflict, as of the last sync.
+fn dirty_and_conflict(buffers: &HashMap<BufferId, BufferState>) -> (bool, bool) {
+    buffers
+        .values()
+        .fold((false, false), |(is_dirty, has_conflict), state| {
+            (
+                is_dirty || state.last_is_dirty,
+                has_conflict || state.last_has_conflict,
             )
-        });
+        })
+}
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(
-            snapshot.text(),
-            "bbb\nccc\nddd\neee\nfff\nggg\nhhh\niii\njjj\n\nnnn\nooo\nppp\nqqq\nrrr\n"
-        );
+/// Returns the ids of the entries at the end of the given history stack, from the most
+/// recent back to the given transaction, or nothing if the transaction isn't on the stack.
+fn transactions_until(
+    stack: &[text::HistoryEntry],
+    transaction_id: TransactionId,
+) -> Vec<TransactionId> {
+    let Some(ix) = stack
+        .iter()
+        .rposition(|entry| entry.transaction_id() == transaction_id)
+    else {
+        return Vec::new();
+    };
+    stack[ix..]
+        .iter()
+        .rev()
+        .map(|entry| entry.transaction_id())
+        .collect()
+}
 
-        assert_eq!(
-            anchor_ranges
-                .iter()
-                .map(|range| range.to_point(&snapshot))
-                .collect::<Vec<_>>(),
-            vec![
-                Point::new(2, 2)..Point::new(3, 2),
-                Point::new(6, 1)..Point::new(6, 3),
-                Point::new(12, 0)..Point::new(12, 0)
-            ]
-        );
+/// Like [`transactions_until`], but skips the transactions after the given one whose edits
+/// were all made by other replicas, such as formatting that a collaborator performed and
+/// pushed onto the buffer's history, so that undoing and redoing leaves their changes alone.
+fn own_transactions_until(
+    stack: &[text::HistoryEntry],
+    transaction_id: TransactionId,
+    buffer: &Buffer,
+) -> Vec<TransactionId> {
+    let replica_id = buffer.replica_id();
+    let Some(ix) = stack
+        .iter()
+        .rposition(|entry| entry.transaction_id() == transaction_id)
+    else {
+        return Vec::new();
+    };
+    stack[ix..]
+        .iter()
+        .rev()
+        .filter(|entry| {
+            // The given transaction was recorded in the multi-buffer's history, so it's always
+            // included, even if it was pushed there on a collaborator's behalf.
+            let edit_ids = &entry.transaction().edit_ids;
+            entry.transaction_id() == transaction_id
+                || edit_ids.is_empty()
+                || edit_ids.iter().any(|id| id.replica_id == replica_id)
+        })
+        .map(|entry| entry.transaction_id())
+        .collect()
+}
+
+/// Splits a transaction's excerpt changes into the excerpts that were inserted and the
+/// excerpts that were removed. An excerpt that was both inserted and removed appears in
+/// both lists, so that undoing or redoing the transaction restores and then removes it.
+fn partition_excerpt_changes(
+    changes: &[ExcerptChange],
+) -> (Vec<ExcerptRecord>, Vec<ExcerptRecord>) {
+    let mut inserted = Vec::new();
+    let mut removed = Vec::new();
+    for change in changes {
+        match change {
+            ExcerptChange::Inserted(excerpt) => inserted.push(excerpt.clone()),
+            ExcerptChange::Removed(excerpt) => removed.push(excerpt.clone()),
+        }
+    }
+    (inserted, removed)
+}
+
+impl History {
+    fn start_transaction(&mut self, now: Instant) -> Option<TransactionId> {
+        self.transaction_depth += 1;
+        if self.transaction_depth == 1 {
+            let id = self.next_transaction_id.tick();
+            self.undo_stack.push(Transaction {
+                id,
+                buffer_transactions: Default::default(),
+                excerpt_changes: Default::default(),
+                first_edit_at: now,
+                last_edit_at: now,
+                suppress_grouping: false,
+            });
+            Some(id)
+        } else {
+            None
+        }
     }
 
-    #[gpui::test]
-    async fn test_stream_excerpts_with_context_lines(cx: &mut TestAppContext) {
-        let buffer = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                sample_text(20, 3, 'a'),
-            )
-        });
-        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
-        let anchor_ranges = multibuffer.update(cx, |multibuffer, cx| {
-            let snapshot = buffer.read(cx);
-            let ranges = vec![
-                snapshot.anchor_before(Point::new(3, 2))..snapshot.anchor_before(Point::new(4, 2)),
-                snapshot.anchor_before(Point::new(7, 1))..snapshot.anchor_before(Point::new(7, 3)),
-                snapshot.anchor_before(Point::new(15, 0))
-                    ..snapshot.anchor_before(Point::new(15, 0)),
-            ];
-            multibuffer.stream_excerpts_with_context_lines(buffer.clone(), ranges, 2, cx)
-        });
+    fn end_transaction(
+        &mut self,
+        now: Instant,
+        buffer_transactions: HashMap<BufferId, TransactionId>,
+    ) -> bool {
+        assert_ne!(self.transaction_depth, 0);
+        self.transaction_depth -= 1;
+        if self.transaction_depth == 0 {
+            let transaction = self.undo_stack.last().unwrap();
+            if buffer_transactions.is_empty() && transaction.excerpt_changes.is_empty() {
+                self.undo_stack.pop();
+                false
+            } else {
+                self.redo_stack.clear();
+                let transaction = self.undo_stack.last_mut().unwrap();
+                transaction.last_edit_at = now;
+                for (buffer_id, transaction_id) in buffer_transactions {
+                    transaction
+                        .buffer_transactions
+                        .entry(buffer_id)
+                        .or_insert(transaction_id);
+                }
+                true
+            }
+        } else {
+            false
+        }
+    }
+
+    fn push_transaction<'a, T>(
+        &mut self,
+        buffer_transactions: T,
+        now: Instant,
+        cx: &mut ModelContext<MultiBuffer>,
+    ) -> Option<TransactionId>
+    where
+        T: IntoIterator<Item = (&'a Model<Buffer>, &'a language::Transaction)>,
+    {
+        assert_eq!(self.transaction_depth, 0);
+        let transaction = Transaction {
+            id: self.next_transaction_id.tick(),
+            buffer_transactions: buffer_transactions
+                .into_iter()
+                .map(|(buffer, transaction)| (buffer.read(cx).remote_id(), transaction.id))
+                .collect(),
+            excerpt_changes: Default::default(),
+            first_edit_at: now,
+            last_edit_at: now,
+            suppress_grouping: false,
+        };
+        if transaction.buffer_transactions.is_empty() {
+            None
+        } else {
+            let transaction_id = transaction.id;
+            self.undo_stack.push(transaction);
+            self.redo_stack.clear();
+            Some(transaction_id)
+        }
+    }
+
+    fn finalize_last_transaction(&mut self) -> Option<TransactionId> {
+        let transaction = self.undo_stack.last_mut()?;
+        transaction.suppress_grouping = true;
+        Some(transaction.id)
+    }
+
+    fn forget(&mut self, transaction_id: TransactionId) -> Option<Transaction> {
+        if let Some(ix) = self
+            .undo_stack
+            .iter()
+            .rposition(|transaction| transaction.id == transaction_id)
+        {
+            Some(self.undo_stack.remove(ix))
+        } else if let Some(ix) = self
+            .redo_stack
+            .iter()
+            .rposition(|transaction| transaction.id == transaction_id)
+        {
+            Some(self.redo_stack.remove(ix))
+        } else {
+            None
+        }
+    }
+
+    fn transaction_mut(&mut self, transaction_id: TransactionId) -> Option<&mut Transaction> {
+        self.undo_stack
+            .iter_mut()
+            .find(|transaction| transaction.id == transaction_id)
+            .or_else(|| {
+                self.redo_stack
+                    .iter_mut()
+                    .find(|transaction| transaction.id == transaction_id)
+            })
+    }
+
+    fn pop_undo(&mut self) -> Option<&mut Transaction> {
+        assert_eq!(self.transaction_depth, 0);
+        if let Some(transaction) = self.undo_stack.pop() {
+            self.redo_stack.push(transaction);
+            self.redo_stack.last_mut()
+        } else {
+            None
+        }
+    }
+
+    fn pop_redo(&mut self) -> Option<&mut Transaction> {
+        assert_eq!(self.transaction_depth, 0);
+        if let Some(transaction) = self.redo_stack.pop() {
+            self.undo_stack.push(transaction);
+            self.undo_stack.last_mut()
+        } else {
+            None
+        }
+    }
+
+    fn remove_from_undo(&mut self, transaction_id: TransactionId) -> Option<&Transaction> {
+        let ix = self
+            .undo_stack
+            .iter()
+            .rposition(|transaction| transaction.id == transaction_id)?;
+        let transaction = self.undo_stack.remove(ix);
+        self.redo_stack.push(transaction);
+        self.redo_stack.last()
+    }
+
+    /// Groups the most recent transactions that occurred within the group interval,
+    /// returning the id of the resulting transaction along with the buffer transactions
+    /// that must be merged to match it.
+    fn group(&mut self) -> (Option<TransactionId>, Vec<BufferTransactionMerge>) {
+        let mut count = 0;
+        let mut transactions = self.undo_stack.iter();
+        if let Some(mut transaction) = transactions.next_back() {
+            while let Some(prev_transaction) = transactions.next_back() {
+                if !prev_transaction.suppress_grouping
+                    && transaction.first_edit_at - prev_transaction.last_edit_at
+                        <= self.group_interval
+                {
+                    transaction = prev_transaction;
+                    count += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.group_trailing(count)
+    }
+
+    fn group_until(&mut self, transaction_id: TransactionId) -> Vec<BufferTransactionMerge> {
+        let mut count = 0;
+        for transaction in self.undo_stack.iter().rev() {
+            if transaction.id == transaction_id {
+                return self.group_trailing(count).1;
+            } else if transaction.suppress_grouping {
+                break;
+            } else {
+                count += 1;
+            }
+        }
+        Vec::new()
+    }
+
+    fn group_trailing(&mut self, n: usize) -> (Option<TransactionId>, Vec<BufferTransactionMerge>) {
+        let mut merges = Vec::new();
+        let new_len = self.undo_stack.len() - n;
+        let (transactions_to_keep, transactions_to_merge) = self.undo_stack.split_at_mut(new_len);
+        if let Some(last_transaction) = transactions_to_keep.last_mut() {
+            if let Some(transaction) = transactions_to_merge.last() {
+                last_transaction.last_edit_at = transaction.last_edit_at;
+            }
+            for to_merge in transactions_to_merge {
+                for (buffer_id, transaction_id) in &to_merge.buffer_transactions {
+                    let destination = *last_transaction
+                        .buffer_transactions
+                        .entry(*buffer_id)
+                        .or_insert(*transaction_id);
+                    if destination != *transaction_id {
+                        merges.push(BufferTransactionMerge {
+                            buffer_id: *buffer_id,
+                            transaction_id: *transaction_id,
+                            destination,
+                        });
+                    }
+                }
+                last_transaction
+                    .excerpt_changes
+                    .append(&mut to_merge.excerpt_changes);
+            }
+        }
+
+        self.undo_stack.truncate(new_len);
+        (self.undo_stack.last().map(|t| t.id), merges)
+    }
+}
+
+impl Excerpt {
+    fn new(
+        id: ExcerptId,
+        locator: Locator,
+        buffer_id: BufferId,
+        buffer: Arc<BufferSnapshot>,
+        range: ExcerptRange<text::Anchor>,
+        has_trailing_newline: bool,
+    ) -> Self {
+        Excerpt {
+            id,
+            locator,
+            max_buffer_row: range.context.end.to_point(&buffer).row,
+            text_summary: buffer
+                .text_summary_for_range::<TextSummary, _>(range.context.to_offset(&buffer)),
+            buffer_id,
+            buffer,
+            range,
+            has_trailing_newline,
+        }
+    }
+
+    fn chunks_in_range(&self, range: Range<usize>, language_aware: bool) -> ExcerptChunks {
+        let content_start = self.range.context.start.to_offset(&self.buffer);
+        let chunks_start = content_start + range.start;
+        let chunks_end = content_start + cmp::min(range.end, self.text_summary.len);
+
+        let footer_height = if self.has_trailing_newline
+            && range.start <= self.text_summary.len
+            && range.end > self.text_summary.len
+        {
+            1
+        } else {
+            0
+        };
+
+        let content_chunks = self.buffer.chunks(chunks_start..chunks_end, language_aware);
+
+        ExcerptChunks {
+            content_chunks,
+            footer_height,
+        }
+    }
+
+    /// Seeks the excerpt's chunks forward to the given offset within the excerpt. Returns false
+    /// if the chunks have already advanced past it, since seeking buffer chunks backward would
+    /// lose the highlights and diagnostics that started before the offset.
+    fn seek_chunks_forward(&self, excerpt_chunks: &mut ExcerptChunks, offset: usize) -> bool {
+        let content_start = self.range.context.start.to_offset(&self.buffer);
+        if content_start + offset < excerpt_chunks.content_chunks.offset() {
+            return false;
+        }
+        excerpt_chunks.content_chunks.seek(content_start + offset);
+        true
+    }
+
+    fn bytes_in_range(&self, range: Range<usize>) -> ExcerptBytes {
+        let content_start = self.range.context.start.to_offset(&self.buffer);
+        let bytes_start = content_start + range.start;
+        let bytes_end = content_start + cmp::min(range.end, self.text_summary.len);
+        let footer_height = if self.has_trailing_newline
+            && range.start <= self.text_summary.len
+            && range.end > self.text_summary.len
+        {
+            1
+        } else {
+            0
+        };
+        let content_bytes = self.buffer.bytes_in_range(bytes_start..bytes_end);
+
+        ExcerptBytes {
+            content_bytes,
+            footer_height,
+        }
+    }
+
+    fn reversed_bytes_in_range(&self, range: Range<usize>) -> ExcerptBytes {
+        let content_start = self.range.context.start.to_offset(&self.buffer);
+        let bytes_start = content_start + range.start;
+        let bytes_end = content_start + cmp::min(range.end, self.text_summary.len);
+        let footer_height = if self.has_trailing_newline
+            && range.start <= self.text_summary.len
+            && range.end > self.text_summary.len
+        {
+            1
+        } else {
+            0
+        };
+        let content_bytes = self.buffer.reversed_bytes_in_range(bytes_start..bytes_end);
+
+        ExcerptBytes {
+            content_bytes,
+            footer_height,
+        }
+    }
+
+    fn clip_anchor(&self, text_anchor: text::Anchor) -> text::Anchor {
+        if text_anchor
+            .cmp(&self.range.context.start, &self.buffer)
+            .is_lt()
+        {
+            self.range.context.start
+        } else if text_anchor
+            .cmp(&self.range.context.end, &self.buffer)
+            .is_gt()
+        {
+            self.range.context.end
+        } else {
+            text_anchor
+        }
+    }
+
+    fn contains(&self, anchor: &Anchor) -> bool {
+        Some(self.buffer_id) == anchor.buffer_id
+            && self
+                .range
+                .context
+                .start
+                .cmp(&anchor.text_anchor, &self.buffer)
+                .is_le()
+            && self
+                .range
+                .context
+                .end
+                .cmp(&anchor.text_anchor, &self.buffer)
+                .is_ge()
+    }
+}
+
+impl ExcerptId {
+    pub fn min() -> Self {
+        Self(0)
+    }
+
+    pub fn max() -> Self {
+        Self(usize::MAX)
+    }
+
+    pub fn to_proto(&self) -> u64 {
+        self.0 as _
+    }
+
+    pub fn from_proto(proto: u64) -> Self {
+        Self(proto as _)
+    }
+
+    /// Returns an id greater than this one. The low bits of the id hold the replica that
+    /// generated it, so that ids generated concurrently on different replicas never collide.
+    fn successor(self, replica_id: ReplicaId) -> Self {
+        let counter = (self.0 >> ReplicaId::BITS) + 1;
+        Self(counter << ReplicaId::BITS | replica_id as usize)
+    }
+
+    pub fn cmp(&self, other: &Self, snapshot: &MultiBufferSnapshot) -> cmp::Ordering {
+        let a = snapshot.excerpt_locator_for_id(*self);
+        let b = snapshot.excerpt_locator_for_id(*other);
+        a.cmp(b).then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl Into<usize> for ExcerptId {
+    fn into(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Debug for Excerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Excerpt")
+            .field("id", &self.id)
+            .field("locator", &self.locator)
+            .field("buffer_id", &self.buffer_id)
+            .field("range", &self.range)
+            .field("text_summary", &self.text_summary)
+            .field("has_trailing_newline", &self.has_trailing_newline)
+            .finish()
+    }
+}
+
+impl sum_tree::Item for Excerpt {
+    type Summary = ExcerptSummary;
+
+    fn summary(&self) -> Self::Summary {
+        let mut text = self.text_summary.clone();
+        if self.has_trailing_newline {
+            text += TextSummary::from("\n");
+        }
+        ExcerptSummary {
+            excerpt_id: self.id,
+            excerpt_locator: self.locator.clone(),
+            max_buffer_row: self.max_buffer_row,
+            text,
+        }
+    }
+}
+
+impl sum_tree::Item for ExcerptIdMapping {
+    type Summary = ExcerptId;
+
+    fn summary(&self) -> Self::Summary {
+        self.id
+    }
+}
+
+impl sum_tree::KeyedItem for ExcerptIdMapping {
+    type Key = ExcerptId;
+
+    fn key(&self) -> Self::Key {
+        self.id
+    }
+}
+
+impl sum_tree::Summary for ExcerptId {
+    type Context = ();
+
+    fn add_summary(&mut self, other: &Self, _: &()) {
+        *self = *other;
+    }
+}
+
+impl sum_tree::Summary for ExcerptSummary {
+    type Context = ();
+
+    fn add_summary(&mut self, summary: &Self, _: &()) {
+        debug_assert!(summary.excerpt_locator > self.excerpt_locator);
+        self.excerpt_locator = summary.excerpt_locator.clone();
+        self.text.add_summary(&summary.text, &());
+        self.max_buffer_row = cmp::max(self.max_buffer_row, summary.max_buffer_row);
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for TextSummary {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self += &summary.text;
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for usize {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self += summary.text.len;
+    }
+}
+
+impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, ExcerptSummary> for usize {
+    fn cmp(&self, cursor_location: &ExcerptSummary, _: &()) -> cmp::Ordering {
+        Ord::cmp(self, &cursor_location.text.len)
+    }
+}
+
+impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, Option<&'a Locator>> for Locator {
+    fn cmp(&self, cursor_location: &Option<&'a Locator>, _: &()) -> cmp::Ordering {
+        Ord::cmp(&Some(self), cursor_location)
+    }
+}
+
+impl<'a> sum_tree::SeekTarget<'a, ExcerptSummary, ExcerptSummary> for Locator {
+    fn cmp(&self, cursor_location: &ExcerptSummary, _: &()) -> cmp::Ordering {
+        Ord::cmp(self, &cursor_location.excerpt_locator)
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for OffsetUtf16 {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self += summary.text.len_utf16;
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Point {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self += summary.text.lines;
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for PointUtf16 {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self += summary.text.lines_utf16()
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Option<&'a Locator> {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self = Some(&summary.excerpt_locator);
+    }
+}
+
+impl<'a> sum_tree::Dimension<'a, ExcerptSummary> for Option<ExcerptId> {
+    fn add_summary(&mut self, summary: &'a ExcerptSummary, _: &()) {
+        *self = Some(summary.excerpt_id);
+    }
+}
+
+impl<'a> MultiBufferRows<'a> {
+    /// Also yields each row's distance from the given multi-buffer row, which can be above or
+    /// below the rows being iterated, while still reporting buffer rows.
+    pub fn relative_to(self, current_row: u32) -> RelativeMultiBufferRows<'a> {
+        RelativeMultiBufferRows {
+            rows: self,
+            current_row,
+        }
+    }
+
+    pub fn seek(&mut self, row: u32) {
+        self.buffer_row_range = 0..0;
+        self.row = row;
+
+        self.excerpts
+            .seek_forward(&Point::new(row, 0), Bias::Right, &());
+        if self.excerpts.item().is_none() {
+            self.excerpts.prev(&());
+
+            if self.excerpts.item().is_none() && row == 0 {
+                self.buffer_row_range = 0..1;
+                return;
+            }
+        }
+
+        if let Some(excerpt) = self.excerpts.item() {
+            let overshoot = row - self.excerpts.start().row;
+            let excerpt_start = excerpt.range.context.start.to_point(&excerpt.buffer).row;
+            self.buffer_row_range.start = excerpt_start + overshoot;
+            self.buffer_row_range.end = excerpt_start + excerpt.text_summary.lines.row + 1;
+        }
+    }
+}
+
+impl<'a> Iterator for MultiBufferRows<'a> {
+    type Item = Option<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if !self.buffer_row_range.is_empty() {
+                let row = Some(self.buffer_row_range.start);
+                self.buffer_row_range.start += 1;
+                self.row += 1;
+                return Some(row);
+            }
+            self.excerpts.item()?;
+            self.excerpts.next(&());
+            let excerpt = self.excerpts.item()?;
+            self.buffer_row_range.start = excerpt.range.context.start.to_point(&excerpt.buffer).row;
+            self.buffer_row_range.end =
+                self.buffer_row_range.start + excerpt.text_summary.lines.row + 1;
+        }
+    }
+}
+
+impl<'a> MinimapRuns<'a> {
+    fn skip_to_line_end(&mut self) {
+        if let Some(newline_ix) = self.text.find('\n') {
+            self.text = &self.text[newline_ix..];
+        } else {
+            // Seek past the rest of a long line, so that it isn't highlighted.
+            let line_end = self
+                .snapshot
+                .point_to_offset(Point::new(self.row, self.snapshot.line_len(self.row)));
+            if line_end > self.chunks.offset() {
+                self.chunks.seek(line_end);
+            }
+            self.text = "";
+        }
+    }
+}
+
+impl<'a> Iterator for MinimapRuns<'a> {
+    type Item = MinimapRun;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(c) = self.text.chars().next() else {
+                let Some(chunk) = self.chunks.next() else {
+                    return self.run.take();
+                };
+                self.text = chunk.text;
+                self.syntax_highlight_id = chunk.syntax_highlight_id;
+                continue;
+            };
+
+            if c == '\n' {
+                self.text = &self.text[1..];
+                self.row += 1;
+                self.column = 0;
+                self.line_run_count = 0;
+                if let Some(run) = self.run.take() {
+                    return Some(run);
+                }
+                continue;
+            }
+
+            if self.column >= self.max_column {
+                self.skip_to_line_end();
+                continue;
+            }
+
+            self.text = &self.text[c.len_utf8()..];
+            let column = self.column;
+            self.column += 1;
+            if c.is_whitespace() {
+                continue;
+            }
+
+            if let Some(run) = self.run.as_mut() {
+                let continues_run = run.columns.end == column
+                    && run.syntax_highlight_id == self.syntax_highlight_id;
+                if continues_run || self.line_run_count >= self.max_runs_per_line {
+                    run.columns.end = column + 1;
+                    continue;
+                }
+            }
+            self.line_run_count += 1;
+            let prev_run = self.run.replace(MinimapRun {
+                row: self.row,
+                columns: column..column + 1,
+                syntax_highlight_id: self.syntax_highlight_id,
+            });
+            if prev_run.is_some() {
+                return prev_run;
+            }
+        }
+    }
+}
+
+impl<'a> RelativeMultiBufferRows<'a> {
+    pub fn seek(&mut self, row: u32) {
+        self.rows.seek(row);
+    }
+}
+
+impl<'a> Iterator for RelativeMultiBufferRows<'a> {
+    /// The buffer row, and the number of rows between it and the current row.
+    type Item = (Option<u32>, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.row;
+        let buffer_row = self.rows.next()?;
+        Some((buffer_row, row.abs_diff(self.current_row)))
+    }
+}
+
+impl<'a> MultiBufferChunks<'a> {
+    pub fn offset(&self) -> usize {
+        self.range.start
+    }
+
+    /// Seeks to the given offset, which may be before or after the current one.
+    ///
+    /// Seeking forward within the current excerpt reuses its chunks, and seeking to
+    /// any other offset within the current excerpt reuses the excerpt cursor, so that
+    /// scrolling in small steps in either direction stays cheap. The excerpt's chunks may
+    /// be ahead of the current offset when part of a chunk is still pending, in which case
+    /// they're recreated rather than sought backward.
+    pub fn seek(&mut self, offset: usize) {
+        self.range.start = offset;
+        let in_current_excerpt = self.excerpts.item().is_some()
+            && *self.excerpts.start() <= offset
+            && offset < self.excerpts.end(&());
+        if in_current_excerpt {
+            if let Some((excerpt, excerpt_chunks)) =
+                self.excerpts.item().zip(self.excerpt_chunks.as_mut())
+            {
+                let overshoot = offset - self.excerpts.start();
+                if offset <= self.range.end
+                    && overshoot < excerpt.text_summary.len
+                    && excerpt.seek_chunks_forward(excerpt_chunks, overshoot)
+                {
+                    self.reset_line_state();
+                    return;
+                }
+            }
+        } else if offset >= *self.excerpts.start() {
+            self.excerpts.seek_forward(&offset, Bias::Right, &());
+        } else {
+            self.excerpts.seek(&offset, Bias::Right, &());
+        }
+
+        if let Some(excerpt) = self.excerpts.item() {
+            self.excerpt_chunks = Some(self.chunks_for_excerpt(
+                excerpt,
+                self.range.start - self.excerpts.start()..self.range.end - self.excerpts.start(),
+            ));
+        } else {
+            self.excerpt_chunks = None;
+        }
+        self.reset_line_state();
+    }
+
+    /// Whether any of the chunks yielded so far were left unstyled because their buffer
+    /// was still being parsed. See [`MultiBufferSnapshot::chunks_unstyled_while_parsing`].
+    pub fn highlights_pending(&self) -> bool {
+        self.highlights_pending
+    }
+
+    fn chunks_for_excerpt(
+        &mut self,
+        excerpt: &'a Excerpt,
+        range: Range<usize>,
+    ) -> ExcerptChunks<'a> {
+        let mut language_aware = self.language_aware;
+        if language_aware && is_large_buffer(&excerpt.buffer, self.large_buffer_threshold) {
+            language_aware = false;
+        } else if language_aware && self.plain_text_while_parsing && excerpt.buffer.is_parsing() {
+            language_aware = false;
+            self.highlights_pending = true;
+        }
+        excerpt.chunks_in_range(range, language_aware)
+    }
+
+    /// Discards any partially-yielded chunk and determines whether the line containing the
+    /// current offset has any content before it, after seeking.
+    fn reset_line_state(&mut self) {
+        self.pending_chunk = None;
+        self.line_content_end = None;
+        self.line_has_content = self.mark_whitespace
+            && self.excerpts.item().map_or(false, |excerpt| {
+                let overshoot = cmp::min(
+                    self.range.start - self.excerpts.start(),
+                    excerpt.text_summary.len,
+                );
+                let excerpt_start = excerpt.range.context.start.to_offset(&excerpt.buffer);
+                excerpt
+                    .buffer
+                    .reversed_chunks_in_range(excerpt_start..excerpt_start + overshoot)
+                    .flat_map(|chunk| chunk.chars().rev())
+                    .take_while(|c| *c != '\n')
+                    .any(|c| !c.is_whitespace())
+            });
+    }
+
+    /// Yields the start of the given chunk up to its first boundary between whitespace and
+    /// other text, saving the rest for the next call to `next`. If the yielded text is a run
+    /// of spaces or tabs, it is marked with its position within its line.
+    fn split_at_whitespace(&mut self, mut chunk: Chunk<'a>) -> Chunk<'a> {
+        let is_space = |c: char| c != '\t' && c != '\n' && c.is_whitespace();
+        let Some(first_char) = chunk.text.chars().next() else {
+            return chunk;
+        };
+        let len = if first_char == '\t' {
+            chunk.text.len() - chunk.text.trim_start_matches('\t').len()
+        } else if is_space(first_char) {
+            chunk.text.len() - chunk.text.trim_start_matches(is_space).len()
+        } else {
+            chunk
+                .text
+                .find(|c: char| c == '\t' || is_space(c))
+                .unwrap_or(chunk.text.len())
+        };
+
+        let (text, rest) = chunk.text.split_at(len);
+        if !rest.is_empty() {
+            self.pending_chunk = Some(Chunk {
+                text: rest,
+                ..chunk
+            });
+        }
+        chunk.text = text;
+
+        if first_char == '\t' || is_space(first_char) {
+            chunk.is_tab = first_char == '\t';
+            chunk.whitespace = Some(if self.line_content_end() <= self.range.start {
+                WhitespacePosition::Trailing
+            } else if self.line_has_content {
+                WhitespacePosition::Inner
+            } else {
+                WhitespacePosition::Leading
+            });
+        } else if let Some(newline_ix) = text.rfind('\n') {
+            self.line_has_content = newline_ix + 1 < text.len();
+            self.line_content_end = None;
+        } else {
+            self.line_has_content = true;
+        }
+        chunk
+    }
+
+    /// Returns the offset at which the current line's non-whitespace text ends, scanning the
+    /// rest of the line within the current excerpt the first time it's needed for the line.
+    fn line_content_end(&mut self) -> usize {
+        if let Some(line_content_end) = self.line_content_end {
+            return line_content_end;
+        }
+
+        let mut line_content_end = self.range.start;
+        if let Some(excerpt) = self.excerpts.item() {
+            let overshoot = cmp::min(
+                self.range.start - self.excerpts.start(),
+                excerpt.text_summary.len,
+            );
+            let excerpt_range = excerpt.range.context.to_offset(&excerpt.buffer);
+            let mut offset = self.range.start;
+            for c in excerpt
+                .buffer
+                .text_for_range(excerpt_range.start + overshoot..excerpt_range.end)
+                .flat_map(str::chars)
+                .take_while(|c| *c != '\n')
+            {
+                offset += c.len_utf8();
+                if !c.is_whitespace() {
+                    line_content_end = offset;
+                }
+            }
+        }
+        self.line_content_end = Some(line_content_end);
+        line_content_end
+    }
+}
+
+impl<'a> Iterator for MultiBufferChunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.range.is_empty() {
+            None
+        } else if let Some(mut chunk) = self
+            .pending_chunk
+            .take()
+            .or_else(|| self.excerpt_chunks.as_mut()?.next())
+        {
+            if self.mark_whitespace {
+                chunk = self.split_at_whitespace(chunk);
+            }
+            self.range.start += chunk.text.len();
+            Some(chunk)
+        } else if self.excerpt_chunks.is_none() {
+            None
+        } else {
+            self.excerpts.next(&());
+            let excerpt = self.excerpts.item()?;
+            self.excerpt_chunks =
+                Some(self.chunks_for_excerpt(excerpt, 0..self.range.end - self.excerpts.start()));
+            self.next()
+        }
+    }
+}
+
+impl<'a> MultiBufferBytes<'a> {
+    fn consume(&mut self, len: usize) {
+        self.range.start += len;
+        self.chunk = &self.chunk[len..];
+
+        if !self.range.is_empty() && self.chunk.is_empty() {
+            if let Some(chunk) = self.excerpt_bytes.as_mut().and_then(|bytes| bytes.next()) {
+                self.chunk = chunk;
+            } else {
+                self.excerpts.next(&());
+                if let Some(excerpt) = self.excerpts.item() {
+                    let mut excerpt_bytes =
+                        excerpt.bytes_in_range(0..self.range.end - self.excerpts.start());
+                    self.chunk = excerpt_bytes.next().unwrap();
+                    self.excerpt_bytes = Some(excerpt_bytes);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for MultiBufferBytes<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunk;
+        if chunk.is_empty() {
+            None
+        } else {
+            self.consume(chunk.len());
+            Some(chunk)
+        }
+    }
+}
+
+impl<'a> io::Read for MultiBufferBytes<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        if len > 0 {
+            self.consume(len);
+        }
+        Ok(len)
+    }
+}
+
+impl<'a> ReversedMultiBufferBytes<'a> {
+    fn consume(&mut self, len: usize) {
+        self.range.end -= len;
+        self.chunk = &self.chunk[..self.chunk.len() - len];
+
+        if !self.range.is_empty() && self.chunk.is_empty() {
+            if let Some(chunk) = self.excerpt_bytes.as_mut().and_then(|bytes| bytes.next()) {
+                self.chunk = chunk;
+            } else {
+                self.excerpts.next(&());
+                if let Some(excerpt) = self.excerpts.item() {
+                    let mut excerpt_bytes =
+                        excerpt.bytes_in_range(0..self.range.end - self.excerpts.start());
+                    self.chunk = excerpt_bytes.next().unwrap();
+                    self.excerpt_bytes = Some(excerpt_bytes);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> io::Read for ReversedMultiBufferBytes<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        buf[..len].reverse();
+        if len > 0 {
+            self.consume(len);
+        }
+        Ok(len)
+    }
+}
+impl<'a> Iterator for ExcerptBytes<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(chunk) = self.content_bytes.next() {
+            if !chunk.is_empty() {
+                return Some(chunk);
+            }
+        }
+
+        if self.footer_height > 0 {
+            let result = &NEWLINES[..self.footer_height];
+            self.footer_height = 0;
+            return Some(result);
+        }
+
+        None
+    }
+}
+
+impl<'a> Iterator for ExcerptChunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(chunk) = self.content_chunks.next() {
+            return Some(chunk);
+        }
+
+        if self.footer_height > 0 {
+            let text = unsafe { str::from_utf8_unchecked(&NEWLINES[..self.footer_height]) };
+            self.footer_height = 0;
+            return Some(Chunk {
+                text,
+                ..Default::default()
+            });
+        }
+
+        None
+    }
+}
+
+impl ToOffset for Point {
+    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
+        snapshot.point_to_offset(*self)
+    }
+}
+
+impl ToOffset for usize {
+    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
+        assert!(*self <= snapshot.len(), "offset is out of range");
+        *self
+    }
+}
+
+impl ToOffset for OffsetUtf16 {
+    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
+        snapshot.offset_utf16_to_offset(*self)
+    }
+}
+
+impl ToOffset for PointUtf16 {
+    fn to_offset<'a>(&self, snapshot: &MultiBufferSnapshot) -> usize {
+        snapshot.point_utf16_to_offset(*self)
+    }
+}
+
+impl ToOffsetUtf16 for OffsetUtf16 {
+    fn to_offset_utf16(&self, _snapshot: &MultiBufferSnapshot) -> OffsetUtf16 {
+        *self
+    }
+}
+
+impl ToOffsetUtf16 for usize {
+    fn to_offset_utf16(&self, snapshot: &MultiBufferSnapshot) -> OffsetUtf16 {
+        snapshot.offset_to_offset_utf16(*self)
+    }
+}
+
+impl ToPoint for usize {
+    fn to_point<'a>(&self, snapshot: &MultiBufferSnapshot) -> Point {
+        snapshot.offset_to_point(*self)
+    }
+}
+
+impl ToPoint for Point {
+    fn to_point<'a>(&self, _: &MultiBufferSnapshot) -> Point {
+        *self
+    }
+}
+
+impl ToPointUtf16 for usize {
+    fn to_point_utf16<'a>(&self, snapshot: &MultiBufferSnapshot) -> PointUtf16 {
+        snapshot.offset_to_point_utf16(*self)
+    }
+}
+
+impl ToPointUtf16 for Point {
+    fn to_point_utf16<'a>(&self, snapshot: &MultiBufferSnapshot) -> PointUtf16 {
+        snapshot.point_to_point_utf16(*self)
+    }
+}
+
+impl ToPointUtf16 for PointUtf16 {
+    fn to_point_utf16<'a>(&self, _: &MultiBufferSnapshot) -> PointUtf16 {
+        *self
+    }
+}
+
+fn build_excerpt_ranges<T>(
+    buffer: &BufferSnapshot,
+    ranges: &[Range<T>],
+    context_line_count: u32,
+) -> (Vec<ExcerptRange<Point>>, Vec<usize>)
+where
+    T: text::ToPoint,
+{
+    let max_point = buffer.max_point();
+    let mut range_counts = Vec::new();
+    let mut excerpt_ranges = Vec::new();
+    let mut range_iter = ranges
+        .iter()
+        .map(|range| range.start.to_point(buffer)..range.end.to_point(buffer))
+        .peekable();
+    while let Some(range) = range_iter.next() {
+        let excerpt_start = Point::new(range.start.row.saturating_sub(context_line_count), 0);
+        let mut excerpt_end = Point::new(range.end.row + 1 + context_line_count, 0).min(max_point);
+        let mut ranges_in_excerpt = 1;
+
+        while let Some(next_range) = range_iter.peek() {
+            if next_range.start.row <= excerpt_end.row + context_line_count {
+                excerpt_end = excerpt_end
+                    .max(Point::new(next_range.end.row + 1 + context_line_count, 0).min(max_point));
+                ranges_in_excerpt += 1;
+                range_iter.next();
+            } else {
+                break;
+            }
+        }
+
+        excerpt_ranges.push(ExcerptRange {
+            context: excerpt_start..excerpt_end,
+            primary: Some(range),
+        });
+        range_counts.push(ranges_in_excerpt);
+    }
+
+    (excerpt_ranges, range_counts)
+}
+
+/// Ends the transaction started by [`MultiBuffer::transact`], even if its update panics, so
+/// that the multi-buffer's transaction depth is always restored.
+struct TransactionGuard<'a, 'b> {
+    multibuffer: &'a mut MultiBuffer,
+    cx: &'a mut ModelContext<'b, MultiBuffer>,
+    ended: bool,
+}
+
+impl TransactionGuard<'_, '_> {
+    fn end(mut self) -> Option<TransactionId> {
+        self.ended = true;
+        self.multibuffer.end_transaction_at(Instant::now(), self.cx)
+    }
+}
+
+impl Drop for TransactionGuard<'_, '_> {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.multibuffer.end_transaction_at(Instant::now(), self.cx);
+        }
+    }
+}
+
+fn is_large_buffer(buffer: &BufferSnapshot, threshold: Option<usize>) -> bool {
+    threshold.map_or(false, |threshold| buffer.len() > threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use gpui::{AppContext, Context, TestAppContext};
+    use language::{Buffer, Language, LanguageConfig, Rope};
+    use parking_lot::RwLock;
+    use rand::prelude::*;
+    use settings::SettingsStore;
+    use std::env;
+    use util::test::sample_text;
+
+    #[gpui::test]
+    fn test_large_buffer_degradation(cx: &mut TestAppContext) {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query("(function_item name: (identifier) @function)")
+            .unwrap(),
+        );
+        let new_buffer = |text: &str, name_range: Range<u32>, cx: &mut TestAppContext| {
+            let buffer = cx.new_model(|cx| {
+                Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text)
+                    .with_language(language.clone(), cx)
+            });
+            buffer.update(cx, |buffer, cx| {
+                let diagnostics = [DiagnosticEntry {
+                    range: PointUtf16::new(0, name_range.start)..PointUtf16::new(0, name_range.end),
+                    diagnostic: language::Diagnostic {
+                        severity: DiagnosticSeverity::ERROR,
+                        ..Default::default()
+                    },
+                }];
+                let diagnostics = language::DiagnosticSet::new(diagnostics, buffer);
+                buffer.update_diagnostics(lsp::LanguageServerId(0), diagnostics, cx);
+            });
+            buffer
+        };
+        let small_buffer = new_buffer("fn a() {}", 3..4, cx);
+        let large_buffer = new_buffer("fn large() {}", 3..8, cx);
+        cx.run_until_parked();
+
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            for buffer in [&small_buffer, &large_buffer] {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 0..buffer.read(cx).len(),
+                        primary: None,
+                    }],
+                    cx,
+                );
+            }
+            multibuffer
+        });
+        let excerpt_ids = multibuffer.read_with(cx, |multibuffer, _| multibuffer.excerpt_ids());
+        // Returns the text that is syntax highlighted, and the text that has diagnostics.
+        let decorated_text = |snapshot: &MultiBufferSnapshot| {
+            let mut highlighted_text = String::new();
+            let mut diagnosed_text = String::new();
+            for chunk in snapshot.chunks(0..snapshot.len(), true) {
+                if chunk.syntax_highlight_id.is_some() {
+                    highlighted_text.push_str(chunk.text);
+                }
+                if chunk.diagnostic_severity.is_some() {
+                    diagnosed_text.push_str(chunk.text);
+                }
+            }
+            (highlighted_text, diagnosed_text)
+        };
+
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert!(!snapshot.has_degraded_excerpts());
+        assert!(!snapshot.is_degraded(excerpt_ids[1]));
+        assert_eq!(
+            decorated_text(&snapshot),
+            ("alarge".to_string(), "alarge".to_string())
+        );
+
+        multibuffer.update(cx, |multibuffer, _| {
+            multibuffer.set_large_buffer_threshold(Some(10))
+        });
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert!(snapshot.has_degraded_excerpts());
+        assert!(!snapshot.is_degraded(excerpt_ids[0]));
+        assert!(snapshot.is_degraded(excerpt_ids[1]));
+        assert_eq!(
+            snapshot
+                .chunks(0..snapshot.len(), true)
+                .map(|chunk| chunk.text)
+                .collect::<String>(),
+            "fn a() {}\nfn large() {}"
+        );
+        assert_eq!(
+            decorated_text(&snapshot),
+            ("a".to_string(), "a".to_string())
+        );
+
+        // Degraded buffers remain editable, and are restored once they shrink.
+        large_buffer.update(cx, |buffer, cx| buffer.edit([(3..6, "")], None, cx));
+        cx.run_until_parked();
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert_eq!(snapshot.text(), "fn a() {}\nfn ge() {}");
+        assert!(!snapshot.has_degraded_excerpts());
+        assert_eq!(
+            decorated_text(&snapshot),
+            ("age".to_string(), "age".to_string())
+        );
+    }
+
+    #[gpui::test]
+    fn test_chunks_unstyled_while_parsing(cx: &mut TestAppContext) {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_highlights_query("(function_item name: (identifier) @function)")
+            .unwrap(),
+        );
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "fn a() {}\n",
+            )
+            .with_language(language, cx)
+        });
+        cx.run_until_parked();
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        let highlighted_text = |snapshot: &MultiBufferSnapshot| {
+            let mut chunks = snapshot.chunks_unstyled_while_parsing(0..snapshot.len());
+            let text = chunks
+                .by_ref()
+                .filter(|chunk| chunk.syntax_highlight_id.is_some())
+                .map(|chunk| chunk.text)
+                .collect::<String>();
+            (text, chunks.highlights_pending())
+        };
+
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert_eq!(highlighted_text(&snapshot), ("a".to_string(), false));
+
+        // While the buffer is being reparsed, its text is yielded without highlights.
+        buffer.update(cx, |buffer, cx| {
+            buffer.set_sync_parse_timeout(Duration::ZERO);
+            buffer.edit([(3..4, "bc")], None, cx);
+            assert!(buffer.is_parsing());
+        });
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        let parse_count = snapshot.parse_count();
+        assert_eq!(highlighted_text(&snapshot), (String::new(), true));
+        assert_eq!(
+            snapshot
+                .chunks_unstyled_while_parsing(0..snapshot.len())
+                .map(|chunk| chunk.text)
+                .collect::<String>(),
+            "fn bc() {}\n"
+        );
+
+        // Once parsing finishes, the parse count is bumped and the highlights return.
+        cx.run_until_parked();
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert!(snapshot.parse_count() > parse_count);
+        assert_eq!(highlighted_text(&snapshot), ("bc".to_string(), false));
+    }
+
+    #[gpui::test]
+    fn test_innermost_bracket_pair(cx: &mut TestAppContext) {
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_brackets_query(
+                r#"
+                ("{" @open "}" @close)
+                ("(" @open ")" @close)
+                "#,
+            )
+            .unwrap(),
+        );
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "fn a() {\n    b(1);\n}\n",
+            )
+            .with_language(language, cx)
+        });
+        cx.run_until_parked();
+
+        // Only show the first two lines of the buffer, which excludes the closing brace.
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 9),
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer
+        });
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+
+        let offset_range =
+            |range: Range<Point>| range.start.to_offset(&snapshot)..range.end.to_offset(&snapshot);
+
+        let position = Point::new(1, 7).to_offset(&snapshot);
+        let pair = snapshot.innermost_bracket_pair(position..position).unwrap();
+        assert!(!pair.is_clipped());
+        assert_eq!(
+            pair,
+            BracketPairRanges {
+                open: Some(offset_range(Point::new(1, 5)..Point::new(1, 6))),
+                close: Some(offset_range(Point::new(1, 7)..Point::new(1, 8))),
+            }
+        );
+
+        // The closing brace of the function isn't visible.
+        let position = Point::new(1, 4).to_offset(&snapshot);
+        let pair = snapshot.innermost_bracket_pair(position..position).unwrap();
+        assert!(pair.is_clipped());
+        assert_eq!(
+            pair,
+            BracketPairRanges {
+                open: Some(offset_range(Point::new(0, 7)..Point::new(0, 8))),
+                close: None,
+            }
+        );
+        assert_eq!(
+            snapshot.innermost_enclosing_bracket_ranges(position..position),
+            None
+        );
+    }
+
+    #[gpui::test]
+    fn test_singleton(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 6, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), buffer.read(cx).text());
+
+        assert_eq!(
+            snapshot.buffer_rows(0).collect::<Vec<_>>(),
+            (0..buffer.read(cx).row_count())
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(1..3, "XXX\n")], None, cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+
+        assert_eq!(snapshot.text(), buffer.read(cx).text());
+        assert_eq!(
+            snapshot.buffer_rows(0).collect::<Vec<_>>(),
+            (0..buffer.read(cx).row_count())
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[gpui::test]
+    fn test_shared_snapshot(cx: &mut AppContext) {
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: 0..3,
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+
+        let version = multibuffer.read(cx).snapshot_version(cx);
+        let snapshot = multibuffer.read(cx).shared_snapshot(cx);
+        assert_eq!(multibuffer.read(cx).snapshot_version(cx), version);
+        assert!(Arc::ptr_eq(
+            &snapshot,
+            &multibuffer.read(cx).shared_snapshot(cx)
+        ));
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(3..3, "d")], None, cx));
+        assert!(multibuffer.read(cx).snapshot_version(cx) > version);
+        let new_snapshot = multibuffer.read(cx).shared_snapshot(cx);
+        assert!(!Arc::ptr_eq(&snapshot, &new_snapshot));
+        assert_eq!(snapshot.text(), "abc");
+        assert_eq!(new_snapshot.text(), "abcd");
+    }
+
+    #[gpui::test]
+    fn test_excerpts_share_buffer_snapshot(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "abcdefghi",
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: 0..2,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 3..5,
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+        });
+        let assert_shared = |snapshot: &MultiBufferSnapshot| {
+            let excerpts = snapshot.excerpts.iter().collect::<Vec<_>>();
+            assert!(excerpts
+                .windows(2)
+                .all(|pair| Arc::ptr_eq(&pair[0].buffer, &pair[1].buffer)));
+            excerpts[0].buffer.clone()
+        };
+        let old_buffer_snapshot = assert_shared(&multibuffer.read(cx).snapshot(cx));
+
+        // Syncing after an edit gives all of the excerpts the same new snapshot.
+        buffer.update(cx, |buffer, cx| buffer.edit([(4..4, "X")], None, cx));
+        let new_buffer_snapshot = assert_shared(&multibuffer.read(cx).snapshot(cx));
+        assert!(!Arc::ptr_eq(&old_buffer_snapshot, &new_buffer_snapshot));
+
+        // New excerpts share the snapshot of the buffer's existing excerpts.
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: 7..9,
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "ab\ndXe\ngh");
+        assert!(Arc::ptr_eq(&assert_shared(&snapshot), &new_buffer_snapshot));
+    }
+
+    #[gpui::test]
+    fn test_sync_only_checks_dirty_buffers(cx: &mut AppContext) {
+        let buffer_1 = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let buffer_2 = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "def"));
+        let buffer_2_id = buffer_2.read(cx).remote_id();
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            for buffer in [&buffer_1, &buffer_2] {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 0..3,
+                        primary: None,
+                    }],
+                    cx,
+                );
+            }
+        });
+        assert_eq!(multibuffer.read(cx).snapshot(cx).text(), "abc\ndef");
+        assert!(multibuffer.read(cx).dirty_buffers.borrow().is_empty());
+        assert!(!multibuffer.read(cx).is_dirty(cx));
+
+        // The edited buffer is marked dirty when it notifies the multi-buffer.
+        buffer_2.update(cx, |buffer, cx| buffer.edit([(3..3, "g")], None, cx));
+        assert_eq!(
+            *multibuffer.read(cx).dirty_buffers.borrow(),
+            HashSet::from_iter([buffer_2_id])
+        );
+        assert_eq!(multibuffer.read(cx).snapshot(cx).text(), "abc\ndefg");
+        assert!(multibuffer.read(cx).dirty_buffers.borrow().is_empty());
+        assert!(multibuffer.read(cx).is_dirty(cx));
+
+        // Edits made through the multi-buffer are visible before the buffers notify it.
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.edit([(0..0, "X")], None, cx);
+            assert_eq!(multibuffer.snapshot(cx).text(), "Xabc\ndefg");
+        });
+
+        // Removing the only dirty buffer updates whether the multi-buffer is dirty.
+        buffer_1.update(cx, |buffer, cx| buffer.undo(cx));
+        multibuffer.update(cx, |multibuffer, cx| {
+            let excerpt_ids = multibuffer.excerpt_ids_for_buffer(buffer_2_id, cx);
+            multibuffer.remove_excerpts(excerpt_ids.iter().copied(), cx);
+        });
+        assert_eq!(multibuffer.read(cx).snapshot(cx).text(), "abc");
+        assert!(!multibuffer.read(cx).is_dirty(cx));
+
+        // Edits made directly to a buffer are visible before it notifies the multi-buffer.
+        multibuffer.update(cx, |multibuffer, cx| {
+            buffer_1.update(cx, |buffer, cx| buffer.edit([(3..3, "d")], None, cx));
+            assert!(multibuffer.dirty_buffers.borrow().is_empty());
+            assert_eq!(multibuffer.snapshot(cx).text(), "abcd");
+        });
+    }
+
+    #[gpui::test]
+    fn test_changes_since(cx: &mut AppContext) {
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [ExcerptRange {
+                    context: 0..3,
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+
+        let old_snapshot = multibuffer.read(cx).snapshot(cx);
+        multibuffer.update(cx, |multibuffer, cx| {
+            let selection = Selection {
+                id: 0,
+                start: Anchor::min(),
+                end: Anchor::min(),
+                reversed: false,
+                goal: text::SelectionGoal::None,
+            };
+            multibuffer.set_local_selections(Arc::from([selection]), cx);
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let changes = snapshot.changes_since(&old_snapshot);
+        assert!(changes.selections);
+        assert!(changes.is_decoration_only());
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "x")], None, cx));
+        let new_snapshot = multibuffer.read(cx).snapshot(cx);
+        let changes = new_snapshot.changes_since(&snapshot);
+        assert!(changes.text);
+        assert!(!changes.selections);
+        assert!(!changes.is_decoration_only());
+    }
+
+    #[gpui::test]
+    fn test_excerpt_changes_since(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(4, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "xyz"));
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            let mut excerpt_ids = multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(0, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(2, 0)..Point::new(2, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            excerpt_ids.extend(multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: 0..3,
+                    primary: None,
+                }],
+                cx,
+            ));
+            excerpt_ids
+        });
+
+        let old_snapshot = multibuffer.read(cx).snapshot(cx);
+        buffer_1.update(cx, |buffer, cx| {
+            buffer.edit([(Point::new(2, 1)..Point::new(2, 1), "!")], None, cx)
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(
+            snapshot
+                .excerpt_changes_since(&old_snapshot)
+                .collect::<Vec<_>>(),
+            [(
+                excerpt_ids[1],
+                MultiBufferChanges {
+                    text: true,
+                    ..Default::default()
+                }
+            )]
+        );
+    }
+
+    #[gpui::test]
+    fn test_remote(cx: &mut AppContext) {
+        let host_buffer =
+            cx.new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "a"));
+        let guest_buffer = cx.new_model(|cx| {
+            let state = host_buffer.read(cx).to_proto();
+            let ops = cx
+                .background_executor()
+                .block(host_buffer.read(cx).serialize_ops(None, cx));
+            let mut buffer = Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap();
+            buffer
+                .apply_ops(
+                    ops.into_iter()
+                        .map(|op| language::proto::deserialize_operation(op).unwrap()),
+                    cx,
+                )
+                .unwrap();
+            buffer
+        });
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(guest_buffer.clone(), cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "a");
+
+        guest_buffer.update(cx, |buffer, cx| buffer.edit([(1..1, "b")], None, cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "ab");
+
+        guest_buffer.update(cx, |buffer, cx| buffer.edit([(2..2, "c")], None, cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "abc");
+    }
+
+    #[gpui::test]
+    fn test_undo_preserves_remote_transactions(cx: &mut AppContext) {
+        let host_buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                host_buffer.clone(),
+                [ExcerptRange {
+                    context: 0..3,
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer
+        });
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.start_transaction(cx);
+            multibuffer.edit([(0..0, "X")], None, cx);
+            multibuffer.end_transaction(cx);
+        });
+
+        // A collaborator edits the buffer, and their transaction is pushed onto the host's
+        // history, as happens when formatting on their behalf.
+        let guest_buffer = cx.new_model(|cx| {
+            let state = host_buffer.read(cx).to_proto();
+            let ops = cx
+                .background_executor()
+                .block(host_buffer.read(cx).serialize_ops(None, cx));
+            let mut buffer = Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap();
+            buffer
+                .apply_ops(
+                    ops.into_iter()
+                        .map(|op| language::proto::deserialize_operation(op).unwrap()),
+                    cx,
+                )
+                .unwrap();
+            buffer
+        });
+        let guest_transaction = guest_buffer.update(cx, |buffer, cx| {
+            buffer.start_transaction();
+            buffer.edit([(4..4, "Y")], None, cx);
+            buffer.end_transaction(cx);
+            buffer.finalize_last_transaction().unwrap().clone()
+        });
+        let guest_ops = cx
+            .background_executor()
+            .block(guest_buffer.read(cx).serialize_ops(None, cx));
+        host_buffer.update(cx, |buffer, cx| {
+            buffer
+                .apply_ops(
+                    guest_ops
+                        .into_iter()
+                        .map(|op| language::proto::deserialize_operation(op).unwrap()),
+                    cx,
+                )
+                .unwrap();
+            buffer.push_transaction(guest_transaction, Instant::now());
+
+            // The host also edits the buffer directly.
+            buffer.start_transaction();
+            buffer.edit([(1..1, "Z")], None, cx);
+            buffer.end_transaction(cx);
+        });
+        assert_eq!(multibuffer.read(cx).read(cx).text(), "XZabcY");
+
+        // Undoing in the multi-buffer undoes the host's own edits, but not the collaborator's.
+        multibuffer.update(cx, |multibuffer, cx| multibuffer.undo(cx));
+        assert_eq!(multibuffer.read(cx).read(cx).text(), "abcY");
+        multibuffer.update(cx, |multibuffer, cx| multibuffer.redo(cx));
+        assert_eq!(multibuffer.read(cx).read(cx).text(), "XZabcY");
+    }
+
+    #[gpui::test]
+    fn test_excerpt_boundaries_and_clipping(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 6, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 6, 'g'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+
+        let events = Arc::new(RwLock::new(Vec::<Event>::new()));
+        multibuffer.update(cx, |_, cx| {
+            let events = events.clone();
+            cx.subscribe(&multibuffer, move |_, _, event, _| {
+                if let Event::Edited { .. } = event {
+                    events.write().push(event.clone())
+                }
+            })
+            .detach();
+        });
+
+        let subscription = multibuffer.update(cx, |multibuffer, cx| {
+            let subscription = multibuffer.subscribe();
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(1, 2)..Point::new(2, 5),
+                    primary: None,
+                }],
+                cx,
+            );
+            assert_eq!(
+                subscription.consume().into_inner(),
+                [Edit {
+                    old: 0..0,
+                    new: 0..10
+                }]
+            );
+
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(3, 3)..Point::new(4, 4),
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: Point::new(3, 1)..Point::new(3, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+            assert_eq!(
+                subscription.consume().into_inner(),
+                [Edit {
+                    old: 10..10,
+                    new: 10..22
+                }]
+            );
+
+            subscription
+        });
+
+        // Adding excerpts emits an edited event.
+        assert_eq!(
+            events.read().as_slice(),
+            &[
+                Event::Edited {
+                    singleton_buffer_edited: false
+                },
+                Event::Edited {
+                    singleton_buffer_edited: false
+                },
+                Event::Edited {
+                    singleton_buffer_edited: false
+                }
+            ]
+        );
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(
+            snapshot.text(),
+            concat!(
+                "bbbb\n",  // Preserve newlines
+                "ccccc\n", //
+                "ddd\n",   //
+                "eeee\n",  //
+                "jj"       //
+            )
+        );
+        assert_eq!(
+            snapshot.buffer_rows(0).collect::<Vec<_>>(),
+            [Some(1), Some(2), Some(3), Some(4), Some(3)]
+        );
+        assert_eq!(
+            snapshot.buffer_rows(2).collect::<Vec<_>>(),
+            [Some(3), Some(4), Some(3)]
+        );
+        assert_eq!(snapshot.buffer_rows(4).collect::<Vec<_>>(), [Some(3)]);
+        assert_eq!(snapshot.buffer_rows(5).collect::<Vec<_>>(), []);
+        assert_eq!(
+            snapshot.buffer_rows(1).relative_to(3).collect::<Vec<_>>(),
+            [(Some(2), 2), (Some(3), 1), (Some(4), 0), (Some(3), 1)]
+        );
+
+        assert_eq!(
+            boundaries_in_range(Point::new(0, 0)..Point::new(4, 2), &snapshot),
+            &[
+                (0, "bbbb\nccccc".to_string(), true),
+                (2, "ddd\neeee".to_string(), false),
+                (4, "jj".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(0, 0)..Point::new(2, 0), &snapshot),
+            &[(0, "bbbb\nccccc".to_string(), true)]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(1, 0)..Point::new(1, 5), &snapshot),
+            &[]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(1, 0)..Point::new(2, 0), &snapshot),
+            &[]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(1, 0)..Point::new(4, 0), &snapshot),
+            &[(2, "ddd\neeee".to_string(), false)]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(1, 0)..Point::new(4, 0), &snapshot),
+            &[(2, "ddd\neeee".to_string(), false)]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(2, 0)..Point::new(3, 0), &snapshot),
+            &[(2, "ddd\neeee".to_string(), false)]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(4, 0)..Point::new(4, 2), &snapshot),
+            &[(4, "jj".to_string(), true)]
+        );
+        assert_eq!(
+            boundaries_in_range(Point::new(4, 2)..Point::new(4, 2), &snapshot),
+            &[]
+        );
+
+        buffer_1.update(cx, |buffer, cx| {
+            let text = "\n";
+            buffer.edit(
+                [
+                    (Point::new(0, 0)..Point::new(0, 0), text),
+                    (Point::new(2, 1)..Point::new(2, 3), text),
+                ],
+                None,
+                cx,
+            );
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(
+            snapshot.text(),
+            concat!(
+                "bbbb\n", // Preserve newlines
+                "c\n",    //
+                "cc\n",   //
+                "ddd\n",  //
+                "eeee\n", //
+                "jj"      //
+            )
+        );
+
+        assert_eq!(
+            subscription.consume().into_inner(),
+            [Edit {
+                old: 6..8,
+                new: 6..7
+            }]
+        );
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(
+            snapshot.clip_point(Point::new(0, 5), Bias::Left),
+            Point::new(0, 4)
+        );
+        assert_eq!(
+            snapshot.clip_point(Point::new(0, 5), Bias::Right),
+            Point::new(0, 4)
+        );
+        assert_eq!(
+            snapshot.clip_point(Point::new(5, 1), Bias::Right),
+            Point::new(5, 1)
+        );
+        assert_eq!(
+            snapshot.clip_point(Point::new(5, 2), Bias::Right),
+            Point::new(5, 2)
+        );
+        assert_eq!(
+            snapshot.clip_point(Point::new(5, 3), Bias::Right),
+            Point::new(5, 2)
+        );
+
+        let buffer_1_id = buffer_1.read(cx).remote_id();
+        let buffer_2_id = buffer_2.read(cx).remote_id();
+        let excerpt_ids = multibuffer.read(cx).excerpt_ids();
+        assert_eq!(
+            &*multibuffer.read(cx).excerpt_ids_for_buffer(buffer_1_id, cx),
+            &excerpt_ids[..2]
+        );
+        assert_eq!(
+            &*multibuffer.read(cx).excerpt_ids_for_buffer(buffer_2_id, cx),
+            &excerpt_ids[2..]
+        );
+
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| {
+            let (buffer_2_excerpt_id, _) =
+                multibuffer.excerpts_for_buffer(&buffer_2, cx)[0].clone();
+            multibuffer.remove_excerpts([buffer_2_excerpt_id], cx);
+            multibuffer.snapshot(cx)
+        });
+        assert!(multibuffer
+            .read(cx)
+            .excerpt_ids_for_buffer(buffer_2_id, cx)
+            .is_empty());
+
+        assert_eq!(
+            snapshot.text(),
+            concat!(
+                "bbbb\n", // Preserve newlines
+                "c\n",    //
+                "cc\n",   //
+                "ddd\n",  //
+                "eeee",   //
+            )
+        );
+
+        fn boundaries_in_range(
+            range: Range<Point>,
+            snapshot: &MultiBufferSnapshot,
+        ) -> Vec<(u32, String, bool)> {
+            snapshot
+                .excerpt_boundaries_in_range(range)
+                .map(|boundary| {
+                    (
+                        boundary.row,
+                        boundary
+                            .buffer
+                            .text_for_range(boundary.range.context)
+                            .collect::<String>(),
+                        boundary.starts_new_buffer,
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    }
+
+    #[gpui::test]
+    fn test_lazy_excerpts(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(10, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            let ranges = [0..2, 4..5, 7..9].map(|rows| {
+                let buffer = buffer.read(cx);
+                ExcerptRange {
+                    context: buffer.anchor_before(Point::new(rows.start, 0))
+                        ..buffer.anchor_after(Point::new(rows.end, 3)),
+                    primary: None,
+                }
+            });
+            multibuffer.push_lazy_excerpts(buffer.clone(), ranges);
+            assert_eq!(multibuffer.pending_excerpt_count(), 3);
+            assert_eq!(multibuffer.estimated_pending_rows(cx), 8);
+            assert_eq!(multibuffer.read(cx).text(), "");
+
+            assert_eq!(multibuffer.hydrate_excerpts(1, cx).len(), 1);
+            assert_eq!(multibuffer.pending_excerpt_count(), 2);
+            assert_eq!(multibuffer.read(cx).text(), "aaa\nbbb\nccc");
+
+            assert_eq!(multibuffer.hydrate_excerpts(u32::MAX, cx).len(), 2);
+            assert_eq!(multibuffer.pending_excerpt_count(), 0);
+            assert_eq!(
+                multibuffer.read(cx).text(),
+                "aaa\nbbb\nccc\neee\nfff\nhhh\niii\njjj"
+            );
+        });
+    }
+
+    #[gpui::test]
+    fn test_edit_with_many_ranges(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'g'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(1, 0)..Point::new(3, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(1, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+            assert_eq!(multibuffer.read(cx).text(), "bbb\nccc\nddd\nggg\nhhh");
+
+            // Edits don't need to be sorted.
+            let edits = (0..5)
+                .rev()
+                .map(|row| (Point::new(row, 0)..Point::new(row, 0), "-"))
+                .collect::<Vec<_>>();
+            multibuffer.edit(edits, None, cx);
+            assert_eq!(multibuffer.read(cx).text(), "-bbb\n-ccc\n-ddd\n-ggg\n-hhh");
+
+            // An edit spanning multiple excerpts inserts its text into the first one.
+            multibuffer.edit([(13..17, "X"), (22..23, "Y")], None, cx);
+            assert_eq!(multibuffer.read(cx).text(), "-bbb\n-ccc\n-ddX\ngg\n-hYh");
+
+            // An edit that starts within the previous one, after the cursor has moved on to a
+            // later excerpt, is merged into it.
+            multibuffer.edit([(12..16, "Z"), (13..14, "W")], None, cx);
+            assert_eq!(multibuffer.read(cx).text(), "-bbb\n-ccc\n-dZ\ng\n-hYh");
+        });
+    }
+
+    #[gpui::test]
+    fn test_elided_excerpts(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(4, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let subscription = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.set_max_excerpt_len(Some(6));
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(2, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(3, 0)..Point::new(3, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer.subscribe()
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let excerpt_ids = snapshot.excerpts().map(|(id, _, _)| id).collect::<Vec<_>>();
+        assert_eq!(snapshot.text(), "aaa\nbb\nddd");
+        assert!(snapshot.is_elided(excerpt_ids[0]));
+        assert!(!snapshot.is_elided(excerpt_ids[1]));
+
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.expand_elided(excerpt_ids[0], cx)
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "aaa\nbbb\nccc\nddd");
+        assert!(!snapshot.is_elided(excerpt_ids[0]));
+        assert_eq!(
+            subscription.consume().into_inner(),
+            [Edit {
+                old: 6..6,
+                new: 6..11
+            }]
+        );
+    }
+
+    #[gpui::test]
+    fn test_excerpt_events(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(10, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(10, 3, 'm'),
+            )
+        });
+
+        let leader_multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let follower_multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let follower_edit_event_count = Arc::new(RwLock::new(0));
+
+        follower_multibuffer.update(cx, |_, cx| {
+            let follower_edit_event_count = follower_edit_event_count.clone();
+            cx.subscribe(
+                &leader_multibuffer,
+                move |follower, _, event, cx| match event.clone() {
+                    Event::ExcerptsAdded {
+                        buffer,
+                        predecessor,
+                        excerpts,
+                    } => follower.insert_excerpts_with_ids_after(predecessor, buffer, excerpts, cx),
+                    Event::ExcerptsRemoved { ids } => follower.remove_excerpts(ids, cx),
+                    Event::Edited { .. } => {
+                        *follower_edit_event_count.write() += 1;
+                    }
+                    _ => {}
+                },
+            )
+            .detach();
+        });
+
+        leader_multibuffer.update(cx, |leader, cx| {
+            leader.push_excerpts(
+                buffer_1.clone(),
+                [
+                    ExcerptRange {
+                        context: 0..8,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 12..16,
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            leader.insert_excerpts_after(
+                leader.excerpt_ids()[0],
+                buffer_2.clone(),
+                [
+                    ExcerptRange {
+                        context: 0..5,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 10..15,
+                        primary: None,
+                    },
+                ],
+                cx,
+            )
+        });
+        assert_eq!(
+            leader_multibuffer.read(cx).snapshot(cx).text(),
+            follower_multibuffer.read(cx).snapshot(cx).text(),
+        );
+        assert_eq!(*follower_edit_event_count.read(), 2);
+
+        leader_multibuffer.update(cx, |leader, cx| {
+            let excerpt_ids = leader.excerpt_ids();
+            leader.remove_excerpts([excerpt_ids[1], excerpt_ids[3]], cx);
+        });
+        assert_eq!(
+            leader_multibuffer.read(cx).snapshot(cx).text(),
+            follower_multibuffer.read(cx).snapshot(cx).text(),
+        );
+        assert_eq!(*follower_edit_event_count.read(), 3);
+
+        // Removing an empty set of excerpts is a noop.
+        leader_multibuffer.update(cx, |leader, cx| {
+            leader.remove_excerpts([], cx);
+        });
+        assert_eq!(
+            leader_multibuffer.read(cx).snapshot(cx).text(),
+            follower_multibuffer.read(cx).snapshot(cx).text(),
+        );
+        assert_eq!(*follower_edit_event_count.read(), 3);
+
+        // Adding an empty set of excerpts is a noop.
+        leader_multibuffer.update(cx, |leader, cx| {
+            leader.push_excerpts::<usize>(buffer_2.clone(), [], cx);
+        });
+        assert_eq!(
+            leader_multibuffer.read(cx).snapshot(cx).text(),
+            follower_multibuffer.read(cx).snapshot(cx).text(),
+        );
+        assert_eq!(*follower_edit_event_count.read(), 3);
+
+        leader_multibuffer.update(cx, |leader, cx| {
+            leader.clear(cx);
+        });
+        assert_eq!(
+            leader_multibuffer.read(cx).snapshot(cx).text(),
+            follower_multibuffer.read(cx).snapshot(cx).text(),
+        );
+        assert_eq!(*follower_edit_event_count.read(), 4);
+    }
+
+    #[gpui::test]
+    fn test_replication(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(10, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(10, 3, 'm'),
+            )
+        });
+
+        let leader = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        leader.update(cx, |leader, cx| {
+            leader.push_excerpts(
+                buffer_1.clone(),
+                [
+                    ExcerptRange {
+                        context: 0..8,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 12..16,
+                        primary: Some(13..14),
+                    },
+                ],
+                cx,
+            );
+            // The ids of these excerpts are greater than the one after them.
+            leader.insert_excerpts_after(
+                leader.excerpt_ids()[0],
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: 0..5,
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+
+        let excerpts = leader.read(cx).to_proto(cx);
+        let buffers = [buffer_1.clone(), buffer_2.clone()];
+        let follower = cx.new_model(|cx| {
+            MultiBuffer::from_proto(1, Capability::ReadWrite, excerpts.clone(), &buffers, cx)
+                .unwrap()
+        });
+        assert_eq!(
+            follower.read(cx).snapshot(cx).text(),
+            leader.read(cx).snapshot(cx).text()
+        );
+        assert_eq!(
+            follower.read(cx).excerpt_ids(),
+            leader.read(cx).excerpt_ids()
+        );
+        assert_eq!(follower.read(cx).to_proto(cx), leader.read(cx).to_proto(cx));
+
+        follower.update(cx, |_, cx| {
+            let buffers = buffers.clone();
+            cx.subscribe(&leader, move |follower, _, event, cx| {
+                if let Some(operation) = ExcerptOperation::from_event(event, cx) {
+                    follower
+                        .apply_excerpt_operation(
+                            operation,
+                            |buffer_id, cx| {
+                                buffers
+                                    .iter()
+                                    .find(|buffer| buffer.read(cx).remote_id() == buffer_id)
+                                    .cloned()
+                            },
+                            cx,
+                        )
+                        .unwrap();
+                }
+            })
+            .detach();
+        });
+
+        leader.update(cx, |leader, cx| {
+            let excerpt_ids = leader.excerpt_ids();
+            leader.insert_excerpts_after(
+                excerpt_ids[1],
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: 20..24,
+                    primary: None,
+                }],
+                cx,
+            );
+            leader.remove_excerpts([excerpt_ids[0]], cx);
+        });
+        assert_eq!(
+            follower.read(cx).snapshot(cx).text(),
+            leader.read(cx).snapshot(cx).text()
+        );
+        assert_eq!(
+            follower.read(cx).excerpt_ids(),
+            leader.read(cx).excerpt_ids()
+        );
+
+        // A replica that missed some operations can catch up with a diff of the excerpts.
+        let state = leader.read(cx).to_proto(cx);
+        let stale_follower = cx.new_model(|cx| {
+            let mut stale_follower =
+                MultiBuffer::from_proto(2, Capability::ReadWrite, excerpts, &buffers, cx).unwrap();
+            let operation = stale_follower.excerpt_operation_to(&state);
+            assert_eq!(operation.deleted_excerpts.len(), 1);
+            assert_eq!(operation.inserted_excerpts.len(), 1);
+            stale_follower
+                .apply_excerpt_operation(
+                    operation,
+                    |buffer_id, cx| {
+                        buffers
+                            .iter()
+                            .find(|buffer| buffer.read(cx).remote_id() == buffer_id)
+                            .cloned()
+                    },
+                    cx,
+                )
+                .unwrap();
+            stale_follower
+        });
+        assert_eq!(stale_follower.read(cx).to_proto(cx), state);
+    }
+
+    #[gpui::test]
+    fn test_remove_emptied_excerpts(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.set_remove_emptied_excerpts(true);
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(1, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(3, 0)..Point::new(4, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            )
+        });
+        assert_eq!(multibuffer.read(cx).text(), "aaa\nbbb\nddd\neee");
+
+        let removed_excerpt_ids = Arc::new(RwLock::new(Vec::new()));
+        cx.subscribe(&multibuffer, {
+            let removed_excerpt_ids = removed_excerpt_ids.clone();
+            move |_, event, _| {
+                if let Event::ExcerptsRemoved { ids } = event {
+                    removed_excerpt_ids.write().extend(ids.iter().copied());
+                }
+            }
+        })
+        .detach();
+
+        // Deleting part of an excerpt keeps it.
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(Point::new(0, 0)..Point::new(1, 0), "")], None, cx)
+        });
+        assert_eq!(multibuffer.read(cx).text(), "bbb\nddd\neee");
+        assert!(removed_excerpt_ids.read().is_empty());
+
+        // Deleting all of an excerpt's content removes it.
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(Point::new(2, 0)..Point::new(3, 3), "")], None, cx)
+        });
+        assert_eq!(multibuffer.read(cx).text(), "bbb");
+        assert_eq!(*removed_excerpt_ids.read(), [excerpt_ids[1]]);
+    }
+
+    #[gpui::test]
+    fn test_excerpt_edges(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(3, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            let range = ExcerptRange {
+                context: Point::new(1, 0)..Point::new(1, 3),
+                primary: None,
+            };
+            multibuffer.push_excerpts(buffer.clone(), [range.clone()], cx);
+            multibuffer.set_excerpt_edges(ExcerptEdges::Exclusive);
+            multibuffer.push_excerpts(buffer.clone(), [range], cx);
+        });
+        assert_eq!(multibuffer.read(cx).text(), "bbb\nbbb");
+
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit(
+                [
+                    (Point::new(1, 0)..Point::new(1, 0), "X"),
+                    (Point::new(1, 3)..Point::new(1, 3), "Y"),
+                ],
+                None,
+                cx,
+            )
+        });
+        assert_eq!(multibuffer.read(cx).text(), "XbbbY\nbbb");
+    }
+
+    #[gpui::test]
+    fn test_chunks_with_whitespace(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "  fn a() {\n\tb  \n}",
+            )
+        });
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+
+        let whitespace_chunks = |offset: usize, cx: &AppContext| {
+            let snapshot = multibuffer.read(cx).snapshot(cx);
+            snapshot
+                .chunks_with_whitespace(offset..snapshot.len(), false)
+                .filter_map(|chunk| Some((chunk.text, chunk.whitespace?, chunk.is_tab)))
+                .map(|(text, position, is_tab)| (text.to_string(), position, is_tab))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            whitespace_chunks(0, cx),
+            [
+                ("  ".into(), WhitespacePosition::Leading, false),
+                (" ".into(), WhitespacePosition::Inner, false),
+                (" ".into(), WhitespacePosition::Inner, false),
+                ("\t".into(), WhitespacePosition::Leading, true),
+                ("  ".into(), WhitespacePosition::Trailing, false),
+            ]
+        );
+
+        // Chunks starting in the middle of a line account for the text before them.
+        assert_eq!(
+            whitespace_chunks(4, cx)[0],
+            (" ".into(), WhitespacePosition::Inner, false)
+        );
+
+        // Trailing whitespace introduced by edits is marked.
+        buffer.update(cx, |buffer, cx| buffer.edit([(17..17, " ")], None, cx));
+        assert_eq!(
+            whitespace_chunks(0, cx).last().unwrap(),
+            &(" ".into(), WhitespacePosition::Trailing, false)
+        );
+
+        // Seeking into the part of a chunk that was split off at whitespace yields the same
+        // chunks as starting there.
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let whitespace_chunks = |chunks: MultiBufferChunks| {
+            chunks
+                .map(|chunk| (chunk.text.to_string(), chunk.whitespace))
+                .collect::<Vec<_>>()
+        };
+        let mut chunks = snapshot.chunks_with_whitespace(0..snapshot.len(), false);
+        assert_eq!(chunks.next().unwrap().text, "  ");
+        chunks.seek(1);
+        assert_eq!(
+            whitespace_chunks(chunks),
+            whitespace_chunks(snapshot.chunks_with_whitespace(1..snapshot.len(), false))
+        );
+        let mut chunks = snapshot.chunks_with_whitespace(0..snapshot.len(), false);
+        chunks.next();
+        chunks.seek(5);
+        assert_eq!(chunks.offset(), 5);
+        assert_eq!(
+            whitespace_chunks(chunks),
+            whitespace_chunks(snapshot.chunks_with_whitespace(5..snapshot.len(), false))
+        );
+
+        // Other chunks are unaffected.
+        assert_eq!(
+            snapshot
+                .chunks_with_whitespace(0..snapshot.len(), false)
+                .map(|chunk| chunk.text)
+                .collect::<String>(),
+            snapshot.text()
+        );
+
+        // Whitespace is classified by the excerpt's text, ignoring the rest of the buffer.
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "a  b  c",
+            )
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer,
+                [
+                    ExcerptRange {
+                        context: 0..2,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 4..7,
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "a \n  c");
+        let whitespace_chunks = |offset: usize| {
+            snapshot
+                .chunks_with_whitespace(offset..snapshot.len(), false)
+                .filter_map(|chunk| Some((chunk.text.to_string(), chunk.whitespace?)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            whitespace_chunks(0),
+            [
+                (" ".into(), WhitespacePosition::Trailing),
+                ("  ".into(), WhitespacePosition::Leading),
+            ]
+        );
+        assert_eq!(
+            whitespace_chunks(4),
+            [(" ".into(), WhitespacePosition::Leading)]
+        );
+    }
+
+    #[gpui::test]
+    fn test_header_for_row(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'm'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            let mut excerpt_ids = multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(1, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(3, 0)..Point::new(4, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            excerpt_ids.extend(multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: Point::new(0, 0)..Point::new(2, 3),
+                    primary: None,
+                }],
+                cx,
+            ));
+            excerpt_ids
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "aaa\nbbb\nddd\neee\nmmm\nnnn\nooo");
+        let headers = (0..8)
+            .map(|row| {
+                let header = snapshot.header_for_row(row).unwrap();
+                (
+                    header.boundary.id,
+                    header.boundary.row,
+                    header.boundary.starts_new_buffer,
+                    header.next_header_row,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            [
+                (excerpt_ids[0], 0, true, Some(2)),
+                (excerpt_ids[0], 0, true, Some(2)),
+                (excerpt_ids[1], 2, false, Some(4)),
+                (excerpt_ids[1], 2, false, Some(4)),
+                (excerpt_ids[2], 4, true, None),
+                (excerpt_ids[2], 4, true, None),
+                (excerpt_ids[2], 4, true, None),
+                (excerpt_ids[2], 4, true, None),
+            ]
+        );
+
+        let singleton = cx.new_model(|cx| MultiBuffer::singleton(buffer_1, cx));
+        assert!(singleton.read(cx).snapshot(cx).header_for_row(0).is_none());
+    }
+
+    #[gpui::test]
+    fn test_marker_summaries(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(12, 3, 'a'),
+            )
+        });
+        buffer.update(cx, |buffer, cx| {
+            let diagnostics = [
+                (1, DiagnosticSeverity::WARNING),
+                (3, DiagnosticSeverity::ERROR),
+                (4, DiagnosticSeverity::WARNING),
+            ]
+            .map(|(row, severity)| DiagnosticEntry {
+                range: PointUtf16::new(row, 0)..PointUtf16::new(row, 2),
+                diagnostic: language::Diagnostic {
+                    severity,
+                    ..Default::default()
+                },
+            });
+            let diagnostics = language::DiagnosticSet::new(diagnostics, buffer);
+            buffer.update_diagnostics(lsp::LanguageServerId(0), diagnostics, cx);
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(4, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(7, 0)..Point::new(11, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let search_matches = [5, 6, 9].map(|row| {
+            snapshot.anchor_before(Point::new(row, 0))..snapshot.anchor_after(Point::new(row, 3))
+        });
+        multibuffer.update(cx, |multibuffer, cx| {
+            let head = snapshot.anchor_after(Point::new(8, 1));
+            let selection = Selection {
+                id: 0,
+                start: head,
+                end: head,
+                reversed: false,
+                goal: language::SelectionGoal::None,
+            };
+            multibuffer.set_local_selections(vec![selection].into(), cx);
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.max_point().row, 9);
+        let summaries = snapshot.marker_summaries(5, &search_matches);
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|summary| summary.diagnostic_severity)
+                .collect::<Vec<_>>(),
+            [
+                Some(DiagnosticSeverity::WARNING),
+                Some(DiagnosticSeverity::ERROR),
+                Some(DiagnosticSeverity::WARNING),
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|summary| summary.search_match_count)
+                .collect::<Vec<_>>(),
+            [0, 0, 1, 1, 1]
+        );
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|summary| summary.selection_count)
+                .collect::<Vec<_>>(),
+            [0, 0, 0, 0, 1]
+        );
+        assert!(snapshot.marker_summaries(0, &search_matches).is_empty());
+    }
+
+    #[gpui::test]
+    fn test_minimap_runs(cx: &mut AppContext) {
+        let text = "ab cd  ef\nxxxxxxxxxxxxxxxxxxxx\n\n  gh";
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+
+        let runs = |rows: Range<u32>| {
+            snapshot
+                .minimap_runs(rows, 2, 8)
+                .map(|run| (run.row, run.columns))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(runs(0..4), [(0, 0..2), (0, 3..8), (1, 0..8), (3, 2..4)]);
+        assert_eq!(runs(1..3), [(1, 0..8)]);
+        assert_eq!(runs(3..10), [(3, 2..4)]);
+    }
+
+    #[gpui::test]
+    fn test_column_ranges(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "αβcdef\nab\n\nabcdefgh",
+            )
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(1, 2),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(2, 0)..Point::new(3, 8),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let ranges = |include_short_lines: bool| {
+            snapshot
+                .column_ranges(
+                    PointUtf16::new(3, 4),
+                    PointUtf16::new(0, 1),
+                    include_short_lines,
+                )
+                .into_iter()
+                .map(|column_range| (column_range.range, column_range.padding))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ranges(false),
+            [
+                (Point::new(0, 2)..Point::new(0, 6), 0),
+                (Point::new(1, 1)..Point::new(1, 2), 0),
+                (Point::new(3, 1)..Point::new(3, 4), 0),
+            ]
+        );
+        assert_eq!(
+            ranges(true),
+            [
+                (Point::new(0, 2)..Point::new(0, 6), 0),
+                (Point::new(1, 1)..Point::new(1, 2), 0),
+                (Point::new(2, 0)..Point::new(2, 0), 1),
+                (Point::new(3, 1)..Point::new(3, 4), 0),
+            ]
+        );
+        assert!(snapshot
+            .column_ranges(PointUtf16::new(3, 4), PointUtf16::new(0, 1), false)
+            .iter()
+            .all(|column_range| column_range.reversed));
+
+        let column_ranges =
+            snapshot.column_ranges(PointUtf16::new(0, 1), PointUtf16::new(3, 4), true);
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.edit_columns(&column_ranges, iter::repeat("X"), cx)
+        });
+        assert_eq!(buffer.read(cx).text(), "αXef\naX\n X\naXefgh");
+    }
+
+    #[gpui::test]
+    fn test_range_to_delete_rows(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer,
+                [
+                    ExcerptRange {
+                        context: Point::new(0, 0)..Point::new(2, 3),
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: Point::new(4, 0)..Point::new(4, 3),
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            multibuffer
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "aaa\nbbb\nccc\neee");
+
+        assert_eq!(
+            snapshot.range_to_delete_rows(0..=1),
+            Point::new(0, 0)..Point::new(2, 0)
+        );
+        // The newline before the last row of an excerpt is removed instead of the one after it.
+        assert_eq!(
+            snapshot.range_to_delete_rows(1..=2),
+            Point::new(0, 3)..Point::new(2, 3)
+        );
+        // A row that's alone in its excerpt can only be cleared.
+        assert_eq!(
+            snapshot.range_to_delete_rows(3..=3),
+            Point::new(3, 0)..Point::new(3, 3)
+        );
+    }
+
+    #[gpui::test]
+    fn test_jump_target_for(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'm'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: Point::new(2, 0)..Point::new(3, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: Point::new(4, 0)..Point::new(5, 3),
+                    primary: None,
+                }],
+                cx,
+            );
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "ccc\nddd\nqqq\nrrr");
+        let buffer_1_id = buffer_1.read(cx).remote_id();
+        let buffer_2_id = buffer_2.read(cx).remote_id();
+        let jump_target = |point| {
+            let (buffer_id, point, file) = snapshot.jump_target_for(point).unwrap();
+            assert!(file.is_none());
+            (buffer_id, point)
+        };
+        assert_eq!(
+            jump_target(Point::new(1, 2)),
+            (buffer_1_id, Point::new(3, 2))
+        );
+        assert_eq!(
+            jump_target(Point::new(2, 1)),
+            (buffer_2_id, Point::new(4, 1))
+        );
+    }
+
+    #[gpui::test]
+    fn test_excerpt_annotations(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_annotated_excerpts(
+                buffer.clone(),
+                [
+                    (
+                        ExcerptRange {
+                            context: Point::new(0, 0)..Point::new(1, 3),
+                            primary: None,
+                        },
+                        ExcerptAnnotation::new(2_usize),
+                    ),
+                    (
+                        ExcerptRange {
+                            context: Point::new(3, 0)..Point::new(4, 3),
+                            primary: None,
+                        },
+                        ExcerptAnnotation::new(5_usize),
+                    ),
+                ],
+                cx,
+            )
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.excerpt_annotation(excerpt_ids[0]), Some(&2_usize));
+        assert_eq!(snapshot.excerpt_annotation(excerpt_ids[1]), Some(&5_usize));
+        assert_eq!(snapshot.excerpt_annotation::<u32>(excerpt_ids[1]), None);
+
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.set_excerpt_annotation(excerpt_ids[0], ExcerptAnnotation::new(3_usize), cx);
+            multibuffer.remove_excerpts([excerpt_ids[1]], cx);
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.excerpt_annotation(excerpt_ids[0]), Some(&3_usize));
+        assert_eq!(snapshot.excerpt_annotation::<usize>(excerpt_ids[1]), None);
+    }
+
+    #[gpui::test]
+    fn test_push_excerpts_with_context_lines(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(20, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let anchor_ranges = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts_with_context_lines(
+                buffer.clone(),
+                vec![
+                    Point::new(3, 2)..Point::new(4, 2),
+                    Point::new(7, 1)..Point::new(7, 3),
+                    Point::new(15, 0)..Point::new(15, 0),
+                ],
+                2,
+                cx,
+            )
+        });
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(
+            snapshot.text(),
+            "bbb\nccc\nddd\neee\nfff\nggg\nhhh\niii\njjj\n\nnnn\nooo\nppp\nqqq\nrrr\n"
+        );
+
+        assert_eq!(
+            anchor_ranges
+                .iter()
+                .map(|range| range.to_point(&snapshot))
+                .collect::<Vec<_>>(),
+            vec![
+                Point::new(2, 2)..Point::new(3, 2),
+                Point::new(6, 1)..Point::new(6, 3),
+                Point::new(12, 0)..Point::new(12, 0)
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_stream_excerpts_with_context_lines(cx: &mut TestAppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(20, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let anchor_ranges = multibuffer.update(cx, |multibuffer, cx| {
+            let snapshot = buffer.read(cx);
+            let ranges = vec![
+                snapshot.anchor_before(Point::new(3, 2))..snapshot.anchor_before(Point::new(4, 2)),
+                snapshot.anchor_before(Point::new(7, 1))..snapshot.anchor_before(Point::new(7, 3)),
+                snapshot.anchor_before(Point::new(15, 0))
+                    ..snapshot.anchor_before(Point::new(15, 0)),
+            ];
+            multibuffer.stream_excerpts_with_context_lines(buffer.clone(), ranges, 2, cx)
+        });
+
+        let anchor_ranges = anchor_ranges.collect::<Vec<_>>().await;
+
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert_eq!(
+            snapshot.text(),
+            "bbb\nccc\nddd\neee\nfff\nggg\nhhh\niii\njjj\n\nnnn\nooo\nppp\nqqq\nrrr\n"
+        );
+
+        assert_eq!(
+            anchor_ranges
+                .iter()
+                .map(|range| range.to_point(&snapshot))
+                .collect::<Vec<_>>(),
+            vec![
+                Point::new(2, 2)..Point::new(3, 2),
+                Point::new(6, 1)..Point::new(6, 3),
+                Point::new(12, 0)..Point::new(12, 0)
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_push_excerpts_in_background(cx: &mut TestAppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                sample_text(6, 3, 'a'),
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            let snapshot = buffer.read(cx);
+            let ranges = [0..1, 3..3, 4..5]
+                .into_iter()
+                .map(|rows| ExcerptRange {
+                    context: snapshot.anchor_before(Point::new(rows.start, 0))
+                        ..snapshot.anchor_after(Point::new(rows.end, 3)),
+                    primary: None,
+                })
+                .collect();
+            multibuffer.push_excerpts_in_background(buffer.clone(), ranges, cx)
+        });
+        let excerpt_ids = excerpt_ids.await;
+        assert_eq!(excerpt_ids.len(), 3);
+
+        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
+        assert_eq!(snapshot.text(), "aaa\nbbb\nddd\neee\nfff");
+        assert_eq!(
+            snapshot.excerpts().map(|(id, _, _)| id).collect::<Vec<_>>(),
+            excerpt_ids
+        );
+    }
+
+    #[gpui::test]
+    fn test_empty_multibuffer(cx: &mut AppContext) {
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "");
+        assert_eq!(snapshot.buffer_rows(0).collect::<Vec<_>>(), &[Some(0)]);
+        assert_eq!(snapshot.buffer_rows(1).collect::<Vec<_>>(), &[]);
+    }
+
+    #[gpui::test]
+    fn test_singleton_multibuffer_anchors(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
+        });
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        let old_snapshot = multibuffer.read(cx).snapshot(cx);
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "X")], None, cx);
+            buffer.edit([(5..5, "Y")], None, cx);
+        });
+        let new_snapshot = multibuffer.read(cx).snapshot(cx);
+
+        assert_eq!(old_snapshot.text(), "abcd");
+        assert_eq!(new_snapshot.text(), "XabcdY");
+
+        assert_eq!(old_snapshot.anchor_before(0).to_offset(&new_snapshot), 0);
+        assert_eq!(old_snapshot.anchor_after(0).to_offset(&new_snapshot), 1);
+        assert_eq!(old_snapshot.anchor_before(4).to_offset(&new_snapshot), 5);
+        assert_eq!(old_snapshot.anchor_after(4).to_offset(&new_snapshot), 6);
+    }
+
+    #[gpui::test]
+    fn test_multibuffer_anchors(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "efghi")
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: 0..4,
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: 0..5,
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer
+        });
+        let old_snapshot = multibuffer.read(cx).snapshot(cx);
+
+        assert_eq!(old_snapshot.anchor_before(0).to_offset(&old_snapshot), 0);
+        assert_eq!(old_snapshot.anchor_after(0).to_offset(&old_snapshot), 0);
+        assert_eq!(Anchor::min().to_offset(&old_snapshot), 0);
+        assert_eq!(Anchor::min().to_offset(&old_snapshot), 0);
+        assert_eq!(Anchor::max().to_offset(&old_snapshot), 10);
+        assert_eq!(Anchor::max().to_offset(&old_snapshot), 10);
+
+        buffer_1.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "W")], None, cx);
+            buffer.edit([(5..5, "X")], None, cx);
+        });
+        buffer_2.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "Y")], None, cx);
+            buffer.edit([(6..6, "Z")], None, cx);
+        });
+        let new_snapshot = multibuffer.read(cx).snapshot(cx);
+
+        assert_eq!(old_snapshot.text(), "abcd\nefghi");
+        assert_eq!(new_snapshot.text(), "WabcdX\nYefghiZ");
+
+        assert_eq!(old_snapshot.anchor_before(0).to_offset(&new_snapshot), 0);
+        assert_eq!(old_snapshot.anchor_after(0).to_offset(&new_snapshot), 1);
+        assert_eq!(old_snapshot.anchor_before(1).to_offset(&new_snapshot), 2);
+        assert_eq!(old_snapshot.anchor_after(1).to_offset(&new_snapshot), 2);
+        assert_eq!(old_snapshot.anchor_before(2).to_offset(&new_snapshot), 3);
+        assert_eq!(old_snapshot.anchor_after(2).to_offset(&new_snapshot), 3);
+        assert_eq!(old_snapshot.anchor_before(5).to_offset(&new_snapshot), 7);
+        assert_eq!(old_snapshot.anchor_after(5).to_offset(&new_snapshot), 8);
+        assert_eq!(old_snapshot.anchor_before(10).to_offset(&new_snapshot), 13);
+        assert_eq!(old_snapshot.anchor_after(10).to_offset(&new_snapshot), 14);
+
+        let excerpt_ids = new_snapshot
+            .excerpts()
+            .map(|(id, _, _)| id)
+            .collect::<Vec<_>>();
+        let edge_offsets = excerpt_ids
+            .iter()
+            .map(|id| {
+                (
+                    new_snapshot
+                        .anchor_at_excerpt_start(*id)
+                        .unwrap()
+                        .to_offset(&new_snapshot),
+                    new_snapshot
+                        .anchor_at_excerpt_end(*id)
+                        .unwrap()
+                        .to_offset(&new_snapshot),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(edge_offsets, [(0, 6), (7, 14)]);
+
+        assert_eq!(
+            old_snapshot.anchor_after(8).to_buffer_point(&new_snapshot),
+            Some((buffer_2.read(cx).remote_id(), Point::new(0, 4)))
+        );
+        assert_eq!(
+            Anchor::max().to_buffer_offset(&new_snapshot),
+            Some((buffer_2.read(cx).remote_id(), 7))
+        );
+
+        assert_eq!(
+            new_snapshot.summaries_for_unsorted_anchors::<usize, _>(&[
+                old_snapshot.anchor_after(10),
+                old_snapshot.anchor_before(1),
+                old_snapshot.anchor_after(5),
+                old_snapshot.anchor_before(1),
+            ]),
+            [14, 2, 8, 2]
+        );
+    }
+
+    #[gpui::test]
+    fn test_resolving_anchors_after_replacing_their_excerpts(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "ABCDEFGHIJKLMNOP",
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+
+        // Create an insertion id in buffer 1 that doesn't exist in buffer 2.
+        // Add an excerpt from buffer 1 that spans this new insertion.
+        buffer_1.update(cx, |buffer, cx| buffer.edit([(4..4, "123")], None, cx));
+        let excerpt_id_1 = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer
+                .push_excerpts(
+                    buffer_1.clone(),
+                    [ExcerptRange {
+                        context: 0..7,
+                        primary: None,
+                    }],
+                    cx,
+                )
+                .pop()
+                .unwrap()
+        });
+
+        let snapshot_1 = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot_1.text(), "abcd123");
+
+        // Replace the buffer 1 excerpt with new excerpts from buffer 2.
+        let (excerpt_id_2, excerpt_id_3) = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.remove_excerpts([excerpt_id_1], cx);
+            let mut ids = multibuffer
+                .push_excerpts(
+                    buffer_2.clone(),
+                    [
+                        ExcerptRange {
+                            context: 0..4,
+                            primary: None,
+                        },
+                        ExcerptRange {
+                            context: 6..10,
+                            primary: None,
+                        },
+                        ExcerptRange {
+                            context: 12..16,
+                            primary: None,
+                        },
+                    ],
+                    cx,
+                )
+                .into_iter();
+            (ids.next().unwrap(), ids.next().unwrap())
+        });
+        let snapshot_2 = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot_2.text(), "ABCD\nGHIJ\nMNOP");
+
+        // The old excerpt id doesn't get reused.
+        assert_ne!(excerpt_id_2, excerpt_id_1);
+
+        // Resolve some anchors from the previous snapshot in the new snapshot.
+        // The current excerpts are from a different buffer, so we don't attempt to
+        // resolve the old text anchor in the new buffer.
+        assert_eq!(
+            snapshot_2.summary_for_anchor::<usize>(&snapshot_1.anchor_before(2)),
+            0
+        );
+        assert_eq!(
+            snapshot_2.summaries_for_anchors::<usize, _>(&[
+                snapshot_1.anchor_before(2),
+                snapshot_1.anchor_after(3)
+            ]),
+            vec![0, 0]
+        );
+
+        // Refresh anchors from the old snapshot. The return value indicates that both
+        // anchors lost their original excerpt.
+        let refresh =
+            snapshot_2.refresh_anchors(&[snapshot_1.anchor_before(2), snapshot_1.anchor_after(3)]);
+        assert_eq!(
+            refresh,
+            &[
+                (0, snapshot_2.anchor_before(0), false),
+                (1, snapshot_2.anchor_after(0), false),
+            ]
+        );
+
+        // Replace the middle excerpt with a smaller excerpt in buffer 2,
+        // that intersects the old excerpt.
+        let excerpt_id_5 = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.remove_excerpts([excerpt_id_3], cx);
+            multibuffer
+                .insert_excerpts_after(
+                    excerpt_id_2,
+                    buffer_2.clone(),
+                    [ExcerptRange {
+                        context: 5..8,
+                        primary: None,
+                    }],
+                    cx,
+                )
+                .pop()
+                .unwrap()
+        });
+
+        let snapshot_3 = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot_3.text(), "ABCD\nFGH\nMNOP");
+        assert_ne!(excerpt_id_5, excerpt_id_3);
+
+        // Resolve some anchors from the previous snapshot in the new snapshot.
+        // The third anchor can't be resolved, since its excerpt has been removed,
+        // so it resolves to the same position as its predecessor.
+        let anchors = [
+            snapshot_2.anchor_before(0),
+            snapshot_2.anchor_after(2),
+            snapshot_2.anchor_after(6),
+            snapshot_2.anchor_after(14),
+        ];
+        assert_eq!(
+            snapshot_3.summaries_for_anchors::<usize, _>(&anchors),
+            &[0, 2, 9, 13]
+        );
+        assert_eq!(
+            snapshot_3.can_resolve_all(&anchors),
+            &[true, true, false, true]
+        );
+        assert_eq!(
+            snapshot_3.validate_anchors(&anchors),
+            &[(2, StaleAnchorReason::ExcerptRemoved)]
+        );
+
+        let new_anchors = snapshot_3.refresh_anchors(&anchors);
+        assert_eq!(
+            new_anchors.iter().map(|a| (a.0, a.2)).collect::<Vec<_>>(),
+            &[(0, true), (1, true), (2, true), (3, true)]
+        );
+        assert_eq!(
+            snapshot_3.summaries_for_anchors::<usize, _>(new_anchors.iter().map(|a| &a.1)),
+            &[0, 2, 7, 13]
+        );
+
+        // Refreshing unsorted and duplicated anchors preserves their input order.
+        let new_anchors =
+            snapshot_3.refresh_anchors(&[anchors[3], anchors[2], anchors[0], anchors[2]]);
+        assert_eq!(
+            new_anchors.iter().map(|a| (a.0, a.2)).collect::<Vec<_>>(),
+            &[(0, true), (1, true), (2, true), (3, true)]
+        );
+        assert_eq!(
+            snapshot_3.summaries_for_unsorted_anchors::<usize, _>(new_anchors.iter().map(|a| &a.1)),
+            &[13, 7, 0, 7]
+        );
+    }
+
+    #[gpui::test(iterations = 100)]
+    fn test_random_excerpt_convergence(cx: &mut AppContext, mut rng: StdRng) {
+        let operations = env::var("OPERATIONS")
+            .map(|i| i.parse().expect("invalid `OPERATIONS` variable"))
+            .unwrap_or(10);
+        let replica_count = rng.gen_range(2..=4);
+
+        let buffers = (0..3)
+            .map(|ix| {
+                cx.new_model(|cx| {
+                    Buffer::new(
+                        0,
+                        BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                        sample_text(10, 4, (b'a' + ix) as char),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Each replica records the excerpt operations it emits, so that local operations can
+        // be broadcast and the echoes of remote ones discarded.
+        let mut replicas = Vec::new();
+        let mut emitted_operations = Vec::new();
+        for replica_id in 0..replica_count {
+            let replica =
+                cx.new_model(|_| MultiBuffer::new(replica_id as ReplicaId, Capability::ReadWrite));
+            let operations = Arc::new(Mutex::new(Vec::new()));
+            cx.subscribe(&replica, {
+                let operations = operations.clone();
+                move |_, event, cx| {
+                    if let Some(operation) = ExcerptOperation::from_event(event, cx) {
+                        operations.lock().push(operation);
+                    }
+                }
+            })
+            .detach();
+            replicas.push(replica);
+            emitted_operations.push(operations);
+        }
+
+        // Operations are delivered in causal order: an operation is only applied once the
+        // receiver has applied everything its sender had applied when generating it.
+        let mut operation_log = vec![Vec::<(ExcerptOperation, Vec<usize>)>::new(); replica_count];
+        let mut applied = vec![vec![0; replica_count]; replica_count];
+        for _ in 0..operations {
+            let replica_ix = rng.gen_range(0..replica_count);
+            if rng.gen_bool(0.5) {
+                let multibuffer = &replicas[replica_ix];
+                let excerpt_ids = multibuffer.read(cx).excerpt_ids();
+                multibuffer.update(cx, |multibuffer, cx| {
+                    if !excerpt_ids.is_empty() && rng.gen_bool(0.3) {
+                        let id = *excerpt_ids.choose(&mut rng).unwrap();
+                        log::info!("replica {replica_ix}: removing excerpt {id:?}");
+                        multibuffer.remove_excerpts([id], cx);
+                    } else {
+                        let prev_excerpt_id = excerpt_ids
+                            .choose(&mut rng)
+                            .copied()
+                            .filter(|_| rng.gen())
+                            .unwrap_or(ExcerptId::max());
+                        let buffer = buffers.choose(&mut rng).unwrap().clone();
+                        let end = rng.gen_range(0..=buffer.read(cx).len());
+                        let start = rng.gen_range(0..=end);
+                        log::info!(
+                            "replica {replica_ix}: inserting {start}..{end} after {prev_excerpt_id:?}"
+                        );
+                        multibuffer.insert_excerpts_after(
+                            prev_excerpt_id,
+                            buffer,
+                            [ExcerptRange {
+                                context: start..end,
+                                primary: None,
+                            }],
+                            cx,
+                        );
+                    }
+                });
+                let clock = applied[replica_ix].clone();
+                for operation in emitted_operations[replica_ix].lock().drain(..) {
+                    operation_log[replica_ix].push((operation, clock.clone()));
+                    applied[replica_ix][replica_ix] += 1;
+                }
+            }
+
+            // Deliver some of the pending operations.
+            for receiver_ix in 0..replica_count {
+                for sender_ix in 0..replica_count {
+                    if receiver_ix == sender_ix || rng.gen_bool(0.5) {
+                        continue;
+                    }
+                    deliver_ready_operations(
+                        receiver_ix,
+                        sender_ix,
+                        &replicas,
+                        &buffers,
+                        &operation_log,
+                        &mut applied,
+                        &emitted_operations,
+                        cx,
+                    );
+                }
+            }
+        }
+
+        // Deliver everything that remains.
+        loop {
+            let mut delivered = false;
+            for receiver_ix in 0..replica_count {
+                for sender_ix in 0..replica_count {
+                    if receiver_ix != sender_ix {
+                        delivered |= deliver_ready_operations(
+                            receiver_ix,
+                            sender_ix,
+                            &replicas,
+                            &buffers,
+                            &operation_log,
+                            &mut applied,
+                            &emitted_operations,
+                            cx,
+                        );
+                    }
+                }
+            }
+            if !delivered {
+                break;
+            }
+        }
+
+        let first_replica = replicas[0].read(cx);
+        for replica in &replicas[1..] {
+            let replica = replica.read(cx);
+            assert_eq!(replica.excerpt_ids(), first_replica.excerpt_ids());
+            assert_eq!(
+                replica.snapshot(cx).text(),
+                first_replica.snapshot(cx).text()
+            );
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn deliver_ready_operations(
+            receiver_ix: usize,
+            sender_ix: usize,
+            replicas: &[Model<MultiBuffer>],
+            buffers: &[Model<Buffer>],
+            operation_log: &[Vec<(ExcerptOperation, Vec<usize>)>],
+            applied: &mut [Vec<usize>],
+            emitted_operations: &[Arc<Mutex<Vec<ExcerptOperation>>>],
+            cx: &mut AppContext,
+        ) -> bool {
+            let mut delivered = false;
+            while let Some((operation, clock)) =
+                operation_log[sender_ix].get(applied[receiver_ix][sender_ix])
+            {
+                let is_ready = clock
+                    .iter()
+                    .enumerate()
+                    .all(|(ix, count)| ix == sender_ix || applied[receiver_ix][ix] >= *count);
+                if !is_ready {
+                    break;
+                }
+                replicas[receiver_ix].update(cx, |multibuffer, cx| {
+                    multibuffer
+                        .apply_excerpt_operation(
+                            operation.clone(),
+                            |buffer_id, cx| {
+                                buffers
+                                    .iter()
+                                    .find(|buffer| buffer.read(cx).remote_id() == buffer_id)
+                                    .cloned()
+                            },
+                            cx,
+                        )
+                        .unwrap();
+                });
+                emitted_operations[receiver_ix].lock().clear();
+                applied[receiver_ix][sender_ix] += 1;
+                delivered = true;
+            }
+            delivered
+        }
+    }
+
+    #[gpui::test(iterations = 100)]
+    fn test_random_multibuffer(cx: &mut AppContext, mut rng: StdRng) {
+        let operations = env::var("OPERATIONS")
+            .map(|i| i.parse().expect("invalid `OPERATIONS` variable"))
+            .unwrap_or(10);
+
+        let mut buffers: Vec<Model<Buffer>> = Vec::new();
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let mut excerpt_ids = Vec::<ExcerptId>::new();
+        let mut expected_excerpts = Vec::<(Model<Buffer>, Range<text::Anchor>)>::new();
+        let mut anchors = Vec::new();
+        let mut old_versions = Vec::new();
+
+        for _ in 0..operations {
+            match rng.gen_range(0..100) {
+                0..=19 if !buffers.is_empty() => {
+                    let buffer = buffers.choose(&mut rng).unwrap();
+                    buffer.update(cx, |buf, cx| buf.randomly_edit(&mut rng, 5, cx));
+                }
+                20..=29 if !expected_excerpts.is_empty() => {
+                    let mut ids_to_remove = vec![];
+                    for _ in 0..rng.gen_range(1..=3) {
+                        if expected_excerpts.is_empty() {
+                            break;
+                        }
+
+                        let ix = rng.gen_range(0..expected_excerpts.len());
+                        ids_to_remove.push(excerpt_ids.remove(ix));
+                        let (buffer, range) = expected_excerpts.remove(ix);
+                        let buffer = buffer.read(cx);
+                        log::info!(
+                            "Removing excerpt {}: {:?}",
+                            ix,
+                            buffer
+                                .text_for_range(range.to_offset(buffer))
+                                .collect::<String>(),
+                        );
+                    }
+                    let snapshot = multibuffer.read(cx).read(cx);
+                    ids_to_remove.sort_unstable_by(|a, b| a.cmp(&b, &snapshot));
+                    drop(snapshot);
+                    multibuffer.update(cx, |multibuffer, cx| {
+                        multibuffer.remove_excerpts(ids_to_remove, cx)
+                    });
+                }
+                30..=39 if !expected_excerpts.is_empty() => {
+                    let multibuffer = multibuffer.read(cx).read(cx);
+                    let offset =
+                        multibuffer.clip_offset(rng.gen_range(0..=multibuffer.len()), Bias::Left);
+                    let bias = if rng.gen() { Bias::Left } else { Bias::Right };
+                    log::info!("Creating anchor at {} with bias {:?}", offset, bias);
+                    anchors.push(multibuffer.anchor_at(offset, bias));
+                    anchors.sort_by(|a, b| a.cmp(b, &multibuffer));
+                }
+                40..=44 if !anchors.is_empty() => {
+                    let multibuffer = multibuffer.read(cx).read(cx);
+                    let prev_len = anchors.len();
+                    anchors = multibuffer
+                        .refresh_anchors(&anchors)
+                        .into_iter()
+                        .map(|a| a.1)
+                        .collect();
+                    anchors.sort_by(|a, b| a.cmp(b, &multibuffer));
+
+                    // Ensure the newly-refreshed anchors point to a valid excerpt and don't
+                    // overshoot its boundaries.
+                    assert_eq!(anchors.len(), prev_len);
+                    for anchor in &anchors {
+                        if anchor.excerpt_id == ExcerptId::min()
+                            || anchor.excerpt_id == ExcerptId::max()
+                        {
+                            continue;
+                        }
+
+                        let excerpt = multibuffer.excerpt(anchor.excerpt_id).unwrap();
+                        assert_eq!(excerpt.id, anchor.excerpt_id);
+                        assert!(excerpt.contains(anchor));
+                    }
+                }
+                _ => {
+                    let buffer_handle = if buffers.is_empty() || rng.gen_bool(0.4) {
+                        let base_text = util::RandomCharIter::new(&mut rng)
+                            .take(10)
+                            .collect::<String>();
+                        buffers.push(cx.new_model(|cx| {
+                            Buffer::new(
+                                0,
+                                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                                base_text,
+                            )
+                        }));
+                        buffers.last().unwrap()
+                    } else {
+                        buffers.choose(&mut rng).unwrap()
+                    };
+
+                    let buffer = buffer_handle.read(cx);
+                    let end_ix = buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Right);
+                    let start_ix = buffer.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
+                    let anchor_range = buffer.anchor_before(start_ix)..buffer.anchor_after(end_ix);
+                    let prev_excerpt_ix = rng.gen_range(0..=expected_excerpts.len());
+                    let prev_excerpt_id = excerpt_ids
+                        .get(prev_excerpt_ix)
+                        .cloned()
+                        .unwrap_or_else(ExcerptId::max);
+                    let excerpt_ix = (prev_excerpt_ix + 1).min(expected_excerpts.len());
+
+                    log::info!(
+                        "Inserting excerpt at {} of {} for buffer {}: {:?}[{:?}] = {:?}",
+                        excerpt_ix,
+                        expected_excerpts.len(),
+                        buffer_handle.read(cx).remote_id(),
+                        buffer.text(),
+                        start_ix..end_ix,
+                        &buffer.text()[start_ix..end_ix]
+                    );
+
+                    let excerpt_id = multibuffer.update(cx, |multibuffer, cx| {
+                        multibuffer
+                            .insert_excerpts_after(
+                                prev_excerpt_id,
+                                buffer_handle.clone(),
+                                [ExcerptRange {
+                                    context: start_ix..end_ix,
+                                    primary: None,
+                                }],
+                                cx,
+                            )
+                            .pop()
+                            .unwrap()
+                    });
+
+                    excerpt_ids.insert(excerpt_ix, excerpt_id);
+                    expected_excerpts.insert(excerpt_ix, (buffer_handle.clone(), anchor_range));
+                }
+            }
+
+            if rng.gen_bool(0.3) {
+                multibuffer.update(cx, |multibuffer, cx| {
+                    old_versions.push((multibuffer.snapshot(cx), multibuffer.subscribe()));
+                })
+            }
+
+            let snapshot = multibuffer.read(cx).snapshot(cx);
+
+            let mut excerpt_starts = Vec::new();
+            let mut expected_text = String::new();
+            let mut expected_buffer_rows = Vec::new();
+            for (buffer, range) in &expected_excerpts {
+                let buffer = buffer.read(cx);
+                let buffer_range = range.to_offset(buffer);
+
+                excerpt_starts.push(TextSummary::from(expected_text.as_str()));
+                expected_text.extend(buffer.text_for_range(buffer_range.clone()));
+                expected_text.push('\n');
+
+                let buffer_row_range = buffer.offset_to_point(buffer_range.start).row
+                    ..=buffer.offset_to_point(buffer_range.end).row;
+                for row in buffer_row_range {
+                    expected_buffer_rows.push(Some(row));
+                }
+            }
+            // Remove final trailing newline.
+            if !expected_excerpts.is_empty() {
+                expected_text.pop();
+            }
+
+            // Always report one buffer row
+            if expected_buffer_rows.is_empty() {
+                expected_buffer_rows.push(Some(0));
+            }
+
+            assert_eq!(snapshot.text(), expected_text);
+            log::info!("MultiBuffer text: {:?}", expected_text);
+
+            assert_eq!(
+                snapshot.buffer_rows(0).collect::<Vec<_>>(),
+                expected_buffer_rows,
+            );
+
+            for _ in 0..5 {
+                let start_row = rng.gen_range(0..=expected_buffer_rows.len());
+                assert_eq!(
+                    snapshot.buffer_rows(start_row as u32).collect::<Vec<_>>(),
+                    &expected_buffer_rows[start_row..],
+                    "buffer_rows({})",
+                    start_row
+                );
+            }
+
+            assert_eq!(
+                snapshot.max_buffer_row(),
+                expected_buffer_rows.into_iter().flatten().max().unwrap()
+            );
+
+            let mut excerpt_starts = excerpt_starts.into_iter();
+            for (buffer, range) in &expected_excerpts {
+                let buffer = buffer.read(cx);
+                let buffer_id = buffer.remote_id();
+                let buffer_range = range.to_offset(buffer);
+                let buffer_start_point = buffer.offset_to_point(buffer_range.start);
+                let buffer_start_point_utf16 =
+                    buffer.text_summary_for_range::<PointUtf16, _>(0..buffer_range.start);
+
+                let excerpt_start = excerpt_starts.next().unwrap();
+                let mut offset = excerpt_start.len;
+                let mut buffer_offset = buffer_range.start;
+                let mut point = excerpt_start.lines;
+                let mut buffer_point = buffer_start_point;
+                let mut point_utf16 = excerpt_start.lines_utf16();
+                let mut buffer_point_utf16 = buffer_start_point_utf16;
+                for ch in buffer
+                    .snapshot()
+                    .chunks(buffer_range.clone(), false)
+                    .flat_map(|c| c.text.chars())
+                {
+                    for _ in 0..ch.len_utf8() {
+                        let left_offset = snapshot.clip_offset(offset, Bias::Left);
+                        let right_offset = snapshot.clip_offset(offset, Bias::Right);
+                        let buffer_left_offset = buffer.clip_offset(buffer_offset, Bias::Left);
+                        let buffer_right_offset = buffer.clip_offset(buffer_offset, Bias::Right);
+                        assert_eq!(
+                            left_offset,
+                            excerpt_start.len + (buffer_left_offset - buffer_range.start),
+                            "clip_offset({:?}, Left). buffer: {:?}, buffer offset: {:?}",
+                            offset,
+                            buffer_id,
+                            buffer_offset,
+                        );
+                        assert_eq!(
+                            right_offset,
+                            excerpt_start.len + (buffer_right_offset - buffer_range.start),
+                            "clip_offset({:?}, Right). buffer: {:?}, buffer offset: {:?}",
+                            offset,
+                            buffer_id,
+                            buffer_offset,
+                        );
+
+                        let left_point = snapshot.clip_point(point, Bias::Left);
+                        let right_point = snapshot.clip_point(point, Bias::Right);
+                        let buffer_left_point = buffer.clip_point(buffer_point, Bias::Left);
+                        let buffer_right_point = buffer.clip_point(buffer_point, Bias::Right);
+                        assert_eq!(
+                            left_point,
+                            excerpt_start.lines + (buffer_left_point - buffer_start_point),
+                            "clip_point({:?}, Left). buffer: {:?}, buffer point: {:?}",
+                            point,
+                            buffer_id,
+                            buffer_point,
+                        );
+                        assert_eq!(
+                            right_point,
+                            excerpt_start.lines + (buffer_right_point - buffer_start_point),
+                            "clip_point({:?}, Right). buffer: {:?}, buffer point: {:?}",
+                            point,
+                            buffer_id,
+                            buffer_point,
+                        );
+
+                        assert_eq!(
+                            snapshot.point_to_offset(left_point),
+                            left_offset,
+                            "point_to_offset({:?})",
+                            left_point,
+                        );
+                        assert_eq!(
+                            snapshot.offset_to_point(left_offset),
+                            left_point,
+                            "offset_to_point({:?})",
+                            left_offset,
+                        );
+
+                        offset += 1;
+                        buffer_offset += 1;
+                        if ch == '\n' {
+                            point += Point::new(1, 0);
+                            buffer_point += Point::new(1, 0);
+                        } else {
+                            point += Point::new(0, 1);
+                            buffer_point += Point::new(0, 1);
+                        }
+                    }
+
+                    for _ in 0..ch.len_utf16() {
+                        let left_point_utf16 =
+                            snapshot.clip_point_utf16(Unclipped(point_utf16), Bias::Left);
+                        let right_point_utf16 =
+                            snapshot.clip_point_utf16(Unclipped(point_utf16), Bias::Right);
+                        let buffer_left_point_utf16 =
+                            buffer.clip_point_utf16(Unclipped(buffer_point_utf16), Bias::Left);
+                        let buffer_right_point_utf16 =
+                            buffer.clip_point_utf16(Unclipped(buffer_point_utf16), Bias::Right);
+                        assert_eq!(
+                            left_point_utf16,
+                            excerpt_start.lines_utf16()
+                                + (buffer_left_point_utf16 - buffer_start_point_utf16),
+                            "clip_point_utf16({:?}, Left). buffer: {:?}, buffer point_utf16: {:?}",
+                            point_utf16,
+                            buffer_id,
+                            buffer_point_utf16,
+                        );
+                        assert_eq!(
+                            right_point_utf16,
+                            excerpt_start.lines_utf16()
+                                + (buffer_right_point_utf16 - buffer_start_point_utf16),
+                            "clip_point_utf16({:?}, Right). buffer: {:?}, buffer point_utf16: {:?}",
+                            point_utf16,
+                            buffer_id,
+                            buffer_point_utf16,
+                        );
+
+                        if ch == '\n' {
+                            point_utf16 += PointUtf16::new(1, 0);
+                            buffer_point_utf16 += PointUtf16::new(1, 0);
+                        } else {
+                            point_utf16 += PointUtf16::new(0, 1);
+                            buffer_point_utf16 += PointUtf16::new(0, 1);
+                        }
+                    }
+                }
+            }
+
+            for (row, line) in expected_text.split('\n').enumerate() {
+                assert_eq!(
+                    snapshot.line_len(row as u32),
+                    line.len() as u32,
+                    "line_len({}).",
+                    row
+                );
+            }
+
+            let text_rope = Rope::from(expected_text.as_str());
+            for _ in 0..10 {
+                let end_ix = text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Right);
+                let start_ix = text_rope.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
+
+                let text_for_range = snapshot
+                    .text_for_range(start_ix..end_ix)
+                    .collect::<String>();
+                assert_eq!(
+                    text_for_range,
+                    &expected_text[start_ix..end_ix],
+                    "incorrect text for range {:?}",
+                    start_ix..end_ix
+                );
+                assert_eq!(
+                    snapshot.rope_for_range(start_ix..end_ix).to_string(),
+                    &expected_text[start_ix..end_ix],
+                    "incorrect rope for range {:?}",
+                    start_ix..end_ix
+                );
+
+                let excerpted_buffer_ranges = multibuffer
+                    .read(cx)
+                    .range_to_buffer_ranges(start_ix..end_ix, cx);
+                let excerpted_buffers_text = excerpted_buffer_ranges
+                    .iter()
+                    .map(|(buffer, buffer_range, _)| {
+                        buffer
+                            .read(cx)
+                            .text_for_range(buffer_range.clone())
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                assert_eq!(excerpted_buffers_text, text_for_range);
+                if !expected_excerpts.is_empty() {
+                    assert!(!excerpted_buffer_ranges.is_empty());
+                }
+
+                let expected_summary = TextSummary::from(&expected_text[start_ix..end_ix]);
+                assert_eq!(
+                    snapshot.text_summary_for_range::<TextSummary, _>(start_ix..end_ix),
+                    expected_summary,
+                    "incorrect summary for range {:?}",
+                    start_ix..end_ix
+                );
+            }
 
-        let anchor_ranges = anchor_ranges.collect::<Vec<_>>().await;
+            let mut ranges = (0..10)
+                .map(|_| {
+                    let end_ix =
+                        text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Right);
+                    let start_ix = text_rope.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
+                    start_ix..end_ix
+                })
+                .collect::<Vec<_>>();
+            if rng.gen() {
+                ranges.sort_by_key(|range| range.start);
+            }
+            let summaries = snapshot
+                .text_summaries_for_ranges::<TextSummary, _>(ranges.iter().cloned())
+                .collect::<Vec<_>>();
+            for (range, summary) in ranges.into_iter().zip(summaries) {
+                assert_eq!(
+                    summary,
+                    TextSummary::from(&expected_text[range.clone()]),
+                    "incorrect batched summary for range {:?}",
+                    range
+                );
+            }
 
-        let snapshot = multibuffer.update(cx, |multibuffer, cx| multibuffer.snapshot(cx));
-        assert_eq!(
-            snapshot.text(),
-            "bbb\nccc\nddd\neee\nfff\nggg\nhhh\niii\njjj\n\nnnn\nooo\nppp\nqqq\nrrr\n"
-        );
+            let mut chunks = snapshot.chunks(0..snapshot.len(), false);
+            for _ in 0..5 {
+                let offset = text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Left);
+                chunks.seek(offset);
+                assert_eq!(chunks.offset(), offset);
+                let text = chunks
+                    .by_ref()
+                    .take(2)
+                    .map(|chunk| chunk.text)
+                    .collect::<String>();
+                assert!(
+                    expected_text[offset..].starts_with(&text),
+                    "incorrect chunks after seeking to {}",
+                    offset
+                );
+            }
 
-        assert_eq!(
-            anchor_ranges
-                .iter()
-                .map(|range| range.to_point(&snapshot))
-                .collect::<Vec<_>>(),
-            vec![
-                Point::new(2, 2)..Point::new(3, 2),
-                Point::new(6, 1)..Point::new(6, 3),
-                Point::new(12, 0)..Point::new(12, 0)
-            ]
-        );
-    }
+            // Anchor resolution
+            let summaries = snapshot.summaries_for_anchors::<usize, _>(&anchors);
+            assert_eq!(anchors.len(), summaries.len());
+            for (anchor, resolved_offset) in anchors.iter().zip(summaries) {
+                assert!(resolved_offset <= snapshot.len());
+                assert_eq!(
+                    snapshot.summary_for_anchor::<usize>(anchor),
+                    resolved_offset
+                );
+            }
 
-    #[gpui::test]
-    fn test_empty_multibuffer(cx: &mut AppContext) {
-        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+            for _ in 0..10 {
+                let end_ix = text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Right);
+                assert_eq!(
+                    snapshot.reversed_chars_at(end_ix).collect::<String>(),
+                    expected_text[..end_ix].chars().rev().collect::<String>(),
+                );
+            }
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot.text(), "");
-        assert_eq!(snapshot.buffer_rows(0).collect::<Vec<_>>(), &[Some(0)]);
-        assert_eq!(snapshot.buffer_rows(1).collect::<Vec<_>>(), &[]);
-    }
+            for _ in 0..10 {
+                let end_ix = rng.gen_range(0..=text_rope.len());
+                let start_ix = rng.gen_range(0..=end_ix);
+                assert_eq!(
+                    snapshot
+                        .bytes_in_range(start_ix..end_ix)
+                        .flatten()
+                        .copied()
+                        .collect::<Vec<_>>(),
+                    expected_text.as_bytes()[start_ix..end_ix].to_vec(),
+                    "bytes_in_range({:?})",
+                    start_ix..end_ix,
+                );
+            }
+        }
 
-    #[gpui::test]
-    fn test_singleton_multibuffer_anchors(cx: &mut AppContext) {
-        let buffer = cx.new_model(|cx| {
-            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
-        });
-        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
-        let old_snapshot = multibuffer.read(cx).snapshot(cx);
-        buffer.update(cx, |buffer, cx| {
-            buffer.edit([(0..0, "X")], None, cx);
-            buffer.edit([(5..5, "Y")], None, cx);
-        });
-        let new_snapshot = multibuffer.read(cx).snapshot(cx);
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        for (old_snapshot, subscription) in old_versions {
+            let edits = subscription.consume().into_inner();
 
-        assert_eq!(old_snapshot.text(), "abcd");
-        assert_eq!(new_snapshot.text(), "XabcdY");
+            log::info!(
+                "applying subscription edits to old text: {:?}: {:?}",
+                old_snapshot.text(),
+                edits,
+            );
 
-        assert_eq!(old_snapshot.anchor_before(0).to_offset(&new_snapshot), 0);
-        assert_eq!(old_snapshot.anchor_after(0).to_offset(&new_snapshot), 1);
-        assert_eq!(old_snapshot.anchor_before(4).to_offset(&new_snapshot), 5);
-        assert_eq!(old_snapshot.anchor_after(4).to_offset(&new_snapshot), 6);
+            let mut text = old_snapshot.text();
+            for edit in edits {
+                let new_text: String = snapshot.text_for_range(edit.new.clone()).collect();
+                text.replace_range(edit.new.start..edit.new.start + edit.old.len(), &new_text);
+            }
+            assert_eq!(text.to_string(), snapshot.text());
+        }
     }
 
     #[gpui::test]
-    fn test_multibuffer_anchors(cx: &mut AppContext) {
-        let buffer_1 = cx.new_model(|cx| {
-            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
-        });
-        let buffer_2 = cx.new_model(|cx| {
-            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "efghi")
-        });
-        let multibuffer = cx.new_model(|cx| {
-            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
-            multibuffer.push_excerpts(
-                buffer_1.clone(),
-                [ExcerptRange {
-                    context: 0..4,
-                    primary: None,
-                }],
-                cx,
-            );
-            multibuffer.push_excerpts(
-                buffer_2.clone(),
-                [ExcerptRange {
-                    context: 0..5,
-                    primary: None,
-                }],
-                cx,
-            );
-            multibuffer
-        });
-        let old_snapshot = multibuffer.read(cx).snapshot(cx);
+    fn test_contains_str_at_excerpt_boundaries(cx: &mut AppContext) {
+        let multibuffer = MultiBuffer::build_multi(
+            [
+                ("abc", vec![Point::new(0, 0)..Point::new(0, 3)]),
+                ("def", vec![Point::new(0, 0)..Point::new(0, 3)]),
+            ],
+            cx,
+        );
 
-        assert_eq!(old_snapshot.anchor_before(0).to_offset(&old_snapshot), 0);
-        assert_eq!(old_snapshot.anchor_after(0).to_offset(&old_snapshot), 0);
-        assert_eq!(Anchor::min().to_offset(&old_snapshot), 0);
-        assert_eq!(Anchor::min().to_offset(&old_snapshot), 0);
-        assert_eq!(Anchor::max().to_offset(&old_snapshot), 10);
-        assert_eq!(Anchor::max().to_offset(&old_snapshot), 10);
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "abc\ndef");
+        assert!(snapshot.contains_str_at(1, "bc"));
+        assert!(!snapshot.contains_str_at(1, "bc\nd"));
+        assert!(snapshot.contains_str_at(4, "def"));
 
-        buffer_1.update(cx, |buffer, cx| {
-            buffer.edit([(0..0, "W")], None, cx);
-            buffer.edit([(5..5, "X")], None, cx);
-        });
-        buffer_2.update(cx, |buffer, cx| {
-            buffer.edit([(0..0, "Y")], None, cx);
-            buffer.edit([(6..6, "Z")], None, cx);
+        multibuffer.update(cx, |multibuffer, _| {
+            multibuffer.set_search_across_excerpts(true)
         });
-        let new_snapshot = multibuffer.read(cx).snapshot(cx);
-
-        assert_eq!(old_snapshot.text(), "abcd\nefghi");
-        assert_eq!(new_snapshot.text(), "WabcdX\nYefghiZ");
-
-        assert_eq!(old_snapshot.anchor_before(0).to_offset(&new_snapshot), 0);
-        assert_eq!(old_snapshot.anchor_after(0).to_offset(&new_snapshot), 1);
-        assert_eq!(old_snapshot.anchor_before(1).to_offset(&new_snapshot), 2);
-        assert_eq!(old_snapshot.anchor_after(1).to_offset(&new_snapshot), 2);
-        assert_eq!(old_snapshot.anchor_before(2).to_offset(&new_snapshot), 3);
-        assert_eq!(old_snapshot.anchor_after(2).to_offset(&new_snapshot), 3);
-        assert_eq!(old_snapshot.anchor_before(5).to_offset(&new_snapshot), 7);
-        assert_eq!(old_snapshot.anchor_after(5).to_offset(&new_snapshot), 8);
-        assert_eq!(old_snapshot.anchor_before(10).to_offset(&new_snapshot), 13);
-        assert_eq!(old_snapshot.anchor_after(10).to_offset(&new_snapshot), 14);
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert!(snapshot.contains_str_at(1, "bc\nd"));
     }
 
     #[gpui::test]
-    fn test_resolving_anchors_after_replacing_their_excerpts(cx: &mut AppContext) {
-        let buffer_1 = cx.new_model(|cx| {
-            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
-        });
-        let buffer_2 = cx.new_model(|cx| {
-            Buffer::new(
-                0,
-                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                "ABCDEFGHIJKLMNOP",
-            )
+    fn test_insert_excerpts_with_invalid_input(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcdef")
         });
-        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_range = |start: usize, end: usize| ExcerptRange {
+            context: start..end,
+            primary: None,
+        };
 
-        // Create an insertion id in buffer 1 that doesn't exist in buffer 2.
-        // Add an excerpt from buffer 1 that spans this new insertion.
-        buffer_1.update(cx, |buffer, cx| buffer.edit([(4..4, "123")], None, cx));
-        let excerpt_id_1 = multibuffer.update(cx, |multibuffer, cx| {
-            multibuffer
-                .push_excerpts(
-                    buffer_1.clone(),
-                    [ExcerptRange {
-                        context: 0..7,
-                        primary: None,
-                    }],
-                    cx,
-                )
-                .pop()
-                .unwrap()
+        // An excerpt id issued by another multi-buffer.
+        let other_multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let stale_excerpt_id = other_multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer.clone(),
+                [excerpt_range(0, 1), excerpt_range(2, 3)],
+                cx,
+            )[1]
         });
 
-        let snapshot_1 = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot_1.text(), "abcd123");
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        multibuffer.update(cx, |multibuffer, cx| {
+            let excerpt_id =
+                multibuffer.push_excerpts(buffer.clone(), [excerpt_range(0, 3)], cx)[0];
 
-        // Replace the buffer 1 excerpt with new excerpts from buffer 2.
-        let (excerpt_id_2, excerpt_id_3) = multibuffer.update(cx, |multibuffer, cx| {
-            multibuffer.remove_excerpts([excerpt_id_1], cx);
-            let mut ids = multibuffer
-                .push_excerpts(
-                    buffer_2.clone(),
-                    [
-                        ExcerptRange {
-                            context: 0..4,
-                            primary: None,
-                        },
-                        ExcerptRange {
-                            context: 6..10,
-                            primary: None,
-                        },
-                        ExcerptRange {
-                            context: 12..16,
-                            primary: None,
-                        },
-                    ],
+            multibuffer.start_transaction(cx);
+            // This used to panic, leaving the transaction open.
+            let ids = multibuffer.insert_excerpts_after(
+                stale_excerpt_id,
+                buffer.clone(),
+                [excerpt_range(3, 4)],
+                cx,
+            );
+            assert!(ids.is_empty());
+            // Reversed ranges are clamped to an empty excerpt.
+            let ids = multibuffer.insert_excerpts_after(
+                excerpt_id,
+                buffer.clone(),
+                [excerpt_range(5, 4)],
+                cx,
+            );
+            assert_eq!(ids.len(), 1);
+            multibuffer.end_transaction(cx);
+
+            assert_eq!(multibuffer.history.transaction_depth, 0);
+            assert_eq!(multibuffer.read(cx).text(), "abc\n");
+        });
+    }
+
+    #[gpui::test]
+    fn test_anchor_range_collections(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcdef")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "ghijkl")
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            for buffer in [&buffer_1, &buffer_2] {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 0..6,
+                        primary: None,
+                    }],
                     cx,
-                )
-                .into_iter();
-            (ids.next().unwrap(), ids.next().unwrap())
+                );
+            }
+            multibuffer
         });
-        let snapshot_2 = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot_2.text(), "ABCD\nGHIJ\nMNOP");
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "abcdef\nghijkl");
+        let anchor_range = |range: Range<usize>| {
+            snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end)
+        };
 
-        // The old excerpt id doesn't get reused.
-        assert_ne!(excerpt_id_2, excerpt_id_1);
+        // Entries are kept sorted, with enclosing ranges before the ranges they contain.
+        let mut map = AnchorRangeMap::from_entries(
+            [
+                (anchor_range(8..10), "second"),
+                (anchor_range(1..12), "outer"),
+            ],
+            &snapshot,
+        );
+        map.insert(anchor_range(0..2), "first", &snapshot);
+        let mut set =
+            AnchorRangeSet::from_ranges(map.iter().map(|(range, _)| range.clone()), &snapshot);
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map.resolve::<usize>(&snapshot).collect::<Vec<_>>(),
+            [(0..2, &"first"), (1..12, &"outer"), (8..10, &"second")]
+        );
 
-        // Resolve some anchors from the previous snapshot in the new snapshot.
-        // The current excerpts are from a different buffer, so we don't attempt to
-        // resolve the old text anchor in the new buffer.
+        // Ranges are resolved against the latest snapshot after edits.
+        buffer_1.update(cx, |buffer, cx| buffer.edit([(3..3, "XX")], None, cx));
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "abcXXdef\nghijkl");
         assert_eq!(
-            snapshot_2.summary_for_anchor::<usize>(&snapshot_1.anchor_before(2)),
-            0
+            map.resolve::<usize>(&snapshot).collect::<Vec<_>>(),
+            [(0..2, &"first"), (1..14, &"outer"), (10..12, &"second")]
         );
         assert_eq!(
-            snapshot_2.summaries_for_anchors::<usize, _>(&[
-                snapshot_1.anchor_before(2),
-                snapshot_1.anchor_after(3)
-            ]),
-            vec![0, 0]
+            set.resolve::<Point>(&snapshot).collect::<Vec<_>>(),
+            [
+                Point::new(0, 0)..Point::new(0, 2),
+                Point::new(0, 1)..Point::new(1, 5),
+                Point::new(1, 1)..Point::new(1, 3),
+            ]
         );
 
-        // Refresh anchors from the old snapshot. The return value indicates that both
-        // anchors lost their original excerpt.
-        let refresh =
-            snapshot_2.refresh_anchors(&[snapshot_1.anchor_before(2), snapshot_1.anchor_after(3)]);
+        set.retain(|range| range.end.to_offset(&snapshot) <= 12);
         assert_eq!(
-            refresh,
-            &[
-                (0, snapshot_2.anchor_before(0), false),
-                (1, snapshot_2.anchor_after(0), false),
-            ]
+            set.resolve::<usize>(&snapshot).collect::<Vec<_>>(),
+            [0..2, 10..12]
         );
+    }
 
-        // Replace the middle excerpt with a smaller excerpt in buffer 2,
-        // that intersects the old excerpt.
-        let excerpt_id_5 = multibuffer.update(cx, |multibuffer, cx| {
-            multibuffer.remove_excerpts([excerpt_id_3], cx);
-            multibuffer
-                .insert_excerpts_after(
-                    excerpt_id_2,
-                    buffer_2.clone(),
+    #[gpui::test]
+    fn test_serialized_anchors(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "abcdefgh",
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let push_excerpt = |cx: &mut AppContext| {
+            multibuffer.update(cx, |multibuffer, cx| {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
                     [ExcerptRange {
-                        context: 5..8,
+                        context: 2..6,
                         primary: None,
                     }],
                     cx,
                 )
-                .pop()
-                .unwrap()
-        });
-
-        let snapshot_3 = multibuffer.read(cx).snapshot(cx);
-        assert_eq!(snapshot_3.text(), "ABCD\nFGH\nMNOP");
-        assert_ne!(excerpt_id_5, excerpt_id_3);
-
-        // Resolve some anchors from the previous snapshot in the new snapshot.
-        // The third anchor can't be resolved, since its excerpt has been removed,
-        // so it resolves to the same position as its predecessor.
-        let anchors = [
-            snapshot_2.anchor_before(0),
-            snapshot_2.anchor_after(2),
-            snapshot_2.anchor_after(6),
-            snapshot_2.anchor_after(14),
-        ];
-        assert_eq!(
-            snapshot_3.summaries_for_anchors::<usize, _>(&anchors),
-            &[0, 2, 9, 13]
-        );
+            })
+        };
+        let excerpt_id = push_excerpt(cx)[0];
 
-        let new_anchors = snapshot_3.refresh_anchors(&anchors);
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let serialized = snapshot.anchor_after(2).serialize(&snapshot);
         assert_eq!(
-            new_anchors.iter().map(|a| (a.0, a.2)).collect::<Vec<_>>(),
-            &[(0, true), (1, true), (2, true), (3, true)]
+            serialized,
+            SerializedAnchor {
+                excerpt_id,
+                path: None,
+                offset: 4,
+                right_biased: true,
+            }
         );
+        let resolved = serialized.resolve(&snapshot).unwrap();
+        assert_eq!(resolved.excerpt_id, excerpt_id);
+        assert_eq!(resolved.to_offset(&snapshot), 2);
         assert_eq!(
-            snapshot_3.summaries_for_anchors::<usize, _>(new_anchors.iter().map(|a| &a.1)),
-            &[0, 2, 7, 13]
+            Anchor::max().serialize(&snapshot).resolve(&snapshot),
+            Some(Anchor::max())
         );
-    }
 
-    #[gpui::test(iterations = 100)]
-    fn test_random_multibuffer(cx: &mut AppContext, mut rng: StdRng) {
-        let operations = env::var("OPERATIONS")
-            .map(|i| i.parse().expect("invalid `OPERATIONS` variable"))
-            .unwrap_or(10);
+        // Once its excerpt is gone, an anchor in a buffer without a file can't be resolved,
+        // even against another excerpt of the same buffer.
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.remove_excerpts([excerpt_id], cx)
+        });
+        push_excerpt(cx);
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(serialized.resolve(&snapshot), None);
+    }
 
-        let mut buffers: Vec<Model<Buffer>> = Vec::new();
+    #[gpui::test]
+    fn test_edit_sources(cx: &mut AppContext) {
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abcd")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "efgh")
+        });
+        let buffer_1_id = buffer_1.read(cx).remote_id();
+        let buffer_2_id = buffer_2.read(cx).remote_id();
         let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
-        let mut excerpt_ids = Vec::<ExcerptId>::new();
-        let mut expected_excerpts = Vec::<(Model<Buffer>, Range<text::Anchor>)>::new();
-        let mut anchors = Vec::new();
-        let mut old_versions = Vec::new();
+        let subscription =
+            multibuffer.update(cx, |multibuffer, _| multibuffer.subscribe_to_edit_sources());
+        let sources = |subscription: &EditSourceSubscription| {
+            subscription
+                .consume()
+                .into_iter()
+                .map(|source| (source.excerpt_id, source.buffer_id))
+                .collect::<Vec<_>>()
+        };
 
-        for _ in 0..operations {
-            match rng.gen_range(0..100) {
-                0..=19 if !buffers.is_empty() => {
-                    let buffer = buffers.choose(&mut rng).unwrap();
-                    buffer.update(cx, |buf, cx| buf.randomly_edit(&mut rng, 5, cx));
-                }
-                20..=29 if !expected_excerpts.is_empty() => {
-                    let mut ids_to_remove = vec![];
-                    for _ in 0..rng.gen_range(1..=3) {
-                        if expected_excerpts.is_empty() {
-                            break;
-                        }
+        let excerpt_ids = multibuffer.update(cx, |multibuffer, cx| {
+            [&buffer_1, &buffer_2]
+                .into_iter()
+                .flat_map(|buffer| {
+                    multibuffer.push_excerpts(
+                        buffer.clone(),
+                        [ExcerptRange {
+                            context: 0..4,
+                            primary: None,
+                        }],
+                        cx,
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(
+            sources(&subscription),
+            [(excerpt_ids[0], buffer_1_id), (excerpt_ids[1], buffer_2_id)]
+        );
 
-                        let ix = rng.gen_range(0..expected_excerpts.len());
-                        ids_to_remove.push(excerpt_ids.remove(ix));
-                        let (buffer, range) = expected_excerpts.remove(ix);
-                        let buffer = buffer.read(cx);
-                        log::info!(
-                            "Removing excerpt {}: {:?}",
-                            ix,
-                            buffer
-                                .text_for_range(range.to_offset(buffer))
-                                .collect::<String>(),
-                        );
-                    }
-                    let snapshot = multibuffer.read(cx).read(cx);
-                    ids_to_remove.sort_unstable_by(|a, b| a.cmp(&b, &snapshot));
-                    drop(snapshot);
-                    multibuffer.update(cx, |multibuffer, cx| {
-                        multibuffer.remove_excerpts(ids_to_remove, cx)
-                    });
-                }
-                30..=39 if !expected_excerpts.is_empty() => {
-                    let multibuffer = multibuffer.read(cx).read(cx);
-                    let offset =
-                        multibuffer.clip_offset(rng.gen_range(0..=multibuffer.len()), Bias::Left);
-                    let bias = if rng.gen() { Bias::Left } else { Bias::Right };
-                    log::info!("Creating anchor at {} with bias {:?}", offset, bias);
-                    anchors.push(multibuffer.anchor_at(offset, bias));
-                    anchors.sort_by(|a, b| a.cmp(b, &multibuffer));
-                }
-                40..=44 if !anchors.is_empty() => {
-                    let multibuffer = multibuffer.read(cx).read(cx);
-                    let prev_len = anchors.len();
-                    anchors = multibuffer
-                        .refresh_anchors(&anchors)
-                        .into_iter()
-                        .map(|a| a.1)
-                        .collect();
+        // Edits to a buffer are attributed to the excerpt that contains them.
+        buffer_2.update(cx, |buffer, cx| buffer.edit([(1..1, "X")], None, cx));
+        assert_eq!(multibuffer.read(cx).read(cx).text(), "abcd\neXfgh");
+        assert_eq!(
+            subscription.consume(),
+            [EditSource {
+                excerpt_id: excerpt_ids[1],
+                buffer_id: buffer_2_id,
+                edit: Edit {
+                    old: 6..6,
+                    new: 6..7,
+                },
+            }]
+        );
 
-                    // Ensure the newly-refreshed anchors point to a valid excerpt and don't
-                    // overshoot its boundaries.
-                    assert_eq!(anchors.len(), prev_len);
-                    for anchor in &anchors {
-                        if anchor.excerpt_id == ExcerptId::min()
-                            || anchor.excerpt_id == ExcerptId::max()
-                        {
-                            continue;
-                        }
+        multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.remove_excerpts([excerpt_ids[0]], cx)
+        });
+        assert_eq!(sources(&subscription), [(excerpt_ids[0], buffer_1_id)]);
+        assert!(subscription.consume().is_empty());
+    }
 
-                        let excerpt = multibuffer.excerpt(anchor.excerpt_id).unwrap();
-                        assert_eq!(excerpt.id, anchor.excerpt_id);
-                        assert!(excerpt.contains(anchor));
-                    }
-                }
-                _ => {
-                    let buffer_handle = if buffers.is_empty() || rng.gen_bool(0.4) {
-                        let base_text = util::RandomCharIter::new(&mut rng)
-                            .take(10)
-                            .collect::<String>();
-                        buffers.push(cx.new_model(|cx| {
-                            Buffer::new(
-                                0,
-                                BufferId::new(cx.entity_id().as_u64()).unwrap(),
-                                base_text,
-                            )
-                        }));
-                        buffers.last().unwrap()
-                    } else {
-                        buffers.choose(&mut rng).unwrap()
-                    };
+    #[gpui::test]
+    fn test_replace_all(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-                    let buffer = buffer_handle.read(cx);
-                    let end_ix = buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Right);
-                    let start_ix = buffer.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
-                    let anchor_range = buffer.anchor_before(start_ix)..buffer.anchor_after(end_ix);
-                    let prev_excerpt_ix = rng.gen_range(0..=expected_excerpts.len());
-                    let prev_excerpt_id = excerpt_ids
-                        .get(prev_excerpt_ix)
-                        .cloned()
-                        .unwrap_or_else(ExcerptId::max);
-                    let excerpt_ix = (prev_excerpt_ix + 1).min(expected_excerpts.len());
+        let multibuffer = MultiBuffer::build_multi(
+            [
+                ("let a = 1;", vec![Point::new(0, 0)..Point::new(0, 10)]),
+                ("let bb = 22;", vec![Point::new(0, 0)..Point::new(0, 12)]),
+            ],
+            cx,
+        );
 
-                    log::info!(
-                        "Inserting excerpt at {} of {} for buffer {}: {:?}[{:?}] = {:?}",
-                        excerpt_ix,
-                        expected_excerpts.len(),
-                        buffer_handle.read(cx).remote_id(),
-                        buffer.text(),
-                        start_ix..end_ix,
-                        &buffer.text()[start_ix..end_ix]
-                    );
+        let regex = Regex::new(r"let (\w+) = (\d+)").unwrap();
+        let ranges = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.replace_all(&regex, "const $1: i32 = $2", cx)
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "const a: i32 = 1;\nconst bb: i32 = 22;");
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| range.to_offset(&snapshot))
+                .collect::<Vec<_>>(),
+            [0..16, 18..36]
+        );
+
+        multibuffer.update(cx, |multibuffer, cx| multibuffer.undo(cx));
+        assert_eq!(
+            multibuffer.read(cx).snapshot(cx).text(),
+            "let a = 1;\nlet bb = 22;"
+        );
+
+        // Adjacent matches and replacements of different lengths don't bleed into each other.
+        let multibuffer = MultiBuffer::build_simple("aab a", cx);
+        let ranges = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.replace_all(&Regex::new("a").unwrap(), "xyz", cx)
+        });
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        assert_eq!(snapshot.text(), "xyzxyzb xyz");
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| range.to_offset(&snapshot))
+                .collect::<Vec<_>>(),
+            [0..3, 3..6, 8..11]
+        );
+    }
 
-                    let excerpt_id = multibuffer.update(cx, |multibuffer, cx| {
-                        multibuffer
-                            .insert_excerpts_after(
-                                prev_excerpt_id,
-                                buffer_handle.clone(),
-                                [ExcerptRange {
-                                    context: start_ix..end_ix,
-                                    primary: None,
-                                }],
-                                cx,
-                            )
-                            .pop()
-                            .unwrap()
-                    });
+    #[gpui::test]
+    fn test_selections_restored_on_undo_and_redo(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-                    excerpt_ids.insert(excerpt_ix, excerpt_id);
-                    expected_excerpts.insert(excerpt_ix, (buffer_handle.clone(), anchor_range));
-                }
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "1234")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "5678")
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            for buffer in [&buffer_1, &buffer_2] {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 0..4,
+                        primary: None,
+                    }],
+                    cx,
+                );
             }
+            multibuffer
+        });
 
-            if rng.gen_bool(0.3) {
-                multibuffer.update(cx, |multibuffer, cx| {
-                    old_versions.push((multibuffer.snapshot(cx), multibuffer.subscribe()));
+        let selections_at = |offsets: &[usize], multibuffer: &MultiBuffer, cx: &AppContext| {
+            let snapshot = multibuffer.read(cx);
+            offsets
+                .iter()
+                .enumerate()
+                .map(|(id, offset)| Selection {
+                    id,
+                    start: snapshot.anchor_before(*offset),
+                    end: snapshot.anchor_before(*offset),
+                    reversed: false,
+                    goal: text::SelectionGoal::None,
                 })
-            }
-
-            let snapshot = multibuffer.read(cx).snapshot(cx);
+                .collect::<Arc<[_]>>()
+        };
+        let selection_offsets = |multibuffer: &MultiBuffer, cx: &AppContext| {
+            let snapshot = multibuffer.snapshot(cx);
+            snapshot
+                .local_selections()
+                .iter()
+                .map(|selection| selection.head().to_offset(&snapshot))
+                .collect::<Vec<_>>()
+        };
 
-            let mut excerpt_starts = Vec::new();
-            let mut expected_text = String::new();
-            let mut expected_buffer_rows = Vec::new();
-            for (buffer, range) in &expected_excerpts {
-                let buffer = buffer.read(cx);
-                let buffer_range = range.to_offset(buffer);
+        multibuffer.update(cx, |multibuffer, cx| {
+            let selections = selections_at(&[1, 6], multibuffer, cx);
+            multibuffer.set_local_selections(selections, cx);
 
-                excerpt_starts.push(TextSummary::from(expected_text.as_str()));
-                expected_text.extend(buffer.text_for_range(buffer_range.clone()));
-                expected_text.push('\n');
+            multibuffer.transact(cx, |multibuffer, cx| {
+                multibuffer.edit([(1..1, "X"), (6..6, "X")], None, cx);
+                let selections = selections_at(&[2, 8], multibuffer, cx);
+                multibuffer.set_local_selections(selections, cx);
+            });
+            assert_eq!(multibuffer.read(cx).text(), "1X234\n5X678");
 
-                let buffer_row_range = buffer.offset_to_point(buffer_range.start).row
-                    ..=buffer.offset_to_point(buffer_range.end).row;
-                for row in buffer_row_range {
-                    expected_buffer_rows.push(Some(row));
-                }
-            }
-            // Remove final trailing newline.
-            if !expected_excerpts.is_empty() {
-                expected_text.pop();
-            }
+            let selections = selections_at(&[0], multibuffer, cx);
+            multibuffer.set_local_selections(selections, cx);
 
-            // Always report one buffer row
-            if expected_buffer_rows.is_empty() {
-                expected_buffer_rows.push(Some(0));
-            }
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "1234\n5678");
+            assert_eq!(selection_offsets(multibuffer, cx), [1, 6]);
 
-            assert_eq!(snapshot.text(), expected_text);
-            log::info!("MultiBuffer text: {:?}", expected_text);
+            multibuffer.redo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "1X234\n5X678");
+            assert_eq!(selection_offsets(multibuffer, cx), [2, 8]);
+        });
+    }
 
-            assert_eq!(
-                snapshot.buffer_rows(0).collect::<Vec<_>>(),
-                expected_buffer_rows,
-            );
+    #[gpui::test]
+    fn test_selections_by_transaction_pruned(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-            for _ in 0..5 {
-                let start_row = rng.gen_range(0..=expected_buffer_rows.len());
-                assert_eq!(
-                    snapshot.buffer_rows(start_row as u32).collect::<Vec<_>>(),
-                    &expected_buffer_rows[start_row..],
-                    "buffer_rows({})",
-                    start_row
-                );
+        let multibuffer = MultiBuffer::build_multi(
+            [
+                ("1234", vec![Point::new(0, 0)..Point::new(0, 4)]),
+                ("5678", vec![Point::new(0, 0)..Point::new(0, 4)]),
+            ],
+            cx,
+        );
+        multibuffer.update(cx, |multibuffer, cx| {
+            // Transactions that don't edit anything aren't kept in the history.
+            for _ in 0..10 {
+                multibuffer.transact(cx, |_, _| {});
             }
+            assert!(multibuffer.selections_by_transaction.is_empty());
 
-            assert_eq!(
-                snapshot.max_buffer_row(),
-                expected_buffer_rows.into_iter().flatten().max().unwrap()
-            );
-
-            let mut excerpt_starts = excerpt_starts.into_iter();
-            for (buffer, range) in &expected_excerpts {
-                let buffer = buffer.read(cx);
-                let buffer_id = buffer.remote_id();
-                let buffer_range = range.to_offset(buffer);
-                let buffer_start_point = buffer.offset_to_point(buffer_range.start);
-                let buffer_start_point_utf16 =
-                    buffer.text_summary_for_range::<PointUtf16, _>(0..buffer_range.start);
+            let first = multibuffer
+                .transact(cx, |multibuffer, cx| {
+                    multibuffer.edit([(0..0, "a")], None, cx)
+                })
+                .unwrap();
+            multibuffer.undo(cx);
+            assert!(multibuffer.selections_for_transaction(first).is_some());
 
-                let excerpt_start = excerpt_starts.next().unwrap();
-                let mut offset = excerpt_start.len;
-                let mut buffer_offset = buffer_range.start;
-                let mut point = excerpt_start.lines;
-                let mut buffer_point = buffer_start_point;
-                let mut point_utf16 = excerpt_start.lines_utf16();
-                let mut buffer_point_utf16 = buffer_start_point_utf16;
-                for ch in buffer
-                    .snapshot()
-                    .chunks(buffer_range.clone(), false)
-                    .flat_map(|c| c.text.chars())
-                {
-                    for _ in 0..ch.len_utf8() {
-                        let left_offset = snapshot.clip_offset(offset, Bias::Left);
-                        let right_offset = snapshot.clip_offset(offset, Bias::Right);
-                        let buffer_left_offset = buffer.clip_offset(buffer_offset, Bias::Left);
-                        let buffer_right_offset = buffer.clip_offset(buffer_offset, Bias::Right);
-                        assert_eq!(
-                            left_offset,
-                            excerpt_start.len + (buffer_left_offset - buffer_range.start),
-                            "clip_offset({:?}, Left). buffer: {:?}, buffer offset: {:?}",
-                            offset,
-                            buffer_id,
-                            buffer_offset,
-                        );
-                        assert_eq!(
-                            right_offset,
-                            excerpt_start.len + (buffer_right_offset - buffer_range.start),
-                            "clip_offset({:?}, Right). buffer: {:?}, buffer offset: {:?}",
-                            offset,
-                            buffer_id,
-                            buffer_offset,
-                        );
+            // Starting a new transaction clears the redo stack.
+            let second = multibuffer
+                .transact(cx, |multibuffer, cx| {
+                    multibuffer.edit([(0..0, "b")], None, cx)
+                })
+                .unwrap();
+            assert!(multibuffer.selections_for_transaction(first).is_none());
+            assert!(multibuffer.selections_for_transaction(second).is_some());
+            assert_eq!(multibuffer.selections_by_transaction.len(), 1);
+        });
+    }
 
-                        let left_point = snapshot.clip_point(point, Bias::Left);
-                        let right_point = snapshot.clip_point(point, Bias::Right);
-                        let buffer_left_point = buffer.clip_point(buffer_point, Bias::Left);
-                        let buffer_right_point = buffer.clip_point(buffer_point, Bias::Right);
-                        assert_eq!(
-                            left_point,
-                            excerpt_start.lines + (buffer_left_point - buffer_start_point),
-                            "clip_point({:?}, Left). buffer: {:?}, buffer point: {:?}",
-                            point,
-                            buffer_id,
-                            buffer_point,
-                        );
-                        assert_eq!(
-                            right_point,
-                            excerpt_start.lines + (buffer_right_point - buffer_start_point),
-                            "clip_point({:?}, Right). buffer: {:?}, buffer point: {:?}",
-                            point,
-                            buffer_id,
-                            buffer_point,
-                        );
+    #[gpui::test]
+    fn test_serialize_and_restore_singleton_history(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-                        assert_eq!(
-                            snapshot.point_to_offset(left_point),
-                            left_offset,
-                            "point_to_offset({:?})",
-                            left_point,
-                        );
-                        assert_eq!(
-                            snapshot.offset_to_point(left_offset),
-                            left_point,
-                            "offset_to_point({:?})",
-                            left_offset,
-                        );
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        let transaction_id = multibuffer.update(cx, |multibuffer, cx| {
+            let cursor = multibuffer.snapshot(cx).anchor_before(1);
+            multibuffer.set_local_selections(
+                Arc::from([Selection {
+                    id: 0,
+                    start: cursor,
+                    end: cursor,
+                    reversed: false,
+                    goal: SelectionGoal::None,
+                }]),
+                cx,
+            );
+            multibuffer.start_transaction_with_label("Insert", cx);
+            multibuffer.edit([(1..1, "X")], None, cx);
+            multibuffer.end_transaction(cx).unwrap()
+        });
+        let history = multibuffer.read(cx).serialize_history(cx);
+        assert_eq!(history.undo_stack.len(), 1);
+        assert_eq!(history.undo_stack[0].label.as_deref(), Some("Insert"));
+        assert_eq!(history.undo_stack[0].selections_before.len(), 1);
+
+        // Another singleton for the same buffer picks up the labels and selections of the
+        // buffer's transactions.
+        let restored = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        restored.update(cx, |restored, cx| {
+            restored.restore_history(history.clone(), cx);
+            assert_eq!(restored.serialize_history(cx), history);
+            assert_eq!(
+                restored.undo_stack_summaries(cx)[0].label.as_deref(),
+                Some("Insert")
+            );
 
-                        offset += 1;
-                        buffer_offset += 1;
-                        if ch == '\n' {
-                            point += Point::new(1, 0);
-                            buffer_point += Point::new(1, 0);
-                        } else {
-                            point += Point::new(0, 1);
-                            buffer_point += Point::new(0, 1);
-                        }
-                    }
+            assert_eq!(restored.undo(cx), Some(transaction_id));
+            let snapshot = restored.snapshot(cx);
+            assert_eq!(snapshot.text(), "abc");
+            assert_eq!(
+                snapshot
+                    .local_selections()
+                    .iter()
+                    .map(|selection| selection.head().to_offset(&snapshot))
+                    .collect::<Vec<_>>(),
+                [1]
+            );
+        });
 
-                    for _ in 0..ch.len_utf16() {
-                        let left_point_utf16 =
-                            snapshot.clip_point_utf16(Unclipped(point_utf16), Bias::Left);
-                        let right_point_utf16 =
-                            snapshot.clip_point_utf16(Unclipped(point_utf16), Bias::Right);
-                        let buffer_left_point_utf16 =
-                            buffer.clip_point_utf16(Unclipped(buffer_point_utf16), Bias::Left);
-                        let buffer_right_point_utf16 =
-                            buffer.clip_point_utf16(Unclipped(buffer_point_utf16), Bias::Right);
-                        assert_eq!(
-                            left_point_utf16,
-                            excerpt_start.lines_utf16()
-                                + (buffer_left_point_utf16 - buffer_start_point_utf16),
-                            "clip_point_utf16({:?}, Left). buffer: {:?}, buffer point_utf16: {:?}",
-                            point_utf16,
-                            buffer_id,
-                            buffer_point_utf16,
-                        );
-                        assert_eq!(
-                            right_point_utf16,
-                            excerpt_start.lines_utf16()
-                                + (buffer_right_point_utf16 - buffer_start_point_utf16),
-                            "clip_point_utf16({:?}, Right). buffer: {:?}, buffer point_utf16: {:?}",
-                            point_utf16,
-                            buffer_id,
-                            buffer_point_utf16,
-                        );
+        // Transactions that aren't in the buffer's history are ignored.
+        let other_buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "abc"));
+        let other = cx.new_model(|cx| MultiBuffer::singleton(other_buffer, cx));
+        other.update(cx, |other, cx| {
+            other.restore_history(history, cx);
+            let history = other.serialize_history(cx);
+            assert!(history.undo_stack.is_empty());
+            assert!(history.redo_stack.is_empty());
+        });
+    }
 
-                        if ch == '\n' {
-                            point_utf16 += PointUtf16::new(1, 0);
-                            buffer_point_utf16 += PointUtf16::new(1, 0);
-                        } else {
-                            point_utf16 += PointUtf16::new(0, 1);
-                            buffer_point_utf16 += PointUtf16::new(0, 1);
-                        }
-                    }
-                }
-            }
+    #[gpui::test]
+    fn test_restore_singleton_history_after_reload(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-            for (row, line) in expected_text.split('\n').enumerate() {
-                assert_eq!(
-                    snapshot.line_len(row as u32),
-                    line.len() as u32,
-                    "line_len({}).",
-                    row
-                );
-            }
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "one two",
+            )
+        });
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer.clone(), cx));
+        let mut now = Instant::now();
+        let transaction_ids = multibuffer.update(cx, |multibuffer, cx| {
+            let transaction_ids = [
+                vec![(3..3, ",")],
+                vec![(0..3, "ONE"), (5..8, "")],
+                vec![(5..5, "three")],
+            ]
+            .into_iter()
+            .map(|edits| {
+                now += Duration::from_secs(1);
+                multibuffer.start_transaction_at(now, cx);
+                multibuffer.edit(edits, None, cx);
+                multibuffer.end_transaction_at(now, cx).unwrap()
+            })
+            .collect::<Vec<_>>();
+            assert_eq!(multibuffer.undo(cx), Some(transaction_ids[2]));
+            transaction_ids
+        });
+        assert_eq!(buffer.read(cx).text(), "ONE, ");
+        let history = multibuffer.read(cx).serialize_history(cx);
 
-            let text_rope = Rope::from(expected_text.as_str());
-            for _ in 0..10 {
-                let end_ix = text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Right);
-                let start_ix = text_rope.clip_offset(rng.gen_range(0..=end_ix), Bias::Left);
+        // A buffer reloaded with the same text has no history of its own, so it's recreated
+        // from the serialized one without changing the text.
+        let reloaded_buffer = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "ONE, ")
+        });
+        let reloaded = cx.new_model(|cx| MultiBuffer::singleton(reloaded_buffer.clone(), cx));
+        reloaded.update(cx, |reloaded, cx| {
+            reloaded.restore_history(history.clone(), cx);
+            assert_eq!(reloaded.read(cx).text(), "ONE, ");
+            assert!(!reloaded_buffer.read(cx).is_dirty());
+            assert_eq!(reloaded.serialize_history(cx), history);
+
+            assert_eq!(reloaded.undo(cx), Some(transaction_ids[1]));
+            assert_eq!(reloaded.read(cx).text(), "one, two");
+            assert_eq!(reloaded.undo(cx), Some(transaction_ids[0]));
+            assert_eq!(reloaded.read(cx).text(), "one two");
+            assert_eq!(reloaded.undo(cx), None);
+
+            assert_eq!(reloaded.redo(cx), Some(transaction_ids[0]));
+            assert_eq!(reloaded.redo(cx), Some(transaction_ids[1]));
+            assert_eq!(reloaded.redo(cx), Some(transaction_ids[2]));
+            assert_eq!(reloaded.read(cx).text(), "ONE, three");
+            assert_eq!(reloaded.redo(cx), None);
+
+            // New transactions don't reuse the ids of the restored ones.
+            now += Duration::from_secs(1);
+            reloaded.start_transaction_at(now, cx);
+            reloaded.edit([(0..0, "!")], None, cx);
+            let transaction_id = reloaded.end_transaction_at(now, cx).unwrap();
+            assert!(!transaction_ids.contains(&transaction_id));
+        });
 
-                let text_for_range = snapshot
-                    .text_for_range(start_ix..end_ix)
-                    .collect::<String>();
-                assert_eq!(
-                    text_for_range,
-                    &expected_text[start_ix..end_ix],
-                    "incorrect text for range {:?}",
-                    start_ix..end_ix
-                );
+        // A buffer whose text changed before the reload keeps its empty history.
+        let changed_buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "ONE"));
+        let changed = cx.new_model(|cx| MultiBuffer::singleton(changed_buffer, cx));
+        changed.update(cx, |changed, cx| {
+            changed.restore_history(history, cx);
+            assert_eq!(changed.undo(cx), None);
+            assert_eq!(changed.read(cx).text(), "ONE");
+        });
+    }
 
-                let excerpted_buffer_ranges = multibuffer
-                    .read(cx)
-                    .range_to_buffer_ranges(start_ix..end_ix, cx);
-                let excerpted_buffers_text = excerpted_buffer_ranges
-                    .iter()
-                    .map(|(buffer, buffer_range, _)| {
-                        buffer
-                            .read(cx)
-                            .text_for_range(buffer_range.clone())
-                            .collect::<String>()
-                    })
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                assert_eq!(excerpted_buffers_text, text_for_range);
-                if !expected_excerpts.is_empty() {
-                    assert!(!excerpted_buffer_ranges.is_empty());
-                }
+    #[gpui::test]
+    fn test_apply_diff(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-                let expected_summary = TextSummary::from(&expected_text[start_ix..end_ix]);
-                assert_eq!(
-                    snapshot.text_summary_for_range::<TextSummary, _>(start_ix..end_ix),
-                    expected_summary,
-                    "incorrect summary for range {:?}",
-                    start_ix..end_ix
-                );
-            }
+        let multibuffer = MultiBuffer::build_simple("one\ntwo\nthree\nfour\nfive\nsix\n", cx);
+        let failed_hunks = multibuffer
+            .update(cx, |multibuffer, cx| {
+                multibuffer.apply_diff(
+                    indoc::indoc! {"
+                        @@ -1,0 +2,1 @@
+                        +one and a half
+                        @@ -3,1 +3,0 @@
+                        -three
+                        @@ -5,1 +4,1 @@
+                        -five
+                        +FIVE
+                        @@ -7,1 +6,1 @@
+                        -seven
+                        +SEVEN
+                    "},
+                    cx,
+                )
+            })
+            .unwrap();
+        assert_eq!(
+            multibuffer.read(cx).read(cx).text(),
+            "one\none and a half\ntwo\nfour\nFIVE\nsix\n"
+        );
+        assert_eq!(
+            failed_hunks,
+            [FailedHunk {
+                path: None,
+                hunk: PatchHunk {
+                    old_start_row: 6,
+                    old_text: "seven\n".into(),
+                    new_text: "SEVEN\n".into(),
+                },
+            }]
+        );
 
-            // Anchor resolution
-            let summaries = snapshot.summaries_for_anchors::<usize, _>(&anchors);
-            assert_eq!(anchors.len(), summaries.len());
-            for (anchor, resolved_offset) in anchors.iter().zip(summaries) {
-                assert!(resolved_offset <= snapshot.len());
-                assert_eq!(
-                    snapshot.summary_for_anchor::<usize>(anchor),
-                    resolved_offset
-                );
-            }
+        // All of the hunks are applied in a single transaction.
+        multibuffer.update(cx, |multibuffer, cx| multibuffer.undo(cx));
+        assert_eq!(
+            multibuffer.read(cx).read(cx).text(),
+            "one\ntwo\nthree\nfour\nfive\nsix\n"
+        );
+    }
 
-            for _ in 0..10 {
-                let end_ix = text_rope.clip_offset(rng.gen_range(0..=text_rope.len()), Bias::Right);
-                assert_eq!(
-                    snapshot.reversed_chars_at(end_ix).collect::<String>(),
-                    expected_text[..end_ix].chars().rev().collect::<String>(),
-                );
-            }
+    #[gpui::test]
+    fn test_revert_to_checkpoint(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
 
-            for _ in 0..10 {
-                let end_ix = rng.gen_range(0..=text_rope.len());
-                let start_ix = rng.gen_range(0..=end_ix);
-                assert_eq!(
-                    snapshot
-                        .bytes_in_range(start_ix..end_ix)
-                        .flatten()
-                        .copied()
-                        .collect::<Vec<_>>(),
-                    expected_text.as_bytes()[start_ix..end_ix].to_vec(),
-                    "bytes_in_range({:?})",
-                    start_ix..end_ix,
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "1234")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "5678")
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, Capability::ReadWrite);
+            for buffer in [&buffer_1, &buffer_2] {
+                multibuffer.push_excerpts(
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 0..4,
+                        primary: None,
+                    }],
+                    cx,
                 );
             }
-        }
+            multibuffer
+        });
 
-        let snapshot = multibuffer.read(cx).snapshot(cx);
-        for (old_snapshot, subscription) in old_versions {
-            let edits = subscription.consume().into_inner();
+        multibuffer.update(cx, |multibuffer, cx| {
+            let checkpoint = multibuffer.checkpoint(cx);
+            assert_eq!(multibuffer.revert_to_checkpoint(&checkpoint, cx), None);
 
-            log::info!(
-                "applying subscription edits to old text: {:?}: {:?}",
-                old_snapshot.text(),
-                edits,
-            );
+            multibuffer.edit([(1..3, "X"), (6..6, "Y")], None, cx);
+            multibuffer.finalize_last_transaction(cx);
+            multibuffer.edit([(0..0, "Z")], None, cx);
+            buffer_2.update(cx, |buffer, cx| buffer.edit([(4..4, "W")], None, cx));
+            assert_eq!(multibuffer.read(cx).text(), "Z1X4\n5Y678W");
 
-            let mut text = old_snapshot.text();
-            for edit in edits {
-                let new_text: String = snapshot.text_for_range(edit.new.clone()).collect();
-                text.replace_range(edit.new.start..edit.new.start + edit.old.len(), &new_text);
-            }
-            assert_eq!(text.to_string(), snapshot.text());
-        }
+            assert!(multibuffer.revert_to_checkpoint(&checkpoint, cx).is_some());
+            assert_eq!(multibuffer.read(cx).text(), "1234\n5678");
+
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "Z1X4\n5Y678W");
+        });
     }
 
     #[gpui::test]
@@ -5550,6 +11299,222 @@ mod tests {
             assert_eq!(multibuffer.read(cx).text(), "1234\n5678");
             multibuffer.redo(cx);
             assert_eq!(multibuffer.read(cx).text(), "XABCD1234\nAB5678");
+
+            // Transactions performed directly on the buffers can be pushed onto
+            // the multibuffer's history, so they're undone together.
+            now += 2 * group_interval;
+            let buffer_transactions = [&buffer_1, &buffer_2].map(|buffer| {
+                buffer.update(cx, |buffer, cx| {
+                    buffer.start_transaction_at(now);
+                    buffer.edit([(0..0, "Y")], None, cx);
+                    buffer.end_transaction_at(now, cx);
+                    buffer.finalize_last_transaction().unwrap().clone()
+                })
+            });
+            assert_eq!(multibuffer.read(cx).text(), "YXABCD1234\nYAB5678");
+            let transaction = multibuffer.push_transaction_at(
+                [
+                    (&buffer_1, &buffer_transactions[0]),
+                    (&buffer_2, &buffer_transactions[1]),
+                ],
+                now,
+                cx,
+            );
+            assert!(transaction.is_some());
+            assert_eq!(multibuffer.undo(cx), transaction);
+            assert_eq!(multibuffer.read(cx).text(), "XABCD1234\nAB5678");
+
+            // Finalized transactions aren't grouped with subsequent ones, even
+            // within the group interval.
+            now += 2 * group_interval;
+            let transaction_2 = multibuffer.start_transaction_at(now, cx);
+            multibuffer.edit([(0..0, "Z")], None, cx);
+            multibuffer.end_transaction_at(now, cx);
+            assert_eq!(multibuffer.finalize_last_transaction(cx), transaction_2);
+            multibuffer.start_transaction_at(now, cx);
+            multibuffer.edit([(0..0, "Z")], None, cx);
+            multibuffer.end_transaction_at(now, cx);
+            assert_eq!(multibuffer.read(cx).text(), "ZZXABCD1234\nAB5678");
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "ZXABCD1234\nAB5678");
+
+            // Forgotten transactions remain applied but are no longer undoable.
+            now += 2 * group_interval;
+            let transaction_3 = multibuffer.start_transaction_at(now, cx).unwrap();
+            multibuffer.edit([(0..0, "W")], None, cx);
+            multibuffer.end_transaction_at(now, cx);
+            multibuffer.forget_transaction(transaction_3, cx);
+            assert_eq!(multibuffer.read(cx).text(), "WZXABCD1234\nAB5678");
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "WXABCD1234\nAB5678");
+
+            // When the undo stack is limited, the oldest transactions are evicted.
+            multibuffer.set_max_undo_transactions(Some(2), cx);
+            assert_eq!(multibuffer.undo_stack_len(), 2);
+            let transactions = ["1", "2", "3"].map(|text| {
+                now += 2 * group_interval;
+                let transaction_id = multibuffer.start_transaction_at(now, cx).unwrap();
+                multibuffer.edit([(0..0, text)], None, cx);
+                multibuffer.end_transaction_at(now, cx);
+                transaction_id
+            });
+            assert_eq!(multibuffer.undo_stack_len(), 2);
+            assert_eq!(multibuffer.read(cx).text(), "321WXABCD1234\nAB5678");
+            assert!(multibuffer
+                .undo_to_transaction(transactions[0], cx)
+                .is_empty());
+            assert_eq!(
+                multibuffer.undo_to_transaction(transactions[1], cx),
+                [transactions[2], transactions[1]]
+            );
+            assert_eq!(multibuffer.read(cx).text(), "1WXABCD1234\nAB5678");
+            assert_eq!(
+                multibuffer.redo_to_transaction(transactions[2], cx),
+                [transactions[1], transactions[2]]
+            );
+            assert_eq!(multibuffer.read(cx).text(), "321WXABCD1234\nAB5678");
+
+            // Transactions can be labeled for display in the undo history.
+            multibuffer.finalize_last_transaction(cx);
+            let transaction_4 = multibuffer.start_transaction_with_label("Insert 4", cx);
+            multibuffer.edit([(0..0, "4")], None, cx);
+            multibuffer.end_transaction(cx);
+            assert_eq!(
+                multibuffer.undo_stack_summaries(cx),
+                [
+                    TransactionSummary {
+                        id: transaction_4.unwrap(),
+                        label: Some("Insert 4".into()),
+                    },
+                    TransactionSummary {
+                        id: transactions[2],
+                        label: None,
+                    },
+                ]
+            );
+        });
+    }
+
+    #[gpui::test]
+    fn test_locator_rebalancing(cx: &mut AppContext) {
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "abcdefghij",
+            )
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let (first_id, last_id) = multibuffer.update(cx, |multibuffer, cx| {
+            let ids = multibuffer.push_excerpts(
+                buffer.clone(),
+                [
+                    ExcerptRange {
+                        context: 0..1,
+                        primary: None,
+                    },
+                    ExcerptRange {
+                        context: 9..10,
+                        primary: None,
+                    },
+                ],
+                cx,
+            );
+            (ids[0], ids[1])
+        });
+        let last_anchor = multibuffer.read(cx).read(cx).anchor_before(3);
+        assert_eq!(last_anchor.excerpt_id, last_id);
+
+        // Repeatedly inserting an excerpt right after the first one, and removing the one
+        // inserted before it, would otherwise make the locators grow on every iteration.
+        let mut prev_id = None;
+        for _ in 0..20 {
+            multibuffer.update(cx, |multibuffer, cx| {
+                let new_id = multibuffer.insert_excerpts_after(
+                    first_id,
+                    buffer.clone(),
+                    [ExcerptRange {
+                        context: 4..6,
+                        primary: None,
+                    }],
+                    cx,
+                )[0];
+                if let Some(prev_id) = prev_id.replace(new_id) {
+                    multibuffer.remove_excerpts([prev_id], cx);
+                }
+            });
+
+            let snapshot = multibuffer.read(cx).snapshot(cx);
+            assert_eq!(snapshot.text(), "a\nef\nj");
+            assert!(snapshot
+                .excerpts
+                .iter()
+                .all(|excerpt| excerpt.locator.len() <= MAX_LOCATOR_LEN));
+            assert_eq!(last_anchor.to_offset(&snapshot), snapshot.len());
+        }
+    }
+
+    #[gpui::test]
+    fn test_undo_excerpt_changes(cx: &mut AppContext) {
+        let test_settings = SettingsStore::test(cx);
+        cx.set_global(test_settings);
+
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "1234")
+        });
+        let buffer_2 = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "5678")
+        });
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, Capability::ReadWrite));
+        let excerpt_1 = multibuffer.update(cx, |multibuffer, cx| {
+            multibuffer.push_excerpts(
+                buffer_1.clone(),
+                [ExcerptRange {
+                    context: 0..4,
+                    primary: None,
+                }],
+                cx,
+            )[0]
+        });
+        let anchor = multibuffer.read(cx).read(cx).anchor_after(2);
+        let group_interval = multibuffer.read(cx).history.group_interval;
+        let mut now = Instant::now();
+
+        multibuffer.update(cx, |multibuffer, cx| {
+            // Insert an excerpt from a new buffer and edit it in the same transaction.
+            multibuffer.start_transaction_at(now, cx);
+            multibuffer.push_excerpts(
+                buffer_2.clone(),
+                [ExcerptRange {
+                    context: 0..4,
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer.edit([(5..5, "X")], None, cx);
+            multibuffer.end_transaction_at(now, cx);
+            assert_eq!(multibuffer.read(cx).text(), "1234\nX5678");
+
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "1234");
+            assert_eq!(buffer_2.read(cx).text(), "5678");
+
+            multibuffer.redo(cx);
+            assert_eq!(multibuffer.read(cx).text(), "1234\nX5678");
+            assert_eq!(buffer_2.read(cx).text(), "X5678");
+
+            // Removing an excerpt can be undone, and anchors into it remain valid.
+            now += 2 * group_interval;
+            multibuffer.start_transaction_at(now, cx);
+            multibuffer.remove_excerpts([excerpt_1], cx);
+            multibuffer.end_transaction_at(now, cx);
+            assert_eq!(multibuffer.read(cx).text(), "X5678");
+
+            multibuffer.undo(cx);
+            assert_eq!(multibuffer.excerpt_ids()[0], excerpt_1);
+            let snapshot = multibuffer.read(cx);
+            assert_eq!(snapshot.text(), "1234\nX5678");
+            assert_eq!(anchor.to_offset(&snapshot), 2);
         });
     }
 }
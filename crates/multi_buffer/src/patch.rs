@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use std::{ops::Range, path::PathBuf};
+
+/// The changes a unified diff makes to a single file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    /// The path of the patched file, with any `a/` or `b/` prefix removed.
+    /// This is `None` when the diff didn't contain file headers.
+    pub path: Option<PathBuf>,
+    pub hunks: Vec<PatchHunk>,
+}
+
+/// A single `@@` section of a unified diff.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchHunk {
+    /// The zero-based row where the hunk's old text is expected to start.
+    pub old_start_row: u32,
+    /// The text being replaced, including context lines.
+    pub old_text: String,
+    /// The replacement text, including context lines.
+    pub new_text: String,
+}
+
+/// A hunk that couldn't be applied by [`MultiBuffer::apply_diff`](crate::MultiBuffer::apply_diff).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailedHunk {
+    pub path: Option<PathBuf>,
+    pub hunk: PatchHunk,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Context,
+    Deletion,
+    Insertion,
+}
+
+/// Parses the given unified diff into the patches it contains for each file.
+pub fn parse_unified_diff(text: &str) -> Result<Vec<FilePatch>> {
+    let mut patches = Vec::<FilePatch>::new();
+    let mut old_path = None;
+    let mut lines = text.lines().enumerate().peekable();
+    while let Some((ix, line)) = lines.next() {
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_path = parse_path(path);
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let path = parse_path(path).or(old_path.take());
+            patches.push(FilePatch {
+                path,
+                hunks: Vec::new(),
+            });
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let (old_range, new_range) = parse_hunk_header(header)
+                .ok_or_else(|| anyhow!("invalid hunk header on line {}", ix + 1))?;
+            let mut hunk = PatchHunk {
+                old_start_row: if old_range.is_empty() {
+                    old_range.start
+                } else {
+                    old_range.start.saturating_sub(1)
+                },
+                ..Default::default()
+            };
+
+            let mut old_remaining = old_range.len();
+            let mut new_remaining = new_range.len();
+            let mut last_kind = None;
+            while old_remaining > 0 || new_remaining > 0 || last_kind.is_some() {
+                let Some((_, line)) = lines.peek() else {
+                    break;
+                };
+                let (kind, content) = if let Some(content) = line.strip_prefix(' ') {
+                    (LineKind::Context, content)
+                } else if let Some(content) = line.strip_prefix('-') {
+                    (LineKind::Deletion, content)
+                } else if let Some(content) = line.strip_prefix('+') {
+                    (LineKind::Insertion, content)
+                } else if line.is_empty() && (old_remaining > 0 || new_remaining > 0) {
+                    // Some tools strip the leading space from empty context lines.
+                    (LineKind::Context, "")
+                } else if line.starts_with('\\') {
+                    // "\ No newline at end of file" applies to the preceding line.
+                    if matches!(last_kind, Some(LineKind::Context | LineKind::Deletion)) {
+                        hunk.old_text.pop();
+                    }
+                    if matches!(last_kind, Some(LineKind::Context | LineKind::Insertion)) {
+                        hunk.new_text.pop();
+                    }
+                    lines.next();
+                    last_kind = None;
+                    continue;
+                } else {
+                    break;
+                };
+
+                if old_remaining == 0 && new_remaining == 0 {
+                    break;
+                }
+                lines.next();
+                if kind != LineKind::Insertion {
+                    hunk.old_text.push_str(content);
+                    hunk.old_text.push('\n');
+                    old_remaining = old_remaining.saturating_sub(1);
+                }
+                if kind != LineKind::Deletion {
+                    hunk.new_text.push_str(content);
+                    hunk.new_text.push('\n');
+                    new_remaining = new_remaining.saturating_sub(1);
+                }
+                last_kind = Some(kind);
+            }
+
+            if patches.is_empty() {
+                patches.push(FilePatch::default());
+            }
+            patches.last_mut().unwrap().hunks.push(hunk);
+        }
+    }
+
+    Ok(patches)
+}
+
+fn parse_path(path: &str) -> Option<PathBuf> {
+    // Strip any trailing timestamp, which is separated from the path by a tab.
+    let path = path.split('\t').next()?.trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+fn parse_hunk_header(header: &str) -> Option<(Range<u32>, Range<u32>)> {
+    let mut parts = header.split_whitespace();
+    let old_range = parse_hunk_range(parts.next()?.strip_prefix('-')?)?;
+    let new_range = parse_hunk_range(parts.next()?.strip_prefix('+')?)?;
+    Some((old_range, new_range))
+}
+
+fn parse_hunk_range(range: &str) -> Option<Range<u32>> {
+    let (start, len) = match range.split_once(',') {
+        Some((start, len)) => (start.parse().ok()?, len.parse().ok()?),
+        None => (range.parse().ok()?, 1),
+    };
+    Some(start..start + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_unified_diff() {
+        let patches = parse_unified_diff(indoc! {"
+            diff --git a/src/main.rs b/src/main.rs
+            --- a/src/main.rs
+            +++ b/src/main.rs
+            @@ -1,3 +1,3 @@
+             fn main() {
+            -    println!(\"hello\");
+            +    println!(\"goodbye\");
+             }
+            @@ -10,0 +11,1 @@
+            +// trailer
+            \\ No newline at end of file
+        "})
+        .unwrap();
+
+        assert_eq!(
+            patches,
+            [FilePatch {
+                path: Some("src/main.rs".into()),
+                hunks: vec![
+                    PatchHunk {
+                        old_start_row: 0,
+                        old_text: "fn main() {\n    println!(\"hello\");\n}\n".into(),
+                        new_text: "fn main() {\n    println!(\"goodbye\");\n}\n".into(),
+                    },
+                    PatchHunk {
+                        old_start_row: 10,
+                        old_text: "".into(),
+                        new_text: "// trailer".into(),
+                    },
+                ],
+            }]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Handles conversions of multi-buffer excerpts to and from the [`rpc`] protocol, so that
+//! collaborators can replicate a multi-buffer composed of buffers they have also opened.
+
+use crate::{Event, ExcerptId, ExcerptRange, MultiBuffer};
+use anyhow::{Context as _, Result};
+use clock::ReplicaId;
+use collections::{BTreeMap, HashSet};
+use gpui::{AppContext, Model, ModelContext};
+use language::{
+    proto::{deserialize_anchor, serialize_anchor},
+    Buffer, Capability,
+};
+use rpc::proto;
+use std::iter;
+use text::BufferId;
+
+/// A change to the excerpts of a [`MultiBuffer`], to be applied on another replica with
+/// [`MultiBuffer::apply_excerpt_operation`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExcerptOperation {
+    /// The inserted excerpts. Each run of excerpts that were inserted together is headed by
+    /// an insertion naming the excerpt that precedes them.
+    pub inserted_excerpts: Vec<proto::ExcerptInsertion>,
+    pub deleted_excerpts: Vec<u64>,
+}
+
+impl ExcerptOperation {
+    /// Returns the operation describing the given event, if it changed the excerpts.
+    pub fn from_event(event: &Event, cx: &AppContext) -> Option<Self> {
+        match event {
+            Event::ExcerptsAdded {
+                buffer,
+                predecessor,
+                excerpts,
+            } => {
+                let buffer_id = buffer.read(cx).remote_id();
+                let inserted_excerpts = excerpts
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, (id, range))| proto::ExcerptInsertion {
+                        previous_excerpt_id: (ix == 0).then(|| predecessor.to_proto()),
+                        excerpt: Some(serialize_excerpt(buffer_id, *id, range)),
+                    })
+                    .collect();
+                Some(Self {
+                    inserted_excerpts,
+                    deleted_excerpts: Vec::new(),
+                })
+            }
+            Event::ExcerptsRemoved { ids } => Some(Self {
+                inserted_excerpts: Vec::new(),
+                deleted_excerpts: ids.iter().map(|id| id.to_proto()).collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inserted_excerpts.is_empty() && self.deleted_excerpts.is_empty()
+    }
+
+    /// The ids of the buffers whose excerpts are inserted by this operation.
+    pub fn buffer_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.inserted_excerpts
+            .iter()
+            .filter_map(|insertion| Some(insertion.excerpt.as_ref()?.buffer_id))
+    }
+}
+
+/// Serializes an excerpt's id and range to be sent over RPC.
+pub fn serialize_excerpt(
+    buffer_id: BufferId,
+    id: ExcerptId,
+    range: &ExcerptRange<text::Anchor>,
+) -> proto::Excerpt {
+    proto::Excerpt {
+        id: id.to_proto(),
+        buffer_id: buffer_id.into(),
+        context_start: Some(serialize_anchor(&range.context.start)),
+        context_end: Some(serialize_anchor(&range.context.end)),
+        primary_start: range
+            .primary
+            .as_ref()
+            .map(|range| serialize_anchor(&range.start)),
+        primary_end: range
+            .primary
+            .as_ref()
+            .map(|range| serialize_anchor(&range.end)),
+    }
+}
+
+/// Deserializes an excerpt's range from the RPC representation.
+pub fn deserialize_excerpt_range(excerpt: proto::Excerpt) -> Option<ExcerptRange<text::Anchor>> {
+    let context = {
+        let start = deserialize_anchor(excerpt.context_start?)?;
+        let end = deserialize_anchor(excerpt.context_end?)?;
+        start..end
+    };
+    let primary = excerpt
+        .primary_start
+        .zip(excerpt.primary_end)
+        .and_then(|(start, end)| {
+            let start = deserialize_anchor(start)?;
+            let end = deserialize_anchor(end)?;
+            Some(start..end)
+        });
+    Some(ExcerptRange { context, primary })
+}
+
+impl MultiBuffer {
+    /// Serializes the list of excerpts, which refer to their buffers by remote id.
+    pub fn to_proto(&self, cx: &AppContext) -> Vec<proto::Excerpt> {
+        self.read(cx)
+            .excerpts()
+            .map(|(id, buffer, range)| serialize_excerpt(buffer.remote_id(), id, &range))
+            .collect()
+    }
+
+    /// Creates a replica of a multi-buffer from its serialized excerpts. The excerpts keep
+    /// their ids, so that anchors received from the original multi-buffer remain valid.
+    /// Excerpts of buffers that aren't in `buffers` are skipped.
+    pub fn from_proto(
+        replica_id: ReplicaId,
+        capability: Capability,
+        excerpts: Vec<proto::Excerpt>,
+        buffers: &[Model<Buffer>],
+        cx: &mut ModelContext<Self>,
+    ) -> Result<Self> {
+        let mut multibuffer = Self::new(replica_id, capability);
+
+        // Excerpt ids must be inserted in increasing order, which may differ from the order
+        // of the excerpts, so insert each one after its nearest inserted predecessor.
+        let mut excerpts = excerpts.into_iter().enumerate().collect::<Vec<_>>();
+        excerpts.sort_by_key(|(_, excerpt)| excerpt.id);
+        let mut inserted_excerpts = BTreeMap::<usize, ExcerptId>::new();
+        for (position, excerpt) in excerpts {
+            let buffer_id = BufferId::new(excerpt.buffer_id)?;
+            let Some(buffer) = buffers
+                .iter()
+                .find(|buffer| buffer.read(cx).remote_id() == buffer_id)
+            else {
+                continue;
+            };
+            let id = ExcerptId::from_proto(excerpt.id);
+            let Some(range) = deserialize_excerpt_range(excerpt) else {
+                continue;
+            };
+
+            let prev_excerpt_id = inserted_excerpts
+                .range(..position)
+                .next_back()
+                .map_or(ExcerptId::min(), |(_, id)| *id);
+            multibuffer.insert_excerpts_with_ids_after(
+                prev_excerpt_id,
+                buffer.clone(),
+                [(id, range)],
+                cx,
+            );
+            inserted_excerpts.insert(position, id);
+        }
+        Ok(multibuffer)
+    }
+
+    /// Returns the operation that makes this multi-buffer's excerpts match the given ones,
+    /// which were serialized on another replica with [`MultiBuffer::to_proto`].
+    pub fn excerpt_operation_to(&self, excerpts: &[proto::Excerpt]) -> ExcerptOperation {
+        let current_ids = self
+            .excerpt_ids()
+            .into_iter()
+            .map(|id| id.to_proto())
+            .collect::<Vec<_>>();
+        let target_ids = excerpts
+            .iter()
+            .map(|excerpt| excerpt.id)
+            .collect::<HashSet<_>>();
+        let deleted_excerpts = current_ids
+            .iter()
+            .copied()
+            .filter(|id| !target_ids.contains(id))
+            .collect();
+
+        let current_ids = current_ids.into_iter().collect::<HashSet<_>>();
+        let mut inserted_excerpts = Vec::new();
+        let mut previous_excerpt_id = ExcerptId::min().to_proto();
+        for excerpt in excerpts {
+            if !current_ids.contains(&excerpt.id) {
+                inserted_excerpts.push(proto::ExcerptInsertion {
+                    previous_excerpt_id: Some(previous_excerpt_id),
+                    excerpt: Some(excerpt.clone()),
+                });
+            }
+            previous_excerpt_id = excerpt.id;
+        }
+
+        ExcerptOperation {
+            inserted_excerpts,
+            deleted_excerpts,
+        }
+    }
+
+    /// Applies an operation produced by another replica with [`ExcerptOperation::from_event`].
+    /// Inserted excerpts whose buffer can't be found with `buffer_for_id` are skipped.
+    pub fn apply_excerpt_operation(
+        &mut self,
+        operation: ExcerptOperation,
+        buffer_for_id: impl Fn(BufferId, &AppContext) -> Option<Model<Buffer>>,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        let mut removed_excerpt_ids = operation
+            .deleted_excerpts
+            .into_iter()
+            .map(ExcerptId::from_proto)
+            .collect::<Vec<_>>();
+        removed_excerpt_ids.sort_by({
+            let snapshot = self.read(cx);
+            move |a, b| a.cmp(b, &snapshot)
+        });
+
+        let mut insertions = operation.inserted_excerpts.into_iter().peekable();
+        while let Some(insertion) = insertions.next() {
+            let Some(excerpt) = insertion.excerpt else {
+                continue;
+            };
+            let Some(previous_excerpt_id) = insertion.previous_excerpt_id else {
+                continue;
+            };
+            let buffer_id = BufferId::new(excerpt.buffer_id)
+                .with_context(|| format!("invalid buffer id {}", excerpt.buffer_id))?;
+            let Some(buffer) = buffer_for_id(buffer_id, cx) else {
+                continue;
+            };
+
+            let adjacent_excerpts = iter::from_fn(|| {
+                let insertion = insertions.peek()?;
+                if insertion.previous_excerpt_id.is_none()
+                    && insertion.excerpt.as_ref()?.buffer_id == u64::from(buffer_id)
+                {
+                    insertions.next()?.excerpt
+                } else {
+                    None
+                }
+            });
+
+            self.insert_excerpts_with_ids_after(
+                ExcerptId::from_proto(previous_excerpt_id),
+                buffer,
+                [excerpt]
+                    .into_iter()
+                    .chain(adjacent_excerpts)
+                    .filter_map(|excerpt| {
+                        Some((
+                            ExcerptId::from_proto(excerpt.id),
+                            deserialize_excerpt_range(excerpt)?,
+                        ))
+                    }),
+                cx,
+            );
+        }
+
+        self.remove_excerpts(removed_excerpt_ids, cx);
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+//! Maintains the excerpts of a [`MultiBuffer`] that shows search results, as they stream in.
+
+use crate::{Anchor, ExcerptId, ExcerptRange, MultiBuffer};
+use collections::{BTreeMap, HashMap, HashSet};
+use gpui::{AppContext, Model};
+use language::{Buffer, OffsetRangeExt as _, Point, ToPoint as _};
+use std::{cmp, ops::Range};
+use text::BufferId;
+
+/// Identifies a match pushed to a [`SearchResultsMultiBuffer`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SearchMatchId(usize);
+
+/// Keeps a [`MultiBuffer`] populated with excerpts around search matches.
+///
+/// Each match gets an excerpt with a configurable number of context lines, and matches whose
+/// excerpts would overlap share a single excerpt. The excerpts of a buffer are kept together
+/// and ordered by position, with buffers ordered by when their first match arrived. Because
+/// merging matches replaces their excerpts, the multi-buffer range of each match is tracked
+/// by its [`SearchMatchId`], for highlighting and navigating between matches.
+pub struct SearchResultsMultiBuffer {
+    multibuffer: Model<MultiBuffer>,
+    context_line_count: u32,
+    /// The excerpts of each buffer, ordered by position.
+    excerpts_by_buffer: HashMap<BufferId, Vec<SearchResultExcerpt>>,
+    /// The matches within each excerpt, ordered by position.
+    matches_by_excerpt: HashMap<ExcerptId, Vec<(SearchMatchId, Range<text::Anchor>)>>,
+    match_ranges: BTreeMap<SearchMatchId, Range<Anchor>>,
+    next_match_id: usize,
+}
+
+struct SearchResultExcerpt {
+    id: ExcerptId,
+    context: Range<text::Anchor>,
+}
+
+impl SearchResultsMultiBuffer {
+    pub fn new(multibuffer: Model<MultiBuffer>, context_line_count: u32) -> Self {
+        Self {
+            multibuffer,
+            context_line_count,
+            excerpts_by_buffer: HashMap::default(),
+            matches_by_excerpt: HashMap::default(),
+            match_ranges: BTreeMap::default(),
+            next_match_id: 0,
+        }
+    }
+
+    pub fn multibuffer(&self) -> &Model<MultiBuffer> {
+        &self.multibuffer
+    }
+
+    /// Adds a match, creating an excerpt for it or growing the excerpts it overlaps.
+    pub fn push_match(
+        &mut self,
+        buffer: Model<Buffer>,
+        range: Range<text::Anchor>,
+        cx: &mut AppContext,
+    ) -> SearchMatchId {
+        let match_id = SearchMatchId(self.next_match_id);
+        self.next_match_id += 1;
+
+        let snapshot = buffer.read(cx).snapshot();
+        let match_range = range.to_point(&snapshot);
+        let mut context = Point::new(
+            match_range
+                .start
+                .row
+                .saturating_sub(self.context_line_count),
+            0,
+        )
+            ..cmp::min(
+                Point::new(match_range.end.row + 1 + self.context_line_count, 0),
+                snapshot.max_point(),
+            );
+
+        // The buffer's excerpts are disjoint and sorted, so the ones overlapping the new
+        // excerpt form a contiguous run that can be found by binary search.
+        let excerpts = self
+            .excerpts_by_buffer
+            .entry(snapshot.remote_id())
+            .or_default();
+        let merge_start_ix = excerpts
+            .partition_point(|excerpt| excerpt.context.end.to_point(&snapshot) < context.start);
+        let merge_end_ix = excerpts
+            .partition_point(|excerpt| excerpt.context.start.to_point(&snapshot) <= context.end);
+        let merged_excerpts = excerpts
+            .drain(merge_start_ix..merge_end_ix)
+            .collect::<Vec<_>>();
+        if let Some((first, last)) = merged_excerpts.first().zip(merged_excerpts.last()) {
+            context.start = cmp::min(context.start, first.context.start.to_point(&snapshot));
+            context.end = cmp::max(context.end, last.context.end.to_point(&snapshot));
+        }
+
+        let mut matches = Vec::new();
+        for excerpt in &merged_excerpts {
+            matches.extend(
+                self.matches_by_excerpt
+                    .remove(&excerpt.id)
+                    .unwrap_or_default(),
+            );
+        }
+        let ix = matches.partition_point(|(_, existing_range)| {
+            existing_range.start.cmp(&range.start, &snapshot).is_le()
+        });
+        matches.insert(ix, (match_id, range));
+
+        let next_excerpt_id = merged_excerpts
+            .first()
+            .or(excerpts.get(merge_start_ix))
+            .map(|excerpt| excerpt.id);
+        let excerpt_id = self.multibuffer.update(cx, |multibuffer, cx| {
+            let prev_excerpt_id =
+                if let Some(prev_excerpt) = merge_start_ix.checked_sub(1).map(|ix| &excerpts[ix]) {
+                    prev_excerpt.id
+                } else if let Some(next_excerpt_id) = next_excerpt_id {
+                    multibuffer.read(cx).excerpt_id_before(next_excerpt_id)
+                } else {
+                    ExcerptId::max()
+                };
+            let excerpt_id = multibuffer.insert_excerpts_after(
+                prev_excerpt_id,
+                buffer,
+                [ExcerptRange {
+                    context: context.clone(),
+                    primary: None,
+                }],
+                cx,
+            )[0];
+            multibuffer.remove_excerpts(merged_excerpts.iter().map(|excerpt| excerpt.id), cx);
+
+            let multibuffer_snapshot = multibuffer.read(cx);
+            for (match_id, range) in &matches {
+                let start = multibuffer_snapshot.anchor_in_excerpt(excerpt_id, range.start);
+                let end = multibuffer_snapshot.anchor_in_excerpt(excerpt_id, range.end);
+                self.match_ranges.insert(*match_id, start..end);
+            }
+            excerpt_id
+        });
+
+        excerpts.insert(
+            merge_start_ix,
+            SearchResultExcerpt {
+                id: excerpt_id,
+                context: snapshot.anchor_before(context.start)..snapshot.anchor_after(context.end),
+            },
+        );
+        self.matches_by_excerpt.insert(excerpt_id, matches);
+        match_id
+    }
+
+    /// Returns the range of the given match in the multi-buffer.
+    pub fn match_range(&self, match_id: SearchMatchId) -> Option<&Range<Anchor>> {
+        self.match_ranges.get(&match_id)
+    }
+
+    /// Returns the ranges of all matches in the multi-buffer, in the order they were pushed.
+    pub fn match_ranges(&self) -> impl Iterator<Item = (SearchMatchId, &Range<Anchor>)> {
+        self.match_ranges
+            .iter()
+            .map(|(match_id, range)| (*match_id, range))
+    }
+
+    /// Returns the matches within the given excerpt, ordered by position.
+    pub fn matches_in_excerpt(&self, excerpt_id: ExcerptId) -> Vec<SearchMatchId> {
+        self.matches_by_excerpt
+            .get(&excerpt_id)
+            .map_or(Vec::new(), |matches| {
+                matches.iter().map(|(match_id, _)| *match_id).collect()
+            })
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.match_ranges.len()
+    }
+
+    /// Removes all matches and their excerpts.
+    pub fn clear(&mut self, cx: &mut AppContext) {
+        let excerpt_ids = self
+            .excerpts_by_buffer
+            .drain()
+            .flat_map(|(_, excerpts)| excerpts)
+            .map(|excerpt| excerpt.id)
+            .collect::<HashSet<_>>();
+        self.matches_by_excerpt.clear();
+        self.match_ranges.clear();
+        self.multibuffer.update(cx, |multibuffer, cx| {
+            // Excerpts must be removed in the order they appear in the multi-buffer.
+            let excerpt_ids = multibuffer
+                .excerpt_ids()
+                .into_iter()
+                .filter(|excerpt_id| excerpt_ids.contains(excerpt_id))
+                .collect::<Vec<_>>();
+            multibuffer.remove_excerpts(excerpt_ids, cx)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToPoint as _;
+    use gpui::Context;
+    use settings::SettingsStore;
+
+    #[gpui::test]
+    fn test_search_results_multibuffer(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+
+        let text = (0..20)
+            .map(|row| format!("line {row}\n"))
+            .collect::<String>();
+        let buffer_1 = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                text.clone(),
+            )
+        });
+        let buffer_2 = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        let multibuffer = cx.new_model(|_| MultiBuffer::new(0, language::Capability::ReadWrite));
+        let mut results = SearchResultsMultiBuffer::new(multibuffer.clone(), 1);
+
+        let push_match = |results: &mut SearchResultsMultiBuffer,
+                          buffer: &Model<Buffer>,
+                          row: u32,
+                          cx: &mut AppContext| {
+            let range = {
+                let buffer = buffer.read(cx);
+                buffer.anchor_before(Point::new(row, 0))..buffer.anchor_after(Point::new(row, 4))
+            };
+            results.push_match(buffer.clone(), range, cx)
+        };
+
+        let match_1 = push_match(&mut results, &buffer_1, 10, cx);
+        let match_2 = push_match(&mut results, &buffer_2, 5, cx);
+        let match_3 = push_match(&mut results, &buffer_1, 2, cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\n\nline 9\nline 10\nline 11\n\nline 4\nline 5\nline 6\n"
+        );
+
+        // Overlapping matches share an excerpt.
+        let match_4 = push_match(&mut results, &buffer_1, 3, cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\nline 4\n\nline 9\nline 10\nline 11\n\nline 4\nline 5\nline 6\n"
+        );
+        assert_eq!(results.match_count(), 4);
+
+        let snapshot = multibuffer.read(cx).snapshot(cx);
+        let match_rows = [match_1, match_2, match_3, match_4].map(|match_id| {
+            let range = results.match_range(match_id).unwrap();
+            range.start.to_point(&snapshot).row..range.end.to_point(&snapshot).row
+        });
+        assert_eq!(match_rows, [6..6, 10..10, 1..1, 2..2]);
+        let first_excerpt_id = snapshot.excerpts().next().unwrap().0;
+        assert_eq!(
+            results.matches_in_excerpt(first_excerpt_id),
+            [match_3, match_4]
+        );
+
+        // A match before a later buffer's excerpts is inserted after the previous buffer's.
+        let match_5 = push_match(&mut results, &buffer_2, 1, cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\nline 4\n\nline 9\nline 10\nline 11\n\nline 0\nline 1\nline 2\n\nline 4\nline 5\nline 6\n"
+        );
+
+        // A match overlapping several excerpts merges all of them.
+        let match_6 = push_match(&mut results, &buffer_2, 3, cx);
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "line 1\nline 2\nline 3\nline 4\n\nline 9\nline 10\nline 11\n\nline 0\nline 1\nline 2\nline 3\nline 4\nline 5\nline 6\n"
+        );
+        let last_excerpt_id = multibuffer.read(cx).excerpt_ids().last().copied().unwrap();
+        assert_eq!(
+            results.matches_in_excerpt(last_excerpt_id),
+            [match_5, match_6, match_2]
+        );
+
+        results.clear(cx);
+        assert_eq!(multibuffer.read(cx).text(), "");
+        assert_eq!(results.match_count(), 0);
+    }
+
+    #[gpui::test]
+    fn test_clear_search_results_from_many_buffers(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+
+        let other_buffer = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "other")
+        });
+        let multibuffer = cx.new_model(|cx| {
+            let mut multibuffer = MultiBuffer::new(0, language::Capability::ReadWrite);
+            multibuffer.push_excerpts(
+                other_buffer,
+                [ExcerptRange {
+                    context: 0..5,
+                    primary: None,
+                }],
+                cx,
+            );
+            multibuffer
+        });
+        let mut results = SearchResultsMultiBuffer::new(multibuffer.clone(), 0);
+
+        for ix in 0..5 {
+            let buffer = cx.new_model(|cx| {
+                Buffer::new(
+                    0,
+                    BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                    format!("buffer {ix}"),
+                )
+            });
+            let range = {
+                let buffer = buffer.read(cx);
+                buffer.anchor_before(0)..buffer.anchor_after(6)
+            };
+            results.push_match(buffer, range, cx);
+        }
+        assert_eq!(
+            multibuffer.read(cx).text(),
+            "other\nbuffer 0\nbuffer 1\nbuffer 2\nbuffer 3\nbuffer 4"
+        );
+
+        // Every result's excerpt is removed, regardless of the order the buffers are stored in.
+        results.clear(cx);
+        assert_eq!(multibuffer.read(cx).text(), "other");
+        assert_eq!(multibuffer.read(cx).excerpt_ids().len(), 1);
+    }
+}
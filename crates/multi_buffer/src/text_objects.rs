@@ -0,0 +1,330 @@
+//! Queries for the text objects that modal editing operates on, such as the word or the
+//! bracketed expression surrounding a position.
+
+use crate::{MultiBufferSnapshot, ToOffset};
+use language::{char_kind, CharKind, Point};
+use std::ops::Range;
+use sum_tree::Bias;
+
+/// Whether a text object includes its delimiters and the whitespace around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextObjectScope {
+    /// Only the object's contents, like vim's `iw` or `i(`.
+    Inner,
+    /// The object along with its delimiters or adjacent whitespace, like vim's `aw` or `a(`.
+    Around,
+}
+
+impl MultiBufferSnapshot {
+    /// Returns the run of word, punctuation, or whitespace characters at `position` within its
+    /// line. Around a word, the whitespace following it is included, or the whitespace preceding
+    /// it if there is none. Around whitespace, the word following it is included. If
+    /// `ignore_punctuation` is true, punctuation is treated as part of words.
+    pub fn word_object<T: ToOffset>(
+        &self,
+        position: T,
+        scope: TextObjectScope,
+        ignore_punctuation: bool,
+    ) -> Option<Range<usize>> {
+        let offset = position.to_offset(self);
+        let language_scope = self.language_scope_at(offset);
+        let kind = |c: char| match char_kind(&language_scope, c) {
+            CharKind::Punctuation if ignore_punctuation => CharKind::Word,
+            kind => kind,
+        };
+        let target_kind = self
+            .chars_at(offset)
+            .next()
+            .filter(|c| *c != '\n')
+            .map(kind)?;
+
+        let is_target = |c: char| kind(c) == target_kind;
+        let mut range = self.run_start(offset, is_target)..self.run_end(offset, is_target);
+        if scope == TextObjectScope::Around {
+            if target_kind == CharKind::Whitespace {
+                if let Some(next_kind) = self.chars_at(range.end).next().map(kind) {
+                    range.end = self.run_end(range.end, |c| kind(c) == next_kind);
+                }
+            } else {
+                range = self.include_adjacent_whitespace(range);
+            }
+        }
+        Some(range)
+    }
+
+    /// Returns the quoted string at `position`, or the next one after it, within its line.
+    /// Quotes are paired from the start of the line, and quotes escaped with a backslash are
+    /// skipped. Around a quoted string, the quotes and the whitespace following them are
+    /// included, or the whitespace preceding them if there is none.
+    pub fn quote_object<T: ToOffset>(
+        &self,
+        position: T,
+        quote: char,
+        scope: TextObjectScope,
+    ) -> Option<Range<usize>> {
+        let offset = position.to_offset(self);
+        let row = self.offset_to_point(offset).row;
+
+        let mut quote_offsets = Vec::new();
+        let mut escaped = false;
+        let mut ix = self.point_to_offset(Point::new(row, 0));
+        for c in self.chars_at(ix).take_while(|c| *c != '\n') {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == quote {
+                quote_offsets.push(ix);
+            }
+            ix += c.len_utf8();
+        }
+
+        let (open, close) = quote_offsets
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|(_, close)| *close >= offset)?;
+        match scope {
+            TextObjectScope::Inner => Some(open + quote.len_utf8()..close),
+            TextObjectScope::Around => {
+                Some(self.include_adjacent_whitespace(open..close + quote.len_utf8()))
+            }
+        }
+    }
+
+    /// Returns the innermost pair of brackets enclosing `position`, as defined by the language's
+    /// bracket queries, optionally only considering pairs opened by `open_bracket`. Both
+    /// brackets must be within the excerpt containing `position`. If the brackets are on
+    /// different lines, the inner range excludes the line breaks after the opening bracket and
+    /// the indentation before the closing bracket.
+    pub fn bracket_object<T: ToOffset>(
+        &self,
+        position: T,
+        open_bracket: Option<char>,
+        scope: TextObjectScope,
+    ) -> Option<Range<usize>> {
+        let offset = position.to_offset(self);
+        let (open, close) = self
+            .enclosing_bracket_ranges(offset..offset)?
+            .filter(|(open, _)| {
+                open_bracket.map_or(true, |bracket| {
+                    self.chars_at(open.start).next() == Some(bracket)
+                })
+            })
+            .min_by_key(|(open, close)| close.end - open.start)?;
+
+        match scope {
+            TextObjectScope::Around => Some(open.start..close.end),
+            TextObjectScope::Inner => {
+                let mut start = open.end;
+                let mut end = close.start;
+                if self.chars_at(start).next() == Some('\n') {
+                    start += 1;
+                }
+                let indent_start = self.run_start(end, char::is_whitespace);
+                if indent_start > start && self.reversed_chars_at(indent_start).next() == Some('\n')
+                {
+                    end = indent_start;
+                }
+                Some(start..end.max(start))
+            }
+        }
+    }
+
+    /// Returns the lines around `position` that are all blank or all non-blank, within the
+    /// excerpt containing it. Around a paragraph, the blank lines following it are included, or
+    /// the blank lines preceding it if there are none. Around blank lines, the paragraph
+    /// following them is included.
+    pub fn paragraph_object<T: ToOffset>(
+        &self,
+        position: T,
+        scope: TextObjectScope,
+    ) -> Option<Range<usize>> {
+        let offset = position.to_offset(self);
+        let mut cursor = self.excerpts.cursor::<usize>();
+        cursor.seek(&offset, Bias::Right, &());
+        if cursor.item().is_none() {
+            cursor.prev(&());
+        }
+        let excerpt = cursor.item()?;
+        let first_row = self.offset_to_point(*cursor.start()).row;
+        let last_row = self
+            .offset_to_point(*cursor.start() + excerpt.text_summary.len)
+            .row;
+
+        let rows_like = |row: u32, blank: bool| {
+            let mut start = row;
+            while start > first_row && self.is_line_blank(start - 1) == blank {
+                start -= 1;
+            }
+            let mut end = row;
+            while end < last_row && self.is_line_blank(end + 1) == blank {
+                end += 1;
+            }
+            (start, end)
+        };
+
+        let row = self.offset_to_point(offset).row;
+        let blank = self.is_line_blank(row);
+        let (mut start_row, mut end_row) = rows_like(row, blank);
+        if scope == TextObjectScope::Around {
+            if end_row < last_row {
+                end_row = rows_like(end_row + 1, !blank).1;
+            } else if start_row > first_row && !blank {
+                start_row = rows_like(start_row - 1, true).0;
+            }
+        }
+
+        let end = if end_row < last_row {
+            Point::new(end_row + 1, 0)
+        } else {
+            Point::new(end_row, self.line_len(end_row))
+        };
+        Some(self.point_to_offset(Point::new(start_row, 0))..self.point_to_offset(end))
+    }
+
+    /// Extends `range` over the whitespace following it on its line, or the whitespace
+    /// preceding it if there is none.
+    fn include_adjacent_whitespace(&self, mut range: Range<usize>) -> Range<usize> {
+        let end = self.run_end(range.end, char::is_whitespace);
+        if end > range.end {
+            range.end = end;
+        } else {
+            range.start = self.run_start(range.start, char::is_whitespace);
+        }
+        range
+    }
+
+    /// Returns the end of the run of characters matching `predicate` that starts at `offset`,
+    /// without crossing a line break.
+    fn run_end(&self, offset: usize, mut predicate: impl FnMut(char) -> bool) -> usize {
+        offset
+            + self
+                .chars_at(offset)
+                .take_while(|c| *c != '\n' && predicate(*c))
+                .map(char::len_utf8)
+                .sum::<usize>()
+    }
+
+    /// Returns the start of the run of characters matching `predicate` that ends at `offset`,
+    /// without crossing a line break.
+    fn run_start(&self, offset: usize, mut predicate: impl FnMut(char) -> bool) -> usize {
+        offset
+            - self
+                .reversed_chars_at(offset)
+                .take_while(|c| *c != '\n' && predicate(*c))
+                .map(char::len_utf8)
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MultiBuffer;
+    use gpui::{AppContext, Context, TestAppContext};
+    use language::{Buffer, Language, LanguageConfig};
+    use std::sync::Arc;
+    use text::BufferId;
+
+    fn snapshot(text: &str, cx: &mut AppContext) -> MultiBufferSnapshot {
+        let buffer = cx
+            .new_model(|cx| Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), text));
+        let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+        multibuffer.read(cx).snapshot(cx)
+    }
+
+    fn text(snapshot: &MultiBufferSnapshot, range: Option<Range<usize>>) -> Option<String> {
+        Some(snapshot.text_for_range(range?).collect())
+    }
+
+    #[gpui::test]
+    fn test_word_and_quote_objects(cx: &mut AppContext) {
+        use TextObjectScope::*;
+
+        let snapshot = snapshot("let a = foo.bar(\"x \\\" y\", 'z');", cx);
+        let word = |offset: usize, scope, ignore_punctuation| {
+            text(
+                &snapshot,
+                snapshot.word_object(offset, scope, ignore_punctuation),
+            )
+        };
+        assert_eq!(word(9, Inner, false).as_deref(), Some("foo"));
+        assert_eq!(word(9, Inner, true).as_deref(), Some("foo.bar(\"x"));
+        assert_eq!(word(4, Around, false).as_deref(), Some("a "));
+        assert_eq!(word(3, Inner, false).as_deref(), Some(" "));
+        assert_eq!(word(3, Around, false).as_deref(), Some(" a"));
+
+        let quote = |offset: usize, quote, scope| {
+            text(&snapshot, snapshot.quote_object(offset, quote, scope))
+        };
+        assert_eq!(quote(18, '"', Inner).as_deref(), Some("x \\\" y"));
+        assert_eq!(quote(18, '"', Around).as_deref(), Some("\"x \\\" y\""));
+        // Before any quotes, the next quoted string is used.
+        assert_eq!(quote(0, '\'', Around).as_deref(), Some(" 'z'"));
+        assert_eq!(quote(0, '`', Inner), None);
+    }
+
+    #[gpui::test]
+    fn test_bracket_object(cx: &mut TestAppContext) {
+        use TextObjectScope::*;
+
+        let language = Arc::new(
+            Language::new(
+                LanguageConfig::default(),
+                Some(tree_sitter_rust::language()),
+            )
+            .with_brackets_query(
+                r#"
+                ("{" @open "}" @close)
+                ("(" @open ")" @close)
+                "#,
+            )
+            .unwrap(),
+        );
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(
+                0,
+                BufferId::new(cx.entity_id().as_u64()).unwrap(),
+                "fn a() {\n    b(1, 2);\n}\n",
+            )
+            .with_language(language, cx)
+        });
+        cx.run_until_parked();
+        let snapshot = cx.update(|cx| {
+            let multibuffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+            multibuffer.read(cx).snapshot(cx)
+        });
+        let bracket = |offset: usize, open_bracket, scope| {
+            text(
+                &snapshot,
+                snapshot.bracket_object(offset, open_bracket, scope),
+            )
+        };
+
+        let offset = snapshot.point_to_offset(Point::new(1, 7));
+        assert_eq!(bracket(offset, None, Inner).as_deref(), Some("1, 2"));
+        assert_eq!(bracket(offset, None, Around).as_deref(), Some("(1, 2)"));
+        assert_eq!(
+            bracket(offset, Some('{'), Inner).as_deref(),
+            Some("    b(1, 2);\n")
+        );
+        assert_eq!(bracket(offset, Some('['), Inner), None);
+    }
+
+    #[gpui::test]
+    fn test_paragraph_object(cx: &mut AppContext) {
+        use TextObjectScope::*;
+
+        let snapshot = snapshot("one\ntwo\n\n\nthree\n  \nfour", cx);
+        let paragraph = |row, scope| {
+            let offset = snapshot.point_to_offset(Point::new(row, 0));
+            text(&snapshot, snapshot.paragraph_object(offset, scope))
+        };
+        assert_eq!(paragraph(1, Inner).as_deref(), Some("one\ntwo\n"));
+        assert_eq!(paragraph(1, Around).as_deref(), Some("one\ntwo\n\n\n"));
+        assert_eq!(paragraph(2, Inner).as_deref(), Some("\n\n"));
+        assert_eq!(paragraph(2, Around).as_deref(), Some("\n\nthree\n"));
+        // The last paragraph includes the blank lines preceding it.
+        assert_eq!(paragraph(6, Around).as_deref(), Some("  \nfour"));
+    }
+}
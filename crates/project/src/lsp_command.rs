@@ -1,7 +1,7 @@
 use crate::{
     DocumentHighlight, Hover, HoverBlock, HoverBlockKind, InlayHint, InlayHintLabel,
     InlayHintLabelPart, InlayHintLabelPartTooltip, InlayHintTooltip, Location, LocationLink,
-    MarkupContent, Project, ProjectTransaction, ResolveState,
+    MarkupContent, Project, ProjectTransaction, ResolveState, SignatureHelp, SignatureInformation,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
@@ -117,6 +117,10 @@ pub(crate) struct GetHover {
     pub position: PointUtf16,
 }
 
+pub(crate) struct GetSignatureHelp {
+    pub position: PointUtf16,
+}
+
 pub(crate) struct GetCompletions {
     pub position: PointUtf16,
 }
@@ -1321,6 +1325,221 @@ impl LspCommand for GetHover {
     }
 }
 
+#[async_trait(?Send)]
+impl LspCommand for GetSignatureHelp {
+    type Response = Option<SignatureHelp>;
+    type LspRequest = lsp::request::SignatureHelpRequest;
+    type ProtoRequest = proto::GetSignatureHelp;
+
+    fn check_capabilities(&self, capabilities: &ServerCapabilities) -> bool {
+        capabilities.signature_help_provider.is_some()
+    }
+
+    fn to_lsp(
+        &self,
+        path: &Path,
+        _: &Buffer,
+        _: &Arc<LanguageServer>,
+        _: &AppContext,
+    ) -> lsp::SignatureHelpParams {
+        lsp::SignatureHelpParams {
+            context: None,
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: lsp::Url::from_file_path(path).unwrap(),
+                },
+                position: point_to_lsp(self.position),
+            },
+            work_done_progress_params: Default::default(),
+        }
+    }
+
+    async fn response_from_lsp(
+        self,
+        message: Option<lsp::SignatureHelp>,
+        _: Model<Project>,
+        _: Model<Buffer>,
+        _: LanguageServerId,
+        _: AsyncAppContext,
+    ) -> Result<Self::Response> {
+        let Some(help) = message else {
+            return Ok(None);
+        };
+
+        let signatures = help
+            .signatures
+            .into_iter()
+            .map(|signature| {
+                let parameters = signature
+                    .parameters
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|parameter| match parameter.label {
+                        lsp::ParameterLabel::Simple(label) => {
+                            let start = signature.label.find(&label)?;
+                            Some(start..start + label.len())
+                        }
+                        lsp::ParameterLabel::LabelOffsets([start, end]) => {
+                            let start = utf16_offset_to_byte_offset(&signature.label, start)?;
+                            let end = utf16_offset_to_byte_offset(&signature.label, end)?;
+                            Some(start..end)
+                        }
+                    })
+                    .collect();
+                let documentation =
+                    signature
+                        .documentation
+                        .map(|documentation| match documentation {
+                            lsp::Documentation::String(text) => text,
+                            lsp::Documentation::MarkupContent(markup) => markup.value,
+                        });
+                SignatureInformation {
+                    label: signature.label,
+                    documentation,
+                    parameters,
+                    active_parameter: signature.active_parameter.map(|ix| ix as usize),
+                }
+            })
+            .collect::<Vec<_>>();
+        if signatures.is_empty() {
+            return Ok(None);
+        }
+
+        let active_signature = help
+            .active_signature
+            .map(|ix| ix as usize)
+            .filter(|ix| *ix < signatures.len())
+            .unwrap_or(0);
+        Ok(Some(SignatureHelp {
+            signatures,
+            active_signature,
+            active_parameter: help.active_parameter.map(|ix| ix as usize),
+        }))
+    }
+
+    fn to_proto(&self, project_id: u64, buffer: &Buffer) -> Self::ProtoRequest {
+        proto::GetSignatureHelp {
+            project_id,
+            buffer_id: buffer.remote_id().into(),
+            position: Some(language::proto::serialize_anchor(
+                &buffer.anchor_before(self.position),
+            )),
+            version: serialize_version(&buffer.version),
+        }
+    }
+
+    async fn from_proto(
+        message: Self::ProtoRequest,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Self> {
+        let position = message
+            .position
+            .and_then(deserialize_anchor)
+            .ok_or_else(|| anyhow!("invalid position"))?;
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        Ok(Self {
+            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        })
+    }
+
+    fn response_to_proto(
+        response: Self::Response,
+        _: &mut Project,
+        _: PeerId,
+        _: &clock::Global,
+        _: &mut AppContext,
+    ) -> proto::GetSignatureHelpResponse {
+        let Some(response) = response else {
+            return proto::GetSignatureHelpResponse {
+                signatures: Vec::new(),
+                active_signature: 0,
+                active_parameter: None,
+            };
+        };
+
+        proto::GetSignatureHelpResponse {
+            signatures: response
+                .signatures
+                .into_iter()
+                .map(|signature| proto::SignatureInformation {
+                    label: signature.label,
+                    documentation: signature.documentation,
+                    parameters: signature
+                        .parameters
+                        .into_iter()
+                        .map(|range| proto::SignatureParameter {
+                            start: range.as_ref().map(|range| range.start as u64),
+                            end: range.map(|range| range.end as u64),
+                        })
+                        .collect(),
+                    active_parameter: signature.active_parameter.map(|ix| ix as u64),
+                })
+                .collect(),
+            active_signature: response.active_signature as u64,
+            active_parameter: response.active_parameter.map(|ix| ix as u64),
+        }
+    }
+
+    async fn response_from_proto(
+        self,
+        message: proto::GetSignatureHelpResponse,
+        _: Model<Project>,
+        _: Model<Buffer>,
+        _: AsyncAppContext,
+    ) -> Result<Self::Response> {
+        let signatures = message
+            .signatures
+            .into_iter()
+            .map(|signature| SignatureInformation {
+                parameters: signature
+                    .parameters
+                    .into_iter()
+                    .map(|parameter| {
+                        let range = parameter.start? as usize..parameter.end? as usize;
+                        (range.start <= range.end
+                            && signature.label.is_char_boundary(range.start)
+                            && signature.label.is_char_boundary(range.end))
+                        .then_some(range)
+                    })
+                    .collect(),
+                label: signature.label,
+                documentation: signature.documentation,
+                active_parameter: signature.active_parameter.map(|ix| ix as usize),
+            })
+            .collect::<Vec<_>>();
+        if signatures.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(SignatureHelp {
+            active_signature: (message.active_signature as usize).min(signatures.len() - 1),
+            active_parameter: message.active_parameter.map(|ix| ix as usize),
+            signatures,
+        }))
+    }
+
+    fn buffer_id_from_proto(message: &Self::ProtoRequest) -> Result<BufferId> {
+        BufferId::new(message.buffer_id)
+    }
+}
+
+fn utf16_offset_to_byte_offset(text: &str, utf16_offset: u32) -> Option<usize> {
+    let mut utf16_len = 0;
+    for (ix, ch) in text.char_indices() {
+        if utf16_len == utf16_offset as usize {
+            return Some(ix);
+        }
+        utf16_len += ch.len_utf16();
+    }
+    (utf16_len == utf16_offset as usize).then_some(text.len())
+}
+
 #[async_trait(?Send)]
 impl LspCommand for GetCompletions {
     type Response = Vec<Completion>;
@@ -41,9 +41,10 @@ use language::{
     },
     range_from_lsp, range_to_lsp, Bias, Buffer, BufferSnapshot, CachedLspAdapter, Capability,
     CodeAction, CodeLabel, Completion, Diagnostic, DiagnosticEntry, DiagnosticSet, Diff,
-    Documentation, Event as BufferEvent, File as _, Language, LanguageRegistry, LanguageServerName,
-    LocalFile, LspAdapterDelegate, OffsetRangeExt, Operation, Patch, PendingLanguageServer,
-    PointUtf16, TextBufferSnapshot, ToOffset, ToPointUtf16, Transaction, Unclipped,
+    Documentation, EditorConfig, Event as BufferEvent, File as _, Language, LanguageRegistry,
+    LanguageServerName, LocalFile, LspAdapterDelegate, OffsetRangeExt, Operation, Patch,
+    PendingLanguageServer, PointUtf16, TextBufferSnapshot, ToOffset, ToPointUtf16, Transaction,
+    Unclipped,
 };
 use log::error;
 use lsp::{
@@ -432,6 +433,36 @@ impl Hover {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureHelp {
+    pub signatures: Vec<SignatureInformation>,
+    pub active_signature: usize,
+    pub active_parameter: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureInformation {
+    pub label: String,
+    pub documentation: Option<String>,
+    /// Byte ranges of each parameter within the signature's label, or `None` for parameters
+    /// whose label couldn't be found in it, so that parameters keep their indices.
+    pub parameters: Vec<Option<Range<usize>>>,
+    /// Overrides the [`SignatureHelp::active_parameter`] for this signature.
+    pub active_parameter: Option<usize>,
+}
+
+impl SignatureHelp {
+    pub fn active_signature(&self) -> Option<&SignatureInformation> {
+        self.signatures.get(self.active_signature)
+    }
+
+    /// The index of the parameter that is currently being typed, if any.
+    pub fn active_parameter(&self) -> Option<usize> {
+        let signature = self.active_signature()?;
+        signature.active_parameter.or(self.active_parameter)
+    }
+}
+
 #[derive(Default)]
 pub struct ProjectTransaction(pub HashMap<Model<Buffer>, language::Transaction>);
 
@@ -589,6 +620,7 @@ impl Project {
         client.add_model_request_handler(Self::handle_lsp_command::<GetCodeActions>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetCompletions>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetHover>);
+        client.add_model_request_handler(Self::handle_lsp_command::<GetSignatureHelp>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetTypeDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetDocumentHighlights>);
@@ -1817,9 +1849,17 @@ impl Project {
             let worktree = worktree.as_local_mut().unwrap();
             worktree.load_buffer(buffer_id, path, cx)
         });
+        let abs_path = worktree.read(cx).absolutize(path);
+        let fs = self.fs.clone();
         cx.spawn(move |this, mut cx| async move {
             let buffer = load_buffer.await?;
             this.update(&mut cx, |this, cx| this.register_buffer(&buffer, cx))??;
+            if let Some(abs_path) = abs_path.log_err() {
+                let editor_config = load_editor_config(fs.as_ref(), &abs_path).await;
+                buffer.update(&mut cx, |buffer, cx| {
+                    buffer.set_editor_config(editor_config, cx)
+                })?;
+            }
             Ok(buffer)
         })
     }
@@ -4202,19 +4242,32 @@ impl Project {
                 for (buffer, buffer_abs_path, lsp_adapter, language_server) in
                     &buffers_with_paths_and_servers
                 {
-                    let settings = buffer.update(&mut cx, |buffer, cx| {
-                        language_settings(buffer.language(), buffer.file(), cx).clone()
+                    let (settings, editor_config) = buffer.update(&mut cx, |buffer, cx| {
+                        (
+                            language_settings(buffer.language(), buffer.file(), cx).clone(),
+                            *buffer.editor_config(),
+                        )
                     })?;
 
-                    let remove_trailing_whitespace = settings.remove_trailing_whitespace_on_save;
-                    let ensure_final_newline = settings.ensure_final_newline_on_save;
-                    let tab_size = settings.tab_size;
+                    let remove_trailing_whitespace = editor_config
+                        .trim_trailing_whitespace
+                        .unwrap_or(settings.remove_trailing_whitespace_on_save);
+                    let ensure_final_newline = editor_config
+                        .insert_final_newline
+                        .unwrap_or(settings.ensure_final_newline_on_save);
+                    let tab_size = editor_config.tab_size().unwrap_or(settings.tab_size);
 
                     // First, format buffer's whitespace according to the settings.
                     let trailing_whitespace_diff = if remove_trailing_whitespace {
                         Some(
                             buffer
-                                .update(&mut cx, |b, cx| b.remove_trailing_whitespace(cx))?
+                                .update(&mut cx, |b, cx| {
+                                    if settings.remove_trailing_whitespace_on_modified_lines_only {
+                                        b.remove_trailing_whitespace_on_modified_lines(cx)
+                                    } else {
+                                        b.remove_trailing_whitespace(cx)
+                                    }
+                                })?
                                 .await,
                         )
                     } else {
@@ -4907,6 +4960,48 @@ impl Project {
             cx,
         )
     }
+
+    pub fn signature_help<T: ToPointUtf16>(
+        &self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Option<SignatureHelp>>> {
+        let position = position.to_point_utf16(buffer.read(cx));
+        self.request_lsp(
+            buffer.clone(),
+            LanguageServerToQuery::Primary,
+            GetSignatureHelp { position },
+            cx,
+        )
+    }
+
+    /// Returns the characters that trigger and retrigger signature help in the given buffer.
+    ///
+    /// Remote projects don't know about their host's language servers, so they fall back to
+    /// the characters that open and separate arguments in most languages.
+    pub fn signature_help_trigger_characters(
+        &self,
+        buffer: &Model<Buffer>,
+        cx: &AppContext,
+    ) -> (Vec<String>, Vec<String>) {
+        if self.is_remote() {
+            return (vec!["(".to_string()], vec![",".to_string()]);
+        }
+
+        let buffer = buffer.read(cx);
+        let Some((_, server)) = self.primary_language_server_for_buffer(buffer, cx) else {
+            return Default::default();
+        };
+        let Some(options) = server.capabilities().signature_help_provider.as_ref() else {
+            return Default::default();
+        };
+        (
+            options.trigger_characters.clone().unwrap_or_default(),
+            options.retrigger_characters.clone().unwrap_or_default(),
+        )
+    }
+
     pub fn hover<T: ToPointUtf16>(
         &self,
         buffer: &Model<Buffer>,
@@ -9337,6 +9432,29 @@ impl Item for Buffer {
     }
 }
 
+/// Reads the `.editorconfig` files that apply to the file at `abs_path`, from its directory
+/// upwards until one of them is marked as the root.
+async fn load_editor_config(fs: &dyn Fs, abs_path: &Path) -> EditorConfig {
+    let mut config_files = Vec::new();
+    for dir in abs_path.ancestors().skip(1) {
+        if let Ok(contents) = fs.load(&dir.join(".editorconfig")).await {
+            let is_root = EditorConfig::is_root(&contents);
+            config_files.push((dir, contents));
+            if is_root {
+                break;
+            }
+        }
+    }
+
+    let mut editor_config = EditorConfig::default();
+    for (dir, contents) in config_files.into_iter().rev() {
+        if let Ok(path) = abs_path.strip_prefix(dir) {
+            editor_config.apply(&contents, path);
+        }
+    }
+    editor_config
+}
+
 async fn wait_for_loading_buffer(
     mut receiver: postage::watch::Receiver<Option<Result<Model<Buffer>, Arc<anyhow::Error>>>>,
 ) -> Result<Model<Buffer>, Arc<anyhow::Error>> {
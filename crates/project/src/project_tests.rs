@@ -4,8 +4,8 @@ use futures::{future, StreamExt};
 use gpui::AppContext;
 use language::{
     language_settings::{AllLanguageSettings, LanguageSettingsContent},
-    tree_sitter_rust, tree_sitter_typescript, Diagnostic, FakeLspAdapter, LanguageConfig,
-    LanguageMatcher, LineEnding, OffsetRangeExt, Point, ToPoint,
+    tree_sitter_rust, tree_sitter_typescript, Diagnostic, FakeLspAdapter, IndentSize,
+    LanguageConfig, LanguageMatcher, LineEnding, OffsetRangeExt, Point, ToPoint,
 };
 use lsp::Url;
 use parking_lot::Mutex;
@@ -142,6 +142,54 @@ async fn test_managing_project_specific_settings(cx: &mut gpui::TestAppContext)
     });
 }
 
+#[gpui::test]
+async fn test_loading_editor_config(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-root",
+        json!({
+            ".editorconfig": "root = true\n[*]\nindent_style = space\nindent_size = 4\ninsert_final_newline = true\n",
+            "a": {
+                "a.rs": "fn a() {\n    A\n}"
+            },
+            "b": {
+                ".editorconfig": "[*.rs]\nindent_size = 2\nmax_line_length = 80\n",
+                "b.rs": "fn b() {\n  B\n}"
+            }
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs.clone(), ["/the-root".as_ref()], cx).await;
+    let buffer_a = project
+        .update(cx, |project, cx| {
+            project.open_local_buffer("/the-root/a/a.rs", cx)
+        })
+        .await
+        .unwrap();
+    let buffer_b = project
+        .update(cx, |project, cx| {
+            project.open_local_buffer("/the-root/b/b.rs", cx)
+        })
+        .await
+        .unwrap();
+
+    buffer_a.update(cx, |buffer, _| {
+        let config = buffer.editor_config();
+        assert_eq!(config.indent_size(), Some(IndentSize::spaces(4)));
+        assert_eq!(config.insert_final_newline, Some(true));
+        assert_eq!(config.max_line_length, None);
+    });
+    buffer_b.update(cx, |buffer, _| {
+        let config = buffer.editor_config();
+        assert_eq!(config.indent_size(), Some(IndentSize::spaces(2)));
+        assert_eq!(config.insert_final_newline, Some(true));
+        assert_eq!(config.max_line_length, Some(80));
+    });
+}
+
 #[gpui::test]
 async fn test_managing_language_servers(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -2,18 +2,51 @@ use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
 use anyhow::{Context, Result};
 use client::proto;
 use itertools::Itertools;
-use language::{char_kind, BufferSnapshot};
+use language::{char_kind, BufferSnapshot, LanguageScope, Rope};
 use regex::{Regex, RegexBuilder};
 use smol::future::yield_now;
 use std::{
     borrow::Cow,
-    io::{BufRead, BufReader, Read},
+    io::{self, BufRead, BufReader, Read},
     ops::Range,
     path::Path,
     sync::Arc,
 };
 use util::paths::PathMatcher;
 
+/// Text that can be searched in place, without first being copied into a single string,
+/// such as a [`Rope`] or the contents of a multi-buffer.
+pub trait SearchableText {
+    type Bytes<'a>: io::Read
+    where
+        Self: 'a;
+
+    fn bytes(&self) -> Self::Bytes<'_>;
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+    fn chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_>;
+    fn reversed_chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_>;
+}
+
+impl SearchableText for Rope {
+    type Bytes<'a> = text::Bytes<'a>;
+
+    fn bytes(&self) -> Self::Bytes<'_> {
+        self.bytes_in_range(0..Rope::len(self))
+    }
+
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(Rope::chunks(self))
+    }
+
+    fn chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_> {
+        Box::new(Rope::chars_at(self, offset))
+    }
+
+    fn reversed_chars_at(&self, offset: usize) -> Box<dyn Iterator<Item = char> + '_> {
+        Box::new(Rope::reversed_chars_at(self, offset))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SearchInputs {
     query: Arc<str>,
@@ -243,12 +276,6 @@ impl SearchQuery {
         buffer: &BufferSnapshot,
         subrange: Option<Range<usize>>,
     ) -> Vec<Range<usize>> {
-        const YIELD_INTERVAL: usize = 20000;
-
-        if self.as_str().is_empty() {
-            return Default::default();
-        }
-
         let range_offset = subrange.as_ref().map(|r| r.start).unwrap_or(0);
         let rope = if let Some(range) = subrange {
             buffer.as_rope().slice(range)
@@ -256,28 +283,45 @@ impl SearchQuery {
             buffer.as_rope().clone()
         };
 
+        self.search_text(&rope, |offset| {
+            buffer.language_scope_at(range_offset + offset)
+        })
+        .await
+    }
+
+    /// Searches text that doesn't necessarily belong to a single buffer, such as the
+    /// contents of a multi-buffer. `language_scope_at` is used to determine word
+    /// boundaries for whole-word searches.
+    pub async fn search_text(
+        &self,
+        text: &impl SearchableText,
+        language_scope_at: impl Fn(usize) -> Option<LanguageScope>,
+    ) -> Vec<Range<usize>> {
+        const YIELD_INTERVAL: usize = 20000;
+
+        if self.as_str().is_empty() {
+            return Default::default();
+        }
+
         let mut matches = Vec::new();
         match self {
             Self::Text {
                 search, whole_word, ..
             } => {
-                for (ix, mat) in search
-                    .stream_find_iter(rope.bytes_in_range(0..rope.len()))
-                    .enumerate()
-                {
+                for (ix, mat) in search.stream_find_iter(text.bytes()).enumerate() {
                     if (ix + 1) % YIELD_INTERVAL == 0 {
                         yield_now().await;
                     }
 
                     let mat = mat.unwrap();
                     if *whole_word {
-                        let scope = buffer.language_scope_at(range_offset + mat.start());
+                        let scope = language_scope_at(mat.start());
                         let kind = |c| char_kind(&scope, c);
 
-                        let prev_kind = rope.reversed_chars_at(mat.start()).next().map(kind);
-                        let start_kind = kind(rope.chars_at(mat.start()).next().unwrap());
-                        let end_kind = kind(rope.reversed_chars_at(mat.end()).next().unwrap());
-                        let next_kind = rope.chars_at(mat.end()).next().map(kind);
+                        let prev_kind = text.reversed_chars_at(mat.start()).next().map(kind);
+                        let start_kind = kind(text.chars_at(mat.start()).next().unwrap());
+                        let end_kind = kind(text.reversed_chars_at(mat.end()).next().unwrap());
+                        let next_kind = text.chars_at(mat.end()).next().map(kind);
                         if Some(start_kind) == prev_kind || Some(end_kind) == next_kind {
                             continue;
                         }
@@ -290,7 +334,7 @@ impl SearchQuery {
                 regex, multiline, ..
             } => {
                 if *multiline {
-                    let text = rope.to_string();
+                    let text = text.chunks().collect::<String>();
                     for (ix, mat) in regex.find_iter(&text).enumerate() {
                         if (ix + 1) % YIELD_INTERVAL == 0 {
                             yield_now().await;
@@ -301,7 +345,7 @@ impl SearchQuery {
                 } else {
                     let mut line = String::new();
                     let mut line_offset = 0;
-                    for (chunk_ix, chunk) in rope.chunks().chain(["\n"]).enumerate() {
+                    for (chunk_ix, chunk) in text.chunks().chain(["\n"]).enumerate() {
                         if (chunk_ix + 1) % YIELD_INTERVAL == 0 {
                             yield_now().await;
                         }
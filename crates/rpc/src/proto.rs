@@ -182,6 +182,8 @@ messages!(
     (GetDocumentHighlightsResponse, Background),
     (GetHover, Background),
     (GetHoverResponse, Background),
+    (GetSignatureHelp, Background),
+    (GetSignatureHelpResponse, Background),
     (GetNotifications, Foreground),
     (GetNotificationsResponse, Foreground),
     (GetPrivateUserInfo, Foreground),
@@ -314,6 +316,7 @@ request_messages!(
     (GetDefinition, GetDefinitionResponse),
     (GetDocumentHighlights, GetDocumentHighlightsResponse),
     (GetHover, GetHoverResponse),
+    (GetSignatureHelp, GetSignatureHelpResponse),
     (GetNotifications, GetNotificationsResponse),
     (GetPrivateUserInfo, GetPrivateUserInfoResponse),
     (GetProjectSymbols, GetProjectSymbolsResponse),
@@ -390,6 +393,7 @@ entity_messages!(
     GetDefinition,
     GetDocumentHighlights,
     GetHover,
+    GetSignatureHelp,
     GetProjectSymbols,
     GetReferences,
     GetTypeDefinition,
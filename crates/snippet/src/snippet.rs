@@ -79,12 +79,18 @@ fn parse_tabstop<'a>(
     let tabstop_start = text.len();
     let tabstop_index;
     if source.starts_with('{') {
+        if source[1..].starts_with(is_variable_start) {
+            return parse_variable(&source[1..], true, text, tabstops);
+        }
+
         let (index, rest) = parse_int(&source[1..])?;
         tabstop_index = index;
         source = rest;
 
         if source.starts_with(':') {
             source = parse_snippet(&source[1..], true, text, tabstops)?;
+        } else if source.starts_with('|') {
+            source = parse_choices(&source[1..], text)?;
         }
 
         if source.starts_with('}') {
@@ -92,6 +98,8 @@ fn parse_tabstop<'a>(
         } else {
             return Err(anyhow!("expected a closing brace"));
         }
+    } else if source.starts_with(is_variable_start) {
+        return parse_variable(source, false, text, tabstops);
     } else {
         let (index, rest) = parse_int(source)?;
         tabstop_index = index;
@@ -105,6 +113,66 @@ fn parse_tabstop<'a>(
     Ok(source)
 }
 
+/// Parses the options of a choice, such as `one,two|` in `${1|one,two|}`, inserting the first
+/// option into the text.
+fn parse_choices<'a>(mut source: &'a str, text: &mut String) -> Result<&'a str> {
+    let mut is_first_choice = true;
+    loop {
+        match source.chars().next() {
+            None => return Err(anyhow!("expected the end of a choice")),
+            Some('|') => return Ok(&source[1..]),
+            Some(',') => {
+                is_first_choice = false;
+                source = &source[1..];
+            }
+            Some(c) => {
+                let c = if c == '\\' {
+                    source = &source[1..];
+                    let Some(c) = source.chars().next() else {
+                        continue;
+                    };
+                    c
+                } else {
+                    c
+                };
+                if is_first_choice {
+                    text.push(c);
+                }
+                source = &source[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Parses a variable, such as `TM_FILENAME` or `{TM_FILENAME:default}`. Variables aren't
+/// resolved, so they're replaced with their default value, if any.
+fn parse_variable<'a>(
+    source: &'a str,
+    braced: bool,
+    text: &mut String,
+    tabstops: &mut BTreeMap<usize, TabStop>,
+) -> Result<&'a str> {
+    let name_len = source
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(source.len());
+    let mut source = &source[name_len..];
+    if braced {
+        if source.starts_with(':') {
+            source = parse_snippet(&source[1..], true, text, tabstops)?;
+        }
+        if source.starts_with('}') {
+            source = &source[1..];
+        } else {
+            return Err(anyhow!("expected a closing brace"));
+        }
+    }
+    Ok(source)
+}
+
+fn is_variable_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
 fn parse_int(source: &str) -> Result<(usize, &str)> {
     let len = source
         .find(|c: char| !c.is_ascii_digit())
@@ -188,6 +256,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_snippet_with_choices() {
+        let snippet = Snippet::parse("let x = ${1|one,t\\,wo|};$0").unwrap();
+        assert_eq!(snippet.text, "let x = one;");
+        assert_eq!(tabstops(&snippet), &[vec![8..11], vec![12..12]]);
+    }
+
+    #[test]
+    fn test_snippet_with_variables() {
+        let snippet = Snippet::parse("// $TM_FILENAME ${CLIPBOARD:${1:text}}$0").unwrap();
+        assert_eq!(snippet.text, "//  text");
+        assert_eq!(tabstops(&snippet), &[vec![4..8], vec![8..8]]);
+    }
+
     #[test]
     fn test_snippet_parsing_with_escaped_chars() {
         let snippet = Snippet::parse("\"\\$schema\": $1").unwrap();
@@ -591,6 +591,33 @@ fn test_history() {
     assert_eq!(buffer.text(), "X12cde6");
 }
 
+#[test]
+fn test_undo_and_redo_specific_transactions() {
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "1234".into());
+    buffer.set_group_interval(Duration::from_secs(0));
+
+    buffer.edit([(0..0, "a")]);
+    buffer.edit([(5..5, "b")]);
+    assert_eq!(buffer.text(), "a1234b");
+    let transaction_ids = buffer
+        .undo_stack()
+        .iter()
+        .map(|entry| entry.transaction_id())
+        .collect::<Vec<_>>();
+
+    buffer.undo_transaction(transaction_ids[0]).unwrap();
+    assert_eq!(buffer.text(), "1234b");
+    buffer.undo_transaction(transaction_ids[1]).unwrap();
+    assert_eq!(buffer.text(), "1234");
+
+    // Redoing an older transaction leaves the more recently undone one on the redo stack.
+    buffer.redo_transaction(transaction_ids[0]).unwrap();
+    assert_eq!(buffer.text(), "a1234");
+    assert!(buffer.redo_transaction(transaction_ids[0]).is_none());
+    buffer.redo();
+    assert_eq!(buffer.text(), "a1234b");
+}
+
 #[test]
 fn test_finalize_last_transaction() {
     let now = Instant::now();
@@ -119,10 +119,35 @@ pub struct Transaction {
     pub start: clock::Global,
 }
 
+/// A buffer's undo and redo stacks in a form that can recreate them in another buffer with
+/// the same text, created by [`Buffer::serialize_history`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BufferHistory {
+    /// The fingerprint of the text the history applies to.
+    pub fingerprint: RopeFingerprint,
+    /// The transactions that can be undone, from the oldest to the most recent.
+    pub undo_stack: Vec<TransactionEdits>,
+    /// The transactions that can be redone, ending with the one that is redone first.
+    pub redo_stack: Vec<TransactionEdits>,
+}
+
+/// The edits that undo a transaction in a [`BufferHistory`]'s undo stack, or that redo a
+/// transaction in its redo stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionEdits {
+    pub id: TransactionId,
+    /// Offset ranges in the text before the edits, with their replacements.
+    pub edits: Vec<(Range<usize>, Arc<str>)>,
+}
+
 impl HistoryEntry {
     pub fn transaction_id(&self) -> TransactionId {
         self.transaction.id
     }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
 }
 
 struct History {
@@ -376,6 +401,21 @@ impl History {
         }
     }
 
+    fn remove_transaction_from_redo(
+        &mut self,
+        transaction_id: TransactionId,
+    ) -> Option<&HistoryEntry> {
+        assert_eq!(self.transaction_depth, 0);
+
+        let entry_ix = self
+            .redo_stack
+            .iter()
+            .rposition(|entry| entry.transaction.id == transaction_id)?;
+        let entry = self.redo_stack.remove(entry_ix);
+        self.undo_stack.push(entry);
+        self.undo_stack.last()
+    }
+
     fn remove_from_redo(&mut self, transaction_id: TransactionId) -> &[HistoryEntry] {
         assert_eq!(self.transaction_depth, 0);
 
@@ -1162,6 +1202,14 @@ impl Buffer {
         self.history.redo_stack.last()
     }
 
+    pub fn undo_stack(&self) -> &[HistoryEntry] {
+        &self.history.undo_stack
+    }
+
+    pub fn redo_stack(&self) -> &[HistoryEntry] {
+        &self.history.redo_stack
+    }
+
     pub fn start_transaction(&mut self) -> Option<TransactionId> {
         self.start_transaction_at(Instant::now())
     }
@@ -1254,6 +1302,15 @@ impl Buffer {
         }
     }
 
+    pub fn redo_transaction(&mut self, transaction_id: TransactionId) -> Option<Operation> {
+        let transaction = self
+            .history
+            .remove_transaction_from_redo(transaction_id)?
+            .transaction
+            .clone();
+        self.undo_or_redo(transaction).log_err()
+    }
+
     pub fn redo_to_transaction(&mut self, transaction_id: TransactionId) -> Vec<Operation> {
         let transactions = self
             .history
@@ -1291,6 +1348,129 @@ impl Buffer {
         self.history.finalize_last_transaction();
     }
 
+    /// Captures the edits of the transactions in the undo and redo stacks, so that they can be
+    /// recreated in another buffer with [`Buffer::restore_history`].
+    pub fn serialize_history(&self) -> BufferHistory {
+        // Undo and redo the transactions in a copy of this buffer to find out what they'd change.
+        let mut buffer = Buffer::new(
+            self.replica_id(),
+            self.remote_id(),
+            self.history.base_text.to_string(),
+        );
+        buffer
+            .apply_ops(self.history.operations.values().cloned())
+            .log_err();
+
+        let mut redo_stack = self
+            .history
+            .redo_stack
+            .iter()
+            .rev()
+            .map(|entry| buffer.toggle_transaction(&entry.transaction))
+            .collect::<Vec<_>>();
+        redo_stack.reverse();
+        for entry in &self.history.redo_stack {
+            buffer.toggle_transaction(&entry.transaction);
+        }
+
+        let mut undo_stack = self
+            .history
+            .undo_stack
+            .iter()
+            .rev()
+            .map(|entry| buffer.toggle_transaction(&entry.transaction))
+            .collect::<Vec<_>>();
+        undo_stack.reverse();
+
+        BufferHistory {
+            fingerprint: self.visible_text.fingerprint(),
+            undo_stack,
+            redo_stack,
+        }
+    }
+
+    fn toggle_transaction(&mut self, transaction: &Transaction) -> TransactionEdits {
+        let version = self.version();
+        self.undo_or_redo(transaction.clone()).log_err();
+        let edits = self
+            .edits_since::<usize>(&version)
+            .map(|edit| {
+                (
+                    edit.old,
+                    self.text_for_range(edit.new).collect::<String>().into(),
+                )
+            })
+            .collect();
+        TransactionEdits {
+            id: transaction.id,
+            edits,
+        }
+    }
+
+    /// Recreates the undo and redo stacks captured by [`Buffer::serialize_history`], leaving
+    /// the text unchanged. The recreated transactions keep their ids. Returns `None` without
+    /// changing anything if the history was captured from different text, or if this buffer
+    /// already has a history.
+    pub fn restore_history(&mut self, history: BufferHistory) -> Option<Vec<Operation>> {
+        if history.fingerprint != self.visible_text.fingerprint()
+            || !self.history.undo_stack.is_empty()
+            || !self.history.redo_stack.is_empty()
+        {
+            return None;
+        }
+
+        // Apply the edits of each stack's transactions in the order they'd be undone or redone,
+        // then undo them all again. Undoing one of these transactions later reapplies its edits.
+        let mut operations = Vec::new();
+        let redo_transactions =
+            self.apply_and_undo_edits(history.redo_stack.iter().rev(), &mut operations);
+        let undo_transactions =
+            self.apply_and_undo_edits(history.undo_stack.iter().rev(), &mut operations);
+
+        let now = Instant::now();
+        self.history.undo_stack.clear();
+        self.history.redo_stack.clear();
+        for transaction in undo_transactions.into_iter().rev() {
+            self.lamport_clock.observe(transaction.id);
+            self.push_transaction(transaction, now);
+        }
+        for transaction in redo_transactions.into_iter().rev() {
+            self.lamport_clock.observe(transaction.id);
+            self.history.redo_stack.push(HistoryEntry {
+                transaction,
+                first_edit_at: now,
+                last_edit_at: now,
+                suppress_grouping: true,
+            });
+        }
+
+        Some(operations)
+    }
+
+    fn apply_and_undo_edits<'a>(
+        &mut self,
+        transactions: impl Iterator<Item = &'a TransactionEdits>,
+        operations: &mut Vec<Operation>,
+    ) -> Vec<Transaction> {
+        let transactions = transactions
+            .map(|transaction_edits| {
+                let start = self.version();
+                let operation = self.edit(transaction_edits.edits.iter().cloned());
+                let edit_ids = vec![operation.timestamp()];
+                operations.push(operation);
+                Transaction {
+                    id: transaction_edits.id,
+                    edit_ids,
+                    start,
+                }
+            })
+            .collect::<Vec<_>>();
+        for transaction in transactions.iter().rev() {
+            operations.extend(self.undo_or_redo(transaction.clone()).log_err());
+        }
+        transactions
+    }
+
     pub fn edited_ranges_for_transaction<'a, D>(
         &'a self,
         transaction: &'a Transaction,
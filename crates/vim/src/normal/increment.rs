@@ -30,7 +30,7 @@ pub fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
             vim.record_current_action(cx);
             let count = vim.take_count(cx).unwrap_or(1);
             let step = if action.step { 1 } else { 0 };
-            increment(vim, count as i32, step, cx)
+            increment(vim, count as i64, step, cx)
         })
     });
     workspace.register_action(|_: &mut Workspace, action: &Decrement, cx| {
@@ -38,12 +38,12 @@ pub fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
             vim.record_current_action(cx);
             let count = vim.take_count(cx).unwrap_or(1);
             let step = if action.step { -1 } else { 0 };
-            increment(vim, count as i32 * -1, step, cx)
+            increment(vim, count as i64 * -1, step, cx)
         })
     });
 }
 
-fn increment(vim: &mut Vim, mut delta: i32, step: i32, cx: &mut WindowContext) {
+fn increment(vim: &mut Vim, mut delta: i64, step: i64, cx: &mut WindowContext) {
     vim.update_active_editor(cx, |editor, cx| {
         let mut edits = Vec::new();
         let mut new_anchors = Vec::new();
@@ -63,21 +63,28 @@ fn increment(vim: &mut Vim, mut delta: i32, step: i32, cx: &mut WindowContext) {
                 };
 
                 if let Some((range, num, radix)) = find_number(&snapshot, start) {
-                    if let Ok(val) = i32::from_str_radix(&num, radix) {
-                        let result = val + delta;
-                        delta += step;
-                        let replace = match radix {
-                            10 => format!("{}", result),
-                            16 => {
-                                if num.to_ascii_lowercase() == num {
-                                    format!("{:x}", result)
-                                } else {
-                                    format!("{:X}", result)
-                                }
+                    // Hexadecimal and binary numbers are unsigned and wrap around, as in vim,
+                    // and keep their leading zeros
+                    let width = num.len();
+                    let replace = match radix {
+                        10 => i64::from_str_radix(&num, radix)
+                            .ok()
+                            .map(|val| format!("{}", val.saturating_add(delta))),
+                        16 => u64::from_str_radix(&num, radix).ok().map(|val| {
+                            let result = val.wrapping_add_signed(delta);
+                            if num.to_ascii_lowercase() == num {
+                                format!("{:0width$x}", result)
+                            } else {
+                                format!("{:0width$X}", result)
                             }
-                            2 => format!("{:b}", result),
-                            _ => unreachable!(),
-                        };
+                        }),
+                        2 => u64::from_str_radix(&num, radix)
+                            .ok()
+                            .map(|val| format!("{:0width$b}", val.wrapping_add_signed(delta))),
+                        _ => unreachable!(),
+                    };
+                    if let Some(replace) = replace {
+                        delta += step;
                         edits.push((range.clone(), replace));
                     }
                     if selection.is_empty() {
@@ -134,12 +141,14 @@ fn find_number(
     let mut chars = snapshot.chars_at(offset).peekable();
     // find the next number on the line (may start after the original cursor position)
     while let Some(ch) = chars.next() {
-        if num == "0" && ch == 'b' && chars.peek().is_some() && chars.peek().unwrap().is_digit(2) {
+        // hexadecimal and binary numbers are unsigned, so a preceding '-' isn't part of them
+        let is_prefix = num == "0" || num == "-0";
+        if is_prefix && ch == 'b' && chars.peek().is_some() && chars.peek().unwrap().is_digit(2) {
             radix = 2;
             begin = None;
             num = String::new();
         }
-        if num == "0" && ch == 'x' && chars.peek().is_some() && chars.peek().unwrap().is_digit(16) {
+        if is_prefix && ch == 'x' && chars.peek().is_some() && chars.peek().unwrap().is_digit(16) {
             radix = 16;
             begin = None;
             num = String::new();
@@ -232,6 +241,31 @@ mod test {
         cx.assert_matches_neovim("ˇ-1", ["ctrl-a"], "ˇ0").await;
         cx.assert_matches_neovim("banˇana", ["ctrl-a"], "banˇana")
             .await;
+        cx.assert_matches_neovim("ˇ total: 0x00ff", ["ctrl-a"], " total: 0x010ˇ0")
+            .await;
+        cx.assert_matches_neovim("ˇ0b0011", ["ctrl-x"], "0b001ˇ0")
+            .await;
+        cx.assert_matches_neovim("ˇ4294967295", ["ctrl-a"], "429496729ˇ6")
+            .await;
+    }
+
+    #[gpui::test]
+    async fn test_increment_wrapping_radix(cx: &mut gpui::TestAppContext) {
+        let mut cx = NeovimBackedTestContext::new(cx).await;
+
+        cx.assert_matches_neovim("ˇ0x0", ["ctrl-x"], "0xfffffffffffffffˇf")
+            .await;
+        cx.assert_matches_neovim("ˇ0x1", ["3", "ctrl-x"], "0xfffffffffffffffˇe")
+            .await;
+        cx.assert_matches_neovim("ˇ0xFFFFFFFFFFFFFFFF", ["ctrl-a"], "0x000000000000000ˇ0")
+            .await;
+        cx.assert_matches_neovim("ˇ-0x5", ["ctrl-x"], "-0xˇ4").await;
+        cx.assert_matches_neovim(
+            "ˇ0b0",
+            ["ctrl-x"],
+            "0b111111111111111111111111111111111111111111111111111111111111111ˇ1",
+        )
+        .await;
     }
 
     #[gpui::test]
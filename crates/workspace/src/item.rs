@@ -502,7 +502,7 @@ impl<T: Item> ItemHandle for View<T> {
                                 let delay = Duration::from_millis(milliseconds);
                                 let item = item.clone();
                                 pending_autosave.fire_new(delay, cx, move |workspace, cx| {
-                                    Pane::autosave_item(&item, workspace.project().clone(), cx)
+                                    workspace.autosave_item(&item, cx)
                                 });
                             }
                         }
@@ -514,8 +514,7 @@ impl<T: Item> ItemHandle for View<T> {
             cx.on_blur(&self.focus_handle(cx), move |workspace, cx| {
                 if WorkspaceSettings::get_global(cx).autosave == AutosaveSetting::OnFocusChange {
                     if let Some(item) = weak_item.upgrade() {
-                        Pane::autosave_item(&item, workspace.project.clone(), cx)
-                            .detach_and_log_err(cx);
+                        workspace.autosave_item(&item, cx).detach_and_log_err(cx);
                     }
                 }
             })
@@ -794,6 +793,7 @@ pub mod test {
         pub is_dirty: bool,
         pub is_singleton: bool,
         pub has_conflict: bool,
+        pub fail_save: bool,
         pub project_items: Vec<Model<TestProjectItem>>,
         pub nav_history: Option<ItemNavHistory>,
         pub tab_descriptions: Option<Vec<&'static str>>,
@@ -846,6 +846,7 @@ pub mod test {
                 reload_count: 0,
                 is_dirty: false,
                 has_conflict: false,
+                fail_save: false,
                 project_items: Vec::new(),
                 is_singleton: true,
                 nav_history: None,
@@ -1022,6 +1023,9 @@ pub mod test {
             _: &mut ViewContext<Self>,
         ) -> Task<anyhow::Result<()>> {
             self.save_count += 1;
+            if self.fail_save {
+                return Task::ready(Err(anyhow::anyhow!("failed to save {}", self.label)));
+            }
             self.is_dirty = false;
             Task::ready(Ok(()))
         }
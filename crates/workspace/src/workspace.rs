@@ -465,6 +465,11 @@ pub enum Event {
     ContactRequestedJoin(u64),
     WorkspaceCreated(WeakView<Workspace>),
     SpawnRunnable(SpawnInTerminal),
+    /// An item failed to save automatically, and remains dirty.
+    AutosaveFailed {
+        item_id: EntityId,
+        message: SharedString,
+    },
 }
 
 pub enum OpenVisible {
@@ -3056,25 +3061,48 @@ impl Workspace {
                 .spawn(persistence::DB.update_timestamp(self.database_id()))
                 .detach();
         } else {
+            let autosave = matches!(
+                WorkspaceSettings::get_global(cx).autosave,
+                AutosaveSetting::OnWindowChange | AutosaveSetting::OnFocusChange
+            );
+            let mut items_to_save = Vec::new();
             for pane in &self.panes {
                 pane.update(cx, |pane, cx| {
                     if let Some(item) = pane.active_item() {
                         item.workspace_deactivated(cx);
                     }
-                    if matches!(
-                        WorkspaceSettings::get_global(cx).autosave,
-                        AutosaveSetting::OnWindowChange | AutosaveSetting::OnFocusChange
-                    ) {
-                        for item in pane.items() {
-                            Pane::autosave_item(item.as_ref(), self.project.clone(), cx)
-                                .detach_and_log_err(cx);
-                        }
+                    if autosave {
+                        items_to_save.extend(pane.items().map(|item| item.boxed_clone()));
                     }
                 });
             }
+            for item in items_to_save {
+                self.autosave_item(item.as_ref(), cx).detach_and_log_err(cx);
+            }
         }
     }
 
+    /// Saves the item if it is dirty and can be saved without prompting, skipping items whose
+    /// file has a conflict or was deleted. Failures are emitted as [`Event::AutosaveFailed`].
+    pub fn autosave_item(
+        &mut self,
+        item: &dyn ItemHandle,
+        cx: &mut ViewContext<Self>,
+    ) -> Task<Result<()>> {
+        let item_id = item.item_id();
+        let save = Pane::autosave_item(item, self.project.clone(), cx);
+        cx.spawn(|workspace, mut cx| async move {
+            let result = save.await;
+            if let Err(error) = &result {
+                let message = SharedString::from(format!("{error:#}"));
+                workspace.update(&mut cx, |_, cx| {
+                    cx.emit(Event::AutosaveFailed { item_id, message })
+                })?;
+            }
+            result
+        })
+    }
+
     fn active_call(&self) -> Option<&Model<ActiveCall>> {
         self.active_call.as_ref().map(|(call, _)| call)
     }
@@ -5062,6 +5090,67 @@ mod tests {
         item.update(cx, |item, _| assert_eq!(item.save_count, 5));
     }
 
+    #[gpui::test]
+    async fn test_autosave_failures_and_conflicts(cx: &mut gpui::TestAppContext) {
+        init_test(cx);
+
+        let fs = FakeFs::new(cx.executor());
+        let project = Project::test(fs, [], cx).await;
+        let (workspace, cx) = cx.add_window_view(|cx| Workspace::test_new(project, cx));
+
+        let item = cx.new_view(|cx| {
+            TestItem::new(cx)
+                .with_label("1.txt")
+                .with_project_items(&[TestProjectItem::new(1, "1.txt", cx)])
+        });
+        let item_id = item.entity_id();
+        let failures = Rc::new(RefCell::new(Vec::new()));
+        workspace.update(cx, |workspace, cx| {
+            workspace.add_item(Box::new(item.clone()), cx);
+        });
+        cx.update(|cx| {
+            let failures = failures.clone();
+            cx.subscribe(&workspace, move |_, event, _| {
+                if let Event::AutosaveFailed { item_id, message } = event {
+                    failures.borrow_mut().push((*item_id, message.clone()));
+                }
+            })
+            .detach();
+            cx.update_global(|settings: &mut SettingsStore, cx| {
+                settings.update_user_settings::<WorkspaceSettings>(cx, |settings| {
+                    settings.autosave = Some(AutosaveSetting::AfterDelay { milliseconds: 500 });
+                })
+            });
+        });
+
+        // Items with conflicts aren't saved.
+        item.update(cx, |item, cx| {
+            item.is_dirty = true;
+            item.has_conflict = true;
+            cx.emit(ItemEvent::Edit);
+        });
+        cx.executor().advance_clock(Duration::from_millis(500));
+        cx.run_until_parked();
+        item.update(cx, |item, _| assert_eq!(item.save_count, 0));
+
+        // Failed saves are reported, and the item remains dirty.
+        item.update(cx, |item, cx| {
+            item.has_conflict = false;
+            item.fail_save = true;
+            cx.emit(ItemEvent::Edit);
+        });
+        cx.executor().advance_clock(Duration::from_millis(500));
+        cx.run_until_parked();
+        item.update(cx, |item, _| {
+            assert_eq!(item.save_count, 1);
+            assert!(item.is_dirty);
+        });
+        assert_eq!(
+            failures.borrow().as_slice(),
+            &[(item_id, SharedString::from("failed to save 1.txt"))]
+        );
+    }
+
     #[gpui::test]
     async fn test_pane_navigation(cx: &mut gpui::TestAppContext) {
         init_test(cx);